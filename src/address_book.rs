@@ -0,0 +1,145 @@
+use crate::io::utils::is_valid_kvstore_str;
+use crate::io::{
+	ADDRESS_LABEL_PERSISTENCE_PRIMARY_NAMESPACE, ADDRESS_LABEL_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use crate::logger::{log_error, Logger};
+use crate::types::AddressLabel;
+use crate::Error;
+
+use lightning::util::persist::KVStore;
+use lightning::util::ser::Writeable;
+
+use bitcoin::address::NetworkUnchecked;
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// The maximum length of an address label, in bytes.
+pub(crate) const MAX_ADDRESS_LABEL_LEN: usize = 64;
+
+/// Returns whether the given label may be attached to an address, i.e., is non-empty, doesn't
+/// exceed [`MAX_ADDRESS_LABEL_LEN`], and doesn't contain any control characters.
+pub(crate) fn is_valid_address_label(label: &str) -> bool {
+	!label.is_empty()
+		&& label.len() <= MAX_ADDRESS_LABEL_LEN
+		&& !label.chars().any(|c| c.is_control())
+}
+
+/// Keeps track of the labels attached to our on-chain addresses.
+///
+/// Each label is persisted under the respective address as key.
+pub(crate) struct AddressBook<K: KVStore + Sync + Send, L: Deref>
+where
+	L::Target: Logger,
+{
+	labels: RwLock<HashMap<String, String>>,
+	kv_store: Arc<K>,
+	logger: L,
+}
+
+impl<K: KVStore + Sync + Send, L: Deref> AddressBook<K, L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(labels: HashMap<String, String>, kv_store: Arc<K>, logger: L) -> Self {
+		let labels = RwLock::new(labels);
+		Self { labels, kv_store, logger }
+	}
+
+	pub(crate) fn set_label(&self, address: &bitcoin::Address, label: String) -> Result<(), Error> {
+		if !is_valid_address_label(&label) {
+			log_error!(self.logger, "Failed to set label of address {}: invalid label", address);
+			return Err(Error::InvalidAddressLabel);
+		}
+
+		let key = address.to_string();
+		if !is_valid_kvstore_str(&key) {
+			log_error!(self.logger, "Failed to set label of address {}: invalid key", address);
+			return Err(Error::InvalidAddress);
+		}
+
+		let mut locked_labels = self.labels.write().unwrap();
+		let data = label.encode();
+		self.kv_store
+			.write(
+				ADDRESS_LABEL_PERSISTENCE_PRIMARY_NAMESPACE,
+				ADDRESS_LABEL_PERSISTENCE_SECONDARY_NAMESPACE,
+				&key,
+				&data,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					ADDRESS_LABEL_PERSISTENCE_PRIMARY_NAMESPACE,
+					ADDRESS_LABEL_PERSISTENCE_SECONDARY_NAMESPACE,
+					key,
+					e
+				);
+				Error::PersistenceFailed
+			})?;
+		locked_labels.insert(key, label);
+		Ok(())
+	}
+
+	pub(crate) fn label(&self, address: &bitcoin::Address) -> Option<String> {
+		self.labels.read().unwrap().get(&address.to_string()).cloned()
+	}
+
+	pub(crate) fn list_labels(&self) -> Vec<AddressLabel> {
+		self.labels
+			.read()
+			.unwrap()
+			.iter()
+			.filter_map(|(address, label)| {
+				let address = bitcoin::Address::<NetworkUnchecked>::from_str(address).ok()?;
+				Some(AddressLabel { address: address.assume_checked(), label: label.clone() })
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::io::utils::read_address_labels;
+	use lightning::util::test_utils::{TestLogger, TestStore};
+
+	use bitcoin::{Network, ScriptBuf};
+
+	#[test]
+	fn labels_are_validated_and_persisted() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let address_book =
+			AddressBook::new(HashMap::new(), Arc::clone(&store), Arc::clone(&logger));
+
+		let address = bitcoin::Address::p2wsh(&ScriptBuf::new(), Network::Regtest);
+
+		assert_eq!(
+			Err(Error::InvalidAddressLabel),
+			address_book.set_label(&address, String::new())
+		);
+		assert_eq!(
+			Err(Error::InvalidAddressLabel),
+			address_book.set_label(&address, "a\nb".to_string())
+		);
+		assert_eq!(
+			Err(Error::InvalidAddressLabel),
+			address_book.set_label(&address, "a".repeat(MAX_ADDRESS_LABEL_LEN + 1))
+		);
+		assert_eq!(None, address_book.label(&address));
+
+		address_book.set_label(&address, "Savings 💰".to_string()).unwrap();
+		assert_eq!(Some("Savings 💰".to_string()), address_book.label(&address));
+
+		let read_labels = read_address_labels(Arc::clone(&store), Arc::clone(&logger)).unwrap();
+		let reread_book = AddressBook::new(read_labels, store, logger);
+		assert_eq!(
+			vec![AddressLabel { address: address.clone(), label: "Savings 💰".to_string() }],
+			reread_book.list_labels()
+		);
+	}
+}
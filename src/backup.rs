@@ -0,0 +1,350 @@
+//! Objects for exporting and importing static channel backups.
+//!
+//! A static channel backup contains the serialized [`ChannelMonitor`]s and the persisted peers of
+//! a node. It is encrypted under a key derived from the node's secret key, so it can only be read
+//! by a node initialized from the same seed.
+//!
+//! As a backup might be outdated by the time it's restored, the restored channel monitors are
+//! only used to claim our funds once our counterparties closed the channels. We never broadcast
+//! our own, possibly revoked, commitment transactions for restored channels.
+//!
+//! [`ChannelMonitor`]: lightning::chain::channelmonitor::ChannelMonitor
+
+use crate::logger::{log_error, log_warn, Logger};
+use crate::peer_store::PeerInfo;
+use crate::types::KeysManager;
+
+use lightning::chain::channelmonitor::ChannelMonitor;
+use lightning::chain::transaction::OutPoint;
+use lightning::impl_writeable_tlv_based;
+use lightning::ln::ChannelId;
+use lightning::sign::InMemorySigner;
+use lightning::util::persist::{
+	KVStore, CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+	CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use lightning::util::ser::{Readable, ReadableArgs, Writeable, Writer};
+
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use bitcoin::{BlockHash, Transaction};
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+
+use std::ops::Deref;
+use std::sync::RwLock;
+
+/// The version byte prepended to encrypted channel backups.
+const CHANNEL_BACKUP_VERSION: u8 = 1;
+const CHANNEL_BACKUP_NONCE_LEN: usize = 12;
+const CHANNEL_BACKUP_KEY_DERIVATION_TAG: &[u8] = b"LDK Node static channel backup";
+
+/// The plaintext contents of a static channel backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ChannelBackup {
+	/// The serialized channel monitors, keyed by their storage key.
+	pub monitors: Vec<(String, Vec<u8>)>,
+	/// The peers we should reconnect to on recovery.
+	pub peers: Vec<PeerInfo>,
+}
+
+impl Writeable for ChannelBackup {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
+		(self.monitors.len() as u32).write(writer)?;
+		for (key, monitor_bytes) in self.monitors.iter() {
+			key.write(writer)?;
+			monitor_bytes.write(writer)?;
+		}
+		(self.peers.len() as u16).write(writer)?;
+		for peer in self.peers.iter() {
+			peer.write(writer)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for ChannelBackup {
+	fn read<R: lightning::io::Read>(
+		reader: &mut R,
+	) -> Result<Self, lightning::ln::msgs::DecodeError> {
+		let num_monitors: u32 = Readable::read(reader)?;
+		let mut monitors = Vec::new();
+		for _ in 0..num_monitors {
+			let key: String = Readable::read(reader)?;
+			let monitor_bytes: Vec<u8> = Readable::read(reader)?;
+			monitors.push((key, monitor_bytes));
+		}
+		let num_peers: u16 = Readable::read(reader)?;
+		let mut peers = Vec::with_capacity(num_peers as usize);
+		for _ in 0..num_peers {
+			peers.push(Readable::read(reader)?);
+		}
+		Ok(Self { monitors, peers })
+	}
+}
+
+impl ChannelBackup {
+	/// Returns the channels contained in the backup.
+	pub(crate) fn channels(
+		&self, keys_manager: &KeysManager,
+	) -> Result<Vec<RecoveredChannel>, lightning::io::Error> {
+		let mut channels = Vec::with_capacity(self.monitors.len());
+		for (key, monitor_bytes) in self.monitors.iter() {
+			let monitor = read_monitor(monitor_bytes, keys_manager)
+				.ok_or_else(|| invalid_monitor_error(key))?;
+			let funding_txo = monitor.get_funding_txo().0;
+			// TODO: Switch to `channel_id` with LDK 0.0.122: let channel_id = monitor.channel_id();
+			let channel_id = funding_txo.to_channel_id();
+			let counterparty_node_id =
+				monitor.get_counterparty_node_id().ok_or_else(|| invalid_monitor_error(key))?;
+			channels.push(RecoveredChannel { channel_id, counterparty_node_id, funding_txo });
+		}
+		Ok(channels)
+	}
+
+	/// Collects the channel monitors currently held in the given store.
+	pub(crate) fn from_store<K: KVStore + Sync + Send, L: Deref>(
+		kv_store: &K, peers: Vec<PeerInfo>, logger: L,
+	) -> Result<Self, lightning::io::Error>
+	where
+		L::Target: Logger,
+	{
+		let mut monitors = Vec::new();
+		for key in kv_store.list(
+			CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+			CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+		)? {
+			let monitor_bytes = kv_store
+				.read(
+					CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+					CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+					&key,
+				)
+				.map_err(|e| {
+					log_error!(logger, "Failed to read channel monitor {}: {}", key, e);
+					e
+				})?;
+			monitors.push((key, monitor_bytes));
+		}
+		Ok(Self { monitors, peers })
+	}
+
+	/// Writes the contained channel monitors to the given store.
+	///
+	/// If the store already holds a more recent state of a channel monitor, it is kept rather than
+	/// being replaced by the stale one from the backup.
+	pub(crate) fn restore_monitors<K: KVStore + Sync + Send, L: Deref>(
+		&self, kv_store: &K, keys_manager: &KeysManager, logger: L,
+	) -> Result<(), lightning::io::Error>
+	where
+		L::Target: Logger,
+	{
+		for (key, monitor_bytes) in self.monitors.iter() {
+			let backup_update_id = read_monitor(monitor_bytes, keys_manager)
+				.map(|monitor| monitor.get_latest_update_id())
+				.ok_or_else(|| {
+					log_error!(logger, "Failed to deserialize channel monitor {} from backup", key);
+					invalid_monitor_error(key)
+				})?;
+
+			let known_update_id = kv_store
+				.read(
+					CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+					CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+					key,
+				)
+				.ok()
+				.and_then(|known_bytes| read_monitor(&known_bytes, keys_manager))
+				.map(|monitor| monitor.get_latest_update_id());
+			if let Some(known_update_id) = known_update_id {
+				if known_update_id > backup_update_id {
+					log_warn!(
+						logger,
+						"Channel monitor {} in backup is stale (update {} vs. known update {}), keeping the known state",
+						key,
+						backup_update_id,
+						known_update_id
+					);
+					continue;
+				}
+			}
+
+			kv_store.write(
+				CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+				CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+				key,
+				monitor_bytes,
+			)?;
+		}
+		Ok(())
+	}
+
+	/// Encrypts the backup under a key derived from the given node secret key.
+	///
+	/// The resulting format is `version || nonce || ciphertext`.
+	pub(crate) fn encrypt(
+		&self, node_secret_key: &SecretKey, rng: &mut dyn RngCore,
+	) -> Result<Vec<u8>, ()> {
+		let mut nonce = [0u8; CHANNEL_BACKUP_NONCE_LEN];
+		rng.fill_bytes(&mut nonce);
+
+		let key = derive_backup_encryption_key(node_secret_key);
+		let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+		let ciphertext =
+			cipher.encrypt(Nonce::from_slice(&nonce), &self.encode()[..]).map_err(|_| ())?;
+
+		let mut res = Vec::with_capacity(1 + CHANNEL_BACKUP_NONCE_LEN + ciphertext.len());
+		res.push(CHANNEL_BACKUP_VERSION);
+		res.extend_from_slice(&nonce);
+		res.extend_from_slice(&ciphertext);
+		Ok(res)
+	}
+
+	/// Decrypts a backup previously created via [`ChannelBackup::encrypt`].
+	///
+	/// Fails if the backup is malformed or was created by a node with a different secret key.
+	pub(crate) fn decrypt(data: &[u8], node_secret_key: &SecretKey) -> Result<Self, ()> {
+		if data.len() <= 1 + CHANNEL_BACKUP_NONCE_LEN || data[0] != CHANNEL_BACKUP_VERSION {
+			return Err(());
+		}
+
+		let nonce = &data[1..1 + CHANNEL_BACKUP_NONCE_LEN];
+		let ciphertext = &data[1 + CHANNEL_BACKUP_NONCE_LEN..];
+
+		let key = derive_backup_encryption_key(node_secret_key);
+		let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+		let plaintext = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| ())?;
+		Readable::read(&mut &plaintext[..]).map_err(|_| ())
+	}
+}
+
+fn read_monitor(
+	monitor_bytes: &[u8], keys_manager: &KeysManager,
+) -> Option<ChannelMonitor<InMemorySigner>> {
+	<(BlockHash, ChannelMonitor<InMemorySigner>)>::read(
+		&mut &monitor_bytes[..],
+		(keys_manager, keys_manager),
+	)
+	.ok()
+	.map(|(_, monitor)| monitor)
+}
+
+fn invalid_monitor_error(key: &str) -> lightning::io::Error {
+	lightning::io::Error::new(
+		lightning::io::ErrorKind::InvalidData,
+		format!("Failed to deserialize channel monitor {}", key),
+	)
+}
+
+/// A channel restored from a static channel backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RecoveredChannel {
+	/// The channel's id.
+	pub channel_id: ChannelId,
+	/// The node id of the channel counterparty.
+	pub counterparty_node_id: PublicKey,
+	/// The channel's funding transaction output.
+	pub funding_txo: OutPoint,
+}
+
+impl_writeable_tlv_based!(RecoveredChannel, {
+	(0, channel_id, required),
+	(2, counterparty_node_id, required),
+	(4, funding_txo, required),
+});
+
+/// Keeps track of the channels restored from static channel backups.
+///
+/// The restored channel monitors might be stale, i.e., the latest commitment transactions they
+/// know of might already be revoked, and broadcasting one of them would allow our counterparty to
+/// claim the full channel balance. We therefore refuse to broadcast any transaction spending the
+/// funding output of a recovered channel, and rather ask our counterparties to force-close.
+pub(crate) struct RecoveredChannels {
+	channels: RwLock<Vec<RecoveredChannel>>,
+}
+
+impl RecoveredChannels {
+	pub(crate) fn new(channels: Vec<RecoveredChannel>) -> Self {
+		Self { channels: RwLock::new(channels) }
+	}
+
+	/// Adds the given channels, returning the full set of recovered channels.
+	pub(crate) fn add(&self, channels: Vec<RecoveredChannel>) -> Vec<RecoveredChannel> {
+		let mut locked_channels = self.channels.write().unwrap();
+		for channel in channels {
+			if !locked_channels.iter().any(|c| c.funding_txo == channel.funding_txo) {
+				locked_channels.push(channel);
+			}
+		}
+		locked_channels.clone()
+	}
+
+	/// Returns the ids of the recovered channels we had with the given counterparty.
+	pub(crate) fn channels_with_peer(&self, counterparty_node_id: &PublicKey) -> Vec<ChannelId> {
+		let locked_channels = self.channels.read().unwrap();
+		locked_channels
+			.iter()
+			.filter(|c| c.counterparty_node_id == *counterparty_node_id)
+			.map(|c| c.channel_id)
+			.collect()
+	}
+
+	/// Returns whether the given transaction spends the funding output of a recovered channel.
+	pub(crate) fn spends_funding_output(&self, tx: &Transaction) -> bool {
+		let locked_channels = self.channels.read().unwrap();
+		tx.input.iter().any(|input| {
+			locked_channels
+				.iter()
+				.any(|c| c.funding_txo.into_bitcoin_outpoint() == input.previous_output)
+		})
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.channels.read().unwrap().is_empty()
+	}
+}
+
+fn derive_backup_encryption_key(node_secret_key: &SecretKey) -> [u8; 32] {
+	let mut engine = Sha256::engine();
+	engine.input(CHANNEL_BACKUP_KEY_DERIVATION_TAG);
+	engine.input(&node_secret_key.secret_bytes());
+	Sha256::from_engine(engine).to_byte_array()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::PeerTransport;
+
+	use lightning::ln::msgs::SocketAddress;
+
+	use bitcoin::secp256k1::{PublicKey, Secp256k1};
+
+	use std::str::FromStr;
+
+	#[test]
+	fn channel_backup_encryption_roundtrip() {
+		let secp_ctx = Secp256k1::new();
+		let node_secret_key = SecretKey::from_slice(&[42; 32]).unwrap();
+		let other_secret_key = SecretKey::from_slice(&[43; 32]).unwrap();
+
+		let peer = PeerInfo {
+			node_id: PublicKey::from_secret_key(&secp_ctx, &other_secret_key),
+			address: SocketAddress::from_str("127.0.0.1:9735").unwrap(),
+			transport: PeerTransport::Tcp,
+			is_persistent: true,
+		};
+		let backup = ChannelBackup {
+			monitors: vec![("0000_0".to_string(), vec![1, 2, 3])],
+			peers: vec![peer],
+		};
+
+		let encrypted = backup.encrypt(&node_secret_key, &mut rand::thread_rng()).unwrap();
+		assert_eq!(ChannelBackup::decrypt(&encrypted, &node_secret_key), Ok(backup));
+		assert!(ChannelBackup::decrypt(&encrypted, &other_secret_key).is_err());
+		assert!(ChannelBackup::decrypt(&encrypted[..10], &node_secret_key).is_err());
+	}
+}
@@ -12,9 +12,37 @@ use crate::sweep::SpendableOutputInfo;
 #[derive(Debug, Clone)]
 pub struct BalanceDetails {
 	/// The total balance of our on-chain wallet.
+	///
+	/// This includes all confirmed and unconfirmed funds, including immature coinbase outputs,
+	/// dust, and the funds we retain as [`total_anchor_channels_reserve_sats`]. Not all of it may
+	/// hence be spendable.
+	///
+	/// [`total_anchor_channels_reserve_sats`]: Self::total_anchor_channels_reserve_sats
 	pub total_onchain_balance_sats: u64,
 	/// The currently spendable balance of our on-chain wallet.
+	///
+	/// This includes any confirmed funds and unconfirmed change outputs of our own transactions,
+	/// minus [`total_anchor_channels_reserve_sats`]. It does, however, include UTXOs which are
+	/// uneconomical to spend at the current fee rate. See [`Node::spendable_onchain_balance_sats`]
+	/// for a balance excluding them.
+	///
+	/// [`total_anchor_channels_reserve_sats`]: Self::total_anchor_channels_reserve_sats
+	/// [`Node::spendable_onchain_balance_sats`]: crate::Node::spendable_onchain_balance_sats
 	pub spendable_onchain_balance_sats: u64,
+	/// The share of our total balance that we retain as an emergency reserve to (hopefully) be
+	/// able to spend the Anchor outputs when one of our channels is closed.
+	///
+	/// This amounts to [`AnchorChannelsConfig::per_channel_reserve_sats`] for each of our Anchor
+	/// channels, except for those with peers listed in
+	/// [`AnchorChannelsConfig::trusted_peers_no_reserve`].
+	///
+	/// [`AnchorChannelsConfig::per_channel_reserve_sats`]: crate::AnchorChannelsConfig::per_channel_reserve_sats
+	/// [`AnchorChannelsConfig::trusted_peers_no_reserve`]: crate::AnchorChannelsConfig::trusted_peers_no_reserve
+	///
+	/// See [`AnchorChannelsConfig`] for more information.
+	///
+	/// [`AnchorChannelsConfig`]: crate::AnchorChannelsConfig
+	pub total_anchor_channels_reserve_sats: u64,
 	/// The total balance that we would be able to claim across all our Lightning channels.
 	///
 	/// Note this excludes balances that we are unsure if we are able to claim (e.g., as we are
@@ -1,28 +1,56 @@
+use crate::address_book::AddressBook;
+use crate::backup::{ChannelBackup, RecoveredChannels};
+use crate::chain::bitcoind_rpc::BitcoindRpcClient;
+#[cfg(feature = "cbf")]
+use crate::chain::cbf::CbfClient;
 use crate::config::{
-	Config, BDK_CLIENT_CONCURRENCY, BDK_CLIENT_STOP_GAP, DEFAULT_ESPLORA_SERVER_URL,
+	AnchorChannelsConfig, BlindedPathConfig, Config, EsploraSyncConfig, LogRotationConfig,
+	MaxFeePolicy, RetryPolicy, BDK_CLIENT_CONCURRENCY, BDK_CLIENT_CONCURRENCY_MAXIMUM,
+	BDK_CLIENT_STOP_GAP, CUSTOM_ONION_MESSAGE_TLV_TYPE_MIN, DEFAULT_ESPLORA_SERVER_URL,
+	MAX_CHANNEL_CONFIRMATION_TARGET, NODE_ANN_BCAST_INTERVAL_SAFE_MINIMUM_SECS,
 	WALLET_KEYS_SEED_LEN,
 };
 use crate::event::EventQueue;
 use crate::fee_estimator::OnchainFeeEstimator;
 use crate::gossip::GossipSource;
+use crate::hold_invoice::HoldInvoiceHandler;
 use crate::io;
+use crate::io::async_kv_store::{
+	read_network_graph_async, read_payments_async, read_scorer_async, AsyncKVStore,
+	AsyncKVStoreAdapter,
+};
+use crate::io::backup_sink_store::{BackupSink, BackupSinkKVStore};
+use crate::io::batch_kv_store::BatchKVStore;
+use crate::io::retrying_store::{KVStoreRetryPolicy, RetryingKVStore};
 use crate::io::sqlite_store::SqliteStore;
 use crate::liquidity::LiquiditySource;
-use crate::logger::{log_error, FilesystemLogger, Logger};
-use crate::message_handler::NodeCustomMessageHandler;
-use crate::payment_store::PaymentStore;
+use crate::logger::{
+	log_error, log_info, log_warn, FilesystemLogger, Log, LogCallback, LogFormat, LogRecord, Logger,
+};
+use crate::message_handler::{NodeCustomMessageHandler, RecoveryMessageHandler};
+use crate::onchain_monitor::{OnchainPaymentMonitor, OnchainPaymentMonitorState};
+use crate::onion_message::{NodeOffersMessageHandler, NodeOnionMessageHandler};
+use crate::payment_retry::PaymentRetrier;
+use crate::payment_store::{PaymentDetails, PaymentStore};
+use crate::peer_monitor::PeerConnectionMonitor;
 use crate::peer_store::PeerStore;
+use crate::router::{ChannelPenalties, NodeRouter};
 use crate::sweep::OutputSweeper;
 use crate::tx_broadcaster::TransactionBroadcaster;
 use crate::types::{
-	ChainMonitor, ChannelManager, FakeMessageRouter, GossipSync, KeysManager, NetworkGraph,
-	OnionMessenger, PeerManager,
+	BumpTransactionEventHandler, ChainMonitor, ChainSource, ChannelDecision, ChannelManager,
+	CustomOnionMessageHandler, GossipSync, InboundChannelPolicy, KeysManager, MessageRouter,
+	NetworkGraph, OnionMessenger, PeerManager, PendingChannelRequest, Scorer,
 };
-use crate::wallet::Wallet;
-use crate::{LogLevel, Node};
+use crate::wallet::{Wallet, WalletBlockchain};
+use crate::{node_alias_bytes, LogLevel, Node};
 
+use lightning::chain::chaininterface::ConfirmationTarget;
 use lightning::chain::{chainmonitor, BestBlock, Watch};
-use lightning::ln::channelmanager::{self, ChainParameters, ChannelManagerReadArgs};
+use lightning::events::bump_transaction::Wallet as LdkWallet;
+use lightning::ln::channelmanager::{
+	self, ChainParameters, ChannelManagerReadArgs, MIN_FINAL_CLTV_EXPIRY_DELTA,
+};
 use lightning::ln::msgs::{RoutingMessageHandler, SocketAddress};
 use lightning::ln::peer_handler::{IgnoringMessageHandler, MessageHandler};
 use lightning::routing::router::DefaultRouter;
@@ -42,9 +70,15 @@ use lightning_persister::fs_store::FilesystemStore;
 
 use lightning_transaction_sync::EsploraSyncClient;
 
+#[cfg(lsps1)]
+use lightning_liquidity::lsps1::client::LSPS1ClientConfig;
 use lightning_liquidity::lsps2::client::LSPS2ClientConfig;
 use lightning_liquidity::{LiquidityClientConfig, LiquidityManager};
 
+#[cfg(any(postgres, postgres_test))]
+use crate::io::postgres_store::PostgresStore;
+#[cfg(any(redis, redis_test))]
+use crate::io::redis_store::RedisStore;
 #[cfg(any(vss, vss_test))]
 use crate::io::vss_store::VssStore;
 use bdk::bitcoin::secp256k1::Secp256k1;
@@ -57,21 +91,27 @@ use bip39::Mnemonic;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::{BlockHash, Network};
 
-#[cfg(any(vss, vss_test))]
-use bitcoin::bip32::ChildNumber;
+use bitcoin::bip32::{ChildNumber, DerivationPath, ExtendedPubKey};
+
+use rand::{thread_rng, RngCore};
+
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::default::Default;
 use std::fmt;
 use std::fs;
 use std::io::Cursor;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex, RwLock};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug, Clone)]
 enum ChainDataSourceConfig {
 	Esplora(String),
+	BitcoindRpc { rpc_host: String, rpc_port: u16, rpc_user: String, rpc_password: String },
+	Cbf { peer_address: String, fee_oracle_url: String },
 }
 
 #[derive(Debug, Clone)]
@@ -91,11 +131,79 @@ enum GossipSourceConfig {
 struct LiquiditySourceConfig {
 	// LSPS2 service's (address, node_id, token)
 	lsps2_service: Option<(SocketAddress, PublicKey, Option<String>)>,
+	// LSPS1 service's (address, node_id, token)
+	#[cfg(lsps1)]
+	lsps1_service: Option<(SocketAddress, PublicKey, Option<String>)>,
 }
 
 impl Default for LiquiditySourceConfig {
 	fn default() -> Self {
-		Self { lsps2_service: None }
+		Self {
+			lsps2_service: None,
+			#[cfg(lsps1)]
+			lsps1_service: None,
+		}
+	}
+}
+
+struct EntropyRng(Mutex<Box<dyn RngCore + Send>>);
+
+impl fmt::Debug for EntropyRng {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("EntropyRng").finish_non_exhaustive()
+	}
+}
+
+#[derive(Clone)]
+struct BackupSinkConfig(Arc<dyn BackupSink>);
+
+impl fmt::Debug for BackupSinkConfig {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("BackupSinkConfig").finish_non_exhaustive()
+	}
+}
+
+#[derive(Clone)]
+struct InboundChannelPolicyConfig(Arc<InboundChannelPolicy>);
+
+impl fmt::Debug for InboundChannelPolicyConfig {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("InboundChannelPolicyConfig").finish_non_exhaustive()
+	}
+}
+
+#[derive(Clone)]
+struct LogSinkConfig {
+	sink: Arc<dyn Log>,
+	write_to_filesystem: bool,
+}
+
+impl fmt::Debug for LogSinkConfig {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("LogSinkConfig")
+			.field("write_to_filesystem", &self.write_to_filesystem)
+			.finish_non_exhaustive()
+	}
+}
+
+#[derive(Clone)]
+struct ScoringParamsConfig {
+	decay_params: ProbabilisticScoringDecayParameters,
+	fee_params: ProbabilisticScoringFeeParameters,
+}
+
+impl Default for ScoringParamsConfig {
+	fn default() -> Self {
+		let decay_params = ProbabilisticScoringDecayParameters::default();
+		let fee_params = ProbabilisticScoringFeeParameters::default();
+		Self { decay_params, fee_params }
+	}
+}
+
+impl fmt::Debug for ScoringParamsConfig {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		// LDK's scoring parameters don't implement `Debug`.
+		f.debug_struct("ScoringParamsConfig").finish_non_exhaustive()
 	}
 }
 
@@ -108,12 +216,38 @@ pub enum BuildError {
 	InvalidSeedBytes,
 	/// The given seed file is invalid, e.g., has invalid length, or could not be read.
 	InvalidSeedFile,
+	/// The given seed file could not be decrypted as the configured passphrase is invalid or
+	/// missing.
+	InvalidSeedFilePassphrase,
 	/// The current system time is invalid, clocks might have gone backwards.
 	InvalidSystemTime,
 	/// The a read channel monitor is invalid.
 	InvalidChannelMonitor,
 	/// The given listening addresses are invalid, e.g. too many were passed.
 	InvalidListeningAddresses,
+	/// The given announced addresses are invalid, e.g. too many were passed or they are not
+	/// publicly routable.
+	InvalidAnnouncedAddresses,
+	/// The given node alias is invalid, e.g. it exceeds 32 bytes.
+	InvalidNodeAlias,
+	/// The given static channel backup is invalid, e.g., it was created with a different seed or
+	/// the node isn't fresh.
+	InvalidChannelBackup,
+	/// The given store snapshot is invalid, e.g., it is malformed or the node isn't fresh.
+	InvalidSnapshot,
+	/// The given chain sync config is invalid, e.g., it contains an interval of zero.
+	InvalidChainSyncConfig,
+	/// The given payment retry policy is invalid, e.g., it allows for zero attempts.
+	InvalidPaymentRetryPolicy,
+	/// The given channel confirmation target is invalid.
+	InvalidChannelConfirmationTarget,
+	/// The given minimum final CLTV expiry delta is invalid.
+	InvalidMinFinalCltvExpiryDelta,
+	/// The given blinded path config is invalid, e.g., it requires too many hops.
+	InvalidBlindedPathConfig,
+	/// The given range of custom onion message TLV types is invalid, e.g., it includes types
+	/// reserved by the Lightning specification.
+	InvalidCustomOnionMessageTlvTypes,
 	/// We failed to read data from the [`KVStore`].
 	ReadFailed,
 	/// We failed to write data to the [`KVStore`].
@@ -126,6 +260,10 @@ pub enum BuildError {
 	WalletSetupFailed,
 	/// We failed to setup the logger.
 	LoggerSetupFailed,
+	/// We failed to setup the chain source, e.g., as it requires a feature that isn't enabled.
+	ChainSourceSetupFailed,
+	/// We failed to migrate the persisted data to the current schema version.
+	MigrationFailed,
 }
 
 impl fmt::Display for BuildError {
@@ -133,6 +271,9 @@ impl fmt::Display for BuildError {
 		match *self {
 			Self::InvalidSeedBytes => write!(f, "Given seed bytes are invalid."),
 			Self::InvalidSeedFile => write!(f, "Given seed file is invalid or could not be read."),
+			Self::InvalidSeedFilePassphrase => {
+				write!(f, "Given seed file passphrase is invalid or missing.")
+			},
 			Self::InvalidSystemTime => {
 				write!(f, "System time is invalid. Clocks might have gone back in time.")
 			},
@@ -140,12 +281,30 @@ impl fmt::Display for BuildError {
 				write!(f, "Failed to watch a deserialized ChannelMonitor")
 			},
 			Self::InvalidListeningAddresses => write!(f, "Given listening addresses are invalid."),
+			Self::InvalidAnnouncedAddresses => write!(f, "Given announced addresses are invalid."),
+			Self::InvalidNodeAlias => write!(f, "Given node alias is invalid."),
+			Self::InvalidChannelBackup => write!(f, "Given channel backup is invalid."),
+			Self::InvalidSnapshot => write!(f, "Given store snapshot is invalid."),
+			Self::InvalidChainSyncConfig => write!(f, "Given chain sync config is invalid."),
+			Self::InvalidPaymentRetryPolicy => write!(f, "Given payment retry policy is invalid."),
+			Self::InvalidChannelConfirmationTarget => {
+				write!(f, "Given channel confirmation target is invalid.")
+			},
+			Self::InvalidMinFinalCltvExpiryDelta => {
+				write!(f, "Given minimum final CLTV expiry delta is invalid.")
+			},
+			Self::InvalidBlindedPathConfig => write!(f, "Given blinded path config is invalid."),
+			Self::InvalidCustomOnionMessageTlvTypes => {
+				write!(f, "Given range of custom onion message TLV types is invalid.")
+			},
 			Self::ReadFailed => write!(f, "Failed to read from store."),
 			Self::WriteFailed => write!(f, "Failed to write to store."),
 			Self::StoragePathAccessFailed => write!(f, "Failed to access the given storage path."),
 			Self::KVStoreSetupFailed => write!(f, "Failed to setup KVStore."),
 			Self::WalletSetupFailed => write!(f, "Failed to setup onchain wallet."),
 			Self::LoggerSetupFailed => write!(f, "Failed to setup the logger."),
+			Self::ChainSourceSetupFailed => write!(f, "Failed to setup the chain source."),
+			Self::MigrationFailed => write!(f, "Failed to migrate the persisted data."),
 		}
 	}
 }
@@ -163,9 +322,27 @@ impl std::error::Error for BuildError {}
 pub struct NodeBuilder {
 	config: Config,
 	entropy_source_config: Option<EntropySourceConfig>,
+	seed_file_passphrase: Option<String>,
+	entropy_rng: Option<EntropyRng>,
 	chain_data_source_config: Option<ChainDataSourceConfig>,
+	wallet_birthday_height: Option<u32>,
+	esplora_concurrency: u8,
+	fee_rate_floors: HashMap<ConfirmationTarget, u32>,
 	gossip_source_config: Option<GossipSourceConfig>,
 	liquidity_source_config: Option<LiquiditySourceConfig>,
+	scoring_params_config: ScoringParamsConfig,
+	skip_corrupt_payments: bool,
+	payment_retry_policy: RetryPolicy,
+	node_alias: Option<String>,
+	node_color: [u8; 3],
+	phantom_cross_node_seed: Option<[u8; 32]>,
+	inbound_channel_policy: Option<InboundChannelPolicyConfig>,
+	trusted_peers_0conf: Option<Vec<PublicKey>>,
+	channel_backup: Option<Vec<u8>>,
+	snapshot: Option<Vec<u8>>,
+	backup_sink: Option<BackupSinkConfig>,
+	module_log_levels: HashMap<String, LogLevel>,
+	log_sink: Option<LogSinkConfig>,
 }
 
 impl NodeBuilder {
@@ -179,15 +356,51 @@ impl NodeBuilder {
 	pub fn from_config(config: Config) -> Self {
 		let config = config;
 		let entropy_source_config = None;
+		let seed_file_passphrase = None;
+		let entropy_rng = None;
 		let chain_data_source_config = None;
+		let wallet_birthday_height = None;
+		let esplora_concurrency = BDK_CLIENT_CONCURRENCY;
+		let fee_rate_floors = HashMap::new();
 		let gossip_source_config = None;
 		let liquidity_source_config = None;
+		let scoring_params_config = ScoringParamsConfig::default();
+		let skip_corrupt_payments = true;
+		let payment_retry_policy = RetryPolicy::default();
+		let node_alias = None;
+		let node_color = [0; 3];
+		let phantom_cross_node_seed = None;
+		let inbound_channel_policy = None;
+		let trusted_peers_0conf = None;
+		let channel_backup = None;
+		let snapshot = None;
+		let backup_sink = None;
+		let module_log_levels = HashMap::new();
+		let log_sink = None;
 		Self {
 			config,
 			entropy_source_config,
+			seed_file_passphrase,
+			entropy_rng,
 			chain_data_source_config,
+			wallet_birthday_height,
+			esplora_concurrency,
+			fee_rate_floors,
 			gossip_source_config,
 			liquidity_source_config,
+			scoring_params_config,
+			skip_corrupt_payments,
+			payment_retry_policy,
+			node_alias,
+			node_color,
+			phantom_cross_node_seed,
+			inbound_channel_policy,
+			trusted_peers_0conf,
+			channel_backup,
+			snapshot,
+			backup_sink,
+			module_log_levels,
+			log_sink,
 		}
 	}
 
@@ -200,6 +413,27 @@ impl NodeBuilder {
 		self
 	}
 
+	/// Configures a passphrase used to encrypt the seed file at rest.
+	///
+	/// If set, newly generated seed files will be stored encrypted, and existing plaintext seed
+	/// files will be migrated to the encrypted format on the next start. Only applies if the wallet
+	/// entropy is sourced from a seed file.
+	pub fn set_entropy_seed_file_passphrase(&mut self, passphrase: String) -> &mut Self {
+		self.seed_file_passphrase = Some(passphrase);
+		self
+	}
+
+	/// Configures the source of randomness used when generating a new seed file.
+	///
+	/// By default, a thread-local CSPRNG is used. Setting a deterministic source allows to create
+	/// reproducible nodes, e.g., for testing purposes. Existing seed files are read as-is.
+	///
+	/// **Note:** The given source must be cryptographically secure unless used for testing.
+	pub fn set_entropy_rng(&mut self, rng: Box<dyn RngCore + Send>) -> &mut Self {
+		self.entropy_rng = Some(EntropyRng(Mutex::new(rng)));
+		self
+	}
+
 	/// Configures the [`Node`] instance to source its wallet entropy from the given 64 seed bytes.
 	pub fn set_entropy_seed_bytes(&mut self, seed_bytes: Vec<u8>) -> Result<&mut Self, BuildError> {
 		if seed_bytes.len() != WALLET_KEYS_SEED_LEN {
@@ -228,6 +462,89 @@ impl NodeBuilder {
 		self
 	}
 
+	/// Configures the intervals in-between background syncs and fee rate updates, as well as the
+	/// number of concurrent requests made against the Esplora server.
+	///
+	/// The intervals override the respective values of the [`Config`] the builder was created
+	/// with. Intervals of zero are rejected, while the number of concurrent requests is clamped
+	/// to a maximum of 32.
+	pub fn set_chain_sync_config(
+		&mut self, sync_config: EsploraSyncConfig,
+	) -> Result<&mut Self, BuildError> {
+		if sync_config.onchain_wallet_sync_interval_secs == 0
+			|| sync_config.lightning_wallet_sync_interval_secs == 0
+			|| sync_config.fee_rate_cache_update_interval_secs == 0
+		{
+			return Err(BuildError::InvalidChainSyncConfig);
+		}
+
+		self.config.onchain_wallet_sync_interval_secs =
+			sync_config.onchain_wallet_sync_interval_secs;
+		self.config.wallet_sync_interval_secs = sync_config.lightning_wallet_sync_interval_secs;
+		self.config.fee_rate_cache_update_interval_secs =
+			sync_config.fee_rate_cache_update_interval_secs;
+		self.esplora_concurrency =
+			sync_config.max_concurrent_requests.clamp(1, BDK_CLIENT_CONCURRENCY_MAXIMUM);
+		Ok(self)
+	}
+
+	/// Sets a minimum fee rate, in satoshis per 1000 weight units, for the given confirmation
+	/// target.
+	///
+	/// If the fee rate estimated by the chain source is below the floor, the floor is used
+	/// instead. Note that we never use fee rates below the minimum relay fee rate, independently
+	/// of the configured floor.
+	pub fn set_fee_rate_floor(
+		&mut self, confirmation_target: ConfirmationTarget, sat_per_kwu: u32,
+	) -> &mut Self {
+		self.fee_rate_floors.insert(confirmation_target, sat_per_kwu);
+		self
+	}
+
+	/// Configures the [`Node`] instance to source its chain data from the given Bitcoin Core RPC
+	/// endpoint.
+	///
+	/// Blocks are fetched and connected in full, while fee rates are retrieved via
+	/// `estimatesmartfee` and transactions are broadcast via `sendrawtransaction`.
+	pub fn set_chain_source_bitcoind_rpc(
+		&mut self, rpc_host: String, rpc_port: u16, rpc_user: String, rpc_password: String,
+	) -> &mut Self {
+		self.chain_data_source_config =
+			Some(ChainDataSourceConfig::BitcoindRpc { rpc_host, rpc_port, rpc_user, rpc_password });
+		self
+	}
+
+	/// Configures the [`Node`] instance to source its chain data via compact block filters
+	/// (BIP157/158) from the peer at the given `host:port` address.
+	///
+	/// The peer needs to serve compact block filters, e.g., a Bitcoin Core node run with
+	/// `-blockfilterindex=1 -peerblockfilters=1`. As filters don't allow to estimate fee rates,
+	/// fee rates are retrieved from the Esplora server at the given `fee_oracle_url`.
+	///
+	/// **Note:** The block headers are kept in memory and hence downloaded from genesis every
+	/// time the node is started, which might take several minutes on mainnet. Afterwards, each
+	/// sync downloads the filters of all blocks since the last sync, as well as any full blocks
+	/// whose filters match our scripts.
+	///
+	/// Requires the `cbf` feature to be enabled, otherwise building the node will fail.
+	pub fn set_chain_source_cbf(
+		&mut self, peer_address: String, fee_oracle_url: String,
+	) -> &mut Self {
+		self.chain_data_source_config =
+			Some(ChainDataSourceConfig::Cbf { peer_address, fee_oracle_url });
+		self
+	}
+
+	/// Sets the height of the block the on-chain wallet was created at, i.e., its birthday.
+	///
+	/// When syncing via the Bitcoin Core RPC or compact block filter chain sources, the initial
+	/// sync of the on-chain wallet scans the chain for wallet transactions starting from this
+	/// height. If not set, the entire chain is scanned, which might take a long time on mainnet.
+	pub fn set_wallet_birthday_height(&mut self, height: u32) -> &mut Self {
+		self.wallet_birthday_height = Some(height);
+		self
+	}
+
 	/// Configures the [`Node`] instance to source its gossip data from the Lightning peer-to-peer
 	/// network.
 	pub fn set_gossip_source_p2p(&mut self) -> &mut Self {
@@ -261,6 +578,21 @@ impl NodeBuilder {
 		self
 	}
 
+	/// Configures the [`Node`] instance to buy channels from the given
+	/// [LSPS1](https://github.com/BitcoinAndLightningLayerSpecs/lsp/blob/main/LSPS1/README.md)
+	/// service.
+	///
+	/// The given `token` will be used by the LSP to authenticate the user.
+	#[cfg(lsps1)]
+	pub fn set_liquidity_source_lsps1(
+		&mut self, address: SocketAddress, node_id: PublicKey, token: Option<String>,
+	) -> &mut Self {
+		let liquidity_source_config =
+			self.liquidity_source_config.get_or_insert(LiquiditySourceConfig::default());
+		liquidity_source_config.lsps1_service = Some((address, node_id, token));
+		self
+	}
+
 	/// Sets the used storage directory path.
 	pub fn set_storage_dir_path(&mut self, storage_dir_path: String) -> &mut Self {
 		self.config.storage_dir_path = storage_dir_path;
@@ -291,12 +623,368 @@ impl NodeBuilder {
 		Ok(self)
 	}
 
+	/// Sets the addresses which [`Node`] will announce to the gossip network that it accepts
+	/// connections on.
+	///
+	/// If not set, the listening addresses will be announced instead.
+	pub fn set_announced_addresses(
+		&mut self, announced_addresses: Vec<SocketAddress>,
+	) -> Result<&mut Self, BuildError> {
+		if announced_addresses.len() > 100
+			|| !announced_addresses.iter().all(is_valid_announced_address)
+		{
+			return Err(BuildError::InvalidAnnouncedAddresses);
+		}
+
+		self.config.announced_addresses = Some(announced_addresses);
+		Ok(self)
+	}
+
+	/// Sets the address of the SOCKS5 proxy, e.g., a local Tor daemon, used to connect to peers
+	/// with `.onion` addresses.
+	pub fn set_tor_proxy(&mut self, proxy_address: SocketAddr) -> &mut Self {
+		self.config.tor_proxy_address = Some(proxy_address);
+		self
+	}
+
+	/// Sets the time in-between broadcasts of our node announcement.
+	///
+	/// **Note:** Intervals below 10 minutes risk our announcements being rate-limited by our peers.
+	pub fn set_node_announcement_interval(&mut self, interval: Duration) -> &mut Self {
+		self.config.node_announcement_interval_secs = interval.as_secs();
+		self
+	}
+
+	/// Sets the expiry time of the invoices we create if no explicit expiry is given.
+	///
+	/// See [`Config::default_invoice_expiry_secs`] for more information.
+	pub fn set_invoice_expiry_default(&mut self, expiry: Duration) -> &mut Self {
+		self.config.default_invoice_expiry_secs = expiry.as_secs().min(u32::MAX as u64) as u32;
+		self
+	}
+
+	/// Sets the backoff schedule used when reconnecting to persistent peers.
+	///
+	/// After a persistent peer disconnected, we wait `initial_delay` before trying to reconnect,
+	/// doubling the delay after every failed attempt up to `max_delay`.
+	pub fn set_persistent_peer_reconnection_backoff(
+		&mut self, initial_delay: Duration, max_delay: Duration,
+	) -> &mut Self {
+		self.config.persistent_peer_reconnection_initial_delay_secs = initial_delay.as_secs();
+		self.config.persistent_peer_reconnection_max_delay_secs = max_delay.as_secs();
+		self
+	}
+
 	/// Sets the level at which [`Node`] will log messages.
 	pub fn set_log_level(&mut self, level: LogLevel) -> &mut Self {
 		self.config.log_level = level;
 		self
 	}
 
+	/// Overrides the log level for all modules whose path starts with the given module path.
+	///
+	/// This allows, e.g., to silence gossip-related logs via `lightning::routing::gossip` while
+	/// keeping channel-related logs of `lightning::ln` at [`LogLevel::Debug`]. If multiple
+	/// overrides match a module, the most specific one is used.
+	pub fn set_module_log_level(&mut self, module_path: String, level: LogLevel) -> &mut Self {
+		self.module_log_levels.insert(module_path, level);
+		self
+	}
+
+	/// Sets the configuration of log file rotation.
+	///
+	/// If set to `None`, log files won't be rotated. Please refer to [`LogRotationConfig`] for
+	/// more information.
+	pub fn set_log_rotation_config(
+		&mut self, log_rotation_config: Option<LogRotationConfig>,
+	) -> &mut Self {
+		self.config.log_rotation_config = log_rotation_config;
+		self
+	}
+
+	/// Configures a [`Log`] sink that receives all log records passing the configured level
+	/// filters.
+	///
+	/// If `write_to_filesystem` is `false`, logs will be handed to the sink only, i.e., no log
+	/// files will be written.
+	pub fn set_log_sink(&mut self, sink: Box<dyn Log>, write_to_filesystem: bool) -> &mut Self {
+		self.log_sink = Some(LogSinkConfig { sink: Arc::from(sink), write_to_filesystem });
+		self
+	}
+
+	/// Configures a callback that is invoked for every log record passing the configured level
+	/// filters, *instead* of writing logs to disk.
+	///
+	/// This allows embedding applications to forward logs to their platform's logging facilities.
+	/// If no callback or [`Log`] sink is set, logs are written to the filesystem.
+	pub fn set_log_callback(
+		&mut self, callback: Box<dyn Fn(LogRecord) + Send + Sync>,
+	) -> &mut Self {
+		self.set_log_sink(Box::new(LogCallback(callback)), false)
+	}
+
+	/// Sets the format in which [`Node`] will write log lines.
+	pub fn set_log_format(&mut self, format: LogFormat) -> &mut Self {
+		self.config.log_format = format;
+		self
+	}
+
+	/// Sets the configuration of Anchor channels.
+	///
+	/// If set to `None`, Anchor channels won't be negotiated and no on-chain reserve will be
+	/// maintained for them. Please refer to [`AnchorChannelsConfig`] for more information on the
+	/// reserve requirement.
+	pub fn set_anchor_channels_config(
+		&mut self, anchor_channels_config: Option<AnchorChannelsConfig>,
+	) -> &mut Self {
+		self.config.anchor_channels_config = anchor_channels_config;
+		self
+	}
+
+	/// Sets the decay parameters used by the [`ProbabilisticScorer`] when scoring channels.
+	///
+	/// If not set, [`ProbabilisticScoringDecayParameters::default`] will be used.
+	///
+	/// **Note:** Changing these parameters after the scorer has been persisted won't reset it, but
+	/// will have the previously learned data be interpreted according to the new parameters.
+	pub fn set_scoring_decay_parameters(
+		&mut self, decay_params: ProbabilisticScoringDecayParameters,
+	) -> &mut Self {
+		self.scoring_params_config.decay_params = decay_params;
+		self
+	}
+
+	/// Sets the fee penalty parameters used by the [`ProbabilisticScorer`] when routing payments.
+	///
+	/// If not set, [`ProbabilisticScoringFeeParameters::default`] will be used.
+	pub fn set_scoring_fee_parameters(
+		&mut self, fee_params: ProbabilisticScoringFeeParameters,
+	) -> &mut Self {
+		self.scoring_params_config.fee_params = fee_params;
+		self
+	}
+
+	/// Configures whether payment entries that fail to deserialize should be skipped on startup.
+	///
+	/// If enabled (the default), corrupt payment entries are logged and skipped, as the payment
+	/// history is not critical for the operation of the node. If disabled, any corrupt entry will
+	/// have building the [`Node`] fail with [`BuildError::ReadFailed`].
+	pub fn set_skip_corrupt_payments(&mut self, skip_corrupt_payments: bool) -> &mut Self {
+		self.skip_corrupt_payments = skip_corrupt_payments;
+		self
+	}
+
+	/// Sets the policy according to which failed outbound payments are retried.
+	///
+	/// Only once the policy is exhausted will an [`Event::PaymentFailed`] be emitted. The policy
+	/// must allow for at least one attempt.
+	///
+	/// [`Event::PaymentFailed`]: crate::Event::PaymentFailed
+	pub fn set_payment_retry_policy(
+		&mut self, retry_policy: RetryPolicy,
+	) -> Result<&mut Self, BuildError> {
+		if retry_policy.max_attempts == 0 {
+			return Err(BuildError::InvalidPaymentRetryPolicy);
+		}
+
+		self.payment_retry_policy = retry_policy;
+		Ok(self)
+	}
+
+	/// Sets the number of confirmations the funding transaction of a channel needs to reach
+	/// before the channel is considered ready.
+	///
+	/// Must be between 1 and 144. See [`Config::channel_confirmation_target`] for more
+	/// information.
+	pub fn set_channel_confirmation_target(
+		&mut self, min_confs: u32,
+	) -> Result<&mut Self, BuildError> {
+		if !is_valid_channel_confirmation_target(min_confs) {
+			return Err(BuildError::InvalidChannelConfirmationTarget);
+		}
+
+		self.config.channel_confirmation_target = Some(min_confs);
+		Ok(self)
+	}
+
+	/// Sets the default minimum number of blocks left until their HTLCs expire that payments to
+	/// our invoices need to arrive with.
+	///
+	/// Must not be below LDK's minimum of 24 blocks. See [`Config::min_final_cltv_expiry_delta`]
+	/// for more information.
+	pub fn set_min_final_cltv_expiry_delta(
+		&mut self, min_final_cltv_expiry_delta: u16,
+	) -> Result<&mut Self, BuildError> {
+		if min_final_cltv_expiry_delta < MIN_FINAL_CLTV_EXPIRY_DELTA {
+			return Err(BuildError::InvalidMinFinalCltvExpiryDelta);
+		}
+
+		self.config.min_final_cltv_expiry_delta = min_final_cltv_expiry_delta;
+		Ok(self)
+	}
+
+	/// Sets the options pertaining to the blinded paths included in our BOLT 12 offers and
+	/// invoices.
+	///
+	/// The number of hops must not exceed `3`. See [`BlindedPathConfig`] for more information.
+	pub fn set_blinded_path_config(
+		&mut self, blinded_path_config: BlindedPathConfig,
+	) -> Result<&mut Self, BuildError> {
+		if !blinded_path_config.is_valid() {
+			return Err(BuildError::InvalidBlindedPathConfig);
+		}
+
+		self.config.blinded_path_config = blinded_path_config;
+		Ok(self)
+	}
+
+	/// Sets the range of TLV types of the custom onion messages we send and receive.
+	///
+	/// The range must not include types below `2^16`, which are reserved for onion messages
+	/// defined by the Lightning specification. See [`Config::custom_onion_message_tlv_type_min`]
+	/// for more information.
+	pub fn set_custom_onion_message_tlv_types(
+		&mut self, min_tlv_type: u64, max_tlv_type: u64,
+	) -> Result<&mut Self, BuildError> {
+		if !is_valid_custom_onion_message_tlv_types(min_tlv_type, max_tlv_type) {
+			return Err(BuildError::InvalidCustomOnionMessageTlvTypes);
+		}
+
+		self.config.custom_onion_message_tlv_type_min = min_tlv_type;
+		self.config.custom_onion_message_tlv_type_max = max_tlv_type;
+		Ok(self)
+	}
+
+	/// Sets the maximum routing fees we're willing to pay for outbound payments.
+	///
+	/// The policy applies to every outbound payment unless a fee limit is given for the
+	/// individual payment. See [`Config::max_routing_fee_policy`] for more information.
+	pub fn set_max_routing_fee_policy(&mut self, policy: MaxFeePolicy) -> &mut Self {
+		self.config.max_routing_fee_policy = Some(policy);
+		self
+	}
+
+	/// Sets the alias which [`Node`] will use in its node announcements.
+	///
+	/// The alias must not exceed 32 bytes when UTF-8 encoded.
+	pub fn set_node_alias(&mut self, node_alias: &str) -> Result<&mut Self, BuildError> {
+		if node_alias_bytes(node_alias).is_none() {
+			return Err(BuildError::InvalidNodeAlias);
+		}
+
+		self.node_alias = Some(node_alias.to_string());
+		Ok(self)
+	}
+
+	/// Sets the RGB color which [`Node`] will use in its node announcements.
+	pub fn set_node_color(&mut self, node_color: [u8; 3]) -> &mut Self {
+		self.node_color = node_color;
+		self
+	}
+
+	/// Enables receiving payments to phantom invoices, i.e., invoices that may be paid to any of
+	/// several nodes sharing the given 32-byte `cross_node_seed`.
+	///
+	/// All nodes backing the same phantom invoices need to be configured with the same seed, while
+	/// each of them still needs to use its own, distinct wallet entropy. As anybody knowing the
+	/// seed is able to derive the phantom node's keys, it needs to be kept as secret as the wallet
+	/// entropy. The seed will be persisted in the configured store, so it only needs to be given
+	/// once.
+	///
+	/// **Note:** The seed is stored unencrypted in the configured [`KVStore`], even if a
+	/// passphrase was set via [`Self::set_entropy_seed_file_passphrase`]. Anybody with read access
+	/// to the store is hence able to derive the phantom node's keys.
+	///
+	/// **Note:** Enabling phantom payments changes the key used to derive the payment secrets of
+	/// our invoices, i.e., payments to invoices created before will fail.
+	pub fn set_phantom_cross_node_seed(
+		&mut self, cross_node_seed: Vec<u8>,
+	) -> Result<&mut Self, BuildError> {
+		let cross_node_seed: [u8; 32] =
+			cross_node_seed.try_into().map_err(|_| BuildError::InvalidSeedBytes)?;
+		self.phantom_cross_node_seed = Some(cross_node_seed);
+		Ok(self)
+	}
+
+	/// Configures a policy deciding whether to accept inbound channels.
+	///
+	/// The given callback is invoked for every inbound channel open request and may accept it,
+	/// reject it, or accept it as a zero-confirmation channel from a trusted peer. Requests we
+	/// can't accept in any case, e.g., Anchor channels while we lack the on-chain reserves to
+	/// cover them, are rejected before the policy is consulted. The callback is invoked while
+	/// handling events, so it should return promptly.
+	///
+	/// If no policy is set, all inbound channels are accepted, as zero-confirmation channels if
+	/// the peer is listed in [`Config::trusted_peers_0conf`].
+	pub fn set_inbound_channel_policy(
+		&mut self, policy: Box<dyn Fn(&PendingChannelRequest) -> ChannelDecision + Send + Sync>,
+	) -> &mut Self {
+		self.inbound_channel_policy = Some(InboundChannelPolicyConfig(Arc::from(policy)));
+		self
+	}
+
+	/// Sets the peers we accept zero-confirmation channels from, which can be used immediately
+	/// rather than only after their funding transaction confirmed.
+	///
+	/// The given list will be persisted in the configured store, replacing any previously
+	/// persisted list, and will be used on subsequent builds until it is set again. Peers listed
+	/// in [`Config::trusted_peers_0conf`] are trusted in addition. Zero-confirmation channel
+	/// requests from any other peer are rejected.
+	///
+	/// **Note:** Accepting zero-confirmation channels is only secure if the peer is trusted not to
+	/// double-spend the funding transaction.
+	pub fn set_trusted_peers_0conf(&mut self, node_ids: Vec<PublicKey>) -> &mut Self {
+		self.trusted_peers_0conf = Some(node_ids);
+		self
+	}
+
+	/// Recovers the channels of a lost node from a static channel backup previously exported via
+	/// [`Node::export_channel_backup`].
+	///
+	/// The backup can only be restored into a fresh node initialized from the same seed as the
+	/// node that created it. Upon start, the node will reconnect to the former channel
+	/// counterparties and request them to force-close the channels, allowing us to claim our
+	/// funds on-chain. As the backup might be outdated, the node will never broadcast its own
+	/// commitment transactions for the restored channels.
+	///
+	/// **Note:** This is meant for disaster recovery only. Channels restored from a backup can't be
+	/// operated any further.
+	///
+	/// [`Node::export_channel_backup`]: crate::Node::export_channel_backup
+	pub fn recover_from_channel_backup(&mut self, channel_backup: Vec<u8>) -> &mut Self {
+		self.channel_backup = Some(channel_backup);
+		self
+	}
+
+	/// Restores the persisted state of a node from a store snapshot previously exported via
+	/// [`Node::export_snapshot`].
+	///
+	/// The snapshot can only be restored into a fresh node. It will be fully validated before any
+	/// of its contents are written to the store.
+	///
+	/// **Caution:** Restoring channel state from an outdated snapshot may lead to loss of funds,
+	/// as broadcasting a revoked commitment transaction allows the counterparty to claim all
+	/// channel funds. Only restore the most recent snapshot and never operate two nodes from the
+	/// same snapshot.
+	///
+	/// [`Node::export_snapshot`]: crate::Node::export_snapshot
+	pub fn restore_from_snapshot(&mut self, snapshot: Vec<u8>) -> &mut Self {
+		self.snapshot = Some(snapshot);
+		self
+	}
+
+	/// Configures a [`BackupSink`] that is notified about every change committed to the store.
+	///
+	/// This allows to continuously stream the node's state to a remote backup location without
+	/// polling. The sink is only used by nodes built via [`build_with_backup_sink_store`], building
+	/// via any other method will fail rather than running without the configured backups.
+	///
+	/// [`build_with_backup_sink_store`]: Self::build_with_backup_sink_store
+	pub fn set_backup_sink(&mut self, backup_sink: Box<dyn BackupSink>) -> &mut Self {
+		self.backup_sink = Some(BackupSinkConfig(Arc::from(backup_sink)));
+		self
+	}
+
 	/// Builds a [`Node`] instance with a [`SqliteStore`] backend and according to the options
 	/// previously configured.
 	pub fn build(&self) -> Result<Node<SqliteStore>, BuildError> {
@@ -326,17 +1014,49 @@ impl NodeBuilder {
 		self.build_with_store(kv_store)
 	}
 
+	/// Builds a [`Node`] instance with a [`PostgresStore`] backend and according to the options
+	/// previously configured.
+	///
+	/// The store connects to the database given by `connection_string` and persists all data in
+	/// the given `kv_table_name`, or the default table if set to `None`.
+	#[cfg(any(postgres, postgres_test))]
+	pub fn build_with_postgres_store(
+		&self, connection_string: String, kv_table_name: Option<String>,
+	) -> Result<Node<PostgresStore>, BuildError> {
+		let kv_store = Arc::new(
+			PostgresStore::new(connection_string, kv_table_name)
+				.map_err(|_| BuildError::KVStoreSetupFailed)?,
+		);
+		self.build_with_store(kv_store)
+	}
+
+	/// Builds a [`Node`] instance with a [`RedisStore`] backend and according to the options
+	/// previously configured.
+	///
+	/// To have transient data expire automatically, configure a [`RedisStore`] via
+	/// [`RedisStore::set_ttl`] and hand it to [`NodeBuilder::build_with_store`] instead.
+	#[cfg(any(redis, redis_test))]
+	pub fn build_with_redis_store(
+		&self, redis_url: String,
+	) -> Result<Node<RedisStore>, BuildError> {
+		let kv_store =
+			Arc::new(RedisStore::new(redis_url).map_err(|_| BuildError::KVStoreSetupFailed)?);
+		self.build_with_store(kv_store)
+	}
+
 	/// Builds a [`Node`] instance with a [`VssStore`] backend and according to the options
 	/// previously configured.
 	#[cfg(any(vss, vss_test))]
 	pub fn build_with_vss_store(
 		&self, url: String, store_id: String,
 	) -> Result<Node<VssStore>, BuildError> {
-		let logger = setup_logger(&self.config)?;
+		let logger = setup_logger(&self.config, &self.module_log_levels, self.log_sink.as_ref())?;
 
 		let seed_bytes = seed_bytes_from_config(
 			&self.config,
 			self.entropy_source_config.as_ref(),
+			self.seed_file_passphrase.as_deref(),
+			self.entropy_rng.as_ref(),
 			Arc::clone(&logger),
 		)?;
 		let config = Arc::new(self.config.clone());
@@ -360,22 +1080,119 @@ impl NodeBuilder {
 		build_with_store_internal(
 			config,
 			self.chain_data_source_config.as_ref(),
+			self.wallet_birthday_height,
+			self.esplora_concurrency,
+			&self.fee_rate_floors,
 			self.gossip_source_config.as_ref(),
 			self.liquidity_source_config.as_ref(),
+			&self.scoring_params_config,
 			seed_bytes,
+			self.skip_corrupt_payments,
+			&self.payment_retry_policy,
+			self.node_alias.clone(),
+			self.node_color,
+			self.phantom_cross_node_seed,
+			self.inbound_channel_policy.as_ref().map(|policy| Arc::clone(&policy.0)),
+			self.trusted_peers_0conf.as_deref(),
+			self.channel_backup.as_deref(),
+			self.snapshot.as_deref(),
+			self.backup_sink.as_ref(),
+			None,
 			logger,
 			vss_store,
 		)
 	}
 
+	/// Builds a [`Node`] instance backed by the given [`KVStore`] and according to the options
+	/// previously configured.
+	///
+	/// Failed store operations are retried according to the given [`KVStoreRetryPolicy`], which
+	/// helps to paper over transient errors of network-backed stores.
+	pub fn build_with_retrying_store<K: KVStore + Sync + Send + 'static>(
+		&self, kv_store: Arc<K>, retry_policy: KVStoreRetryPolicy,
+	) -> Result<Node<RetryingKVStore<Arc<K>>>, BuildError> {
+		let kv_store = Arc::new(RetryingKVStore::new(kv_store, retry_policy));
+		self.build_with_store(kv_store)
+	}
+
+	/// Builds a [`Node`] instance backed by the given [`KVStore`] and according to the options
+	/// previously configured.
+	///
+	/// All writes and removals committed to the given store will be forwarded to the
+	/// [`BackupSink`] configured via [`set_backup_sink`]. Fails if no sink was configured.
+	///
+	/// [`set_backup_sink`]: Self::set_backup_sink
+	pub fn build_with_backup_sink_store<K: KVStore + Sync + Send + 'static>(
+		&self, kv_store: Arc<K>,
+	) -> Result<Node<BackupSinkKVStore<Arc<K>>>, BuildError> {
+		let logger = setup_logger(&self.config, &self.module_log_levels, self.log_sink.as_ref())?;
+		let backup_sink = match &self.backup_sink {
+			Some(BackupSinkConfig(backup_sink)) => Arc::clone(backup_sink),
+			None => {
+				log_error!(logger, "Failed to setup store: no backup sink configured.");
+				return Err(BuildError::KVStoreSetupFailed);
+			},
+		};
+		let kv_store = Arc::new(BackupSinkKVStore::new(kv_store, backup_sink, logger));
+		self.build_with_store_and_backup_sink(kv_store, None, None)
+	}
+
+	/// Builds a [`Node`] instance backed by the given [`AsyncKVStore`] and according to the
+	/// options previously configured.
+	///
+	/// The network graph, scorer, and payments are read concurrently via the given store. The
+	/// store will then be wrapped in an [`AsyncKVStoreAdapter`] to satisfy LDK's synchronous
+	/// persistence interface.
+	pub fn build_with_async_store<A: AsyncKVStore + Sync + Send + 'static>(
+		&self, kv_store: Arc<A>,
+	) -> Result<Node<AsyncKVStoreAdapter<A>>, BuildError> {
+		let logger = setup_logger(&self.config, &self.module_log_levels, self.log_sink.as_ref())?;
+		let kv_store = Arc::new(
+			AsyncKVStoreAdapter::new(kv_store).map_err(|_| BuildError::KVStoreSetupFailed)?,
+		);
+
+		// A snapshot is restored while building, so we can only read ahead if there is none.
+		let preloaded_state = if self.snapshot.is_none() {
+			// Bring the persisted data up to date before we read any of it. This is a no-op when
+			// the migrations are run again while building.
+			io::migrations::run_migrations(&kv_store, &io::migrations::migrations(), &logger)
+				.map_err(|_| BuildError::MigrationFailed)?;
+			Some(kv_store.block_on(preload_state_async(
+				kv_store.inner(),
+				self.config.network,
+				self.scoring_params_config.decay_params,
+				self.skip_corrupt_payments,
+				Arc::clone(&logger),
+			))?)
+		} else {
+			None
+		};
+
+		self.build_with_store_and_backup_sink(kv_store, self.backup_sink.as_ref(), preloaded_state)
+	}
+
 	/// Builds a [`Node`] instance according to the options previously configured.
-	pub fn build_with_store<K: KVStore + Sync + Send + 'static>(
+	///
+	/// See [`BatchKVStore`] on how to use a custom store that doesn't support batching.
+	pub fn build_with_store<K: BatchKVStore + Sync + Send + 'static>(
 		&self, kv_store: Arc<K>,
 	) -> Result<Node<K>, BuildError> {
-		let logger = setup_logger(&self.config)?;
+		self.build_with_store_and_backup_sink(kv_store, self.backup_sink.as_ref(), None)
+	}
+
+	/// Builds a [`Node`] instance, where `backup_sink` is the configured [`BackupSink`] if the
+	/// given store doesn't already forward changes to it, and `preloaded_state` is any state that
+	/// was already read from the store.
+	fn build_with_store_and_backup_sink<K: BatchKVStore + Sync + Send + 'static>(
+		&self, kv_store: Arc<K>, backup_sink: Option<&BackupSinkConfig>,
+		preloaded_state: Option<PreloadedState>,
+	) -> Result<Node<K>, BuildError> {
+		let logger = setup_logger(&self.config, &self.module_log_levels, self.log_sink.as_ref())?;
 		let seed_bytes = seed_bytes_from_config(
 			&self.config,
 			self.entropy_source_config.as_ref(),
+			self.seed_file_passphrase.as_deref(),
+			self.entropy_rng.as_ref(),
 			Arc::clone(&logger),
 		)?;
 		let config = Arc::new(self.config.clone());
@@ -383,9 +1200,24 @@ impl NodeBuilder {
 		build_with_store_internal(
 			config,
 			self.chain_data_source_config.as_ref(),
+			self.wallet_birthday_height,
+			self.esplora_concurrency,
+			&self.fee_rate_floors,
 			self.gossip_source_config.as_ref(),
 			self.liquidity_source_config.as_ref(),
+			&self.scoring_params_config,
 			seed_bytes,
+			self.skip_corrupt_payments,
+			&self.payment_retry_policy,
+			self.node_alias.clone(),
+			self.node_color,
+			self.phantom_cross_node_seed,
+			self.inbound_channel_policy.as_ref().map(|policy| Arc::clone(&policy.0)),
+			self.trusted_peers_0conf.as_deref(),
+			self.channel_backup.as_deref(),
+			self.snapshot.as_deref(),
+			backup_sink,
+			preloaded_state,
 			logger,
 			kv_store,
 		)
@@ -427,6 +1259,15 @@ impl ArcedNodeBuilder {
 		self.inner.write().unwrap().set_entropy_seed_path(seed_path);
 	}
 
+	/// Configures a passphrase used to encrypt the seed file at rest.
+	///
+	/// If set, newly generated seed files will be stored encrypted, and existing plaintext seed
+	/// files will be migrated to the encrypted format on the next start. Only applies if the wallet
+	/// entropy is sourced from a seed file.
+	pub fn set_entropy_seed_file_passphrase(&self, passphrase: String) {
+		self.inner.write().unwrap().set_entropy_seed_file_passphrase(passphrase);
+	}
+
 	/// Configures the [`Node`] instance to source its wallet entropy from the given 64 seed bytes.
 	///
 	/// **Note:** Panics if the length of the given `seed_bytes` differs from 64.
@@ -446,6 +1287,68 @@ impl ArcedNodeBuilder {
 		self.inner.write().unwrap().set_esplora_server(esplora_server_url);
 	}
 
+	/// Configures the intervals in-between background syncs and fee rate updates, as well as the
+	/// number of concurrent requests made against the Esplora server.
+	///
+	/// The intervals override the respective values of the [`Config`] the builder was created
+	/// with. Intervals of zero are rejected, while the number of concurrent requests is clamped
+	/// to a maximum of 32.
+	pub fn set_chain_sync_config(&self, sync_config: EsploraSyncConfig) -> Result<(), BuildError> {
+		self.inner.write().unwrap().set_chain_sync_config(sync_config).map(|_| ())
+	}
+
+	/// Sets a minimum fee rate, in satoshis per 1000 weight units, for the given confirmation
+	/// target.
+	///
+	/// If the fee rate estimated by the chain source is below the floor, the floor is used
+	/// instead. Note that we never use fee rates below the minimum relay fee rate, independently
+	/// of the configured floor.
+	pub fn set_fee_rate_floor(&self, confirmation_target: ConfirmationTarget, sat_per_kwu: u32) {
+		self.inner.write().unwrap().set_fee_rate_floor(confirmation_target, sat_per_kwu);
+	}
+
+	/// Configures the [`Node`] instance to source its chain data from the given Bitcoin Core RPC
+	/// endpoint.
+	///
+	/// Blocks are fetched and connected in full, while fee rates are retrieved via
+	/// `estimatesmartfee` and transactions are broadcast via `sendrawtransaction`.
+	pub fn set_chain_source_bitcoind_rpc(
+		&self, rpc_host: String, rpc_port: u16, rpc_user: String, rpc_password: String,
+	) {
+		self.inner.write().unwrap().set_chain_source_bitcoind_rpc(
+			rpc_host,
+			rpc_port,
+			rpc_user,
+			rpc_password,
+		);
+	}
+
+	/// Configures the [`Node`] instance to source its chain data via compact block filters
+	/// (BIP157/158) from the peer at the given `host:port` address.
+	///
+	/// The peer needs to serve compact block filters, e.g., a Bitcoin Core node run with
+	/// `-blockfilterindex=1 -peerblockfilters=1`. As filters don't allow to estimate fee rates,
+	/// fee rates are retrieved from the Esplora server at the given `fee_oracle_url`.
+	///
+	/// **Note:** The block headers are kept in memory and hence downloaded from genesis every
+	/// time the node is started, which might take several minutes on mainnet. Afterwards, each
+	/// sync downloads the filters of all blocks since the last sync, as well as any full blocks
+	/// whose filters match our scripts.
+	///
+	/// Requires the `cbf` feature to be enabled, otherwise building the node will fail.
+	pub fn set_chain_source_cbf(&self, peer_address: String, fee_oracle_url: String) {
+		self.inner.write().unwrap().set_chain_source_cbf(peer_address, fee_oracle_url);
+	}
+
+	/// Sets the height of the block the on-chain wallet was created at, i.e., its birthday.
+	///
+	/// When syncing via the Bitcoin Core RPC or compact block filter chain sources, the initial
+	/// sync of the on-chain wallet scans the chain for wallet transactions starting from this
+	/// height. If not set, the entire chain is scanned, which might take a long time on mainnet.
+	pub fn set_wallet_birthday_height(&self, height: u32) {
+		self.inner.write().unwrap().set_wallet_birthday_height(height);
+	}
+
 	/// Configures the [`Node`] instance to source its gossip data from the Lightning peer-to-peer
 	/// network.
 	pub fn set_gossip_source_p2p(&self) {
@@ -493,9 +1396,245 @@ impl ArcedNodeBuilder {
 		self.inner.write().unwrap().set_listening_addresses(listening_addresses).map(|_| ())
 	}
 
-	/// Sets the level at which [`Node`] will log messages.
-	pub fn set_log_level(&self, level: LogLevel) {
-		self.inner.write().unwrap().set_log_level(level);
+	/// Sets the addresses which [`Node`] will announce to the gossip network that it accepts
+	/// connections on.
+	///
+	/// If not set, the listening addresses will be announced instead.
+	pub fn set_announced_addresses(
+		&self, announced_addresses: Vec<SocketAddress>,
+	) -> Result<(), BuildError> {
+		self.inner.write().unwrap().set_announced_addresses(announced_addresses).map(|_| ())
+	}
+
+	/// Sets the address of the SOCKS5 proxy, e.g., a local Tor daemon, used to connect to peers
+	/// with `.onion` addresses.
+	pub fn set_tor_proxy(&self, proxy_address: SocketAddr) {
+		self.inner.write().unwrap().set_tor_proxy(proxy_address);
+	}
+
+	/// Sets the time in-between broadcasts of our node announcement.
+	///
+	/// **Note:** Intervals below 10 minutes risk our announcements being rate-limited by our peers.
+	pub fn set_node_announcement_interval(&self, interval: Duration) {
+		self.inner.write().unwrap().set_node_announcement_interval(interval);
+	}
+
+	/// Sets the expiry time of the invoices we create if no explicit expiry is given.
+	///
+	/// See [`Config::default_invoice_expiry_secs`] for more information.
+	pub fn set_invoice_expiry_default(&self, expiry: Duration) {
+		self.inner.write().unwrap().set_invoice_expiry_default(expiry);
+	}
+
+	/// Sets the backoff schedule used when reconnecting to persistent peers.
+	///
+	/// After a persistent peer disconnected, we wait `initial_delay` before trying to reconnect,
+	/// doubling the delay after every failed attempt up to `max_delay`.
+	pub fn set_persistent_peer_reconnection_backoff(
+		&self, initial_delay: Duration, max_delay: Duration,
+	) {
+		self.inner
+			.write()
+			.unwrap()
+			.set_persistent_peer_reconnection_backoff(initial_delay, max_delay);
+	}
+
+	/// Sets the level at which [`Node`] will log messages.
+	pub fn set_log_level(&self, level: LogLevel) {
+		self.inner.write().unwrap().set_log_level(level);
+	}
+
+	/// Overrides the log level for all modules whose path starts with the given module path.
+	///
+	/// If multiple overrides match a module, the most specific one is used.
+	pub fn set_module_log_level(&self, module_path: String, level: LogLevel) {
+		self.inner.write().unwrap().set_module_log_level(module_path, level);
+	}
+
+	/// Sets the configuration of log file rotation.
+	///
+	/// If set to `None`, log files won't be rotated. Please refer to [`LogRotationConfig`] for
+	/// more information.
+	pub fn set_log_rotation_config(&self, log_rotation_config: Option<LogRotationConfig>) {
+		self.inner.write().unwrap().set_log_rotation_config(log_rotation_config);
+	}
+
+	/// Configures a [`Log`] sink that receives all log records passing the configured level
+	/// filters.
+	///
+	/// If `write_to_filesystem` is `false`, logs will be handed to the sink only.
+	pub fn set_log_sink(&self, sink: Box<dyn Log>, write_to_filesystem: bool) {
+		self.inner.write().unwrap().set_log_sink(sink, write_to_filesystem);
+	}
+
+	/// Configures a callback that is invoked for every log record passing the configured level
+	/// filters, *instead* of writing logs to disk.
+	pub fn set_log_callback(&self, callback: Box<dyn Fn(LogRecord) + Send + Sync>) {
+		self.inner.write().unwrap().set_log_callback(callback);
+	}
+
+	/// Sets the format in which [`Node`] will write log lines.
+	pub fn set_log_format(&self, format: LogFormat) {
+		self.inner.write().unwrap().set_log_format(format);
+	}
+
+	/// Sets the configuration of Anchor channels.
+	///
+	/// If set to `None`, Anchor channels won't be negotiated and no on-chain reserve will be
+	/// maintained for them. Please refer to [`AnchorChannelsConfig`] for more information on the
+	/// reserve requirement.
+	pub fn set_anchor_channels_config(&self, anchor_channels_config: Option<AnchorChannelsConfig>) {
+		self.inner.write().unwrap().set_anchor_channels_config(anchor_channels_config);
+	}
+
+	/// Configures whether payment entries that fail to deserialize should be skipped on startup.
+	///
+	/// If enabled (the default), corrupt payment entries are logged and skipped, as the payment
+	/// history is not critical for the operation of the node. If disabled, any corrupt entry will
+	/// have building the [`Node`] fail with [`BuildError::ReadFailed`].
+	pub fn set_skip_corrupt_payments(&self, skip_corrupt_payments: bool) {
+		self.inner.write().unwrap().set_skip_corrupt_payments(skip_corrupt_payments);
+	}
+
+	/// Sets the policy according to which failed outbound payments are retried.
+	///
+	/// Only once the policy is exhausted will an [`Event::PaymentFailed`] be emitted. The policy
+	/// must allow for at least one attempt.
+	///
+	/// [`Event::PaymentFailed`]: crate::Event::PaymentFailed
+	pub fn set_payment_retry_policy(&self, retry_policy: RetryPolicy) -> Result<(), BuildError> {
+		self.inner.write().unwrap().set_payment_retry_policy(retry_policy).map(|_| ())
+	}
+
+	/// Sets the number of confirmations the funding transaction of a channel needs to reach
+	/// before the channel is considered ready.
+	///
+	/// See [`NodeBuilder::set_channel_confirmation_target`] for more information.
+	pub fn set_channel_confirmation_target(&self, min_confs: u32) -> Result<(), BuildError> {
+		self.inner.write().unwrap().set_channel_confirmation_target(min_confs).map(|_| ())
+	}
+
+	/// Sets the default minimum number of blocks left until their HTLCs expire that payments to
+	/// our invoices need to arrive with.
+	///
+	/// See [`NodeBuilder::set_min_final_cltv_expiry_delta`] for more information.
+	pub fn set_min_final_cltv_expiry_delta(
+		&self, min_final_cltv_expiry_delta: u16,
+	) -> Result<(), BuildError> {
+		self.inner
+			.write()
+			.unwrap()
+			.set_min_final_cltv_expiry_delta(min_final_cltv_expiry_delta)
+			.map(|_| ())
+	}
+
+	/// Sets the options pertaining to the blinded paths included in our BOLT 12 offers and
+	/// invoices.
+	///
+	/// See [`NodeBuilder::set_blinded_path_config`] for more information.
+	pub fn set_blinded_path_config(
+		&self, blinded_path_config: BlindedPathConfig,
+	) -> Result<(), BuildError> {
+		self.inner.write().unwrap().set_blinded_path_config(blinded_path_config).map(|_| ())
+	}
+
+	/// Sets the range of TLV types of the custom onion messages we send and receive.
+	///
+	/// See [`NodeBuilder::set_custom_onion_message_tlv_types`] for more information.
+	pub fn set_custom_onion_message_tlv_types(
+		&self, min_tlv_type: u64, max_tlv_type: u64,
+	) -> Result<(), BuildError> {
+		self.inner
+			.write()
+			.unwrap()
+			.set_custom_onion_message_tlv_types(min_tlv_type, max_tlv_type)
+			.map(|_| ())
+	}
+
+	/// Sets the maximum routing fees we're willing to pay for outbound payments.
+	///
+	/// See [`NodeBuilder::set_max_routing_fee_policy`] for more information.
+	pub fn set_max_routing_fee_policy(&self, policy: MaxFeePolicy) {
+		self.inner.write().unwrap().set_max_routing_fee_policy(policy);
+	}
+
+	/// Sets the alias which [`Node`] will use in its node announcements.
+	///
+	/// The alias must not exceed 32 bytes when UTF-8 encoded.
+	pub fn set_node_alias(&self, node_alias: String) -> Result<(), BuildError> {
+		self.inner.write().unwrap().set_node_alias(&node_alias).map(|_| ())
+	}
+
+	/// Sets the RGB color which [`Node`] will use in its node announcements.
+	pub fn set_node_color(&self, red: u8, green: u8, blue: u8) {
+		self.inner.write().unwrap().set_node_color([red, green, blue]);
+	}
+
+	/// Enables receiving payments to phantom invoices, i.e., invoices that may be paid to any of
+	/// several nodes sharing the given 32-byte `cross_node_seed`.
+	///
+	/// See [`NodeBuilder::set_phantom_cross_node_seed`] for more information.
+	pub fn set_phantom_cross_node_seed(&self, cross_node_seed: Vec<u8>) -> Result<(), BuildError> {
+		self.inner.write().unwrap().set_phantom_cross_node_seed(cross_node_seed).map(|_| ())
+	}
+
+	/// Configures a policy deciding whether to accept inbound channels.
+	///
+	/// See [`NodeBuilder::set_inbound_channel_policy`] for more information.
+	pub fn set_inbound_channel_policy(
+		&self, policy: Box<dyn Fn(&PendingChannelRequest) -> ChannelDecision + Send + Sync>,
+	) {
+		self.inner.write().unwrap().set_inbound_channel_policy(policy);
+	}
+
+	/// Sets the peers we accept zero-confirmation channels from.
+	///
+	/// See [`NodeBuilder::set_trusted_peers_0conf`] for more information.
+	pub fn set_trusted_peers_0conf(&self, node_ids: Vec<PublicKey>) {
+		self.inner.write().unwrap().set_trusted_peers_0conf(node_ids);
+	}
+
+	/// Recovers the channels of a lost node from a static channel backup previously exported via
+	/// [`Node::export_channel_backup`].
+	///
+	/// The backup can only be restored into a fresh node initialized from the same seed as the
+	/// node that created it. Upon start, the node will reconnect to the former channel
+	/// counterparties and request them to force-close the channels, allowing us to claim our
+	/// funds on-chain. As the backup might be outdated, the node will never broadcast its own
+	/// commitment transactions for the restored channels.
+	///
+	/// **Note:** This is meant for disaster recovery only. Channels restored from a backup can't be
+	/// operated any further.
+	///
+	/// [`Node::export_channel_backup`]: crate::Node::export_channel_backup
+	pub fn recover_from_channel_backup(&self, channel_backup: Vec<u8>) {
+		self.inner.write().unwrap().recover_from_channel_backup(channel_backup);
+	}
+
+	/// Restores the persisted state of a node from a store snapshot previously exported via
+	/// [`Node::export_snapshot`].
+	///
+	/// The snapshot can only be restored into a fresh node. It will be fully validated before any
+	/// of its contents are written to the store.
+	///
+	/// **Caution:** Restoring channel state from an outdated snapshot may lead to loss of funds,
+	/// as broadcasting a revoked commitment transaction allows the counterparty to claim all
+	/// channel funds. Only restore the most recent snapshot and never operate two nodes from the
+	/// same snapshot.
+	///
+	/// [`Node::export_snapshot`]: crate::Node::export_snapshot
+	pub fn restore_from_snapshot(&self, snapshot: Vec<u8>) {
+		self.inner.write().unwrap().restore_from_snapshot(snapshot);
+	}
+
+	/// Configures a [`BackupSink`] that is notified about every change committed to the store.
+	///
+	/// The sink is only used by nodes built via [`build_with_backup_sink_store`], building via any
+	/// other method will fail rather than running without the configured backups.
+	///
+	/// [`build_with_backup_sink_store`]: Self::build_with_backup_sink_store
+	pub fn set_backup_sink(&self, backup_sink: Box<dyn BackupSink>) {
+		self.inner.write().unwrap().set_backup_sink(backup_sink);
 	}
 
 	/// Builds a [`Node`] instance with a [`SqliteStore`] backend and according to the options
@@ -510,21 +1649,224 @@ impl ArcedNodeBuilder {
 		self.inner.read().unwrap().build_with_fs_store().map(Arc::new)
 	}
 
+	/// Builds a [`Node`] instance backed by the given [`KVStore`] and according to the options
+	/// previously configured.
+	///
+	/// Failed store operations are retried according to the given [`KVStoreRetryPolicy`].
+	pub fn build_with_retrying_store<K: KVStore + Sync + Send + 'static>(
+		&self, kv_store: Arc<K>, retry_policy: KVStoreRetryPolicy,
+	) -> Result<Arc<Node<RetryingKVStore<Arc<K>>>>, BuildError> {
+		self.inner.read().unwrap().build_with_retrying_store(kv_store, retry_policy).map(Arc::new)
+	}
+
+	/// Builds a [`Node`] instance backed by the given [`KVStore`] and according to the options
+	/// previously configured.
+	///
+	/// All changes committed to the given store will be forwarded to the configured
+	/// [`BackupSink`].
+	pub fn build_with_backup_sink_store<K: KVStore + Sync + Send + 'static>(
+		&self, kv_store: Arc<K>,
+	) -> Result<Arc<Node<BackupSinkKVStore<Arc<K>>>>, BuildError> {
+		self.inner.read().unwrap().build_with_backup_sink_store(kv_store).map(Arc::new)
+	}
+
+	/// Builds a [`Node`] instance backed by the given [`AsyncKVStore`] and according to the
+	/// options previously configured.
+	pub fn build_with_async_store<A: AsyncKVStore + Sync + Send + 'static>(
+		&self, kv_store: Arc<A>,
+	) -> Result<Arc<Node<AsyncKVStoreAdapter<A>>>, BuildError> {
+		self.inner.read().unwrap().build_with_async_store(kv_store).map(Arc::new)
+	}
+
 	/// Builds a [`Node`] instance according to the options previously configured.
-	pub fn build_with_store<K: KVStore + Sync + Send + 'static>(
+	pub fn build_with_store<K: BatchKVStore + Sync + Send + 'static>(
 		&self, kv_store: Arc<K>,
 	) -> Result<Arc<Node<K>>, BuildError> {
 		self.inner.read().unwrap().build_with_store(kv_store).map(Arc::new)
 	}
 }
 
+/// Node state that was read via an [`AsyncKVStore`] before building the [`Node`].
+struct PreloadedState {
+	network_graph: Arc<NetworkGraph>,
+	scorer: Scorer,
+	payments: (Vec<PaymentDetails>, Vec<String>),
+}
+
+/// Reads the network graph, scorer, and payments from the given [`AsyncKVStore`], awaiting the
+/// reads concurrently rather than blocking on each of them in turn.
+async fn preload_state_async<A: AsyncKVStore + Sync + Send>(
+	kv_store: Arc<A>, network: Network, decay_params: ProbabilisticScoringDecayParameters,
+	skip_corrupt_payments: bool, logger: Arc<FilesystemLogger>,
+) -> Result<PreloadedState, BuildError> {
+	let graph_and_scorer = async {
+		let network_graph =
+			match read_network_graph_async(Arc::clone(&kv_store), Arc::clone(&logger)).await {
+				Ok(graph) => Arc::new(graph),
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+					Arc::new(NetworkGraph::new(network, Arc::clone(&logger)))
+				},
+				Err(_) => return Err(BuildError::ReadFailed),
+			};
+
+		let scorer = match read_scorer_async(
+			Arc::clone(&kv_store),
+			decay_params,
+			Arc::clone(&network_graph),
+			Arc::clone(&logger),
+		)
+		.await
+		{
+			Ok(scorer) => scorer,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => ProbabilisticScorer::new(
+				decay_params,
+				Arc::clone(&network_graph),
+				Arc::clone(&logger),
+			),
+			Err(_) => return Err(BuildError::ReadFailed),
+		};
+		Ok((network_graph, scorer))
+	};
+	let payments =
+		read_payments_async(Arc::clone(&kv_store), skip_corrupt_payments, Arc::clone(&logger));
+
+	let (graph_and_scorer, payments) = futures::future::join(graph_and_scorer, payments).await;
+	let (network_graph, scorer) = graph_and_scorer?;
+	let payments = payments.map_err(|_| BuildError::ReadFailed)?;
+	Ok(PreloadedState { network_graph, scorer, payments })
+}
+
 /// Builds a [`Node`] instance according to the options previously configured.
-fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
+fn build_with_store_internal<K: BatchKVStore + Sync + Send + 'static>(
 	config: Arc<Config>, chain_data_source_config: Option<&ChainDataSourceConfig>,
+	wallet_birthday_height: Option<u32>, esplora_concurrency: u8,
+	fee_rate_floors: &HashMap<ConfirmationTarget, u32>,
 	gossip_source_config: Option<&GossipSourceConfig>,
-	liquidity_source_config: Option<&LiquiditySourceConfig>, seed_bytes: [u8; 64],
-	logger: Arc<FilesystemLogger>, kv_store: Arc<K>,
+	liquidity_source_config: Option<&LiquiditySourceConfig>,
+	scoring_params_config: &ScoringParamsConfig, seed_bytes: [u8; 64], skip_corrupt_payments: bool,
+	payment_retry_policy: &RetryPolicy, node_alias: Option<String>, node_color: [u8; 3],
+	phantom_cross_node_seed: Option<[u8; 32]>,
+	inbound_channel_policy: Option<Arc<InboundChannelPolicy>>,
+	trusted_peers_0conf: Option<&[PublicKey]>, channel_backup: Option<&[u8]>,
+	snapshot: Option<&[u8]>, backup_sink: Option<&BackupSinkConfig>,
+	preloaded_state: Option<PreloadedState>, logger: Arc<FilesystemLogger>, kv_store: Arc<K>,
 ) -> Result<Node<K>, BuildError> {
+	// The backup sink can only be honoured if the store forwards its changes to it, so we refuse
+	// to silently run without backups.
+	if backup_sink.is_some() {
+		log_error!(
+			logger,
+			"Failed to setup store: the configured backup sink requires build_with_backup_sink_store."
+		);
+		return Err(BuildError::KVStoreSetupFailed);
+	}
+
+	if let Some(announced_addresses) = &config.announced_addresses {
+		if !announced_addresses.iter().all(is_valid_announced_address) {
+			log_error!(logger, "Failed to setup node: invalid announced addresses given.");
+			return Err(BuildError::InvalidAnnouncedAddresses);
+		}
+
+		for addr in announced_addresses.iter().filter(|a| is_private_address(a)) {
+			log_warn!(logger, "Announced address {} is not publicly reachable.", addr);
+		}
+	}
+
+	if let Some(min_confs) = config.channel_confirmation_target {
+		if !is_valid_channel_confirmation_target(min_confs) {
+			log_error!(logger, "Failed to setup node: invalid channel confirmation target given.");
+			return Err(BuildError::InvalidChannelConfirmationTarget);
+		}
+	}
+
+	if config.min_final_cltv_expiry_delta < MIN_FINAL_CLTV_EXPIRY_DELTA {
+		log_error!(logger, "Failed to setup node: invalid minimum final CLTV expiry delta given.");
+		return Err(BuildError::InvalidMinFinalCltvExpiryDelta);
+	}
+
+	if !config.blinded_path_config.is_valid() {
+		log_error!(logger, "Failed to setup node: invalid blinded path config given.");
+		return Err(BuildError::InvalidBlindedPathConfig);
+	}
+
+	if !is_valid_custom_onion_message_tlv_types(
+		config.custom_onion_message_tlv_type_min,
+		config.custom_onion_message_tlv_type_max,
+	) {
+		log_error!(logger, "Failed to setup node: invalid custom onion message TLV types given.");
+		return Err(BuildError::InvalidCustomOnionMessageTlvTypes);
+	}
+
+	if config.node_announcement_interval_secs < NODE_ANN_BCAST_INTERVAL_SAFE_MINIMUM_SECS {
+		log_warn!(
+			logger,
+			"Node announcement interval of {}s is below the safe minimum of {}s.",
+			config.node_announcement_interval_secs,
+			NODE_ANN_BCAST_INTERVAL_SAFE_MINIMUM_SECS
+		);
+	}
+
+	// Restore the persisted state from the store snapshot, if given.
+	if let Some(mut snapshot_bytes) = snapshot {
+		let has_channel_manager = kv_store
+			.read(
+				CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE,
+				CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE,
+				CHANNEL_MANAGER_PERSISTENCE_KEY,
+			)
+			.is_ok();
+		if has_channel_manager {
+			log_error!(logger, "Failed to restore from store snapshot: node isn't fresh.");
+			return Err(BuildError::InvalidSnapshot);
+		}
+
+		let entries = io::utils::read_store_snapshot(&mut snapshot_bytes).map_err(|e| {
+			log_error!(logger, "Failed to read store snapshot: {}", e);
+			BuildError::InvalidSnapshot
+		})?;
+		io::utils::restore_store_snapshot(&*kv_store, &entries).map_err(|e| {
+			log_error!(logger, "Failed to restore store snapshot: {}", e);
+			BuildError::WriteFailed
+		})?;
+		log_info!(logger, "Restored {} entries from store snapshot.", entries.len());
+	}
+
+	// Bring the persisted data up to date before we read any of it.
+	io::migrations::run_migrations(&kv_store, &io::migrations::migrations(), &logger)
+		.map_err(|_| BuildError::MigrationFailed)?;
+
+	// Once configured, we keep trusting the persisted 0conf peers in addition to the configured
+	// ones.
+	let persisted_trusted_peers_0conf = match trusted_peers_0conf {
+		Some(trusted_peers) => {
+			io::utils::write_trusted_peers_0conf(
+				trusted_peers,
+				Arc::clone(&kv_store),
+				Arc::clone(&logger),
+			)
+			.map_err(|_| BuildError::WriteFailed)?;
+			trusted_peers.to_vec()
+		},
+		None => {
+			match io::utils::read_trusted_peers_0conf(Arc::clone(&kv_store), Arc::clone(&logger)) {
+				Ok(trusted_peers) => trusted_peers,
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+				Err(_) => return Err(BuildError::ReadFailed),
+			}
+		},
+	};
+	let config = if persisted_trusted_peers_0conf.is_empty() {
+		config
+	} else {
+		let mut config = (*config).clone();
+		for node_id in persisted_trusted_peers_0conf {
+			if !config.trusted_peers_0conf.contains(&node_id) {
+				config.trusted_peers_0conf.push(node_id);
+			}
+		}
+		Arc::new(config)
+	};
+
 	// Initialize the on-chain wallet and chain access
 	let xprv = bitcoin::bip32::ExtendedPrivKey::new_master(config.network.into(), &seed_bytes)
 		.map_err(|e| {
@@ -532,11 +1874,27 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 			BuildError::InvalidSeedBytes
 		})?;
 
+	// Derive the BIP84 account-level xpub, which allows to watch the wallet externally.
+	let secp = Secp256k1::new();
+	let coin_type = if config.network == Network::Bitcoin { 0 } else { 1 };
+	let account_path = DerivationPath::from(vec![
+		ChildNumber::Hardened { index: 84 },
+		ChildNumber::Hardened { index: coin_type },
+		ChildNumber::Hardened { index: 0 },
+	]);
+	let account_xpub = xprv
+		.derive_priv(&secp, &account_path)
+		.map(|account_xprv| ExtendedPubKey::from_priv(&secp, &account_xprv))
+		.map_err(|e| {
+			log_error!(logger, "Failed to derive account xpub: {}", e);
+			BuildError::WalletSetupFailed
+		})?;
+
 	let wallet_name = bdk::wallet::wallet_name_from_descriptor(
 		Bip84(xprv, bdk::KeychainKind::External),
 		Some(Bip84(xprv, bdk::KeychainKind::Internal)),
 		config.network.into(),
-		&Secp256k1::new(),
+		&secp,
 	)
 	.map_err(|e| {
 		log_error!(logger, "Failed to derive wallet name: {}", e);
@@ -557,47 +1915,107 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 		BuildError::WalletSetupFailed
 	})?;
 
-	let (blockchain, tx_sync, tx_broadcaster, fee_estimator) = match chain_data_source_config {
+	let (chain_source, blockchain) = match chain_data_source_config {
 		Some(ChainDataSourceConfig::Esplora(server_url)) => {
-			let tx_sync = Arc::new(EsploraSyncClient::new(server_url.clone(), Arc::clone(&logger)));
+			let tx_sync = EsploraSyncClient::new(server_url.clone(), Arc::clone(&logger));
 			let blockchain =
 				EsploraBlockchain::from_client(tx_sync.client().clone(), BDK_CLIENT_STOP_GAP)
-					.with_concurrency(BDK_CLIENT_CONCURRENCY);
-			let tx_broadcaster = Arc::new(TransactionBroadcaster::new(
-				tx_sync.client().clone(),
+					.with_concurrency(esplora_concurrency);
+			let chain_source = Arc::new(ChainSource::new_esplora(tx_sync));
+			(chain_source, WalletBlockchain::Esplora(blockchain))
+		},
+		Some(ChainDataSourceConfig::BitcoindRpc { rpc_host, rpc_port, rpc_user, rpc_password }) => {
+			let bitcoind_rpc_client = Arc::new(BitcoindRpcClient::new(
+				rpc_host.clone(),
+				*rpc_port,
+				rpc_user.clone(),
+				rpc_password.clone(),
+				wallet_birthday_height,
+			));
+			let chain_source = Arc::new(ChainSource::new_bitcoind_rpc(
+				Arc::clone(&bitcoind_rpc_client),
+				config.network,
 				Arc::clone(&logger),
 			));
-			let fee_estimator = Arc::new(OnchainFeeEstimator::new(
-				tx_sync.client().clone(),
-				Arc::clone(&config),
+			(chain_source, WalletBlockchain::BitcoindRpc(bitcoind_rpc_client))
+		},
+		#[cfg(feature = "cbf")]
+		Some(ChainDataSourceConfig::Cbf { peer_address, fee_oracle_url }) => {
+			let fee_oracle =
+				esplora_client::Builder::new(fee_oracle_url).build_async().map_err(|e| {
+					log_error!(logger, "Failed to set up fee oracle client: {}", e);
+					BuildError::ChainSourceSetupFailed
+				})?;
+			let cbf_client = Arc::new(CbfClient::new(
+				peer_address.clone(),
+				config.network,
+				wallet_birthday_height,
 				Arc::clone(&logger),
 			));
-			(blockchain, tx_sync, tx_broadcaster, fee_estimator)
+			let chain_source = Arc::new(ChainSource::new_cbf(Arc::clone(&cbf_client), fee_oracle));
+			(chain_source, WalletBlockchain::Cbf(cbf_client))
+		},
+		#[cfg(not(feature = "cbf"))]
+		Some(ChainDataSourceConfig::Cbf { .. }) => {
+			log_error!(
+				logger,
+				"Failed to set up chain source: compact block filter support is not enabled."
+			);
+			return Err(BuildError::ChainSourceSetupFailed);
 		},
 		None => {
 			// Default to Esplora client.
 			let server_url = DEFAULT_ESPLORA_SERVER_URL.to_string();
-			let tx_sync = Arc::new(EsploraSyncClient::new(server_url, Arc::clone(&logger)));
+			let tx_sync = EsploraSyncClient::new(server_url, Arc::clone(&logger));
 			let blockchain =
 				EsploraBlockchain::from_client(tx_sync.client().clone(), BDK_CLIENT_STOP_GAP)
-					.with_concurrency(BDK_CLIENT_CONCURRENCY);
-			let tx_broadcaster = Arc::new(TransactionBroadcaster::new(
-				tx_sync.client().clone(),
-				Arc::clone(&logger),
-			));
-			let fee_estimator = Arc::new(OnchainFeeEstimator::new(
-				tx_sync.client().clone(),
-				Arc::clone(&config),
-				Arc::clone(&logger),
-			));
-			(blockchain, tx_sync, tx_broadcaster, fee_estimator)
+					.with_concurrency(esplora_concurrency);
+			let chain_source = Arc::new(ChainSource::new_esplora(tx_sync));
+			(chain_source, WalletBlockchain::Esplora(blockchain))
 		},
 	};
 
+	let recovered_channels =
+		match io::utils::read_recovered_channels(Arc::clone(&kv_store), Arc::clone(&logger)) {
+			Ok(channels) => Arc::new(RecoveredChannels::new(channels)),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+				Arc::new(RecoveredChannels::new(Vec::new()))
+			},
+			Err(_) => return Err(BuildError::ReadFailed),
+		};
+
+	let tx_broadcaster = Arc::new(TransactionBroadcaster::new(
+		Arc::clone(&chain_source),
+		Arc::clone(&recovered_channels),
+		Arc::clone(&logger),
+	));
+	let fee_estimator = Arc::new(OnchainFeeEstimator::new(
+		Arc::clone(&chain_source),
+		fee_rate_floors,
+		Arc::clone(&config),
+		Arc::clone(&logger),
+	));
+
+	// Start out with the last known fee rate estimates, so that we can operate reasonably even if
+	// our fee rate source is unavailable on startup.
+	let mut latest_fee_rate_cache_update_timestamp = None;
+	match io::utils::read_fee_rate_cache(Arc::clone(&kv_store), Arc::clone(&logger)) {
+		Ok(fee_rate_cache) => {
+			fee_estimator.restore_cached_fee_rates(fee_rate_cache.fee_rates);
+			latest_fee_rate_cache_update_timestamp = Some(fee_rate_cache.update_timestamp);
+		},
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+		Err(e) => {
+			// We'll just use the fallback rates until we retrieve fresh estimates.
+			log_error!(logger, "Failed to read persisted fee rate cache: {}", e);
+		},
+	}
+
 	let runtime = Arc::new(RwLock::new(None));
 	let wallet = Arc::new(Wallet::new(
 		blockchain,
 		bdk_wallet,
+		account_xpub,
 		Arc::clone(&tx_broadcaster),
 		Arc::clone(&fee_estimator),
 		Arc::clone(&logger),
@@ -605,7 +2023,7 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 
 	// Initialize the ChainMonitor
 	let chain_monitor: Arc<ChainMonitor<K>> = Arc::new(chainmonitor::ChainMonitor::new(
-		Some(Arc::clone(&tx_sync)),
+		Some(Arc::clone(&chain_source)),
 		Arc::clone(&tx_broadcaster),
 		Arc::clone(&logger),
 		Arc::clone(&fee_estimator),
@@ -618,57 +2036,178 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 		BuildError::InvalidSystemTime
 	})?;
 
+	// Once configured, we keep using the persisted phantom seed.
+	let phantom_cross_node_seed = match phantom_cross_node_seed {
+		Some(cross_node_seed) => {
+			io::utils::write_phantom_cross_node_seed(
+				&cross_node_seed,
+				Arc::clone(&kv_store),
+				Arc::clone(&logger),
+			)
+			.map_err(|_| BuildError::WriteFailed)?;
+			Some(cross_node_seed)
+		},
+		None => {
+			match io::utils::read_phantom_cross_node_seed(
+				Arc::clone(&kv_store),
+				Arc::clone(&logger),
+			) {
+				Ok(cross_node_seed) => Some(cross_node_seed),
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+				Err(_) => return Err(BuildError::ReadFailed),
+			}
+		},
+	};
+
 	let ldk_seed_bytes: [u8; 32] = xprv.private_key.secret_bytes();
 	let keys_manager = Arc::new(KeysManager::new(
 		&ldk_seed_bytes,
 		cur_time.as_secs(),
 		cur_time.subsec_nanos(),
+		phantom_cross_node_seed.as_ref(),
 		Arc::clone(&wallet),
 		Arc::clone(&logger),
 	));
 
+	let (preloaded_graph_and_scorer, preloaded_payments) = match preloaded_state {
+		Some(PreloadedState { network_graph, scorer, payments }) => {
+			(Some((network_graph, scorer)), Some(payments))
+		},
+		None => (None, None),
+	};
+
 	// Initialize the network graph, scorer, and router
-	let network_graph =
-		match io::utils::read_network_graph(Arc::clone(&kv_store), Arc::clone(&logger)) {
-			Ok(graph) => Arc::new(graph),
-			Err(e) => {
-				if e.kind() == std::io::ErrorKind::NotFound {
-					Arc::new(NetworkGraph::new(config.network.into(), Arc::clone(&logger)))
-				} else {
-					return Err(BuildError::ReadFailed);
-				}
+	let scoring_decay_params = scoring_params_config.decay_params;
+	let (network_graph, scorer) = match preloaded_graph_and_scorer {
+		Some((network_graph, scorer)) => (network_graph, Arc::new(Mutex::new(scorer))),
+		None => {
+			let network_graph =
+				match io::utils::read_network_graph(Arc::clone(&kv_store), Arc::clone(&logger)) {
+					Ok(graph) => Arc::new(graph),
+					Err(e) => {
+						if e.kind() == std::io::ErrorKind::NotFound {
+							Arc::new(NetworkGraph::new(config.network.into(), Arc::clone(&logger)))
+						} else {
+							return Err(BuildError::ReadFailed);
+						}
+					},
+				};
+
+			let scorer = match io::utils::read_scorer(
+				Arc::clone(&kv_store),
+				scoring_decay_params,
+				Arc::clone(&network_graph),
+				Arc::clone(&logger),
+			) {
+				Ok(scorer) => Arc::new(Mutex::new(scorer)),
+				Err(e) => {
+					if e.kind() == std::io::ErrorKind::NotFound {
+						Arc::new(Mutex::new(ProbabilisticScorer::new(
+							scoring_decay_params,
+							Arc::clone(&network_graph),
+							Arc::clone(&logger),
+						)))
+					} else {
+						return Err(BuildError::ReadFailed);
+					}
+				},
+			};
+			(network_graph, scorer)
+		},
+	};
+
+	let channel_penalties: Arc<ChannelPenalties> =
+		match io::utils::read_channel_penalties(Arc::clone(&kv_store), Arc::clone(&logger)) {
+			Ok(mut penalties) => {
+				// Drop the penalties that expired while we were offline.
+				let now_secs = SystemTime::now()
+					.duration_since(SystemTime::UNIX_EPOCH)
+					.map_or(0, |d| d.as_secs());
+				penalties.retain(|short_channel_id, expiry_secs| {
+					if *expiry_secs > now_secs {
+						return true;
+					}
+					// Errors are logged, and we'd simply retry on the next start.
+					let _ = io::utils::remove_channel_penalty(
+						*short_channel_id,
+						Arc::clone(&kv_store),
+						Arc::clone(&logger),
+					);
+					false
+				});
+				Arc::new(RwLock::new(penalties))
+			},
+			Err(_) => {
+				return Err(BuildError::ReadFailed);
 			},
 		};
 
-	let scorer = match io::utils::read_scorer(
-		Arc::clone(&kv_store),
+	let scoring_fee_params = scoring_params_config.fee_params.clone();
+	let router = Arc::new(NodeRouter::new(
+		DefaultRouter::new(
+			Arc::clone(&network_graph),
+			Arc::clone(&logger),
+			keys_manager.get_secure_random_bytes(),
+			Arc::clone(&scorer),
+			scoring_fee_params,
+		),
 		Arc::clone(&network_graph),
+		config.blinded_path_config.clone(),
+		Arc::clone(&channel_penalties),
 		Arc::clone(&logger),
-	) {
-		Ok(scorer) => Arc::new(Mutex::new(scorer)),
-		Err(e) => {
-			if e.kind() == std::io::ErrorKind::NotFound {
-				let params = ProbabilisticScoringDecayParameters::default();
-				Arc::new(Mutex::new(ProbabilisticScorer::new(
-					params,
-					Arc::clone(&network_graph),
-					Arc::clone(&logger),
-				)))
-			} else {
-				return Err(BuildError::ReadFailed);
+	));
+
+	// Restore the ChannelMonitors from the static channel backup, if given.
+	let channel_backup = match channel_backup {
+		Some(backup_bytes) => {
+			let has_channel_manager = kv_store
+				.read(
+					CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE,
+					CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE,
+					CHANNEL_MANAGER_PERSISTENCE_KEY,
+				)
+				.is_ok();
+			if has_channel_manager {
+				log_error!(logger, "Failed to recover from channel backup: node isn't fresh.");
+				return Err(BuildError::InvalidChannelBackup);
 			}
+
+			let backup = ChannelBackup::decrypt(backup_bytes, &keys_manager.get_node_secret_key())
+				.map_err(|()| {
+					log_error!(logger, "Failed to decrypt channel backup.");
+					BuildError::InvalidChannelBackup
+				})?;
+
+			// We record the restored channels before restoring their monitors, so that we never
+			// broadcast a possibly revoked commitment transaction for them, even after restarts.
+			let channels = backup.channels(&keys_manager).map_err(|e| {
+				log_error!(logger, "Failed to read channels from backup: {}", e);
+				BuildError::InvalidChannelBackup
+			})?;
+			let all_recovered_channels = recovered_channels.add(channels);
+			io::utils::write_recovered_channels(
+				&all_recovered_channels,
+				Arc::clone(&kv_store),
+				Arc::clone(&logger),
+			)
+			.map_err(|_| BuildError::WriteFailed)?;
+
+			backup.restore_monitors(&*kv_store, &keys_manager, Arc::clone(&logger)).map_err(
+				|e| {
+					log_error!(logger, "Failed to restore channel monitors from backup: {}", e);
+					BuildError::WriteFailed
+				},
+			)?;
+			log_info!(
+				logger,
+				"Restored {} channel monitors from channel backup.",
+				backup.monitors.len()
+			);
+			Some(backup)
 		},
+		None => None,
 	};
 
-	let scoring_fee_params = ProbabilisticScoringFeeParameters::default();
-	let router = Arc::new(DefaultRouter::new(
-		Arc::clone(&network_graph),
-		Arc::clone(&logger),
-		keys_manager.get_secure_random_bytes(),
-		Arc::clone(&scorer),
-		scoring_fee_params,
-	));
-
 	// Read ChannelMonitor state from store
 	let mut channel_monitors = match read_channel_monitors(
 		Arc::clone(&kv_store),
@@ -693,10 +2232,20 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 	// for inbound channels.
 	let mut user_config = UserConfig::default();
 	user_config.channel_handshake_limits.force_announced_channel_preference = false;
+	if let Some(min_confs) = config.channel_confirmation_target {
+		user_config.channel_handshake_config.minimum_depth = min_confs;
+	}
+
+	if !config.trusted_peers_0conf.is_empty() || inbound_channel_policy.is_some() {
+		// Manually accept inbound channels if we expect 0conf channel requests or need to consult
+		// the inbound channel policy, avoid generating the events otherwise.
+		user_config.manually_accept_inbound_channels = true;
+	}
 
-	if !config.trusted_peers_0conf.is_empty() {
-		// Manually accept inbound channels if we expect 0conf channel requests, avoid
-		// generating the events otherwise.
+	if config.anchor_channels_config.is_some() {
+		// Negotiate Anchor channels if possible. We need to manually accept inbound channels to
+		// check we have sufficient on-chain funds to maintain the reserve for them.
+		user_config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx = true;
 		user_config.manually_accept_inbound_channels = true;
 	}
 
@@ -776,14 +2325,42 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 		})?;
 	}
 
+	let mut event_queue =
+		match io::utils::read_event_queue(Arc::clone(&kv_store), Arc::clone(&logger)) {
+			Ok(event_queue) => event_queue,
+			Err(e) => {
+				if e.kind() == std::io::ErrorKind::NotFound {
+					EventQueue::new(Arc::clone(&kv_store), Arc::clone(&logger))
+				} else {
+					return Err(BuildError::ReadFailed);
+				}
+			},
+		};
+	event_queue.set_max_redeliveries(config.max_event_redeliveries);
+	let event_queue = Arc::new(event_queue);
+
 	// Initialize the PeerManager
-	let onion_messenger: Arc<OnionMessenger> = Arc::new(OnionMessenger::new(
+	let message_router = Arc::new(MessageRouter::new(Arc::clone(&network_graph)));
+	let custom_onion_message_handler = Arc::new(CustomOnionMessageHandler::new(
+		config.custom_onion_message_tlv_type_min..=config.custom_onion_message_tlv_type_max,
+		Arc::clone(&event_queue),
+		Arc::clone(&logger),
+	));
+	let offers_message_handler =
+		Arc::new(NodeOffersMessageHandler::new(Arc::clone(&channel_manager)));
+	let onion_messenger: Arc<OnionMessenger<K>> = Arc::new(OnionMessenger::new(
+		Arc::clone(&keys_manager),
 		Arc::clone(&keys_manager),
+		Arc::clone(&logger),
+		message_router,
+		Arc::clone(&offers_message_handler),
+		Arc::clone(&custom_onion_message_handler),
+	));
+	let onion_message_handler = Arc::new(NodeOnionMessageHandler::new(
+		onion_messenger,
+		Arc::clone(&custom_onion_message_handler),
 		Arc::clone(&keys_manager),
 		Arc::clone(&logger),
-		Arc::new(FakeMessageRouter {}),
-		IgnoringMessageHandler {},
-		IgnoringMessageHandler {},
 	));
 	let ephemeral_bytes: [u8; 32] = keys_manager.get_secure_random_bytes();
 
@@ -823,30 +2400,51 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 		},
 	};
 
-	let liquidity_source = liquidity_source_config.as_ref().and_then(|lsc| {
-		lsc.lsps2_service.as_ref().map(|(address, node_id, token)| {
-			let lsps2_client_config = Some(LSPS2ClientConfig {});
-			let liquidity_client_config = Some(LiquidityClientConfig { lsps2_client_config });
+	let liquidity_source = match liquidity_source_config.as_ref() {
+		Some(lsc) => {
+			let lsps2_client_config = lsc.lsps2_service.as_ref().map(|_| LSPS2ClientConfig {});
+			#[cfg(lsps1)]
+			let lsps1_client_config = lsc
+				.lsps1_service
+				.as_ref()
+				.map(|_| LSPS1ClientConfig { max_channel_fees_msat: None });
+			let liquidity_client_config = Some(LiquidityClientConfig {
+				#[cfg(lsps1)]
+				lsps1_client_config,
+				lsps2_client_config,
+			});
 			let liquidity_manager = Arc::new(LiquidityManager::new(
 				Arc::clone(&keys_manager),
 				Arc::clone(&channel_manager),
-				Some(Arc::clone(&tx_sync)),
+				Some(Arc::clone(&chain_source)),
 				None,
 				None,
 				liquidity_client_config,
 			));
-			Arc::new(LiquiditySource::new_lsps2(
-				address.clone(),
-				*node_id,
-				token.clone(),
+			let pending_quotes =
+				io::utils::read_jit_channel_quotes(Arc::clone(&kv_store), Arc::clone(&logger))
+					.map_err(|_| BuildError::ReadFailed)?;
+			let liquidity_source = LiquiditySource::new(
+				lsc.lsps2_service.clone(),
+				pending_quotes,
 				Arc::clone(&channel_manager),
 				Arc::clone(&keys_manager),
 				liquidity_manager,
+				Arc::clone(&kv_store),
 				Arc::clone(&config),
 				Arc::clone(&logger),
-			))
-		})
-	});
+			);
+			#[cfg(lsps1)]
+			let liquidity_source = match lsc.lsps1_service.clone() {
+				Some((address, node_id, token)) => {
+					liquidity_source.with_lsps1_service(address, node_id, token)
+				},
+				None => liquidity_source,
+			};
+			Some(Arc::new(liquidity_source))
+		},
+		None => None,
+	};
 
 	let custom_message_handler = if let Some(liquidity_source) = liquidity_source.as_ref() {
 		Arc::new(NodeCustomMessageHandler::new_liquidity(Arc::clone(&liquidity_source)))
@@ -854,26 +2452,37 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 		Arc::new(NodeCustomMessageHandler::new_ignoring())
 	};
 
-	let msg_handler = match gossip_source.as_gossip_sync() {
-		GossipSync::P2P(p2p_gossip_sync) => MessageHandler {
-			chan_handler: Arc::clone(&channel_manager),
-			route_handler: Arc::clone(&p2p_gossip_sync)
-				as Arc<dyn RoutingMessageHandler + Sync + Send>,
-			onion_message_handler: onion_messenger,
-			custom_message_handler,
-		},
-		GossipSync::Rapid(_) => MessageHandler {
-			chan_handler: Arc::clone(&channel_manager),
-			route_handler: Arc::new(IgnoringMessageHandler {})
-				as Arc<dyn RoutingMessageHandler + Sync + Send>,
-			onion_message_handler: onion_messenger,
-			custom_message_handler,
+	let route_handler = match gossip_source.as_gossip_sync() {
+		GossipSync::P2P(p2p_gossip_sync) => {
+			Arc::clone(&p2p_gossip_sync) as Arc<dyn RoutingMessageHandler + Sync + Send>
+		},
+		GossipSync::Rapid(_) => {
+			Arc::new(IgnoringMessageHandler {}) as Arc<dyn RoutingMessageHandler + Sync + Send>
 		},
 		GossipSync::None => {
 			unreachable!("We must always have a gossip sync!");
 		},
 	};
 
+	// Have the counterparties of channels restored from a backup close them whenever they
+	// connect.
+	let route_handler = if recovered_channels.is_empty() {
+		route_handler
+	} else {
+		Arc::new(RecoveryMessageHandler::new(
+			route_handler,
+			Arc::clone(&recovered_channels),
+			Arc::clone(&logger),
+		)) as Arc<dyn RoutingMessageHandler + Sync + Send>
+	};
+
+	let msg_handler = MessageHandler {
+		chan_handler: Arc::clone(&channel_manager),
+		route_handler,
+		onion_message_handler,
+		custom_message_handler,
+	};
+
 	let cur_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_err(|e| {
 		log_error!(logger, "Failed to get current time: {}", e);
 		BuildError::InvalidSystemTime
@@ -893,8 +2502,24 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 	liquidity_source.as_ref().map(|l| l.set_peer_manager(Arc::clone(&peer_manager)));
 
 	// Init payment info storage
-	let payment_store = match io::utils::read_payments(Arc::clone(&kv_store), Arc::clone(&logger)) {
-		Ok(payments) => {
+	let payments = match preloaded_payments {
+		Some(payments) => Ok(payments),
+		None => io::utils::read_payments(
+			Arc::clone(&kv_store),
+			skip_corrupt_payments,
+			Arc::clone(&logger),
+		),
+	};
+	let payment_store = match payments {
+		Ok((payments, skipped_keys)) => {
+			if !skipped_keys.is_empty() {
+				log_error!(
+					logger,
+					"Skipped {} corrupt payment entries while loading the payment store: {}",
+					skipped_keys.len(),
+					skipped_keys.join(", ")
+				);
+			}
 			Arc::new(PaymentStore::new(payments, Arc::clone(&kv_store), Arc::clone(&logger)))
 		},
 		Err(_) => {
@@ -902,17 +2527,83 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 		},
 	};
 
-	let event_queue = match io::utils::read_event_queue(Arc::clone(&kv_store), Arc::clone(&logger))
-	{
-		Ok(event_queue) => Arc::new(event_queue),
+	let address_book =
+		match io::utils::read_address_labels(Arc::clone(&kv_store), Arc::clone(&logger)) {
+			Ok(labels) => {
+				Arc::new(AddressBook::new(labels, Arc::clone(&kv_store), Arc::clone(&logger)))
+			},
+			Err(_) => {
+				return Err(BuildError::ReadFailed);
+			},
+		};
+
+	let onchain_monitor_state = match io::utils::read_onchain_payment_monitor_state(
+		Arc::clone(&kv_store),
+		Arc::clone(&logger),
+	) {
+		Ok(state) => state,
 		Err(e) => {
 			if e.kind() == std::io::ErrorKind::NotFound {
-				Arc::new(EventQueue::new(Arc::clone(&kv_store), Arc::clone(&logger)))
+				// We don't want to emit events for the payments the wallet already knew about
+				// before we started tracking them.
+				let handled_txids = wallet
+					.list_transactions()
+					.map_err(|_| BuildError::ReadFailed)?
+					.into_iter()
+					.map(|tx| tx.txid)
+					.collect();
+				OnchainPaymentMonitorState { handled_txids, ..Default::default() }
 			} else {
 				return Err(BuildError::ReadFailed);
 			}
 		},
 	};
+	let onchain_monitor = Arc::new(OnchainPaymentMonitor::new(
+		onchain_monitor_state,
+		config.emit_onchain_payment_events,
+		config.onchain_receive_min_confirmations,
+		Arc::clone(&address_book),
+		Arc::clone(&event_queue),
+		Arc::clone(&kv_store),
+		Arc::clone(&logger),
+	));
+
+	let payment_retrier =
+		match io::utils::read_payment_retries(Arc::clone(&kv_store), Arc::clone(&logger)) {
+			Ok(pending_retries) => Arc::new(PaymentRetrier::new(
+				payment_retry_policy.clone(),
+				pending_retries,
+				Arc::clone(&channel_manager),
+				Arc::clone(&payment_store),
+				Arc::clone(&event_queue),
+				Arc::clone(&kv_store),
+				Arc::clone(&logger),
+			)),
+			Err(_) => {
+				return Err(BuildError::ReadFailed);
+			},
+		};
+
+	let hold_invoice_handler =
+		match io::utils::read_held_payments(Arc::clone(&kv_store), Arc::clone(&logger)) {
+			Ok(held_payments) => Arc::new(HoldInvoiceHandler::new(
+				held_payments,
+				Arc::clone(&channel_manager),
+				Arc::clone(&payment_store),
+				Arc::clone(&event_queue),
+				Arc::clone(&kv_store),
+				Arc::clone(&logger),
+			)),
+			Err(_) => {
+				return Err(BuildError::ReadFailed);
+			},
+		};
+
+	let peer_monitor = Arc::new(PeerConnectionMonitor::new(
+		Arc::clone(&event_queue),
+		config.emit_peer_connection_events,
+		Arc::clone(&logger),
+	));
 
 	let peer_store = match io::utils::read_peer_info(Arc::clone(&kv_store), Arc::clone(&logger)) {
 		Ok(peer_store) => Arc::new(peer_store),
@@ -925,6 +2616,14 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 		},
 	};
 
+	if let Some(backup) = channel_backup {
+		// Reconnect to our former counterparties, so that we can ask them to force-close the
+		// channels they can't reestablish with us.
+		for peer_info in backup.peers {
+			peer_store.add_or_update_peer(peer_info).map_err(|_| BuildError::WriteFailed)?;
+		}
+	}
+
 	let best_block = channel_manager.current_best_block();
 	let output_sweeper =
 		match io::utils::read_spendable_outputs(Arc::clone(&kv_store), Arc::clone(&logger)) {
@@ -936,7 +2635,7 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 				Arc::clone(&keys_manager),
 				Arc::clone(&kv_store),
 				best_block,
-				Some(Arc::clone(&tx_sync)),
+				Some(Arc::clone(&chain_source)),
 				Arc::clone(&logger),
 			)),
 			Err(_) => {
@@ -944,27 +2643,45 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 			},
 		};
 
+	let bump_tx_event_handler = Arc::new(BumpTransactionEventHandler::new(
+		Arc::clone(&tx_broadcaster),
+		Arc::new(LdkWallet::new(Arc::clone(&wallet), Arc::clone(&logger))),
+		Arc::clone(&keys_manager),
+		Arc::clone(&logger),
+	));
+
+	let force_close_reasons = Arc::new(Mutex::new(HashMap::new()));
+
 	let (stop_sender, _) = tokio::sync::watch::channel(());
+	let background_tasks = Mutex::new(Vec::new());
 
 	let is_listening = Arc::new(AtomicBool::new(false));
+	let sync_paused = Arc::new(AtomicBool::new(false));
+	let sync_now_lock = tokio::sync::Mutex::new(());
+	let sync_lock = Arc::new(tokio::sync::Mutex::new(()));
 	let latest_wallet_sync_timestamp = Arc::new(RwLock::new(None));
 	let latest_onchain_wallet_sync_timestamp = Arc::new(RwLock::new(None));
-	let latest_fee_rate_cache_update_timestamp = Arc::new(RwLock::new(None));
+	let latest_fee_rate_cache_update_timestamp =
+		Arc::new(RwLock::new(latest_fee_rate_cache_update_timestamp));
 	let latest_rgs_snapshot_timestamp = Arc::new(RwLock::new(None));
 	let latest_node_announcement_broadcast_timestamp = Arc::new(RwLock::new(None));
+	let node_alias = Arc::new(RwLock::new(node_alias));
 
 	Ok(Node {
 		runtime,
 		stop_sender,
+		background_tasks,
 		config,
 		wallet,
-		tx_sync,
+		chain_source,
 		tx_broadcaster,
 		fee_estimator,
 		event_queue,
 		channel_manager,
 		chain_monitor,
 		output_sweeper,
+		bump_tx_event_handler,
+		force_close_reasons,
 		peer_manager,
 		keys_manager,
 		network_graph,
@@ -972,41 +2689,90 @@ fn build_with_store_internal<K: KVStore + Sync + Send + 'static>(
 		liquidity_source,
 		kv_store,
 		logger,
-		_router: router,
+		router,
 		scorer,
+		scoring_decay_params,
 		peer_store,
+		peer_monitor,
 		payment_store,
+		payment_retrier,
+		hold_invoice_handler,
+		address_book,
+		onchain_monitor,
 		is_listening,
+		sync_paused,
+		sync_now_lock,
+		sync_lock,
 		latest_wallet_sync_timestamp,
 		latest_onchain_wallet_sync_timestamp,
 		latest_fee_rate_cache_update_timestamp,
 		latest_rgs_snapshot_timestamp,
 		latest_node_announcement_broadcast_timestamp,
+		node_alias,
+		node_color,
+		inbound_channel_policy,
+		channel_penalties,
+		custom_onion_message_handler,
+		offers_message_handler,
 	})
 }
 
-fn setup_logger(config: &Config) -> Result<Arc<FilesystemLogger>, BuildError> {
+fn is_valid_channel_confirmation_target(min_confs: u32) -> bool {
+	(1..=MAX_CHANNEL_CONFIRMATION_TARGET).contains(&min_confs)
+}
+
+fn is_valid_custom_onion_message_tlv_types(min_tlv_type: u64, max_tlv_type: u64) -> bool {
+	min_tlv_type >= CUSTOM_ONION_MESSAGE_TLV_TYPE_MIN && min_tlv_type <= max_tlv_type
+}
+
+fn setup_logger(
+	config: &Config, module_log_levels: &HashMap<String, LogLevel>,
+	log_sink: Option<&LogSinkConfig>,
+) -> Result<Arc<FilesystemLogger>, BuildError> {
 	let log_dir = match &config.log_dir_path {
 		Some(log_dir) => String::from(log_dir),
 		None => config.storage_dir_path.clone() + "/logs",
 	};
 
-	Ok(Arc::new(
-		FilesystemLogger::new(log_dir, config.log_level)
-			.map_err(|_| BuildError::LoggerSetupFailed)?,
-	))
+	let new_file_logger = || {
+		FilesystemLogger::new(log_dir, config.log_level, config.log_format)
+			.map(|logger| logger.with_rotation(config.log_rotation_config.clone()))
+			.map_err(|_| BuildError::LoggerSetupFailed)
+	};
+	let logger = match log_sink {
+		Some(LogSinkConfig { sink, write_to_filesystem: false }) => {
+			FilesystemLogger::new_sink_only(Arc::clone(sink), config.log_level)
+		},
+		Some(LogSinkConfig { sink, write_to_filesystem: true }) => {
+			new_file_logger()?.with_sink(Arc::clone(sink))
+		},
+		None => new_file_logger()?,
+	};
+	let module_levels = module_log_levels.iter().map(|(path, level)| (path.clone(), *level));
+	Ok(Arc::new(logger.with_module_levels(module_levels)))
 }
 
 fn seed_bytes_from_config(
 	config: &Config, entropy_source_config: Option<&EntropySourceConfig>,
+	seed_file_passphrase: Option<&str>, entropy_rng: Option<&EntropyRng>,
 	logger: Arc<FilesystemLogger>,
 ) -> Result<[u8; 64], BuildError> {
+	let mut default_rng = thread_rng();
+	let mut locked_rng = entropy_rng.map(|r| r.0.lock().unwrap());
+	let rng: &mut dyn RngCore = match locked_rng.as_mut() {
+		Some(locked_rng) => &mut ***locked_rng,
+		None => &mut default_rng,
+	};
+
 	match entropy_source_config {
 		Some(EntropySourceConfig::SeedBytes(bytes)) => Ok(bytes.clone()),
-		Some(EntropySourceConfig::SeedFile(seed_path)) => {
-			Ok(io::utils::read_or_generate_seed_file(&seed_path, Arc::clone(&logger))
-				.map_err(|_| BuildError::InvalidSeedFile)?)
-		},
+		Some(EntropySourceConfig::SeedFile(seed_path)) => io::utils::read_or_generate_seed_file(
+			&seed_path,
+			seed_file_passphrase,
+			rng,
+			Arc::clone(&logger),
+		)
+		.map_err(seed_file_error_to_build_error),
 		Some(EntropySourceConfig::Bip39Mnemonic { mnemonic, passphrase }) => match passphrase {
 			Some(passphrase) => Ok(mnemonic.to_seed(passphrase)),
 			None => Ok(mnemonic.to_seed("")),
@@ -1014,8 +2780,54 @@ fn seed_bytes_from_config(
 		None => {
 			// Default to read or generate from the default location generate a seed file.
 			let seed_path = format!("{}/keys_seed", config.storage_dir_path);
-			Ok(io::utils::read_or_generate_seed_file(&seed_path, Arc::clone(&logger))
-				.map_err(|_| BuildError::InvalidSeedFile)?)
+			io::utils::read_or_generate_seed_file(
+				&seed_path,
+				seed_file_passphrase,
+				rng,
+				Arc::clone(&logger),
+			)
+			.map_err(seed_file_error_to_build_error)
+		},
+	}
+}
+
+fn seed_file_error_to_build_error(e: std::io::Error) -> BuildError {
+	match e.kind() {
+		std::io::ErrorKind::InvalidInput => BuildError::InvalidSeedFilePassphrase,
+		_ => BuildError::InvalidSeedFile,
+	}
+}
+
+/// Returns whether the given address has a shape that may be announced, i.e., it names a concrete
+/// host and a non-zero port.
+fn is_valid_announced_address(addr: &SocketAddress) -> bool {
+	match addr {
+		SocketAddress::TcpIpV4 { addr, port } => {
+			*port != 0 && !Ipv4Addr::from(*addr).is_unspecified()
+		},
+		SocketAddress::TcpIpV6 { addr, port } => {
+			*port != 0 && !Ipv6Addr::from(*addr).is_unspecified()
+		},
+		SocketAddress::OnionV2(_) => true,
+		SocketAddress::OnionV3 { port, .. } => *port != 0,
+		SocketAddress::Hostname { port, .. } => *port != 0,
+	}
+}
+
+/// Returns whether the given address is a loopback, private, or link-local IP address.
+fn is_private_address(addr: &SocketAddress) -> bool {
+	match addr {
+		SocketAddress::TcpIpV4 { addr, .. } => {
+			let ip = Ipv4Addr::from(*addr);
+			ip.is_loopback() || ip.is_private() || ip.is_link_local()
+		},
+		SocketAddress::TcpIpV6 { addr, .. } => {
+			let ip = Ipv6Addr::from(*addr);
+			// Unique local (fc00::/7) and unicast link-local (fe80::/10) ranges.
+			ip.is_loopback()
+				|| (ip.segments()[0] & 0xfe00) == 0xfc00
+				|| (ip.segments()[0] & 0xffc0) == 0xfe80
 		},
+		_ => false,
 	}
 }
@@ -0,0 +1,288 @@
+pub(crate) mod bitcoind_rpc;
+#[cfg(feature = "cbf")]
+pub(crate) mod cbf;
+
+use crate::io::batch_kv_store::BatchKVStore;
+use crate::logger::{log_error, log_info, FilesystemLogger, Logger};
+use crate::types::{ChainMonitor, ChannelManager, Sweeper};
+use crate::Error;
+
+use bitcoind_rpc::BitcoindRpcClient;
+#[cfg(feature = "cbf")]
+use cbf::CbfClient;
+
+use lightning::chain::{self, BestBlock, Confirm, Filter, Listen, WatchedOutput};
+use lightning::util::persist::KVStore;
+
+use lightning_block_sync::init::synchronize_listeners;
+use lightning_block_sync::poll::{ChainPoller, Validate, ValidatedBlockHeader};
+use lightning_block_sync::{BlockSource, SpvClient, UnboundedCache};
+
+use lightning_transaction_sync::EsploraSyncClient;
+
+use bitcoin::blockdata::block::Header;
+use bitcoin::{BlockHash, Network, Script, Txid};
+
+use std::sync::{Arc, Mutex};
+
+/// The source of chain data, i.e., blocks, transaction confirmations, and fee estimates.
+pub(crate) enum ChainSource {
+	Esplora {
+		tx_sync: EsploraSyncClient<Arc<FilesystemLogger>>,
+	},
+	BitcoindRpc {
+		bitcoind_rpc_client: Arc<BitcoindRpcClient>,
+		// The chain tip our Lightning wallet is synced to and the headers we have seen so far.
+		sync_state: tokio::sync::Mutex<Option<(ValidatedBlockHeader, UnboundedCache)>>,
+		network: Network,
+		logger: Arc<FilesystemLogger>,
+	},
+	#[cfg(feature = "cbf")]
+	Cbf {
+		cbf_client: Arc<CbfClient>,
+		// As compact block filters don't allow to estimate fee rates, we query an Esplora server.
+		fee_oracle: esplora_client::AsyncClient,
+	},
+}
+
+impl ChainSource {
+	pub(crate) fn new_esplora(tx_sync: EsploraSyncClient<Arc<FilesystemLogger>>) -> Self {
+		Self::Esplora { tx_sync }
+	}
+
+	pub(crate) fn new_bitcoind_rpc(
+		bitcoind_rpc_client: Arc<BitcoindRpcClient>, network: Network,
+		logger: Arc<FilesystemLogger>,
+	) -> Self {
+		let sync_state = tokio::sync::Mutex::new(None);
+		Self::BitcoindRpc { bitcoind_rpc_client, sync_state, network, logger }
+	}
+
+	#[cfg(feature = "cbf")]
+	pub(crate) fn new_cbf(
+		cbf_client: Arc<CbfClient>, fee_oracle: esplora_client::AsyncClient,
+	) -> Self {
+		Self::Cbf { cbf_client, fee_oracle }
+	}
+
+	/// Returns the height of the current chain tip as reported by the chain source.
+	pub(crate) async fn get_tip_height(&self) -> Result<u32, Error> {
+		match self {
+			Self::Esplora { tx_sync } => {
+				tx_sync.client().get_height().await.map_err(|_| Error::TxSyncFailed)
+			},
+			Self::BitcoindRpc { bitcoind_rpc_client, .. } => {
+				bitcoind_rpc_client.get_best_block_height().await.map_err(|_| Error::TxSyncFailed)
+			},
+			#[cfg(feature = "cbf")]
+			Self::Cbf { cbf_client, .. } => Ok(cbf_client.tip_height()),
+		}
+	}
+
+	/// Syncs the Lightning wallet, i.e., the given channel manager, chain monitor, and output
+	/// sweeper, to the current chain tip.
+	pub(crate) async fn sync_lightning_wallet<K: BatchKVStore + Sync + Send + 'static>(
+		&self, channel_manager: Arc<ChannelManager<K>>, chain_monitor: Arc<ChainMonitor<K>>,
+		output_sweeper: Arc<Sweeper<K>>,
+	) -> Result<(), Error> {
+		match self {
+			Self::Esplora { tx_sync, .. } => {
+				let confirmables = vec![
+					&*channel_manager as &(dyn Confirm + Sync + Send),
+					&*chain_monitor as &(dyn Confirm + Sync + Send),
+					&*output_sweeper as &(dyn Confirm + Sync + Send),
+				];
+				tx_sync.sync(confirmables).await.map_err(|e| e.into())
+			},
+			Self::BitcoindRpc { bitcoind_rpc_client, sync_state, network, logger } => {
+				let rpc_client = bitcoind_rpc_client.rpc_client();
+				let mut locked_sync_state = sync_state.lock().await;
+
+				let (chain_tip, mut header_cache) = match locked_sync_state.take() {
+					Some(sync_state) => sync_state,
+					None => {
+						let mut header_cache = UnboundedCache::new();
+
+						// A fresh node starts out at the genesis block, so we skip ahead to the
+						// current tip rather than connecting every block in between.
+						if channel_manager.current_best_block().height() == 0 {
+							let (tip_hash, tip_height) =
+								rpc_client.get_best_block().await.map_err(|e| {
+									log_error!(logger, "Failed to retrieve chain tip: {:?}", e);
+									Error::TxSyncFailed
+								})?;
+							let tip_header = rpc_client
+								.get_header(&tip_hash, tip_height)
+								.await
+								.map_err(|e| {
+									log_error!(logger, "Failed to retrieve chain tip: {:?}", e);
+									Error::TxSyncFailed
+								})?;
+							channel_manager
+								.best_block_updated(&tip_header.header, tip_header.height);
+							output_sweeper
+								.best_block_updated(&tip_header.header, tip_header.height);
+						}
+
+						let channel_manager_best_block = channel_manager.current_best_block();
+						let output_sweeper_best_block = output_sweeper.current_best_block();
+						let mut chain_listeners = vec![
+							(
+								channel_manager_best_block.block_hash(),
+								&*channel_manager as &(dyn Listen + Send + Sync),
+							),
+							(
+								output_sweeper_best_block.block_hash(),
+								&*output_sweeper as &(dyn Listen + Send + Sync),
+							),
+						];
+
+						// The monitors might be at different heights, so we synchronize the
+						// chain monitor from the least advanced one.
+						if let Some(best_block) = worst_monitor_best_block(&chain_monitor) {
+							chain_listeners.push((
+								best_block.block_hash(),
+								&*chain_monitor as &(dyn Listen + Send + Sync),
+							));
+						}
+
+						let chain_tip = synchronize_listeners(
+							Arc::clone(&rpc_client),
+							*network,
+							&mut header_cache,
+							chain_listeners,
+						)
+						.await
+						.map_err(|e| {
+							log_error!(logger, "Failed to synchronize chain listeners: {:?}", e);
+							Error::TxSyncFailed
+						})?;
+						log_info!(
+							logger,
+							"Synchronized Lightning wallet to chain tip at height {}",
+							chain_tip.height
+						);
+						(chain_tip, header_cache)
+					},
+				};
+
+				let chain_listener = ChainListener {
+					listeners: vec![
+						&*channel_manager as &(dyn Listen + Send + Sync),
+						&*chain_monitor as &(dyn Listen + Send + Sync),
+						&*output_sweeper as &(dyn Listen + Send + Sync),
+					],
+					chain_tip: Mutex::new((chain_tip.header.block_hash(), chain_tip.height)),
+				};
+
+				let chain_poller = ChainPoller::new(Arc::clone(&rpc_client), *network);
+				let mut spv_client =
+					SpvClient::new(chain_tip, chain_poller, &mut header_cache, &chain_listener);
+				let poll_res = spv_client.poll_best_tip().await;
+
+				// The listeners might have been advanced even if polling failed, so we always pick
+				// up the tip they are actually synced to.
+				let (tip_hash, tip_height) = *chain_listener.chain_tip.lock().unwrap();
+				let new_chain_tip = if tip_hash == chain_tip.header.block_hash() {
+					chain_tip
+				} else if let Some(header) = header_cache.get(&tip_hash) {
+					*header
+				} else {
+					let header_data =
+						rpc_client.get_header(&tip_hash, Some(tip_height)).await.map_err(|e| {
+							log_error!(logger, "Failed to retrieve chain tip: {:?}", e);
+							Error::TxSyncFailed
+						})?;
+					header_data.validate(tip_hash).map_err(|e| {
+						log_error!(logger, "Failed to validate chain tip: {:?}", e);
+						Error::TxSyncFailed
+					})?
+				};
+				*locked_sync_state = Some((new_chain_tip, header_cache));
+
+				poll_res.map(|_| ()).map_err(|e| {
+					log_error!(logger, "Failed to poll for chain updates: {:?}", e);
+					Error::TxSyncFailed
+				})
+			},
+			#[cfg(feature = "cbf")]
+			Self::Cbf { cbf_client, .. } => {
+				// We start scanning from the least advanced component, as monitors might be at
+				// different heights.
+				let start_height = worst_monitor_best_block(&chain_monitor)
+					.map(|best_block| best_block.height())
+					.into_iter()
+					.chain([
+						channel_manager.current_best_block().height(),
+						output_sweeper.current_best_block().height(),
+					])
+					.min()
+					.unwrap_or(0);
+				let confirmables = vec![
+					&*channel_manager as &(dyn Confirm + Sync + Send),
+					&*chain_monitor as &(dyn Confirm + Sync + Send),
+					&*output_sweeper as &(dyn Confirm + Sync + Send),
+				];
+				cbf_client.sync_confirmables(confirmables, start_height).await
+			},
+		}
+	}
+}
+
+impl Filter for ChainSource {
+	fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
+		match self {
+			Self::Esplora { tx_sync, .. } => tx_sync.register_tx(txid, script_pubkey),
+			// We see all transactions when connecting full blocks, so there is nothing to do.
+			Self::BitcoindRpc { .. } => (),
+			#[cfg(feature = "cbf")]
+			Self::Cbf { cbf_client, .. } => cbf_client.register_tx(txid, script_pubkey),
+		}
+	}
+
+	fn register_output(&self, output: WatchedOutput) {
+		match self {
+			Self::Esplora { tx_sync, .. } => tx_sync.register_output(output),
+			Self::BitcoindRpc { .. } => (),
+			#[cfg(feature = "cbf")]
+			Self::Cbf { cbf_client, .. } => cbf_client.register_output(output),
+		}
+	}
+}
+
+fn worst_monitor_best_block<K: KVStore + Sync + Send + 'static>(
+	chain_monitor: &ChainMonitor<K>,
+) -> Option<BestBlock> {
+	chain_monitor
+		.list_monitors()
+		.into_iter()
+		.filter_map(|funding_txo| {
+			chain_monitor.get_monitor(funding_txo).ok().map(|monitor| monitor.current_best_block())
+		})
+		.min_by_key(|best_block| best_block.height())
+}
+
+/// Forwards connected and disconnected blocks to all Lightning wallet components, keeping track
+/// of the chain tip they are synced to.
+struct ChainListener<'a> {
+	listeners: Vec<&'a (dyn Listen + Send + Sync)>,
+	chain_tip: Mutex<(BlockHash, u32)>,
+}
+
+impl Listen for ChainListener<'_> {
+	fn filtered_block_connected(
+		&self, header: &Header, txdata: &chain::transaction::TransactionData, height: u32,
+	) {
+		for listener in &self.listeners {
+			listener.filtered_block_connected(header, txdata, height);
+		}
+		*self.chain_tip.lock().unwrap() = (header.block_hash(), height);
+	}
+
+	fn block_disconnected(&self, header: &Header, height: u32) {
+		for listener in &self.listeners {
+			listener.block_disconnected(header, height);
+		}
+		*self.chain_tip.lock().unwrap() = (header.prev_blockhash, height.saturating_sub(1));
+	}
+}
@@ -0,0 +1,572 @@
+use crate::logger::{log_error, log_trace, Logger};
+use crate::Error;
+
+use lightning_block_sync::http::{HttpEndpoint, JsonResponse};
+use lightning_block_sync::rpc::RpcClient;
+use lightning_block_sync::{BlockData, BlockSource};
+
+use bdk::blockchain::{GetHeight, Progress, WalletSync};
+use bdk::database::{BatchDatabase, Database};
+use bdk::{BlockTime, FeeRate, LocalUtxo, TransactionDetails};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::{Block, BlockHash, OutPoint, Transaction, Txid};
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+// The number of most recently scanned blocks whose hashes we keep around to detect reorgs.
+const MAX_TRACKED_BLOCKS: u32 = 100;
+
+// The number of blocks we rescan after a restart, as we don't know the hashes of the blocks we
+// scanned before and hence can't tell whether they were reorged out in the meantime.
+pub(super) const RESTART_RESCAN_DEPTH: u32 = 6;
+
+/// The estimation mode passed to `estimatesmartfee`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FeeRateEstimationMode {
+	Economical,
+	Conservative,
+}
+
+impl FeeRateEstimationMode {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Self::Economical => "ECONOMICAL",
+			Self::Conservative => "CONSERVATIVE",
+		}
+	}
+}
+
+/// A client talking to the JSON-RPC interface of a Bitcoin Core node.
+pub(crate) struct BitcoindRpcClient {
+	rpc_client: Arc<RpcClient>,
+	// The height from which we scan for wallet transactions on the initial sync of the on-chain
+	// wallet. If unknown, we scan the entire chain.
+	wallet_birthday_height: Option<u32>,
+	wallet_sync_state: Mutex<WalletSyncState>,
+}
+
+#[derive(Clone, Default)]
+struct WalletSyncState {
+	// The hashes of the most recently scanned blocks, keyed by their height.
+	scanned_blocks: BTreeMap<u32, BlockHash>,
+	// The mempool transactions we already scanned for wallet transactions.
+	scanned_mempool_txids: HashSet<Txid>,
+}
+
+impl BitcoindRpcClient {
+	pub(crate) fn new(
+		host: String, port: u16, rpc_user: String, rpc_password: String,
+		wallet_birthday_height: Option<u32>,
+	) -> Self {
+		let http_endpoint = HttpEndpoint::for_host(host).with_port(port);
+		let rpc_credentials = BASE64_STANDARD.encode(format!("{}:{}", rpc_user, rpc_password));
+
+		let rpc_client = Arc::new(
+			RpcClient::new(&rpc_credentials, http_endpoint)
+				.expect("RpcClient::new is actually infallible"),
+		);
+		let wallet_sync_state = Mutex::new(WalletSyncState::default());
+		Self { rpc_client, wallet_birthday_height, wallet_sync_state }
+	}
+
+	pub(crate) fn rpc_client(&self) -> Arc<RpcClient> {
+		Arc::clone(&self.rpc_client)
+	}
+
+	/// Broadcasts the given transaction via `sendrawtransaction`.
+	///
+	/// Transactions that are already known to the node are not considered an error.
+	pub(crate) async fn broadcast_transaction<L: Deref>(
+		&self, tx: &Transaction, logger: L,
+	) -> Result<(), Error>
+	where
+		L::Target: Logger,
+	{
+		let tx_serialized = bitcoin::consensus::encode::serialize_hex(tx);
+		let tx_json = serde_json::json!(tx_serialized);
+		match self.rpc_client.call_method::<Txid>("sendrawtransaction", &[tx_json]).await {
+			Ok(_) => Ok(()),
+			Err(e) => match BroadcastRejection::from_rpc_error(&e) {
+				BroadcastRejection::AlreadyKnown => {
+					log_trace!(logger, "Transaction {} is already known to bitcoind", tx.txid());
+					Ok(())
+				},
+				rejection => {
+					log_error!(
+						logger,
+						"Failed to broadcast transaction {}: {}: {}",
+						tx.txid(),
+						rejection,
+						e
+					);
+					Err(Error::TransactionRejected)
+				},
+			},
+		}
+	}
+
+	/// Retrieves a fee rate estimate for the given confirmation target via `estimatesmartfee`.
+	///
+	/// Returns `None` if bitcoind doesn't have enough data to provide an estimate.
+	pub(crate) async fn get_fee_estimate_for_target(
+		&self, num_blocks: usize, estimation_mode: FeeRateEstimationMode,
+	) -> std::io::Result<Option<FeeRate>> {
+		let num_blocks_json = serde_json::json!(num_blocks);
+		let estimation_mode_json = serde_json::json!(estimation_mode.as_str());
+		self.rpc_client
+			.call_method::<FeeResponse>(
+				"estimatesmartfee",
+				&[num_blocks_json, estimation_mode_json],
+			)
+			.await
+			.map(|resp| resp.0)
+	}
+
+	pub(crate) async fn get_best_block_height(&self) -> std::io::Result<u32> {
+		let (_, height) = self.rpc_client.get_best_block().await.map_err(|e| {
+			std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", e.into_inner()))
+		})?;
+		height.ok_or_else(|| {
+			std::io::Error::new(std::io::ErrorKind::Other, "Failed to retrieve best block height")
+		})
+	}
+
+	async fn get_block_hash_at_height(&self, height: u32) -> std::io::Result<BlockHash> {
+		let height_json = serde_json::json!(height);
+		self.rpc_client
+			.call_method::<BlockHashResponse>("getblockhash", &[height_json])
+			.await
+			.map(|resp| resp.0)
+	}
+
+	async fn get_block_at_height(&self, height: u32) -> std::io::Result<Block> {
+		let block_hash = self.get_block_hash_at_height(height).await?;
+		match self.rpc_client.get_block(&block_hash).await {
+			Ok(BlockData::FullBlock(block)) => Ok(block),
+			Ok(BlockData::HeaderOnly(_)) => {
+				Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to retrieve full block"))
+			},
+			Err(e) => {
+				Err(std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", e.into_inner())))
+			},
+		}
+	}
+
+	async fn get_raw_mempool(&self) -> std::io::Result<Vec<Txid>> {
+		self.rpc_client
+			.call_method::<MempoolResponse>("getrawmempool", &[])
+			.await
+			.map(|resp| resp.0)
+	}
+
+	/// Retrieves the given transaction via `getrawtransaction`, which doesn't require a transaction
+	/// index for transactions in the mempool.
+	async fn get_raw_transaction(&self, txid: &Txid) -> std::io::Result<Transaction> {
+		let txid_json = serde_json::json!(txid.to_string());
+		self.rpc_client
+			.call_method::<RawTransactionResponse>("getrawtransaction", &[txid_json])
+			.await
+			.map(|resp| resp.0)
+	}
+
+	/// Returns the height of the first scanned block that is no longer part of the best chain,
+	/// if any.
+	async fn find_fork_height(
+		&self, scanned_blocks: &BTreeMap<u32, BlockHash>, tip_height: u32,
+	) -> std::io::Result<Option<u32>> {
+		// As blocks commit to their predecessors, we walk back from the most recently scanned
+		// block until we find one that is still part of the best chain.
+		let mut fork_height = None;
+		for (height, block_hash) in scanned_blocks.iter().rev() {
+			if *height <= tip_height && self.get_block_hash_at_height(*height).await? == *block_hash
+			{
+				break;
+			}
+			fork_height = Some(*height);
+		}
+		Ok(fork_height)
+	}
+
+	async fn sync_mempool<D: Database>(
+		&self, database: &RefCell<D>, scanned_mempool_txids: &mut HashSet<Txid>,
+	) -> Result<(), bdk::Error> {
+		let mut mempool_txids =
+			self.get_raw_mempool().await.map_err(to_bdk_error)?.into_iter().collect::<HashSet<_>>();
+
+		let mut unscanned_txs = HashMap::new();
+		for txid in mempool_txids.difference(scanned_mempool_txids) {
+			match self.get_raw_transaction(txid).await {
+				Ok(tx) => {
+					unscanned_txs.insert(*txid, tx);
+				},
+				// The transaction might have been evicted or confirmed in the meantime, in which
+				// case we either don't care about it anymore or pick it up when scanning blocks.
+				Err(_) => continue,
+			}
+		}
+		// We'll retry retrieving any transactions we failed to retrieve on the next sync.
+		mempool_txids.retain(|txid| {
+			scanned_mempool_txids.contains(txid) || unscanned_txs.contains_key(txid)
+		});
+
+		// Transactions might spend outputs of other unconfirmed transactions, so we make sure to
+		// apply parents before their children.
+		while !unscanned_txs.is_empty() {
+			let ready_txids = unscanned_txs
+				.iter()
+				.filter(|(_, tx)| {
+					tx.input.iter().all(|i| !unscanned_txs.contains_key(&i.previous_output.txid))
+				})
+				.map(|(txid, _)| *txid)
+				.collect::<Vec<_>>();
+			for txid in ready_txids {
+				let tx = unscanned_txs.remove(&txid).expect("txid was just looked up");
+				let is_confirmed = database
+					.borrow()
+					.get_tx(&txid, false)?
+					.map_or(false, |details| details.confirmation_time.is_some());
+				if !is_confirmed {
+					apply_transaction(&mut *database.borrow_mut(), &tx, None)?;
+				}
+			}
+		}
+
+		// We forget about transactions that left the mempool, so that we rescan them if they
+		// re-enter it, e.g., after being reorged out.
+		*scanned_mempool_txids = mempool_txids;
+		Ok(())
+	}
+}
+
+// As BDK doesn't allow to update the wallet database directly, we implement the sync interface
+// and scan blocks and the mempool for relevant transactions ourselves.
+#[async_trait::async_trait(?Send)]
+impl WalletSync for BitcoindRpcClient {
+	async fn wallet_setup<D: BatchDatabase>(
+		&self, database: &RefCell<D>, _progress_update: Box<dyn Progress>,
+	) -> Result<(), bdk::Error> {
+		let mut sync_state = self.wallet_sync_state.lock().unwrap().clone();
+		let tip_height = self.get_best_block_height().await.map_err(to_bdk_error)?;
+
+		let sync_height = database.borrow().get_sync_time()?.map(|t| t.block_time.height);
+		let start_height = match sync_height {
+			Some(sync_height) => {
+				let fork_height = if sync_state.scanned_blocks.is_empty() {
+					Some((sync_height + 1).saturating_sub(RESTART_RESCAN_DEPTH))
+				} else {
+					self.find_fork_height(&sync_state.scanned_blocks, tip_height)
+						.await
+						.map_err(to_bdk_error)?
+				};
+
+				match fork_height {
+					Some(fork_height) => {
+						transactions_unconfirmed(&mut *database.borrow_mut(), fork_height)?;
+						sync_state.scanned_blocks.retain(|height, _| *height < fork_height);
+						fork_height
+					},
+					None => sync_height + 1,
+				}
+			},
+			None => self.wallet_birthday_height.unwrap_or(0).min(tip_height),
+		};
+
+		for height in start_height..=tip_height {
+			let block = self.get_block_at_height(height).await.map_err(to_bdk_error)?;
+			apply_block(&mut *database.borrow_mut(), &block, height)?;
+			sync_state.scanned_blocks.insert(height, block.block_hash());
+		}
+		let min_tracked_height = tip_height.saturating_sub(MAX_TRACKED_BLOCKS - 1);
+		sync_state.scanned_blocks.retain(|height, _| *height >= min_tracked_height);
+
+		self.sync_mempool(database, &mut sync_state.scanned_mempool_txids).await?;
+
+		*self.wallet_sync_state.lock().unwrap() = sync_state;
+		Ok(())
+	}
+}
+
+#[async_trait::async_trait(?Send)]
+impl GetHeight for BitcoindRpcClient {
+	async fn get_height(&self) -> Result<u32, bdk::Error> {
+		// BDK records the height we return here as the height the wallet is synced to, so we make
+		// sure to report the height we actually scanned to.
+		let scanned_height =
+			self.wallet_sync_state.lock().unwrap().scanned_blocks.keys().next_back().copied();
+		if let Some(scanned_height) = scanned_height {
+			return Ok(scanned_height);
+		}
+		self.get_best_block_height().await.map_err(to_bdk_error)
+	}
+}
+
+/// Marks all wallet transactions confirmed at or above `fork_height` as unconfirmed, mirroring
+/// LDK's [`Confirm::transaction_unconfirmed`] for blocks that were reorged out.
+///
+/// The transactions will be marked as confirmed again when we rescan the blocks they were
+/// included in.
+///
+/// [`Confirm::transaction_unconfirmed`]: lightning::chain::Confirm::transaction_unconfirmed
+pub(super) fn transactions_unconfirmed<D: Database>(
+	database: &mut D, fork_height: u32,
+) -> Result<(), bdk::Error> {
+	for mut details in database.iter_txs(true)? {
+		if details.confirmation_time.as_ref().map_or(false, |t| t.height >= fork_height) {
+			details.confirmation_time = None;
+			database.set_tx(&details)?;
+		}
+	}
+	Ok(())
+}
+
+pub(super) fn apply_block<D: Database>(
+	database: &mut D, block: &Block, height: u32,
+) -> Result<(), bdk::Error> {
+	let confirmation_time = BlockTime { height, timestamp: block.header.time as u64 };
+	for tx in &block.txdata {
+		apply_transaction(database, tx, Some(confirmation_time.clone()))?;
+	}
+	Ok(())
+}
+
+fn apply_transaction<D: Database>(
+	database: &mut D, tx: &Transaction, confirmation_time: Option<BlockTime>,
+) -> Result<(), bdk::Error> {
+	let txid = tx.txid();
+	let mut sent = 0;
+	let mut received = 0;
+	let mut all_inputs_ours = !tx.is_coin_base();
+
+	for input in &tx.input {
+		match database.get_utxo(&input.previous_output)? {
+			Some(mut utxo) => {
+				sent += utxo.txout.value;
+				utxo.is_spent = true;
+				database.set_utxo(&utxo)?;
+			},
+			None => all_inputs_ours = false,
+		}
+	}
+
+	for (vout, output) in tx.output.iter().enumerate() {
+		if let Some((keychain, index)) =
+			database.get_path_from_script_pubkey(&output.script_pubkey)?
+		{
+			received += output.value;
+			// We might rescan transactions, e.g., once they confirm or after a reorg, so we make
+			// sure not to forget that an output was spent already.
+			let outpoint = OutPoint::new(txid, vout as u32);
+			let is_spent = database.get_utxo(&outpoint)?.map_or(false, |utxo| utxo.is_spent);
+			let utxo = LocalUtxo { outpoint, txout: output.clone(), keychain, is_spent };
+			database.set_utxo(&utxo)?;
+
+			if database.get_last_index(keychain)?.map_or(true, |last_index| index > last_index) {
+				database.set_last_index(keychain, index)?;
+			}
+		}
+	}
+
+	if sent == 0 && received == 0 {
+		return Ok(());
+	}
+
+	let fee = if all_inputs_ours {
+		sent.checked_sub(tx.output.iter().map(|o| o.value).sum::<u64>())
+	} else {
+		None
+	};
+	database.set_tx(&TransactionDetails {
+		transaction: Some(tx.clone()),
+		txid,
+		received,
+		sent,
+		fee,
+		confirmation_time,
+	})?;
+	Ok(())
+}
+
+pub(super) fn to_bdk_error(e: std::io::Error) -> bdk::Error {
+	bdk::Error::Generic(e.to_string())
+}
+
+/// The reason bitcoind rejected a transaction broadcast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BroadcastRejection {
+	AlreadyKnown,
+	InsufficientFee,
+	MissingOrSpentInputs,
+	PolicyViolation,
+	Other,
+}
+
+impl BroadcastRejection {
+	fn from_rpc_error(e: &std::io::Error) -> Self {
+		let msg = e.to_string();
+		if msg.contains("already in block chain")
+			|| msg.contains("txn-already-known")
+			|| msg.contains("txn-already-in-mempool")
+		{
+			Self::AlreadyKnown
+		} else if msg.contains("insufficient fee")
+			|| msg.contains("min relay fee not met")
+			|| msg.contains("mempool min fee not met")
+		{
+			Self::InsufficientFee
+		} else if msg.contains("missingorspent")
+			|| msg.contains("missing-inputs")
+			|| msg.contains("txn-mempool-conflict")
+		{
+			Self::MissingOrSpentInputs
+		} else if msg.contains("dust")
+			|| msg.contains("non-final")
+			|| msg.contains("script-verify-flag")
+			|| msg.contains("too-long-mempool-chain")
+		{
+			Self::PolicyViolation
+		} else {
+			Self::Other
+		}
+	}
+}
+
+impl std::fmt::Display for BroadcastRejection {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::AlreadyKnown => write!(f, "transaction is already known"),
+			Self::InsufficientFee => write!(f, "transaction fee is too low to enter the mempool"),
+			Self::MissingOrSpentInputs => {
+				write!(f, "transaction inputs are missing or already spent")
+			},
+			Self::PolicyViolation => write!(f, "transaction was rejected by mempool policy"),
+			Self::Other => write!(f, "transaction was rejected"),
+		}
+	}
+}
+
+struct FeeResponse(Option<FeeRate>);
+
+impl TryInto<FeeResponse> for JsonResponse {
+	type Error = std::io::Error;
+	fn try_into(self) -> std::io::Result<FeeResponse> {
+		if !self.0["errors"].is_null() {
+			// bitcoind reports errors if it doesn't have enough data to estimate a fee rate yet.
+			return Ok(FeeResponse(None));
+		}
+
+		let fee_rate_btc_per_kvbyte = self.0["feerate"].as_f64().ok_or_else(|| {
+			std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to parse fee rate")
+		})?;
+
+		// Bitcoin Core gives us a fee rate in BTC/kvB, so we multiply by 25_000_000 (10^8 / 4)
+		// to get satoshis per 1000 weight units.
+		let fee_rate_sat_per_kwu = (fee_rate_btc_per_kvbyte * 25_000_000.0).round() as f32;
+		Ok(FeeResponse(Some(FeeRate::from_sat_per_kwu(fee_rate_sat_per_kwu))))
+	}
+}
+
+struct MempoolResponse(Vec<Txid>);
+
+impl TryInto<MempoolResponse> for JsonResponse {
+	type Error = std::io::Error;
+	fn try_into(self) -> std::io::Result<MempoolResponse> {
+		let txids = self.0.as_array().ok_or_else(|| {
+			std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to parse mempool")
+		})?;
+		txids
+			.iter()
+			.map(|txid| {
+				txid.as_str().and_then(|s| Txid::from_str(s).ok()).ok_or_else(|| {
+					std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to parse txid")
+				})
+			})
+			.collect::<std::io::Result<Vec<_>>>()
+			.map(MempoolResponse)
+	}
+}
+
+struct RawTransactionResponse(Transaction);
+
+impl TryInto<RawTransactionResponse> for JsonResponse {
+	type Error = std::io::Error;
+	fn try_into(self) -> std::io::Result<RawTransactionResponse> {
+		let tx = self
+			.0
+			.as_str()
+			.and_then(|s| Vec::<u8>::from_hex(s).ok())
+			.and_then(|bytes| bitcoin::consensus::encode::deserialize(&bytes).ok())
+			.ok_or_else(|| {
+				std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to parse transaction")
+			})?;
+		Ok(RawTransactionResponse(tx))
+	}
+}
+
+struct BlockHashResponse(BlockHash);
+
+impl TryInto<BlockHashResponse> for JsonResponse {
+	type Error = std::io::Error;
+	fn try_into(self) -> std::io::Result<BlockHashResponse> {
+		let block_hash =
+			self.0.as_str().and_then(|s| BlockHash::from_str(s).ok()).ok_or_else(|| {
+				std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to parse block hash")
+			})?;
+		Ok(BlockHashResponse(block_hash))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fee_response_is_converted_to_sat_per_kwu() {
+		let resp = JsonResponse(serde_json::json!({ "feerate": 0.00010000, "blocks": 2 }));
+		let fee_rate: FeeResponse = resp.try_into().unwrap();
+		assert_eq!(fee_rate.0, Some(FeeRate::from_sat_per_kwu(2500.0)));
+
+		let resp =
+			JsonResponse(serde_json::json!({ "errors": ["Insufficient data"], "blocks": 0 }));
+		let fee_rate: FeeResponse = resp.try_into().unwrap();
+		assert_eq!(fee_rate.0, None);
+	}
+
+	#[test]
+	fn mempool_response_is_parsed() {
+		let txid_str = "f9f5e9b7b1d4f7d0f3f3c1b0f0c6c2b4e1d7b1f3e4c5d6a7b8c9d0e1f2a3b4c5";
+		let resp = JsonResponse(serde_json::json!([txid_str]));
+		let mempool: MempoolResponse = resp.try_into().unwrap();
+		assert_eq!(mempool.0, vec![Txid::from_str(txid_str).unwrap()]);
+
+		let resp = JsonResponse(serde_json::json!(["not a txid"]));
+		assert!(TryInto::<MempoolResponse>::try_into(resp).is_err());
+	}
+
+	#[test]
+	fn broadcast_rejections_are_classified() {
+		let err = |msg: &str| std::io::Error::new(std::io::ErrorKind::Other, msg.to_string());
+		assert_eq!(
+			BroadcastRejection::from_rpc_error(&err("Transaction already in block chain")),
+			BroadcastRejection::AlreadyKnown
+		);
+		assert_eq!(
+			BroadcastRejection::from_rpc_error(&err("min relay fee not met, 100 < 110")),
+			BroadcastRejection::InsufficientFee
+		);
+		assert_eq!(
+			BroadcastRejection::from_rpc_error(&err("bad-txns-inputs-missingorspent")),
+			BroadcastRejection::MissingOrSpentInputs
+		);
+		assert_eq!(
+			BroadcastRejection::from_rpc_error(&err("Connection refused")),
+			BroadcastRejection::Other
+		);
+	}
+}
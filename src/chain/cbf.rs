@@ -0,0 +1,971 @@
+//! A chain source syncing via BIP157/158 compact block filters served by a single P2P peer.
+use super::bitcoind_rpc::{
+	apply_block, to_bdk_error, transactions_unconfirmed, RESTART_RESCAN_DEPTH,
+};
+
+use crate::logger::{log_debug, log_error, log_info, FilesystemLogger, Logger};
+use crate::Error;
+
+use lightning::chain::{Confirm, WatchedOutput};
+
+use bdk::blockchain::{GetHeight, Progress, WalletSync};
+use bdk::database::BatchDatabase;
+
+use bitcoin::bip158::BlockFilter;
+use bitcoin::blockdata::block::Header;
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::consensus::encode;
+use bitcoin::hashes::Hash;
+use bitcoin::network::address::Address;
+use bitcoin::network::constants::{Magic, ServiceFlags};
+use bitcoin::network::message::{NetworkMessage, RawNetworkMessage};
+use bitcoin::network::message_blockdata::{GetHeadersMessage, Inventory};
+use bitcoin::network::message_filter::{CFHeaders, CFilter, GetCFHeaders, GetCFilters};
+use bitcoin::network::message_network::VersionMessage;
+use bitcoin::pow::{CompactTarget, Target, Work};
+use bitcoin::{
+	Block, BlockHash, FilterHash, Network, OutPoint, Script, ScriptBuf, Transaction, Txid,
+};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// The protocol version we announce. BIP157 messages require at least 70015 (NO_BLOOM_VERSION).
+const PROTOCOL_VERSION: u32 = 70016;
+
+// The user agent we announce to our peer.
+const USER_AGENT: &str = "/ldk-node:0.2.1/";
+
+// The BIP158 filter type of basic block filters.
+const BASIC_FILTER_TYPE: u8 = 0x00;
+
+// The maximum number of headers a peer sends in reply to a single `getheaders` message.
+const MAX_HEADERS_PER_MSG: usize = 2000;
+
+// The maximum number of filters a peer serves in reply to a single `getcfilters` message.
+const MAX_FILTERS_PER_MSG: u32 = 1000;
+
+// The maximum payload size of a message we're willing to read from our peer.
+const MAX_MSG_PAYLOAD_SIZE: usize = 32 * 1024 * 1024;
+
+// The time we wait for our peer to reply to a request before giving up on it.
+const PEER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A client syncing chain data via compact block filters (BIP157/158) from a single peer.
+///
+/// The client keeps the header chain in memory, i.e., all block headers are downloaded from the
+/// peer when the node is (re-)started. Filters are then only downloaded and scanned for the
+/// range of blocks that haven't been scanned yet, and full blocks are only retrieved if their
+/// filter matches any of the scripts we're interested in.
+///
+/// Block headers are required to commit to the proof of work demanded by the consensus rules, and
+/// we follow the chain with the most accumulated proof of work. Note that we however fully rely
+/// on the configured peer for the validity of the filters it serves, as we don't compare filter
+/// headers across multiple peers.
+pub(crate) struct CbfClient {
+	peer_address: String,
+	network: Network,
+	peer: tokio::sync::Mutex<Option<CbfPeer>>,
+	header_chain: Mutex<HeaderChain>,
+	watched: Mutex<WatchedItems>,
+	// The height up to which the Lightning wallet was last synced.
+	lightning_synced_height: tokio::sync::Mutex<Option<u32>>,
+	// The lowest height at which we detected a reorg since the Lightning and on-chain wallet were
+	// last synced, respectively.
+	lightning_fork_height: Mutex<Option<u32>>,
+	wallet_fork_height: Mutex<Option<u32>>,
+	// The height from which we scan for wallet transactions on the initial sync of the on-chain
+	// wallet. If unknown, we scan the entire chain.
+	wallet_birthday_height: Option<u32>,
+	// The height up to which the on-chain wallet was last scanned.
+	wallet_scanned_height: Mutex<Option<u32>>,
+	logger: Arc<FilesystemLogger>,
+}
+
+impl CbfClient {
+	pub(crate) fn new(
+		peer_address: String, network: Network, wallet_birthday_height: Option<u32>,
+		logger: Arc<FilesystemLogger>,
+	) -> Self {
+		let peer = tokio::sync::Mutex::new(None);
+		let header_chain = Mutex::new(HeaderChain::new(network));
+		let watched = Mutex::new(WatchedItems::default());
+		let lightning_synced_height = tokio::sync::Mutex::new(None);
+		let lightning_fork_height = Mutex::new(None);
+		let wallet_fork_height = Mutex::new(None);
+		let wallet_scanned_height = Mutex::new(None);
+		Self {
+			peer_address,
+			network,
+			peer,
+			header_chain,
+			watched,
+			lightning_synced_height,
+			lightning_fork_height,
+			wallet_fork_height,
+			wallet_birthday_height,
+			wallet_scanned_height,
+			logger,
+		}
+	}
+
+	/// Returns the height of the best header chain we know of.
+	pub(crate) fn tip_height(&self) -> u32 {
+		self.header_chain.lock().unwrap().tip_height()
+	}
+
+	pub(crate) fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
+		let mut locked_watched = self.watched.lock().unwrap();
+		locked_watched.txids.insert(*txid);
+		locked_watched.add_script(script_pubkey);
+	}
+
+	pub(crate) fn register_output(&self, output: WatchedOutput) {
+		let mut locked_watched = self.watched.lock().unwrap();
+		locked_watched.outpoints.insert(output.outpoint.into_bitcoin_outpoint());
+		locked_watched.add_script(&output.script_pubkey);
+	}
+
+	/// Syncs the given [`Confirm`] implementations to the chain tip, starting from the block
+	/// following `start_height`.
+	///
+	/// If `start_height` is 0, i.e., the confirmables were never synced, we skip ahead to the
+	/// current chain tip.
+	pub(crate) async fn sync_confirmables(
+		&self, confirmables: Vec<&(dyn Confirm + Sync + Send)>, start_height: u32,
+	) -> Result<(), Error> {
+		let mut locked_synced_height = self.lightning_synced_height.lock().await;
+
+		let mut locked_peer = self.connected_peer().await?;
+		let peer = locked_peer.as_mut().expect("We just connected");
+		let res = self
+			.sync_confirmables_with_peer(
+				peer,
+				confirmables,
+				start_height,
+				&mut *locked_synced_height,
+			)
+			.await;
+		if res.is_err() {
+			// Reconnect on the next attempt.
+			*locked_peer = None;
+		}
+		res.map_err(|e| {
+			log_error!(self.logger, "Failed to sync Lightning wallet via compact filters: {}", e);
+			Error::TxSyncFailed
+		})
+	}
+
+	async fn sync_confirmables_with_peer(
+		&self, peer: &mut CbfPeer, confirmables: Vec<&(dyn Confirm + Sync + Send)>,
+		start_height: u32, synced_height: &mut Option<u32>,
+	) -> std::io::Result<()> {
+		let header_sync = self.sync_headers(peer).await?;
+		let fork_height = *self.lightning_fork_height.lock().unwrap();
+
+		// Unconfirm any transactions whose block was reorged out of the chain.
+		for confirmable in &confirmables {
+			for (txid, height, block_hash) in confirmable.get_relevant_txids() {
+				if let Some(block_hash) = block_hash {
+					if self.header_chain.lock().unwrap().hash_at(height) != Some(block_hash) {
+						confirmable.transaction_unconfirmed(&txid);
+					}
+				}
+			}
+		}
+
+		let mut scan_start = match *synced_height {
+			Some(height) => height + 1,
+			None if start_height == 0 => header_sync.tip_height + 1,
+			None => start_height + 1,
+		};
+		if let Some(fork_height) = fork_height {
+			scan_start = scan_start.min(fork_height);
+		}
+
+		let mut scripts = {
+			let mut locked_watched = self.watched.lock().unwrap();
+			locked_watched.new_scripts.clear();
+			locked_watched.scripts.clone()
+		};
+
+		// Processing confirmed transactions might have the confirmables register further
+		// outputs, e.g., of a commitment transaction, which might have been spent in the
+		// range we just scanned. We therefore rescan the range for any newly registered scripts.
+		while !scripts.is_empty() {
+			let matched_blocks =
+				self.scan_filters(peer, scan_start, header_sync.tip_height, &scripts).await?;
+			for (height, block) in matched_blocks {
+				let relevant_txs = {
+					let locked_watched = self.watched.lock().unwrap();
+					block
+						.txdata
+						.iter()
+						.enumerate()
+						.filter(|(_, tx)| locked_watched.is_relevant(tx))
+						.collect::<Vec<_>>()
+				};
+				if relevant_txs.is_empty() {
+					continue;
+				}
+				for confirmable in &confirmables {
+					confirmable.transactions_confirmed(&block.header, &relevant_txs, height);
+				}
+			}
+
+			scripts = std::mem::take(&mut self.watched.lock().unwrap().new_scripts);
+		}
+
+		for confirmable in &confirmables {
+			confirmable.best_block_updated(&header_sync.tip_header, header_sync.tip_height);
+		}
+		*synced_height = Some(header_sync.tip_height);
+		*self.lightning_fork_height.lock().unwrap() = None;
+		Ok(())
+	}
+
+	/// Broadcasts the given transaction to our peer.
+	///
+	/// Note that peers don't report whether they accepted the transaction, i.e., we only learn
+	/// about a successful broadcast once the transaction confirms.
+	pub(crate) async fn broadcast_transaction(&self, tx: &Transaction) -> Result<(), Error> {
+		let mut locked_peer = self.connected_peer().await?;
+		let peer = locked_peer.as_mut().expect("We just connected");
+		peer.send(NetworkMessage::Tx(tx.clone())).await.map_err(|e| {
+			log_error!(self.logger, "Failed to broadcast transaction {}: {}", tx.txid(), e);
+			*locked_peer = None;
+			Error::TxSyncFailed
+		})
+	}
+
+	async fn connected_peer(&self) -> Result<tokio::sync::MutexGuard<'_, Option<CbfPeer>>, Error> {
+		let mut locked_peer = self.peer.lock().await;
+		if locked_peer.is_none() {
+			let start_height = self.header_chain.lock().unwrap().tip_height();
+			let peer = CbfPeer::connect(&self.peer_address, self.network, start_height)
+				.await
+				.map_err(|e| {
+					log_error!(
+						self.logger,
+						"Failed to connect to compact filter peer {}: {}",
+						self.peer_address,
+						e
+					);
+					Error::ConnectionFailed
+				})?;
+			log_info!(self.logger, "Connected to compact filter peer {}", self.peer_address);
+			*locked_peer = Some(peer);
+		}
+		Ok(locked_peer)
+	}
+
+	async fn sync_headers(&self, peer: &mut CbfPeer) -> std::io::Result<HeaderSyncUpdate> {
+		loop {
+			let locator = self.header_chain.lock().unwrap().locator();
+			let headers = peer.get_headers(locator).await?;
+			if headers.is_empty() {
+				break;
+			}
+
+			let mut locked_header_chain = self.header_chain.lock().unwrap();
+			if let Some(height) = locked_header_chain.connect_headers(&headers)? {
+				log_info!(self.logger, "Detected chain reorganization at height {}", height);
+				for fork_height in [&self.lightning_fork_height, &self.wallet_fork_height] {
+					let mut locked_fork_height = fork_height.lock().unwrap();
+					*locked_fork_height =
+						Some(locked_fork_height.map_or(height, |h| h.min(height)));
+				}
+			}
+			log_debug!(
+				self.logger,
+				"Synced block headers up to height {}",
+				locked_header_chain.tip_height()
+			);
+
+			if headers.len() < MAX_HEADERS_PER_MSG {
+				break;
+			}
+		}
+
+		let locked_header_chain = self.header_chain.lock().unwrap();
+		Ok(HeaderSyncUpdate {
+			tip_header: locked_header_chain.tip_header,
+			tip_height: locked_header_chain.tip_height(),
+		})
+	}
+
+	// Scans the filters of the given range of blocks for the given scripts, returning all blocks
+	// that matched.
+	async fn scan_filters(
+		&self, peer: &mut CbfPeer, start_height: u32, stop_height: u32,
+		scripts: &HashSet<ScriptBuf>,
+	) -> std::io::Result<Vec<(u32, Block)>> {
+		let mut matched_blocks = Vec::new();
+		if scripts.is_empty() {
+			return Ok(matched_blocks);
+		}
+
+		let mut batch_start = start_height;
+		while batch_start <= stop_height {
+			let batch_stop = stop_height.min(batch_start + MAX_FILTERS_PER_MSG - 1);
+			let stop_hash = self.hash_at(batch_stop)?;
+			let num_filters = (batch_stop - batch_start + 1) as usize;
+
+			let cfheaders = peer.get_cfheaders(batch_start, stop_hash).await?;
+			if cfheaders.filter_hashes.len() != num_filters {
+				return Err(invalid_data("Peer sent an unexpected number of filter headers"));
+			}
+
+			let cfilters = peer.get_cfilters(batch_start, stop_hash, num_filters).await?;
+			for (i, (cfilter, filter_hash)) in
+				cfilters.iter().zip(cfheaders.filter_hashes.iter()).enumerate()
+			{
+				let height = batch_start + i as u32;
+				let block_hash = self.hash_at(height)?;
+				if cfilter.block_hash != block_hash {
+					return Err(invalid_data("Peer sent a filter for an unexpected block"));
+				}
+				if FilterHash::hash(&cfilter.filter) != *filter_hash {
+					return Err(invalid_data("Peer sent a filter not matching its filter header"));
+				}
+
+				let filter = BlockFilter::new(&cfilter.filter);
+				let matches =
+					filter
+						.match_any(&block_hash, scripts.iter().map(|s| s.as_bytes()))
+						.map_err(|e| invalid_data(&format!("Failed to match filter: {}", e)))?;
+				if matches {
+					let block = peer.get_block(block_hash).await?;
+					matched_blocks.push((height, block));
+				}
+			}
+
+			batch_start = batch_stop + 1;
+		}
+		Ok(matched_blocks)
+	}
+
+	fn hash_at(&self, height: u32) -> std::io::Result<BlockHash> {
+		self.header_chain
+			.lock()
+			.unwrap()
+			.hash_at(height)
+			.ok_or_else(|| invalid_data("Block height is beyond the known chain tip"))
+	}
+}
+
+// As BDK doesn't allow to update the wallet database directly, we implement the sync interface
+// and scan the filters for wallet scripts ourselves.
+//
+// Note that we only track confirmed transactions, as we don't have access to the mempool.
+#[async_trait::async_trait(?Send)]
+impl WalletSync for CbfClient {
+	async fn wallet_setup<D: BatchDatabase>(
+		&self, database: &RefCell<D>, _progress_update: Box<dyn Progress>,
+	) -> Result<(), bdk::Error> {
+		let scripts: HashSet<ScriptBuf> =
+			database.borrow().iter_script_pubkeys(None)?.into_iter().collect();
+		let sync_height = database.borrow().get_sync_time()?.map(|t| t.block_time.height);
+
+		let mut locked_peer = self.connected_peer().await.map_err(|e| {
+			bdk::Error::Generic(format!("Failed to connect to compact filter peer: {}", e))
+		})?;
+		let peer = locked_peer.as_mut().expect("We just connected");
+
+		let is_restarted = self.wallet_scanned_height.lock().unwrap().is_none();
+		let sync_res = async {
+			let tip_height = self.sync_headers(peer).await?.tip_height;
+			let (rescan_height, start_height) = match sync_height {
+				Some(height) => {
+					// We rescan any blocks that were reorged out since the last sync. After a
+					// restart we don't know whether that was the case for the blocks we scanned
+					// before, so we conservatively rescan the most recent ones.
+					let fork_height = *self.wallet_fork_height.lock().unwrap();
+					let restart_height =
+						is_restarted.then(|| (height + 1).saturating_sub(RESTART_RESCAN_DEPTH));
+					let rescan_height = fork_height
+						.into_iter()
+						.chain(restart_height)
+						.min()
+						.filter(|h| *h <= height);
+					(rescan_height, rescan_height.unwrap_or(height + 1))
+				},
+				None => (None, self.wallet_birthday_height.unwrap_or(0).min(tip_height)),
+			};
+			let matched_blocks =
+				self.scan_filters(peer, start_height, tip_height, &scripts).await?;
+			Ok::<_, std::io::Error>((tip_height, rescan_height, matched_blocks))
+		}
+		.await;
+
+		let (tip_height, rescan_height, matched_blocks) = match sync_res {
+			Ok(res) => res,
+			Err(e) => {
+				*locked_peer = None;
+				return Err(to_bdk_error(e));
+			},
+		};
+
+		if let Some(rescan_height) = rescan_height {
+			transactions_unconfirmed(&mut *database.borrow_mut(), rescan_height)?;
+		}
+		for (height, block) in matched_blocks {
+			apply_block(&mut *database.borrow_mut(), &block, height)?;
+		}
+
+		*self.wallet_scanned_height.lock().unwrap() = Some(tip_height);
+		*self.wallet_fork_height.lock().unwrap() = None;
+		Ok(())
+	}
+}
+
+#[async_trait::async_trait(?Send)]
+impl GetHeight for CbfClient {
+	async fn get_height(&self) -> Result<u32, bdk::Error> {
+		// BDK records the height we return here as the height the wallet is synced to, so we make
+		// sure to report the height we actually scanned to.
+		if let Some(scanned_height) = *self.wallet_scanned_height.lock().unwrap() {
+			return Ok(scanned_height);
+		}
+		Ok(self.header_chain.lock().unwrap().tip_height())
+	}
+}
+
+struct HeaderSyncUpdate {
+	tip_header: Header,
+	tip_height: u32,
+}
+
+#[derive(Default)]
+struct WatchedItems {
+	txids: HashSet<Txid>,
+	outpoints: HashSet<OutPoint>,
+	scripts: HashSet<ScriptBuf>,
+	// Scripts registered since the last time we started scanning.
+	new_scripts: HashSet<ScriptBuf>,
+}
+
+impl WatchedItems {
+	fn add_script(&mut self, script_pubkey: &Script) {
+		if self.scripts.insert(script_pubkey.to_owned()) {
+			self.new_scripts.insert(script_pubkey.to_owned());
+		}
+	}
+
+	fn is_relevant(&self, tx: &Transaction) -> bool {
+		self.txids.contains(&tx.txid())
+			|| tx.input.iter().any(|input| self.outpoints.contains(&input.previous_output))
+	}
+}
+
+/// The chain of block headers from genesis to the best known tip, i.e., the valid chain with the
+/// most accumulated proof of work.
+struct HeaderChain {
+	network: Network,
+	entries: Vec<HeaderEntry>,
+	tip_header: Header,
+}
+
+/// The parts of a block header we need to keep around to validate the headers following it.
+#[derive(Clone, Copy)]
+struct HeaderEntry {
+	block_hash: BlockHash,
+	bits: CompactTarget,
+	time: u32,
+}
+
+impl From<&Header> for HeaderEntry {
+	fn from(header: &Header) -> Self {
+		Self { block_hash: header.block_hash(), bits: header.bits, time: header.time }
+	}
+}
+
+impl HeaderChain {
+	fn new(network: Network) -> Self {
+		let genesis_header = genesis_block(network).header;
+		Self {
+			network,
+			entries: vec![HeaderEntry::from(&genesis_header)],
+			tip_header: genesis_header,
+		}
+	}
+
+	fn tip_height(&self) -> u32 {
+		(self.entries.len() - 1) as u32
+	}
+
+	fn hash_at(&self, height: u32) -> Option<BlockHash> {
+		self.entries.get(height as usize).map(|entry| entry.block_hash)
+	}
+
+	// Returns the block locator for our current tip, i.e., the hashes of the last ten blocks
+	// followed by exponentially sparser hashes back to genesis.
+	fn locator(&self) -> Vec<BlockHash> {
+		let mut locator = Vec::new();
+		let mut height = self.entries.len() - 1;
+		let mut step = 1;
+		loop {
+			locator.push(self.entries[height].block_hash);
+			if height == 0 {
+				break;
+			}
+			if locator.len() >= 10 {
+				step *= 2;
+			}
+			height = height.saturating_sub(step);
+		}
+		locator
+	}
+
+	// Connects the given headers, returning the height of the first disconnected block if they
+	// reorganized our chain.
+	//
+	// Every header needs to commit to the proof of work required by the consensus rules, and we
+	// only reorganize to forks with more accumulated proof of work than our current chain.
+	fn connect_headers(&mut self, headers: &[Header]) -> std::io::Result<Option<u32>> {
+		let first_prev_hash = headers[0].prev_blockhash;
+		let prev_height = self
+			.entries
+			.iter()
+			.rposition(|entry| entry.block_hash == first_prev_hash)
+			.ok_or_else(|| invalid_data("Peer sent headers not connecting to our chain"))?;
+
+		let mut new_entries: Vec<HeaderEntry> = Vec::with_capacity(headers.len());
+		for header in headers {
+			let prev_entry = new_entries.last().unwrap_or(&self.entries[prev_height]);
+			if header.prev_blockhash != prev_entry.block_hash {
+				return Err(invalid_data("Peer sent non-continuous headers"));
+			}
+
+			let height = prev_height + 1 + new_entries.len();
+			let required_bits = required_bits(self.network, height, header.time, |h| {
+				if h <= prev_height {
+					self.entries[h]
+				} else {
+					new_entries[h - prev_height - 1]
+				}
+			});
+			header
+				.validate_pow(Target::from_compact(required_bits))
+				.map_err(|_| invalid_data("Peer sent a header with invalid proof of work"))?;
+			new_entries.push(HeaderEntry::from(header));
+		}
+
+		let fork_height = if prev_height + 1 < self.entries.len() {
+			let disconnected_work = total_work(&self.entries[prev_height + 1..]);
+			let connected_work = total_work(&new_entries);
+			if connected_work <= disconnected_work {
+				return Err(invalid_data("Peer sent headers of a fork with less work"));
+			}
+			self.entries.truncate(prev_height + 1);
+			Some((prev_height + 1) as u32)
+		} else {
+			None
+		};
+
+		self.entries.extend(new_entries);
+		self.tip_header = *headers.last().expect("headers are non-empty");
+		Ok(fork_height)
+	}
+}
+
+// The number of blocks between difficulty adjustments.
+const DIFFICULTY_ADJUSTMENT_INTERVAL: usize = 2016;
+
+// The expected time it takes to mine the blocks between two difficulty adjustments.
+const POW_TARGET_TIMESPAN: u32 = 14 * 24 * 60 * 60;
+
+// The expected time between two blocks.
+const POW_TARGET_SPACING: u32 = 10 * 60;
+
+// Returns the easiest proof of work target allowed on the given network.
+fn pow_limit(network: Network) -> Target {
+	let bits = match network {
+		Network::Regtest => 0x207fffff,
+		Network::Signet => 0x1e0377ae,
+		_ => 0x1d00ffff,
+	};
+	Target::from_compact(CompactTarget::from_consensus(bits))
+}
+
+// Returns the proof of work target the header at the given height with the given timestamp needs
+// to commit to, following Bitcoin Core's `GetNextWorkRequired`. `entry_at` returns the header
+// entries of the chain the header builds on.
+fn required_bits<F: Fn(usize) -> HeaderEntry>(
+	network: Network, height: usize, time: u32, entry_at: F,
+) -> CompactTarget {
+	let prev_entry = entry_at(height - 1);
+	let pow_limit = pow_limit(network);
+
+	// Regtest never adjusts the difficulty.
+	if network == Network::Regtest {
+		return prev_entry.bits;
+	}
+
+	if height % DIFFICULTY_ADJUSTMENT_INTERVAL != 0 {
+		if network != Network::Testnet {
+			return prev_entry.bits;
+		}
+
+		// Testnet allows mining a minimum difficulty block if no block was found for twice the
+		// target spacing. Otherwise, the difficulty of the last regular block applies.
+		if time > prev_entry.time + 2 * POW_TARGET_SPACING {
+			return pow_limit.to_compact_lossy();
+		}
+		let mut h = height - 1;
+		while h % DIFFICULTY_ADJUSTMENT_INTERVAL != 0
+			&& Target::from_compact(entry_at(h).bits) == pow_limit
+		{
+			h -= 1;
+		}
+		return entry_at(h).bits;
+	}
+
+	let first_entry = entry_at(height - DIFFICULTY_ADJUSTMENT_INTERVAL);
+	let actual_timespan = prev_entry
+		.time
+		.saturating_sub(first_entry.time)
+		.clamp(POW_TARGET_TIMESPAN / 4, POW_TARGET_TIMESPAN * 4);
+	let target = Target::from_compact(prev_entry.bits);
+	let new_target = mul_div_target(target, actual_timespan, POW_TARGET_TIMESPAN);
+	new_target.min(pow_limit).to_compact_lossy()
+}
+
+// Returns `target * mul / div`. The result is expected to fit 256 bits, which is given for
+// valid targets multiplied by the bounded adjustment factor.
+fn mul_div_target(target: Target, mul: u32, div: u32) -> Target {
+	let bytes = target.to_le_bytes();
+	let mut limbs = [0u32; 8];
+	for (i, limb) in limbs.iter_mut().enumerate() {
+		*limb = u32::from_le_bytes([
+			bytes[4 * i],
+			bytes[4 * i + 1],
+			bytes[4 * i + 2],
+			bytes[4 * i + 3],
+		]);
+	}
+
+	let mut carry = 0u64;
+	for limb in limbs.iter_mut() {
+		let product = *limb as u64 * mul as u64 + carry;
+		*limb = product as u32;
+		carry = product >> 32;
+	}
+	debug_assert_eq!(carry, 0);
+
+	let mut remainder = 0u64;
+	for limb in limbs.iter_mut().rev() {
+		let dividend = (remainder << 32) | *limb as u64;
+		*limb = (dividend / div as u64) as u32;
+		remainder = dividend % div as u64;
+	}
+
+	let mut bytes = [0u8; 32];
+	for (i, limb) in limbs.iter().enumerate() {
+		bytes[4 * i..4 * i + 4].copy_from_slice(&limb.to_le_bytes());
+	}
+	Target::from_le_bytes(bytes)
+}
+
+// Returns the accumulated proof of work of the given header entries.
+fn total_work(entries: &[HeaderEntry]) -> Option<Work> {
+	entries.iter().map(|entry| Target::from_compact(entry.bits).to_work()).reduce(|a, b| a + b)
+}
+
+/// A connection to a peer serving compact block filters.
+struct CbfPeer {
+	stream: TcpStream,
+	magic: Magic,
+}
+
+impl CbfPeer {
+	async fn connect(address: &str, network: Network, start_height: u32) -> std::io::Result<Self> {
+		let stream = tokio::time::timeout(PEER_TIMEOUT, TcpStream::connect(address))
+			.await
+			.map_err(|_| timed_out())??;
+		let peer_addr = stream.peer_addr()?;
+		let mut peer = Self { stream, magic: network.magic() };
+		peer.handshake(peer_addr, start_height).await?;
+		Ok(peer)
+	}
+
+	async fn handshake(&mut self, peer_addr: SocketAddr, start_height: u32) -> std::io::Result<()> {
+		let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+		let receiver = Address::new(&peer_addr, ServiceFlags::NONE);
+		let sender = Address::new(&SocketAddr::from(([0, 0, 0, 0], 0)), ServiceFlags::NONE);
+		let mut version_msg = VersionMessage::new(
+			ServiceFlags::NONE,
+			timestamp as i64,
+			receiver,
+			sender,
+			rand::random(),
+			USER_AGENT.to_string(),
+			start_height as i32,
+		);
+		version_msg.version = PROTOCOL_VERSION;
+		version_msg.relay = false;
+		self.send(NetworkMessage::Version(version_msg)).await?;
+
+		let mut got_version = false;
+		let mut got_verack = false;
+		while !(got_version && got_verack) {
+			match self.recv().await? {
+				NetworkMessage::Version(version_msg) => {
+					if !version_msg.services.has(ServiceFlags::COMPACT_FILTERS) {
+						return Err(std::io::Error::new(
+							std::io::ErrorKind::Unsupported,
+							"Peer doesn't serve compact block filters",
+						));
+					}
+					self.send(NetworkMessage::Verack).await?;
+					got_version = true;
+				},
+				NetworkMessage::Verack => got_verack = true,
+				_ => {},
+			}
+		}
+		Ok(())
+	}
+
+	async fn get_headers(&mut self, locator: Vec<BlockHash>) -> std::io::Result<Vec<Header>> {
+		let msg = GetHeadersMessage::new(locator, BlockHash::all_zeros());
+		self.send(NetworkMessage::GetHeaders(msg)).await?;
+		loop {
+			if let NetworkMessage::Headers(headers) = self.recv().await? {
+				return Ok(headers);
+			}
+		}
+	}
+
+	async fn get_cfheaders(
+		&mut self, start_height: u32, stop_hash: BlockHash,
+	) -> std::io::Result<CFHeaders> {
+		let msg = GetCFHeaders { filter_type: BASIC_FILTER_TYPE, start_height, stop_hash };
+		self.send(NetworkMessage::GetCFHeaders(msg)).await?;
+		loop {
+			if let NetworkMessage::CFHeaders(cfheaders) = self.recv().await? {
+				if cfheaders.stop_hash == stop_hash {
+					return Ok(cfheaders);
+				}
+			}
+		}
+	}
+
+	async fn get_cfilters(
+		&mut self, start_height: u32, stop_hash: BlockHash, num_filters: usize,
+	) -> std::io::Result<Vec<CFilter>> {
+		let msg = GetCFilters { filter_type: BASIC_FILTER_TYPE, start_height, stop_hash };
+		self.send(NetworkMessage::GetCFilters(msg)).await?;
+		let mut cfilters = Vec::with_capacity(num_filters);
+		while cfilters.len() < num_filters {
+			if let NetworkMessage::CFilter(cfilter) = self.recv().await? {
+				cfilters.push(cfilter);
+			}
+		}
+		Ok(cfilters)
+	}
+
+	async fn get_block(&mut self, block_hash: BlockHash) -> std::io::Result<Block> {
+		self.send(NetworkMessage::GetData(vec![Inventory::WitnessBlock(block_hash)])).await?;
+		loop {
+			match self.recv().await? {
+				NetworkMessage::Block(block) if block.block_hash() == block_hash => {
+					return Ok(block);
+				},
+				NetworkMessage::NotFound(_) => {
+					return Err(std::io::Error::new(
+						std::io::ErrorKind::NotFound,
+						"Peer doesn't have the requested block",
+					));
+				},
+				_ => {},
+			}
+		}
+	}
+
+	async fn send(&mut self, payload: NetworkMessage) -> std::io::Result<()> {
+		let msg = RawNetworkMessage { magic: self.magic, payload };
+		let msg_bytes = encode::serialize(&msg);
+		tokio::time::timeout(PEER_TIMEOUT, self.stream.write_all(&msg_bytes))
+			.await
+			.map_err(|_| timed_out())?
+	}
+
+	// Receives the next message from our peer, answering any pings in the meantime.
+	async fn recv(&mut self) -> std::io::Result<NetworkMessage> {
+		loop {
+			let msg = tokio::time::timeout(PEER_TIMEOUT, self.read_message())
+				.await
+				.map_err(|_| timed_out())??;
+			if msg.magic != self.magic {
+				return Err(invalid_data("Peer sent a message for a different network"));
+			}
+			match msg.payload {
+				NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(nonce)).await?,
+				payload => return Ok(payload),
+			}
+		}
+	}
+
+	async fn read_message(&mut self) -> std::io::Result<RawNetworkMessage> {
+		// The message header consists of the network magic, the command, the payload length, and
+		// the payload checksum.
+		let mut msg_bytes = vec![0u8; 24];
+		self.stream.read_exact(&mut msg_bytes).await?;
+		let mut payload_len_bytes = [0u8; 4];
+		payload_len_bytes.copy_from_slice(&msg_bytes[16..20]);
+		let payload_len = u32::from_le_bytes(payload_len_bytes) as usize;
+		if payload_len > MAX_MSG_PAYLOAD_SIZE {
+			return Err(invalid_data("Peer sent an oversized message"));
+		}
+
+		msg_bytes.resize(24 + payload_len, 0);
+		self.stream.read_exact(&mut msg_bytes[24..]).await?;
+		encode::deserialize(&msg_bytes)
+			.map_err(|e| invalid_data(&format!("Failed to decode message: {}", e)))
+	}
+}
+
+fn invalid_data(msg: &str) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn timed_out() -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::TimedOut, "Peer didn't respond in time")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use bitcoin::blockdata::block::Version;
+
+	// Mines a regtest header on top of the given one.
+	fn mine_header(prev_header: &Header, time: u32) -> Header {
+		let mut header = Header {
+			version: Version::from_consensus(0x20000000),
+			prev_blockhash: prev_header.block_hash(),
+			merkle_root: prev_header.merkle_root,
+			time,
+			bits: CompactTarget::from_consensus(0x207fffff),
+			nonce: 0,
+		};
+		while header.validate_pow(header.target()).is_err() {
+			header.nonce += 1;
+		}
+		header
+	}
+
+	#[test]
+	fn header_chain_follows_forks_with_more_work_only() {
+		let mut header_chain = HeaderChain::new(Network::Regtest);
+		let genesis_header = header_chain.tip_header;
+
+		let header_a1 = mine_header(&genesis_header, 1);
+		let header_a2 = mine_header(&header_a1, 2);
+		assert_eq!(header_chain.connect_headers(&[header_a1, header_a2]).unwrap(), None);
+		assert_eq!(header_chain.tip_height(), 2);
+		assert_eq!(header_chain.locator().last(), Some(&genesis_header.block_hash()));
+
+		// A fork with less work is rejected.
+		let header_b1 = mine_header(&genesis_header, 3);
+		assert!(header_chain.connect_headers(&[header_b1]).is_err());
+		assert_eq!(header_chain.hash_at(1), Some(header_a1.block_hash()));
+
+		// So is a fork with the same amount of work, as we stick to the chain we saw first.
+		let header_b2 = mine_header(&header_b1, 4);
+		assert!(header_chain.connect_headers(&[header_b1, header_b2]).is_err());
+		assert_eq!(header_chain.hash_at(2), Some(header_a2.block_hash()));
+
+		// A fork with more work reorganizes the chain.
+		let header_b3 = mine_header(&header_b2, 5);
+		assert_eq!(
+			header_chain.connect_headers(&[header_b1, header_b2, header_b3]).unwrap(),
+			Some(1)
+		);
+		assert_eq!(header_chain.tip_height(), 3);
+		assert_eq!(header_chain.hash_at(1), Some(header_b1.block_hash()));
+		assert_eq!(header_chain.tip_header, header_b3);
+
+		// Headers not connecting to our chain are rejected.
+		let header_c = mine_header(&header_a1, 6);
+		assert!(header_chain.connect_headers(&[header_c]).is_err());
+	}
+
+	#[test]
+	fn header_chain_rejects_invalid_proof_of_work() {
+		let mut header_chain = HeaderChain::new(Network::Regtest);
+		let genesis_header = header_chain.tip_header;
+
+		// A header whose hash doesn't meet its target is rejected.
+		let mut header = mine_header(&genesis_header, 1);
+		while header.validate_pow(header.target()).is_ok() {
+			header.nonce += 1;
+		}
+		assert!(header_chain.connect_headers(&[header]).is_err());
+
+		// So is a header committing to an easier target than required, even if it meets it.
+		let mut header = mine_header(&genesis_header, 1);
+		header.bits = CompactTarget::from_consensus(0x2100ffff);
+		assert!(header.validate_pow(header.target()).is_ok());
+		assert!(header_chain.connect_headers(&[header]).is_err());
+
+		// And one committing to a harder target than required.
+		let mut header = mine_header(&genesis_header, 1);
+		header.bits = CompactTarget::from_consensus(0x203fffff);
+		while header.validate_pow(header.target()).is_err() {
+			header.nonce += 1;
+		}
+		assert!(header_chain.connect_headers(&[header]).is_err());
+
+		assert_eq!(header_chain.tip_height(), 0);
+		let header = mine_header(&genesis_header, 1);
+		assert_eq!(header_chain.connect_headers(&[header]).unwrap(), None);
+		assert_eq!(header_chain.tip_height(), 1);
+	}
+
+	#[test]
+	fn required_bits_follow_difficulty_adjustments() {
+		let pow_limit_bits = CompactTarget::from_consensus(0x1d00ffff);
+		let entry = |bits, time| HeaderEntry { block_hash: BlockHash::all_zeros(), bits, time };
+
+		// Between adjustments, the difficulty stays the same.
+		let entries = |_: usize| entry(pow_limit_bits, 0);
+		assert_eq!(required_bits(Network::Bitcoin, 2017, 0, entries), pow_limit_bits);
+
+		// If blocks were found twice as fast as expected, the difficulty doubles.
+		let entries =
+			|h: usize| entry(pow_limit_bits, if h == 0 { 0 } else { POW_TARGET_TIMESPAN / 2 });
+		assert_eq!(
+			required_bits(Network::Bitcoin, 2016, 0, entries),
+			CompactTarget::from_consensus(0x1c7fff80)
+		);
+
+		// The adjustment is bounded to a factor of four.
+		let entries =
+			|h: usize| entry(pow_limit_bits, if h == 0 { 0 } else { POW_TARGET_TIMESPAN / 8 });
+		assert_eq!(
+			required_bits(Network::Bitcoin, 2016, 0, entries),
+			CompactTarget::from_consensus(0x1c3fffc0)
+		);
+
+		// And never results in a target easier than the proof of work limit.
+		let entries =
+			|h: usize| entry(pow_limit_bits, if h == 0 { 0 } else { POW_TARGET_TIMESPAN * 2 });
+		assert_eq!(required_bits(Network::Bitcoin, 2016, 0, entries), pow_limit_bits);
+
+		// On testnet, minimum difficulty blocks are allowed if no block was found for a while,
+		// but don't change the difficulty of the following regular blocks.
+		let regular_bits = CompactTarget::from_consensus(0x1c7fff80);
+		let entries =
+			|h: usize| if h == 2 { entry(pow_limit_bits, 1000) } else { entry(regular_bits, 0) };
+		assert_eq!(required_bits(Network::Testnet, 3, 1000 + 1201, entries), pow_limit_bits);
+		assert_eq!(required_bits(Network::Testnet, 3, 1000 + 1200, entries), regular_bits);
+	}
+}
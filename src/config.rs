@@ -1,8 +1,12 @@
+use std::net::SocketAddr;
 use std::time::Duration;
 
+use lightning::ln::channelmanager::MIN_FINAL_CLTV_EXPIRY_DELTA;
 use lightning::ln::msgs::SocketAddress;
 use lightning::util::logger::Level as LogLevel;
 
+use crate::logger::LogFormat;
+
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::Network;
 
@@ -15,6 +19,18 @@ const DEFAULT_LDK_WALLET_SYNC_INTERVAL_SECS: u64 = 30;
 const DEFAULT_FEE_RATE_CACHE_UPDATE_INTERVAL_SECS: u64 = 60 * 10;
 const DEFAULT_PROBING_LIQUIDITY_LIMIT_MULTIPLIER: u64 = 3;
 const DEFAULT_LOG_LEVEL: LogLevel = LogLevel::Debug;
+const DEFAULT_NODE_ANN_BCAST_INTERVAL_SECS: u64 = 60 * 60;
+const DEFAULT_PERSISTENT_PEER_RECONNECTION_INITIAL_DELAY_SECS: u64 = 1;
+const DEFAULT_PERSISTENT_PEER_RECONNECTION_MAX_DELAY_SECS: u64 = 60 * 5;
+const DEFAULT_ANCHOR_PER_CHANNEL_RESERVE_SATS: u64 = 25_000;
+const DEFAULT_ONCHAIN_RECEIVE_MIN_CONFIRMATIONS: u32 = 1;
+const DEFAULT_INVOICE_EXPIRY_SECS: u32 = 60 * 60;
+const DEFAULT_LOG_ROTATION_MAX_FILE_SIZE_BYTES: u64 = 10 * 1024 * 1024;
+const DEFAULT_LOG_ROTATION_MAX_FILE_AGE_SECS: u64 = 60 * 60 * 24;
+const DEFAULT_LOG_ROTATION_MAX_RETAINED_FILES: u32 = 5;
+const DEFAULT_PAYMENT_RETRY_MAX_ATTEMPTS: u32 = 1;
+const DEFAULT_PAYMENT_RETRY_MAX_TOTAL_TIME_SECS: u64 = 60;
+const DEFAULT_PAYMENT_RETRY_BACKOFF_SECS: u64 = 5;
 
 // The 'stop gap' parameter used by BDK's wallet sync. This seems to configure the threshold
 // number of derivation indexes after which BDK stops looking for new scripts belonging to the wallet.
@@ -23,20 +39,39 @@ pub(crate) const BDK_CLIENT_STOP_GAP: usize = 20;
 // The number of concurrent requests made against the API provider.
 pub(crate) const BDK_CLIENT_CONCURRENCY: u8 = 4;
 
+// The upper limit which we apply to the configured number of concurrent Esplora requests.
+pub(crate) const BDK_CLIENT_CONCURRENCY_MAXIMUM: u8 = 32;
+
 // The default Esplora server we're using.
 pub(crate) const DEFAULT_ESPLORA_SERVER_URL: &str = "https://blockstream.info/api";
 
 // The timeout after which we abandon retrying failed payments.
 pub(crate) const LDK_PAYMENT_RETRY_TIMEOUT: Duration = Duration::from_secs(10);
 
+// The lowest type number allowed for custom TLV records, as lower ones are reserved for the BOLTs.
+pub(crate) const CUSTOM_TLV_TYPE_NUM_MIN: u64 = 1 << 16;
+
+// The lowest TLV type allowed for custom onion messages, as lower ones are reserved for the BOLTs.
+pub(crate) const CUSTOM_ONION_MESSAGE_TLV_TYPE_MIN: u64 = 1 << 16;
+
+// The highest TLV type allowed for custom onion messages by default.
+pub(crate) const DEFAULT_CUSTOM_ONION_MESSAGE_TLV_TYPE_MAX: u64 = u64::MAX;
+
+// The time in-between checks for changes in our peer connections.
+pub(crate) const PEER_MONITOR_INTERVAL: Duration = Duration::from_secs(1);
+
+// The time in-between checks whether we need to reconnect to any persistent peers.
+pub(crate) const PERSISTENT_PEER_RECONNECTION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 // The time in-between peer reconnection attempts.
 pub(crate) const PEER_RECONNECTION_INTERVAL: Duration = Duration::from_secs(10);
 
 // The time in-between RGS sync attempts.
 pub(crate) const RGS_SYNC_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
-// The time in-between node announcement broadcast attempts.
-pub(crate) const NODE_ANN_BCAST_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// The lowest node announcement broadcast interval we consider safe. Peers might rate-limit our
+// gossip if we broadcast announcements more often than this.
+pub(crate) const NODE_ANN_BCAST_INTERVAL_SAFE_MINIMUM_SECS: u64 = 60 * 10;
 
 // The lower limit which we apply to any configured wallet sync intervals.
 pub(crate) const WALLET_SYNC_INTERVAL_MINIMUM_SECS: u64 = 10;
@@ -44,6 +79,40 @@ pub(crate) const WALLET_SYNC_INTERVAL_MINIMUM_SECS: u64 = 10;
 // The length in bytes of our wallets' keys seed.
 pub(crate) const WALLET_KEYS_SEED_LEN: usize = 64;
 
+// The minimum channel reserve we expect the counterparty to require us to keep, in satoshis.
+pub(crate) const MIN_CHANNEL_RESERVE_SATS: u64 = 1000;
+
+// The maximum number of HTLCs a channel counterparty may offer us as per BOLT 2.
+pub(crate) const MAX_ACCEPTED_HTLCS: u16 = 483;
+
+// The maximum channel confirmation target we allow, matching the maximum funding depth LDK
+// accepts counterparties to require by default.
+pub(crate) const MAX_CHANNEL_CONFIRMATION_TARGET: u32 = 144;
+
+// The maximum number of hops we allow to precede us in the blinded paths of our offers.
+pub(crate) const MAX_BLINDED_PATH_HOPS: u8 = 3;
+
+// The time in-between checks whether any held payments are about to reach their claim deadline.
+pub(crate) const HELD_PAYMENT_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// The number of blocks before their claim deadline at which we fail back held payments, leaving
+// some leeway so that we never get close to having the HTLCs force-closed on-chain.
+pub(crate) const HELD_PAYMENT_AUTO_FAIL_SAFETY_MARGIN_BLOCKS: u32 = 3;
+
+// The time after their invoice expired at which we stop tracking held payments that were failed
+// or never paid. LDK checks invoice expiry against block header timestamps, which may lag behind.
+pub(crate) const HELD_PAYMENT_PRUNE_DELAY_SECS: u64 = 2 * 60 * 60;
+
+// The number of blocks we may lag behind the chain tip before health checks warn or fail.
+pub(crate) const HEALTH_CHECK_CHAIN_LAG_WARN_BLOCKS: u32 = 2;
+pub(crate) const HEALTH_CHECK_CHAIN_LAG_FAIL_BLOCKS: u32 = 6;
+
+// The number of update intervals after which health checks consider data stale.
+pub(crate) const HEALTH_CHECK_STALENESS_MULTIPLIER: u64 = 3;
+
+// The time in-between checks whether any of our unpaid invoices expired.
+pub(crate) const INVOICE_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
 #[derive(Debug, Clone)]
 /// Represents the configuration of an [`Node`] instance.
 ///
@@ -55,13 +124,36 @@ pub(crate) const WALLET_KEYS_SEED_LEN: usize = 64;
 /// | `log_dir_path`                         | None               |
 /// | `network`                              | Bitcoin            |
 /// | `listening_addresses`                  | None               |
+/// | `announced_addresses`                  | None               |
 /// | `default_cltv_expiry_delta`            | 144                |
 /// | `onchain_wallet_sync_interval_secs`    | 80                 |
 /// | `wallet_sync_interval_secs`            | 30                 |
 /// | `fee_rate_cache_update_interval_secs`  | 600                |
+/// | `node_announcement_interval_secs`      | 3600               |
 /// | `trusted_peers_0conf`                  | []                 |
 /// | `probing_liquidity_limit_multiplier`   | 3                  |
 /// | `log_level`                            | Debug              |
+/// | `log_format`                           | Text               |
+/// | `log_rotation_config`                  | None               |
+/// | `anchor_channels_config`               | None               |
+/// | `strict_forwarding_fee_policy`         | false              |
+/// | `tor_proxy_address`                    | None               |
+/// | `emit_peer_connection_events`          | false              |
+/// | `persistent_peer_reconnection_initial_delay_secs` | 1       |
+/// | `persistent_peer_reconnection_max_delay_secs`     | 300     |
+/// | `emit_onchain_payment_events`          | false              |
+/// | `onchain_receive_min_confirmations`    | 1                  |
+/// | `default_invoice_expiry_secs`          | 3600               |
+/// | `max_event_redeliveries`               | None               |
+/// | `channel_confirmation_target`          | None               |
+/// | `min_final_cltv_expiry_delta`          | 24                 |
+/// | `blinded_path_config`                  | Default            |
+/// | `max_routing_fee_policy`               | None               |
+/// | `custom_onion_message_tlv_type_min`    | 65536              |
+/// | `custom_onion_message_tlv_type_max`    | u64::MAX           |
+///
+/// See [`AnchorChannelsConfig`] and [`BlindedPathConfig`] for more information on their
+/// respective default values.
 ///
 /// [`Node`]: crate::Node
 pub struct Config {
@@ -75,6 +167,11 @@ pub struct Config {
 	pub network: Network,
 	/// The addresses on which the node will listen for incoming connections.
 	pub listening_addresses: Option<Vec<SocketAddress>>,
+	/// The addresses which the node will announce to the gossip network that it accepts connections
+	/// on.
+	///
+	/// If set to `None`, the [`Config::listening_addresses`] will be announced instead.
+	pub announced_addresses: Option<Vec<SocketAddress>>,
 	/// The default CLTV expiry delta to be used for payments.
 	pub default_cltv_expiry_delta: u32,
 	/// The time in-between background sync attempts of the onchain wallet, in seconds.
@@ -89,11 +186,20 @@ pub struct Config {
 	///
 	/// **Note:** A minimum of 10 seconds is always enforced.
 	pub fee_rate_cache_update_interval_secs: u64,
+	/// The time in-between broadcasts of our node announcement, in seconds.
+	///
+	/// **Note:** Setting this below 600 seconds risks our announcements being rate-limited by
+	/// our peers.
+	pub node_announcement_interval_secs: u64,
 	/// A list of peers that we allow to establish zero confirmation channels to us.
 	///
+	/// Unlike this list, peers set via [`Builder::set_trusted_peers_0conf`] are persisted.
+	///
 	/// **Note:** Allowing payments via zero-confirmation channels is potentially insecure if the
 	/// funding transaction ends up never being confirmed on-chain. Zero-confirmation channels
 	/// should therefore only be accepted from trusted peers.
+	///
+	/// [`Builder::set_trusted_peers_0conf`]: crate::Builder::set_trusted_peers_0conf
 	pub trusted_peers_0conf: Vec<PublicKey>,
 	/// The liquidity factor by which we filter the outgoing channels used for sending probes.
 	///
@@ -104,6 +210,157 @@ pub struct Config {
 	///
 	/// Any messages below this level will be excluded from the logs.
 	pub log_level: LogLevel,
+	/// The format in which we write log lines.
+	pub log_format: LogFormat,
+	/// Configuration options pertaining to the rotation of log files.
+	///
+	/// If set to `None`, log files won't be rotated. Please refer to [`LogRotationConfig`] for
+	/// further information.
+	pub log_rotation_config: Option<LogRotationConfig>,
+	/// Configuration options pertaining to Anchor channels, i.e., channels for which the
+	/// `option_anchors_zero_fee_htlc_tx` channel type is negotiated.
+	///
+	/// Please refer to [`AnchorChannelsConfig`] for further information on Anchor channels.
+	///
+	/// If set to `Some`, we'll try to open new channels with Anchors enabled, i.e., new channels
+	/// will be negotiated with the `option_anchors_zero_fee_htlc_tx` channel type if supported by
+	/// the counterparty. Note that this won't prevent us from opening non-Anchor channels if the
+	/// counterparty doesn't support `option_anchors_zero_fee_htlc_tx`. If set to `None`, new
+	/// channels will be negotiated with the legacy `option_static_remotekey` channel type only.
+	///
+	/// **Note:** If set to `None` *after* some Anchor channels have already been
+	/// opened, no dedicated emergency on-chain reserve will be maintained for these channels,
+	/// which can be dangerous if only insufficient funds are available at the time of channel
+	/// closure. We *will* however still try to get the Anchor spending transactions confirmed
+	/// on-chain with the funds available.
+	pub anchor_channels_config: Option<AnchorChannelsConfig>,
+	/// Whether channel config updates setting both the base and the proportional forwarding fee
+	/// to zero should be rejected.
+	///
+	/// This guards routing node operators against accidentally forwarding payments for free.
+	pub strict_forwarding_fee_policy: bool,
+	/// The address of a SOCKS5 proxy, e.g., a local Tor daemon, used to reach peers that are only
+	/// available via the Tor network.
+	///
+	/// If set, connections to `.onion` peer addresses will be routed through this proxy. If set to
+	/// `None`, connecting to such addresses will fail with [`Error::TorProxyNotConfigured`].
+	///
+	/// [`Error::TorProxyNotConfigured`]: crate::Error::TorProxyNotConfigured
+	pub tor_proxy_address: Option<SocketAddr>,
+	/// Whether [`Event::PeerConnected`] and [`Event::PeerDisconnected`] events should be emitted.
+	///
+	/// [`Event::PeerConnected`]: crate::Event::PeerConnected
+	/// [`Event::PeerDisconnected`]: crate::Event::PeerDisconnected
+	pub emit_peer_connection_events: bool,
+	/// The time we wait before the first attempt to reconnect to a persistent peer after it
+	/// disconnected, in seconds.
+	///
+	/// The delay is doubled after every failed attempt, up to
+	/// [`Config::persistent_peer_reconnection_max_delay_secs`].
+	///
+	/// See [`Node::add_persistent_peer`] for more information on persistent peers.
+	///
+	/// [`Node::add_persistent_peer`]: crate::Node::add_persistent_peer
+	pub persistent_peer_reconnection_initial_delay_secs: u64,
+	/// The maximum time we wait in-between attempts to reconnect to a persistent peer, in seconds.
+	pub persistent_peer_reconnection_max_delay_secs: u64,
+	/// Whether [`Event::OnchainPaymentReceived`] and [`Event::OnchainPaymentSent`] events should be
+	/// emitted.
+	///
+	/// [`Event::OnchainPaymentReceived`]: crate::Event::OnchainPaymentReceived
+	/// [`Event::OnchainPaymentSent`]: crate::Event::OnchainPaymentSent
+	pub emit_onchain_payment_events: bool,
+	/// The number of confirmations an on-chain payment to our wallet needs to reach before we emit
+	/// an [`Event::OnchainPaymentReceived`] for it.
+	///
+	/// If set to `0`, the event is emitted as soon as the unconfirmed transaction is observed.
+	///
+	/// [`Event::OnchainPaymentReceived`]: crate::Event::OnchainPaymentReceived
+	pub onchain_receive_min_confirmations: u32,
+	/// The expiry time used for invoices we create, in seconds, if the caller passes an
+	/// `expiry_secs` of `0` to any of the `receive_*` methods.
+	///
+	/// Unpaid invoices are marked as [`PaymentStatus::Expired`] once their expiry time has passed.
+	///
+	/// [`PaymentStatus::Expired`]: crate::payment_store::PaymentStatus::Expired
+	pub default_invoice_expiry_secs: u32,
+	/// The number of times an event may be redelivered via [`Node::next_event`],
+	/// [`Node::wait_next_event`], [`Node::next_event_async`], [`Node::next_event_matching`],
+	/// [`Node::wait_next_event_matching`], or `Node::event_stream` without being marked
+	/// handled. A delivery is only counted once the event is actually handed to the caller.
+	/// Deliveries are counted across restarts.
+	///
+	/// Once exceeded, the event is moved to a dead-letter store, from where it can be inspected
+	/// via [`Node::list_dead_letter_events`] and re-queued via [`Node::replay_dead_letter_event`],
+	/// and the event queue advances to the next event. This prevents a single event that
+	/// repeatedly fails to be handled from stalling the event queue forever.
+	///
+	/// If set to `None`, events are redelivered until they are marked handled.
+	///
+	/// [`Node::next_event`]: crate::Node::next_event
+	/// [`Node::wait_next_event`]: crate::Node::wait_next_event
+	/// [`Node::next_event_async`]: crate::Node::next_event_async
+	/// [`Node::next_event_matching`]: crate::Node::next_event_matching
+	/// [`Node::wait_next_event_matching`]: crate::Node::wait_next_event_matching
+	/// [`Node::list_dead_letter_events`]: crate::Node::list_dead_letter_events
+	/// [`Node::replay_dead_letter_event`]: crate::Node::replay_dead_letter_event
+	pub max_event_redeliveries: Option<u32>,
+	/// The number of confirmations the funding transaction of a channel needs to reach before
+	/// the channel is considered ready and an [`Event::ChannelReady`] is emitted.
+	///
+	/// For inbound channels, this is the depth we require the funding transaction to reach. For
+	/// outbound channels, the depth is chosen by the counterparty, but if this is set we won't
+	/// use a channel before its funding transaction confirmed, even if the counterparty would
+	/// allow it. Zero-confirmation channels accepted from [`Config::trusted_peers_0conf`] are
+	/// ready immediately regardless.
+	///
+	/// Must be between 1 and 144. If set to `None`, LDK's default of 6 confirmations is used.
+	///
+	/// [`Event::ChannelReady`]: crate::Event::ChannelReady
+	pub channel_confirmation_target: Option<u32>,
+	/// The minimum number of blocks left until their HTLCs expire that payments to our invoices
+	/// need to arrive with, as encoded in the invoices we create.
+	///
+	/// A higher delta gives us more time to claim payments on-chain should a channel be
+	/// force-closed while they are pending, at the cost of locking up the payer's funds for longer
+	/// if a payment fails and making it harder for payers to find a route within their total CLTV
+	/// limit. Must not be below LDK's minimum of 24 blocks.
+	pub min_final_cltv_expiry_delta: u16,
+	/// Configuration options pertaining to the blinded paths included in our [BOLT 12] offers
+	/// and invoices.
+	///
+	/// Please refer to [`BlindedPathConfig`] for further information.
+	///
+	/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+	pub blinded_path_config: BlindedPathConfig,
+	/// The maximum routing fees we're willing to pay for outbound payments.
+	///
+	/// If set, the policy replaces LDK's default fee limit for every outbound payment, including
+	/// the payments of refunds created via [`Node::initiate_refund`], unless a limit is given for
+	/// the individual payment, e.g., via [`SendingParameters::max_total_routing_fee_msat`] or to
+	/// [`Node::rebalance_channels`]. Payments that can only be routed by exceeding the limit fail
+	/// with [`Error::RoutingFeeLimitExceeded`], while refund payments, which are only sent once
+	/// the refund's recipient provided an invoice, fail with an [`Event::PaymentFailed`].
+	///
+	/// [`Node::initiate_refund`]: crate::Node::initiate_refund
+	/// [`SendingParameters::max_total_routing_fee_msat`]: crate::SendingParameters::max_total_routing_fee_msat
+	/// [`Node::rebalance_channels`]: crate::Node::rebalance_channels
+	/// [`Error::RoutingFeeLimitExceeded`]: crate::Error::RoutingFeeLimitExceeded
+	/// [`Event::PaymentFailed`]: crate::Event::PaymentFailed
+	pub max_routing_fee_policy: Option<MaxFeePolicy>,
+	/// The lowest TLV type of the custom onion messages we send and receive.
+	///
+	/// Custom onion messages of types outside of the range given by this and
+	/// [`Config::custom_onion_message_tlv_type_max`] are neither sent nor emitted as an
+	/// [`Event::OnionMessageReceived`]. Must be at least `2^16`, as lower types are reserved for
+	/// onion messages defined by the Lightning specification, e.g., the ones used by BOLT 12.
+	///
+	/// [`Event::OnionMessageReceived`]: crate::Event::OnionMessageReceived
+	pub custom_onion_message_tlv_type_min: u64,
+	/// The highest TLV type of the custom onion messages we send and receive.
+	///
+	/// Must not be below [`Config::custom_onion_message_tlv_type_min`].
+	pub custom_onion_message_tlv_type_max: u64,
 }
 
 impl Default for Config {
@@ -113,17 +370,356 @@ impl Default for Config {
 			log_dir_path: None,
 			network: DEFAULT_NETWORK,
 			listening_addresses: None,
+			announced_addresses: None,
 			default_cltv_expiry_delta: DEFAULT_CLTV_EXPIRY_DELTA,
 			onchain_wallet_sync_interval_secs: DEFAULT_BDK_WALLET_SYNC_INTERVAL_SECS,
 			wallet_sync_interval_secs: DEFAULT_LDK_WALLET_SYNC_INTERVAL_SECS,
 			fee_rate_cache_update_interval_secs: DEFAULT_FEE_RATE_CACHE_UPDATE_INTERVAL_SECS,
+			node_announcement_interval_secs: DEFAULT_NODE_ANN_BCAST_INTERVAL_SECS,
 			trusted_peers_0conf: Vec::new(),
 			probing_liquidity_limit_multiplier: DEFAULT_PROBING_LIQUIDITY_LIMIT_MULTIPLIER,
 			log_level: DEFAULT_LOG_LEVEL,
+			log_format: LogFormat::Text,
+			log_rotation_config: None,
+			anchor_channels_config: None,
+			strict_forwarding_fee_policy: false,
+			tor_proxy_address: None,
+			emit_peer_connection_events: false,
+			persistent_peer_reconnection_initial_delay_secs:
+				DEFAULT_PERSISTENT_PEER_RECONNECTION_INITIAL_DELAY_SECS,
+			persistent_peer_reconnection_max_delay_secs:
+				DEFAULT_PERSISTENT_PEER_RECONNECTION_MAX_DELAY_SECS,
+			emit_onchain_payment_events: false,
+			onchain_receive_min_confirmations: DEFAULT_ONCHAIN_RECEIVE_MIN_CONFIRMATIONS,
+			default_invoice_expiry_secs: DEFAULT_INVOICE_EXPIRY_SECS,
+			max_event_redeliveries: None,
+			channel_confirmation_target: None,
+			min_final_cltv_expiry_delta: MIN_FINAL_CLTV_EXPIRY_DELTA,
+			blinded_path_config: BlindedPathConfig::default(),
+			max_routing_fee_policy: None,
+			custom_onion_message_tlv_type_min: CUSTOM_ONION_MESSAGE_TLV_TYPE_MIN,
+			custom_onion_message_tlv_type_max: DEFAULT_CUSTOM_ONION_MESSAGE_TLV_TYPE_MAX,
+		}
+	}
+}
+
+/// Configuration options pertaining to 'Anchor' channels, i.e., channels for which the
+/// `option_anchors_zero_fee_htlc_tx` channel type is negotiated.
+///
+/// Prior to the introduction of Anchor channels, the on-chain fees paying for the transactions
+/// issued on channel closure were pre-determined and locked-in at the time of the channel
+/// opening. This required to estimate what fee rate would be sufficient to still have the
+/// closing transactions be spendable on-chain (i.e., not be considered dust). This legacy
+/// design of pre-anchor channels proved inadequate in the unpredictable, often turbulent, fee
+/// markets we experience today.
+///
+/// In contrast, Anchor channels allow to determine an adequate fee rate *at the time of channel
+/// closure*, making them much more robust in the face of fee spikes. In turn, they require to
+/// maintain a reserve of on-chain funds to have the channel closure transactions confirmed
+/// on-chain, at least if the channel counterparty can't be trusted to do this for us. Once a
+/// channel is force-closed, we'll spend from this reserve to bump the fee of the commitment
+/// transaction via its anchor output (CPFP) until it confirms.
+///
+/// The reserve is kept in our on-chain wallet and is deducted from the spendable on-chain
+/// balance, i.e., it won't be spent when sending on-chain payments or opening further channels.
+/// New outbound channels will only be opened and new inbound channels will only be accepted if
+/// sufficient funds are available to cover the reserve for all existing and the new channel.
+///
+/// See [`BalanceDetails::total_anchor_channels_reserve_sats`] for the currently required
+/// reserve.
+///
+/// ### Defaults
+///
+/// | Parameter                  | Value  |
+/// |----------------------------|--------|
+/// | `trusted_peers_no_reserve` | []     |
+/// | `per_channel_reserve_sats` | 25000  |
+///
+/// [`BalanceDetails::total_anchor_channels_reserve_sats`]: crate::BalanceDetails::total_anchor_channels_reserve_sats
+#[derive(Debug, Clone)]
+pub struct AnchorChannelsConfig {
+	/// A list of peers that we trust to get the required channel closing transactions confirmed
+	/// on-chain.
+	///
+	/// Channels with these peers won't count towards the retained on-chain reserve and we won't
+	/// take any action to get the required transactions confirmed ourselves.
+	///
+	/// **Note:** Trusting the channel counterparty to take the necessary actions to get the
+	/// required Anchor spending and HTLC transactions confirmed on-chain is potentially
+	/// insecure as the channel may not be closed if they refuse to do so, potentially leaving
+	/// the user funds stuck.
+	pub trusted_peers_no_reserve: Vec<PublicKey>,
+	/// The amount of satoshis per anchors-negotiated channel with an untrusted peer that we keep
+	/// as an emergency reserve in our on-chain wallet.
+	///
+	/// This allows for having the required Anchor output spending and HTLC transactions confirmed
+	/// when the channel is closed.
+	///
+	/// If the channel peer is not marked as trusted via
+	/// [`AnchorChannelsConfig::trusted_peers_no_reserve`], we will always try to spend the Anchor
+	/// outputs with *any* on-chain funds available, i.e., the total reserve value as well as any
+	/// spendable funds available in the on-chain wallet. Therefore, this per-channel multiplier is
+	/// really an emergency reserve that we maintain at all times to reduce the risk of
+	/// insufficient funds at time of a channel closure. To this end, we will refuse to open
+	/// outbound or accept inbound channels if we don't have sufficient on-chain funds available to
+	/// cover the additional reserve requirement.
+	///
+	/// **Note:** Depending on the fee market at the time of closure, this reserve amount might or
+	/// might not suffice to successfully spend the Anchor output and have the HTLC transactions
+	/// confirmed on-chain, i.e., you may want to adjust this value accordingly.
+	pub per_channel_reserve_sats: u64,
+}
+
+impl Default for AnchorChannelsConfig {
+	fn default() -> Self {
+		Self {
+			trusted_peers_no_reserve: Vec::new(),
+			per_channel_reserve_sats: DEFAULT_ANCHOR_PER_CHANNEL_RESERVE_SATS,
 		}
 	}
 }
 
+/// Returns a [`AnchorChannelsConfig`] object populated with default values.
+///
+/// See the documentation of [`AnchorChannelsConfig`] for more information on the used defaults.
+///
+/// This is mostly meant for use in bindings, in Rust this is synonymous with
+/// [`AnchorChannelsConfig::default()`].
+pub fn default_anchor_channels_config() -> AnchorChannelsConfig {
+	AnchorChannelsConfig::default()
+}
+
+/// Options related to the blinded paths through which we receive [BOLT 12] payments.
+///
+/// To keep our node id private, our offers and the invoices we return for them only contain
+/// blinded paths leading to us, starting at an *introduction node*. We build these paths through
+/// our connected peers, preferring peers with at least three public channels so that we can't
+/// easily be identified as the recipient. If no suitable introduction node is available, we fall
+/// back to a direct path with ourselves as the introduction node, which doesn't hide our node
+/// id, and log a warning.
+///
+/// ### Defaults
+///
+/// | Parameter                       | Value |
+/// |---------------------------------|-------|
+/// | `num_hops`                      | 1     |
+/// | `preferred_introduction_nodes`  | []    |
+///
+/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlindedPathConfig {
+	/// The number of blinded hops preceding us in the blinded paths of our offers.
+	///
+	/// With a single hop, one of our peers is used as the introduction node. With more hops, the
+	/// paths are extended through the channel counterparties of that peer as known from the
+	/// network graph. If set to `0`, only a direct path is used, which doesn't hide our node id.
+	///
+	/// **Note:** Must not exceed `3`. The blinded payment paths of our invoices always use a single
+	/// hop through one of our channel counterparties, as multi-hop payment paths aren't supported
+	/// yet.
+	pub num_hops: u8,
+	/// A list of nodes we prefer to use as introduction nodes, most preferred first.
+	///
+	/// Paths starting at one of these nodes are used before any others. Other suitable nodes are
+	/// still used if none of the preferred nodes is available.
+	pub preferred_introduction_nodes: Vec<PublicKey>,
+}
+
+impl BlindedPathConfig {
+	pub(crate) fn is_valid(&self) -> bool {
+		self.num_hops <= MAX_BLINDED_PATH_HOPS
+	}
+}
+
+impl Default for BlindedPathConfig {
+	fn default() -> Self {
+		Self { num_hops: 1, preferred_introduction_nodes: Vec::new() }
+	}
+}
+
+/// Returns a [`BlindedPathConfig`] object populated with default values.
+///
+/// See the documentation of [`BlindedPathConfig`] for more information on the used defaults.
+///
+/// This is mostly meant for use in bindings, in Rust this is synonymous with
+/// [`BlindedPathConfig::default()`].
+pub fn default_blinded_path_config() -> BlindedPathConfig {
+	BlindedPathConfig::default()
+}
+
+/// A limit on the routing fees paid for outbound payments, given as a fixed base plus a
+/// proportion of the amount sent.
+///
+/// For example, a policy of `MaxFeePolicy { percent_millionths: 10_000, base_msat: 10_000 }`
+/// never pays more than 1% + 10 sat in routing fees.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MaxFeePolicy {
+	/// The proportion of the amount sent we're willing to pay, in millionths.
+	pub percent_millionths: u32,
+	/// The fixed amount we're willing to pay on top, in millisatoshis.
+	pub base_msat: u64,
+}
+
+impl MaxFeePolicy {
+	/// Returns the maximum total routing fee, in millisatoshis, for a payment of the given amount.
+	pub(crate) fn max_fee_msat(&self, amount_msat: u64) -> u64 {
+		let proportional_msat =
+			(amount_msat as u128 * self.percent_millionths as u128 / 1_000_000) as u64;
+		self.base_msat.saturating_add(proportional_msat)
+	}
+}
+
+/// Options related to syncing the Lightning and on-chain wallets via Esplora.
+///
+/// ### Defaults
+///
+/// | Parameter                              | Value  |
+/// |----------------------------------------|--------|
+/// | `onchain_wallet_sync_interval_secs`    | 80     |
+/// | `lightning_wallet_sync_interval_secs`  | 30     |
+/// | `fee_rate_cache_update_interval_secs`  | 600    |
+/// | `max_concurrent_requests`              | 4      |
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EsploraSyncConfig {
+	/// The time in-between background sync attempts of the onchain wallet, in seconds.
+	///
+	/// **Note:** A minimum of 10 seconds is always enforced.
+	pub onchain_wallet_sync_interval_secs: u64,
+	/// The time in-between background sync attempts of the LDK wallet, i.e., the channel
+	/// monitors, in seconds.
+	///
+	/// **Note:** A minimum of 10 seconds is always enforced.
+	pub lightning_wallet_sync_interval_secs: u64,
+	/// The time in-between background update attempts to our fee rate cache, in seconds.
+	///
+	/// **Note:** A minimum of 10 seconds is always enforced.
+	pub fee_rate_cache_update_interval_secs: u64,
+	/// The maximum number of concurrent requests made against the Esplora server during on-chain
+	/// wallet syncs.
+	///
+	/// **Note:** Values above 32 will be clamped.
+	pub max_concurrent_requests: u8,
+}
+
+impl Default for EsploraSyncConfig {
+	fn default() -> Self {
+		Self {
+			onchain_wallet_sync_interval_secs: DEFAULT_BDK_WALLET_SYNC_INTERVAL_SECS,
+			lightning_wallet_sync_interval_secs: DEFAULT_LDK_WALLET_SYNC_INTERVAL_SECS,
+			fee_rate_cache_update_interval_secs: DEFAULT_FEE_RATE_CACHE_UPDATE_INTERVAL_SECS,
+			max_concurrent_requests: BDK_CLIENT_CONCURRENCY,
+		}
+	}
+}
+
+/// Returns a [`EsploraSyncConfig`] object populated with default values.
+///
+/// See the documentation of [`EsploraSyncConfig`] for more information on the used defaults.
+///
+/// This is mostly meant for use in bindings, in Rust this is synonymous with
+/// [`EsploraSyncConfig::default()`].
+pub fn default_esplora_sync_config() -> EsploraSyncConfig {
+	EsploraSyncConfig::default()
+}
+
+/// Options related to the rotation of log files.
+///
+/// The current log file is rotated once it would exceed the configured size or once it is older
+/// than the configured age, whatever happens first. Rotated files are renamed by appending a
+/// timestamp suffix, e.g., `ldk_node_2024_01_01.log.20240101_120000`, and only the most recent
+/// rotated files are retained.
+///
+/// ### Defaults
+///
+/// | Parameter             | Value            |
+/// |-----------------------|------------------|
+/// | `max_file_size_bytes` | 10485760 (10MiB) |
+/// | `max_file_age_secs`   | 86400 (1 day)    |
+/// | `max_retained_files`  | 5                |
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRotationConfig {
+	/// The size in bytes after which the log file is rotated.
+	///
+	/// If set to `None`, log files won't be rotated based on their size.
+	pub max_file_size_bytes: Option<u64>,
+	/// The age in seconds after which the log file is rotated.
+	///
+	/// If set to `None`, log files won't be rotated based on their age.
+	pub max_file_age_secs: Option<u64>,
+	/// The number of rotated log files that are retained. Older files are deleted.
+	pub max_retained_files: u32,
+}
+
+impl Default for LogRotationConfig {
+	fn default() -> Self {
+		Self {
+			max_file_size_bytes: Some(DEFAULT_LOG_ROTATION_MAX_FILE_SIZE_BYTES),
+			max_file_age_secs: Some(DEFAULT_LOG_ROTATION_MAX_FILE_AGE_SECS),
+			max_retained_files: DEFAULT_LOG_ROTATION_MAX_RETAINED_FILES,
+		}
+	}
+}
+
+/// Returns a [`LogRotationConfig`] object populated with default values.
+///
+/// See the documentation of [`LogRotationConfig`] for more information on the used defaults.
+///
+/// This is mostly meant for use in bindings, in Rust this is synonymous with
+/// [`LogRotationConfig::default()`].
+pub fn default_log_rotation_config() -> LogRotationConfig {
+	LogRotationConfig::default()
+}
+
+/// The policy according to which failed outbound payments are retried.
+///
+/// Every attempt is a new payment to the same payment hash, which itself is retried along
+/// different paths by LDK for up to 10 seconds. If an attempt fails and the policy isn't
+/// exhausted, a new attempt is made after a backoff period. Pending retries are persisted and
+/// resumed after a restart.
+///
+/// ### Defaults
+///
+/// | Parameter               | Value |
+/// |-------------------------|-------|
+/// | `max_attempts`          | 1     |
+/// | `max_total_time_secs`   | 60    |
+/// | `backoff_secs`          | 5     |
+///
+/// That is, failed payments are not retried by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+	/// The maximum number of attempts made to send a payment, including the initial one.
+	///
+	/// **Note:** Must be at least `1`.
+	pub max_attempts: u32,
+	/// The time after the initial attempt, in seconds, after which no further attempts are made.
+	pub max_total_time_secs: u64,
+	/// The time we wait before retrying a failed payment, in seconds.
+	///
+	/// The backoff is doubled after every failed attempt.
+	pub backoff_secs: u64,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_attempts: DEFAULT_PAYMENT_RETRY_MAX_ATTEMPTS,
+			max_total_time_secs: DEFAULT_PAYMENT_RETRY_MAX_TOTAL_TIME_SECS,
+			backoff_secs: DEFAULT_PAYMENT_RETRY_BACKOFF_SECS,
+		}
+	}
+}
+
+/// Returns a [`RetryPolicy`] object populated with default values.
+///
+/// See the documentation of [`RetryPolicy`] for more information on the used defaults.
+///
+/// This is mostly meant for use in bindings, in Rust this is synonymous with
+/// [`RetryPolicy::default()`].
+pub fn default_retry_policy() -> RetryPolicy {
+	RetryPolicy::default()
+}
+
 /// Returns a [`Config`] object populated with default values.
 ///
 /// See the documentation of [`Config`] for more information on the used defaults.
@@ -69,6 +69,96 @@ pub enum Error {
 	LiquiditySourceUnavailable,
 	/// The given operation failed due to the LSP's required opening fee being too high.
 	LiquidityFeeTooHigh,
+	/// The given gossip snapshot is older than the latest applied one.
+	RgsSnapshotOutdated,
+	/// The given scorer data is invalid.
+	InvalidScorer,
+	/// The given scorer doesn't match the current network graph.
+	ScorerGraphMismatch,
+	/// An offer could not be created.
+	OfferCreationFailed,
+	/// The given offer is invalid.
+	InvalidOffer,
+	/// A refund could not be created.
+	RefundCreationFailed,
+	/// The given refund is invalid.
+	InvalidRefund,
+	/// The given custom TLVs are invalid.
+	InvalidCustomTlvs,
+	/// The given description hash is invalid.
+	InvalidDescriptionHash,
+	/// No route for the given payment could be found.
+	RouteNotFound,
+	/// No single-path route for the given payment could be found while multi-path payments were disabled.
+	SinglePathRouteNotFound,
+	/// The given JIT channel quote is unknown or invalid.
+	InvalidJitChannelQuote,
+	/// The given JIT channel quote has expired.
+	JitChannelQuoteExpired,
+	/// The channel order expired before it was satisfied.
+	OrderExpired,
+	/// A given funding UTXO is unknown, already spent, or reserved for another channel.
+	FundingUtxoUnavailable,
+	/// The given fee rate is invalid.
+	InvalidFeeRate,
+	/// The given transaction is unknown or not eligible for a fee bump.
+	TransactionNotFound,
+	/// The given transaction is already confirmed.
+	TransactionAlreadyConfirmed,
+	/// The given channel configuration is invalid.
+	InvalidChannelConfig,
+	/// The given address can only be reached via Tor, but no Tor proxy is configured.
+	TorProxyNotConfigured,
+	/// The given node alias is invalid.
+	InvalidNodeAlias,
+	/// A static channel backup could not be created.
+	ChannelBackupFailed,
+	/// Exporting a store snapshot failed.
+	SnapshotExportFailed,
+	/// A transaction was rejected by the chain source's mempool.
+	TransactionRejected,
+	/// A given UTXO is unknown to the wallet, already spent, or reserved.
+	UtxoUnavailable,
+	/// The given address label is invalid.
+	InvalidAddressLabel,
+	/// The given payment is not a pending outbound payment.
+	PaymentNotPending,
+	/// The given payment is not a held payment awaiting to be claimed.
+	PaymentNotClaimable,
+	/// The given payment URI is invalid.
+	InvalidUri,
+	/// None of the payment methods of the given URI can be used.
+	PaymentRailUnavailable,
+	/// Retrieving data from an LNURL service failed.
+	LnurlRequestFailed,
+	/// The LNURL-withdraw service refused to pay our invoice.
+	LnurlWithdrawRejected,
+	/// Some background tasks didn't stop in time during shutdown.
+	ShutdownTimedOut,
+	/// Background syncing is paused.
+	SyncPaused,
+	/// The given dead-letter event could not be found.
+	DeadLetterEventNotFound,
+	/// The given payment id is invalid.
+	InvalidPaymentId,
+	/// No payment with the given id is known.
+	PaymentNotFound,
+	/// The payment didn't reach a final status in time.
+	PaymentTimedOut,
+	/// Receiving phantom payments was not enabled.
+	PhantomPaymentsNotEnabled,
+	/// The given phantom route hints are invalid.
+	InvalidPhantomRouteHints,
+	/// The given CLTV expiry delta is invalid.
+	InvalidCltvExpiryDelta,
+	/// A route could only be found by exceeding the configured maximum routing fee.
+	RoutingFeeLimitExceeded,
+	/// The requested first-hop channel can't be used to send the payment.
+	FirstHopChannelUnusable,
+	/// The given onion message type is not in the range allowed for custom messages.
+	InvalidOnionMessageType,
+	/// The given blinded path is invalid.
+	InvalidBlindedPath,
 }
 
 impl fmt::Display for Error {
@@ -119,6 +209,87 @@ impl fmt::Display for Error {
 			Self::LiquidityFeeTooHigh => {
 				write!(f, "The given operation failed due to the LSP's required opening fee being too high.")
 			},
+			Self::RgsSnapshotOutdated => {
+				write!(f, "The given gossip snapshot is older than the latest applied one.")
+			},
+			Self::InvalidScorer => write!(f, "The given scorer data is invalid."),
+			Self::ScorerGraphMismatch => {
+				write!(f, "The given scorer doesn't match the current network graph.")
+			},
+			Self::OfferCreationFailed => write!(f, "Failed to create offer."),
+			Self::InvalidOffer => write!(f, "The given offer is invalid."),
+			Self::RefundCreationFailed => write!(f, "Failed to create refund."),
+			Self::InvalidRefund => write!(f, "The given refund is invalid."),
+			Self::InvalidCustomTlvs => write!(f, "The given custom TLVs are invalid."),
+			Self::InvalidDescriptionHash => write!(f, "The given description hash is invalid."),
+			Self::RouteNotFound => write!(f, "Failed to find a route for the given payment."),
+			Self::SinglePathRouteNotFound => {
+				write!(f, "Failed to find a single-path route for the given payment.")
+			},
+			Self::InvalidJitChannelQuote => {
+				write!(f, "The given JIT channel quote is unknown or invalid.")
+			},
+			Self::JitChannelQuoteExpired => write!(f, "The given JIT channel quote has expired."),
+			Self::OrderExpired => write!(f, "The channel order expired before it was satisfied."),
+			Self::FundingUtxoUnavailable => {
+				write!(f, "A given funding UTXO is unknown, already spent, or reserved for another channel.")
+			},
+			Self::InvalidFeeRate => write!(f, "The given fee rate is invalid."),
+			Self::TransactionNotFound => {
+				write!(f, "The given transaction is unknown or not eligible for a fee bump.")
+			},
+			Self::TransactionAlreadyConfirmed => {
+				write!(f, "The given transaction is already confirmed.")
+			},
+			Self::InvalidChannelConfig => write!(f, "The given channel configuration is invalid."),
+			Self::TorProxyNotConfigured => {
+				write!(f, "Connecting to the given address requires a Tor proxy to be configured.")
+			},
+			Self::InvalidNodeAlias => write!(f, "The given node alias is invalid."),
+			Self::ChannelBackupFailed => write!(f, "Failed to create a static channel backup."),
+			Self::SnapshotExportFailed => write!(f, "Failed to export store snapshot."),
+			Self::TransactionRejected => write!(f, "The transaction was rejected by the mempool."),
+			Self::UtxoUnavailable => write!(f, "The given UTXO is unavailable."),
+			Self::InvalidAddressLabel => write!(f, "The given address label is invalid."),
+			Self::PaymentNotPending => {
+				write!(f, "The given payment is not a pending outbound payment.")
+			},
+			Self::PaymentNotClaimable => write!(f, "The given payment is not claimable."),
+			Self::InvalidUri => write!(f, "The given payment URI is invalid."),
+			Self::PaymentRailUnavailable => {
+				write!(f, "None of the payment methods of the given URI can be used.")
+			},
+			Self::LnurlRequestFailed => {
+				write!(f, "Failed to retrieve data from the LNURL service.")
+			},
+			Self::LnurlWithdrawRejected => {
+				write!(f, "The LNURL-withdraw service refused to pay our invoice.")
+			},
+			Self::ShutdownTimedOut => {
+				write!(f, "Some background tasks didn't stop in time during shutdown.")
+			},
+			Self::SyncPaused => write!(f, "Background syncing is paused."),
+			Self::DeadLetterEventNotFound => {
+				write!(f, "The given dead-letter event could not be found.")
+			},
+			Self::InvalidPaymentId => write!(f, "The given payment id is invalid."),
+			Self::PaymentNotFound => write!(f, "No payment with the given id is known."),
+			Self::PaymentTimedOut => write!(f, "The payment didn't reach a final status in time."),
+			Self::PhantomPaymentsNotEnabled => {
+				write!(f, "Receiving phantom payments was not enabled.")
+			},
+			Self::InvalidPhantomRouteHints => {
+				write!(f, "The given phantom route hints are invalid.")
+			},
+			Self::InvalidCltvExpiryDelta => write!(f, "The given CLTV expiry delta is invalid."),
+			Self::RoutingFeeLimitExceeded => {
+				write!(f, "Failed to find a route within the maximum routing fee limit.")
+			},
+			Self::FirstHopChannelUnusable => {
+				write!(f, "The requested first-hop channel can't be used to send the payment.")
+			},
+			Self::InvalidOnionMessageType => write!(f, "The given onion message type is invalid."),
+			Self::InvalidBlindedPath => write!(f, "The given blinded path is invalid."),
 		}
 	}
 }
@@ -1,42 +1,62 @@
-use crate::types::{Sweeper, Wallet};
+use crate::types::{
+	BumpTransactionEventHandler, ChannelDecision, CustomTlvRecord, InboundChannelPolicy,
+	PeerDisconnectReason, PendingChannelRequest, Router, Sweeper, Wallet,
+};
 use crate::{
-	hex_utils, ChannelManager, Config, Error, NetworkGraph, PeerInfo, PeerStore, UserChannelId,
+	hex_utils, total_anchor_channels_reserve_sats, ChannelManager, Config, Error, NetworkGraph,
+	PeerInfo, PeerStore, PeerTransport, UserChannelId,
 };
 
 use crate::payment_store::{
-	PaymentDetails, PaymentDetailsUpdate, PaymentDirection, PaymentStatus, PaymentStore,
+	PaymentDetails, PaymentDetailsUpdate, PaymentDirection, PaymentKind, PaymentStatus,
+	PaymentStore,
 };
 
+use crate::hold_invoice::HoldInvoiceHandler;
 use crate::io::{
-	EVENT_QUEUE_PERSISTENCE_KEY, EVENT_QUEUE_PERSISTENCE_PRIMARY_NAMESPACE,
-	EVENT_QUEUE_PERSISTENCE_SECONDARY_NAMESPACE,
+	DEAD_LETTER_EVENT_PERSISTENCE_PRIMARY_NAMESPACE,
+	DEAD_LETTER_EVENT_PERSISTENCE_SECONDARY_NAMESPACE, EVENT_QUEUE_PERSISTENCE_KEY,
+	EVENT_QUEUE_PERSISTENCE_PRIMARY_NAMESPACE, EVENT_QUEUE_PERSISTENCE_SECONDARY_NAMESPACE,
 };
 use crate::logger::{log_error, log_info, Logger};
+use crate::payment_retry::PaymentRetrier;
 
+use lightning::blinded_path::BlindedPath;
 use lightning::chain::chaininterface::ConfirmationTarget;
+use lightning::events::bump_transaction::BumpTransactionEvent;
 use lightning::events::{ClosureReason, PaymentPurpose};
 use lightning::events::{Event as LdkEvent, PaymentFailureReason};
-use lightning::impl_writeable_tlv_based_enum;
+use lightning::ln::msgs::SocketAddress;
 use lightning::ln::{ChannelId, PaymentHash};
 use lightning::routing::gossip::NodeId;
 use lightning::util::errors::APIError;
 use lightning::util::persist::KVStore;
 use lightning::util::ser::{Readable, ReadableArgs, Writeable, Writer};
+use lightning::{impl_writeable_tlv_based, impl_writeable_tlv_based_enum};
 
 use lightning_liquidity::lsps2::utils::compute_opening_fee;
 
 use bitcoin::blockdata::locktime::absolute::LockTime;
 use bitcoin::secp256k1::PublicKey;
-use bitcoin::OutPoint;
+use bitcoin::{OutPoint, Transaction, Txid};
 
 use rand::{thread_rng, Rng};
 
 use core::future::Future;
 use core::task::{Poll, Waker};
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ops::Deref;
 use std::sync::{Arc, Condvar, Mutex, RwLock};
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// The estimated weight of a transaction spending an anchor output and a single P2WPKH wallet
+// input to a single P2WPKH change output, used to determine the funds required to bump the fee of
+// a commitment transaction via CPFP.
+//
+// This is comprised of the transaction overhead including the segwit marker and flag (42 WU),
+// the anchor input (164 WU non-witness data + 115 WU witness data), the wallet input (164 WU
+// non-witness data + 109 WU witness data), and the change output (124 WU).
+const ANCHOR_CHILD_TX_WEIGHT: u64 = 42 + 164 + 115 + 164 + 109 + 124;
 
 /// An event emitted by [`Node`], which should be handled by the user.
 ///
@@ -65,6 +85,13 @@ pub enum Event {
 		payment_hash: PaymentHash,
 		/// The value, in thousandths of a satoshi, that has been received.
 		amount_msat: u64,
+		/// The custom TLV records the sender attached to the payment, if any.
+		custom_tlvs: Vec<CustomTlvRecord>,
+		/// Indicates whether the received amount met the amount we expected to receive.
+		///
+		/// This is only `Some` for payments to "zero-amount" invoices created with an expected
+		/// amount.
+		met_expected_amount: Option<bool>,
 	},
 	/// A channel has been created and is pending confirmation on-chain.
 	ChannelPending {
@@ -102,6 +129,172 @@ pub enum Event {
 		counterparty_node_id: Option<PublicKey>,
 		/// This will be `None` for events serialized by LDK Node v0.2.1 and prior.
 		reason: Option<ClosureReason>,
+		/// The reason given when force-closing the channel via [`Node::force_close_channel`], if
+		/// any.
+		///
+		/// [`Node::force_close_channel`]: crate::Node::force_close_channel
+		force_close_reason: Option<String>,
+	},
+	/// A probe succeeded, i.e., the probed path had sufficient liquidity.
+	ProbeSuccessful {
+		/// The hash of the probe.
+		payment_hash: PaymentHash,
+	},
+	/// A probe failed.
+	ProbeFailed {
+		/// The hash of the probe.
+		payment_hash: PaymentHash,
+		/// The short channel id of the channel at which the probe failed, if known.
+		short_channel_id: Option<u64>,
+	},
+	/// Our confirmed on-chain funds are insufficient to bump the fee of a force-closed channel's
+	/// commitment transaction via its anchor output.
+	///
+	/// We'll retry bumping the fee on every new block, so the commitment transaction will get
+	/// confirmed once sufficient on-chain funds are available. The wallet should therefore be
+	/// topped up by at least the missing amount as soon as possible, as the channel funds may be at
+	/// risk otherwise.
+	///
+	/// This event is only emitted once per channel until sufficient funds become available.
+	///
+	/// See [`AnchorChannelsConfig`] for more information on the on-chain reserve we maintain for
+	/// Anchor channels.
+	///
+	/// [`AnchorChannelsConfig`]: crate::AnchorChannelsConfig
+	AnchorBumpFailed {
+		/// The `channel_id` of the channel.
+		channel_id: ChannelId,
+		/// The estimated amount of on-chain funds, in satoshis, required to bump the fee of the
+		/// commitment transaction to the target fee rate.
+		required_sat: u64,
+		/// The amount of confirmed on-chain funds, in satoshis, currently available to us.
+		available_sat: u64,
+	},
+	/// A connection to a peer has been established.
+	///
+	/// **Note:** This event is only emitted if [`Config::emit_peer_connection_events`] is set.
+	///
+	/// [`Config::emit_peer_connection_events`]: crate::Config::emit_peer_connection_events
+	PeerConnected {
+		/// The node ID of the peer.
+		node_id: PublicKey,
+		/// The network address of the peer, if known.
+		address: Option<SocketAddress>,
+	},
+	/// A connection to a peer has been closed.
+	///
+	/// **Note:** This event is only emitted if [`Config::emit_peer_connection_events`] is set.
+	///
+	/// [`Config::emit_peer_connection_events`]: crate::Config::emit_peer_connection_events
+	PeerDisconnected {
+		/// The node ID of the peer.
+		node_id: PublicKey,
+		/// The reason the connection was closed.
+		reason: PeerDisconnectReason,
+	},
+	/// We failed to retrieve fresh fee rate estimates on startup.
+	///
+	/// Until a fresh estimate arrives, we keep operating with the most recent estimates we
+	/// persisted, or with conservative fallback rates if none are available. The age of the fee
+	/// rates in use is reflected by [`NodeStatus::latest_fee_rate_cache_update_timestamp`].
+	///
+	/// [`NodeStatus::latest_fee_rate_cache_update_timestamp`]: crate::NodeStatus::latest_fee_rate_cache_update_timestamp
+	FeeEstimationStale {
+		/// The time, in seconds since the UNIX epoch, the fee rates in use were retrieved.
+		///
+		/// Will be `None` if we never retrieved any estimates and are using fallback rates.
+		last_update_timestamp: Option<u64>,
+	},
+	/// An on-chain payment to our wallet reached [`Config::onchain_receive_min_confirmations`].
+	///
+	/// This includes funds swept to our wallet after a channel was closed, but not funds we sent to
+	/// ourselves.
+	///
+	/// **Note:** This event is only emitted if [`Config::emit_onchain_payment_events`] is set.
+	///
+	/// [`Config::onchain_receive_min_confirmations`]: crate::Config::onchain_receive_min_confirmations
+	/// [`Config::emit_onchain_payment_events`]: crate::Config::emit_onchain_payment_events
+	OnchainPaymentReceived {
+		/// The txid of the transaction paying to us.
+		txid: Txid,
+		/// The amount received, in satoshis.
+		amount_sat: u64,
+		/// The number of confirmations of the transaction when the event was emitted.
+		confirmations: u32,
+		/// The label attached to the receiving address via
+		/// [`Node::new_onchain_address_with_label`], if any.
+		///
+		/// [`Node::new_onchain_address_with_label`]: crate::Node::new_onchain_address_with_label
+		label: Option<String>,
+	},
+	/// An on-chain payment from our wallet has been observed by our chain source.
+	///
+	/// This isn't emitted for channel funding transactions or transactions only spending to
+	/// ourselves, e.g., fee bumps.
+	///
+	/// **Note:** This event is only emitted if [`Config::emit_onchain_payment_events`] is set.
+	///
+	/// [`Config::emit_onchain_payment_events`]: crate::Config::emit_onchain_payment_events
+	OnchainPaymentSent {
+		/// The txid of the sending transaction.
+		txid: Txid,
+		/// The amount sent to others, in satoshis, not including the transaction fee.
+		amount_sat: u64,
+	},
+	/// A payment to a hold invoice created via [`Node::receive_hold_payment`] has arrived and may
+	/// now be claimed via [`Node::claim_payment`] or failed via [`Node::fail_payment`].
+	///
+	/// If the payment is neither claimed nor failed before `claim_deadline`, we'll fail it back
+	/// and emit an [`Event::HeldPaymentAutoFailed`].
+	///
+	/// [`Node::receive_hold_payment`]: crate::Node::receive_hold_payment
+	/// [`Node::claim_payment`]: crate::Node::claim_payment
+	/// [`Node::fail_payment`]: crate::Node::fail_payment
+	PaymentClaimable {
+		/// The hash of the payment.
+		payment_hash: PaymentHash,
+		/// The value, in thousandths of a satoshi, that may be claimed.
+		amount_msat: u64,
+		/// The block height at which the payment's HTLCs would expire, if known.
+		claim_deadline: Option<u32>,
+	},
+	/// A held payment wasn't claimed or failed in time and was therefore failed back, as we'd
+	/// otherwise risk the channel being force-closed.
+	HeldPaymentAutoFailed {
+		/// The hash of the payment.
+		payment_hash: PaymentHash,
+		/// The block height at which the payment's HTLCs would have expired.
+		claim_deadline: u32,
+	},
+	/// The invoice of an inbound payment expired before it was paid.
+	///
+	/// The payment's status is now [`PaymentStatus::Expired`]. Note that a payment may still
+	/// arrive after the invoice expired, in which case we'll claim it nonetheless and emit an
+	/// [`Event::PaymentReceived`].
+	///
+	/// [`PaymentStatus::Expired`]: crate::payment_store::PaymentStatus::Expired
+	PaymentExpired {
+		/// The hash of the payment.
+		payment_hash: PaymentHash,
+	},
+	/// An onion message of a custom TLV type was received.
+	///
+	/// See [`Node::send_onion_message`] for the range of TLV types allowed for custom messages.
+	///
+	/// **Note:** The sender isn't revealed to us, but they may give us a reply path to respond
+	/// to the message.
+	///
+	/// [`Node::send_onion_message`]: crate::Node::send_onion_message
+	OnionMessageReceived {
+		/// The TLV type of the message.
+		tlv_type: u64,
+		/// The contents of the message.
+		data: Vec<u8>,
+		/// The blinded path given by the sender to reply to the message via
+		/// [`Node::reply_to_onion_message`], if any.
+		///
+		/// [`Node::reply_to_onion_message`]: crate::Node::reply_to_onion_message
+		reply_path: Option<BlindedPath>,
 	},
 }
 
@@ -116,7 +309,9 @@ impl_writeable_tlv_based_enum!(Event,
 	},
 	(2, PaymentReceived) => {
 		(0, payment_hash, required),
+		(1, custom_tlvs, optional_vec),
 		(2, amount_msat, required),
+		(3, met_expected_amount, option),
 	},
 	(3, ChannelReady) => {
 		(0, channel_id, required),
@@ -135,16 +330,117 @@ impl_writeable_tlv_based_enum!(Event,
 		(1, counterparty_node_id, option),
 		(2, user_channel_id, required),
 		(3, reason, upgradable_option),
+		(5, force_close_reason, option),
+	},
+	(6, ProbeSuccessful) => {
+		(0, payment_hash, required),
+	},
+	(7, ProbeFailed) => {
+		(0, payment_hash, required),
+		(1, short_channel_id, option),
+	},
+	(8, AnchorBumpFailed) => {
+		(0, channel_id, required),
+		(2, required_sat, required),
+		(4, available_sat, required),
+	},
+	(9, PeerConnected) => {
+		(0, node_id, required),
+		(1, address, option),
+	},
+	(10, PeerDisconnected) => {
+		(0, node_id, required),
+		(2, reason, required),
+	},
+	(11, FeeEstimationStale) => {
+		(1, last_update_timestamp, option),
+	},
+	(12, OnchainPaymentReceived) => {
+		(0, txid, required),
+		(2, amount_sat, required),
+		(4, confirmations, required),
+		(5, label, option),
+	},
+	(13, OnchainPaymentSent) => {
+		(0, txid, required),
+		(2, amount_sat, required),
+	},
+	(14, PaymentClaimable) => {
+		(0, payment_hash, required),
+		(2, amount_msat, required),
+		(4, claim_deadline, option),
+	},
+	(15, HeldPaymentAutoFailed) => {
+		(0, payment_hash, required),
+		(2, claim_deadline, required),
+	},
+	(16, PaymentExpired) => {
+		(0, payment_hash, required),
+	},
+	(17, OnionMessageReceived) => {
+		(0, tlv_type, required),
+		(1, reply_path, option),
+		(2, data, required),
 	};
 );
 
+/// An [`Event`] along with the time it was generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimestampedEvent {
+	/// The event.
+	pub event: Event,
+	/// The time the event was generated, in seconds since the UNIX epoch.
+	///
+	/// Will be `None` for events that were persisted by a version of LDK Node that didn't record
+	/// this information yet.
+	pub generated_at: Option<u64>,
+}
+
+/// An event that was moved to the dead-letter store as it wasn't marked handled after the
+/// configured number of redeliveries.
+///
+/// See [`Config::max_event_redeliveries`] for more information.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetterEvent {
+	/// The identifier of the dead-letter entry.
+	pub id: String,
+	/// The event that failed to be handled.
+	pub event: Event,
+	/// The number of times the event was delivered before it was moved to the dead-letter store.
+	pub delivery_attempts: u32,
+	/// The time the event was moved to the dead-letter store, in seconds since the UNIX epoch.
+	pub dead_lettered_at: u64,
+	/// The time the event was originally generated, in seconds since the UNIX epoch.
+	///
+	/// Will be `None` if the event was persisted by a version of LDK Node that didn't record this
+	/// information yet.
+	pub generated_at: Option<u64>,
+}
+
+impl_writeable_tlv_based!(DeadLetterEvent, {
+	(0, id, required),
+	(2, event, required),
+	(4, delivery_attempts, required),
+	(6, dead_lettered_at, required),
+	(8, generated_at, option),
+});
+
+// A pending event, along with the number of times it was delivered so far.
+struct QueuedEvent {
+	event: TimestampedEvent,
+	deliveries: u32,
+	// Whether the event was handed out since the queue was loaded. Not persisted.
+	handed_out: bool,
+}
+
 pub struct EventQueue<K: KVStore + Sync + Send, L: Deref>
 where
 	L::Target: Logger,
 {
-	queue: Arc<Mutex<VecDeque<Event>>>,
+	queue: Arc<Mutex<VecDeque<QueuedEvent>>>,
 	waker: Arc<Mutex<Option<Waker>>>,
 	notifier: Condvar,
+	max_redeliveries: Option<u32>,
 	kv_store: Arc<K>,
 	logger: L,
 }
@@ -157,17 +453,34 @@ where
 		let queue = Arc::new(Mutex::new(VecDeque::new()));
 		let waker = Arc::new(Mutex::new(None));
 		let notifier = Condvar::new();
-		Self { queue, waker, notifier, kv_store, logger }
+		let max_redeliveries = None;
+		Self { queue, waker, notifier, max_redeliveries, kv_store, logger }
+	}
+
+	/// Sets the number of times an event may be redelivered before it's moved to the dead-letter
+	/// store.
+	pub(crate) fn set_max_redeliveries(&mut self, max_redeliveries: Option<u32>) {
+		self.max_redeliveries = max_redeliveries;
+	}
+
+	/// Returns the number of events that have yet to be handled.
+	pub(crate) fn depth(&self) -> usize {
+		self.queue.lock().unwrap().len()
 	}
 
 	pub(crate) fn add_event(&self, event: Event) -> Result<(), Error> {
+		let generated_at = SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+		self.push_event(TimestampedEvent { event, generated_at })
+	}
+
+	fn push_event(&self, event: TimestampedEvent) -> Result<(), Error> {
 		{
 			let mut locked_queue = self.queue.lock().unwrap();
-			locked_queue.push_back(event);
+			locked_queue.push_back(QueuedEvent { event, deliveries: 0, handed_out: false });
 			self.persist_queue(&locked_queue)?;
 		}
 
-		self.notifier.notify_one();
+		self.notifier.notify_all();
 
 		if let Some(waker) = self.waker.lock().unwrap().take() {
 			waker.wake();
@@ -175,19 +488,227 @@ where
 		Ok(())
 	}
 
-	pub(crate) fn next_event(&self) -> Option<Event> {
+	/// Persists the current state of the queue.
+	pub(crate) fn persist(&self) -> Result<(), Error> {
+		let locked_queue = self.queue.lock().unwrap();
+		self.persist_queue(&locked_queue)
+	}
+
+	/// Returns up to `max` pending events in queue order, without consuming them.
+	pub(crate) fn peek_events(&self, max: usize) -> Vec<Event> {
 		let locked_queue = self.queue.lock().unwrap();
-		locked_queue.front().map(|e| e.clone())
+		locked_queue.iter().take(max).map(|e| e.event.event.clone()).collect()
+	}
+
+	pub(crate) fn next_event(&self) -> Option<Event> {
+		self.next_timestamped_event().map(|e| e.event)
+	}
+
+	pub(crate) fn next_timestamped_event(&self) -> Option<TimestampedEvent> {
+		let mut locked_queue = self.queue.lock().unwrap();
+		self.deliver_front(&mut locked_queue)
 	}
 
 	pub(crate) async fn next_event_async(&self) -> Event {
-		EventFuture { event_queue: Arc::clone(&self.queue), waker: Arc::clone(&self.waker) }.await
+		loop {
+			EventFuture { event_queue: Arc::clone(&self.queue), waker: Arc::clone(&self.waker) }
+				.await;
+			let mut locked_queue = self.queue.lock().unwrap();
+			if let Some(event) = self.deliver_front(&mut locked_queue) {
+				return event.event;
+			}
+		}
 	}
 
 	pub(crate) fn wait_next_event(&self) -> Event {
-		let locked_queue =
-			self.notifier.wait_while(self.queue.lock().unwrap(), |queue| queue.is_empty()).unwrap();
-		locked_queue.front().unwrap().clone()
+		self.wait_next_timestamped_event().event
+	}
+
+	pub(crate) fn wait_next_timestamped_event(&self) -> TimestampedEvent {
+		let mut locked_queue = self.queue.lock().unwrap();
+		loop {
+			locked_queue =
+				self.notifier.wait_while(locked_queue, |queue| queue.is_empty()).unwrap();
+			if let Some(event) = self.deliver_front(&mut locked_queue) {
+				return event;
+			}
+		}
+	}
+
+	fn deliver_front(&self, locked_queue: &mut VecDeque<QueuedEvent>) -> Option<TimestampedEvent> {
+		self.deliver_matching(locked_queue, |_| true)
+	}
+
+	// Returns the first event matching the filter and counts its delivery. If it was already
+	// delivered more often than allowed, it's moved to the dead-letter store instead and the
+	// search continues.
+	fn deliver_matching<F: Fn(&Event) -> bool>(
+		&self, locked_queue: &mut VecDeque<QueuedEvent>, filter: F,
+	) -> Option<TimestampedEvent> {
+		loop {
+			let pos = locked_queue.iter().position(|e| filter(&e.event.event))?;
+			let max_redeliveries = match self.max_redeliveries {
+				Some(max_redeliveries) => max_redeliveries,
+				None => return Some(locked_queue[pos].event.clone()),
+			};
+
+			let queued_event = &mut locked_queue[pos];
+			if queued_event.deliveries > max_redeliveries
+				&& self.dead_letter_event(&queued_event.event, queued_event.deliveries).is_ok()
+			{
+				locked_queue.remove(pos);
+				// Errors are logged by `persist_queue`, and the event would at worst be
+				// redelivered after a restart.
+				let _ = self.persist_queue(locked_queue);
+				continue;
+			}
+
+			queued_event.deliveries += 1;
+			let event = queued_event.event.clone();
+			if !queued_event.handed_out {
+				queued_event.handed_out = true;
+				// We persist the delivery count on the first hand-out so that an event crashing
+				// its handler is still dead-lettered eventually. Later redeliveries are only
+				// persisted along with the next queue update to avoid rewriting the queue on every
+				// poll. Errors are logged by `persist_queue`, and would at worst lead to
+				// additional redeliveries.
+				let _ = self.persist_queue(locked_queue);
+			}
+			return Some(event);
+		}
+	}
+
+	fn dead_letter_event(
+		&self, event: &TimestampedEvent, delivery_attempts: u32,
+	) -> Result<(), Error> {
+		let id = hex_utils::to_string(&thread_rng().gen::<[u8; 16]>());
+		let dead_lettered_at =
+			SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+		let entry = DeadLetterEvent {
+			id: id.clone(),
+			event: event.event.clone(),
+			delivery_attempts,
+			dead_lettered_at,
+			generated_at: event.generated_at,
+		};
+		self.kv_store
+			.write(
+				DEAD_LETTER_EVENT_PERSISTENCE_PRIMARY_NAMESPACE,
+				DEAD_LETTER_EVENT_PERSISTENCE_SECONDARY_NAMESPACE,
+				&id,
+				&entry.encode(),
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					DEAD_LETTER_EVENT_PERSISTENCE_PRIMARY_NAMESPACE,
+					DEAD_LETTER_EVENT_PERSISTENCE_SECONDARY_NAMESPACE,
+					id,
+					e
+				);
+				Error::PersistenceFailed
+			})?;
+		log_error!(
+			self.logger,
+			"Event {:?} wasn't handled after {} deliveries, moved it to the dead-letter store with id {}",
+			event.event,
+			delivery_attempts,
+			id
+		);
+		Ok(())
+	}
+
+	pub(crate) fn list_dead_letter_events(&self) -> Result<Vec<DeadLetterEvent>, Error> {
+		let keys = self
+			.kv_store
+			.list(
+				DEAD_LETTER_EVENT_PERSISTENCE_PRIMARY_NAMESPACE,
+				DEAD_LETTER_EVENT_PERSISTENCE_SECONDARY_NAMESPACE,
+			)
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to list dead-letter events: {}", e);
+				Error::PersistenceFailed
+			})?;
+		keys.iter().map(|id| self.read_dead_letter_event(id)).collect()
+	}
+
+	fn read_dead_letter_event(&self, id: &str) -> Result<DeadLetterEvent, Error> {
+		let data = self
+			.kv_store
+			.read(
+				DEAD_LETTER_EVENT_PERSISTENCE_PRIMARY_NAMESPACE,
+				DEAD_LETTER_EVENT_PERSISTENCE_SECONDARY_NAMESPACE,
+				id,
+			)
+			.map_err(|e| match e.kind() {
+				std::io::ErrorKind::NotFound | std::io::ErrorKind::InvalidInput => {
+					Error::DeadLetterEventNotFound
+				},
+				_ => {
+					log_error!(self.logger, "Failed to read dead-letter event {}: {}", id, e);
+					Error::PersistenceFailed
+				},
+			})?;
+		DeadLetterEvent::read(&mut &data[..]).map_err(|e| {
+			log_error!(self.logger, "Failed to deserialize dead-letter event {}: {}", id, e);
+			Error::PersistenceFailed
+		})
+	}
+
+	/// Moves the given dead-letter event back to the end of the event queue.
+	pub(crate) fn replay_dead_letter_event(&self, id: &str) -> Result<(), Error> {
+		let entry = self.read_dead_letter_event(id)?;
+		self.push_event(TimestampedEvent { event: entry.event, generated_at: entry.generated_at })?;
+		self.kv_store
+			.remove(
+				DEAD_LETTER_EVENT_PERSISTENCE_PRIMARY_NAMESPACE,
+				DEAD_LETTER_EVENT_PERSISTENCE_SECONDARY_NAMESPACE,
+				id,
+				false,
+			)
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to remove dead-letter event {}: {}", id, e);
+				Error::PersistenceFailed
+			})
+	}
+
+	pub(crate) fn next_event_matching<F: Fn(&Event) -> bool>(&self, filter: F) -> Option<Event> {
+		let mut locked_queue = self.queue.lock().unwrap();
+		self.deliver_matching(&mut locked_queue, filter).map(|e| e.event)
+	}
+
+	pub(crate) fn wait_next_event_matching<F: Fn(&Event) -> bool>(&self, filter: F) -> Event {
+		let mut locked_queue = self.queue.lock().unwrap();
+		loop {
+			locked_queue = self
+				.notifier
+				.wait_while(locked_queue, |queue| !queue.iter().any(|e| filter(&e.event.event)))
+				.unwrap();
+			if let Some(event) = self.deliver_matching(&mut locked_queue, &filter) {
+				return event.event;
+			}
+		}
+	}
+
+	/// Removes the first pending event equal to the given one, if any.
+	pub(crate) fn matching_event_handled(&self, event: &Event) -> Result<(), Error> {
+		{
+			let mut locked_queue = self.queue.lock().unwrap();
+			match locked_queue.iter().position(|e| e.event.event == *event) {
+				Some(pos) => {
+					locked_queue.remove(pos);
+				},
+				None => return Ok(()),
+			}
+			self.persist_queue(&locked_queue)?;
+		}
+		self.notifier.notify_all();
+
+		if let Some(waker) = self.waker.lock().unwrap().take() {
+			waker.wake();
+		}
+		Ok(())
 	}
 
 	pub(crate) fn event_handled(&self) -> Result<(), Error> {
@@ -196,7 +717,7 @@ where
 			locked_queue.pop_front();
 			self.persist_queue(&locked_queue)?;
 		}
-		self.notifier.notify_one();
+		self.notifier.notify_all();
 
 		if let Some(waker) = self.waker.lock().unwrap().take() {
 			waker.wake();
@@ -204,7 +725,7 @@ where
 		Ok(())
 	}
 
-	fn persist_queue(&self, locked_queue: &VecDeque<Event>) -> Result<(), Error> {
+	fn persist_queue(&self, locked_queue: &VecDeque<QueuedEvent>) -> Result<(), Error> {
 		let data = EventQueueSerWrapper(locked_queue).encode();
 		self.kv_store
 			.write(
@@ -241,11 +762,12 @@ where
 		let queue = Arc::new(Mutex::new(read_queue.0));
 		let waker = Arc::new(Mutex::new(None));
 		let notifier = Condvar::new();
-		Ok(Self { queue, waker, notifier, kv_store, logger })
+		let max_redeliveries = None;
+		Ok(Self { queue, waker, notifier, max_redeliveries, kv_store, logger })
 	}
 }
 
-struct EventQueueDeserWrapper(VecDeque<Event>);
+struct EventQueueDeserWrapper(VecDeque<QueuedEvent>);
 
 impl Readable for EventQueueDeserWrapper {
 	fn read<R: lightning::io::Read>(
@@ -254,26 +776,66 @@ impl Readable for EventQueueDeserWrapper {
 		let len: u16 = Readable::read(reader)?;
 		let mut queue = VecDeque::with_capacity(len as usize);
 		for _ in 0..len {
-			queue.push_back(Readable::read(reader)?);
+			let event = Readable::read(reader)?;
+			queue.push_back(QueuedEvent {
+				event: TimestampedEvent { event, generated_at: None },
+				deliveries: 0,
+				handed_out: false,
+			});
+		}
+
+		// The timestamps are appended after the events, so older queues without them simply end
+		// here.
+		let timestamps_len: u16 = match Readable::read(reader) {
+			Ok(timestamps_len) => timestamps_len,
+			Err(lightning::ln::msgs::DecodeError::ShortRead) => return Ok(Self(queue)),
+			Err(e) => return Err(e),
+		};
+		if timestamps_len as usize != queue.len() {
+			return Err(lightning::ln::msgs::DecodeError::InvalidValue);
+		}
+		for e in queue.iter_mut() {
+			e.event.generated_at = Readable::read(reader)?;
+		}
+
+		// Likewise, the delivery counts are appended after the timestamps.
+		let deliveries_len: u16 = match Readable::read(reader) {
+			Ok(deliveries_len) => deliveries_len,
+			Err(lightning::ln::msgs::DecodeError::ShortRead) => return Ok(Self(queue)),
+			Err(e) => return Err(e),
+		};
+		if deliveries_len as usize != queue.len() {
+			return Err(lightning::ln::msgs::DecodeError::InvalidValue);
+		}
+		for e in queue.iter_mut() {
+			e.deliveries = Readable::read(reader)?;
 		}
 		Ok(Self(queue))
 	}
 }
 
-struct EventQueueSerWrapper<'a>(&'a VecDeque<Event>);
+struct EventQueueSerWrapper<'a>(&'a VecDeque<QueuedEvent>);
 
 impl Writeable for EventQueueSerWrapper<'_> {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
 		(self.0.len() as u16).write(writer)?;
 		for e in self.0.iter() {
-			e.write(writer)?;
+			e.event.event.write(writer)?;
+		}
+		(self.0.len() as u16).write(writer)?;
+		for e in self.0.iter() {
+			e.event.generated_at.write(writer)?;
+		}
+		(self.0.len() as u16).write(writer)?;
+		for e in self.0.iter() {
+			e.deliveries.write(writer)?;
 		}
 		Ok(())
 	}
 }
 
 struct EventFuture {
-	event_queue: Arc<Mutex<VecDeque<Event>>>,
+	event_queue: Arc<Mutex<VecDeque<QueuedEvent>>>,
 	waker: Arc<Mutex<Option<Waker>>>,
 }
 
@@ -284,7 +846,7 @@ impl Future for EventFuture {
 		self: core::pin::Pin<&mut Self>, cx: &mut core::task::Context<'_>,
 	) -> core::task::Poll<Self::Output> {
 		if let Some(event) = self.event_queue.lock().unwrap().front() {
-			Poll::Ready(event.clone())
+			Poll::Ready(event.event.event.clone())
 		} else {
 			*self.waker.lock().unwrap() = Some(cx.waker().clone());
 			Poll::Pending
@@ -292,6 +854,68 @@ impl Future for EventFuture {
 	}
 }
 
+/// A [`Stream`] yielding the events of an [`EventQueue`].
+///
+/// An event is only marked as handled once the consumer polls for the next one, i.e., after it
+/// finished processing the previously yielded event. Dropping the stream will therefore leave the
+/// in-flight event in the queue so that it's replayed (e.g., after a restart).
+///
+/// [`Stream`]: futures::Stream
+#[cfg(feature = "event-stream")]
+pub(crate) struct EventStream<K: KVStore + Sync + Send, L: Deref>
+where
+	L::Target: Logger,
+{
+	event_queue: Arc<EventQueue<K, L>>,
+	pending_ack: bool,
+}
+
+#[cfg(feature = "event-stream")]
+impl<K: KVStore + Sync + Send, L: Deref> EventStream<K, L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(event_queue: Arc<EventQueue<K, L>>) -> Self {
+		Self { event_queue, pending_ack: false }
+	}
+}
+
+#[cfg(feature = "event-stream")]
+impl<K: KVStore + Sync + Send, L: Deref> futures::Stream for EventStream<K, L>
+where
+	L::Target: Logger,
+{
+	type Item = Event;
+
+	fn poll_next(
+		mut self: core::pin::Pin<&mut Self>, cx: &mut core::task::Context<'_>,
+	) -> Poll<Option<Self::Item>> {
+		if self.pending_ack {
+			self.event_queue.event_handled().unwrap_or_else(|e| {
+				log_error!(
+					self.event_queue.logger,
+					"Couldn't mark event handled due to persistence failure: {}",
+					e
+				);
+				panic!("Couldn't mark event handled due to persistence failure");
+			});
+			self.pending_ack = false;
+		}
+
+		// We hold the queue lock while registering the waker so we can't miss a concurrent
+		// `add_event`.
+		let mut locked_queue = self.event_queue.queue.lock().unwrap();
+		if let Some(event) = self.event_queue.deliver_front(&mut locked_queue) {
+			drop(locked_queue);
+			self.pending_ack = true;
+			Poll::Ready(Some(event.event))
+		} else {
+			*self.event_queue.waker.lock().unwrap() = Some(cx.waker().clone());
+			Poll::Pending
+		}
+	}
+}
+
 pub(crate) struct EventHandler<K: KVStore + Sync + Send, L: Deref>
 where
 	L::Target: Logger,
@@ -300,12 +924,21 @@ where
 	wallet: Arc<Wallet>,
 	channel_manager: Arc<ChannelManager<K>>,
 	output_sweeper: Arc<Sweeper<K>>,
+	bump_tx_event_handler: Arc<BumpTransactionEventHandler>,
+	// The reasons given for pending force-closures, keyed by `user_channel_id`.
+	force_close_reasons: Arc<Mutex<HashMap<u128, String>>>,
 	network_graph: Arc<NetworkGraph>,
 	payment_store: Arc<PaymentStore<K, L>>,
+	payment_retrier: Arc<PaymentRetrier<K, L>>,
+	hold_invoice_handler: Arc<HoldInvoiceHandler<K, L>>,
 	peer_store: Arc<PeerStore<K, L>>,
+	inbound_channel_policy: Option<Arc<InboundChannelPolicy>>,
+	router: Arc<Router>,
 	runtime: Arc<RwLock<Option<tokio::runtime::Runtime>>>,
 	logger: L,
 	config: Arc<Config>,
+	// Channels for which we already reported insufficient funds to bump the anchor output.
+	anchor_bump_failures: Mutex<HashSet<ChannelId>>,
 }
 
 impl<K: KVStore + Sync + Send + 'static, L: Deref> EventHandler<K, L>
@@ -315,21 +948,72 @@ where
 	pub fn new(
 		event_queue: Arc<EventQueue<K, L>>, wallet: Arc<Wallet>,
 		channel_manager: Arc<ChannelManager<K>>, output_sweeper: Arc<Sweeper<K>>,
-		network_graph: Arc<NetworkGraph>, payment_store: Arc<PaymentStore<K, L>>,
-		peer_store: Arc<PeerStore<K, L>>, runtime: Arc<RwLock<Option<tokio::runtime::Runtime>>>,
-		logger: L, config: Arc<Config>,
+		bump_tx_event_handler: Arc<BumpTransactionEventHandler>,
+		force_close_reasons: Arc<Mutex<HashMap<u128, String>>>, network_graph: Arc<NetworkGraph>,
+		payment_store: Arc<PaymentStore<K, L>>, payment_retrier: Arc<PaymentRetrier<K, L>>,
+		hold_invoice_handler: Arc<HoldInvoiceHandler<K, L>>, peer_store: Arc<PeerStore<K, L>>,
+		inbound_channel_policy: Option<Arc<InboundChannelPolicy>>, router: Arc<Router>,
+		runtime: Arc<RwLock<Option<tokio::runtime::Runtime>>>, logger: L, config: Arc<Config>,
 	) -> Self {
+		let anchor_bump_failures = Mutex::new(HashSet::new());
 		Self {
 			event_queue,
 			wallet,
 			channel_manager,
 			output_sweeper,
+			bump_tx_event_handler,
+			force_close_reasons,
 			network_graph,
 			payment_store,
+			payment_retrier,
+			hold_invoice_handler,
 			peer_store,
+			inbound_channel_policy,
+			router,
 			logger,
 			runtime,
 			config,
+			anchor_bump_failures,
+		}
+	}
+
+	// Checks whether our confirmed on-chain funds suffice to bump the fee of the given commitment
+	// transaction via its anchor output, and emits an `AnchorBumpFailed` event if they don't.
+	//
+	// As LDK regenerates `BumpTransactionEvent`s on every new block until the commitment
+	// transaction confirms, this is re-checked on every block. We only emit the event once until
+	// sufficient funds become available again.
+	fn check_anchor_bump_funds(
+		&self, channel_id: ChannelId, package_target_feerate_sat_per_1000_weight: u32,
+		commitment_tx: &Transaction, commitment_tx_fee_satoshis: u64,
+	) {
+		let package_weight = commitment_tx.weight().to_wu() + ANCHOR_CHILD_TX_WEIGHT;
+		let package_fee_sat =
+			package_weight * package_target_feerate_sat_per_1000_weight as u64 / 1000;
+		let required_sat = package_fee_sat.saturating_sub(commitment_tx_fee_satoshis);
+		let available_sat = self.wallet.get_balance().map(|bal| bal.confirmed).unwrap_or(0);
+
+		let mut locked_failures = self.anchor_bump_failures.lock().unwrap();
+		if available_sat >= required_sat {
+			locked_failures.remove(&channel_id);
+			return;
+		}
+
+		log_error!(
+			self.logger,
+			"Insufficient on-chain funds to bump the fee of the commitment transaction of channel {}. Required: {}sats, available: {}sats",
+			channel_id,
+			required_sat,
+			available_sat,
+		);
+
+		if locked_failures.insert(channel_id) {
+			self.event_queue
+				.add_event(Event::AnchorBumpFailed { channel_id, required_sat, available_sat })
+				.unwrap_or_else(|e| {
+					log_error!(self.logger, "Failed to push to event queue: {}", e);
+					panic!("Failed to push to event queue");
+				});
 		}
 	}
 
@@ -340,6 +1024,7 @@ where
 				counterparty_node_id,
 				channel_value_satoshis,
 				output_script,
+				user_channel_id,
 				..
 			} => {
 				// Construct the raw transaction with the output that is paid the amount of the
@@ -356,6 +1041,7 @@ where
 					channel_value_satoshis,
 					confirmation_target,
 					locktime,
+					user_channel_id,
 				) {
 					Ok(final_tx) => {
 						// Give the funding transaction back to LDK for opening the channel.
@@ -408,8 +1094,8 @@ where
 				receiver_node_id: _,
 				via_channel_id: _,
 				via_user_channel_id: _,
-				claim_deadline: _,
-				onion_fields: _,
+				claim_deadline,
+				onion_fields,
 				counterparty_skimmed_fee_msat,
 			} => {
 				if let Some(info) = self.payment_store.get(&payment_hash) {
@@ -467,6 +1153,55 @@ where
 					}
 				}
 
+				// We persist any custom TLVs right away, so that they're still around to be
+				// reported once the payment is claimed, even if we restart in-between.
+				let custom_tlvs: Vec<CustomTlvRecord> = onion_fields
+					.map(|f| f.custom_tlvs().iter().map(|tlv| tlv.into()).collect())
+					.unwrap_or_default();
+				if !custom_tlvs.is_empty() {
+					let update = PaymentDetailsUpdate {
+						custom_tlvs: Some(custom_tlvs.clone()),
+						..PaymentDetailsUpdate::new(payment_hash)
+					};
+					let updated = self.payment_store.update(&update).unwrap_or_else(|e| {
+						log_error!(self.logger, "Failed to access payment store: {}", e);
+						panic!("Failed to access payment store");
+					});
+					if let (false, PaymentPurpose::SpontaneousPayment(preimage)) =
+						(updated, &purpose)
+					{
+						// Spontaneous payments are unknown to us until they become claimable.
+						let payment = PaymentDetails {
+							preimage: Some(*preimage),
+							hash: payment_hash,
+							id: None,
+							secret: None,
+							amount_msat: Some(amount_msat),
+							direction: PaymentDirection::Inbound,
+							status: PaymentStatus::Pending,
+							lsp_fee_limits: None,
+							kind: Some(PaymentKind::Spontaneous),
+							description_hash: None,
+							expected_amount_msat: None,
+							invoice_expiry_timestamp: None,
+							custom_tlvs,
+						};
+						self.payment_store.insert(payment).unwrap_or_else(|e| {
+							log_error!(self.logger, "Failed to access payment store: {}", e);
+							panic!("Failed to access payment store");
+						});
+					}
+				}
+
+				if self.hold_invoice_handler.handle_payment_claimable(
+					payment_hash,
+					amount_msat,
+					claim_deadline,
+				) {
+					// Held payments are only claimed once the user asks us to.
+					return;
+				}
+
 				log_info!(
 					self.logger,
 					"Received payment from payment hash {} of {}msat",
@@ -510,7 +1245,7 @@ where
 				payment_hash,
 				purpose,
 				amount_msat,
-				receiver_node_id: _,
+				receiver_node_id,
 				htlcs: _,
 				sender_intended_total_msat: _,
 			} => {
@@ -520,6 +1255,7 @@ where
 					hex_utils::to_string(&payment_hash.0),
 					amount_msat,
 				);
+				self.hold_invoice_handler.handle_payment_claimed(&payment_hash);
 				match purpose {
 					PaymentPurpose::InvoicePayment { payment_preimage, payment_secret, .. } => {
 						let update = PaymentDetailsUpdate {
@@ -532,12 +1268,39 @@ where
 						match self.payment_store.update(&update) {
 							Ok(true) => (),
 							Ok(false) => {
-								log_error!(
-									self.logger,
-									"Payment with hash {} couldn't be found in store",
-									hex_utils::to_string(&payment_hash.0)
-								);
-								debug_assert!(false);
+								// Payments for our BOLT12 offers are only known to us once
+								// they're claimed, as the invoice is created and sent by LDK. The
+								// same holds for payments to phantom invoices created by another
+								// node.
+								let our_node_id = self.channel_manager.get_our_node_id();
+								let kind = match receiver_node_id {
+									Some(node_id) if node_id != our_node_id => PaymentKind::Bolt11,
+									_ => PaymentKind::Bolt12,
+								};
+								let payment = PaymentDetails {
+									preimage: payment_preimage,
+									hash: payment_hash,
+									id: None,
+									secret: Some(payment_secret),
+									amount_msat: Some(amount_msat),
+									direction: PaymentDirection::Inbound,
+									status: PaymentStatus::Succeeded,
+									lsp_fee_limits: None,
+									kind: Some(kind),
+									description_hash: None,
+									expected_amount_msat: None,
+									invoice_expiry_timestamp: None,
+									custom_tlvs: Vec::new(),
+								};
+								self.payment_store.insert(payment).unwrap_or_else(|e| {
+									log_error!(
+										self.logger,
+										"Failed to insert payment with hash {}: {}",
+										hex_utils::to_string(&payment_hash.0),
+										e
+									);
+									debug_assert!(false);
+								});
 							},
 							Err(e) => {
 								log_error!(
@@ -551,18 +1314,35 @@ where
 						}
 					},
 					PaymentPurpose::SpontaneousPayment(preimage) => {
+						// Spontaneous payments carrying custom TLVs were already recorded when
+						// they became claimable.
+						let claimable_payment = self
+							.payment_store
+							.get(&payment_hash)
+							.filter(|p| p.status == PaymentStatus::Pending);
+						let custom_tlvs = claimable_payment
+							.as_ref()
+							.map(|p| p.custom_tlvs.clone())
+							.unwrap_or_default();
 						let payment = PaymentDetails {
 							preimage: Some(preimage),
 							hash: payment_hash,
+							id: None,
 							secret: None,
 							amount_msat: Some(amount_msat),
 							direction: PaymentDirection::Inbound,
 							status: PaymentStatus::Succeeded,
 							lsp_fee_limits: None,
+							kind: Some(PaymentKind::Spontaneous),
+							description_hash: None,
+							expected_amount_msat: None,
+							invoice_expiry_timestamp: None,
+							custom_tlvs,
 						};
 
 						match self.payment_store.insert(payment) {
 							Ok(false) => (),
+							Ok(true) if claimable_payment.is_some() => (),
 							Ok(true) => {
 								log_error!(
 									self.logger,
@@ -584,14 +1364,41 @@ where
 					},
 				};
 
+				// Rebalancing payments are circular payments to ourselves, whose outcome is reported
+				// on the sending side only.
+				if self.payment_store.get(&payment_hash).and_then(|p| p.kind)
+					== Some(PaymentKind::Rebalance)
+				{
+					return;
+				}
+
+				let payment = self.payment_store.get(&payment_hash);
+				let custom_tlvs =
+					payment.as_ref().map(|p| p.custom_tlvs.clone()).unwrap_or_default();
+				let met_expected_amount = payment
+					.and_then(|p| p.expected_amount_msat)
+					.map(|expected_amount_msat| amount_msat >= expected_amount_msat);
 				self.event_queue
-					.add_event(Event::PaymentReceived { payment_hash, amount_msat })
+					.add_event(Event::PaymentReceived {
+						payment_hash,
+						amount_msat,
+						custom_tlvs,
+						met_expected_amount,
+					})
 					.unwrap_or_else(|e| {
 						log_error!(self.logger, "Failed to push to event queue: {}", e);
 						panic!("Failed to push to event queue");
 					});
 			},
-			LdkEvent::PaymentSent { payment_preimage, payment_hash, fee_paid_msat, .. } => {
+			LdkEvent::PaymentSent {
+				payment_id,
+				payment_preimage,
+				payment_hash,
+				fee_paid_msat,
+				..
+			} => {
+				self.payment_retrier.handle_payment_sent(&payment_hash);
+				self.router.unpin_first_hop(&payment_hash);
 				if let Some(mut payment) = self.payment_store.get(&payment_hash) {
 					payment.preimage = Some(payment_preimage);
 					payment.status = PaymentStatus::Succeeded;
@@ -612,6 +1419,44 @@ where
 						hex_utils::to_string(&payment_hash.0),
 						hex_utils::to_string(&payment_preimage.0)
 					);
+				} else if let Some(amount_msat) =
+					payment_id.and_then(|id| self.payment_store.take_pending_refund(&id))
+				{
+					// Payments for refunds we initiated are only known by their payment id
+					// beforehand, as their payment hash is chosen by the refund's recipient.
+					let payment = PaymentDetails {
+						preimage: Some(payment_preimage),
+						hash: payment_hash,
+						id: payment_id,
+						secret: None,
+						amount_msat: Some(amount_msat),
+						direction: PaymentDirection::Outbound,
+						status: PaymentStatus::Succeeded,
+						lsp_fee_limits: None,
+						kind: Some(PaymentKind::Bolt12Refund),
+						description_hash: None,
+						expected_amount_msat: None,
+						invoice_expiry_timestamp: None,
+						custom_tlvs: Vec::new(),
+					};
+					self.payment_store.insert(payment).unwrap_or_else(|e| {
+						log_error!(self.logger, "Failed to access payment store: {}", e);
+						panic!("Failed to access payment store");
+					});
+					log_info!(
+						self.logger,
+						"Successfully sent refund payment from payment hash {:?} with preimage {:?}",
+						hex_utils::to_string(&payment_hash.0),
+						hex_utils::to_string(&payment_preimage.0)
+					);
+				} else {
+					log_error!(
+						self.logger,
+						"Skipping unknown outbound payment with payment hash {:?} and id {:?}",
+						hex_utils::to_string(&payment_hash.0),
+						payment_id.map(|id| hex_utils::to_string(&id.0))
+					);
+					return;
 				}
 				self.event_queue
 					.add_event(Event::PaymentSuccessful { payment_hash, fee_paid_msat })
@@ -620,7 +1465,7 @@ where
 						panic!("Failed to push to event queue");
 					});
 			},
-			LdkEvent::PaymentFailed { payment_hash, reason, .. } => {
+			LdkEvent::PaymentFailed { payment_id, payment_hash, reason, .. } => {
 				log_info!(
 					self.logger,
 					"Failed to send payment to payment hash {:?} due to {:?}.",
@@ -628,6 +1473,13 @@ where
 					reason
 				);
 
+				if self.payment_retrier.handle_payment_failed(&payment_hash, reason) {
+					// We'll retry sending the payment, so it's not failed yet.
+					return;
+				}
+				self.router.unpin_first_hop(&payment_hash);
+				self.payment_store.take_pending_refund(&payment_id);
+
 				let update = PaymentDetailsUpdate {
 					status: Some(PaymentStatus::Failed),
 					..PaymentDetailsUpdate::new(payment_hash)
@@ -646,8 +1498,33 @@ where
 
 			LdkEvent::PaymentPathSuccessful { .. } => {},
 			LdkEvent::PaymentPathFailed { .. } => {},
-			LdkEvent::ProbeSuccessful { .. } => {},
-			LdkEvent::ProbeFailed { .. } => {},
+			LdkEvent::ProbeSuccessful { payment_hash, .. } => {
+				log_info!(
+					self.logger,
+					"Probe with payment hash {} succeeded",
+					hex_utils::to_string(&payment_hash.0)
+				);
+				self.event_queue.add_event(Event::ProbeSuccessful { payment_hash }).unwrap_or_else(
+					|e| {
+						log_error!(self.logger, "Failed to push to event queue: {}", e);
+						panic!("Failed to push to event queue");
+					},
+				);
+			},
+			LdkEvent::ProbeFailed { payment_hash, short_channel_id, .. } => {
+				log_info!(
+					self.logger,
+					"Probe with payment hash {} failed at channel {:?}",
+					hex_utils::to_string(&payment_hash.0),
+					short_channel_id
+				);
+				self.event_queue
+					.add_event(Event::ProbeFailed { payment_hash, short_channel_id })
+					.unwrap_or_else(|e| {
+						log_error!(self.logger, "Failed to push to event queue: {}", e);
+						panic!("Failed to push to event queue");
+					});
+			},
 			LdkEvent::HTLCHandlingFailed { .. } => {},
 			LdkEvent::PendingHTLCsForwardable { time_forwardable } => {
 				let forwarding_channel_manager = self.channel_manager.clone();
@@ -672,11 +1549,121 @@ where
 				temporary_channel_id,
 				counterparty_node_id,
 				funding_satoshis,
-				channel_type: _,
-				push_msat: _,
+				channel_type,
+				push_msat,
 			} => {
+				if channel_type.requires_anchors_zero_fee_htlc_tx() {
+					if let Some(anchor_channels_config) =
+						self.config.anchor_channels_config.as_ref()
+					{
+						if !anchor_channels_config
+							.trusted_peers_no_reserve
+							.contains(&counterparty_node_id)
+						{
+							let required_reserve_sats = total_anchor_channels_reserve_sats(
+								&self.channel_manager,
+								&self.config,
+							) + anchor_channels_config
+								.per_channel_reserve_sats;
+							let spendable_onchain_balance_sats = self
+								.wallet
+								.get_balance()
+								.map(|bal| bal.get_spendable())
+								.unwrap_or(0);
+							if spendable_onchain_balance_sats < required_reserve_sats {
+								log_error!(
+									self.logger,
+									"Rejecting inbound Anchor channel from peer {} due to insufficient available on-chain reserves.",
+									counterparty_node_id,
+								);
+								self.channel_manager
+									.force_close_without_broadcasting_txn(
+										&temporary_channel_id,
+										&counterparty_node_id,
+									)
+									.unwrap_or_else(|e| {
+										log_error!(self.logger, "Failed to reject channel: {:?}", e)
+									});
+								return;
+							}
+						}
+					} else {
+						log_error!(
+							self.logger,
+							"Rejecting inbound channel from peer {} due to Anchor channels being disabled.",
+							counterparty_node_id,
+						);
+						self.channel_manager
+							.force_close_without_broadcasting_txn(
+								&temporary_channel_id,
+								&counterparty_node_id,
+							)
+							.unwrap_or_else(|e| {
+								log_error!(self.logger, "Failed to reject channel: {:?}", e)
+							});
+						return;
+					}
+				}
+
+				let decision = match self.inbound_channel_policy.as_ref() {
+					Some(policy) => {
+						let is_persistent_peer = self
+							.peer_store
+							.get_peer(&counterparty_node_id)
+							.map_or(false, |peer| peer.is_persistent);
+						policy(&PendingChannelRequest {
+							counterparty_node_id,
+							funding_sats: funding_satoshis,
+							push_msat,
+							is_anchor_channel: channel_type.requires_anchors_zero_fee_htlc_tx(),
+							is_persistent_peer,
+						})
+					},
+					None if self.config.trusted_peers_0conf.contains(&counterparty_node_id) => {
+						ChannelDecision::AcceptTrustedZeroConf
+					},
+					None => ChannelDecision::Accept,
+				};
+				let allow_0conf = match decision {
+					ChannelDecision::Accept => false,
+					ChannelDecision::AcceptTrustedZeroConf => true,
+					ChannelDecision::Reject => {
+						log_info!(
+							self.logger,
+							"Rejecting inbound channel of {}sats from peer {} as per our inbound channel policy.",
+							funding_satoshis,
+							counterparty_node_id,
+						);
+						self.channel_manager
+							.force_close_without_broadcasting_txn(
+								&temporary_channel_id,
+								&counterparty_node_id,
+							)
+							.unwrap_or_else(|e| {
+								log_error!(self.logger, "Failed to reject channel: {:?}", e)
+							});
+						return;
+					},
+				};
+
+				if channel_type.requires_zero_conf() && !allow_0conf {
+					log_error!(
+						self.logger,
+						"Rejecting inbound 0conf channel from untrusted peer {}.",
+						counterparty_node_id,
+					);
+					self.channel_manager
+						.force_close_without_broadcasting_txn(
+							&temporary_channel_id,
+							&counterparty_node_id,
+						)
+						.unwrap_or_else(|e| {
+							log_error!(self.logger, "Failed to reject channel: {:?}", e)
+						});
+					return;
+				}
+
 				let user_channel_id: u128 = rand::thread_rng().gen::<u128>();
-				let allow_0conf = self.config.trusted_peers_0conf.contains(&counterparty_node_id);
 				let res = if allow_0conf {
 					self.channel_manager.accept_inbound_channel_from_trusted_peer_0conf(
 						&temporary_channel_id,
@@ -817,6 +1804,8 @@ where
 							let peer = PeerInfo {
 								node_id: counterparty_node_id,
 								address: address.clone(),
+								transport: PeerTransport::Tcp,
+								is_persistent: false,
 							};
 
 							self.peer_store.add_peer(peer).unwrap_or_else(|e| {
@@ -859,12 +1848,16 @@ where
 				..
 			} => {
 				log_info!(self.logger, "Channel {} closed due to: {}", channel_id, reason);
+				self.wallet.release_channel_funding(user_channel_id);
+				let force_close_reason =
+					self.force_close_reasons.lock().unwrap().remove(&user_channel_id);
 				self.event_queue
 					.add_event(Event::ChannelClosed {
 						channel_id,
 						user_channel_id: UserChannelId(user_channel_id),
 						counterparty_node_id,
 						reason: Some(reason),
+						force_close_reason,
 					})
 					.unwrap_or_else(|e| {
 						log_error!(self.logger, "Failed to push to event queue: {}", e);
@@ -873,8 +1866,59 @@ where
 			},
 			LdkEvent::DiscardFunding { .. } => {},
 			LdkEvent::HTLCIntercepted { .. } => {},
-			LdkEvent::BumpTransaction(_) => {},
-			LdkEvent::InvoiceRequestFailed { .. } => {},
+			LdkEvent::BumpTransaction(bte) => {
+				let (channel_id, counterparty_node_id) = match bte {
+					BumpTransactionEvent::ChannelClose {
+						ref channel_id,
+						ref counterparty_node_id,
+						..
+					} => (channel_id, counterparty_node_id),
+					BumpTransactionEvent::HTLCResolution {
+						ref channel_id,
+						ref counterparty_node_id,
+						..
+					} => (channel_id, counterparty_node_id),
+				};
+
+				if let Some(anchor_channels_config) = self.config.anchor_channels_config.as_ref() {
+					if anchor_channels_config
+						.trusted_peers_no_reserve
+						.contains(counterparty_node_id)
+					{
+						// We trust the counterparty to get the channel closing transactions
+						// confirmed on-chain.
+						log_info!(
+							self.logger,
+							"Ignoring BumpTransactionEvent for channel {} due to trusted counterparty {}",
+							channel_id,
+							counterparty_node_id
+						);
+						return;
+					}
+				}
+
+				if let BumpTransactionEvent::ChannelClose {
+					channel_id,
+					package_target_feerate_sat_per_1000_weight,
+					ref commitment_tx,
+					commitment_tx_fee_satoshis,
+					..
+				} = bte
+				{
+					self.check_anchor_bump_funds(
+						channel_id,
+						package_target_feerate_sat_per_1000_weight,
+						commitment_tx,
+						commitment_tx_fee_satoshis,
+					);
+				}
+
+				self.bump_tx_event_handler.handle_event(&bte);
+			},
+			LdkEvent::InvoiceRequestFailed { payment_id } => {
+				// We didn't receive an invoice for a refund we initiated before it expired.
+				self.payment_store.take_pending_refund(&payment_id);
+			},
 			LdkEvent::ConnectionNeeded { .. } => {},
 		}
 	}
@@ -924,6 +1968,217 @@ mod tests {
 		assert_eq!(event_queue.next_event(), None);
 	}
 
+	#[test]
+	fn event_timestamps_are_persisted() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let event_queue = EventQueue::new(Arc::clone(&store), Arc::clone(&logger));
+
+		let expected_event = Event::PaymentExpired { payment_hash: PaymentHash([42u8; 32]) };
+		event_queue.add_event(expected_event.clone()).unwrap();
+		let timestamped_event = event_queue.next_timestamped_event().unwrap();
+		assert_eq!(timestamped_event.event, expected_event);
+		assert!(timestamped_event.generated_at.is_some());
+
+		let persisted_bytes = store
+			.read(
+				EVENT_QUEUE_PERSISTENCE_PRIMARY_NAMESPACE,
+				EVENT_QUEUE_PERSISTENCE_SECONDARY_NAMESPACE,
+				EVENT_QUEUE_PERSISTENCE_KEY,
+			)
+			.unwrap();
+		let deser_event_queue =
+			EventQueue::read(&mut &persisted_bytes[..], (Arc::clone(&store), Arc::clone(&logger)))
+				.unwrap();
+		assert_eq!(deser_event_queue.wait_next_timestamped_event(), timestamped_event);
+
+		// Queues persisted without timestamps are still readable.
+		let mut legacy_bytes = Vec::new();
+		1u16.write(&mut legacy_bytes).unwrap();
+		expected_event.write(&mut legacy_bytes).unwrap();
+		let legacy_event_queue =
+			EventQueue::read(&mut &legacy_bytes[..], (Arc::clone(&store), logger)).unwrap();
+		let legacy_event = legacy_event_queue.next_timestamped_event().unwrap();
+		assert_eq!(legacy_event.event, expected_event);
+		assert_eq!(legacy_event.generated_at, None);
+	}
+
+	#[test]
+	fn peeking_events_does_not_consume_them() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let event_queue = EventQueue::new(Arc::clone(&store), Arc::clone(&logger));
+		assert!(event_queue.peek_events(10).is_empty());
+
+		let events = (0..3u128)
+			.map(|i| Event::ChannelReady {
+				channel_id: ChannelId([i as u8; 32]),
+				user_channel_id: UserChannelId(i),
+				counterparty_node_id: None,
+			})
+			.collect::<Vec<_>>();
+		for event in &events {
+			event_queue.add_event(event.clone()).unwrap();
+		}
+
+		assert_eq!(event_queue.peek_events(2), events[..2].to_vec());
+		assert_eq!(event_queue.peek_events(10), events);
+		assert_eq!(event_queue.next_event(), Some(events[0].clone()));
+
+		event_queue.event_handled().unwrap();
+		assert_eq!(event_queue.peek_events(10), events[1..].to_vec());
+	}
+
+	#[test]
+	fn filtered_events_are_handled_independently() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let event_queue = Arc::new(EventQueue::new(Arc::clone(&store), Arc::clone(&logger)));
+
+		let channel_ready = Event::ChannelReady {
+			channel_id: ChannelId([23u8; 32]),
+			user_channel_id: UserChannelId(2323),
+			counterparty_node_id: None,
+		};
+		let payment_expired = Event::PaymentExpired { payment_hash: PaymentHash([42u8; 32]) };
+		let is_payment_expired = |e: &Event| matches!(e, Event::PaymentExpired { .. });
+
+		assert_eq!(event_queue.next_event_matching(is_payment_expired), None);
+		let waiting_queue = Arc::clone(&event_queue);
+		let waiter =
+			std::thread::spawn(move || waiting_queue.wait_next_event_matching(is_payment_expired));
+
+		event_queue.add_event(channel_ready.clone()).unwrap();
+		event_queue.add_event(payment_expired.clone()).unwrap();
+		assert_eq!(waiter.join().unwrap(), payment_expired);
+
+		// Handling the matching event leaves the skipped one in place.
+		event_queue.matching_event_handled(&payment_expired).unwrap();
+		assert_eq!(event_queue.next_event_matching(is_payment_expired), None);
+		assert_eq!(event_queue.next_event(), Some(channel_ready.clone()));
+
+		// A reloaded queue sees the same state.
+		let persisted_bytes = store
+			.read(
+				EVENT_QUEUE_PERSISTENCE_PRIMARY_NAMESPACE,
+				EVENT_QUEUE_PERSISTENCE_SECONDARY_NAMESPACE,
+				EVENT_QUEUE_PERSISTENCE_KEY,
+			)
+			.unwrap();
+		let deser_event_queue =
+			EventQueue::read(&mut &persisted_bytes[..], (Arc::clone(&store), logger)).unwrap();
+		assert_eq!(deser_event_queue.peek_events(10), vec![channel_ready]);
+	}
+
+	#[test]
+	fn undeliverable_events_are_dead_lettered() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let mut event_queue = EventQueue::new(Arc::clone(&store), Arc::clone(&logger));
+		event_queue.set_max_redeliveries(Some(2));
+
+		let poison_event = Event::PaymentExpired { payment_hash: PaymentHash([42u8; 32]) };
+		let next_event = Event::PaymentExpired { payment_hash: PaymentHash([43u8; 32]) };
+		event_queue.add_event(poison_event.clone()).unwrap();
+		event_queue.add_event(next_event.clone()).unwrap();
+
+		// The initial delivery and two redeliveries.
+		for _ in 0..3 {
+			assert_eq!(event_queue.next_event(), Some(poison_event.clone()));
+		}
+		assert_eq!(event_queue.next_event(), Some(next_event.clone()));
+		assert_eq!(event_queue.peek_events(10), vec![next_event.clone()]);
+
+		let dead_letter_events = event_queue.list_dead_letter_events().unwrap();
+		assert_eq!(dead_letter_events.len(), 1);
+		assert_eq!(dead_letter_events[0].event, poison_event);
+		assert_eq!(dead_letter_events[0].delivery_attempts, 3);
+		assert!(dead_letter_events[0].generated_at.is_some());
+
+		// Replaying the event keeps its original timestamp.
+		event_queue.event_handled().unwrap();
+		event_queue.replay_dead_letter_event(&dead_letter_events[0].id).unwrap();
+		let replayed_event = event_queue.next_timestamped_event().unwrap();
+		assert_eq!(replayed_event.event, poison_event);
+		assert_eq!(replayed_event.generated_at, dead_letter_events[0].generated_at);
+		assert!(event_queue.list_dead_letter_events().unwrap().is_empty());
+		assert_eq!(
+			event_queue.replay_dead_letter_event(&dead_letter_events[0].id),
+			Err(Error::DeadLetterEventNotFound)
+		);
+	}
+
+	#[test]
+	fn delivery_counts_survive_restarts() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let mut event_queue = EventQueue::new(Arc::clone(&store), Arc::clone(&logger));
+		event_queue.set_max_redeliveries(Some(2));
+
+		let poison_event = Event::PaymentExpired { payment_hash: PaymentHash([42u8; 32]) };
+		event_queue.add_event(poison_event.clone()).unwrap();
+		for _ in 0..2 {
+			assert_eq!(event_queue.next_event(), Some(poison_event.clone()));
+		}
+
+		// Only the first delivery was persisted right away, the second one is persisted on
+		// shutdown.
+		let read_persisted_bytes = || {
+			store
+				.read(
+					EVENT_QUEUE_PERSISTENCE_PRIMARY_NAMESPACE,
+					EVENT_QUEUE_PERSISTENCE_SECONDARY_NAMESPACE,
+					EVENT_QUEUE_PERSISTENCE_KEY,
+				)
+				.unwrap()
+		};
+		let first_delivery_bytes = read_persisted_bytes();
+		event_queue.persist().unwrap();
+		assert_ne!(read_persisted_bytes(), first_delivery_bytes);
+
+		// The deliveries before the restart are still counted afterwards.
+		let persisted_bytes = read_persisted_bytes();
+		let mut deser_event_queue =
+			EventQueue::read(&mut &persisted_bytes[..], (Arc::clone(&store), Arc::clone(&logger)))
+				.unwrap();
+		deser_event_queue.set_max_redeliveries(Some(2));
+		assert_eq!(deser_event_queue.next_event(), Some(poison_event.clone()));
+		assert_eq!(deser_event_queue.next_event(), None);
+
+		let dead_letter_events = deser_event_queue.list_dead_letter_events().unwrap();
+		assert_eq!(dead_letter_events.len(), 1);
+		assert_eq!(dead_letter_events[0].delivery_attempts, 3);
+	}
+
+	#[test]
+	fn filtered_deliveries_are_counted() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let mut event_queue = EventQueue::new(Arc::clone(&store), Arc::clone(&logger));
+		event_queue.set_max_redeliveries(Some(1));
+
+		let channel_ready = Event::ChannelReady {
+			channel_id: ChannelId([23u8; 32]),
+			user_channel_id: UserChannelId(2323),
+			counterparty_node_id: None,
+		};
+		let poison_event = Event::PaymentExpired { payment_hash: PaymentHash([42u8; 32]) };
+		let is_payment_expired = |e: &Event| matches!(e, Event::PaymentExpired { .. });
+		event_queue.add_event(channel_ready.clone()).unwrap();
+		event_queue.add_event(poison_event.clone()).unwrap();
+
+		assert_eq!(event_queue.next_event_matching(is_payment_expired), Some(poison_event.clone()));
+		assert_eq!(event_queue.wait_next_event_matching(is_payment_expired), poison_event);
+		assert_eq!(event_queue.next_event_matching(is_payment_expired), None);
+
+		// Only the repeatedly delivered event was dead-lettered.
+		let dead_letter_events = event_queue.list_dead_letter_events().unwrap();
+		assert_eq!(dead_letter_events.len(), 1);
+		assert_eq!(dead_letter_events[0].event, poison_event);
+		assert_eq!(dead_letter_events[0].delivery_attempts, 2);
+		assert_eq!(event_queue.peek_events(10), vec![channel_ready]);
+	}
+
 	#[tokio::test]
 	async fn event_queue_concurrency() {
 		let store = Arc::new(TestStore::new(false));
@@ -1015,4 +2270,87 @@ mod tests {
 		rx.changed().await.unwrap();
 		assert_eq!(event_queue.next_event(), None);
 	}
+
+	#[cfg(feature = "event-stream")]
+	#[tokio::test]
+	async fn event_stream_acks_on_next_poll() {
+		use futures::StreamExt;
+
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let event_queue = Arc::new(EventQueue::new(Arc::clone(&store), Arc::clone(&logger)));
+
+		let first_event = Event::ChannelReady {
+			channel_id: ChannelId([23u8; 32]),
+			user_channel_id: UserChannelId(2323),
+			counterparty_node_id: None,
+		};
+		let second_event = Event::ChannelReady {
+			channel_id: ChannelId([42u8; 32]),
+			user_channel_id: UserChannelId(4242),
+			counterparty_node_id: None,
+		};
+		event_queue.add_event(first_event.clone()).unwrap();
+		event_queue.add_event(second_event.clone()).unwrap();
+
+		// Dropping the stream doesn't mark the in-flight event handled.
+		{
+			let mut stream = EventStream::new(Arc::clone(&event_queue));
+			assert_eq!(stream.next().await, Some(first_event.clone()));
+		}
+		assert_eq!(event_queue.next_event(), Some(first_event.clone()));
+
+		// Polling for the next event acks the previous one.
+		let mut stream = EventStream::new(Arc::clone(&event_queue));
+		assert_eq!(stream.next().await, Some(first_event.clone()));
+		assert_eq!(stream.next().await, Some(second_event.clone()));
+		assert_eq!(event_queue.next_event(), Some(second_event.clone()));
+
+		// The stream waits for new events to arrive.
+		tokio::select! {
+			_ = tokio::time::sleep(Duration::from_millis(100)) => {}
+			_ = stream.next() => {
+				panic!();
+			}
+		}
+		assert_eq!(event_queue.next_event(), None);
+
+		event_queue.add_event(first_event.clone()).unwrap();
+		assert_eq!(stream.next().await, Some(first_event));
+	}
+
+	#[cfg(feature = "event-stream")]
+	#[tokio::test]
+	async fn event_stream_only_counts_handed_out_events() {
+		use futures::StreamExt;
+
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let mut event_queue = EventQueue::new(Arc::clone(&store), Arc::clone(&logger));
+		event_queue.set_max_redeliveries(Some(1));
+		let event_queue = Arc::new(event_queue);
+
+		// Polling an empty queue doesn't count as a delivery.
+		let mut stream = EventStream::new(Arc::clone(&event_queue));
+		for _ in 0..3 {
+			tokio::select! {
+				_ = tokio::time::sleep(Duration::from_millis(10)) => {}
+				_ = stream.next() => {
+					panic!();
+				}
+			}
+		}
+
+		let poison_event = Event::PaymentExpired { payment_hash: PaymentHash([42u8; 32]) };
+		event_queue.add_event(poison_event.clone()).unwrap();
+		assert_eq!(stream.next().await, Some(poison_event.clone()));
+		drop(stream);
+
+		// The stream's delivery is counted along with the others.
+		assert_eq!(event_queue.next_event(), Some(poison_event.clone()));
+		assert_eq!(event_queue.next_event(), None);
+		let dead_letter_events = event_queue.list_dead_letter_events().unwrap();
+		assert_eq!(dead_letter_events.len(), 1);
+		assert_eq!(dead_letter_events[0].delivery_attempts, 2);
+	}
 }
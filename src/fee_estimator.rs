@@ -1,12 +1,16 @@
+use crate::chain::bitcoind_rpc::FeeRateEstimationMode;
+use crate::chain::ChainSource;
 use crate::logger::{log_error, log_trace, Logger};
 use crate::{Config, Error};
 
 use lightning::chain::chaininterface::{
 	ConfirmationTarget, FeeEstimator, FEERATE_FLOOR_SATS_PER_KW,
 };
+use lightning::io;
+use lightning::ln::msgs::DecodeError;
+use lightning::util::ser::{Readable, Writeable, Writer};
 
 use bdk::FeeRate;
-use esplora_client::AsyncClient as EsploraClient;
 
 use bitcoin::blockdata::weight::Weight;
 use bitcoin::Network;
@@ -20,7 +24,9 @@ where
 	L::Target: Logger,
 {
 	fee_rate_cache: RwLock<HashMap<ConfirmationTarget, FeeRate>>,
-	esplora_client: EsploraClient,
+	fee_rate_floors: HashMap<ConfirmationTarget, FeeRate>,
+	fee_rate_overrides: RwLock<HashMap<ConfirmationTarget, FeeRate>>,
+	chain_source: Arc<ChainSource>,
 	config: Arc<Config>,
 	logger: L,
 }
@@ -29,9 +35,35 @@ impl<L: Deref> OnchainFeeEstimator<L>
 where
 	L::Target: Logger,
 {
-	pub(crate) fn new(esplora_client: EsploraClient, config: Arc<Config>, logger: L) -> Self {
+	pub(crate) fn new(
+		chain_source: Arc<ChainSource>, fee_rate_floors: &HashMap<ConfirmationTarget, u32>,
+		config: Arc<Config>, logger: L,
+	) -> Self {
 		let fee_rate_cache = RwLock::new(HashMap::new());
-		Self { fee_rate_cache, esplora_client, config, logger }
+		let fee_rate_floors = fee_rate_floors
+			.iter()
+			.map(|(target, sat_per_kwu)| (*target, FeeRate::from_sat_per_kwu(*sat_per_kwu as f32)))
+			.collect();
+		let fee_rate_overrides = RwLock::new(HashMap::new());
+		Self { fee_rate_cache, fee_rate_floors, fee_rate_overrides, chain_source, config, logger }
+	}
+
+	/// Sets a fee rate that is used for the given target instead of any estimates or floors.
+	///
+	/// Passing `None` removes the override again.
+	pub(crate) fn set_fee_rate_override(
+		&self, confirmation_target: ConfirmationTarget, sat_per_kwu: Option<u32>,
+	) {
+		let mut locked_overrides = self.fee_rate_overrides.write().unwrap();
+		match sat_per_kwu {
+			Some(sat_per_kwu) => {
+				let fee_rate = FeeRate::from_sat_per_kwu(sat_per_kwu as f32);
+				locked_overrides.insert(confirmation_target, fee_rate);
+			},
+			None => {
+				locked_overrides.remove(&confirmation_target);
+			},
+		}
 	}
 
 	pub(crate) async fn update_fee_estimates(&self) -> Result<(), Error> {
@@ -53,38 +85,48 @@ where
 				ConfirmationTarget::ChannelCloseMinimum => 144,
 			};
 
-			let estimates = self.esplora_client.get_fee_estimates().await.map_err(|e| {
-				log_error!(
-					self.logger,
-					"Failed to retrieve fee rate estimates for {:?}: {}",
-					target,
-					e
-				);
-				Error::FeerateEstimationUpdateFailed
-			})?;
-
-			if estimates.is_empty() && self.config.network == Network::Bitcoin {
-				// Ensure we fail if we didn't receive any estimates.
-				log_error!(
-					self.logger,
-					"Failed to retrieve fee rate estimates for {:?}: empty fee estimates are dissallowed on Mainnet.",
-					target,
-				);
-				return Err(Error::FeerateEstimationUpdateFailed);
-			}
-
-			let converted_estimates = esplora_client::convert_fee_rate(num_blocks, estimates)
-				.map_err(|e| {
-					log_error!(
-						self.logger,
-						"Failed to convert fee rate estimates for {:?}: {}",
-						target,
-						e
-					);
-					Error::FeerateEstimationUpdateFailed
-				})?;
-
-			let fee_rate = FeeRate::from_sat_per_vb(converted_estimates);
+			let fee_rate = match &*self.chain_source {
+				ChainSource::Esplora { tx_sync } => {
+					self.get_esplora_fee_rate(tx_sync.client(), target, num_blocks).await?
+				},
+				#[cfg(feature = "cbf")]
+				ChainSource::Cbf { fee_oracle, .. } => {
+					self.get_esplora_fee_rate(fee_oracle, target, num_blocks).await?
+				},
+				ChainSource::BitcoindRpc { bitcoind_rpc_client, .. } => {
+					let estimation_mode = match target {
+						ConfirmationTarget::OnChainSweep => FeeRateEstimationMode::Conservative,
+						_ => FeeRateEstimationMode::Economical,
+					};
+					let estimate = bitcoind_rpc_client
+						.get_fee_estimate_for_target(num_blocks, estimation_mode)
+						.await
+						.map_err(|e| {
+							log_error!(
+								self.logger,
+								"Failed to retrieve fee rate estimate for {:?}: {}",
+								target,
+								e
+							);
+							Error::FeerateEstimationUpdateFailed
+						})?;
+					match estimate {
+						Some(fee_rate) => fee_rate,
+						None if self.config.network == Network::Bitcoin => {
+							log_error!(
+								self.logger,
+								"Failed to retrieve fee rate estimate for {:?}: bitcoind has insufficient data.",
+								target,
+							);
+							return Err(Error::FeerateEstimationUpdateFailed);
+						},
+						None => {
+							// Keep using the fallback rate, e.g., on regtest.
+							continue;
+						},
+					}
+				},
+			};
 
 			// LDK 0.0.118 introduced changes to the `ConfirmationTarget` semantics that
 			// require some post-estimation adjustments to the fee rates, which we do here.
@@ -109,6 +151,57 @@ where
 		Ok(())
 	}
 
+	async fn get_esplora_fee_rate(
+		&self, client: &esplora_client::AsyncClient, target: ConfirmationTarget, num_blocks: usize,
+	) -> Result<FeeRate, Error> {
+		let estimates = client.get_fee_estimates().await.map_err(|e| {
+			log_error!(
+				self.logger,
+				"Failed to retrieve fee rate estimates for {:?}: {}",
+				target,
+				e
+			);
+			Error::FeerateEstimationUpdateFailed
+		})?;
+
+		if estimates.is_empty() && self.config.network == Network::Bitcoin {
+			// Ensure we fail if we didn't receive any estimates.
+			log_error!(
+				self.logger,
+				"Failed to retrieve fee rate estimates for {:?}: empty fee estimates are dissallowed on Mainnet.",
+				target,
+			);
+			return Err(Error::FeerateEstimationUpdateFailed);
+		}
+
+		let converted_estimates =
+			esplora_client::convert_fee_rate(num_blocks, estimates).map_err(|e| {
+				log_error!(
+					self.logger,
+					"Failed to convert fee rate estimates for {:?}: {}",
+					target,
+					e
+				);
+				Error::FeerateEstimationUpdateFailed
+			})?;
+
+		Ok(FeeRate::from_sat_per_vb(converted_estimates))
+	}
+
+	/// Returns the current contents of the fee rate cache, e.g., to persist them.
+	pub(crate) fn cached_fee_rates(&self) -> HashMap<ConfirmationTarget, FeeRate> {
+		self.fee_rate_cache.read().unwrap().clone()
+	}
+
+	/// Populates the fee rate cache with previously persisted estimates, which will be used until
+	/// they are replaced by fresh estimates.
+	pub(crate) fn restore_cached_fee_rates(&self, fee_rates: HashMap<ConfirmationTarget, FeeRate>) {
+		let mut locked_fee_rate_cache = self.fee_rate_cache.write().unwrap();
+		for (target, fee_rate) in fee_rates {
+			locked_fee_rate_cache.entry(target).or_insert(fee_rate);
+		}
+	}
+
 	pub(crate) fn estimate_fee_rate(&self, confirmation_target: ConfirmationTarget) -> FeeRate {
 		let locked_fee_rate_cache = self.fee_rate_cache.read().unwrap();
 
@@ -124,7 +217,25 @@ where
 		// We'll fall back on this, if we really don't have any other information.
 		let fallback_rate = FeeRate::from_sat_per_kwu(fallback_sats_kwu as f32);
 
-		*locked_fee_rate_cache.get(&confirmation_target).unwrap_or(&fallback_rate)
+		let fee_rate = match self.fee_rate_overrides.read().unwrap().get(&confirmation_target) {
+			Some(override_rate) => *override_rate,
+			None => {
+				let estimated_rate =
+					*locked_fee_rate_cache.get(&confirmation_target).unwrap_or(&fallback_rate);
+				match self.fee_rate_floors.get(&confirmation_target) {
+					Some(floor_rate) if *floor_rate > estimated_rate => *floor_rate,
+					_ => estimated_rate,
+				}
+			},
+		};
+
+		// Never undercut the minimum relay fee rate, no matter what was configured.
+		let min_relay_rate = FeeRate::from_sat_per_kwu(FEERATE_FLOOR_SATS_PER_KW as f32);
+		if fee_rate < min_relay_rate {
+			min_relay_rate
+		} else {
+			fee_rate
+		}
 	}
 }
 
@@ -137,3 +248,125 @@ where
 			.max(FEERATE_FLOOR_SATS_PER_KW)
 	}
 }
+
+/// The most recent successful fee rate estimates, persisted so that we can start up with
+/// reasonable fee rates even if our fee rate source is temporarily unavailable.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PersistedFeeRateCache {
+	/// The time the estimates were retrieved, in seconds since the UNIX epoch.
+	pub(crate) update_timestamp: u64,
+	pub(crate) fee_rates: HashMap<ConfirmationTarget, FeeRate>,
+}
+
+fn confirmation_target_to_id(target: ConfirmationTarget) -> u8 {
+	match target {
+		ConfirmationTarget::OnChainSweep => 0,
+		ConfirmationTarget::MinAllowedAnchorChannelRemoteFee => 1,
+		ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee => 2,
+		ConfirmationTarget::AnchorChannelFee => 3,
+		ConfirmationTarget::NonAnchorChannelFee => 4,
+		ConfirmationTarget::ChannelCloseMinimum => 5,
+	}
+}
+
+fn confirmation_target_from_id(id: u8) -> Option<ConfirmationTarget> {
+	match id {
+		0 => Some(ConfirmationTarget::OnChainSweep),
+		1 => Some(ConfirmationTarget::MinAllowedAnchorChannelRemoteFee),
+		2 => Some(ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee),
+		3 => Some(ConfirmationTarget::AnchorChannelFee),
+		4 => Some(ConfirmationTarget::NonAnchorChannelFee),
+		5 => Some(ConfirmationTarget::ChannelCloseMinimum),
+		_ => None,
+	}
+}
+
+impl Writeable for PersistedFeeRateCache {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.update_timestamp.write(writer)?;
+		(self.fee_rates.len() as u16).write(writer)?;
+		for (target, fee_rate) in &self.fee_rates {
+			confirmation_target_to_id(*target).write(writer)?;
+			(fee_rate.fee_wu(Weight::from_wu(1000)) as u32).write(writer)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for PersistedFeeRateCache {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
+		let update_timestamp: u64 = Readable::read(reader)?;
+		let num_entries: u16 = Readable::read(reader)?;
+		let mut fee_rates = HashMap::with_capacity(num_entries as usize);
+		for _ in 0..num_entries {
+			let target_id: u8 = Readable::read(reader)?;
+			let sat_per_kwu: u32 = Readable::read(reader)?;
+			// Skip targets we don't know about, e.g., if they were written by a newer version.
+			if let Some(target) = confirmation_target_from_id(target_id) {
+				fee_rates.insert(target, FeeRate::from_sat_per_kwu(sat_per_kwu as f32));
+			}
+		}
+		Ok(Self { update_timestamp, fee_rates })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::io::test_utils::random_storage_path;
+	use crate::logger::{FilesystemLogger, LogFormat};
+
+	use lightning::util::logger::Level;
+	use lightning_transaction_sync::EsploraSyncClient;
+
+	#[test]
+	fn fee_rate_floors_and_overrides_are_applied() {
+		let log_dir = random_storage_path().to_str().unwrap().to_string();
+		let logger =
+			Arc::new(FilesystemLogger::new(log_dir, Level::Trace, LogFormat::Text).unwrap());
+		let tx_sync =
+			EsploraSyncClient::new("http://127.0.0.1:3002".to_string(), Arc::clone(&logger));
+		let chain_source = Arc::new(ChainSource::new_esplora(tx_sync));
+		let mut fee_rate_floors = HashMap::new();
+		fee_rate_floors.insert(ConfirmationTarget::OnChainSweep, 10_000);
+		fee_rate_floors.insert(ConfirmationTarget::AnchorChannelFee, 100);
+		let fee_estimator = OnchainFeeEstimator::new(
+			chain_source,
+			&fee_rate_floors,
+			Arc::new(Config::default()),
+			logger,
+		);
+
+		// Floors are applied on top of the (fallback) estimates.
+		let sweep_target = ConfirmationTarget::OnChainSweep;
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(sweep_target), 10_000);
+		assert_eq!(
+			fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::AnchorChannelFee),
+			500
+		);
+
+		// Overrides take precedence, but never undercut the minimum relay fee rate.
+		fee_estimator.set_fee_rate_override(sweep_target, Some(2_000));
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(sweep_target), 2_000);
+		fee_estimator.set_fee_rate_override(sweep_target, Some(1));
+		assert_eq!(
+			fee_estimator.estimate_fee_rate(sweep_target),
+			FeeRate::from_sat_per_kwu(FEERATE_FLOOR_SATS_PER_KW as f32)
+		);
+
+		fee_estimator.set_fee_rate_override(sweep_target, None);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(sweep_target), 10_000);
+	}
+
+	#[test]
+	fn persisted_fee_rate_cache_roundtrip() {
+		let mut fee_rates = HashMap::new();
+		fee_rates.insert(ConfirmationTarget::OnChainSweep, FeeRate::from_sat_per_kwu(5000.0));
+		fee_rates.insert(ConfirmationTarget::ChannelCloseMinimum, FeeRate::from_sat_per_kwu(253.0));
+		let fee_rate_cache = PersistedFeeRateCache { update_timestamp: 1_700_000_000, fee_rates };
+
+		let encoded = fee_rate_cache.encode();
+		let decoded = PersistedFeeRateCache::read(&mut &encoded[..]).unwrap();
+		assert_eq!(decoded, fee_rate_cache);
+	}
+}
@@ -1,4 +1,4 @@
-use crate::logger::{log_trace, FilesystemLogger, Logger};
+use crate::logger::{log_error, log_trace, FilesystemLogger, Logger};
 use crate::types::{GossipSync, NetworkGraph, P2PGossipSync, RapidGossipSync};
 use crate::Error;
 
@@ -87,4 +87,156 @@ impl GossipSource {
 			},
 		}
 	}
+
+	pub fn apply_rgs_snapshot(&self, snapshot: &[u8]) -> Result<u32, Error> {
+		match self {
+			Self::P2PNetwork { gossip_sync: _ } => Err(Error::GossipUpdateFailed),
+			Self::RapidGossipSync { gossip_sync, latest_sync_timestamp, logger, .. } => {
+				let snapshot_timestamp = rgs_snapshot_timestamp(snapshot).ok_or_else(|| {
+					log_error!(logger, "Failed to parse RGS snapshot header");
+					Error::GossipUpdateFailed
+				})?;
+
+				let cur_sync_timestamp = latest_sync_timestamp.load(Ordering::Acquire);
+				if snapshot_timestamp < cur_sync_timestamp {
+					log_error!(
+						logger,
+						"Refusing to apply RGS snapshot from {} as it's older than the latest applied snapshot from {}",
+						snapshot_timestamp,
+						cur_sync_timestamp
+					);
+					return Err(Error::RgsSnapshotOutdated);
+				}
+
+				let new_latest_sync_timestamp =
+					gossip_sync.update_network_graph(snapshot).map_err(|e| {
+						log_error!(logger, "Failed to apply RGS snapshot: {:?}", e);
+						Error::GossipUpdateFailed
+					})?;
+				latest_sync_timestamp.fetch_max(new_latest_sync_timestamp, Ordering::AcqRel);
+				Ok(new_latest_sync_timestamp)
+			},
+		}
+	}
+}
+
+// RGS snapshots start with the `LDK` prefix and a version byte, followed by the chain hash and the
+// timestamp the snapshot was generated at.
+const RGS_SNAPSHOT_PREFIX: [u8; 4] = [76, 68, 75, 1];
+const RGS_SNAPSHOT_TIMESTAMP_OFFSET: usize = 4 + 32;
+
+fn rgs_snapshot_timestamp(snapshot: &[u8]) -> Option<u32> {
+	if !snapshot.starts_with(&RGS_SNAPSHOT_PREFIX) {
+		return None;
+	}
+	let timestamp_bytes =
+		snapshot.get(RGS_SNAPSHOT_TIMESTAMP_OFFSET..RGS_SNAPSHOT_TIMESTAMP_OFFSET + 4)?;
+	Some(u32::from_be_bytes(timestamp_bytes.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::io::test_utils::random_storage_path;
+	use crate::logger::LogFormat;
+
+	use lightning::util::logger::Level;
+	use lightning::util::ser::Writeable;
+
+	use bitcoin::blockdata::constants::ChainHash;
+	use bitcoin::Network;
+
+	use std::time::{SystemTime, UNIX_EPOCH};
+
+	// Returns an RGS snapshot generated at the given timestamp which doesn't hold any gossip.
+	fn empty_rgs_snapshot(timestamp: u32) -> Vec<u8> {
+		let mut snapshot = RGS_SNAPSHOT_PREFIX.to_vec();
+		snapshot.extend_from_slice(&ChainHash::using_genesis_block(Network::Testnet).encode());
+		snapshot.extend_from_slice(&timestamp.to_be_bytes());
+		// The node id, channel announcement, and channel update counts.
+		snapshot.extend_from_slice(&[0u8; 12]);
+		// The default values of channel updates.
+		snapshot.extend_from_slice(&[0u8; 26]);
+		snapshot
+	}
+
+	fn test_rgs_source(latest_sync_timestamp: u32) -> GossipSource {
+		let log_dir = random_storage_path().to_str().unwrap().to_string();
+		let logger =
+			Arc::new(FilesystemLogger::new(log_dir, Level::Trace, LogFormat::Text).unwrap());
+		let network_graph = Arc::new(NetworkGraph::new(Network::Testnet, Arc::clone(&logger)));
+		let server_url = "http://127.0.0.1:8080".to_string();
+		GossipSource::new_rgs(server_url, latest_sync_timestamp, network_graph, logger)
+	}
+
+	fn latest_sync_timestamp(gossip_source: &GossipSource) -> u32 {
+		match gossip_source {
+			GossipSource::RapidGossipSync { latest_sync_timestamp, .. } => {
+				latest_sync_timestamp.load(Ordering::Acquire)
+			},
+			GossipSource::P2PNetwork { .. } => panic!("Unexpected gossip source"),
+		}
+	}
+
+	fn now_secs() -> u32 {
+		SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32
+	}
+
+	#[test]
+	fn rgs_snapshot_timestamp_is_parsed_from_header() {
+		let snapshot = empty_rgs_snapshot(1_700_000_000);
+		assert_eq!(rgs_snapshot_timestamp(&snapshot), Some(1_700_000_000));
+		assert_eq!(rgs_snapshot_timestamp(&snapshot[..40]), Some(1_700_000_000));
+
+		// Truncated headers are rejected.
+		assert_eq!(rgs_snapshot_timestamp(&snapshot[..39]), None);
+		assert_eq!(rgs_snapshot_timestamp(&[]), None);
+
+		// As are snapshots of an unknown version.
+		let mut unknown_version_snapshot = snapshot.clone();
+		unknown_version_snapshot[3] = 2;
+		assert_eq!(rgs_snapshot_timestamp(&unknown_version_snapshot), None);
+	}
+
+	#[test]
+	fn outdated_or_truncated_rgs_snapshots_are_rejected() {
+		let now = now_secs();
+		let gossip_source = test_rgs_source(now - 60);
+
+		let outdated_snapshot = empty_rgs_snapshot(now - 120);
+		assert_eq!(
+			gossip_source.apply_rgs_snapshot(&outdated_snapshot),
+			Err(Error::RgsSnapshotOutdated)
+		);
+
+		let snapshot = empty_rgs_snapshot(now);
+		assert_eq!(
+			gossip_source.apply_rgs_snapshot(&snapshot[..39]),
+			Err(Error::GossipUpdateFailed)
+		);
+		// A snapshot whose header is intact but whose contents are truncated.
+		assert_eq!(
+			gossip_source.apply_rgs_snapshot(&snapshot[..44]),
+			Err(Error::GossipUpdateFailed)
+		);
+
+		assert_eq!(latest_sync_timestamp(&gossip_source), now - 60);
+	}
+
+	#[test]
+	fn applied_rgs_snapshot_updates_latest_sync_timestamp() {
+		let now = now_secs();
+		let gossip_source = test_rgs_source(0);
+
+		let snapshot = empty_rgs_snapshot(now - 60);
+		assert_eq!(gossip_source.apply_rgs_snapshot(&snapshot), Ok(now - 60));
+		assert_eq!(latest_sync_timestamp(&gossip_source), now - 60);
+
+		// Snapshots as recent as the latest applied one are accepted.
+		assert_eq!(gossip_source.apply_rgs_snapshot(&snapshot), Ok(now - 60));
+
+		let snapshot = empty_rgs_snapshot(now);
+		assert_eq!(gossip_source.apply_rgs_snapshot(&snapshot), Ok(now));
+		assert_eq!(latest_sync_timestamp(&gossip_source), now);
+	}
 }
@@ -0,0 +1,184 @@
+//! Objects for querying the network graph.
+
+use lightning::ln::msgs::SocketAddress;
+use lightning::routing::gossip::{
+	ChannelInfo as LdkChannelInfo, ChannelUpdateInfo as LdkChannelUpdateInfo,
+	NodeAnnouncementInfo as LdkNodeAnnouncementInfo, NodeId, NodeInfo as LdkNodeInfo,
+	ReadOnlyNetworkGraph,
+};
+
+/// Statistics about the network graph as returned by [`Node::network_graph_stats`].
+///
+/// [`Node::network_graph_stats`]: crate::Node::network_graph_stats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkGraphStats {
+	/// The number of known nodes.
+	pub node_count: u64,
+	/// The number of known channels.
+	pub channel_count: u64,
+	/// The total capacity of all known channels whose capacity is known, in satoshis.
+	pub total_capacity_sats: u64,
+}
+
+impl From<&ReadOnlyNetworkGraph<'_>> for NetworkGraphStats {
+	fn from(value: &ReadOnlyNetworkGraph) -> Self {
+		let total_capacity_sats =
+			value.channels().unordered_iter().filter_map(|(_, c)| c.capacity_sats).sum();
+		Self {
+			node_count: value.nodes().len() as u64,
+			channel_count: value.channels().len() as u64,
+			total_capacity_sats,
+		}
+	}
+}
+
+/// Details about a channel in the network graph as returned by [`Node::lookup_channel`].
+///
+/// [`Node::lookup_channel`]: crate::Node::lookup_channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelInfo {
+	/// Source node of the first direction of the channel.
+	pub node_one: NodeId,
+	/// Details about the first direction of the channel.
+	pub one_to_two: Option<ChannelUpdateInfo>,
+	/// Source node of the second direction of the channel.
+	pub node_two: NodeId,
+	/// Details about the second direction of the channel.
+	pub two_to_one: Option<ChannelUpdateInfo>,
+	/// The channel capacity as seen on-chain, if the chain lookup was successful.
+	pub capacity_sats: Option<u64>,
+}
+
+impl From<&LdkChannelInfo> for ChannelInfo {
+	fn from(value: &LdkChannelInfo) -> Self {
+		Self {
+			node_one: value.node_one,
+			one_to_two: value.one_to_two.as_ref().map(|u| u.into()),
+			node_two: value.node_two,
+			two_to_one: value.two_to_one.as_ref().map(|u| u.into()),
+			capacity_sats: value.capacity_sats,
+		}
+	}
+}
+
+/// Details about one direction of a channel in the network graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelUpdateInfo {
+	/// When the last update to the channel direction was issued, as a UNIX timestamp.
+	pub last_update: u32,
+	/// Whether the channel can be currently used for payments (in this one direction).
+	pub enabled: bool,
+	/// The difference in CLTV values that you must have when routing through this channel.
+	pub cltv_expiry_delta: u16,
+	/// The minimum value, which must be relayed to the next hop via the channel.
+	pub htlc_minimum_msat: u64,
+	/// The maximum value which may be relayed to the next hop via the channel.
+	pub htlc_maximum_msat: u64,
+	/// The flat fee charged for relaying payments over this channel direction.
+	pub fee_base_msat: u32,
+	/// The fee charged per million msat relayed over this channel direction.
+	pub fee_proportional_millionths: u32,
+}
+
+impl From<&LdkChannelUpdateInfo> for ChannelUpdateInfo {
+	fn from(value: &LdkChannelUpdateInfo) -> Self {
+		Self {
+			last_update: value.last_update,
+			enabled: value.enabled,
+			cltv_expiry_delta: value.cltv_expiry_delta,
+			htlc_minimum_msat: value.htlc_minimum_msat,
+			htlc_maximum_msat: value.htlc_maximum_msat,
+			fee_base_msat: value.fees.base_msat,
+			fee_proportional_millionths: value.fees.proportional_millionths,
+		}
+	}
+}
+
+/// Details about a node in the network graph as returned by [`Node::lookup_node`].
+///
+/// [`Node::lookup_node`]: crate::Node::lookup_node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInfo {
+	/// The short channel IDs of all known channels of this node.
+	pub channels: Vec<u64>,
+	/// More information about the node from its latest node announcement, if we received one.
+	pub announcement_info: Option<NodeAnnouncementInfo>,
+}
+
+impl From<&LdkNodeInfo> for NodeInfo {
+	fn from(value: &LdkNodeInfo) -> Self {
+		Self {
+			channels: value.channels.clone(),
+			announcement_info: value.announcement_info.as_ref().map(|a| a.into()),
+		}
+	}
+}
+
+/// Information received in the latest node announcement of a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeAnnouncementInfo {
+	/// When the last known update to the node state was issued, as a UNIX timestamp.
+	pub last_update: u32,
+	/// The node's moniker.
+	pub alias: String,
+	/// The addresses the node announced to be reachable at.
+	pub addresses: Vec<SocketAddress>,
+}
+
+impl From<&LdkNodeAnnouncementInfo> for NodeAnnouncementInfo {
+	fn from(value: &LdkNodeAnnouncementInfo) -> Self {
+		Self {
+			last_update: value.last_update,
+			alias: value.alias.to_string(),
+			addresses: value.addresses().to_vec(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::io::test_utils::{
+		create_test_network_graph, test_node_ids, TEST_CHANNEL_HTLC_MAXIMUM_MSAT,
+	};
+
+	use lightning::util::test_utils::TestLogger;
+
+	use std::sync::Arc;
+
+	#[test]
+	fn network_graph_stats_and_lookups() {
+		let nodes = test_node_ids(4);
+		let channels = [(1, nodes[0], nodes[1]), (2, nodes[1], nodes[2])];
+		let network_graph = create_test_network_graph(&channels, Arc::new(TestLogger::new()));
+		let read_only_graph = network_graph.read_only();
+
+		// Only the nodes with channels are known, and channels of unknown capacity aren't
+		// included in the total capacity.
+		let expected_stats =
+			NetworkGraphStats { node_count: 3, channel_count: 2, total_capacity_sats: 0 };
+		assert_eq!(NetworkGraphStats::from(&read_only_graph), expected_stats);
+
+		let channel_info = ChannelInfo::from(read_only_graph.channel(1).unwrap());
+		assert_eq!(channel_info.node_one, NodeId::from_pubkey(&nodes[0]));
+		assert_eq!(channel_info.node_two, NodeId::from_pubkey(&nodes[1]));
+		assert_eq!(channel_info.two_to_one, None);
+		assert_eq!(channel_info.capacity_sats, None);
+		let update_info = channel_info.one_to_two.unwrap();
+		assert!(update_info.enabled);
+		assert_eq!(update_info.cltv_expiry_delta, 144);
+		assert_eq!(update_info.htlc_minimum_msat, 1_000);
+		assert_eq!(update_info.htlc_maximum_msat, TEST_CHANNEL_HTLC_MAXIMUM_MSAT);
+		assert_eq!(update_info.fee_base_msat, 1_000);
+		assert_eq!(update_info.fee_proportional_millionths, 100);
+		assert!(read_only_graph.channel(3).is_none());
+
+		let node_info =
+			NodeInfo::from(read_only_graph.node(&NodeId::from_pubkey(&nodes[1])).unwrap());
+		assert_eq!(node_info, NodeInfo { channels: vec![1, 2], announcement_info: None });
+		let node_info =
+			NodeInfo::from(read_only_graph.node(&NodeId::from_pubkey(&nodes[2])).unwrap());
+		assert_eq!(node_info.channels, vec![2]);
+		assert!(read_only_graph.node(&NodeId::from_pubkey(&nodes[3])).is_none());
+	}
+}
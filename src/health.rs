@@ -0,0 +1,171 @@
+//! Objects for reporting the health of a [`Node`].
+//!
+//! [`Node`]: crate::Node
+
+use crate::config::{
+	HEALTH_CHECK_CHAIN_LAG_FAIL_BLOCKS, HEALTH_CHECK_CHAIN_LAG_WARN_BLOCKS,
+	HEALTH_CHECK_STALENESS_MULTIPLIER, RGS_SYNC_INTERVAL,
+};
+
+/// The outcome of a single health check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthCheckState {
+	/// The check passed.
+	Ok,
+	/// The check passed, but something may require attention.
+	Warn,
+	/// The check failed.
+	Fail,
+}
+
+/// The result of a single health check, along with a human-readable explanation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+	/// The outcome of the check.
+	pub state: HealthCheckState,
+	/// A message describing the outcome.
+	pub message: String,
+}
+
+impl HealthCheck {
+	pub(crate) fn ok(message: impl Into<String>) -> Self {
+		Self { state: HealthCheckState::Ok, message: message.into() }
+	}
+
+	pub(crate) fn warn(message: impl Into<String>) -> Self {
+		Self { state: HealthCheckState::Warn, message: message.into() }
+	}
+
+	pub(crate) fn fail(message: impl Into<String>) -> Self {
+		Self { state: HealthCheckState::Fail, message: message.into() }
+	}
+}
+
+/// The health of a [`Node`], as returned by [`Node::health_check`].
+///
+/// [`Node`]: crate::Node
+/// [`Node::health_check`]: crate::Node::health_check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthStatus {
+	/// Whether our view of the chain is close to the chain tip reported by the chain source.
+	pub chain_sync: HealthCheck,
+	/// Whether our fee rate estimates were updated recently.
+	pub fee_estimates: HealthCheck,
+	/// Whether the [`KVStore`] can be written to and read from.
+	///
+	/// [`KVStore`]: lightning::util::persist::KVStore
+	pub kv_store: HealthCheck,
+	/// Whether we're connected to any peers.
+	pub peer_connectivity: HealthCheck,
+	/// Whether our Rapid Gossip Sync data is fresh, if Rapid Gossip Sync is used.
+	pub gossip: HealthCheck,
+}
+
+impl HealthStatus {
+	/// Returns the worst state of all checks.
+	pub fn overall_state(&self) -> HealthCheckState {
+		[
+			&self.chain_sync,
+			&self.fee_estimates,
+			&self.kv_store,
+			&self.peer_connectivity,
+			&self.gossip,
+		]
+		.iter()
+		.map(|check| check.state)
+		.max()
+		.unwrap_or(HealthCheckState::Ok)
+	}
+}
+
+pub(crate) fn check_chain_sync(best_block_height: u32, tip_height: u32) -> HealthCheck {
+	let lag = tip_height.saturating_sub(best_block_height);
+	let message = format!("{} blocks behind the chain tip at height {}", lag, tip_height);
+	if lag >= HEALTH_CHECK_CHAIN_LAG_FAIL_BLOCKS {
+		HealthCheck::fail(message)
+	} else if lag >= HEALTH_CHECK_CHAIN_LAG_WARN_BLOCKS {
+		HealthCheck::warn(message)
+	} else {
+		HealthCheck::ok(message)
+	}
+}
+
+// Checks whether an update that is expected every `interval_secs` happened recently enough.
+pub(crate) fn check_freshness(
+	what: &str, latest_update_timestamp: Option<u64>, interval_secs: u64, now_secs: u64,
+) -> HealthCheck {
+	match latest_update_timestamp {
+		Some(timestamp) => {
+			let age_secs = now_secs.saturating_sub(timestamp);
+			let message = format!("{} last updated {}s ago", what, age_secs);
+			if age_secs > interval_secs.saturating_mul(HEALTH_CHECK_STALENESS_MULTIPLIER) {
+				HealthCheck::warn(message)
+			} else {
+				HealthCheck::ok(message)
+			}
+		},
+		None => HealthCheck::fail(format!("{} were never updated", what)),
+	}
+}
+
+pub(crate) fn check_rgs_freshness(
+	latest_snapshot_timestamp: Option<u64>, now_secs: u64,
+) -> HealthCheck {
+	check_freshness(
+		"Rapid Gossip Sync snapshots",
+		latest_snapshot_timestamp,
+		RGS_SYNC_INTERVAL.as_secs(),
+		now_secs,
+	)
+}
+
+pub(crate) fn check_peer_connectivity(
+	num_connected_peers: usize, num_channel_peers: usize,
+) -> HealthCheck {
+	let message = format!("Connected to {} peers", num_connected_peers);
+	if num_connected_peers > 0 {
+		HealthCheck::ok(message)
+	} else if num_channel_peers > 0 {
+		HealthCheck::fail(message)
+	} else {
+		HealthCheck::warn(message)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn checks_are_graded() {
+		assert_eq!(check_chain_sync(100, 100).state, HealthCheckState::Ok);
+		assert_eq!(check_chain_sync(100, 102).state, HealthCheckState::Warn);
+		assert_eq!(check_chain_sync(100, 110).state, HealthCheckState::Fail);
+		// We may be ahead of a lagging chain source.
+		assert_eq!(check_chain_sync(101, 100).state, HealthCheckState::Ok);
+
+		assert_eq!(check_freshness("Fee rates", Some(900), 60, 1000).state, HealthCheckState::Ok);
+		assert_eq!(check_freshness("Fee rates", Some(0), 60, 1000).state, HealthCheckState::Warn);
+		assert_eq!(check_freshness("Fee rates", None, 60, 1000).state, HealthCheckState::Fail);
+
+		assert_eq!(check_peer_connectivity(1, 1).state, HealthCheckState::Ok);
+		assert_eq!(check_peer_connectivity(0, 0).state, HealthCheckState::Warn);
+		assert_eq!(check_peer_connectivity(0, 1).state, HealthCheckState::Fail);
+	}
+
+	#[test]
+	fn overall_state_is_worst_state() {
+		let mut status = HealthStatus {
+			chain_sync: HealthCheck::ok(""),
+			fee_estimates: HealthCheck::ok(""),
+			kv_store: HealthCheck::ok(""),
+			peer_connectivity: HealthCheck::ok(""),
+			gossip: HealthCheck::ok(""),
+		};
+		assert_eq!(status.overall_state(), HealthCheckState::Ok);
+		status.gossip = HealthCheck::warn("");
+		assert_eq!(status.overall_state(), HealthCheckState::Warn);
+		status.kv_store = HealthCheck::fail("");
+		assert_eq!(status.overall_state(), HealthCheckState::Fail);
+	}
+}
@@ -0,0 +1,378 @@
+use crate::config::{HELD_PAYMENT_AUTO_FAIL_SAFETY_MARGIN_BLOCKS, HELD_PAYMENT_PRUNE_DELAY_SECS};
+use crate::event::EventQueue;
+use crate::hex_utils;
+use crate::io::{
+	HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE, HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use crate::logger::{log_error, log_info, Logger};
+use crate::payment_store::{PaymentDetails, PaymentDetailsUpdate, PaymentStatus, PaymentStore};
+use crate::types::ChannelManager;
+use crate::{Error, Event};
+
+use lightning::impl_writeable_tlv_based;
+use lightning::ln::{PaymentHash, PaymentPreimage};
+use lightning::util::persist::KVStore;
+use lightning::util::ser::Writeable;
+
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::Hash;
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+/// An inbound payment whose HTLCs we hold until the user explicitly claims or fails them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct HeldPayment {
+	pub payment_hash: PaymentHash,
+	/// The amount we may claim, set once the payment's HTLCs arrived.
+	pub claimable_amount_msat: Option<u64>,
+	/// The block height at which LDK would fail the HTLCs back, set once they arrived.
+	pub claim_deadline: Option<u32>,
+}
+
+impl_writeable_tlv_based!(HeldPayment, {
+	(0, payment_hash, required),
+	(2, claimable_amount_msat, option),
+	(4, claim_deadline, option),
+});
+
+/// Keeps track of the payments received via hold invoices.
+///
+/// Rather than being claimed automatically, such payments are surfaced to the user via
+/// [`Event::PaymentClaimable`] and then claimed or failed manually. Held payments that are not
+/// settled in time are failed back before their HTLCs would expire.
+pub(crate) struct HoldInvoiceHandler<K: KVStore + Sync + Send, L: Deref>
+where
+	L::Target: Logger,
+{
+	held_payments: Mutex<HashMap<PaymentHash, HeldPayment>>,
+	channel_manager: Arc<ChannelManager<K>>,
+	payment_store: Arc<PaymentStore<K, L>>,
+	event_queue: Arc<EventQueue<K, L>>,
+	kv_store: Arc<K>,
+	logger: L,
+}
+
+impl<K: KVStore + Sync + Send + 'static, L: Deref> HoldInvoiceHandler<K, L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(
+		held_payments: Vec<HeldPayment>, channel_manager: Arc<ChannelManager<K>>,
+		payment_store: Arc<PaymentStore<K, L>>, event_queue: Arc<EventQueue<K, L>>,
+		kv_store: Arc<K>, logger: L,
+	) -> Self {
+		let held_payments = Mutex::new(
+			held_payments.into_iter().map(|payment| (payment.payment_hash, payment)).collect(),
+		);
+		Self { held_payments, channel_manager, payment_store, event_queue, kv_store, logger }
+	}
+
+	/// Registers a hold invoice's payment hash, so that payments to it won't be claimed
+	/// automatically.
+	pub(crate) fn register(&self, payment_hash: PaymentHash) -> Result<(), Error> {
+		let held_payment =
+			HeldPayment { payment_hash, claimable_amount_msat: None, claim_deadline: None };
+
+		let mut locked_held_payments = self.held_payments.lock().unwrap();
+		self.persist_held_payment(&held_payment)?;
+		locked_held_payments.insert(payment_hash, held_payment);
+		Ok(())
+	}
+
+	/// Handles the arrival of the HTLCs of a payment.
+	///
+	/// Returns `false` if the payment isn't held, i.e., needs to be handled by the caller.
+	pub(crate) fn handle_payment_claimable(
+		&self, payment_hash: PaymentHash, amount_msat: u64, claim_deadline: Option<u32>,
+	) -> bool {
+		let mut locked_held_payments = self.held_payments.lock().unwrap();
+		let held_payment = match locked_held_payments.get_mut(&payment_hash) {
+			Some(held_payment) => held_payment,
+			None => return false,
+		};
+
+		let is_failed = self
+			.payment_store
+			.get(&payment_hash)
+			.map_or(false, |payment| payment.status == PaymentStatus::Failed);
+		if is_failed {
+			log_info!(
+				self.logger,
+				"Refusing inbound payment with hash {} as the held payment was failed before",
+				hex_utils::to_string(&payment_hash.0),
+			);
+			self.channel_manager.fail_htlc_backwards(&payment_hash);
+			return true;
+		}
+
+		log_info!(
+			self.logger,
+			"Holding payment with hash {} of {}msat until it's claimed or failed",
+			hex_utils::to_string(&payment_hash.0),
+			amount_msat,
+		);
+		held_payment.claimable_amount_msat = Some(amount_msat);
+		held_payment.claim_deadline = claim_deadline;
+		let _ = self.persist_held_payment(held_payment);
+
+		self.event_queue
+			.add_event(Event::PaymentClaimable { payment_hash, amount_msat, claim_deadline })
+			.unwrap_or_else(|e| {
+				log_error!(self.logger, "Failed to push to event queue: {}", e);
+				panic!("Failed to push to event queue");
+			});
+		true
+	}
+
+	/// Claims the held payment with the given hash using the given preimage.
+	pub(crate) fn claim(
+		&self, payment_hash: &PaymentHash, payment_preimage: PaymentPreimage,
+	) -> Result<(), Error> {
+		if Sha256::hash(&payment_preimage.0).to_byte_array() != payment_hash.0 {
+			log_error!(
+				self.logger,
+				"Failed to claim payment with hash {}: preimage doesn't match",
+				hex_utils::to_string(&payment_hash.0)
+			);
+			return Err(Error::InvalidPaymentPreimage);
+		}
+
+		let locked_held_payments = self.held_payments.lock().unwrap();
+		match locked_held_payments.get(payment_hash) {
+			Some(held_payment) if held_payment.claimable_amount_msat.is_some() => {},
+			_ => {
+				log_error!(
+					self.logger,
+					"Failed to claim payment with hash {}: payment isn't claimable",
+					hex_utils::to_string(&payment_hash.0)
+				);
+				return Err(Error::PaymentNotClaimable);
+			},
+		}
+
+		// The held payment will be removed once we see the `PaymentClaimed` event.
+		self.channel_manager.claim_funds(payment_preimage);
+		Ok(())
+	}
+
+	/// Fails the held payment with the given hash back to the sender.
+	pub(crate) fn fail(&self, payment_hash: &PaymentHash) -> Result<(), Error> {
+		let mut locked_held_payments = self.held_payments.lock().unwrap();
+		match locked_held_payments.get_mut(payment_hash) {
+			Some(held_payment) if held_payment.claimable_amount_msat.is_some() => {
+				self.fail_held_payment(held_payment)
+			},
+			_ => {
+				log_error!(
+					self.logger,
+					"Failed to fail payment with hash {}: payment isn't claimable",
+					hex_utils::to_string(&payment_hash.0)
+				);
+				Err(Error::PaymentNotClaimable)
+			},
+		}
+	}
+
+	/// Stops tracking the given payment after it was claimed.
+	pub(crate) fn handle_payment_claimed(&self, payment_hash: &PaymentHash) {
+		if self.held_payments.lock().unwrap().remove(payment_hash).is_some() {
+			self.remove_held_payment(payment_hash);
+		}
+	}
+
+	/// Returns the hashes of the held payments whose HTLCs arrived and are awaiting a decision.
+	pub(crate) fn claimable_payment_hashes(&self) -> Vec<PaymentHash> {
+		let locked_held_payments = self.held_payments.lock().unwrap();
+		locked_held_payments
+			.values()
+			.filter(|held_payment| held_payment.claimable_amount_msat.is_some())
+			.map(|held_payment| held_payment.payment_hash)
+			.collect()
+	}
+
+	/// Fails back any held payments whose claim deadline is about to be reached at the given
+	/// block height, emitting an [`Event::HeldPaymentAutoFailed`] for each of them.
+	pub(crate) fn fail_expiring_payments(&self, cur_height: u32) {
+		let mut locked_held_payments = self.held_payments.lock().unwrap();
+		for held_payment in locked_held_payments.values_mut() {
+			let claim_deadline = match held_payment.claim_deadline {
+				Some(claim_deadline) if held_payment.claimable_amount_msat.is_some() => {
+					claim_deadline
+				},
+				_ => continue,
+			};
+			if cur_height + HELD_PAYMENT_AUTO_FAIL_SAFETY_MARGIN_BLOCKS < claim_deadline {
+				continue;
+			}
+
+			let payment_hash = held_payment.payment_hash;
+			log_error!(
+				self.logger,
+				"Failing held payment with hash {} as its claim deadline at height {} is reached",
+				hex_utils::to_string(&payment_hash.0),
+				claim_deadline
+			);
+			if self.fail_held_payment(held_payment).is_ok() {
+				self.event_queue
+					.add_event(Event::HeldPaymentAutoFailed { payment_hash, claim_deadline })
+					.unwrap_or_else(|e| {
+						log_error!(self.logger, "Failed to push to event queue: {}", e);
+					});
+			}
+		}
+	}
+
+	/// Stops tracking the held payments that were failed or never paid once their invoice expired,
+	/// as no further HTLCs paying to them will be accepted.
+	pub(crate) fn prune_resolved_payments(&self, now_secs: u64) {
+		let mut locked_held_payments = self.held_payments.lock().unwrap();
+		let resolved_hashes: Vec<PaymentHash> = locked_held_payments
+			.values()
+			.filter(|held_payment| {
+				let payment = self.payment_store.get(&held_payment.payment_hash);
+				is_resolved(held_payment, payment.as_ref(), now_secs)
+			})
+			.map(|held_payment| held_payment.payment_hash)
+			.collect();
+
+		for payment_hash in resolved_hashes {
+			locked_held_payments.remove(&payment_hash);
+			self.remove_held_payment(&payment_hash);
+		}
+	}
+
+	fn fail_held_payment(&self, held_payment: &mut HeldPayment) -> Result<(), Error> {
+		let payment_hash = held_payment.payment_hash;
+		self.channel_manager.fail_htlc_backwards(&payment_hash);
+
+		// We keep tracking the payment so that any further HTLCs paying to it are failed, too.
+		held_payment.claimable_amount_msat = None;
+		held_payment.claim_deadline = None;
+		self.persist_held_payment(held_payment)?;
+
+		let update = PaymentDetailsUpdate {
+			status: Some(PaymentStatus::Failed),
+			..PaymentDetailsUpdate::new(payment_hash)
+		};
+		self.payment_store.update(&update)?;
+		Ok(())
+	}
+
+	fn persist_held_payment(&self, held_payment: &HeldPayment) -> Result<(), Error> {
+		let store_key = hex_utils::to_string(&held_payment.payment_hash.0);
+		let data = held_payment.encode();
+		self.kv_store
+			.write(
+				HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+				HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				&data,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+					HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})
+	}
+
+	fn remove_held_payment(&self, payment_hash: &PaymentHash) {
+		let store_key = hex_utils::to_string(&payment_hash.0);
+		if let Err(e) = self.kv_store.remove(
+			HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+			HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+			&store_key,
+			false,
+		) {
+			log_error!(
+				self.logger,
+				"Removing held payment data for key {}/{}/{} failed due to: {}",
+				HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+				HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+				store_key,
+				e
+			);
+		}
+	}
+}
+
+fn is_resolved(
+	held_payment: &HeldPayment, payment: Option<&PaymentDetails>, now_secs: u64,
+) -> bool {
+	if held_payment.claimable_amount_msat.is_some() {
+		// The payment's HTLCs arrived and await a decision.
+		return false;
+	}
+	match payment {
+		Some(payment) => match payment.invoice_expiry_timestamp {
+			Some(expiry_timestamp) => {
+				expiry_timestamp.saturating_add(HELD_PAYMENT_PRUNE_DELAY_SECS) <= now_secs
+			},
+			None => payment.status != PaymentStatus::Pending,
+		},
+		None => true,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::payment_store::PaymentDirection;
+	use lightning::util::ser::Readable;
+
+	#[test]
+	fn held_payment_serialization_roundtrip() {
+		let held_payment = HeldPayment {
+			payment_hash: PaymentHash([42; 32]),
+			claimable_amount_msat: Some(100_000),
+			claim_deadline: Some(850_000),
+		};
+
+		let encoded = held_payment.encode();
+		let decoded = HeldPayment::read(&mut &encoded[..]).unwrap();
+		assert_eq!(held_payment, decoded);
+	}
+
+	#[test]
+	fn held_payments_are_resolved_after_invoice_expiry() {
+		let payment_hash = PaymentHash([42; 32]);
+		let mut held_payment =
+			HeldPayment { payment_hash, claimable_amount_msat: None, claim_deadline: None };
+		let expiry_timestamp = 1_700_000_000;
+		let payment = PaymentDetails {
+			hash: payment_hash,
+			id: None,
+			preimage: None,
+			secret: None,
+			amount_msat: Some(100_000),
+			direction: PaymentDirection::Inbound,
+			status: PaymentStatus::Failed,
+			lsp_fee_limits: None,
+			kind: None,
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: Some(expiry_timestamp),
+			custom_tlvs: Vec::new(),
+		};
+
+		// Failed or unpaid payments are kept until their invoice expired for long enough.
+		let prune_timestamp = expiry_timestamp + HELD_PAYMENT_PRUNE_DELAY_SECS;
+		assert!(!is_resolved(&held_payment, Some(&payment), expiry_timestamp));
+		assert!(!is_resolved(&held_payment, Some(&payment), prune_timestamp - 1));
+		assert!(is_resolved(&held_payment, Some(&payment), prune_timestamp));
+		assert!(is_resolved(&held_payment, None, expiry_timestamp));
+
+		// Payments awaiting a decision are never resolved.
+		held_payment.claimable_amount_msat = Some(100_000);
+		held_payment.claim_deadline = Some(850_000);
+		assert!(!is_resolved(&held_payment, Some(&payment), prune_timestamp));
+		assert!(!is_resolved(&held_payment, None, prune_timestamp));
+	}
+}
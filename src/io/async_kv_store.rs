@@ -0,0 +1,345 @@
+//! Objects related to [`AsyncKVStore`] live here.
+use crate::io::batch_kv_store::BatchKVStore;
+use crate::io::utils::check_namespace_key_validity;
+use crate::io::{
+	PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE, PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use crate::logger::{log_error, Logger};
+use crate::PaymentDetails;
+
+use lightning::routing::gossip::NetworkGraph;
+use lightning::routing::scoring::{ProbabilisticScorer, ProbabilisticScoringDecayParameters};
+use lightning::util::persist::{
+	KVStore, NETWORK_GRAPH_PERSISTENCE_KEY, NETWORK_GRAPH_PERSISTENCE_PRIMARY_NAMESPACE,
+	NETWORK_GRAPH_PERSISTENCE_SECONDARY_NAMESPACE, SCORER_PERSISTENCE_KEY,
+	SCORER_PERSISTENCE_PRIMARY_NAMESPACE, SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use lightning::util::ser::{Readable, ReadableArgs};
+
+use tokio::runtime::Runtime;
+
+use std::future::Future;
+use std::io::Cursor;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The future returned by the methods of [`AsyncKVStore`].
+pub type AsyncKVStoreFuture<'a, T> =
+	Pin<Box<dyn Future<Output = Result<T, std::io::Error>> + Send + 'a>>;
+
+/// An asynchronous variant of LDK's [`KVStore`] interface.
+///
+/// This allows to implement storage backends that are accessed over the network (e.g., remote
+/// databases or object storage) without blocking the runtime on every operation.
+///
+/// The semantics of each method exactly mirror the ones of the corresponding [`KVStore`] method.
+/// In particular, implementations must validate the given namespaces and keys and return an error
+/// of kind [`std::io::ErrorKind::NotFound`] on reads of unknown keys.
+pub trait AsyncKVStore {
+	/// Returns the data stored for the given `primary_namespace`, `secondary_namespace`, and
+	/// `key`.
+	///
+	/// See [`KVStore::read`] for more details.
+	fn read<'a>(
+		&'a self, primary_namespace: &'a str, secondary_namespace: &'a str, key: &'a str,
+	) -> AsyncKVStoreFuture<'a, Vec<u8>>;
+
+	/// Persists the given data under the given `key`.
+	///
+	/// See [`KVStore::write`] for more details.
+	fn write<'a>(
+		&'a self, primary_namespace: &'a str, secondary_namespace: &'a str, key: &'a str,
+		buf: &'a [u8],
+	) -> AsyncKVStoreFuture<'a, ()>;
+
+	/// Removes any data that had previously been persisted under the given `key`.
+	///
+	/// See [`KVStore::remove`] for more details.
+	fn remove<'a>(
+		&'a self, primary_namespace: &'a str, secondary_namespace: &'a str, key: &'a str,
+		lazy: bool,
+	) -> AsyncKVStoreFuture<'a, ()>;
+
+	/// Returns a list of keys that are stored under the given `secondary_namespace` in
+	/// `primary_namespace`.
+	///
+	/// See [`KVStore::list`] for more details.
+	fn list<'a>(
+		&'a self, primary_namespace: &'a str, secondary_namespace: &'a str,
+	) -> AsyncKVStoreFuture<'a, Vec<String>>;
+}
+
+/// Wraps an [`AsyncKVStore`] to provide a [`KVStore`] implementation, allowing it to back a
+/// [`Node`].
+///
+/// The operations of the inner store are driven on a dedicated runtime. Any data that is read or
+/// written by `ldk-node` itself may be accessed asynchronously via [`AsyncKVStoreAdapter::inner`],
+/// while LDK's background persistence, which requires a synchronous [`KVStore`], uses the
+/// adapter.
+///
+/// [`Node`]: crate::Node
+pub struct AsyncKVStoreAdapter<A: AsyncKVStore + Sync + Send> {
+	inner: Arc<A>,
+	runtime: Runtime,
+}
+
+impl<A: AsyncKVStore + Sync + Send> AsyncKVStoreAdapter<A> {
+	/// Constructs a new [`AsyncKVStoreAdapter`] wrapping the given store.
+	pub fn new(inner: Arc<A>) -> std::io::Result<Self> {
+		let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+		Ok(Self { inner, runtime })
+	}
+
+	/// Returns the wrapped [`AsyncKVStore`].
+	pub fn inner(&self) -> Arc<A> {
+		Arc::clone(&self.inner)
+	}
+
+	/// Drives the given future to completion on the adapter's runtime.
+	pub(crate) fn block_on<F: Future>(&self, future: F) -> F::Output {
+		tokio::task::block_in_place(|| self.runtime.block_on(future))
+	}
+}
+
+impl<A: AsyncKVStore + Sync + Send> KVStore for AsyncKVStoreAdapter<A> {
+	fn read(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str,
+	) -> std::io::Result<Vec<u8>> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "read")?;
+		self.block_on(self.inner.read(primary_namespace, secondary_namespace, key))
+	}
+
+	fn write(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, buf: &[u8],
+	) -> std::io::Result<()> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "write")?;
+		self.block_on(self.inner.write(primary_namespace, secondary_namespace, key, buf))
+	}
+
+	fn remove(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, lazy: bool,
+	) -> std::io::Result<()> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "remove")?;
+		self.block_on(self.inner.remove(primary_namespace, secondary_namespace, key, lazy))
+	}
+
+	fn list(
+		&self, primary_namespace: &str, secondary_namespace: &str,
+	) -> std::io::Result<Vec<String>> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, None, "list")?;
+		self.block_on(self.inner.list(primary_namespace, secondary_namespace))
+	}
+}
+
+impl<A: AsyncKVStore + Sync + Send> BatchKVStore for AsyncKVStoreAdapter<A> {}
+
+/// Read a previously persisted [`NetworkGraph`] from the given [`AsyncKVStore`].
+pub async fn read_network_graph_async<A: AsyncKVStore + Sync + Send, L: Deref + Clone>(
+	kv_store: Arc<A>, logger: L,
+) -> Result<NetworkGraph<L>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(
+		kv_store
+			.read(
+				NETWORK_GRAPH_PERSISTENCE_PRIMARY_NAMESPACE,
+				NETWORK_GRAPH_PERSISTENCE_SECONDARY_NAMESPACE,
+				NETWORK_GRAPH_PERSISTENCE_KEY,
+			)
+			.await?,
+	);
+	NetworkGraph::read(&mut reader, logger.clone()).map_err(|e| {
+		log_error!(logger, "Failed to deserialize NetworkGraph: {}", e);
+		std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to deserialize NetworkGraph")
+	})
+}
+
+/// Read a previously persisted [`ProbabilisticScorer`] from the given [`AsyncKVStore`].
+pub async fn read_scorer_async<
+	A: AsyncKVStore + Send + Sync,
+	G: Deref<Target = NetworkGraph<L>>,
+	L: Deref + Clone,
+>(
+	kv_store: Arc<A>, params: ProbabilisticScoringDecayParameters, network_graph: G, logger: L,
+) -> Result<ProbabilisticScorer<G, L>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(
+		kv_store
+			.read(
+				SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
+				SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+				SCORER_PERSISTENCE_KEY,
+			)
+			.await?,
+	);
+	let args = (params, network_graph, logger.clone());
+	ProbabilisticScorer::read(&mut reader, args).map_err(|e| {
+		log_error!(logger, "Failed to deserialize scorer: {}", e);
+		std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to deserialize Scorer")
+	})
+}
+
+/// Read previously persisted payments information from the given [`AsyncKVStore`].
+///
+/// If `skip_corrupt` is set, entries that fail to deserialize are skipped rather than failing the
+/// whole read, and their keys are returned alongside the successfully read payments.
+pub async fn read_payments_async<A: AsyncKVStore + Sync + Send, L: Deref>(
+	kv_store: Arc<A>, skip_corrupt: bool, logger: L,
+) -> Result<(Vec<PaymentDetails>, Vec<String>), std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = Vec::new();
+	let mut skipped_keys = Vec::new();
+
+	for stored_key in kv_store
+		.list(
+			PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+			PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+		)
+		.await?
+	{
+		let mut reader = Cursor::new(
+			kv_store
+				.read(
+					PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+					PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+					&stored_key,
+				)
+				.await?,
+		);
+		match PaymentDetails::read(&mut reader) {
+			Ok(payment) => res.push(payment),
+			Err(e) if skip_corrupt => {
+				log_error!(
+					logger,
+					"Skipping corrupt PaymentDetails stored under key {}: {}",
+					stored_key,
+					e
+				);
+				skipped_keys.push(stored_key);
+			},
+			Err(e) => {
+				log_error!(logger, "Failed to deserialize PaymentDetails: {}", e);
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::InvalidData,
+					"Failed to deserialize PaymentDetails",
+				));
+			},
+		}
+	}
+	Ok((res, skipped_keys))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::io::test_utils::do_read_write_remove_list_persist;
+	use lightning::util::test_utils::TestStore;
+
+	struct TestAsyncStore {
+		inner: TestStore,
+	}
+
+	impl AsyncKVStore for TestAsyncStore {
+		fn read<'a>(
+			&'a self, primary_namespace: &'a str, secondary_namespace: &'a str, key: &'a str,
+		) -> AsyncKVStoreFuture<'a, Vec<u8>> {
+			Box::pin(async move { self.inner.read(primary_namespace, secondary_namespace, key) })
+		}
+
+		fn write<'a>(
+			&'a self, primary_namespace: &'a str, secondary_namespace: &'a str, key: &'a str,
+			buf: &'a [u8],
+		) -> AsyncKVStoreFuture<'a, ()> {
+			Box::pin(
+				async move { self.inner.write(primary_namespace, secondary_namespace, key, buf) },
+			)
+		}
+
+		fn remove<'a>(
+			&'a self, primary_namespace: &'a str, secondary_namespace: &'a str, key: &'a str,
+			lazy: bool,
+		) -> AsyncKVStoreFuture<'a, ()> {
+			Box::pin(
+				async move { self.inner.remove(primary_namespace, secondary_namespace, key, lazy) },
+			)
+		}
+
+		fn list<'a>(
+			&'a self, primary_namespace: &'a str, secondary_namespace: &'a str,
+		) -> AsyncKVStoreFuture<'a, Vec<String>> {
+			Box::pin(async move { self.inner.list(primary_namespace, secondary_namespace) })
+		}
+	}
+
+	#[test]
+	fn read_write_remove_list_persist() {
+		let async_store = Arc::new(TestAsyncStore { inner: TestStore::new(false) });
+		let store = AsyncKVStoreAdapter::new(async_store).unwrap();
+
+		do_read_write_remove_list_persist(&store);
+	}
+
+	#[test]
+	fn read_payments_async_skips_corrupt_entries() {
+		use crate::payment_store::{PaymentDirection, PaymentKind, PaymentStatus};
+		use lightning::ln::PaymentHash;
+		use lightning::util::ser::Writeable;
+		use lightning::util::test_utils::TestLogger;
+
+		let store = Arc::new(TestAsyncStore { inner: TestStore::new(false) });
+		let logger = Arc::new(TestLogger::new());
+
+		let payment = PaymentDetails {
+			hash: PaymentHash([42u8; 32]),
+			id: None,
+			preimage: None,
+			secret: None,
+			amount_msat: None,
+			direction: PaymentDirection::Inbound,
+			status: PaymentStatus::Pending,
+			lsp_fee_limits: None,
+			kind: Some(PaymentKind::Bolt11),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: None,
+			custom_tlvs: Vec::new(),
+		};
+		let valid_key = crate::hex_utils::to_string(&payment.hash.0);
+		let corrupt_key = crate::hex_utils::to_string(&[23u8; 32]);
+		let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+		runtime.block_on(async {
+			store
+				.write(
+					PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+					PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+					&valid_key,
+					&payment.encode(),
+				)
+				.await
+				.unwrap();
+			store
+				.write(
+					PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+					PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+					&corrupt_key,
+					&[1, 2, 3],
+				)
+				.await
+				.unwrap();
+
+			assert!(read_payments_async(Arc::clone(&store), false, Arc::clone(&logger))
+				.await
+				.is_err());
+			assert_eq!(
+				read_payments_async(Arc::clone(&store), true, Arc::clone(&logger)).await.unwrap(),
+				(vec![payment], vec![corrupt_key])
+			);
+		});
+	}
+}
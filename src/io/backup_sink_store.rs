@@ -0,0 +1,164 @@
+//! Objects related to [`BackupSinkKVStore`] live here.
+use crate::io::batch_kv_store::BatchKVStore;
+use crate::logger::{log_error, FilesystemLogger, Logger};
+
+use lightning::util::persist::KVStore;
+
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A sink notified about every change committed to a [`KVStore`], e.g., to stream the changes
+/// to a remote backup location.
+///
+/// The methods are invoked synchronously after the underlying store successfully committed the
+/// respective operation. As the change is already committed at that point, returning an error
+/// won't revert it, but the error will be logged.
+pub trait BackupSink: Send + Sync {
+	/// Called after the given value was written under the given namespaces and key.
+	fn on_write(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, buf: &[u8],
+	) -> std::io::Result<()>;
+
+	/// Called after the given key was removed, i.e., the sink should record a tombstone.
+	fn on_remove(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str,
+	) -> std::io::Result<()>;
+}
+
+/// A [`KVStore`] decorator forwarding all successful writes and removals of the wrapped store to
+/// a [`BackupSink`].
+pub struct BackupSinkKVStore<K: Deref>
+where
+	K::Target: KVStore,
+{
+	inner: K,
+	sink: Arc<dyn BackupSink>,
+	logger: Arc<FilesystemLogger>,
+}
+
+impl<K: Deref> BackupSinkKVStore<K>
+where
+	K::Target: KVStore,
+{
+	pub(crate) fn new(inner: K, sink: Arc<dyn BackupSink>, logger: Arc<FilesystemLogger>) -> Self {
+		Self { inner, sink, logger }
+	}
+}
+
+impl<K: Deref> KVStore for BackupSinkKVStore<K>
+where
+	K::Target: KVStore,
+{
+	fn read(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str,
+	) -> std::io::Result<Vec<u8>> {
+		self.inner.read(primary_namespace, secondary_namespace, key)
+	}
+
+	fn write(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, buf: &[u8],
+	) -> std::io::Result<()> {
+		self.inner.write(primary_namespace, secondary_namespace, key, buf)?;
+
+		self.sink.on_write(primary_namespace, secondary_namespace, key, buf).unwrap_or_else(|e| {
+			log_error!(
+				self.logger,
+				"Backup sink failed to record write of {}/{}/{}: {}",
+				primary_namespace,
+				secondary_namespace,
+				key,
+				e
+			);
+		});
+		Ok(())
+	}
+
+	fn remove(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, lazy: bool,
+	) -> std::io::Result<()> {
+		self.inner.remove(primary_namespace, secondary_namespace, key, lazy)?;
+
+		self.sink.on_remove(primary_namespace, secondary_namespace, key).unwrap_or_else(|e| {
+			log_error!(
+				self.logger,
+				"Backup sink failed to record removal of {}/{}/{}: {}",
+				primary_namespace,
+				secondary_namespace,
+				key,
+				e
+			);
+		});
+		Ok(())
+	}
+
+	fn list(
+		&self, primary_namespace: &str, secondary_namespace: &str,
+	) -> std::io::Result<Vec<String>> {
+		self.inner.list(primary_namespace, secondary_namespace)
+	}
+}
+
+impl<K: Deref> BatchKVStore for BackupSinkKVStore<K> where K::Target: KVStore {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::io::test_utils::random_storage_path;
+	use crate::logger::LogFormat;
+	use lightning::util::logger::Level;
+	use lightning::util::test_utils::TestStore;
+	use std::sync::Mutex;
+
+	#[derive(Default)]
+	struct RecordingSink {
+		changes: Mutex<Vec<(String, Option<Vec<u8>>)>>,
+	}
+
+	impl BackupSink for RecordingSink {
+		fn on_write(&self, p: &str, s: &str, k: &str, buf: &[u8]) -> std::io::Result<()> {
+			self.changes.lock().unwrap().push((format!("{}/{}/{}", p, s, k), Some(buf.to_vec())));
+			Ok(())
+		}
+
+		fn on_remove(&self, p: &str, s: &str, k: &str) -> std::io::Result<()> {
+			self.changes.lock().unwrap().push((format!("{}/{}/{}", p, s, k), None));
+			Err(std::io::Error::new(std::io::ErrorKind::Other, "sink failure"))
+		}
+	}
+
+	#[test]
+	fn committed_changes_are_forwarded_to_sink() {
+		let log_dir = random_storage_path().to_str().unwrap().to_string();
+		let logger =
+			Arc::new(FilesystemLogger::new(log_dir, Level::Trace, LogFormat::Text).unwrap());
+		let sink = Arc::new(RecordingSink::default());
+		let store = BackupSinkKVStore::new(
+			Arc::new(TestStore::new(false)),
+			Arc::clone(&sink) as Arc<_>,
+			logger,
+		);
+
+		store.write("primary", "secondary", "key", &[42u8]).unwrap();
+		assert_eq!(store.read("primary", "secondary", "key").unwrap(), vec![42u8]);
+
+		// Sink errors don't fail the already committed operation.
+		store.remove("primary", "secondary", "key", false).unwrap();
+		assert!(store.read("primary", "secondary", "key").is_err());
+
+		// Failed writes aren't forwarded.
+		let read_only_store = BackupSinkKVStore::new(
+			Arc::new(TestStore::new(true)),
+			Arc::clone(&sink) as Arc<_>,
+			Arc::clone(&store.logger),
+		);
+		assert!(read_only_store.write("primary", "secondary", "key", &[23u8]).is_err());
+
+		assert_eq!(
+			*sink.changes.lock().unwrap(),
+			vec![
+				("primary/secondary/key".to_string(), Some(vec![42u8])),
+				("primary/secondary/key".to_string(), None),
+			]
+		);
+	}
+}
@@ -0,0 +1,70 @@
+//! Objects related to [`BatchKVStore`] live here.
+use lightning::util::persist::KVStore;
+use lightning_persister::fs_store::FilesystemStore;
+
+/// An extension of LDK's [`KVStore`] interface allowing to write or remove several entries of a
+/// namespace at once.
+///
+/// The default implementations simply write or remove the entries one after another and return
+/// on the first error, in which case the preceding entries will have been written or removed
+/// already. Stores able to do better, e.g., by using a single database transaction, should
+/// override them so that batches are applied atomically.
+///
+/// Stores used to back a [`Node`] need to implement this trait. Custom stores that don't support
+/// batching may do so by simply relying on the default implementations:
+///
+/// ```ignore
+/// impl BatchKVStore for MyStore {}
+/// ```
+///
+/// [`Node`]: crate::Node
+pub trait BatchKVStore: KVStore {
+	/// Persists all the given `(key, data)` entries under the given `secondary_namespace` in
+	/// `primary_namespace`.
+	///
+	/// See [`KVStore::write`] for more details.
+	fn write_batch(
+		&self, primary_namespace: &str, secondary_namespace: &str, entries: &[(&str, &[u8])],
+	) -> std::io::Result<()> {
+		for (key, buf) in entries {
+			self.write(primary_namespace, secondary_namespace, key, buf)?;
+		}
+		Ok(())
+	}
+
+	/// Removes any data that had previously been persisted under the given `keys`.
+	///
+	/// See [`KVStore::remove`] for more details.
+	fn remove_batch(
+		&self, primary_namespace: &str, secondary_namespace: &str, keys: &[&str], lazy: bool,
+	) -> std::io::Result<()> {
+		for key in keys {
+			self.remove(primary_namespace, secondary_namespace, key, lazy)?;
+		}
+		Ok(())
+	}
+}
+
+impl BatchKVStore for FilesystemStore {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use lightning::util::test_utils::TestStore;
+
+	impl BatchKVStore for TestStore {}
+
+	#[test]
+	fn default_batch_operations_apply_all_entries() {
+		let store = TestStore::new(false);
+		store.write("namespace", "", "untouched", &[0]).unwrap();
+
+		let entries: [(&str, &[u8]); 2] = [("key_a", &[1]), ("key_b", &[2, 2])];
+		store.write_batch("namespace", "", &entries).unwrap();
+		assert_eq!(store.read("namespace", "", "key_a").unwrap(), vec![1]);
+		assert_eq!(store.read("namespace", "", "key_b").unwrap(), vec![2, 2]);
+
+		store.remove_batch("namespace", "", &["key_a", "key_b"], false).unwrap();
+		assert_eq!(store.list("namespace", "").unwrap(), vec!["untouched".to_string()]);
+	}
+}
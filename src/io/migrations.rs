@@ -0,0 +1,183 @@
+//! Versioned migrations of the persisted data.
+//!
+//! The schema version of the data persisted in the [`KVStore`] is tracked under a dedicated
+//! namespace. On startup, all migrations with a version newer than the persisted one are run in
+//! order, and the persisted version is bumped after each successful step.
+
+use crate::io::{
+	SCHEMA_VERSION_PERSISTENCE_KEY, SCHEMA_VERSION_PERSISTENCE_PRIMARY_NAMESPACE,
+	SCHEMA_VERSION_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use crate::logger::{log_error, log_info, Logger};
+
+use lightning::util::persist::KVStore;
+use lightning::util::ser::{Readable, Writeable};
+
+use std::io::Cursor;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A single migration step, upgrading the persisted data to `version`.
+pub(crate) struct Migration<K: KVStore + Sync + Send, L: Deref>
+where
+	L::Target: Logger,
+{
+	/// The schema version the persisted data is at after this migration ran.
+	pub(crate) version: u32,
+	/// A short description of what the migration does, used for logging.
+	pub(crate) description: &'static str,
+	/// The migration logic.
+	pub(crate) migrate: fn(&Arc<K>, &L) -> Result<(), std::io::Error>,
+}
+
+/// Returns the list of all known migrations, ordered by their version.
+///
+/// New migrations must be appended with a version one greater than the previous entry.
+pub(crate) fn migrations<K: KVStore + Sync + Send, L: Deref>() -> Vec<Migration<K, L>>
+where
+	L::Target: Logger,
+{
+	Vec::new()
+}
+
+/// Reads the persisted schema version, defaulting to `0` if none was persisted yet.
+pub(crate) fn read_schema_version<K: KVStore + Sync + Send>(
+	kv_store: &Arc<K>,
+) -> Result<u32, std::io::Error> {
+	match kv_store.read(
+		SCHEMA_VERSION_PERSISTENCE_PRIMARY_NAMESPACE,
+		SCHEMA_VERSION_PERSISTENCE_SECONDARY_NAMESPACE,
+		SCHEMA_VERSION_PERSISTENCE_KEY,
+	) {
+		Ok(data) => {
+			let mut reader = Cursor::new(data);
+			u32::read(&mut reader).map_err(|_| {
+				std::io::Error::new(
+					std::io::ErrorKind::InvalidData,
+					"Failed to deserialize schema version",
+				)
+			})
+		},
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+		Err(e) => Err(e),
+	}
+}
+
+fn write_schema_version<K: KVStore + Sync + Send>(
+	kv_store: &Arc<K>, version: u32,
+) -> Result<(), std::io::Error> {
+	kv_store.write(
+		SCHEMA_VERSION_PERSISTENCE_PRIMARY_NAMESPACE,
+		SCHEMA_VERSION_PERSISTENCE_SECONDARY_NAMESPACE,
+		SCHEMA_VERSION_PERSISTENCE_KEY,
+		&version.encode(),
+	)
+}
+
+/// Runs all given migrations newer than the persisted schema version, in order.
+///
+/// The persisted schema version is bumped after each successful migration so that an interrupted
+/// run resumes with the first migration that didn't complete.
+pub(crate) fn run_migrations<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: &Arc<K>, migrations: &[Migration<K, L>], logger: &L,
+) -> Result<(), std::io::Error>
+where
+	L::Target: Logger,
+{
+	let latest_version = migrations.last().map_or(0, |m| m.version);
+	let persisted_version = read_schema_version(kv_store).map_err(|e| {
+		log_error!(logger, "Failed to read persisted schema version: {}", e);
+		e
+	})?;
+
+	if persisted_version > latest_version {
+		log_error!(
+			logger,
+			"Persisted schema version {} is newer than the latest known version {}",
+			persisted_version,
+			latest_version
+		);
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"Persisted schema version is unknown",
+		));
+	}
+
+	let mut cur_version = persisted_version;
+	for migration in migrations.iter().filter(|m| m.version > persisted_version) {
+		debug_assert_eq!(migration.version, cur_version + 1);
+		log_info!(
+			logger,
+			"Migrating persisted data to schema version {}: {}",
+			migration.version,
+			migration.description
+		);
+		(migration.migrate)(kv_store, logger).map_err(|e| {
+			log_error!(
+				logger,
+				"Failed to migrate persisted data to schema version {}: {}",
+				migration.version,
+				e
+			);
+			e
+		})?;
+		write_schema_version(kv_store, migration.version).map_err(|e| {
+			log_error!(logger, "Failed to persist schema version {}: {}", migration.version, e);
+			e
+		})?;
+		cur_version = migration.version;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use lightning::util::test_utils::{TestLogger, TestStore};
+
+	fn write_marker(kv_store: &Arc<TestStore>, key: &str) -> Result<(), std::io::Error> {
+		kv_store.write("migration_test", "", key, &[1])
+	}
+
+	#[test]
+	fn migrations_run_in_order_once() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		assert_eq!(read_schema_version(&store).unwrap(), 0);
+
+		let mut migrations: Vec<Migration<TestStore, Arc<TestLogger>>> = vec![Migration {
+			version: 1,
+			description: "first",
+			migrate: |kv_store, _| write_marker(kv_store, "first"),
+		}];
+		run_migrations(&store, &migrations, &logger).unwrap();
+		assert_eq!(read_schema_version(&store).unwrap(), 1);
+		assert!(store.read("migration_test", "", "first").is_ok());
+
+		// Already applied migrations are skipped.
+		store.remove("migration_test", "", "first", false).unwrap();
+		migrations.push(Migration {
+			version: 2,
+			description: "second",
+			migrate: |kv_store, _| write_marker(kv_store, "second"),
+		});
+		run_migrations(&store, &migrations, &logger).unwrap();
+		assert_eq!(read_schema_version(&store).unwrap(), 2);
+		assert!(store.read("migration_test", "", "first").is_err());
+		assert!(store.read("migration_test", "", "second").is_ok());
+
+		// A failing migration doesn't bump the version.
+		migrations.push(Migration {
+			version: 3,
+			description: "failing",
+			migrate: |_, _| Err(std::io::Error::new(std::io::ErrorKind::Other, "failed")),
+		});
+		assert!(run_migrations(&store, &migrations, &logger).is_err());
+		assert_eq!(read_schema_version(&store).unwrap(), 2);
+
+		// We refuse to run if the persisted version is newer than what we know.
+		migrations.truncate(1);
+		assert!(run_migrations(&store, &migrations, &logger).is_err());
+	}
+}
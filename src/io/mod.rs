@@ -1,5 +1,14 @@
 //! Objects and traits for data persistence.
 
+pub mod async_kv_store;
+pub mod backup_sink_store;
+pub mod batch_kv_store;
+pub(crate) mod migrations;
+#[cfg(any(postgres, postgres_test))]
+pub mod postgres_store;
+#[cfg(any(redis, redis_test))]
+pub mod redis_store;
+pub mod retrying_store;
 pub mod sqlite_store;
 #[cfg(test)]
 pub(crate) mod test_utils;
@@ -7,30 +16,177 @@ pub(crate) mod utils;
 #[cfg(any(vss, vss_test))]
 pub(crate) mod vss_store;
 
-/// The event queue will be persisted under this key.
-pub(crate) const EVENT_QUEUE_PERSISTENCE_PRIMARY_NAMESPACE: &str = "";
-pub(crate) const EVENT_QUEUE_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
-pub(crate) const EVENT_QUEUE_PERSISTENCE_KEY: &str = "events";
-
-/// The peer information will be persisted under this key.
-pub(crate) const PEER_INFO_PERSISTENCE_PRIMARY_NAMESPACE: &str = "";
-pub(crate) const PEER_INFO_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
-pub(crate) const PEER_INFO_PERSISTENCE_KEY: &str = "peers";
-
-/// The payment information will be persisted under this prefix.
-pub(crate) const PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE: &str = "payments";
-pub(crate) const PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
-
-/// The spendable output information will be persisted under this prefix.
-pub(crate) const SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE: &str = "spendable_outputs";
-pub(crate) const SPENDABLE_OUTPUT_INFO_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
-
-/// RapidGossipSync's `latest_sync_timestamp` will be persisted under this key.
-pub(crate) const LATEST_RGS_SYNC_TIMESTAMP_PRIMARY_NAMESPACE: &str = "";
-pub(crate) const LATEST_RGS_SYNC_TIMESTAMP_SECONDARY_NAMESPACE: &str = "";
-pub(crate) const LATEST_RGS_SYNC_TIMESTAMP_KEY: &str = "latest_rgs_sync_timestamp";
-
-/// The last time we broadcast a node announcement will be persisted under this key.
-pub(crate) const LATEST_NODE_ANN_BCAST_TIMESTAMP_PRIMARY_NAMESPACE: &str = "";
-pub(crate) const LATEST_NODE_ANN_BCAST_TIMESTAMP_SECONDARY_NAMESPACE: &str = "";
-pub(crate) const LATEST_NODE_ANN_BCAST_TIMESTAMP_KEY: &str = "latest_node_ann_bcast_timestamp";
+pub use utils::{verify_store, CorruptStoreEntry, StoreVerificationReport};
+
+use lightning::util::persist;
+
+/// A location in the [`KVStore`] we persist data under.
+///
+/// [`KVStore`]: lightning::util::persist::KVStore
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct PersistedNamespace {
+	pub primary_namespace: &'static str,
+	pub secondary_namespace: &'static str,
+	/// The only key persisted by this entry, or `None` if it persists any number of keys under
+	/// its namespace.
+	pub key: Option<&'static str>,
+}
+
+/// Defines the namespace (and key) constants of everything we persist, along with the
+/// [`PERSISTED_NAMESPACES`] registry listing all of them.
+macro_rules! persisted_namespaces {
+	(@key $key_const: ident) => {
+		Some($key_const)
+	};
+	(@key) => {
+		None
+	};
+	($(
+		$(#[$doc: meta])*
+		$primary_const: ident = $primary: expr,
+		$secondary_const: ident = $secondary: expr
+		$(, $key_const: ident = $key: expr)?;
+	)*) => {
+		$(
+			$(#[$doc])*
+			pub(crate) const $primary_const: &str = $primary;
+			pub(crate) const $secondary_const: &str = $secondary;
+			$(pub(crate) const $key_const: &str = $key;)?
+		)*
+
+		/// Everything we persist data under, including the data persisted on our behalf by LDK.
+		///
+		/// Used to include all our data in store snapshots and to verify it via [`verify_store`].
+		pub(crate) const PERSISTED_NAMESPACES: &[PersistedNamespace] = &[
+			$(PersistedNamespace {
+				primary_namespace: $primary_const,
+				secondary_namespace: $secondary_const,
+				key: persisted_namespaces!(@key $($key_const)?),
+			},)*
+			PersistedNamespace {
+				primary_namespace: persist::CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE,
+				secondary_namespace: persist::CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE,
+				key: Some(persist::CHANNEL_MANAGER_PERSISTENCE_KEY),
+			},
+			PersistedNamespace {
+				primary_namespace: persist::CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+				secondary_namespace: persist::CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+				key: None,
+			},
+			PersistedNamespace {
+				primary_namespace: persist::NETWORK_GRAPH_PERSISTENCE_PRIMARY_NAMESPACE,
+				secondary_namespace: persist::NETWORK_GRAPH_PERSISTENCE_SECONDARY_NAMESPACE,
+				key: Some(persist::NETWORK_GRAPH_PERSISTENCE_KEY),
+			},
+			PersistedNamespace {
+				primary_namespace: persist::SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
+				secondary_namespace: persist::SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+				key: Some(persist::SCORER_PERSISTENCE_KEY),
+			},
+		];
+	};
+}
+
+// When adding a new persisted namespace, define it here so it is included in store snapshots and
+// checked by `verify_store`.
+persisted_namespaces! {
+	/// The event queue will be persisted under this key.
+	EVENT_QUEUE_PERSISTENCE_PRIMARY_NAMESPACE = "",
+	EVENT_QUEUE_PERSISTENCE_SECONDARY_NAMESPACE = "",
+	EVENT_QUEUE_PERSISTENCE_KEY = "events";
+
+	/// Events that repeatedly failed to be handled will be persisted under this prefix.
+	DEAD_LETTER_EVENT_PERSISTENCE_PRIMARY_NAMESPACE = "dead_letter_events",
+	DEAD_LETTER_EVENT_PERSISTENCE_SECONDARY_NAMESPACE = "";
+
+	/// The peer information will be persisted under this key.
+	PEER_INFO_PERSISTENCE_PRIMARY_NAMESPACE = "",
+	PEER_INFO_PERSISTENCE_SECONDARY_NAMESPACE = "",
+	PEER_INFO_PERSISTENCE_KEY = "peers";
+
+	/// The payment information will be persisted under this prefix.
+	PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE = "payments",
+	PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE = "";
+
+	/// The amounts of the refunds we initiated but weren't paid for yet will be persisted under
+	/// this prefix, keyed by the respective payment id.
+	PENDING_REFUND_PERSISTENCE_PRIMARY_NAMESPACE = "pending_refunds",
+	PENDING_REFUND_PERSISTENCE_SECONDARY_NAMESPACE = "";
+
+	/// The retry state of outbound payments will be persisted under this prefix.
+	PAYMENT_RETRY_PERSISTENCE_PRIMARY_NAMESPACE = "payment_retries",
+	PAYMENT_RETRY_PERSISTENCE_SECONDARY_NAMESPACE = "";
+
+	/// The state of payments received via hold invoices will be persisted under this prefix.
+	HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE = "held_payments",
+	HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE = "";
+
+	/// The spendable output information will be persisted under this prefix.
+	SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE = "spendable_outputs",
+	SPENDABLE_OUTPUT_INFO_PERSISTENCE_SECONDARY_NAMESPACE = "";
+
+	/// Pending quotes for just-in-time channels will be persisted under this prefix.
+	JIT_CHANNEL_QUOTE_PERSISTENCE_PRIMARY_NAMESPACE = "jit_channel_quotes",
+	JIT_CHANNEL_QUOTE_PERSISTENCE_SECONDARY_NAMESPACE = "";
+
+	/// RapidGossipSync's `latest_sync_timestamp` will be persisted under this key.
+	LATEST_RGS_SYNC_TIMESTAMP_PRIMARY_NAMESPACE = "",
+	LATEST_RGS_SYNC_TIMESTAMP_SECONDARY_NAMESPACE = "",
+	LATEST_RGS_SYNC_TIMESTAMP_KEY = "latest_rgs_sync_timestamp";
+
+	/// The last time we broadcast a node announcement will be persisted under this key.
+	LATEST_NODE_ANN_BCAST_TIMESTAMP_PRIMARY_NAMESPACE = "",
+	LATEST_NODE_ANN_BCAST_TIMESTAMP_SECONDARY_NAMESPACE = "",
+	LATEST_NODE_ANN_BCAST_TIMESTAMP_KEY = "latest_node_ann_bcast_timestamp";
+
+	/// The most recent successful fee rate estimates will be persisted under this key.
+	FEE_RATE_CACHE_PERSISTENCE_PRIMARY_NAMESPACE = "",
+	FEE_RATE_CACHE_PERSISTENCE_SECONDARY_NAMESPACE = "",
+	FEE_RATE_CACHE_PERSISTENCE_KEY = "fee_rate_cache";
+
+	/// The cross-node seed shared with other nodes to receive phantom payments will be persisted
+	/// under this key.
+	PHANTOM_SEED_PERSISTENCE_PRIMARY_NAMESPACE = "",
+	PHANTOM_SEED_PERSISTENCE_SECONDARY_NAMESPACE = "",
+	PHANTOM_SEED_PERSISTENCE_KEY = "phantom_cross_node_seed";
+
+	/// The node ids of the peers we accept zero-confirmation channels from will be persisted under
+	/// this key.
+	TRUSTED_PEERS_0CONF_PERSISTENCE_PRIMARY_NAMESPACE = "",
+	TRUSTED_PEERS_0CONF_PERSISTENCE_SECONDARY_NAMESPACE = "",
+	TRUSTED_PEERS_0CONF_PERSISTENCE_KEY = "trusted_peers_0conf";
+
+	/// The channels we restored from a static channel backup will be persisted under this key.
+	RECOVERED_CHANNELS_PERSISTENCE_PRIMARY_NAMESPACE = "",
+	RECOVERED_CHANNELS_PERSISTENCE_SECONDARY_NAMESPACE = "",
+	RECOVERED_CHANNELS_PERSISTENCE_KEY = "recovered_channels";
+
+	/// The time until which the channels we manually penalized are avoided will be persisted under
+	/// this prefix, keyed by the respective short channel id.
+	CHANNEL_PENALTIES_PERSISTENCE_PRIMARY_NAMESPACE = "channel_penalties",
+	CHANNEL_PENALTIES_PERSISTENCE_SECONDARY_NAMESPACE = "";
+
+	/// The labels attached to our on-chain addresses will be persisted under this prefix, keyed by
+	/// the respective address.
+	ADDRESS_LABEL_PERSISTENCE_PRIMARY_NAMESPACE = "address_labels",
+	ADDRESS_LABEL_PERSISTENCE_SECONDARY_NAMESPACE = "";
+
+	/// The txids of the on-chain payments we already emitted events for will be persisted under
+	/// this key.
+	ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE = "",
+	ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE = "",
+	ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_KEY = "onchain_payment_monitor";
+
+	/// The schema version of the persisted data will be persisted under this key.
+	SCHEMA_VERSION_PERSISTENCE_PRIMARY_NAMESPACE = "migrations",
+	SCHEMA_VERSION_PERSISTENCE_SECONDARY_NAMESPACE = "",
+	SCHEMA_VERSION_PERSISTENCE_KEY = "schema_version";
+}
+
+/// The throwaway entry written and removed again when probing the store during health checks.
+pub(crate) const HEALTH_CHECK_PROBE_PRIMARY_NAMESPACE: &str = "health_check";
+pub(crate) const HEALTH_CHECK_PROBE_SECONDARY_NAMESPACE: &str = "";
+pub(crate) const HEALTH_CHECK_PROBE_KEY: &str = "probe";
+
+/// Entries found to be corrupt by [`verify_store`] will be quarantined under this namespace.
+pub const CORRUPT_PERSISTENCE_PRIMARY_NAMESPACE: &str = "corrupt";
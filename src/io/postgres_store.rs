@@ -0,0 +1,435 @@
+//! Objects related to [`PostgresStore`] live here.
+use crate::io::batch_kv_store::BatchKVStore;
+use crate::io::utils::check_namespace_key_validity;
+
+use lightning::util::persist::KVStore;
+use lightning::util::string::PrintableString;
+
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+use tokio::runtime::Runtime;
+use tokio_postgres::NoTls;
+
+use std::io::{Error, ErrorKind};
+
+/// The default table in which we store all data.
+pub const DEFAULT_KV_TABLE_NAME: &str = "ldk_data";
+
+// The maximum number of connections held by the connection pool.
+const MAX_POOL_SIZE: usize = 16;
+
+// As the table name is interpolated into our SQL statements, we only allow plain identifiers, i.e.,
+// names matching `[A-Za-z_][A-Za-z0-9_]*`.
+fn is_valid_table_name(name: &str) -> bool {
+	let mut chars = name.chars();
+	match chars.next() {
+		Some(c) if c.is_ascii_alphabetic() || c == '_' => {},
+		_ => return false,
+	}
+	chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A [`KVStore`] implementation that writes to and reads from a [PostgreSQL] database.
+///
+/// Entries are stored in a single table with a composite primary key over the primary namespace,
+/// the secondary namespace, and the key. Lazy removals are implemented as soft-deletes, i.e., the
+/// entry is only marked as deleted and will be overwritten by any subsequent write.
+///
+/// **Note:** connections are currently established without TLS.
+///
+/// [PostgreSQL]: https://www.postgresql.org
+pub struct PostgresStore {
+	pool: Pool,
+	kv_table_name: String,
+	runtime: Runtime,
+}
+
+impl PostgresStore {
+	/// Constructs a new [`PostgresStore`].
+	///
+	/// Connects to the database given by the `connection_string` (e.g.,
+	/// `host=localhost user=postgres dbname=ldk_node` or `postgresql://postgres@localhost/ldk_node`)
+	/// and creates the given `kv_table_name` (or [`DEFAULT_KV_TABLE_NAME`] if set to `None`) if it
+	/// doesn't exist yet.
+	///
+	/// The table name must start with an ASCII letter or underscore and may otherwise only consist
+	/// of ASCII alphanumeric characters and underscores.
+	pub fn new(connection_string: String, kv_table_name: Option<String>) -> std::io::Result<Self> {
+		let kv_table_name = kv_table_name.unwrap_or(DEFAULT_KV_TABLE_NAME.to_string());
+		if !is_valid_table_name(&kv_table_name) {
+			let msg = format!("Invalid table name: {}", PrintableString(&kv_table_name));
+			return Err(Error::new(ErrorKind::InvalidInput, msg));
+		}
+
+		let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+
+		let pg_config = connection_string.parse::<tokio_postgres::Config>().map_err(|e| {
+			let msg = format!("Failed to parse connection string: {}", e);
+			Error::new(ErrorKind::InvalidInput, msg)
+		})?;
+		let manager_config = ManagerConfig { recycling_method: RecyclingMethod::Fast };
+		let manager = Manager::from_config(pg_config, NoTls, manager_config);
+		let pool = Pool::builder(manager).max_size(MAX_POOL_SIZE).build().map_err(|e| {
+			let msg = format!("Failed to create connection pool: {}", e);
+			Error::new(ErrorKind::Other, msg)
+		})?;
+
+		let sql = format!(
+			"CREATE TABLE IF NOT EXISTS {} (
+			primary_namespace TEXT NOT NULL,
+			secondary_namespace TEXT DEFAULT '' NOT NULL,
+			key TEXT NOT NULL CHECK (key <> ''),
+			value BYTEA,
+			deleted BOOLEAN DEFAULT FALSE NOT NULL,
+			PRIMARY KEY ( primary_namespace, secondary_namespace, key )
+			);",
+			kv_table_name
+		);
+
+		runtime.block_on(async {
+			let client = pool.get().await.map_err(|e| {
+				let msg = format!("Failed to connect to database: {}", e);
+				Error::new(ErrorKind::Other, msg)
+			})?;
+			client.execute(&sql, &[]).await.map_err(|e| {
+				let msg = format!("Failed to create table {}: {}", kv_table_name, e);
+				Error::new(ErrorKind::Other, msg)
+			})
+		})?;
+
+		Ok(Self { pool, kv_table_name, runtime })
+	}
+
+	async fn get_client(&self) -> std::io::Result<deadpool_postgres::Client> {
+		self.pool.get().await.map_err(|e| {
+			let msg = format!("Failed to get database connection: {}", e);
+			Error::new(ErrorKind::Other, msg)
+		})
+	}
+
+	async fn read_internal(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str,
+	) -> std::io::Result<Vec<u8>> {
+		let client = self.get_client().await?;
+		let sql = format!(
+			"SELECT value FROM {} WHERE primary_namespace=$1 AND secondary_namespace=$2 AND key=$3 AND deleted=FALSE;",
+			self.kv_table_name
+		);
+
+		let row_opt = client
+			.query_opt(&sql, &[&primary_namespace, &secondary_namespace, &key])
+			.await
+			.map_err(|e| {
+				let msg = format!(
+					"Failed to read from key {}/{}/{}: {}",
+					PrintableString(primary_namespace),
+					PrintableString(secondary_namespace),
+					PrintableString(key),
+					e
+				);
+				Error::new(ErrorKind::Other, msg)
+			})?;
+
+		match row_opt {
+			Some(row) => Ok(row.get(0)),
+			None => {
+				let msg = format!(
+					"Failed to read as key could not be found: {}/{}/{}",
+					PrintableString(primary_namespace),
+					PrintableString(secondary_namespace),
+					PrintableString(key)
+				);
+				Err(Error::new(ErrorKind::NotFound, msg))
+			},
+		}
+	}
+
+	async fn write_internal(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, buf: &[u8],
+	) -> std::io::Result<()> {
+		let mut client = self.get_client().await?;
+		let sql = format!(
+			"INSERT INTO {} (primary_namespace, secondary_namespace, key, value, deleted) VALUES ($1, $2, $3, $4, FALSE)
+			ON CONFLICT (primary_namespace, secondary_namespace, key) DO UPDATE SET value=EXCLUDED.value, deleted=FALSE;",
+			self.kv_table_name
+		);
+
+		let map_err = |e: tokio_postgres::Error| {
+			let msg = format!(
+				"Failed to write to key {}/{}/{}: {}",
+				PrintableString(primary_namespace),
+				PrintableString(secondary_namespace),
+				PrintableString(key),
+				e
+			);
+			Error::new(ErrorKind::Other, msg)
+		};
+
+		let tx = client.transaction().await.map_err(map_err)?;
+		tx.execute(&sql, &[&primary_namespace, &secondary_namespace, &key, &buf])
+			.await
+			.map_err(map_err)?;
+		tx.commit().await.map_err(map_err)
+	}
+
+	async fn remove_internal(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, lazy: bool,
+	) -> std::io::Result<()> {
+		let client = self.get_client().await?;
+		let sql = if lazy {
+			format!(
+				"UPDATE {} SET deleted=TRUE WHERE primary_namespace=$1 AND secondary_namespace=$2 AND key=$3;",
+				self.kv_table_name
+			)
+		} else {
+			format!(
+				"DELETE FROM {} WHERE primary_namespace=$1 AND secondary_namespace=$2 AND key=$3;",
+				self.kv_table_name
+			)
+		};
+
+		client.execute(&sql, &[&primary_namespace, &secondary_namespace, &key]).await.map_err(
+			|e| {
+				let msg = format!(
+					"Failed to delete key {}/{}/{}: {}",
+					PrintableString(primary_namespace),
+					PrintableString(secondary_namespace),
+					PrintableString(key),
+					e
+				);
+				Error::new(ErrorKind::Other, msg)
+			},
+		)?;
+		Ok(())
+	}
+
+	async fn write_batch_internal(
+		&self, primary_namespace: &str, secondary_namespace: &str, entries: &[(&str, &[u8])],
+	) -> std::io::Result<()> {
+		let mut client = self.get_client().await?;
+		let sql = format!(
+			"INSERT INTO {} (primary_namespace, secondary_namespace, key, value, deleted) VALUES ($1, $2, $3, $4, FALSE)
+			ON CONFLICT (primary_namespace, secondary_namespace, key) DO UPDATE SET value=EXCLUDED.value, deleted=FALSE;",
+			self.kv_table_name
+		);
+
+		let tx = client.transaction().await.map_err(|e| {
+			let msg = format!("Failed to start transaction: {}", e);
+			Error::new(ErrorKind::Other, msg)
+		})?;
+		for (key, buf) in entries {
+			tx.execute(&sql, &[&primary_namespace, &secondary_namespace, key, buf]).await.map_err(
+				|e| {
+					let msg = format!(
+						"Failed to write to key {}/{}/{}: {}",
+						PrintableString(primary_namespace),
+						PrintableString(secondary_namespace),
+						PrintableString(key),
+						e
+					);
+					Error::new(ErrorKind::Other, msg)
+				},
+			)?;
+		}
+		// If any of the writes failed, the transaction is rolled back when dropped.
+		tx.commit().await.map_err(|e| {
+			let msg = format!("Failed to commit transaction: {}", e);
+			Error::new(ErrorKind::Other, msg)
+		})
+	}
+
+	async fn remove_batch_internal(
+		&self, primary_namespace: &str, secondary_namespace: &str, keys: &[&str], lazy: bool,
+	) -> std::io::Result<()> {
+		let mut client = self.get_client().await?;
+		let sql = if lazy {
+			format!(
+				"UPDATE {} SET deleted=TRUE WHERE primary_namespace=$1 AND secondary_namespace=$2 AND key=$3;",
+				self.kv_table_name
+			)
+		} else {
+			format!(
+				"DELETE FROM {} WHERE primary_namespace=$1 AND secondary_namespace=$2 AND key=$3;",
+				self.kv_table_name
+			)
+		};
+
+		let tx = client.transaction().await.map_err(|e| {
+			let msg = format!("Failed to start transaction: {}", e);
+			Error::new(ErrorKind::Other, msg)
+		})?;
+		for key in keys {
+			tx.execute(&sql, &[&primary_namespace, &secondary_namespace, key]).await.map_err(
+				|e| {
+					let msg = format!(
+						"Failed to delete key {}/{}/{}: {}",
+						PrintableString(primary_namespace),
+						PrintableString(secondary_namespace),
+						PrintableString(key),
+						e
+					);
+					Error::new(ErrorKind::Other, msg)
+				},
+			)?;
+		}
+		tx.commit().await.map_err(|e| {
+			let msg = format!("Failed to commit transaction: {}", e);
+			Error::new(ErrorKind::Other, msg)
+		})
+	}
+
+	async fn list_internal(
+		&self, primary_namespace: &str, secondary_namespace: &str,
+	) -> std::io::Result<Vec<String>> {
+		let client = self.get_client().await?;
+		let sql = format!(
+			"SELECT key FROM {} WHERE primary_namespace=$1 AND secondary_namespace=$2 AND deleted=FALSE;",
+			self.kv_table_name
+		);
+
+		let rows =
+			client.query(&sql, &[&primary_namespace, &secondary_namespace]).await.map_err(|e| {
+				let msg = format!("Failed to retrieve queried rows: {}", e);
+				Error::new(ErrorKind::Other, msg)
+			})?;
+
+		Ok(rows.iter().map(|row| row.get(0)).collect())
+	}
+}
+
+impl KVStore for PostgresStore {
+	fn read(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str,
+	) -> std::io::Result<Vec<u8>> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "read")?;
+		tokio::task::block_in_place(|| {
+			self.runtime.block_on(self.read_internal(primary_namespace, secondary_namespace, key))
+		})
+	}
+
+	fn write(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, buf: &[u8],
+	) -> std::io::Result<()> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "write")?;
+		tokio::task::block_in_place(|| {
+			self.runtime.block_on(self.write_internal(
+				primary_namespace,
+				secondary_namespace,
+				key,
+				buf,
+			))
+		})
+	}
+
+	fn remove(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, lazy: bool,
+	) -> std::io::Result<()> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "remove")?;
+		tokio::task::block_in_place(|| {
+			self.runtime.block_on(self.remove_internal(
+				primary_namespace,
+				secondary_namespace,
+				key,
+				lazy,
+			))
+		})
+	}
+
+	fn list(
+		&self, primary_namespace: &str, secondary_namespace: &str,
+	) -> std::io::Result<Vec<String>> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, None, "list")?;
+		tokio::task::block_in_place(|| {
+			self.runtime.block_on(self.list_internal(primary_namespace, secondary_namespace))
+		})
+	}
+}
+
+impl BatchKVStore for PostgresStore {
+	fn write_batch(
+		&self, primary_namespace: &str, secondary_namespace: &str, entries: &[(&str, &[u8])],
+	) -> std::io::Result<()> {
+		for (key, _) in entries {
+			check_namespace_key_validity(
+				primary_namespace,
+				secondary_namespace,
+				Some(*key),
+				"write",
+			)?;
+		}
+		tokio::task::block_in_place(|| {
+			self.runtime.block_on(self.write_batch_internal(
+				primary_namespace,
+				secondary_namespace,
+				entries,
+			))
+		})
+	}
+
+	fn remove_batch(
+		&self, primary_namespace: &str, secondary_namespace: &str, keys: &[&str], lazy: bool,
+	) -> std::io::Result<()> {
+		for key in keys {
+			check_namespace_key_validity(
+				primary_namespace,
+				secondary_namespace,
+				Some(*key),
+				"remove",
+			)?;
+		}
+		tokio::task::block_in_place(|| {
+			self.runtime.block_on(self.remove_batch_internal(
+				primary_namespace,
+				secondary_namespace,
+				keys,
+				lazy,
+			))
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::io::test_utils::{do_batch_write_and_remove, do_read_write_remove_list_persist};
+	use rand::distributions::Alphanumeric;
+	use rand::{thread_rng, Rng};
+
+	fn test_store() -> PostgresStore {
+		let connection_string = std::env::var("TEST_POSTGRES_CONNECTION_STRING").unwrap();
+		let mut rng = thread_rng();
+		let rand_suffix: String = (0..7).map(|_| rng.sample(Alphanumeric) as char).collect();
+		let kv_table_name = format!("ldk_data_test_{}", rand_suffix.to_lowercase());
+		PostgresStore::new(connection_string, Some(kv_table_name)).unwrap()
+	}
+
+	#[test]
+	fn read_write_remove_list_persist() {
+		let store = test_store();
+		do_read_write_remove_list_persist(&store);
+	}
+
+	#[test]
+	fn batch_write_and_remove() {
+		let store = test_store();
+		do_batch_write_and_remove(&store);
+
+		// Lazily removed entries are soft-deleted in one go.
+		let entries: [(&str, &[u8]); 2] = [("testkey_a", &[1u8; 32]), ("testkey_b", &[2u8; 32])];
+		store.write_batch("testspace", "", &entries).unwrap();
+		store.remove_batch("testspace", "", &["testkey_a", "testkey_b"], true).unwrap();
+		assert!(store.list("testspace", "").unwrap().is_empty());
+	}
+
+	#[test]
+	fn table_names_are_validated() {
+		assert!(is_valid_table_name(DEFAULT_KV_TABLE_NAME));
+		assert!(is_valid_table_name("_ldk_data_2"));
+		assert!(!is_valid_table_name(""));
+		assert!(!is_valid_table_name("2ldk_data"));
+		assert!(!is_valid_table_name("ldk-data"));
+		assert!(!is_valid_table_name("ldk_data; DROP TABLE ldk_data"));
+
+		let res = PostgresStore::new("host=localhost".to_string(), Some("ldk data".to_string()));
+		assert_eq!(res.err().unwrap().kind(), ErrorKind::InvalidInput);
+	}
+}
@@ -0,0 +1,312 @@
+//! Objects related to [`RedisStore`] live here.
+use crate::io::batch_kv_store::BatchKVStore;
+use crate::io::utils::check_namespace_key_validity;
+
+use lightning::util::persist::KVStore;
+use lightning::util::string::PrintableString;
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use std::sync::Mutex;
+
+/// A [`KVStore`] implementation that writes to and reads from a [Redis] server.
+///
+/// Entries are stored under Redis keys of the form `primary_namespace:secondary_namespace:key`.
+/// As `:` is not part of the allowed namespace and key alphabet, this mapping is unambiguous.
+///
+/// Entries that only hold transient data may be configured to expire automatically via
+/// [`RedisStore::set_ttl`].
+///
+/// [Redis]: https://redis.io
+pub struct RedisStore {
+	connection: Mutex<redis::Connection>,
+	// Maps (primary_namespace, secondary_namespace) to the TTLs of the entries stored within. A
+	// `None` key applies to all entries in the namespace not otherwise configured.
+	ttls: HashMap<(String, String), HashMap<Option<String>, u64>>,
+}
+
+impl RedisStore {
+	/// Constructs a new [`RedisStore`] connecting to the server at the given `redis_url`, e.g.,
+	/// `redis://127.0.0.1/`.
+	pub fn new(redis_url: String) -> std::io::Result<Self> {
+		let client = redis::Client::open(redis_url).map_err(|e| {
+			let msg = format!("Failed to parse Redis URL: {}", e);
+			Error::new(ErrorKind::InvalidInput, msg)
+		})?;
+		let connection = client.get_connection().map_err(|e| {
+			let msg = format!("Failed to connect to Redis server: {}", e);
+			Error::new(ErrorKind::Other, msg)
+		})?;
+		Ok(Self { connection: Mutex::new(connection), ttls: HashMap::new() })
+	}
+
+	/// Configures entries written to the given namespace to expire after `ttl_secs`.
+	///
+	/// If `key` is set, only the entry with the given key is affected, otherwise all entries in the
+	/// namespace are. This should only be used for transient data that the node is able to recover
+	/// from losing, such as the latest RGS sync timestamp.
+	pub fn set_ttl(
+		&mut self, primary_namespace: &str, secondary_namespace: &str, key: Option<&str>,
+		ttl_secs: u64,
+	) -> std::io::Result<()> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, key, "set_ttl")?;
+		self.ttls
+			.entry((primary_namespace.to_string(), secondary_namespace.to_string()))
+			.or_insert_with(HashMap::new)
+			.insert(key.map(|k| k.to_string()), ttl_secs);
+		Ok(())
+	}
+
+	fn ttl_secs(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str,
+	) -> Option<u64> {
+		let namespace_ttls =
+			self.ttls.get(&(primary_namespace.to_string(), secondary_namespace.to_string()))?;
+		namespace_ttls.get(&Some(key.to_string())).or(namespace_ttls.get(&None)).copied()
+	}
+
+	fn build_key(primary_namespace: &str, secondary_namespace: &str, key: &str) -> String {
+		format!("{}:{}:{}", primary_namespace, secondary_namespace, key)
+	}
+}
+
+impl KVStore for RedisStore {
+	fn read(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str,
+	) -> std::io::Result<Vec<u8>> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "read")?;
+
+		let redis_key = Self::build_key(primary_namespace, secondary_namespace, key);
+		let mut locked_conn = self.connection.lock().unwrap();
+		let res: Option<Vec<u8>> =
+			redis::cmd("GET").arg(&redis_key).query(&mut *locked_conn).map_err(|e| {
+				let msg = format!(
+					"Failed to read from key {}/{}/{}: {}",
+					PrintableString(primary_namespace),
+					PrintableString(secondary_namespace),
+					PrintableString(key),
+					e
+				);
+				Error::new(ErrorKind::Other, msg)
+			})?;
+
+		res.ok_or_else(|| {
+			let msg = format!(
+				"Failed to read as key could not be found: {}/{}/{}",
+				PrintableString(primary_namespace),
+				PrintableString(secondary_namespace),
+				PrintableString(key)
+			);
+			Error::new(ErrorKind::NotFound, msg)
+		})
+	}
+
+	fn write(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, buf: &[u8],
+	) -> std::io::Result<()> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "write")?;
+
+		let redis_key = Self::build_key(primary_namespace, secondary_namespace, key);
+		let mut pipe = redis::pipe();
+		pipe.atomic();
+		match self.ttl_secs(primary_namespace, secondary_namespace, key) {
+			Some(ttl_secs) => pipe.cmd("SET").arg(&redis_key).arg(buf).arg("EX").arg(ttl_secs),
+			None => pipe.cmd("SET").arg(&redis_key).arg(buf),
+		};
+
+		let mut locked_conn = self.connection.lock().unwrap();
+		pipe.query::<()>(&mut *locked_conn).map_err(|e| {
+			let msg = format!(
+				"Failed to write to key {}/{}/{}: {}",
+				PrintableString(primary_namespace),
+				PrintableString(secondary_namespace),
+				PrintableString(key),
+				e
+			);
+			Error::new(ErrorKind::Other, msg)
+		})
+	}
+
+	fn remove(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, lazy: bool,
+	) -> std::io::Result<()> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "remove")?;
+
+		let redis_key = Self::build_key(primary_namespace, secondary_namespace, key);
+		// `UNLINK` reclaims the memory in the background, which is fine if we're allowed to be lazy.
+		let cmd = if lazy { "UNLINK" } else { "DEL" };
+		let mut locked_conn = self.connection.lock().unwrap();
+		redis::cmd(cmd).arg(&redis_key).query::<()>(&mut *locked_conn).map_err(|e| {
+			let msg = format!(
+				"Failed to delete key {}/{}/{}: {}",
+				PrintableString(primary_namespace),
+				PrintableString(secondary_namespace),
+				PrintableString(key),
+				e
+			);
+			Error::new(ErrorKind::Other, msg)
+		})
+	}
+
+	fn list(
+		&self, primary_namespace: &str, secondary_namespace: &str,
+	) -> std::io::Result<Vec<String>> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, None, "list")?;
+
+		// As the namespace alphabet doesn't contain any glob-style special characters, we don't
+		// need to escape the pattern.
+		let prefix = format!("{}:{}:", primary_namespace, secondary_namespace);
+		let pattern = format!("{}*", prefix);
+		let mut locked_conn = self.connection.lock().unwrap();
+		let iter = redis::cmd("SCAN")
+			.cursor_arg(0)
+			.arg("MATCH")
+			.arg(&pattern)
+			.clone()
+			.iter::<String>(&mut *locked_conn)
+			.map_err(|e| {
+				let msg = format!("Failed to retrieve keys with prefix {}: {}", prefix, e);
+				Error::new(ErrorKind::Other, msg)
+			})?;
+
+		Ok(keys_with_prefix(&prefix, iter))
+	}
+}
+
+// Strips the given prefix from the Redis keys returned by a `SCAN`. As `SCAN` may return a key
+// multiple times, e.g., if the keyspace is rehashed while iterating, the keys are deduplicated.
+fn keys_with_prefix<I: Iterator<Item = String>>(prefix: &str, redis_keys: I) -> Vec<String> {
+	let keys = redis_keys
+		.filter_map(|k| k.strip_prefix(prefix).map(|k| k.to_string()))
+		.collect::<HashSet<_>>();
+	keys.into_iter().collect()
+}
+
+impl BatchKVStore for RedisStore {
+	fn write_batch(
+		&self, primary_namespace: &str, secondary_namespace: &str, entries: &[(&str, &[u8])],
+	) -> std::io::Result<()> {
+		for (key, _) in entries {
+			check_namespace_key_validity(
+				primary_namespace,
+				secondary_namespace,
+				Some(*key),
+				"write",
+			)?;
+		}
+		if entries.is_empty() {
+			return Ok(());
+		}
+
+		let mut pipe = redis::pipe();
+		pipe.atomic();
+		for (key, buf) in entries {
+			let redis_key = Self::build_key(primary_namespace, secondary_namespace, key);
+			match self.ttl_secs(primary_namespace, secondary_namespace, key) {
+				Some(ttl_secs) => pipe.cmd("SET").arg(&redis_key).arg(*buf).arg("EX").arg(ttl_secs),
+				None => pipe.cmd("SET").arg(&redis_key).arg(*buf),
+			};
+		}
+
+		let mut locked_conn = self.connection.lock().unwrap();
+		pipe.query::<()>(&mut *locked_conn).map_err(|e| {
+			let msg = format!(
+				"Failed to write batch to namespace {}/{}: {}",
+				PrintableString(primary_namespace),
+				PrintableString(secondary_namespace),
+				e
+			);
+			Error::new(ErrorKind::Other, msg)
+		})
+	}
+
+	fn remove_batch(
+		&self, primary_namespace: &str, secondary_namespace: &str, keys: &[&str], lazy: bool,
+	) -> std::io::Result<()> {
+		for key in keys {
+			check_namespace_key_validity(
+				primary_namespace,
+				secondary_namespace,
+				Some(*key),
+				"remove",
+			)?;
+		}
+		if keys.is_empty() {
+			return Ok(());
+		}
+
+		let cmd = if lazy { "UNLINK" } else { "DEL" };
+		let mut pipe = redis::pipe();
+		pipe.atomic();
+		for key in keys {
+			pipe.cmd(cmd).arg(Self::build_key(primary_namespace, secondary_namespace, key));
+		}
+
+		let mut locked_conn = self.connection.lock().unwrap();
+		pipe.query::<()>(&mut *locked_conn).map_err(|e| {
+			let msg = format!(
+				"Failed to delete batch from namespace {}/{}: {}",
+				PrintableString(primary_namespace),
+				PrintableString(secondary_namespace),
+				e
+			);
+			Error::new(ErrorKind::Other, msg)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::io::test_utils::{do_batch_write_and_remove, do_read_write_remove_list_persist};
+
+	#[test]
+	fn read_write_remove_list_persist() {
+		let redis_url = std::env::var("TEST_REDIS_URL").unwrap();
+		let store = RedisStore::new(redis_url).unwrap();
+
+		do_read_write_remove_list_persist(&store);
+	}
+
+	#[test]
+	fn listed_keys_are_deduplicated() {
+		let prefix = "testspace:testsubspace:";
+		let redis_keys = vec![
+			"testspace:testsubspace:key_a".to_string(),
+			"testspace:testsubspace:key_b".to_string(),
+			"testspace:testsubspace:key_a".to_string(),
+			"testspace:otherspace:key_c".to_string(),
+		];
+		let mut keys = keys_with_prefix(prefix, redis_keys.into_iter());
+		keys.sort();
+		assert_eq!(keys, vec!["key_a".to_string(), "key_b".to_string()]);
+	}
+
+	#[test]
+	fn batch_write_and_remove() {
+		let redis_url = std::env::var("TEST_REDIS_URL").unwrap();
+		let mut store = RedisStore::new(redis_url).unwrap();
+		store.set_ttl("batchspace", "", Some("expiring"), 3600).unwrap();
+
+		do_batch_write_and_remove(&store);
+
+		// TTLs are honored for entries written in a batch.
+		let entries: [(&str, &[u8]); 2] = [("expiring", &[1u8; 32]), ("persistent", &[2u8; 32])];
+		store.write_batch("batchspace", "", &entries).unwrap();
+		let mut locked_conn = store.connection.lock().unwrap();
+		let ttl: i64 = redis::cmd("TTL")
+			.arg(RedisStore::build_key("batchspace", "", "expiring"))
+			.query(&mut *locked_conn)
+			.unwrap();
+		assert!(ttl > 0 && ttl <= 3600);
+		let ttl: i64 = redis::cmd("TTL")
+			.arg(RedisStore::build_key("batchspace", "", "persistent"))
+			.query(&mut *locked_conn)
+			.unwrap();
+		assert_eq!(ttl, -1);
+		drop(locked_conn);
+
+		store.remove_batch("batchspace", "", &["expiring", "persistent"], true).unwrap();
+		assert!(store.list("batchspace", "").unwrap().is_empty());
+	}
+}
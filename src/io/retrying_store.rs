@@ -0,0 +1,230 @@
+//! Objects related to [`RetryingKVStore`] live here.
+use crate::io::batch_kv_store::BatchKVStore;
+use crate::io::utils::check_namespace_key_validity;
+
+use lightning::util::persist::KVStore;
+
+use tokio::runtime::RuntimeFlavor;
+
+use std::ops::Deref;
+use std::time::Duration;
+
+/// The policy used by [`RetryingKVStore`] to retry failed operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KVStoreRetryPolicy {
+	/// The maximum number of attempts made for each operation, including the first one.
+	pub max_attempts: u32,
+	/// The time to wait before the first retry. It is doubled after each further failed attempt.
+	pub initial_backoff: Duration,
+	/// The maximum time to wait between two attempts.
+	pub max_backoff: Duration,
+}
+
+impl Default for KVStoreRetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_attempts: 3,
+			initial_backoff: Duration::from_millis(100),
+			max_backoff: Duration::from_secs(2),
+		}
+	}
+}
+
+/// A [`KVStore`] decorator retrying failed operations of the wrapped store with exponential
+/// backoff.
+///
+/// This is useful for network-backed stores that may return transient errors. Errors that won't
+/// be resolved by retrying, such as invalid namespaces or keys, unknown keys, or invalid data,
+/// are returned immediately.
+pub struct RetryingKVStore<K: Deref>
+where
+	K::Target: KVStore,
+{
+	inner: K,
+	policy: KVStoreRetryPolicy,
+}
+
+impl<K: Deref> RetryingKVStore<K>
+where
+	K::Target: KVStore,
+{
+	/// Constructs a new [`RetryingKVStore`] wrapping the given store.
+	pub fn new(inner: K, policy: KVStoreRetryPolicy) -> Self {
+		Self { inner, policy }
+	}
+
+	fn with_retries<T, F: Fn() -> std::io::Result<T>>(&self, f: F) -> std::io::Result<T> {
+		let mut backoff = self.policy.initial_backoff;
+		let mut attempt = 1;
+		loop {
+			match f() {
+				Ok(res) => return Ok(res),
+				Err(e) if attempt >= self.policy.max_attempts || !is_retryable(&e) => {
+					return Err(e)
+				},
+				Err(_) => {
+					wait_for_backoff(backoff);
+					backoff = (backoff * 2).min(self.policy.max_backoff);
+					attempt += 1;
+				},
+			}
+		}
+	}
+}
+
+fn wait_for_backoff(backoff: Duration) {
+	// Store operations are regularly invoked from tasks of the node's runtime, in which case we
+	// must not stall the other tasks scheduled on the same worker thread while we wait.
+	match tokio::runtime::Handle::try_current() {
+		Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+			tokio::task::block_in_place(|| std::thread::sleep(backoff))
+		},
+		_ => std::thread::sleep(backoff),
+	}
+}
+
+fn is_retryable(e: &std::io::Error) -> bool {
+	match e.kind() {
+		std::io::ErrorKind::NotFound
+		| std::io::ErrorKind::InvalidInput
+		| std::io::ErrorKind::InvalidData
+		| std::io::ErrorKind::PermissionDenied
+		| std::io::ErrorKind::Unsupported => false,
+		_ => true,
+	}
+}
+
+impl<K: Deref> KVStore for RetryingKVStore<K>
+where
+	K::Target: KVStore,
+{
+	fn read(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str,
+	) -> std::io::Result<Vec<u8>> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "read")?;
+		self.with_retries(|| self.inner.read(primary_namespace, secondary_namespace, key))
+	}
+
+	fn write(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, buf: &[u8],
+	) -> std::io::Result<()> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "write")?;
+		self.with_retries(|| self.inner.write(primary_namespace, secondary_namespace, key, buf))
+	}
+
+	fn remove(
+		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, lazy: bool,
+	) -> std::io::Result<()> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "remove")?;
+		self.with_retries(|| self.inner.remove(primary_namespace, secondary_namespace, key, lazy))
+	}
+
+	fn list(
+		&self, primary_namespace: &str, secondary_namespace: &str,
+	) -> std::io::Result<Vec<String>> {
+		check_namespace_key_validity(primary_namespace, secondary_namespace, None, "list")?;
+		self.with_retries(|| self.inner.list(primary_namespace, secondary_namespace))
+	}
+}
+
+impl<K: Deref> BatchKVStore for RetryingKVStore<K> where K::Target: KVStore {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use lightning::util::test_utils::TestStore;
+	use std::sync::atomic::{AtomicU32, Ordering};
+	use std::sync::Arc;
+
+	struct FlakyStore {
+		inner: TestStore,
+		remaining_failures: AtomicU32,
+	}
+
+	impl FlakyStore {
+		fn maybe_fail(&self) -> std::io::Result<()> {
+			let remaining = self.remaining_failures.load(Ordering::SeqCst);
+			if remaining > 0 {
+				self.remaining_failures.store(remaining - 1, Ordering::SeqCst);
+				return Err(std::io::Error::new(std::io::ErrorKind::Other, "transient failure"));
+			}
+			Ok(())
+		}
+	}
+
+	impl KVStore for FlakyStore {
+		fn read(&self, p: &str, s: &str, k: &str) -> std::io::Result<Vec<u8>> {
+			self.maybe_fail()?;
+			self.inner.read(p, s, k)
+		}
+
+		fn write(&self, p: &str, s: &str, k: &str, buf: &[u8]) -> std::io::Result<()> {
+			self.maybe_fail()?;
+			self.inner.write(p, s, k, buf)
+		}
+
+		fn remove(&self, p: &str, s: &str, k: &str, lazy: bool) -> std::io::Result<()> {
+			self.maybe_fail()?;
+			self.inner.remove(p, s, k, lazy)
+		}
+
+		fn list(&self, p: &str, s: &str) -> std::io::Result<Vec<String>> {
+			self.maybe_fail()?;
+			self.inner.list(p, s)
+		}
+	}
+
+	#[test]
+	fn transient_failures_are_retried() {
+		let flaky_store = Arc::new(FlakyStore {
+			inner: TestStore::new(false),
+			remaining_failures: AtomicU32::new(2),
+		});
+		let policy = KVStoreRetryPolicy {
+			max_attempts: 3,
+			initial_backoff: Duration::from_millis(1),
+			max_backoff: Duration::from_millis(2),
+		};
+		let store = RetryingKVStore::new(Arc::clone(&flaky_store), policy);
+
+		store.write("primary", "secondary", "key", &[42u8]).unwrap();
+		assert_eq!(store.read("primary", "secondary", "key").unwrap(), vec![42u8]);
+
+		// We give up after `max_attempts`.
+		flaky_store.remaining_failures.store(3, Ordering::SeqCst);
+		assert!(store.read("primary", "secondary", "key").is_err());
+		assert_eq!(flaky_store.remaining_failures.load(Ordering::SeqCst), 0);
+
+		// Unknown keys are not retried.
+		let err = store.read("primary", "secondary", "unknown_key").unwrap_err();
+		assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+		// Invalid keys are rejected right away.
+		flaky_store.remaining_failures.store(1, Ordering::SeqCst);
+		assert!(store.read("primary", "secondary", "invalid key").is_err());
+		assert_eq!(flaky_store.remaining_failures.load(Ordering::SeqCst), 1);
+	}
+
+	#[test]
+	fn transient_failures_are_retried_on_runtime_worker() {
+		let flaky_store = Arc::new(FlakyStore {
+			inner: TestStore::new(false),
+			remaining_failures: AtomicU32::new(2),
+		});
+		let policy = KVStoreRetryPolicy {
+			max_attempts: 3,
+			initial_backoff: Duration::from_millis(1),
+			max_backoff: Duration::from_millis(2),
+		};
+		let store = Arc::new(RetryingKVStore::new(Arc::clone(&flaky_store), policy));
+
+		let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+		let task_store = Arc::clone(&store);
+		runtime
+			.block_on(runtime.spawn(async move {
+				task_store.write("primary", "secondary", "key", &[42u8]).unwrap();
+			}))
+			.unwrap();
+		assert_eq!(store.read("primary", "secondary", "key").unwrap(), vec![42u8]);
+	}
+}
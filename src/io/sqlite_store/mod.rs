@@ -1,4 +1,5 @@
 //! Objects related to [`SqliteStore`] live here.
+use crate::io::batch_kv_store::BatchKVStore;
 use crate::io::utils::check_namespace_key_validity;
 
 use lightning::io;
@@ -281,11 +282,119 @@ impl KVStore for SqliteStore {
 	}
 }
 
+impl BatchKVStore for SqliteStore {
+	fn write_batch(
+		&self, primary_namespace: &str, secondary_namespace: &str, entries: &[(&str, &[u8])],
+	) -> std::io::Result<()> {
+		for (key, _) in entries {
+			check_namespace_key_validity(
+				primary_namespace,
+				secondary_namespace,
+				Some(*key),
+				"write",
+			)?;
+		}
+
+		let mut locked_conn = self.connection.lock().unwrap();
+		let tx = locked_conn.transaction().map_err(|e| {
+			let msg = format!("Failed to start transaction: {}", e);
+			std::io::Error::new(std::io::ErrorKind::Other, msg)
+		})?;
+
+		{
+			let sql = format!(
+				"INSERT OR REPLACE INTO {} (primary_namespace, secondary_namespace, key, value) VALUES (:primary_namespace, :secondary_namespace, :key, :value);",
+				self.kv_table_name
+			);
+			let mut stmt = tx.prepare_cached(&sql).map_err(|e| {
+				let msg = format!("Failed to prepare statement: {}", e);
+				std::io::Error::new(std::io::ErrorKind::Other, msg)
+			})?;
+
+			for (key, buf) in entries {
+				stmt.execute(named_params! {
+					":primary_namespace": primary_namespace,
+					":secondary_namespace": secondary_namespace,
+					":key": key,
+					":value": buf,
+				})
+				.map_err(|e| {
+					let msg = format!(
+						"Failed to write to key {}/{}/{}: {}",
+						PrintableString(primary_namespace),
+						PrintableString(secondary_namespace),
+						PrintableString(key),
+						e
+					);
+					std::io::Error::new(std::io::ErrorKind::Other, msg)
+				})?;
+			}
+		}
+
+		// If any of the writes failed, the transaction is rolled back when dropped.
+		tx.commit().map_err(|e| {
+			let msg = format!("Failed to commit transaction: {}", e);
+			std::io::Error::new(std::io::ErrorKind::Other, msg)
+		})
+	}
+
+	fn remove_batch(
+		&self, primary_namespace: &str, secondary_namespace: &str, keys: &[&str], _lazy: bool,
+	) -> std::io::Result<()> {
+		for key in keys {
+			check_namespace_key_validity(
+				primary_namespace,
+				secondary_namespace,
+				Some(*key),
+				"remove",
+			)?;
+		}
+
+		let mut locked_conn = self.connection.lock().unwrap();
+		let tx = locked_conn.transaction().map_err(|e| {
+			let msg = format!("Failed to start transaction: {}", e);
+			std::io::Error::new(std::io::ErrorKind::Other, msg)
+		})?;
+
+		{
+			let sql = format!("DELETE FROM {} WHERE primary_namespace=:primary_namespace AND secondary_namespace=:secondary_namespace AND key=:key;", self.kv_table_name);
+			let mut stmt = tx.prepare_cached(&sql).map_err(|e| {
+				let msg = format!("Failed to prepare statement: {}", e);
+				std::io::Error::new(std::io::ErrorKind::Other, msg)
+			})?;
+
+			for key in keys {
+				stmt.execute(named_params! {
+					":primary_namespace": primary_namespace,
+					":secondary_namespace": secondary_namespace,
+					":key": key,
+				})
+				.map_err(|e| {
+					let msg = format!(
+						"Failed to delete key {}/{}/{}: {}",
+						PrintableString(primary_namespace),
+						PrintableString(secondary_namespace),
+						PrintableString(key),
+						e
+					);
+					std::io::Error::new(std::io::ErrorKind::Other, msg)
+				})?;
+			}
+		}
+
+		tx.commit().map_err(|e| {
+			let msg = format!("Failed to commit transaction: {}", e);
+			std::io::Error::new(std::io::ErrorKind::Other, msg)
+		})
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 	use crate::io::test_utils::{
-		do_read_write_remove_list_persist, do_test_store, random_storage_path,
+		do_batch_write_and_remove, do_read_write_remove_list_persist, do_test_store,
+		random_storage_path,
 	};
 
 	impl Drop for SqliteStore {
@@ -328,6 +437,19 @@ mod tests {
 		.unwrap();
 		do_test_store(&store_0, &store_1)
 	}
+
+	#[test]
+	fn batch_write_and_remove() {
+		let mut temp_path = random_storage_path();
+		temp_path.push("batch_write_and_remove");
+		let store = SqliteStore::new(
+			temp_path,
+			Some("test_db".to_string()),
+			Some("test_table".to_string()),
+		)
+		.unwrap();
+		do_batch_write_and_remove(&store);
+	}
 }
 
 #[cfg(ldk_bench)]
@@ -1,7 +1,13 @@
+use crate::io::batch_kv_store::BatchKVStore;
+
+use lightning::ln::features::ChannelFeatures;
 use lightning::ln::functional_test_utils::{
 	connect_block, create_announced_chan_between_nodes, create_chanmon_cfgs, create_dummy_block,
 	create_network, create_node_cfgs, create_node_chanmgrs, send_payment,
 };
+use lightning::ln::msgs::UnsignedChannelUpdate;
+use lightning::routing::gossip::NetworkGraph;
+use lightning::util::logger::Logger;
 use lightning::util::persist::{read_channel_monitors, KVStore, KVSTORE_NAMESPACE_KEY_MAX_LEN};
 
 use lightning::chain::channelmonitor::CLOSED_CHANNEL_UPDATE_ID;
@@ -9,11 +15,21 @@ use lightning::events::ClosureReason;
 use lightning::util::test_utils;
 use lightning::{check_added_monitors, check_closed_broadcast, check_closed_event};
 
+use bitcoin::blockdata::constants::ChainHash;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::Network;
+
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 
+use std::ops::Deref;
 use std::panic::RefUnwindSafe;
 use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The maximum HTLC amount advertised for the channels of test network graphs, which is also what
+// the channels' capacity is assumed to be.
+pub(crate) const TEST_CHANNEL_HTLC_MAXIMUM_MSAT: u64 = 100_000_000;
 
 pub(crate) fn random_storage_path() -> PathBuf {
 	let mut temp_path = std::env::temp_dir();
@@ -72,6 +88,85 @@ pub(crate) fn do_read_write_remove_list_persist<K: KVStore + RefUnwindSafe>(kv_s
 	assert_eq!(listed_keys.len(), 0);
 }
 
+// Tests that a `BatchKVStore` applies all entries of a batch, leaving other entries untouched.
+pub(crate) fn do_batch_write_and_remove<K: BatchKVStore>(kv_store: &K) {
+	let primary_namespace = "testspace";
+	let secondary_namespace = "testsubspace";
+	kv_store.write(primary_namespace, secondary_namespace, "untouched", &[0u8; 32]).unwrap();
+
+	let entries: [(&str, &[u8]); 3] =
+		[("testkey_a", &[1u8; 32]), ("testkey_b", &[2u8; 32]), ("testkey_c", &[3u8; 32])];
+	kv_store.write_batch(primary_namespace, secondary_namespace, &entries).unwrap();
+	for (key, data) in entries {
+		let read_data = kv_store.read(primary_namespace, secondary_namespace, key).unwrap();
+		assert_eq!(data, &*read_data);
+	}
+
+	// Entries written in a batch may be overwritten in a batch.
+	let entries: [(&str, &[u8]); 1] = [("testkey_a", &[4u8; 32])];
+	kv_store.write_batch(primary_namespace, secondary_namespace, &entries).unwrap();
+	let read_data = kv_store.read(primary_namespace, secondary_namespace, "testkey_a").unwrap();
+	assert_eq!([4u8; 32], &*read_data);
+
+	let keys = ["testkey_a", "testkey_b", "testkey_c", "testkey_unknown"];
+	kv_store.remove_batch(primary_namespace, secondary_namespace, &keys, false).unwrap();
+	let listed_keys = kv_store.list(primary_namespace, secondary_namespace).unwrap();
+	assert_eq!(listed_keys, vec!["untouched".to_string()]);
+
+	// Empty batches are a no-op.
+	kv_store.write_batch(primary_namespace, secondary_namespace, &[]).unwrap();
+	kv_store.remove_batch(primary_namespace, secondary_namespace, &[], true).unwrap();
+	assert_eq!(kv_store.list(primary_namespace, secondary_namespace).unwrap().len(), 1);
+
+	kv_store.remove(primary_namespace, secondary_namespace, "untouched", false).unwrap();
+}
+
+// Returns the public keys of `num_nodes` distinct test nodes.
+pub(crate) fn test_node_ids(num_nodes: u8) -> Vec<PublicKey> {
+	let secp_ctx = Secp256k1::new();
+	(1..=num_nodes)
+		.map(|i| PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[i; 32]).unwrap()))
+		.collect()
+}
+
+// Creates a testnet network graph holding the given `(short_channel_id, node_one, node_two)`
+// channels. As when learned via rapid gossip sync, the channels' capacity is unknown, and only
+// their direction from `node_one` to `node_two` is known.
+pub(crate) fn create_test_network_graph<L: Deref>(
+	channels: &[(u64, PublicKey, PublicKey)], logger: L,
+) -> NetworkGraph<L>
+where
+	L::Target: Logger,
+{
+	let network_graph = NetworkGraph::new(Network::Testnet, logger);
+	let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+	for (short_channel_id, node_one, node_two) in channels {
+		network_graph
+			.add_channel_from_partial_announcement(
+				*short_channel_id,
+				timestamp,
+				ChannelFeatures::empty(),
+				*node_one,
+				*node_two,
+			)
+			.unwrap();
+		let update = UnsignedChannelUpdate {
+			chain_hash: ChainHash::using_genesis_block(Network::Testnet),
+			short_channel_id: *short_channel_id,
+			timestamp: timestamp as u32,
+			flags: 0,
+			cltv_expiry_delta: 144,
+			htlc_minimum_msat: 1_000,
+			htlc_maximum_msat: TEST_CHANNEL_HTLC_MAXIMUM_MSAT,
+			fee_base_msat: 1_000,
+			fee_proportional_millionths: 100,
+			excess_data: Vec::new(),
+		};
+		network_graph.update_channel_unsigned(&update).unwrap();
+	}
+	network_graph
+}
+
 // Integration-test the given KVStore implementation. Test relaying a few payments and check that
 // the persisted data is updated the appropriate number of times.
 pub(crate) fn do_test_store<K: KVStore>(store_0: &K, store_1: &K) {
@@ -1,26 +1,39 @@
 use super::*;
+use crate::backup::RecoveredChannel;
 use crate::config::WALLET_KEYS_SEED_LEN;
+use crate::fee_estimator::PersistedFeeRateCache;
+use crate::hold_invoice::HeldPayment;
 
+use crate::liquidity::PendingJitChannelQuote;
 use crate::logger::log_error;
+use crate::onchain_monitor::OnchainPaymentMonitorState;
+use crate::payment_retry::PendingPaymentRetry;
 use crate::peer_store::PeerStore;
 use crate::sweep::SpendableOutputInfo;
-use crate::{Error, EventQueue, PaymentDetails};
+use crate::{DeadLetterEvent, Error, EventQueue, PaymentDetails};
 
+use lightning::ln::msgs::DecodeError;
 use lightning::routing::gossip::NetworkGraph;
 use lightning::routing::scoring::{ProbabilisticScorer, ProbabilisticScoringDecayParameters};
 use lightning::util::logger::Logger;
 use lightning::util::persist::{
-	KVStore, KVSTORE_NAMESPACE_KEY_ALPHABET, KVSTORE_NAMESPACE_KEY_MAX_LEN,
-	NETWORK_GRAPH_PERSISTENCE_KEY, NETWORK_GRAPH_PERSISTENCE_PRIMARY_NAMESPACE,
-	NETWORK_GRAPH_PERSISTENCE_SECONDARY_NAMESPACE, SCORER_PERSISTENCE_KEY,
-	SCORER_PERSISTENCE_PRIMARY_NAMESPACE, SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+	KVStore, CHANNEL_MANAGER_PERSISTENCE_KEY, CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+	KVSTORE_NAMESPACE_KEY_ALPHABET, KVSTORE_NAMESPACE_KEY_MAX_LEN, NETWORK_GRAPH_PERSISTENCE_KEY,
+	NETWORK_GRAPH_PERSISTENCE_PRIMARY_NAMESPACE, NETWORK_GRAPH_PERSISTENCE_SECONDARY_NAMESPACE,
+	SCORER_PERSISTENCE_KEY, SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
+	SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
 };
 use lightning::util::ser::{Readable, ReadableArgs, Writeable};
 use lightning::util::string::PrintableString;
 
+use argon2::Argon2;
 use bip39::Mnemonic;
+use bitcoin::secp256k1::PublicKey;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
 use rand::{thread_rng, RngCore};
 
+use std::collections::HashMap;
 use std::fs;
 use std::io::{Cursor, Write};
 use std::ops::Deref;
@@ -36,14 +49,45 @@ use std::sync::Arc;
 /// [`Node`]: crate::Node
 /// [`Builder::set_entropy_bip39_mnemonic`]: crate::Builder::set_entropy_bip39_mnemonic
 pub fn generate_entropy_mnemonic() -> Mnemonic {
+	generate_entropy_mnemonic_from_rng(&mut thread_rng())
+}
+
+/// Generates a random [BIP 39] mnemonic, drawing the entropy from the given source of randomness.
+///
+/// This allows to generate reproducible mnemonics, e.g., for testing purposes.
+///
+/// [BIP 39]: https://github.com/bitcoin/bips/blob/master/bip-0039.mediawiki
+pub fn generate_entropy_mnemonic_from_rng(rng: &mut dyn RngCore) -> Mnemonic {
 	// bip39::Mnemonic supports 256 bit entropy max
 	let mut entropy = [0; 32];
-	thread_rng().fill_bytes(&mut entropy);
+	rng.fill_bytes(&mut entropy);
 	Mnemonic::from_entropy(&entropy).unwrap()
 }
 
+/// The version byte prepended to encrypted seed files.
+const ENCRYPTED_SEED_FILE_VERSION: u8 = 1;
+const ENCRYPTED_SEED_FILE_SALT_LEN: usize = 16;
+const ENCRYPTED_SEED_FILE_NONCE_LEN: usize = 12;
+const ENCRYPTED_SEED_FILE_TAG_LEN: usize = 16;
+const ENCRYPTED_SEED_FILE_LEN: usize = 1
+	+ ENCRYPTED_SEED_FILE_SALT_LEN
+	+ ENCRYPTED_SEED_FILE_NONCE_LEN
+	+ WALLET_KEYS_SEED_LEN
+	+ ENCRYPTED_SEED_FILE_TAG_LEN;
+
+/// Reads the keys seed from the given file, or generates and persists a new random one if it
+/// doesn't exist.
+///
+/// If a `passphrase` is given, the seed is stored encrypted with ChaCha20-Poly1305 under a key
+/// derived via Argon2. The encrypted file format is `version || salt || nonce || ciphertext`.
+/// Existing plaintext seed files are transparently migrated to the encrypted format.
+///
+/// Will return an error of kind [`std::io::ErrorKind::InvalidInput`] if the file is encrypted and
+/// the given passphrase is wrong or missing.
+///
+/// The given `rng` is only used when generating a new seed file, an existing file is read as-is.
 pub(crate) fn read_or_generate_seed_file<L: Deref>(
-	keys_seed_path: &str, logger: L,
+	keys_seed_path: &str, passphrase: Option<&str>, rng: &mut dyn RngCore, logger: L,
 ) -> std::io::Result<[u8; WALLET_KEYS_SEED_LEN]>
 where
 	L::Target: Logger,
@@ -54,6 +98,24 @@ where
 			e
 		})?;
 
+		if seed.len() == ENCRYPTED_SEED_FILE_LEN && seed[0] == ENCRYPTED_SEED_FILE_VERSION {
+			let passphrase = passphrase.ok_or_else(|| {
+				log_error!(
+					logger,
+					"Failed to read encrypted keys seed file as no passphrase was given: {}",
+					keys_seed_path
+				);
+				std::io::Error::new(
+					std::io::ErrorKind::InvalidInput,
+					"Failed to read encrypted keys seed file as no passphrase was given",
+				)
+			})?;
+			return decrypt_seed(&seed, passphrase).map_err(|e| {
+				log_error!(logger, "Failed to decrypt keys seed file: {}", keys_seed_path);
+				e
+			});
+		}
+
 		if seed.len() != WALLET_KEYS_SEED_LEN {
 			log_error!(
 				logger,
@@ -68,28 +130,248 @@ where
 
 		let mut key = [0; WALLET_KEYS_SEED_LEN];
 		key.copy_from_slice(&seed);
+
+		if let Some(passphrase) = passphrase {
+			// Migrate the legacy plaintext seed file to the encrypted format. We write to a
+			// temporary file first to make sure we never end up with a partially written seed.
+			let encrypted_seed = encrypt_seed(&key, passphrase, rng)?;
+			let tmp_path = format!("{}.tmp", keys_seed_path);
+			write_seed_file(&tmp_path, &encrypted_seed, &logger)?;
+			fs::rename(&tmp_path, keys_seed_path).map_err(|e| {
+				log_error!(logger, "Failed to migrate keys seed file: {}", keys_seed_path);
+				e
+			})?;
+		}
+
 		Ok(key)
 	} else {
 		let mut key = [0; WALLET_KEYS_SEED_LEN];
-		thread_rng().fill_bytes(&mut key);
+		rng.fill_bytes(&mut key);
 
-		let mut f = fs::File::create(keys_seed_path).map_err(|e| {
-			log_error!(logger, "Failed to create keys seed file: {}", keys_seed_path);
-			e
-		})?;
+		if let Some(passphrase) = passphrase {
+			let encrypted_seed = encrypt_seed(&key, passphrase, rng)?;
+			write_seed_file(keys_seed_path, &encrypted_seed, &logger)?;
+		} else {
+			write_seed_file(keys_seed_path, &key, &logger)?;
+		}
 
-		f.write_all(&key).map_err(|e| {
-			log_error!(logger, "Failed to write node keys seed to disk: {}", keys_seed_path);
-			e
-		})?;
+		Ok(key)
+	}
+}
+
+fn write_seed_file<L: Deref>(keys_seed_path: &str, data: &[u8], logger: &L) -> std::io::Result<()>
+where
+	L::Target: Logger,
+{
+	let mut f = fs::File::create(keys_seed_path).map_err(|e| {
+		log_error!(logger, "Failed to create keys seed file: {}", keys_seed_path);
+		e
+	})?;
 
-		f.sync_all().map_err(|e| {
-			log_error!(logger, "Failed to sync node keys seed to disk: {}", keys_seed_path);
+	f.write_all(data).map_err(|e| {
+		log_error!(logger, "Failed to write node keys seed to disk: {}", keys_seed_path);
+		e
+	})?;
+
+	f.sync_all().map_err(|e| {
+		log_error!(logger, "Failed to sync node keys seed to disk: {}", keys_seed_path);
+		e
+	})?;
+
+	Ok(())
+}
+
+fn derive_seed_encryption_key(passphrase: &str, salt: &[u8]) -> std::io::Result<[u8; 32]> {
+	let mut key = [0u8; 32];
+	Argon2::default().hash_password_into(passphrase.as_bytes(), salt, &mut key).map_err(|_| {
+		std::io::Error::new(std::io::ErrorKind::Other, "Failed to derive seed encryption key")
+	})?;
+	Ok(key)
+}
+
+fn encrypt_seed(
+	seed: &[u8; WALLET_KEYS_SEED_LEN], passphrase: &str, rng: &mut dyn RngCore,
+) -> std::io::Result<Vec<u8>> {
+	let mut salt = [0u8; ENCRYPTED_SEED_FILE_SALT_LEN];
+	rng.fill_bytes(&mut salt);
+	let mut nonce = [0u8; ENCRYPTED_SEED_FILE_NONCE_LEN];
+	rng.fill_bytes(&mut nonce);
+
+	let key = derive_seed_encryption_key(passphrase, &salt)?;
+	let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+	let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), &seed[..]).map_err(|_| {
+		std::io::Error::new(std::io::ErrorKind::Other, "Failed to encrypt keys seed")
+	})?;
+
+	let mut res = Vec::with_capacity(ENCRYPTED_SEED_FILE_LEN);
+	res.push(ENCRYPTED_SEED_FILE_VERSION);
+	res.extend_from_slice(&salt);
+	res.extend_from_slice(&nonce);
+	res.extend_from_slice(&ciphertext);
+	Ok(res)
+}
+
+fn decrypt_seed(data: &[u8], passphrase: &str) -> std::io::Result<[u8; WALLET_KEYS_SEED_LEN]> {
+	let salt_end = 1 + ENCRYPTED_SEED_FILE_SALT_LEN;
+	let nonce_end = salt_end + ENCRYPTED_SEED_FILE_NONCE_LEN;
+	let salt = &data[1..salt_end];
+	let nonce = &data[salt_end..nonce_end];
+	let ciphertext = &data[nonce_end..];
+
+	let key = derive_seed_encryption_key(passphrase, salt)?;
+	let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+	let plaintext = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| {
+		std::io::Error::new(
+			std::io::ErrorKind::InvalidInput,
+			"Failed to decrypt keys seed file due to invalid passphrase",
+		)
+	})?;
+
+	let mut seed = [0; WALLET_KEYS_SEED_LEN];
+	seed.copy_from_slice(&plaintext);
+	Ok(seed)
+}
+
+/// The version byte prepended to store snapshots.
+const STORE_SNAPSHOT_VERSION: u8 = 1;
+
+/// Returns the distinct namespaces included in store snapshots.
+///
+/// Many of our [`PERSISTED_NAMESPACES`] share the top-level namespace, so we only list each once.
+fn store_snapshot_namespaces() -> Vec<(&'static str, &'static str)> {
+	let mut namespaces = PERSISTED_NAMESPACES
+		.iter()
+		.map(|n| (n.primary_namespace, n.secondary_namespace))
+		.collect::<Vec<_>>();
+	namespaces.sort_unstable();
+	namespaces.dedup();
+	namespaces
+}
+
+/// A single entry of a store snapshot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct StoreSnapshotEntry {
+	pub primary_namespace: String,
+	pub secondary_namespace: String,
+	pub key: String,
+	pub value: Vec<u8>,
+}
+
+/// Writes a snapshot of all data persisted in the given store to `writer`.
+///
+/// The snapshot format is `version || num_entries || entries`, where each entry consists of the
+/// length-prefixed primary namespace, secondary namespace, key, and value.
+pub(crate) fn write_store_snapshot<K: KVStore + Sync + Send, W: Write, L: Deref>(
+	kv_store: &K, writer: &mut W, logger: L,
+) -> std::io::Result<()>
+where
+	L::Target: Logger,
+{
+	let mut keys = Vec::new();
+	for (primary_namespace, secondary_namespace) in store_snapshot_namespaces() {
+		for key in kv_store.list(primary_namespace, secondary_namespace)? {
+			keys.push((primary_namespace, secondary_namespace, key));
+		}
+	}
+
+	writer.write_all(&[STORE_SNAPSHOT_VERSION])?;
+	writer.write_all(&(keys.len() as u64).to_be_bytes())?;
+	for (primary_namespace, secondary_namespace, key) in keys {
+		let value = kv_store.read(primary_namespace, secondary_namespace, &key).map_err(|e| {
+			log_error!(
+				logger,
+				"Failed to read {}/{}/{} for snapshot: {}",
+				primary_namespace,
+				secondary_namespace,
+				key,
+				e
+			);
 			e
 		})?;
+		write_snapshot_field(writer, primary_namespace.as_bytes())?;
+		write_snapshot_field(writer, secondary_namespace.as_bytes())?;
+		write_snapshot_field(writer, key.as_bytes())?;
+		write_snapshot_field(writer, &value)?;
+	}
+	writer.flush()
+}
 
-		Ok(key)
+/// Reads a snapshot previously written via [`write_store_snapshot`].
+///
+/// Fails if the snapshot is malformed, including if any of the contained namespaces or keys are
+/// invalid.
+pub(crate) fn read_store_snapshot<R: std::io::Read>(
+	reader: &mut R,
+) -> std::io::Result<Vec<StoreSnapshotEntry>> {
+	let mut version = [0u8; 1];
+	std::io::Read::read_exact(reader, &mut version)?;
+	if version[0] != STORE_SNAPSHOT_VERSION {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"Unsupported store snapshot version",
+		));
+	}
+
+	let mut num_entries_bytes = [0u8; 8];
+	std::io::Read::read_exact(reader, &mut num_entries_bytes)?;
+	let num_entries = u64::from_be_bytes(num_entries_bytes);
+
+	let mut entries = Vec::new();
+	for _ in 0..num_entries {
+		let primary_namespace = read_snapshot_string(reader)?;
+		let secondary_namespace = read_snapshot_string(reader)?;
+		let key = read_snapshot_string(reader)?;
+		let value = read_snapshot_field(reader)?;
+		check_namespace_key_validity(
+			&primary_namespace,
+			&secondary_namespace,
+			Some(&key),
+			"import",
+		)?;
+		entries.push(StoreSnapshotEntry { primary_namespace, secondary_namespace, key, value });
+	}
+	Ok(entries)
+}
+
+/// Writes the given snapshot entries to the store.
+pub(crate) fn restore_store_snapshot<K: KVStore + Sync + Send>(
+	kv_store: &K, entries: &[StoreSnapshotEntry],
+) -> std::io::Result<()> {
+	for entry in entries {
+		kv_store.write(
+			&entry.primary_namespace,
+			&entry.secondary_namespace,
+			&entry.key,
+			&entry.value,
+		)?;
+	}
+	Ok(())
+}
+
+fn write_snapshot_field<W: Write>(writer: &mut W, data: &[u8]) -> std::io::Result<()> {
+	writer.write_all(&(data.len() as u64).to_be_bytes())?;
+	writer.write_all(data)
+}
+
+fn read_snapshot_field<R: std::io::Read>(reader: &mut R) -> std::io::Result<Vec<u8>> {
+	let mut len_bytes = [0u8; 8];
+	std::io::Read::read_exact(reader, &mut len_bytes)?;
+	let len = u64::from_be_bytes(len_bytes);
+	let mut data = Vec::new();
+	std::io::Read::read_to_end(&mut std::io::Read::take(reader, len), &mut data)?;
+	if data.len() as u64 != len {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::UnexpectedEof,
+			"Store snapshot is truncated",
+		));
 	}
+	Ok(data)
+}
+
+fn read_snapshot_string<R: std::io::Read>(reader: &mut R) -> std::io::Result<String> {
+	String::from_utf8(read_snapshot_field(reader)?).map_err(|_| {
+		std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid string in store snapshot")
+	})
 }
 
 /// Read a previously persisted [`NetworkGraph`] from the store.
@@ -116,12 +398,11 @@ pub(crate) fn read_scorer<
 	G: Deref<Target = NetworkGraph<L>>,
 	L: Deref + Clone,
 >(
-	kv_store: Arc<K>, network_graph: G, logger: L,
+	kv_store: Arc<K>, params: ProbabilisticScoringDecayParameters, network_graph: G, logger: L,
 ) -> Result<ProbabilisticScorer<G, L>, std::io::Error>
 where
 	L::Target: Logger,
 {
-	let params = ProbabilisticScoringDecayParameters::default();
 	let mut reader = Cursor::new(kv_store.read(
 		SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
 		SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
@@ -134,143 +415,906 @@ where
 	})
 }
 
-/// Read previously persisted events from the store.
-pub(crate) fn read_event_queue<K: KVStore + Sync + Send, L: Deref + Clone>(
+/// Deserialize a scorer exported by another node, checking it is compatible with our network
+/// graph.
+pub(crate) fn read_imported_scorer<G: Deref<Target = NetworkGraph<L>> + Clone, L: Deref + Clone>(
+	scorer_bytes: &[u8], params: ProbabilisticScoringDecayParameters, network_graph: G, logger: L,
+) -> Result<ProbabilisticScorer<G, L>, Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(scorer_bytes);
+	let args = (params, network_graph.clone(), logger.clone());
+	let scorer = ProbabilisticScorer::read(&mut reader, args).map_err(|e| {
+		log_error!(logger, "Failed to deserialize scorer: {}", e);
+		Error::InvalidScorer
+	})?;
+
+	if reader.position() != scorer_bytes.len() as u64 {
+		log_error!(logger, "Failed to import scorer due to trailing data");
+		return Err(Error::InvalidScorer);
+	}
+
+	// The scorer data is keyed by short channel id only, so a scorer that was built against
+	// another graph (e.g., another network) still deserializes fine. We hence require it to
+	// know about at least one of our channels if it isn't empty.
+	let empty_scorer = ProbabilisticScorer::new(params, network_graph.clone(), logger.clone());
+	if scorer_bytes.len() > empty_scorer.serialized_length() {
+		let known_channels = network_graph
+			.read_only()
+			.channels()
+			.unordered_iter()
+			.map(|(scid, channel)| (*scid, channel.node_one, channel.node_two))
+			.collect::<Vec<_>>();
+		let matches_graph = known_channels.iter().any(|(scid, node_one, node_two)| {
+			scorer.estimated_channel_liquidity_range(*scid, node_one).is_some()
+				|| scorer.estimated_channel_liquidity_range(*scid, node_two).is_some()
+		});
+		if !matches_graph {
+			log_error!(logger, "Failed to import scorer as it doesn't match our network graph");
+			return Err(Error::ScorerGraphMismatch);
+		}
+	}
+
+	Ok(scorer)
+}
+
+/// Read previously persisted events from the store.
+pub(crate) fn read_event_queue<K: KVStore + Sync + Send, L: Deref + Clone>(
+	kv_store: Arc<K>, logger: L,
+) -> Result<EventQueue<K, L>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(kv_store.read(
+		EVENT_QUEUE_PERSISTENCE_PRIMARY_NAMESPACE,
+		EVENT_QUEUE_PERSISTENCE_SECONDARY_NAMESPACE,
+		EVENT_QUEUE_PERSISTENCE_KEY,
+	)?);
+	EventQueue::read(&mut reader, (kv_store, logger.clone())).map_err(|e| {
+		log_error!(logger, "Failed to deserialize event queue: {}", e);
+		std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to deserialize EventQueue")
+	})
+}
+
+/// Read previously persisted address labels from the store, keyed by the respective address.
+pub(crate) fn read_address_labels<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: Arc<K>, logger: L,
+) -> Result<HashMap<String, String>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = HashMap::new();
+
+	for stored_key in kv_store.list(
+		ADDRESS_LABEL_PERSISTENCE_PRIMARY_NAMESPACE,
+		ADDRESS_LABEL_PERSISTENCE_SECONDARY_NAMESPACE,
+	)? {
+		let mut reader = Cursor::new(kv_store.read(
+			ADDRESS_LABEL_PERSISTENCE_PRIMARY_NAMESPACE,
+			ADDRESS_LABEL_PERSISTENCE_SECONDARY_NAMESPACE,
+			&stored_key,
+		)?);
+		let label = String::read(&mut reader).map_err(|e| {
+			log_error!(logger, "Failed to deserialize label of address {}: {}", stored_key, e);
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"Failed to deserialize address label",
+			)
+		})?;
+		res.insert(stored_key, label);
+	}
+	Ok(res)
+}
+
+/// Read previously persisted peer info from the store.
+pub(crate) fn read_peer_info<K: KVStore + Sync + Send, L: Deref + Clone>(
+	kv_store: Arc<K>, logger: L,
+) -> Result<PeerStore<K, L>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(kv_store.read(
+		PEER_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+		PEER_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+		PEER_INFO_PERSISTENCE_KEY,
+	)?);
+	PeerStore::read(&mut reader, (kv_store, logger.clone())).map_err(|e| {
+		log_error!(logger, "Failed to deserialize peer store: {}", e);
+		std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to deserialize PeerStore")
+	})
+}
+
+/// Read previously persisted payments information from the store.
+///
+/// If `skip_corrupt` is set, entries that fail to deserialize are skipped rather than failing the
+/// whole read. The keys of any skipped entries are returned alongside the read payments.
+pub(crate) fn read_payments<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: Arc<K>, skip_corrupt: bool, logger: L,
+) -> Result<(Vec<PaymentDetails>, Vec<String>), std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = Vec::new();
+	let mut skipped_keys = Vec::new();
+
+	for stored_key in kv_store.list(
+		PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+		PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+	)? {
+		let mut reader = Cursor::new(kv_store.read(
+			PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+			PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+			&stored_key,
+		)?);
+		match PaymentDetails::read(&mut reader) {
+			Ok(payment) => res.push(payment),
+			Err(e) if skip_corrupt => {
+				log_error!(
+					logger,
+					"Skipping corrupt PaymentDetails stored under key {}: {}",
+					stored_key,
+					e
+				);
+				skipped_keys.push(stored_key);
+			},
+			Err(e) => {
+				log_error!(logger, "Failed to deserialize PaymentDetails: {}", e);
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::InvalidData,
+					"Failed to deserialize PaymentDetails",
+				));
+			},
+		}
+	}
+	Ok((res, skipped_keys))
+}
+
+/// Read previously persisted spendable output information from the store.
+pub(crate) fn read_spendable_outputs<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: Arc<K>, logger: L,
+) -> Result<Vec<SpendableOutputInfo>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = Vec::new();
+
+	for stored_key in kv_store.list(
+		SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+		SPENDABLE_OUTPUT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+	)? {
+		let mut reader = Cursor::new(kv_store.read(
+			SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+			SPENDABLE_OUTPUT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+			&stored_key,
+		)?);
+		let output = SpendableOutputInfo::read(&mut reader).map_err(|e| {
+			log_error!(logger, "Failed to deserialize SpendableOutputInfo: {}", e);
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"Failed to deserialize SpendableOutputInfo",
+			)
+		})?;
+		res.push(output);
+	}
+	Ok(res)
+}
+
+/// Read previously persisted pending JIT-channel quotes from the store.
+pub(crate) fn read_jit_channel_quotes<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: Arc<K>, logger: L,
+) -> Result<Vec<PendingJitChannelQuote>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = Vec::new();
+
+	for stored_key in kv_store.list(
+		JIT_CHANNEL_QUOTE_PERSISTENCE_PRIMARY_NAMESPACE,
+		JIT_CHANNEL_QUOTE_PERSISTENCE_SECONDARY_NAMESPACE,
+	)? {
+		let mut reader = Cursor::new(kv_store.read(
+			JIT_CHANNEL_QUOTE_PERSISTENCE_PRIMARY_NAMESPACE,
+			JIT_CHANNEL_QUOTE_PERSISTENCE_SECONDARY_NAMESPACE,
+			&stored_key,
+		)?);
+		let quote = PendingJitChannelQuote::read(&mut reader).map_err(|e| {
+			log_error!(logger, "Failed to deserialize PendingJitChannelQuote: {}", e);
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"Failed to deserialize PendingJitChannelQuote",
+			)
+		})?;
+		res.push(quote);
+	}
+	Ok(res)
+}
+
+/// Read previously persisted payment retry state from the store.
+pub(crate) fn read_payment_retries<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: Arc<K>, logger: L,
+) -> Result<Vec<PendingPaymentRetry>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = Vec::new();
+
+	for stored_key in kv_store.list(
+		PAYMENT_RETRY_PERSISTENCE_PRIMARY_NAMESPACE,
+		PAYMENT_RETRY_PERSISTENCE_SECONDARY_NAMESPACE,
+	)? {
+		let mut reader = Cursor::new(kv_store.read(
+			PAYMENT_RETRY_PERSISTENCE_PRIMARY_NAMESPACE,
+			PAYMENT_RETRY_PERSISTENCE_SECONDARY_NAMESPACE,
+			&stored_key,
+		)?);
+		let retry = PendingPaymentRetry::read(&mut reader).map_err(|e| {
+			log_error!(logger, "Failed to deserialize PendingPaymentRetry: {}", e);
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"Failed to deserialize PendingPaymentRetry",
+			)
+		})?;
+		res.push(retry);
+	}
+	Ok(res)
+}
+
+/// Checks that the given store is reachable by writing, reading back, and removing a throwaway
+/// entry.
+pub(crate) fn probe_kv_store<K: KVStore + Sync + Send>(
+	kv_store: &K, probe_data: &[u8],
+) -> Result<(), std::io::Error> {
+	check_namespace_key_validity(
+		HEALTH_CHECK_PROBE_PRIMARY_NAMESPACE,
+		HEALTH_CHECK_PROBE_SECONDARY_NAMESPACE,
+		Some(HEALTH_CHECK_PROBE_KEY),
+		"probe",
+	)?;
+
+	kv_store.write(
+		HEALTH_CHECK_PROBE_PRIMARY_NAMESPACE,
+		HEALTH_CHECK_PROBE_SECONDARY_NAMESPACE,
+		HEALTH_CHECK_PROBE_KEY,
+		probe_data,
+	)?;
+	let read_data = kv_store.read(
+		HEALTH_CHECK_PROBE_PRIMARY_NAMESPACE,
+		HEALTH_CHECK_PROBE_SECONDARY_NAMESPACE,
+		HEALTH_CHECK_PROBE_KEY,
+	)?;
+	kv_store.remove(
+		HEALTH_CHECK_PROBE_PRIMARY_NAMESPACE,
+		HEALTH_CHECK_PROBE_SECONDARY_NAMESPACE,
+		HEALTH_CHECK_PROBE_KEY,
+		false,
+	)?;
+
+	if read_data != probe_data {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"Read back data differing from what was written",
+		));
+	}
+	Ok(())
+}
+
+/// Read previously persisted held payments from the store.
+pub(crate) fn read_held_payments<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: Arc<K>, logger: L,
+) -> Result<Vec<HeldPayment>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = Vec::new();
+
+	for stored_key in kv_store.list(
+		HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+		HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+	)? {
+		let mut reader = Cursor::new(kv_store.read(
+			HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+			HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+			&stored_key,
+		)?);
+		let held_payment = HeldPayment::read(&mut reader).map_err(|e| {
+			log_error!(logger, "Failed to deserialize HeldPayment: {}", e);
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"Failed to deserialize HeldPayment",
+			)
+		})?;
+		res.push(held_payment);
+	}
+	Ok(res)
+}
+
+/// An entry of the [`KVStore`] that could not be deserialized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorruptStoreEntry {
+	/// The primary namespace of the entry.
+	pub primary_namespace: String,
+	/// The secondary namespace of the entry.
+	pub secondary_namespace: String,
+	/// The key of the entry.
+	pub key: String,
+}
+
+/// The result of checking the consistency of the data in the [`KVStore`] via [`verify_store`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoreVerificationReport {
+	/// The entries that could not be deserialized.
+	pub corrupt_entries: Vec<CorruptStoreEntry>,
+	/// Whether the corrupt entries were moved to the [`CORRUPT_PERSISTENCE_PRIMARY_NAMESPACE`].
+	///
+	/// [`CORRUPT_PERSISTENCE_PRIMARY_NAMESPACE`]: crate::io::CORRUPT_PERSISTENCE_PRIMARY_NAMESPACE
+	pub repaired: bool,
+}
+
+/// Checks that all data persisted by `ldk-node` in the given [`KVStore`] can be deserialized.
+///
+/// Returns a report listing all entries that failed to deserialize rather than failing on the
+/// first one. If `repair` is set, corrupt entries are quarantined, i.e., moved to the
+/// [`CORRUPT_PERSISTENCE_PRIMARY_NAMESPACE`] (with the original primary namespace as the secondary
+/// namespace), so that the node is able to start again.
+///
+/// **Note:** the channel manager and channel monitors are not checked and will never be
+/// quarantined, as losing them would risk loss of funds. The scorer can only be checked if the
+/// network graph can be deserialized.
+///
+/// [`CORRUPT_PERSISTENCE_PRIMARY_NAMESPACE`]: crate::io::CORRUPT_PERSISTENCE_PRIMARY_NAMESPACE
+pub fn verify_store<K: KVStore + Sync + Send, L: Deref + Clone>(
+	kv_store: Arc<K>, repair: bool, logger: L,
+) -> Result<StoreVerificationReport, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let network_graph = match kv_store.read(
+		NETWORK_GRAPH_PERSISTENCE_PRIMARY_NAMESPACE,
+		NETWORK_GRAPH_PERSISTENCE_SECONDARY_NAMESPACE,
+		NETWORK_GRAPH_PERSISTENCE_KEY,
+	) {
+		Ok(data) => NetworkGraph::read(&mut Cursor::new(data), logger.clone()).ok(),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+		Err(e) => return Err(e),
+	};
+
+	let mut corrupt_entries = Vec::new();
+	for namespace in PERSISTED_NAMESPACES {
+		if namespace.key == Some(CHANNEL_MANAGER_PERSISTENCE_KEY)
+			|| namespace.primary_namespace == CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE
+		{
+			continue;
+		}
+
+		let keys = match namespace.key {
+			Some(key) => vec![key.to_string()],
+			None => kv_store.list(namespace.primary_namespace, namespace.secondary_namespace)?,
+		};
+		for key in keys {
+			let is_valid = |data: &[u8]| {
+				let res = decode_store_entry(
+					namespace,
+					data,
+					&kv_store,
+					network_graph.as_ref(),
+					logger.clone(),
+				);
+				!matches!(res, Some(Err(_)))
+			};
+			check_store_entry(
+				&kv_store,
+				namespace.primary_namespace,
+				namespace.secondary_namespace,
+				&key,
+				&is_valid,
+				repair,
+				&mut corrupt_entries,
+				logger.clone(),
+			)?;
+		}
+	}
+
+	Ok(StoreVerificationReport { corrupt_entries, repaired: repair })
+}
+
+/// Tries to deserialize an entry persisted under the given namespace.
+///
+/// Returns `None` if entries of the namespace can't be checked.
+fn decode_store_entry<K: KVStore + Sync + Send, L: Deref + Clone>(
+	namespace: &PersistedNamespace, data: &[u8], kv_store: &Arc<K>,
+	network_graph: Option<&NetworkGraph<L>>, logger: L,
+) -> Option<Result<(), DecodeError>>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(data);
+	let res = match (namespace.primary_namespace, namespace.key) {
+		(_, Some(EVENT_QUEUE_PERSISTENCE_KEY)) => {
+			EventQueue::read(&mut reader, (Arc::clone(kv_store), logger)).map(|_| ())
+		},
+		(DEAD_LETTER_EVENT_PERSISTENCE_PRIMARY_NAMESPACE, None) => {
+			DeadLetterEvent::read(&mut reader).map(|_| ())
+		},
+		(_, Some(PEER_INFO_PERSISTENCE_KEY)) => {
+			PeerStore::read(&mut reader, (Arc::clone(kv_store), logger)).map(|_| ())
+		},
+		(PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE, None) => {
+			PaymentDetails::read(&mut reader).map(|_| ())
+		},
+		(PENDING_REFUND_PERSISTENCE_PRIMARY_NAMESPACE, None) => u64::read(&mut reader).map(|_| ()),
+		(PAYMENT_RETRY_PERSISTENCE_PRIMARY_NAMESPACE, None) => {
+			PendingPaymentRetry::read(&mut reader).map(|_| ())
+		},
+		(HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE, None) => {
+			HeldPayment::read(&mut reader).map(|_| ())
+		},
+		(SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE, None) => {
+			SpendableOutputInfo::read(&mut reader).map(|_| ())
+		},
+		(JIT_CHANNEL_QUOTE_PERSISTENCE_PRIMARY_NAMESPACE, None) => {
+			PendingJitChannelQuote::read(&mut reader).map(|_| ())
+		},
+		(_, Some(LATEST_RGS_SYNC_TIMESTAMP_KEY)) => u32::read(&mut reader).map(|_| ()),
+		(_, Some(LATEST_NODE_ANN_BCAST_TIMESTAMP_KEY)) => u64::read(&mut reader).map(|_| ()),
+		(_, Some(FEE_RATE_CACHE_PERSISTENCE_KEY)) => {
+			PersistedFeeRateCache::read(&mut reader).map(|_| ())
+		},
+		(_, Some(PHANTOM_SEED_PERSISTENCE_KEY)) => <[u8; 32]>::read(&mut reader).map(|_| ()),
+		(_, Some(TRUSTED_PEERS_0CONF_PERSISTENCE_KEY)) => {
+			read_len_prefixed_vec::<PublicKey, _>(&mut reader).map(|_| ())
+		},
+		(_, Some(RECOVERED_CHANNELS_PERSISTENCE_KEY)) => {
+			read_len_prefixed_vec::<RecoveredChannel, _>(&mut reader).map(|_| ())
+		},
+		(CHANNEL_PENALTIES_PERSISTENCE_PRIMARY_NAMESPACE, None) => {
+			u64::read(&mut reader).map(|_| ())
+		},
+		(ADDRESS_LABEL_PERSISTENCE_PRIMARY_NAMESPACE, None) => {
+			String::read(&mut reader).map(|_| ())
+		},
+		(_, Some(ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_KEY)) => {
+			OnchainPaymentMonitorState::read(&mut reader).map(|_| ())
+		},
+		(_, Some(SCHEMA_VERSION_PERSISTENCE_KEY)) => u32::read(&mut reader).map(|_| ()),
+		(_, Some(NETWORK_GRAPH_PERSISTENCE_KEY)) => {
+			NetworkGraph::read(&mut reader, logger).map(|_| ())
+		},
+		(_, Some(SCORER_PERSISTENCE_KEY)) => {
+			let params = ProbabilisticScoringDecayParameters::default();
+			let args = (params, network_graph?, logger);
+			ProbabilisticScorer::read(&mut reader, args).map(|_| ())
+		},
+		_ => return None,
+	};
+	Some(res)
+}
+
+fn check_store_entry<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: &Arc<K>, primary_namespace: &str, secondary_namespace: &str, key: &str,
+	is_valid: &dyn Fn(&[u8]) -> bool, repair: bool, corrupt_entries: &mut Vec<CorruptStoreEntry>,
+	logger: L,
+) -> Result<(), std::io::Error>
+where
+	L::Target: Logger,
+{
+	let data = match kv_store.read(primary_namespace, secondary_namespace, key) {
+		Ok(data) => data,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+		Err(e) => return Err(e),
+	};
+
+	if is_valid(&data) {
+		return Ok(());
+	}
+
+	log_error!(
+		logger,
+		"Found corrupt entry under key {}/{}/{}",
+		primary_namespace,
+		secondary_namespace,
+		key
+	);
+	let entry = CorruptStoreEntry {
+		primary_namespace: primary_namespace.to_string(),
+		secondary_namespace: secondary_namespace.to_string(),
+		key: key.to_string(),
+	};
+
+	if repair {
+		quarantine_store_entry(kv_store, &entry, &data, logger)?;
+	}
+	corrupt_entries.push(entry);
+	Ok(())
+}
+
+fn quarantine_store_entry<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: &Arc<K>, entry: &CorruptStoreEntry, data: &[u8], logger: L,
+) -> Result<(), std::io::Error>
+where
+	L::Target: Logger,
+{
+	kv_store
+		.write(CORRUPT_PERSISTENCE_PRIMARY_NAMESPACE, &entry.primary_namespace, &entry.key, data)
+		.map_err(|e| {
+			log_error!(
+				logger,
+				"Writing data to key {}/{}/{} failed due to: {}",
+				CORRUPT_PERSISTENCE_PRIMARY_NAMESPACE,
+				entry.primary_namespace,
+				entry.key,
+				e
+			);
+			e
+		})?;
+	kv_store
+		.remove(&entry.primary_namespace, &entry.secondary_namespace, &entry.key, false)
+		.map_err(|e| {
+			log_error!(
+				logger,
+				"Removing key {}/{}/{} failed due to: {}",
+				entry.primary_namespace,
+				entry.secondary_namespace,
+				entry.key,
+				e
+			);
+			e
+		})
+}
+
+/// Read the state of the `OnchainPaymentMonitor`, i.e., the on-chain payments it previously handled.
+pub(crate) fn read_onchain_payment_monitor_state<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: Arc<K>, logger: L,
+) -> Result<OnchainPaymentMonitorState, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(kv_store.read(
+		ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+		ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+		ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_KEY,
+	)?);
+	OnchainPaymentMonitorState::read(&mut reader).map_err(|e| {
+		log_error!(logger, "Failed to deserialize on-chain payment monitor data: {}", e);
+		std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"Failed to deserialize on-chain payment monitor data",
+		)
+	})
+}
+
+pub(crate) fn read_latest_rgs_sync_timestamp<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: Arc<K>, logger: L,
+) -> Result<u32, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(kv_store.read(
+		LATEST_RGS_SYNC_TIMESTAMP_PRIMARY_NAMESPACE,
+		LATEST_RGS_SYNC_TIMESTAMP_SECONDARY_NAMESPACE,
+		LATEST_RGS_SYNC_TIMESTAMP_KEY,
+	)?);
+	u32::read(&mut reader).map_err(|e| {
+		log_error!(logger, "Failed to deserialize latest RGS sync timestamp: {}", e);
+		std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"Failed to deserialize latest RGS sync timestamp",
+		)
+	})
+}
+
+pub(crate) fn write_latest_rgs_sync_timestamp<K: KVStore + Sync + Send, L: Deref>(
+	updated_timestamp: u32, kv_store: Arc<K>, logger: L,
+) -> Result<(), Error>
+where
+	L::Target: Logger,
+{
+	let data = updated_timestamp.encode();
+	kv_store
+		.write(
+			LATEST_RGS_SYNC_TIMESTAMP_PRIMARY_NAMESPACE,
+			LATEST_RGS_SYNC_TIMESTAMP_SECONDARY_NAMESPACE,
+			LATEST_RGS_SYNC_TIMESTAMP_KEY,
+			&data,
+		)
+		.map_err(|e| {
+			log_error!(
+				logger,
+				"Writing data to key {}/{}/{} failed due to: {}",
+				LATEST_RGS_SYNC_TIMESTAMP_PRIMARY_NAMESPACE,
+				LATEST_RGS_SYNC_TIMESTAMP_SECONDARY_NAMESPACE,
+				LATEST_RGS_SYNC_TIMESTAMP_KEY,
+				e
+			);
+			Error::PersistenceFailed
+		})
+}
+
+pub(crate) fn read_fee_rate_cache<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: Arc<K>, logger: L,
+) -> Result<PersistedFeeRateCache, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(kv_store.read(
+		FEE_RATE_CACHE_PERSISTENCE_PRIMARY_NAMESPACE,
+		FEE_RATE_CACHE_PERSISTENCE_SECONDARY_NAMESPACE,
+		FEE_RATE_CACHE_PERSISTENCE_KEY,
+	)?);
+	PersistedFeeRateCache::read(&mut reader).map_err(|e| {
+		log_error!(logger, "Failed to deserialize fee rate cache: {}", e);
+		std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to deserialize fee rate cache")
+	})
+}
+
+pub(crate) fn write_fee_rate_cache<K: KVStore + Sync + Send, L: Deref>(
+	fee_rate_cache: &PersistedFeeRateCache, kv_store: Arc<K>, logger: L,
+) -> Result<(), Error>
+where
+	L::Target: Logger,
+{
+	let data = fee_rate_cache.encode();
+	kv_store
+		.write(
+			FEE_RATE_CACHE_PERSISTENCE_PRIMARY_NAMESPACE,
+			FEE_RATE_CACHE_PERSISTENCE_SECONDARY_NAMESPACE,
+			FEE_RATE_CACHE_PERSISTENCE_KEY,
+			&data,
+		)
+		.map_err(|e| {
+			log_error!(
+				logger,
+				"Writing data to key {}/{}/{} failed due to: {}",
+				FEE_RATE_CACHE_PERSISTENCE_PRIMARY_NAMESPACE,
+				FEE_RATE_CACHE_PERSISTENCE_SECONDARY_NAMESPACE,
+				FEE_RATE_CACHE_PERSISTENCE_KEY,
+				e
+			);
+			Error::PersistenceFailed
+		})
+}
+
+pub(crate) fn read_phantom_cross_node_seed<K: KVStore + Sync + Send, L: Deref>(
+	kv_store: Arc<K>, logger: L,
+) -> Result<[u8; 32], std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(kv_store.read(
+		PHANTOM_SEED_PERSISTENCE_PRIMARY_NAMESPACE,
+		PHANTOM_SEED_PERSISTENCE_SECONDARY_NAMESPACE,
+		PHANTOM_SEED_PERSISTENCE_KEY,
+	)?);
+	<[u8; 32]>::read(&mut reader).map_err(|e| {
+		log_error!(logger, "Failed to deserialize phantom cross-node seed: {}", e);
+		std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"Failed to deserialize phantom cross-node seed",
+		)
+	})
+}
+
+pub(crate) fn write_phantom_cross_node_seed<K: KVStore + Sync + Send, L: Deref>(
+	cross_node_seed: &[u8; 32], kv_store: Arc<K>, logger: L,
+) -> Result<(), Error>
+where
+	L::Target: Logger,
+{
+	let data = cross_node_seed.encode();
+	kv_store
+		.write(
+			PHANTOM_SEED_PERSISTENCE_PRIMARY_NAMESPACE,
+			PHANTOM_SEED_PERSISTENCE_SECONDARY_NAMESPACE,
+			PHANTOM_SEED_PERSISTENCE_KEY,
+			&data,
+		)
+		.map_err(|e| {
+			log_error!(
+				logger,
+				"Writing data to key {}/{}/{} failed due to: {}",
+				PHANTOM_SEED_PERSISTENCE_PRIMARY_NAMESPACE,
+				PHANTOM_SEED_PERSISTENCE_SECONDARY_NAMESPACE,
+				PHANTOM_SEED_PERSISTENCE_KEY,
+				e
+			);
+			Error::PersistenceFailed
+		})
+}
+
+/// Reads a `u64`-length-prefixed list of entries as written by [`write_trusted_peers_0conf`] and
+/// [`write_recovered_channels`].
+fn read_len_prefixed_vec<T: Readable, R: lightning::io::Read>(
+	reader: &mut R,
+) -> Result<Vec<T>, DecodeError> {
+	let len: u64 = Readable::read(reader)?;
+	let mut entries = Vec::with_capacity(len.min(1024) as usize);
+	for _ in 0..len {
+		entries.push(Readable::read(reader)?);
+	}
+	Ok(entries)
+}
+
+pub(crate) fn read_trusted_peers_0conf<K: KVStore + Sync + Send, L: Deref>(
 	kv_store: Arc<K>, logger: L,
-) -> Result<EventQueue<K, L>, std::io::Error>
+) -> Result<Vec<PublicKey>, std::io::Error>
 where
 	L::Target: Logger,
 {
 	let mut reader = Cursor::new(kv_store.read(
-		EVENT_QUEUE_PERSISTENCE_PRIMARY_NAMESPACE,
-		EVENT_QUEUE_PERSISTENCE_SECONDARY_NAMESPACE,
-		EVENT_QUEUE_PERSISTENCE_KEY,
+		TRUSTED_PEERS_0CONF_PERSISTENCE_PRIMARY_NAMESPACE,
+		TRUSTED_PEERS_0CONF_PERSISTENCE_SECONDARY_NAMESPACE,
+		TRUSTED_PEERS_0CONF_PERSISTENCE_KEY,
 	)?);
-	EventQueue::read(&mut reader, (kv_store, logger.clone())).map_err(|e| {
-		log_error!(logger, "Failed to deserialize event queue: {}", e);
-		std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to deserialize EventQueue")
+	read_len_prefixed_vec(&mut reader).map_err(|e| {
+		log_error!(logger, "Failed to deserialize trusted 0conf peers: {}", e);
+		std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"Failed to deserialize trusted 0conf peers",
+		)
 	})
 }
 
-/// Read previously persisted peer info from the store.
-pub(crate) fn read_peer_info<K: KVStore + Sync + Send, L: Deref + Clone>(
+pub(crate) fn write_trusted_peers_0conf<K: KVStore + Sync + Send, L: Deref>(
+	trusted_peers: &[PublicKey], kv_store: Arc<K>, logger: L,
+) -> Result<(), Error>
+where
+	L::Target: Logger,
+{
+	let mut data = (trusted_peers.len() as u64).encode();
+	for peer in trusted_peers {
+		data.extend(peer.encode());
+	}
+	kv_store
+		.write(
+			TRUSTED_PEERS_0CONF_PERSISTENCE_PRIMARY_NAMESPACE,
+			TRUSTED_PEERS_0CONF_PERSISTENCE_SECONDARY_NAMESPACE,
+			TRUSTED_PEERS_0CONF_PERSISTENCE_KEY,
+			&data,
+		)
+		.map_err(|e| {
+			log_error!(
+				logger,
+				"Writing data to key {}/{}/{} failed due to: {}",
+				TRUSTED_PEERS_0CONF_PERSISTENCE_PRIMARY_NAMESPACE,
+				TRUSTED_PEERS_0CONF_PERSISTENCE_SECONDARY_NAMESPACE,
+				TRUSTED_PEERS_0CONF_PERSISTENCE_KEY,
+				e
+			);
+			Error::PersistenceFailed
+		})
+}
+
+pub(crate) fn read_recovered_channels<K: KVStore + Sync + Send, L: Deref>(
 	kv_store: Arc<K>, logger: L,
-) -> Result<PeerStore<K, L>, std::io::Error>
+) -> Result<Vec<RecoveredChannel>, std::io::Error>
 where
 	L::Target: Logger,
 {
 	let mut reader = Cursor::new(kv_store.read(
-		PEER_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
-		PEER_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
-		PEER_INFO_PERSISTENCE_KEY,
+		RECOVERED_CHANNELS_PERSISTENCE_PRIMARY_NAMESPACE,
+		RECOVERED_CHANNELS_PERSISTENCE_SECONDARY_NAMESPACE,
+		RECOVERED_CHANNELS_PERSISTENCE_KEY,
 	)?);
-	PeerStore::read(&mut reader, (kv_store, logger.clone())).map_err(|e| {
-		log_error!(logger, "Failed to deserialize peer store: {}", e);
-		std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to deserialize PeerStore")
+	read_len_prefixed_vec(&mut reader).map_err(|e| {
+		log_error!(logger, "Failed to deserialize recovered channels: {}", e);
+		std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"Failed to deserialize recovered channels",
+		)
 	})
 }
 
-/// Read previously persisted payments information from the store.
-pub(crate) fn read_payments<K: KVStore + Sync + Send, L: Deref>(
-	kv_store: Arc<K>, logger: L,
-) -> Result<Vec<PaymentDetails>, std::io::Error>
+pub(crate) fn write_recovered_channels<K: KVStore + Sync + Send, L: Deref>(
+	recovered_channels: &[RecoveredChannel], kv_store: Arc<K>, logger: L,
+) -> Result<(), Error>
 where
 	L::Target: Logger,
 {
-	let mut res = Vec::new();
-
-	for stored_key in kv_store.list(
-		PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
-		PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
-	)? {
-		let mut reader = Cursor::new(kv_store.read(
-			PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
-			PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
-			&stored_key,
-		)?);
-		let payment = PaymentDetails::read(&mut reader).map_err(|e| {
-			log_error!(logger, "Failed to deserialize PaymentDetails: {}", e);
-			std::io::Error::new(
-				std::io::ErrorKind::InvalidData,
-				"Failed to deserialize PaymentDetails",
-			)
-		})?;
-		res.push(payment);
+	let mut data = (recovered_channels.len() as u64).encode();
+	for channel in recovered_channels {
+		data.extend(channel.encode());
 	}
-	Ok(res)
+	kv_store
+		.write(
+			RECOVERED_CHANNELS_PERSISTENCE_PRIMARY_NAMESPACE,
+			RECOVERED_CHANNELS_PERSISTENCE_SECONDARY_NAMESPACE,
+			RECOVERED_CHANNELS_PERSISTENCE_KEY,
+			&data,
+		)
+		.map_err(|e| {
+			log_error!(
+				logger,
+				"Writing data to key {}/{}/{} failed due to: {}",
+				RECOVERED_CHANNELS_PERSISTENCE_PRIMARY_NAMESPACE,
+				RECOVERED_CHANNELS_PERSISTENCE_SECONDARY_NAMESPACE,
+				RECOVERED_CHANNELS_PERSISTENCE_KEY,
+				e
+			);
+			Error::PersistenceFailed
+		})
 }
 
-/// Read previously persisted spendable output information from the store.
-pub(crate) fn read_spendable_outputs<K: KVStore + Sync + Send, L: Deref>(
+pub(crate) fn read_channel_penalties<K: KVStore + Sync + Send, L: Deref>(
 	kv_store: Arc<K>, logger: L,
-) -> Result<Vec<SpendableOutputInfo>, std::io::Error>
+) -> Result<HashMap<u64, u64>, std::io::Error>
 where
 	L::Target: Logger,
 {
-	let mut res = Vec::new();
+	let mut res = HashMap::new();
 
 	for stored_key in kv_store.list(
-		SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
-		SPENDABLE_OUTPUT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+		CHANNEL_PENALTIES_PERSISTENCE_PRIMARY_NAMESPACE,
+		CHANNEL_PENALTIES_PERSISTENCE_SECONDARY_NAMESPACE,
 	)? {
+		let short_channel_id = stored_key.parse::<u64>().map_err(|_| {
+			log_error!(logger, "Failed to parse channel penalty key {}", stored_key);
+			std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid channel penalty key")
+		})?;
 		let mut reader = Cursor::new(kv_store.read(
-			SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
-			SPENDABLE_OUTPUT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+			CHANNEL_PENALTIES_PERSISTENCE_PRIMARY_NAMESPACE,
+			CHANNEL_PENALTIES_PERSISTENCE_SECONDARY_NAMESPACE,
 			&stored_key,
 		)?);
-		let output = SpendableOutputInfo::read(&mut reader).map_err(|e| {
-			log_error!(logger, "Failed to deserialize SpendableOutputInfo: {}", e);
+		let expiry_secs = u64::read(&mut reader).map_err(|e| {
+			log_error!(logger, "Failed to deserialize penalty of channel {}: {}", stored_key, e);
 			std::io::Error::new(
 				std::io::ErrorKind::InvalidData,
-				"Failed to deserialize SpendableOutputInfo",
+				"Failed to deserialize channel penalty",
 			)
 		})?;
-		res.push(output);
+		res.insert(short_channel_id, expiry_secs);
 	}
 	Ok(res)
 }
 
-pub(crate) fn read_latest_rgs_sync_timestamp<K: KVStore + Sync + Send, L: Deref>(
-	kv_store: Arc<K>, logger: L,
-) -> Result<u32, std::io::Error>
+pub(crate) fn write_channel_penalty<K: KVStore + Sync + Send, L: Deref>(
+	short_channel_id: u64, expiry_secs: u64, kv_store: Arc<K>, logger: L,
+) -> Result<(), Error>
 where
 	L::Target: Logger,
 {
-	let mut reader = Cursor::new(kv_store.read(
-		LATEST_RGS_SYNC_TIMESTAMP_PRIMARY_NAMESPACE,
-		LATEST_RGS_SYNC_TIMESTAMP_SECONDARY_NAMESPACE,
-		LATEST_RGS_SYNC_TIMESTAMP_KEY,
-	)?);
-	u32::read(&mut reader).map_err(|e| {
-		log_error!(logger, "Failed to deserialize latest RGS sync timestamp: {}", e);
-		std::io::Error::new(
-			std::io::ErrorKind::InvalidData,
-			"Failed to deserialize latest RGS sync timestamp",
+	let key = short_channel_id.to_string();
+	kv_store
+		.write(
+			CHANNEL_PENALTIES_PERSISTENCE_PRIMARY_NAMESPACE,
+			CHANNEL_PENALTIES_PERSISTENCE_SECONDARY_NAMESPACE,
+			&key,
+			&expiry_secs.encode(),
 		)
-	})
+		.map_err(|e| {
+			log_error!(
+				logger,
+				"Writing data to key {}/{}/{} failed due to: {}",
+				CHANNEL_PENALTIES_PERSISTENCE_PRIMARY_NAMESPACE,
+				CHANNEL_PENALTIES_PERSISTENCE_SECONDARY_NAMESPACE,
+				key,
+				e
+			);
+			Error::PersistenceFailed
+		})
 }
 
-pub(crate) fn write_latest_rgs_sync_timestamp<K: KVStore + Sync + Send, L: Deref>(
-	updated_timestamp: u32, kv_store: Arc<K>, logger: L,
+pub(crate) fn remove_channel_penalty<K: KVStore + Sync + Send, L: Deref>(
+	short_channel_id: u64, kv_store: Arc<K>, logger: L,
 ) -> Result<(), Error>
 where
 	L::Target: Logger,
 {
-	let data = updated_timestamp.encode();
+	let key = short_channel_id.to_string();
 	kv_store
-		.write(
-			LATEST_RGS_SYNC_TIMESTAMP_PRIMARY_NAMESPACE,
-			LATEST_RGS_SYNC_TIMESTAMP_SECONDARY_NAMESPACE,
-			LATEST_RGS_SYNC_TIMESTAMP_KEY,
-			&data,
+		.remove(
+			CHANNEL_PENALTIES_PERSISTENCE_PRIMARY_NAMESPACE,
+			CHANNEL_PENALTIES_PERSISTENCE_SECONDARY_NAMESPACE,
+			&key,
+			false,
 		)
 		.map_err(|e| {
 			log_error!(
 				logger,
-				"Writing data to key {}/{}/{} failed due to: {}",
-				LATEST_RGS_SYNC_TIMESTAMP_PRIMARY_NAMESPACE,
-				LATEST_RGS_SYNC_TIMESTAMP_SECONDARY_NAMESPACE,
-				LATEST_RGS_SYNC_TIMESTAMP_KEY,
+				"Removing data for key {}/{}/{} failed due to: {}",
+				CHANNEL_PENALTIES_PERSISTENCE_PRIMARY_NAMESPACE,
+				CHANNEL_PENALTIES_PERSISTENCE_SECONDARY_NAMESPACE,
+				key,
 				e
 			);
 			Error::PersistenceFailed
@@ -422,6 +1466,68 @@ pub(crate) fn check_namespace_key_validity(
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use lightning::util::persist::{
+		CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+		CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+	};
+
+	#[test]
+	fn trusted_peers_0conf_roundtrip() {
+		use bitcoin::secp256k1::{Secp256k1, SecretKey};
+		use lightning::util::test_utils::{TestLogger, TestStore};
+
+		let logger = Arc::new(TestLogger::new());
+		let kv_store = Arc::new(TestStore::new(false));
+		let read_res = read_trusted_peers_0conf(Arc::clone(&kv_store), Arc::clone(&logger));
+		assert_eq!(read_res.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+		let secp_ctx = Secp256k1::new();
+		let trusted_peers = (1..=3u8)
+			.map(|i| {
+				PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[i; 32]).unwrap())
+			})
+			.collect::<Vec<_>>();
+		write_trusted_peers_0conf(&trusted_peers, Arc::clone(&kv_store), Arc::clone(&logger))
+			.unwrap();
+		assert_eq!(
+			read_trusted_peers_0conf(Arc::clone(&kv_store), Arc::clone(&logger)).unwrap(),
+			trusted_peers
+		);
+	}
+
+	#[test]
+	fn channel_penalties_roundtrip() {
+		use lightning::util::test_utils::{TestLogger, TestStore};
+
+		let logger = Arc::new(TestLogger::new());
+		let kv_store = Arc::new(TestStore::new(false));
+		assert!(read_channel_penalties(Arc::clone(&kv_store), Arc::clone(&logger))
+			.unwrap()
+			.is_empty());
+
+		let mut penalties =
+			(1..=3u64).map(|i| (i << 40, 1_700_000_000 + i)).collect::<HashMap<_, _>>();
+		for (short_channel_id, expiry_secs) in &penalties {
+			write_channel_penalty(
+				*short_channel_id,
+				*expiry_secs,
+				Arc::clone(&kv_store),
+				Arc::clone(&logger),
+			)
+			.unwrap();
+		}
+		assert_eq!(
+			read_channel_penalties(Arc::clone(&kv_store), Arc::clone(&logger)).unwrap(),
+			penalties
+		);
+
+		remove_channel_penalty(2 << 40, Arc::clone(&kv_store), Arc::clone(&logger)).unwrap();
+		penalties.remove(&(2 << 40));
+		assert_eq!(
+			read_channel_penalties(Arc::clone(&kv_store), Arc::clone(&logger)).unwrap(),
+			penalties
+		);
+	}
 
 	#[test]
 	fn mnemonic_to_entropy_to_mnemonic() {
@@ -430,4 +1536,584 @@ mod tests {
 		let entropy = mnemonic.to_entropy();
 		assert_eq!(mnemonic, Mnemonic::from_entropy(&entropy).unwrap());
 	}
+
+	#[test]
+	fn seed_file_generation_honors_given_rng() {
+		use rand::rngs::StdRng;
+		use rand::SeedableRng;
+
+		let logger = Arc::new(lightning::util::test_utils::TestLogger::new());
+		let storage_path = crate::io::test_utils::random_storage_path();
+		fs::create_dir_all(&storage_path).unwrap();
+		let seed_path_a = storage_path.join("keys_seed_a").to_str().unwrap().to_string();
+		let seed_path_b = storage_path.join("keys_seed_b").to_str().unwrap().to_string();
+
+		let mut rng = StdRng::seed_from_u64(42);
+		let seed_a =
+			read_or_generate_seed_file(&seed_path_a, None, &mut rng, Arc::clone(&logger)).unwrap();
+		let mut rng = StdRng::seed_from_u64(42);
+		let seed_b =
+			read_or_generate_seed_file(&seed_path_b, None, &mut rng, Arc::clone(&logger)).unwrap();
+		assert_eq!(seed_a, seed_b);
+
+		// An existing seed file is read as-is, independently of the given rng.
+		let mut rng = StdRng::seed_from_u64(43);
+		let read_seed =
+			read_or_generate_seed_file(&seed_path_a, None, &mut rng, Arc::clone(&logger)).unwrap();
+		assert_eq!(read_seed, seed_a);
+
+		let mut rng = StdRng::seed_from_u64(42);
+		let mnemonic_a = generate_entropy_mnemonic_from_rng(&mut rng);
+		let mut rng = StdRng::seed_from_u64(42);
+		let mnemonic_b = generate_entropy_mnemonic_from_rng(&mut rng);
+		assert_eq!(mnemonic_a, mnemonic_b);
+	}
+
+	#[test]
+	fn encrypted_seed_file_roundtrip_and_migration() {
+		let logger = Arc::new(lightning::util::test_utils::TestLogger::new());
+		let storage_path = crate::io::test_utils::random_storage_path();
+		fs::create_dir_all(&storage_path).unwrap();
+		let seed_path = storage_path.join("keys_seed").to_str().unwrap().to_string();
+
+		// Generate a legacy plaintext seed file.
+		let seed =
+			read_or_generate_seed_file(&seed_path, None, &mut thread_rng(), Arc::clone(&logger))
+				.unwrap();
+		assert_eq!(fs::read(&seed_path).unwrap(), seed.to_vec());
+
+		// Reading it with a passphrase migrates it to the encrypted format.
+		let read_seed = read_or_generate_seed_file(
+			&seed_path,
+			Some("hunter2"),
+			&mut thread_rng(),
+			Arc::clone(&logger),
+		)
+		.unwrap();
+		assert_eq!(read_seed, seed);
+		let encrypted = fs::read(&seed_path).unwrap();
+		assert_eq!(encrypted.len(), ENCRYPTED_SEED_FILE_LEN);
+		assert_eq!(encrypted[0], ENCRYPTED_SEED_FILE_VERSION);
+
+		let read_seed = read_or_generate_seed_file(
+			&seed_path,
+			Some("hunter2"),
+			&mut thread_rng(),
+			Arc::clone(&logger),
+		)
+		.unwrap();
+		assert_eq!(read_seed, seed);
+
+		// A wrong or missing passphrase results in a distinct error.
+		let err = read_or_generate_seed_file(
+			&seed_path,
+			Some("hunter3"),
+			&mut thread_rng(),
+			Arc::clone(&logger),
+		)
+		.unwrap_err();
+		assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+		let err =
+			read_or_generate_seed_file(&seed_path, None, &mut thread_rng(), Arc::clone(&logger))
+				.unwrap_err();
+		assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+	}
+
+	#[test]
+	fn verify_store_quarantines_corrupt_entries() {
+		use crate::payment_store::{PaymentDirection, PaymentKind, PaymentStatus};
+		use lightning::ln::PaymentHash;
+		use lightning::util::test_utils::{TestLogger, TestStore};
+
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+
+		let payment = PaymentDetails {
+			hash: PaymentHash([42u8; 32]),
+			id: None,
+			preimage: None,
+			secret: None,
+			amount_msat: None,
+			direction: PaymentDirection::Inbound,
+			status: PaymentStatus::Pending,
+			lsp_fee_limits: None,
+			kind: Some(PaymentKind::Bolt11),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: None,
+			custom_tlvs: Vec::new(),
+		};
+		let valid_key = crate::hex_utils::to_string(&payment.hash.0);
+		store
+			.write(
+				PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				&valid_key,
+				&payment.encode(),
+			)
+			.unwrap();
+		let corrupt_key = crate::hex_utils::to_string(&[23u8; 32]);
+		store
+			.write(
+				PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				&corrupt_key,
+				&[1, 2, 3],
+			)
+			.unwrap();
+
+		let report = verify_store(Arc::clone(&store), false, Arc::clone(&logger)).unwrap();
+		let expected_entry = CorruptStoreEntry {
+			primary_namespace: PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE.to_string(),
+			secondary_namespace: PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE.to_string(),
+			key: corrupt_key.clone(),
+		};
+		assert_eq!(report.corrupt_entries, vec![expected_entry.clone()]);
+		assert!(!report.repaired);
+		assert!(read_payments(Arc::clone(&store), false, Arc::clone(&logger)).is_err());
+		assert_eq!(
+			read_payments(Arc::clone(&store), true, Arc::clone(&logger)).unwrap(),
+			(vec![payment.clone()], vec![corrupt_key.clone()])
+		);
+
+		let report = verify_store(Arc::clone(&store), true, Arc::clone(&logger)).unwrap();
+		assert_eq!(report.corrupt_entries, vec![expected_entry]);
+		assert!(report.repaired);
+		assert_eq!(
+			store
+				.read(
+					CORRUPT_PERSISTENCE_PRIMARY_NAMESPACE,
+					PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+					&corrupt_key
+				)
+				.unwrap(),
+			vec![1, 2, 3]
+		);
+		assert_eq!(
+			read_payments(Arc::clone(&store), false, Arc::clone(&logger)).unwrap(),
+			(vec![payment], Vec::new())
+		);
+
+		let report = verify_store(Arc::clone(&store), false, logger).unwrap();
+		assert!(report.corrupt_entries.is_empty());
+	}
+
+	#[test]
+	fn verify_store_checks_all_persisted_namespaces() {
+		use bitcoin::Network;
+		use lightning::util::test_utils::{TestLogger, TestStore};
+
+		let logger = Arc::new(TestLogger::new());
+		for namespace in PERSISTED_NAMESPACES {
+			if namespace.key == Some(CHANNEL_MANAGER_PERSISTENCE_KEY)
+				|| namespace.primary_namespace == CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE
+			{
+				continue;
+			}
+
+			let store = Arc::new(TestStore::new(false));
+			if namespace.key == Some(SCORER_PERSISTENCE_KEY) {
+				// The scorer is only checked given a valid network graph.
+				let network_graph = NetworkGraph::new(Network::Testnet, Arc::clone(&logger));
+				store
+					.write(
+						NETWORK_GRAPH_PERSISTENCE_PRIMARY_NAMESPACE,
+						NETWORK_GRAPH_PERSISTENCE_SECONDARY_NAMESPACE,
+						NETWORK_GRAPH_PERSISTENCE_KEY,
+						&network_graph.encode(),
+					)
+					.unwrap();
+			}
+			let key = namespace.key.unwrap_or("corrupt_entry");
+			store
+				.write(namespace.primary_namespace, namespace.secondary_namespace, key, &[0xff; 3])
+				.unwrap();
+
+			let report = verify_store(Arc::clone(&store), false, Arc::clone(&logger)).unwrap();
+			let expected_entry = CorruptStoreEntry {
+				primary_namespace: namespace.primary_namespace.to_string(),
+				secondary_namespace: namespace.secondary_namespace.to_string(),
+				key: key.to_string(),
+			};
+			assert_eq!(report.corrupt_entries, vec![expected_entry], "{:?}", namespace);
+		}
+	}
+
+	#[test]
+	fn read_payments_preserves_description_hash() {
+		use crate::payment_store::{PaymentDirection, PaymentKind, PaymentStatus};
+		use crate::types::DescriptionHash;
+		use lightning::ln::PaymentHash;
+		use lightning::util::test_utils::{TestLogger, TestStore};
+
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+
+		let mut payments = Vec::new();
+		for (i, description_hash) in [None, Some(DescriptionHash([23u8; 32]))].iter().enumerate() {
+			let payment = PaymentDetails {
+				hash: PaymentHash([i as u8; 32]),
+				id: None,
+				preimage: None,
+				secret: None,
+				amount_msat: Some(1000),
+				direction: PaymentDirection::Inbound,
+				status: PaymentStatus::Pending,
+				lsp_fee_limits: None,
+				kind: Some(PaymentKind::Bolt11),
+				description_hash: *description_hash,
+				expected_amount_msat: None,
+				invoice_expiry_timestamp: None,
+				custom_tlvs: Vec::new(),
+			};
+			store
+				.write(
+					PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+					PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+					&crate::hex_utils::to_string(&payment.hash.0),
+					&payment.encode(),
+				)
+				.unwrap();
+			payments.push(payment);
+		}
+
+		let (mut read_payments, skipped_keys) = read_payments(store, false, logger).unwrap();
+		read_payments.sort_by_key(|p| p.hash.0);
+		assert!(skipped_keys.is_empty());
+		assert_eq!(read_payments, payments);
+		assert_eq!(read_payments[0].description_hash, None);
+		assert_eq!(read_payments[1].description_hash, Some(DescriptionHash([23u8; 32])));
+	}
+
+	#[test]
+	fn read_payments_skips_corrupt_entries_unless_strict() {
+		use crate::payment_store::{PaymentDirection, PaymentKind, PaymentStatus};
+		use lightning::ln::PaymentHash;
+		use lightning::util::test_utils::{TestLogger, TestStore};
+
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+
+		let payment = PaymentDetails {
+			hash: PaymentHash([42u8; 32]),
+			id: None,
+			preimage: None,
+			secret: None,
+			amount_msat: Some(1000),
+			direction: PaymentDirection::Outbound,
+			status: PaymentStatus::Succeeded,
+			lsp_fee_limits: None,
+			kind: Some(PaymentKind::Bolt11),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: None,
+			custom_tlvs: Vec::new(),
+		};
+		store
+			.write(
+				PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				&crate::hex_utils::to_string(&payment.hash.0),
+				&payment.encode(),
+			)
+			.unwrap();
+
+		// A record cut off in the middle, as left behind by an interrupted write.
+		let encoded_payment = payment.encode();
+		let corrupt_key = crate::hex_utils::to_string(&[23u8; 32]);
+		store
+			.write(
+				PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				&corrupt_key,
+				&encoded_payment[..encoded_payment.len() / 2],
+			)
+			.unwrap();
+
+		let (payments, skipped_keys) =
+			read_payments(Arc::clone(&store), true, Arc::clone(&logger)).unwrap();
+		assert_eq!(payments, vec![payment]);
+		assert_eq!(skipped_keys, vec![corrupt_key]);
+
+		let err = read_payments(Arc::clone(&store), false, Arc::clone(&logger)).unwrap_err();
+		assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn store_snapshot_roundtrip() {
+		use lightning::util::test_utils::{TestLogger, TestStore};
+
+		let store = TestStore::new(false);
+		let logger = Arc::new(TestLogger::new());
+
+		store.write("", "", PEER_INFO_PERSISTENCE_KEY, &[1, 2, 3]).unwrap();
+		store
+			.write(
+				PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				"payment",
+				&[4, 5],
+			)
+			.unwrap();
+		store
+			.write(
+				CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+				CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+				"monitor",
+				&[],
+			)
+			.unwrap();
+
+		let mut snapshot = Vec::new();
+		write_store_snapshot(&store, &mut snapshot, logger).unwrap();
+		let entries = read_store_snapshot(&mut &snapshot[..]).unwrap();
+		assert_eq!(entries.len(), 3);
+
+		let restored_store = TestStore::new(false);
+		restore_store_snapshot(&restored_store, &entries).unwrap();
+		for entry in entries {
+			assert_eq!(
+				restored_store
+					.read(&entry.primary_namespace, &entry.secondary_namespace, &entry.key)
+					.unwrap(),
+				store
+					.read(&entry.primary_namespace, &entry.secondary_namespace, &entry.key)
+					.unwrap()
+			);
+		}
+
+		// Truncated snapshots are rejected.
+		assert!(read_store_snapshot(&mut &snapshot[..snapshot.len() - 1]).is_err());
+
+		// Snapshots containing invalid keys are rejected.
+		let mut invalid_snapshot = vec![STORE_SNAPSHOT_VERSION];
+		invalid_snapshot.extend_from_slice(&1u64.to_be_bytes());
+		write_snapshot_field(&mut invalid_snapshot, b"").unwrap();
+		write_snapshot_field(&mut invalid_snapshot, b"").unwrap();
+		write_snapshot_field(&mut invalid_snapshot, b"../key").unwrap();
+		write_snapshot_field(&mut invalid_snapshot, &[1]).unwrap();
+		assert!(read_store_snapshot(&mut &invalid_snapshot[..]).is_err());
+	}
+
+	#[test]
+	fn store_snapshot_covers_all_persisted_namespaces() {
+		for namespace in PERSISTED_NAMESPACES {
+			assert!(
+				store_snapshot_namespaces()
+					.contains(&(namespace.primary_namespace, namespace.secondary_namespace)),
+				"{:?} is not included in store snapshots",
+				namespace
+			);
+		}
+		assert!(store_snapshot_namespaces().contains(&(
+			CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+			CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE
+		)));
+		assert!(!store_snapshot_namespaces().contains(&(
+			HEALTH_CHECK_PROBE_PRIMARY_NAMESPACE,
+			HEALTH_CHECK_PROBE_SECONDARY_NAMESPACE
+		)));
+
+		// Entries in a non-default namespace end up in the snapshot.
+		use lightning::util::test_utils::{TestLogger, TestStore};
+		let store = TestStore::new(false);
+		store
+			.write(
+				HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+				HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+				"held",
+				&[1],
+			)
+			.unwrap();
+		let mut snapshot = Vec::new();
+		write_store_snapshot(&store, &mut snapshot, Arc::new(TestLogger::new())).unwrap();
+		let entries = read_store_snapshot(&mut &snapshot[..]).unwrap();
+		assert_eq!(entries.len(), 1);
+		assert_eq!(entries[0].primary_namespace, HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE);
+	}
+
+	// Has the scorer learn about the liquidity of the given channel direction by letting it
+	// observe a payment of `amount_msat` being routed to `target` over it.
+	fn observe_successful_payment<G: Deref<Target = NetworkGraph<L>>, L: Deref>(
+		scorer: &mut ProbabilisticScorer<G, L>, short_channel_id: u64, target: PublicKey,
+		amount_msat: u64,
+	) where
+		L::Target: Logger,
+	{
+		use lightning::ln::features::{ChannelFeatures, NodeFeatures};
+		use lightning::routing::router::{Path, RouteHop};
+		use lightning::routing::scoring::ScoreUpdate;
+
+		let hop = RouteHop {
+			pubkey: target,
+			node_features: NodeFeatures::empty(),
+			short_channel_id,
+			channel_features: ChannelFeatures::empty(),
+			fee_msat: amount_msat,
+			cltv_expiry_delta: 144,
+			maybe_announced_channel: true,
+		};
+		scorer.payment_path_successful(&Path { hops: vec![hop], blinded_tail: None });
+	}
+
+	#[test]
+	fn imported_scorer_roundtrip() {
+		use crate::io::test_utils::{create_test_network_graph, test_node_ids};
+		use lightning::routing::gossip::NodeId;
+		use lightning::util::test_utils::TestLogger;
+
+		let logger = Arc::new(TestLogger::new());
+		let nodes = test_node_ids(3);
+		let channels = [(1, nodes[0], nodes[1]), (2, nodes[1], nodes[2])];
+		let network_graph = Arc::new(create_test_network_graph(&channels, Arc::clone(&logger)));
+		let params = ProbabilisticScoringDecayParameters::default();
+
+		// An empty scorer matches any graph.
+		let mut scorer =
+			ProbabilisticScorer::new(params, Arc::clone(&network_graph), Arc::clone(&logger));
+		let scorer_bytes = scorer.encode();
+		assert!(read_imported_scorer(
+			&scorer_bytes,
+			params,
+			Arc::clone(&network_graph),
+			Arc::clone(&logger)
+		)
+		.is_ok());
+
+		observe_successful_payment(&mut scorer, 1, nodes[1], 10_000_000);
+		let target = NodeId::from_pubkey(&nodes[1]);
+		let liquidity_range = scorer.estimated_channel_liquidity_range(1, &target);
+		assert!(liquidity_range.is_some());
+
+		let scorer_bytes = scorer.encode();
+		let imported_scorer = read_imported_scorer(
+			&scorer_bytes,
+			params,
+			Arc::clone(&network_graph),
+			Arc::clone(&logger),
+		)
+		.unwrap();
+		assert_eq!(imported_scorer.estimated_channel_liquidity_range(1, &target), liquidity_range);
+		assert_eq!(
+			imported_scorer.estimated_channel_liquidity_range(2, &NodeId::from_pubkey(&nodes[2])),
+			None
+		);
+	}
+
+	#[test]
+	fn imported_scorer_of_other_graph_is_rejected() {
+		use crate::io::test_utils::{create_test_network_graph, test_node_ids};
+		use lightning::util::test_utils::TestLogger;
+
+		let logger = Arc::new(TestLogger::new());
+		let nodes = test_node_ids(4);
+		let params = ProbabilisticScoringDecayParameters::default();
+
+		let channels = [(1, nodes[0], nodes[1])];
+		let network_graph = Arc::new(create_test_network_graph(&channels, Arc::clone(&logger)));
+		let mut scorer =
+			ProbabilisticScorer::new(params, Arc::clone(&network_graph), Arc::clone(&logger));
+		observe_successful_payment(&mut scorer, 1, nodes[1], 10_000_000);
+		let scorer_bytes = scorer.encode();
+
+		let other_channels = [(2, nodes[2], nodes[3])];
+		let other_network_graph =
+			Arc::new(create_test_network_graph(&other_channels, Arc::clone(&logger)));
+		assert_eq!(
+			read_imported_scorer(&scorer_bytes, params, other_network_graph, Arc::clone(&logger))
+				.err(),
+			Some(Error::ScorerGraphMismatch)
+		);
+	}
+
+	#[test]
+	fn imported_scorer_garbage_is_rejected() {
+		use crate::io::test_utils::{create_test_network_graph, test_node_ids};
+		use lightning::util::test_utils::TestLogger;
+
+		let logger = Arc::new(TestLogger::new());
+		let nodes = test_node_ids(2);
+		let channels = [(1, nodes[0], nodes[1])];
+		let network_graph = Arc::new(create_test_network_graph(&channels, Arc::clone(&logger)));
+		let params = ProbabilisticScoringDecayParameters::default();
+
+		let mut scorer =
+			ProbabilisticScorer::new(params, Arc::clone(&network_graph), Arc::clone(&logger));
+		observe_successful_payment(&mut scorer, 1, nodes[1], 10_000_000);
+		let scorer_bytes = scorer.encode();
+
+		let mut trailing_bytes = scorer_bytes.clone();
+		trailing_bytes.push(0);
+		let truncated_bytes = &scorer_bytes[..scorer_bytes.len() - 1];
+		for invalid_bytes in [&[][..], &[0xff; 64][..], &trailing_bytes[..], truncated_bytes] {
+			assert_eq!(
+				read_imported_scorer(
+					invalid_bytes,
+					params,
+					Arc::clone(&network_graph),
+					Arc::clone(&logger)
+				)
+				.err(),
+				Some(Error::InvalidScorer)
+			);
+		}
+	}
+
+	#[test]
+	fn scoring_decay_parameters_apply_to_persisted_scorer() {
+		use crate::io::test_utils::{
+			create_test_network_graph, test_node_ids, TEST_CHANNEL_HTLC_MAXIMUM_MSAT,
+		};
+		use lightning::routing::gossip::NodeId;
+		use lightning::util::test_utils::{TestLogger, TestStore};
+		use std::time::Duration;
+
+		let logger = Arc::new(TestLogger::new());
+		let kv_store = Arc::new(TestStore::new(false));
+		let nodes = test_node_ids(2);
+		let channels = [(1, nodes[0], nodes[1])];
+		let network_graph = Arc::new(create_test_network_graph(&channels, Arc::clone(&logger)));
+		let target = NodeId::from_pubkey(&nodes[1]);
+
+		let params = ProbabilisticScoringDecayParameters::default();
+		let mut scorer =
+			ProbabilisticScorer::new(params, Arc::clone(&network_graph), Arc::clone(&logger));
+		observe_successful_payment(&mut scorer, 1, nodes[1], 10_000_000);
+		kv_store
+			.write(
+				SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
+				SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+				SCORER_PERSISTENCE_KEY,
+				&scorer.encode(),
+			)
+			.unwrap();
+
+		let scorer = read_scorer(
+			Arc::clone(&kv_store),
+			params,
+			Arc::clone(&network_graph),
+			Arc::clone(&logger),
+		)
+		.unwrap();
+		let learned_range = (0, TEST_CHANNEL_HTLC_MAXIMUM_MSAT - 10_000_000);
+		assert_eq!(scorer.estimated_channel_liquidity_range(1, &target), Some(learned_range));
+
+		// Without a half-life, the learned liquidity offsets decay immediately. The persisted data
+		// isn't reset but reinterpreted according to the new parameters.
+		let params = ProbabilisticScoringDecayParameters {
+			liquidity_offset_half_life: Duration::ZERO,
+			..Default::default()
+		};
+		let scorer = read_scorer(
+			Arc::clone(&kv_store),
+			params,
+			Arc::clone(&network_graph),
+			Arc::clone(&logger),
+		)
+		.unwrap();
+		assert_eq!(
+			scorer.estimated_channel_liquidity_range(1, &target),
+			Some((0, TEST_CHANNEL_HTLC_MAXIMUM_MSAT))
+		);
+	}
 }
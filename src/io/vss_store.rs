@@ -5,16 +5,17 @@ use std::io::ErrorKind;
 use std::panic::RefUnwindSafe;
 use std::time::Duration;
 
+use crate::io::batch_kv_store::BatchKVStore;
 use crate::io::utils::check_namespace_key_validity;
 use lightning::util::persist::KVStore;
 use prost::Message;
 use rand::RngCore;
 use tokio::runtime::Runtime;
+use tokio::sync::Mutex;
 use vss_client::client::VssClient;
 use vss_client::error::VssError;
 use vss_client::types::{
-	DeleteObjectRequest, GetObjectRequest, KeyValue, ListKeyVersionsRequest, PutObjectRequest,
-	Storable,
+	GetObjectRequest, KeyValue, ListKeyVersionsRequest, PutObjectRequest, Storable,
 };
 use vss_client::util::retry::{
 	ExponentialBackoffRetryPolicy, FilteredRetryPolicy, JitteredRetryPolicy,
@@ -22,6 +23,9 @@ use vss_client::util::retry::{
 };
 use vss_client::util::storable_builder::{EntropySource, StorableBuilder};
 
+/// The number of times a write is retried after it failed due to a global version conflict.
+const MAX_VERSION_CONFLICT_RETRIES: usize = 5;
+
 type CustomRetryPolicy = FilteredRetryPolicy<
 	JitteredRetryPolicy<
 		MaxTotalDelayRetryPolicy<MaxAttemptsRetryPolicy<ExponentialBackoffRetryPolicy<VssError>>>,
@@ -30,11 +34,18 @@ type CustomRetryPolicy = FilteredRetryPolicy<
 >;
 
 /// A [`KVStore`] implementation that writes to and reads from a [VSS](https://github.com/lightningdevkit/vss-server/blob/main/README.md) backend.
+///
+/// Values are encrypted client-side before they are sent to the server. All writes are made
+/// conditional on the store's global version, so that concurrent writers are serialized. On a
+/// version conflict, the global version is re-read and the write is retried a bounded number of
+/// times before failing with an error.
 pub struct VssStore {
 	client: VssClient<CustomRetryPolicy>,
 	store_id: String,
 	runtime: Runtime,
 	storable_builder: StorableBuilder<RandEntropySource>,
+	// The last known global version of the store. Held during writes to serialize them.
+	global_version: Mutex<Option<i64>>,
 }
 
 impl VssStore {
@@ -55,7 +66,8 @@ impl VssStore {
 			}) as _);
 
 		let client = VssClient::new(&base_url, retry_policy);
-		Self { client, store_id, runtime, storable_builder }
+		let global_version = Mutex::new(None);
+		Self { client, store_id, runtime, storable_builder, global_version }
 	}
 
 	fn build_key(
@@ -69,6 +81,11 @@ impl VssStore {
 	}
 
 	fn extract_key(&self, unified_key: &str) -> io::Result<String> {
+		if !unified_key.contains('#') {
+			// Keys in the empty primary namespace are stored as-is.
+			return Ok(unified_key.to_string());
+		}
+
 		let mut parts = unified_key.splitn(3, '#');
 		let (_primary_namespace, _secondary_namespace) = (parts.next(), parts.next());
 		match parts.next() {
@@ -82,11 +99,17 @@ impl VssStore {
 	) -> io::Result<Vec<String>> {
 		let mut page_token = None;
 		let mut keys = vec![];
-		let key_prefix = format!("{}#{}", primary_namespace, secondary_namespace);
+		// Keys in the empty primary namespace are stored without any prefix, so we have to list
+		// all keys and filter out the namespaced ones.
+		let key_prefix = if primary_namespace.is_empty() {
+			None
+		} else {
+			Some(format!("{}#{}#", primary_namespace, secondary_namespace))
+		};
 		while page_token != Some("".to_string()) {
 			let request = ListKeyVersionsRequest {
 				store_id: self.store_id.clone(),
-				key_prefix: Some(key_prefix.clone()),
+				key_prefix: key_prefix.clone(),
 				page_token,
 				page_size: None,
 			};
@@ -100,12 +123,82 @@ impl VssStore {
 			})?;
 
 			for kv in response.key_versions {
+				if key_prefix.is_none() && kv.key.contains('#') {
+					continue;
+				}
 				keys.push(self.extract_key(&kv.key)?);
 			}
 			page_token = response.next_page_token;
 		}
 		Ok(keys)
 	}
+
+	async fn fetch_global_version(&self) -> Result<i64, VssError> {
+		let request = ListKeyVersionsRequest {
+			store_id: self.store_id.clone(),
+			key_prefix: None,
+			page_token: None,
+			page_size: Some(1),
+		};
+		let response = self.client.list_key_versions(&request).await?;
+		// The server only omits the global version if the store was never written to.
+		Ok(response.global_version.unwrap_or(0))
+	}
+
+	/// Commits the given items conditional on the last known global version of the store.
+	///
+	/// If another writer modified the store in the meantime, the request fails with a
+	/// [`VssError::ConflictError`]. As our items are written unconditionally on a per-key basis,
+	/// they don't depend on the other writer's changes, so we re-read the global version and
+	/// retry, up to [`MAX_VERSION_CONFLICT_RETRIES`] times.
+	async fn put_versioned(
+		&self, transaction_items: Vec<KeyValue>, delete_items: Vec<KeyValue>,
+	) -> Result<(), VssError> {
+		let mut locked_global_version = self.global_version.lock().await;
+		let mut request = PutObjectRequest {
+			store_id: self.store_id.clone(),
+			global_version: None,
+			transaction_items,
+			delete_items,
+		};
+
+		let mut conflict_retries = 0;
+		loop {
+			let global_version = match *locked_global_version {
+				Some(global_version) => global_version,
+				None => self.fetch_global_version().await?,
+			};
+			request.global_version = Some(global_version);
+
+			match self.client.put_object(&request).await {
+				Ok(_) => {
+					*locked_global_version = Some(global_version + 1);
+					return Ok(());
+				},
+				Err(VssError::ConflictError(..))
+					if conflict_retries < MAX_VERSION_CONFLICT_RETRIES =>
+				{
+					*locked_global_version = None;
+					conflict_retries += 1;
+				},
+				Err(e) => {
+					*locked_global_version = None;
+					return Err(e);
+				},
+			}
+		}
+	}
+}
+
+fn vss_write_error(e: VssError, msg: String) -> Error {
+	match e {
+		VssError::ConflictError(..) => Error::new(
+			ErrorKind::Other,
+			format!("{} (too many conflicting concurrent writes)", msg),
+		),
+		VssError::InvalidRequestError(..) => Error::new(ErrorKind::InvalidInput, msg),
+		_ => Error::new(ErrorKind::Other, msg),
+	}
 }
 
 impl KVStore for VssStore {
@@ -142,25 +235,22 @@ impl KVStore for VssStore {
 		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "write")?;
 		let version = -1;
 		let storable = self.storable_builder.build(buf.to_vec(), version);
-		let request = PutObjectRequest {
-			store_id: self.store_id.clone(),
-			global_version: None,
-			transaction_items: vec![KeyValue {
-				key: self.build_key(primary_namespace, secondary_namespace, key)?,
-				version,
-				value: storable.encode_to_vec(),
-			}],
-			delete_items: vec![],
-		};
+		let transaction_items = vec![KeyValue {
+			key: self.build_key(primary_namespace, secondary_namespace, key)?,
+			version,
+			value: storable.encode_to_vec(),
+		}];
 
-		tokio::task::block_in_place(|| self.runtime.block_on(self.client.put_object(&request)))
-			.map_err(|e| {
-				let msg = format!(
-					"Failed to write to key {}/{}/{}: {}",
-					primary_namespace, secondary_namespace, key, e
-				);
-				Error::new(ErrorKind::Other, msg)
-			})?;
+		tokio::task::block_in_place(|| {
+			self.runtime.block_on(self.put_versioned(transaction_items, vec![]))
+		})
+		.map_err(|e| {
+			let msg = format!(
+				"Failed to write to key {}/{}/{}: {}",
+				primary_namespace, secondary_namespace, key, e
+			);
+			vss_write_error(e, msg)
+		})?;
 
 		Ok(())
 	}
@@ -169,23 +259,22 @@ impl KVStore for VssStore {
 		&self, primary_namespace: &str, secondary_namespace: &str, key: &str, _lazy: bool,
 	) -> io::Result<()> {
 		check_namespace_key_validity(primary_namespace, secondary_namespace, Some(key), "remove")?;
-		let request = DeleteObjectRequest {
-			store_id: self.store_id.clone(),
-			key_value: Some(KeyValue {
-				key: self.build_key(primary_namespace, secondary_namespace, key)?,
-				version: -1,
-				value: vec![],
-			}),
-		};
+		let delete_items = vec![KeyValue {
+			key: self.build_key(primary_namespace, secondary_namespace, key)?,
+			version: -1,
+			value: vec![],
+		}];
 
-		tokio::task::block_in_place(|| self.runtime.block_on(self.client.delete_object(&request)))
-			.map_err(|e| {
-				let msg = format!(
-					"Failed to delete key {}/{}/{}: {}",
-					primary_namespace, secondary_namespace, key, e
-				);
-				Error::new(ErrorKind::Other, msg)
-			})?;
+		tokio::task::block_in_place(|| {
+			self.runtime.block_on(self.put_versioned(vec![], delete_items))
+		})
+		.map_err(|e| {
+			let msg = format!(
+				"Failed to delete key {}/{}/{}: {}",
+				primary_namespace, secondary_namespace, key, e
+			);
+			vss_write_error(e, msg)
+		})?;
 		Ok(())
 	}
 
@@ -207,6 +296,8 @@ impl KVStore for VssStore {
 	}
 }
 
+impl BatchKVStore for VssStore {}
+
 /// A source for generating entropy/randomness using [`rand`].
 pub(crate) struct RandEntropySource;
 
@@ -238,4 +329,38 @@ mod tests {
 
 		do_read_write_remove_list_persist(&vss_store);
 	}
+
+	#[test]
+	fn concurrent_writers_conflict() {
+		let vss_base_url = std::env::var("TEST_VSS_BASE_URL").unwrap();
+		let mut rng = thread_rng();
+		let rand_store_id: String = (0..7).map(|_| rng.sample(Alphanumeric) as char).collect();
+		let mut data_encryption_key = [0u8; 32];
+		rng.fill_bytes(&mut data_encryption_key);
+		let store_a =
+			VssStore::new(vss_base_url.clone(), rand_store_id.clone(), data_encryption_key);
+		let store_b = VssStore::new(vss_base_url, rand_store_id, data_encryption_key);
+
+		// Both writers learn the same global version.
+		store_a.write("primary", "secondary", "key_a", &[1u8]).unwrap();
+		store_b.write("primary", "secondary", "key_b", &[2u8]).unwrap();
+
+		// The first writer is now outdated, so its write conflicts, but is transparently retried
+		// with the refreshed global version without clobbering the other writer's changes.
+		store_a.write("primary", "secondary", "key_a", &[3u8]).unwrap();
+		assert_eq!(store_b.read("primary", "secondary", "key_a").unwrap(), vec![3u8]);
+		assert_eq!(store_a.read("primary", "secondary", "key_b").unwrap(), vec![2u8]);
+
+		// Interleaved writes from both writers keep succeeding.
+		for i in 0..5u8 {
+			store_a.write("primary", "secondary", "key_a", &[i]).unwrap();
+			store_b.write("primary", "secondary", "key_b", &[i]).unwrap();
+		}
+		assert_eq!(store_b.read("primary", "secondary", "key_a").unwrap(), vec![4u8]);
+		assert_eq!(store_a.read("primary", "secondary", "key_b").unwrap(), vec![4u8]);
+
+		let mut keys = store_b.list("primary", "secondary").unwrap();
+		keys.sort();
+		assert_eq!(keys, vec!["key_a".to_string(), "key_b".to_string()]);
+	}
 }
@@ -75,26 +75,44 @@
 #![allow(ellipsis_inclusive_range_patterns)]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
+mod address_book;
+mod backup;
 mod balance;
 mod builder;
+mod chain;
 mod config;
 mod error;
 mod event;
 mod fee_estimator;
 mod gossip;
+mod graph;
+mod health;
 mod hex_utils;
+mod hold_invoice;
 pub mod io;
 mod liquidity;
+mod lnurl;
 mod logger;
 mod message_handler;
+mod metrics;
+mod onchain_monitor;
+mod onion_message;
+mod parse;
+mod payment_retry;
 mod payment_store;
+mod peer_monitor;
 mod peer_store;
+mod router;
 mod sweep;
+mod tor;
 mod tx_broadcaster;
 mod types;
 #[cfg(feature = "uniffi")]
 mod uniffi_types;
+mod unified_qr;
 mod wallet;
+#[cfg(feature = "websocket")]
+mod websocket;
 
 pub use bip39;
 pub use bitcoin;
@@ -102,14 +120,33 @@ pub use lightning;
 pub use lightning_invoice;
 
 pub use balance::{BalanceDetails, LightningBalance, PendingSweepBalance};
-pub use config::{default_config, Config};
+pub use config::{
+	default_anchor_channels_config, default_blinded_path_config, default_config,
+	default_esplora_sync_config, default_log_rotation_config, default_retry_policy,
+	AnchorChannelsConfig, BlindedPathConfig, Config, EsploraSyncConfig, LogRotationConfig,
+	MaxFeePolicy, RetryPolicy,
+};
 pub use error::Error as NodeError;
 use error::Error;
 
-pub use event::Event;
+pub use event::{DeadLetterEvent, Event, TimestampedEvent};
+pub use graph::{
+	ChannelInfo, ChannelUpdateInfo, NetworkGraphStats, NodeAnnouncementInfo, NodeInfo,
+};
+pub use health::{HealthCheck, HealthCheckState, HealthStatus};
+pub use logger::{Log, LogFormat, LogRecord};
+pub use metrics::Metrics;
+pub use parse::{
+	parse_bip21, parse_bolt11_invoice, parse_bolt12_offer, Bolt11InvoiceInfo, OfferAmount,
+	OfferInfo,
+};
 pub use types::{BestBlock, ChannelConfig};
+pub use unified_qr::{
+	QrPaymentResult, RailPreference, UnifiedPaymentRequest, UnifiedQrComponents, UnifiedQrPayment,
+};
 
-pub use io::utils::generate_entropy_mnemonic;
+pub use io::utils::{generate_entropy_mnemonic, generate_entropy_mnemonic_from_rng};
+pub use io::{CorruptStoreEntry, StoreVerificationReport};
 
 #[cfg(feature = "uniffi")]
 use uniffi_types::*;
@@ -120,53 +157,103 @@ pub use builder::BuildError;
 #[cfg(not(feature = "uniffi"))]
 pub use builder::NodeBuilder as Builder;
 
+use address_book::{is_valid_address_label, AddressBook};
+use backup::ChannelBackup;
 use config::{
-	LDK_PAYMENT_RETRY_TIMEOUT, NODE_ANN_BCAST_INTERVAL, PEER_RECONNECTION_INTERVAL,
-	RGS_SYNC_INTERVAL, WALLET_SYNC_INTERVAL_MINIMUM_SECS,
+	CUSTOM_TLV_TYPE_NUM_MIN, HELD_PAYMENT_CHECK_INTERVAL, INVOICE_EXPIRY_CHECK_INTERVAL,
+	LDK_PAYMENT_RETRY_TIMEOUT, MAX_ACCEPTED_HTLCS, MIN_CHANNEL_RESERVE_SATS, PEER_MONITOR_INTERVAL,
+	PEER_RECONNECTION_INTERVAL, PERSISTENT_PEER_RECONNECTION_CHECK_INTERVAL, RGS_SYNC_INTERVAL,
+	WALLET_SYNC_INTERVAL_MINIMUM_SECS,
 };
 use event::{EventHandler, EventQueue};
 use gossip::GossipSource;
+use hold_invoice::HoldInvoiceHandler;
+use io::batch_kv_store::BatchKVStore;
+#[cfg(lsps1)]
+pub use liquidity::lsps1::{ChannelOrder, OrderChannelInfo, OrderId, OrderPaymentState};
 use liquidity::LiquiditySource;
-use payment_store::PaymentStore;
-pub use payment_store::{LSPFeeLimits, PaymentDetails, PaymentDirection, PaymentStatus};
+use onchain_monitor::OnchainPaymentMonitor;
+use onion_message::NodeOffersMessageHandler;
+use payment_retry::PaymentRetrier;
+pub use payment_store::{
+	LSPFeeLimits, PaymentDetails, PaymentDirection, PaymentKind, PaymentStatus,
+};
+use payment_store::{PaymentDetailsUpdate, PaymentStore};
+use peer_monitor::PeerConnectionMonitor;
 use peer_store::{PeerInfo, PeerStore};
+use router::ChannelPenalties;
+pub use types::{
+	AddressLabel, ChannelConfigUpdate, ChannelDecision, ChannelDetails, ChannelFundingParameters,
+	ChannelHandshakeParameters, CoinSelectionStrategy, CustomTlvRecord, DescriptionHash,
+	JitChannelQuote, JitChannelQuoteId, MaxDustHTLCExposure, PeerConnectionStatus, PeerDetails,
+	PeerDisconnectReason, PeerTransport, PendingChannelRequest, ProbeResult, ProbeTarget,
+	RouteHintSelection, SendOnchainParams, SendingParameters, SweepResult, UserChannelId, Utxo,
+	WalletDescriptors,
+};
 use types::{
-	Broadcaster, ChainMonitor, ChannelManager, FeeEstimator, KeysManager, NetworkGraph,
+	Broadcaster, BumpTransactionEventHandler, ChainMonitor, ChainSource, ChannelManager,
+	CustomOnionMessageHandler, FeeEstimator, InboundChannelPolicy, KeysManager, NetworkGraph,
 	PeerManager, Router, Scorer, Sweeper, Wallet,
 };
-pub use types::{ChannelDetails, PeerDetails, UserChannelId};
 
-use logger::{log_error, log_info, log_trace, FilesystemLogger, Logger};
+use logger::{log_error, log_info, log_trace, log_warn, FilesystemLogger, Logger};
 
-use lightning::chain::Confirm;
-use lightning::ln::channelmanager::{self, PaymentId, RecipientOnionFields, Retry};
+use lightning::blinded_path::BlindedPath;
+use lightning::chain::chaininterface::ConfirmationTarget;
+use lightning::ln::channelmanager::{
+	self, ChannelDetails as LdkChannelDetails, PaymentId, PhantomRouteHints, RecipientOnionFields,
+	Retry, MIN_CLTV_EXPIRY_DELTA, MIN_FINAL_CLTV_EXPIRY_DELTA,
+};
+use lightning::ln::features::ChannelFeatures;
 use lightning::ln::msgs::SocketAddress;
-use lightning::ln::{PaymentHash, PaymentPreimage};
+use lightning::ln::script::ShutdownScript;
+use lightning::ln::{ChannelId, PaymentHash, PaymentPreimage};
+use lightning::onion_message::messenger::Destination;
 
 use lightning::sign::EntropySource;
 
-use lightning::util::persist::KVStore;
+use lightning::util::persist::{
+	KVStore, SCORER_PERSISTENCE_KEY, SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
+	SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use lightning::util::ser::Writeable;
 
-use lightning::util::config::{ChannelHandshakeConfig, UserConfig};
+use lightning::util::config::{ChannelHandshakeConfig, ChannelHandshakeLimits, UserConfig};
+use lightning::util::errors::APIError;
 pub use lightning::util::logger::Level as LogLevel;
 
 use lightning_background_processor::process_events_async;
 
-use lightning_transaction_sync::EsploraSyncClient;
-
-use lightning::routing::router::{PaymentParameters, RouteParameters};
-use lightning_invoice::{payment, Bolt11Invoice, Currency};
+use lightning::offers::offer::{Offer, Quantity};
+use lightning::offers::refund::Refund;
+use lightning::routing::gossip::NodeId;
+use lightning::routing::router::{
+	Path, PaymentParameters, Route, RouteHint, RouteHintHop, RouteHop, RouteParameters,
+	Router as LdkRouter,
+};
+use lightning::routing::scoring::ProbabilisticScoringDecayParameters;
+use lightning_invoice::{
+	payment, Bolt11Invoice, CreationError, Currency, InvoiceBuilder, RoutingFees,
+	SignOrCreationError,
+};
 
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::Hash;
-use bitcoin::secp256k1::PublicKey;
+use bitcoin::secp256k1::{PublicKey, Secp256k1};
+
+use bitcoin::bip32::ExtendedPubKey;
+use bitcoin::{Address, OutPoint, Txid};
 
-use bitcoin::{Address, Txid};
+use bdk::KeychainKind;
 
 use rand::Rng;
 
+use std::collections::{HashMap, HashSet};
 use std::default::Default;
-use std::net::ToSocketAddrs;
+use std::future::Future;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::num::NonZeroU64;
+use std::pin::Pin;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
@@ -177,18 +264,21 @@ uniffi::include_scaffolding!("ldk_node");
 /// The main interface object of LDK Node, wrapping the necessary LDK and BDK functionalities.
 ///
 /// Needs to be initialized and instantiated through [`Builder::build`].
-pub struct Node<K: KVStore + Sync + Send + 'static> {
+pub struct Node<K: BatchKVStore + Sync + Send + 'static> {
 	runtime: Arc<RwLock<Option<tokio::runtime::Runtime>>>,
 	stop_sender: tokio::sync::watch::Sender<()>,
+	background_tasks: Mutex<Vec<(&'static str, BackgroundTask)>>,
 	config: Arc<Config>,
 	wallet: Arc<Wallet>,
-	tx_sync: Arc<EsploraSyncClient<Arc<FilesystemLogger>>>,
+	chain_source: Arc<ChainSource>,
 	tx_broadcaster: Arc<Broadcaster>,
 	fee_estimator: Arc<FeeEstimator>,
 	event_queue: Arc<EventQueue<K, Arc<FilesystemLogger>>>,
 	channel_manager: Arc<ChannelManager<K>>,
 	chain_monitor: Arc<ChainMonitor<K>>,
 	output_sweeper: Arc<Sweeper<K>>,
+	bump_tx_event_handler: Arc<BumpTransactionEventHandler>,
+	force_close_reasons: Arc<Mutex<HashMap<u128, String>>>,
 	peer_manager: Arc<PeerManager<K>>,
 	keys_manager: Arc<KeysManager>,
 	network_graph: Arc<NetworkGraph>,
@@ -196,19 +286,36 @@ pub struct Node<K: KVStore + Sync + Send + 'static> {
 	liquidity_source: Option<Arc<LiquiditySource<K, Arc<FilesystemLogger>>>>,
 	kv_store: Arc<K>,
 	logger: Arc<FilesystemLogger>,
-	_router: Arc<Router>,
+	router: Arc<Router>,
 	scorer: Arc<Mutex<Scorer>>,
+	scoring_decay_params: ProbabilisticScoringDecayParameters,
 	peer_store: Arc<PeerStore<K, Arc<FilesystemLogger>>>,
+	peer_monitor: Arc<PeerConnectionMonitor<K, Arc<FilesystemLogger>>>,
 	payment_store: Arc<PaymentStore<K, Arc<FilesystemLogger>>>,
+	payment_retrier: Arc<PaymentRetrier<K, Arc<FilesystemLogger>>>,
+	hold_invoice_handler: Arc<HoldInvoiceHandler<K, Arc<FilesystemLogger>>>,
+	address_book: Arc<AddressBook<K, Arc<FilesystemLogger>>>,
+	onchain_monitor: Arc<OnchainPaymentMonitor<K, Arc<FilesystemLogger>>>,
 	is_listening: Arc<AtomicBool>,
+	sync_paused: Arc<AtomicBool>,
+	sync_now_lock: tokio::sync::Mutex<()>,
+	// Held while syncing any of the wallets or the fee rate cache, so that explicit syncs never
+	// run concurrently with the background syncs.
+	sync_lock: Arc<tokio::sync::Mutex<()>>,
 	latest_wallet_sync_timestamp: Arc<RwLock<Option<u64>>>,
 	latest_onchain_wallet_sync_timestamp: Arc<RwLock<Option<u64>>>,
 	latest_fee_rate_cache_update_timestamp: Arc<RwLock<Option<u64>>>,
 	latest_rgs_snapshot_timestamp: Arc<RwLock<Option<u64>>>,
 	latest_node_announcement_broadcast_timestamp: Arc<RwLock<Option<u64>>>,
+	node_alias: Arc<RwLock<Option<String>>>,
+	node_color: [u8; 3],
+	inbound_channel_policy: Option<Arc<InboundChannelPolicy>>,
+	channel_penalties: Arc<ChannelPenalties>,
+	custom_onion_message_handler: Arc<CustomOnionMessageHandler<K>>,
+	offers_message_handler: Arc<NodeOffersMessageHandler<K>>,
 }
 
-impl<K: KVStore + Sync + Send + 'static> Node<K> {
+impl<K: BatchKVStore + Sync + Send + 'static> Node<K> {
 	/// Starts the necessary background tasks, such as handling events coming from user input,
 	/// LDK/BDK, and the peer-to-peer network.
 	///
@@ -225,12 +332,16 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		log_info!(self.logger, "Starting up LDK Node on network: {}", self.config.network);
 
 		let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+		let mut background_tasks = Vec::new();
 
-		// Block to ensure we update our fee rate cache once on startup
+		// Block to ensure we update our fee rate cache once on startup. If this fails, we continue
+		// with the last known estimates and let the user know they might be stale.
 		let fee_estimator = Arc::clone(&self.fee_estimator);
 		let sync_logger = Arc::clone(&self.logger);
 		let sync_fee_rate_update_timestamp =
 			Arc::clone(&self.latest_fee_rate_cache_update_timestamp);
+		let sync_kv_store = Arc::clone(&self.kv_store);
+		let sync_event_queue = Arc::clone(&self.event_queue);
 		let runtime_ref = &runtime;
 		tokio::task::block_in_place(move || {
 			runtime_ref.block_on(async move {
@@ -245,26 +356,44 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 						let unix_time_secs_opt =
 							SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
 						*sync_fee_rate_update_timestamp.write().unwrap() = unix_time_secs_opt;
-						Ok(())
+						persist_fee_rate_cache(
+							&fee_estimator,
+							unix_time_secs_opt,
+							sync_kv_store,
+							&sync_logger,
+						);
 					},
 					Err(e) => {
-						log_error!(sync_logger, "Initial fee rate cache update failed: {}", e,);
-						Err(e)
+						log_error!(
+							sync_logger,
+							"Initial fee rate cache update failed, continuing with cached fee rates: {}",
+							e
+						);
+						let last_update_timestamp = *sync_fee_rate_update_timestamp.read().unwrap();
+						sync_event_queue
+							.add_event(Event::FeeEstimationStale { last_update_timestamp })
+							.unwrap_or_else(|e| {
+								log_error!(sync_logger, "Failed to push to event queue: {}", e);
+							});
 					},
 				}
 			})
-		})?;
+		});
 
 		// Setup wallet sync
 		let wallet = Arc::clone(&self.wallet);
+		let sync_onchain_monitor = Arc::clone(&self.onchain_monitor);
+		let sync_onchain_cman = Arc::clone(&self.channel_manager);
 		let sync_logger = Arc::clone(&self.logger);
 		let sync_onchain_wallet_timestamp = Arc::clone(&self.latest_onchain_wallet_sync_timestamp);
+		let sync_onchain_paused = Arc::clone(&self.sync_paused);
+		let sync_onchain_lock = Arc::clone(&self.sync_lock);
 		let mut stop_sync = self.stop_sender.subscribe();
 		let onchain_wallet_sync_interval_secs = self
 			.config
 			.onchain_wallet_sync_interval_secs
 			.max(config::WALLET_SYNC_INTERVAL_MINIMUM_SECS);
-		std::thread::spawn(move || {
+		let handle = std::thread::spawn(move || {
 			tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(
 				async move {
 					let mut onchain_wallet_sync_interval = tokio::time::interval(
@@ -278,6 +407,10 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 								return;
 							}
 							_ = onchain_wallet_sync_interval.tick() => {
+								if sync_onchain_paused.load(Ordering::Acquire) {
+									continue;
+								}
+								let _sync_guard = sync_onchain_lock.lock().await;
 								let now = Instant::now();
 								match wallet.sync().await {
 									Ok(()) => {
@@ -289,6 +422,15 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 										let unix_time_secs_opt =
 											SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
 										*sync_onchain_wallet_timestamp.write().unwrap() = unix_time_secs_opt;
+										if let Err(e) =
+											sync_onchain_monitor.update(&wallet, &sync_onchain_cman)
+										{
+											log_error!(
+												sync_logger,
+												"Failed to update on-chain payments: {}",
+												e
+											);
+										}
 									}
 									Err(err) => {
 										log_error!(
@@ -304,14 +446,18 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				},
 			);
 		});
+		background_tasks.push(("on-chain wallet sync", BackgroundTask::Thread(handle)));
 
+		let fee_update_paused = Arc::clone(&self.sync_paused);
+		let fee_update_lock = Arc::clone(&self.sync_lock);
 		let mut stop_fee_updates = self.stop_sender.subscribe();
 		let fee_update_logger = Arc::clone(&self.logger);
 		let fee_update_timestamp = Arc::clone(&self.latest_fee_rate_cache_update_timestamp);
+		let fee_update_kv_store = Arc::clone(&self.kv_store);
 		let fee_estimator = Arc::clone(&self.fee_estimator);
 		let fee_rate_cache_update_interval_secs =
 			self.config.fee_rate_cache_update_interval_secs.max(WALLET_SYNC_INTERVAL_MINIMUM_SECS);
-		runtime.spawn(async move {
+		let handle = runtime.spawn(async move {
 			let mut fee_rate_update_interval =
 				tokio::time::interval(Duration::from_secs(fee_rate_cache_update_interval_secs));
 			// We just blocked on updating, so skip the first tick.
@@ -324,6 +470,10 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 						return;
 					}
 					_ = fee_rate_update_interval.tick() => {
+						if fee_update_paused.load(Ordering::Acquire) {
+							continue;
+						}
+						let _sync_guard = fee_update_lock.lock().await;
 						let now = Instant::now();
 						match fee_estimator.update_fee_estimates().await {
 							Ok(()) => {
@@ -335,6 +485,12 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 								let unix_time_secs_opt =
 									SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
 								*fee_update_timestamp.write().unwrap() = unix_time_secs_opt;
+								persist_fee_rate_cache(
+									&fee_estimator,
+									unix_time_secs_opt,
+									Arc::clone(&fee_update_kv_store),
+									&fee_update_logger,
+								);
 							}
 							Err(err) => {
 								log_error!(
@@ -348,17 +504,20 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				}
 			}
 		});
+		background_tasks.push(("fee rate updates", BackgroundTask::Task(handle)));
 
-		let tx_sync = Arc::clone(&self.tx_sync);
+		let chain_source = Arc::clone(&self.chain_source);
 		let sync_cman = Arc::clone(&self.channel_manager);
 		let sync_cmon = Arc::clone(&self.chain_monitor);
 		let sync_sweeper = Arc::clone(&self.output_sweeper);
 		let sync_logger = Arc::clone(&self.logger);
 		let sync_wallet_timestamp = Arc::clone(&self.latest_wallet_sync_timestamp);
+		let sync_wallet_paused = Arc::clone(&self.sync_paused);
+		let sync_wallet_lock = Arc::clone(&self.sync_lock);
 		let mut stop_sync = self.stop_sender.subscribe();
 		let wallet_sync_interval_secs =
 			self.config.wallet_sync_interval_secs.max(WALLET_SYNC_INTERVAL_MINIMUM_SECS);
-		runtime.spawn(async move {
+		let handle = runtime.spawn(async move {
 			let mut wallet_sync_interval =
 				tokio::time::interval(Duration::from_secs(wallet_sync_interval_secs));
 			wallet_sync_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
@@ -368,13 +527,16 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 						return;
 					}
 					_ = wallet_sync_interval.tick() => {
-						let confirmables = vec![
-							&*sync_cman as &(dyn Confirm + Sync + Send),
-							&*sync_cmon as &(dyn Confirm + Sync + Send),
-							&*sync_sweeper as &(dyn Confirm + Sync + Send),
-						];
+						if sync_wallet_paused.load(Ordering::Acquire) {
+							continue;
+						}
+						let _sync_guard = sync_wallet_lock.lock().await;
 						let now = Instant::now();
-						match tx_sync.sync(confirmables).await {
+						match chain_source.sync_lightning_wallet(
+							Arc::clone(&sync_cman),
+							Arc::clone(&sync_cmon),
+							Arc::clone(&sync_sweeper),
+						).await {
 							Ok(()) => {
 								log_trace!(
 								sync_logger,
@@ -393,14 +555,16 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				}
 			}
 		});
+		background_tasks.push(("Lightning wallet sync", BackgroundTask::Task(handle)));
 
 		if self.gossip_source.is_rgs() {
 			let gossip_source = Arc::clone(&self.gossip_source);
 			let gossip_sync_store = Arc::clone(&self.kv_store);
 			let gossip_sync_logger = Arc::clone(&self.logger);
 			let gossip_rgs_sync_timestamp = Arc::clone(&self.latest_rgs_snapshot_timestamp);
+			let gossip_sync_paused = Arc::clone(&self.sync_paused);
 			let mut stop_gossip_sync = self.stop_sender.subscribe();
-			runtime.spawn(async move {
+			let handle = runtime.spawn(async move {
 				let mut interval = tokio::time::interval(RGS_SYNC_INTERVAL);
 				loop {
 					tokio::select! {
@@ -408,6 +572,9 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 							return;
 						}
 						_ = interval.tick() => {
+							if gossip_sync_paused.load(Ordering::Acquire) {
+								continue;
+							}
 							let gossip_sync_logger = Arc::clone(&gossip_sync_logger);
 							let now = Instant::now();
 							match gossip_source.update_rgs_snapshot().await {
@@ -438,6 +605,7 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 					}
 				}
 			});
+			background_tasks.push(("RGS gossip sync", BackgroundTask::Task(handle)));
 		}
 
 		if let Some(listening_addresses) = &self.config.listening_addresses {
@@ -463,7 +631,7 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				bind_addrs.extend(resolved_address);
 			}
 
-			runtime.spawn(async move {
+			let handle = runtime.spawn(async move {
 				{
 				let listener =
 					tokio::net::TcpListener::bind(&*bind_addrs).await
@@ -498,14 +666,114 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 
 				listening_indicator.store(false, Ordering::Release);
 			});
+			background_tasks.push(("peer listener", BackgroundTask::Task(handle)));
 		}
 
-		// Regularly reconnect to persisted peers.
+		// Regularly check for changes in our peer connections.
+		let monitor_pm = Arc::clone(&self.peer_manager);
+		let monitor = Arc::clone(&self.peer_monitor);
+		let mut stop_monitor = self.stop_sender.subscribe();
+		let handle = runtime.spawn(async move {
+			let mut interval = tokio::time::interval(PEER_MONITOR_INTERVAL);
+			interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+			loop {
+				tokio::select! {
+						_ = stop_monitor.changed() => {
+							return;
+						}
+						_ = interval.tick() => {
+							monitor.update(monitor_pm.get_peer_node_ids());
+						}
+				}
+			}
+		});
+		background_tasks.push(("peer monitor", BackgroundTask::Task(handle)));
+
+		// Reconnect to persistent peers with exponential backoff.
+		let persistent_pm = Arc::clone(&self.peer_manager);
+		let persistent_logger = Arc::clone(&self.logger);
+		let persistent_peer_store = Arc::clone(&self.peer_store);
+		let persistent_tor_proxy = self.config.tor_proxy_address;
+		let persistent_peer_monitor = Arc::clone(&self.peer_monitor);
+		let initial_delay =
+			Duration::from_secs(self.config.persistent_peer_reconnection_initial_delay_secs);
+		let max_delay =
+			Duration::from_secs(self.config.persistent_peer_reconnection_max_delay_secs)
+				.max(initial_delay);
+		let mut stop_persistent = self.stop_sender.subscribe();
+		let handle = runtime.spawn(async move {
+			// Tracks the next reconnection attempt and the current delay for each disconnected peer.
+			let mut backoff_states: HashMap<PublicKey, (Instant, Duration)> = HashMap::new();
+			let mut interval = tokio::time::interval(PERSISTENT_PEER_RECONNECTION_CHECK_INTERVAL);
+			interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+			loop {
+				tokio::select! {
+						_ = stop_persistent.changed() => {
+							return;
+						}
+						_ = interval.tick() => {
+							let pm_peers = persistent_pm
+								.get_peer_node_ids()
+								.iter()
+								.map(|(peer, _addr)| *peer)
+								.collect::<Vec<_>>();
+
+							let persistent_peers = persistent_peer_store
+								.list_peers()
+								.into_iter()
+								.filter(|info| info.is_persistent)
+								.collect::<Vec<_>>();
+
+							backoff_states.retain(|node_id, _| {
+								!pm_peers.contains(node_id)
+									&& persistent_peers.iter().any(|info| info.node_id == *node_id)
+							});
+
+							for peer_info in persistent_peers.iter().filter(|info| !pm_peers.contains(&info.node_id)) {
+								let now = Instant::now();
+								let (next_attempt, delay) = *backoff_states
+									.entry(peer_info.node_id)
+									.or_insert((now + initial_delay, initial_delay));
+
+								if now < next_attempt {
+									continue;
+								}
+
+								let res = do_connect_peer(
+									peer_info.node_id,
+									peer_info.address.clone(),
+									peer_info.transport,
+									persistent_tor_proxy,
+									Arc::clone(&persistent_pm),
+									Arc::clone(&persistent_peer_monitor),
+									Arc::clone(&persistent_logger),
+									).await;
+								match res {
+									Ok(_) => {
+										log_info!(persistent_logger, "Successfully reconnected to persistent peer {}", peer_info.node_id);
+										backoff_states.remove(&peer_info.node_id);
+									},
+									Err(e) => {
+										let next_delay = (delay * 2).min(max_delay);
+										log_error!(persistent_logger, "Failed to reconnect to persistent peer {}, retrying in {}s: {}", peer_info.node_id, next_delay.as_secs(), e);
+										backoff_states.insert(peer_info.node_id, (Instant::now() + next_delay, next_delay));
+									}
+								}
+							}
+						}
+				}
+			}
+		});
+		background_tasks.push(("persistent peer reconnection", BackgroundTask::Task(handle)));
+
+		// Regularly reconnect to other persisted peers.
 		let connect_pm = Arc::clone(&self.peer_manager);
 		let connect_logger = Arc::clone(&self.logger);
 		let connect_peer_store = Arc::clone(&self.peer_store);
+		let connect_tor_proxy = self.config.tor_proxy_address;
+		let connect_peer_monitor = Arc::clone(&self.peer_monitor);
 		let mut stop_connect = self.stop_sender.subscribe();
-		runtime.spawn(async move {
+		let handle = runtime.spawn(async move {
 			let mut interval = tokio::time::interval(PEER_RECONNECTION_INTERVAL);
 			interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 			loop {
@@ -520,11 +788,14 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 								.map(|(peer, _addr)| *peer)
 								.collect::<Vec<_>>();
 
-							for peer_info in connect_peer_store.list_peers().iter().filter(|info| !pm_peers.contains(&info.node_id)) {
+							for peer_info in connect_peer_store.list_peers().iter().filter(|info| !info.is_persistent && !pm_peers.contains(&info.node_id)) {
 								let res = do_connect_peer(
 									peer_info.node_id,
 									peer_info.address.clone(),
+									peer_info.transport,
+									connect_tor_proxy,
 									Arc::clone(&connect_pm),
+									Arc::clone(&connect_peer_monitor),
 									Arc::clone(&connect_logger),
 									).await;
 								match res {
@@ -540,6 +811,7 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				}
 			}
 		});
+		background_tasks.push(("peer reconnection", BackgroundTask::Task(handle)));
 
 		// Regularly broadcast node announcements.
 		let bcast_cm = Arc::clone(&self.channel_manager);
@@ -548,9 +820,12 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		let bcast_store = Arc::clone(&self.kv_store);
 		let bcast_logger = Arc::clone(&self.logger);
 		let bcast_ann_timestamp = Arc::clone(&self.latest_node_announcement_broadcast_timestamp);
+		let bcast_node_alias = Arc::clone(&self.node_alias);
+		let bcast_node_color = self.node_color;
+		let bcast_interval = Duration::from_secs(self.config.node_announcement_interval_secs);
 		let mut stop_bcast = self.stop_sender.subscribe();
-		runtime.spawn(async move {
-			// We check every 30 secs whether our last broadcast is NODE_ANN_BCAST_INTERVAL away.
+		let handle = runtime.spawn(async move {
+			// We check every 30 secs whether our last broadcast is `bcast_interval` away.
 			let mut interval = tokio::time::interval(Duration::from_secs(30));
 			loop {
 				tokio::select! {
@@ -561,7 +836,7 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 							let skip_broadcast = match io::utils::read_latest_node_ann_bcast_timestamp(Arc::clone(&bcast_store), Arc::clone(&bcast_logger)) {
 								Ok(latest_bcast_time_secs) => {
 									// Skip if the time hasn't elapsed yet.
-									let next_bcast_unix_time = SystemTime::UNIX_EPOCH + Duration::from_secs(latest_bcast_time_secs) + NODE_ANN_BCAST_INTERVAL;
+									let next_bcast_unix_time = SystemTime::UNIX_EPOCH + Duration::from_secs(latest_bcast_time_secs) + bcast_interval;
 									next_bcast_unix_time.elapsed().is_err()
 								}
 								Err(_) => {
@@ -584,14 +859,24 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 								continue;
 							}
 
-							let addresses = bcast_config.listening_addresses.clone().unwrap_or(Vec::new());
+							let addresses = bcast_config
+								.announced_addresses
+								.clone()
+								.or_else(|| bcast_config.listening_addresses.clone())
+								.unwrap_or(Vec::new());
 
 							if addresses.is_empty() {
-								// Skip if we are not listening on any addresses.
+								// Skip if we don't have any addresses to announce.
 								continue;
 							}
 
-							bcast_pm.broadcast_node_announcement([0; 3], [0; 32], addresses);
+							let alias = bcast_node_alias
+								.read()
+								.unwrap()
+								.as_deref()
+								.and_then(node_alias_bytes)
+								.unwrap_or([0; 32]);
+							bcast_pm.broadcast_node_announcement(bcast_node_color, alias, addresses);
 
 							let unix_time_secs_opt =
 								SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
@@ -608,10 +893,11 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				}
 			}
 		});
+		background_tasks.push(("node announcement broadcast", BackgroundTask::Task(handle)));
 
 		let mut stop_tx_bcast = self.stop_sender.subscribe();
 		let tx_bcaster = Arc::clone(&self.tx_broadcaster);
-		runtime.spawn(async move {
+		let handle = runtime.spawn(async move {
 			// Every second we try to clear our broadcasting queue.
 			let mut interval = tokio::time::interval(Duration::from_secs(1));
 			interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
@@ -626,15 +912,114 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				}
 			}
 		});
+		background_tasks.push(("transaction broadcast", BackgroundTask::Task(handle)));
+
+		let mut stop_payment_retries = self.stop_sender.subscribe();
+		let payment_retrier = Arc::clone(&self.payment_retrier);
+		let handle = runtime.spawn(async move {
+			// Every second we make new attempts for the failed payments that are due for a retry,
+			// including any that were still pending when we were last stopped.
+			let mut interval = tokio::time::interval(Duration::from_secs(1));
+			interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+			loop {
+				tokio::select! {
+						_ = stop_payment_retries.changed() => {
+							return;
+						}
+						_ = interval.tick() => {
+							payment_retrier.process_pending_retries();
+						}
+				}
+			}
+		});
+		background_tasks.push(("payment retries", BackgroundTask::Task(handle)));
+
+		let mut stop_held_payments = self.stop_sender.subscribe();
+		let hold_invoice_handler = Arc::clone(&self.hold_invoice_handler);
+		let held_payments_chan_man = Arc::clone(&self.channel_manager);
+		let handle = runtime.spawn(async move {
+			let mut interval = tokio::time::interval(HELD_PAYMENT_CHECK_INTERVAL);
+			interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+			loop {
+				tokio::select! {
+						_ = stop_held_payments.changed() => {
+							return;
+						}
+						_ = interval.tick() => {
+							let cur_height = held_payments_chan_man.current_best_block().height();
+							hold_invoice_handler.fail_expiring_payments(cur_height);
+							if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+								hold_invoice_handler.prune_resolved_payments(now.as_secs());
+							}
+						}
+				}
+			}
+		});
+		background_tasks.push(("held payments", BackgroundTask::Task(handle)));
+
+		let mut stop_invoice_expiry = self.stop_sender.subscribe();
+		let expiry_payment_store = Arc::clone(&self.payment_store);
+		let expiry_hold_handler = Arc::clone(&self.hold_invoice_handler);
+		let expiry_event_queue = Arc::clone(&self.event_queue);
+		let expiry_logger = Arc::clone(&self.logger);
+		let handle = runtime.spawn(async move {
+			let mut interval = tokio::time::interval(INVOICE_EXPIRY_CHECK_INTERVAL);
+			interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+			loop {
+				tokio::select! {
+						_ = stop_invoice_expiry.changed() => {
+							return;
+						}
+						_ = interval.tick() => {
+							let now_secs = match SystemTime::now().duration_since(UNIX_EPOCH) {
+								Ok(now) => now.as_secs(),
+								Err(_) => continue,
+							};
+							// Payments that arrived but are still held aren't considered expired.
+							let held_hashes = expiry_hold_handler.claimable_payment_hashes();
+							match expiry_payment_store.expire_invoices(now_secs, &held_hashes) {
+								Ok(expired_hashes) => {
+									for payment_hash in expired_hashes {
+										log_info!(
+											expiry_logger,
+											"Invoice for payment with hash {} expired",
+											hex_utils::to_string(&payment_hash.0)
+										);
+										expiry_event_queue
+											.add_event(Event::PaymentExpired { payment_hash })
+											.unwrap_or_else(|e| {
+												log_error!(
+													expiry_logger,
+													"Failed to push to event queue: {}",
+													e
+												);
+											});
+									}
+								},
+								Err(e) => {
+									log_error!(expiry_logger, "Failed to expire invoices: {}", e);
+								},
+							}
+						}
+				}
+			}
+		});
+		background_tasks.push(("invoice expiry", BackgroundTask::Task(handle)));
 
 		let event_handler = Arc::new(EventHandler::new(
 			Arc::clone(&self.event_queue),
 			Arc::clone(&self.wallet),
 			Arc::clone(&self.channel_manager),
 			Arc::clone(&self.output_sweeper),
+			Arc::clone(&self.bump_tx_event_handler),
+			Arc::clone(&self.force_close_reasons),
 			Arc::clone(&self.network_graph),
 			Arc::clone(&self.payment_store),
+			Arc::clone(&self.payment_retrier),
+			Arc::clone(&self.hold_invoice_handler),
 			Arc::clone(&self.peer_store),
+			self.inbound_channel_policy.clone(),
+			Arc::clone(&self.router),
 			Arc::clone(&self.runtime),
 			Arc::clone(&self.logger),
 			Arc::clone(&self.config),
@@ -665,7 +1050,7 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 			})
 		};
 
-		runtime.spawn(async move {
+		let handle = runtime.spawn(async move {
 			process_events_async(
 				background_persister,
 				|e| background_event_handler.handle_event(e),
@@ -685,11 +1070,12 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				panic!("Failed to process events");
 			});
 		});
+		background_tasks.push(("background processor", BackgroundTask::Task(handle)));
 
 		if let Some(liquidity_source) = self.liquidity_source.as_ref() {
 			let mut stop_liquidity_handler = self.stop_sender.subscribe();
 			let liquidity_handler = Arc::clone(&liquidity_source);
-			runtime.spawn(async move {
+			let handle = runtime.spawn(async move {
 				loop {
 					tokio::select! {
 						_ = stop_liquidity_handler.changed() => {
@@ -699,8 +1085,10 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 					}
 				}
 			});
+			background_tasks.push(("liquidity handler", BackgroundTask::Task(handle)));
 		}
 
+		*self.background_tasks.lock().unwrap() = background_tasks;
 		*runtime_lock = Some(runtime);
 
 		log_info!(self.logger, "Startup complete.");
@@ -711,7 +1099,24 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 	///
 	/// After this returns most API methods will return [`Error::NotRunning`].
 	pub fn stop(&self) -> Result<(), Error> {
+		match self.stop_with_timeout(Duration::from_secs(10)) {
+			Err(Error::ShutdownTimedOut) => Ok(()),
+			res => res,
+		}
+	}
+
+	/// Disconnects all peers, stops all running background tasks, and shuts down [`Node`],
+	/// waiting at most the given `timeout` for the background tasks to finish.
+	///
+	/// Once the background tasks stopped or the timeout elapsed, the event queue and the scorer
+	/// are persisted. This allows to cleanly shut down the node, e.g., when receiving a `SIGTERM`.
+	///
+	/// Will return [`Error::ShutdownTimedOut`] if any background tasks, such as chain syncing,
+	/// gossip syncing, or peer reconnection, were still running when the timeout elapsed. The
+	/// respective tasks are logged. The node is considered stopped either way.
+	pub fn stop_with_timeout(&self, timeout: Duration) -> Result<(), Error> {
 		let runtime = self.runtime.write().unwrap().take().ok_or(Error::NotRunning)?;
+		let deadline = Instant::now() + timeout;
 
 		log_info!(self.logger, "Shutting down LDK Node...");
 
@@ -731,12 +1136,80 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		// Stop disconnect peers.
 		self.peer_manager.disconnect_all_peers();
 
-		runtime.shutdown_timeout(Duration::from_secs(10));
+		// Wait for the background tasks to wind down.
+		let background_tasks = std::mem::take(&mut *self.background_tasks.lock().unwrap());
+		while Instant::now() < deadline
+			&& background_tasks.iter().any(|(_, task)| !task.is_finished())
+		{
+			std::thread::sleep(Duration::from_millis(10));
+		}
+		let still_running = background_tasks
+			.iter()
+			.filter(|(_, task)| !task.is_finished())
+			.map(|(name, _)| *name)
+			.collect::<Vec<_>>();
+
+		// Flush the state that isn't necessarily persisted by the background tasks.
+		let mut flush_failed = false;
+		if let Err(e) = self.event_queue.persist() {
+			log_error!(self.logger, "Failed to persist event queue during shutdown: {}", e);
+			flush_failed = true;
+		}
+		let scorer_bytes = self.scorer.lock().unwrap().encode();
+		if let Err(e) = self.kv_store.write(
+			SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
+			SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+			SCORER_PERSISTENCE_KEY,
+			&scorer_bytes,
+		) {
+			log_error!(self.logger, "Failed to persist scorer during shutdown: {}", e);
+			flush_failed = true;
+		}
+
+		runtime.shutdown_timeout(deadline.saturating_duration_since(Instant::now()));
+
+		if !still_running.is_empty() {
+			log_error!(
+				self.logger,
+				"Shutdown timed out, background tasks still running: {}",
+				still_running.join(", ")
+			);
+			return Err(Error::ShutdownTimedOut);
+		}
+
+		if flush_failed {
+			return Err(Error::PersistenceFailed);
+		}
 
 		log_info!(self.logger, "Shutdown complete.");
 		Ok(())
 	}
 
+	/// Pauses the periodic background syncing of the on-chain and Lightning wallets, the fee rate
+	/// cache, and the Rapid Gossip Sync data.
+	///
+	/// The node keeps running and stays connected to its peers. While syncing is paused,
+	/// operations relying on fresh chain data, such as [`Node::sync_wallets`] or sending on-chain
+	/// payments, will fail with [`Error::SyncPaused`].
+	///
+	/// Syncing can be resumed via [`Node::resume_background_sync`].
+	pub fn pause_background_sync(&self) {
+		self.sync_paused.store(true, Ordering::Release);
+		log_info!(self.logger, "Paused background syncing.");
+	}
+
+	/// Resumes the periodic background syncing previously paused via
+	/// [`Node::pause_background_sync`].
+	pub fn resume_background_sync(&self) {
+		self.sync_paused.store(false, Ordering::Release);
+		log_info!(self.logger, "Resumed background syncing.");
+	}
+
+	/// Returns whether background syncing is currently paused.
+	pub fn is_sync_paused(&self) -> bool {
+		self.sync_paused.load(Ordering::Acquire)
+	}
+
 	/// Returns the status of the [`Node`].
 	pub fn status(&self) -> NodeStatus {
 		let is_running = self.runtime.read().unwrap().is_some();
@@ -763,6 +1236,76 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		}
 	}
 
+	/// Checks the health of the [`Node`], e.g., to serve readiness or liveness probes.
+	///
+	/// This checks how far we lag behind the chain tip reported by our chain source, whether our
+	/// fee rate estimates and Rapid Gossip Sync data are fresh, whether the [`KVStore`] can be
+	/// written to and read from, and whether we're connected to any peers.
+	///
+	/// **Note:** This queries the chain source and writes to the [`KVStore`], so it shouldn't be
+	/// called too frequently.
+	pub fn health_check(&self) -> HealthStatus {
+		let now_secs =
+			SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+		let chain_sync = {
+			let rt_lock = self.runtime.read().unwrap();
+			match rt_lock.as_ref() {
+				Some(runtime) => {
+					let tip_height_res = tokio::task::block_in_place(|| {
+						runtime.block_on(self.chain_source.get_tip_height())
+					});
+					match tip_height_res {
+						Ok(tip_height) => health::check_chain_sync(
+							self.channel_manager.current_best_block().height(),
+							tip_height,
+						),
+						Err(e) => HealthCheck::fail(format!("Failed to retrieve chain tip: {}", e)),
+					}
+				},
+				None => HealthCheck::fail("Node is not running"),
+			}
+		};
+
+		let fee_estimates = health::check_freshness(
+			"Fee rate estimates",
+			*self.latest_fee_rate_cache_update_timestamp.read().unwrap(),
+			self.config.fee_rate_cache_update_interval_secs.max(WALLET_SYNC_INTERVAL_MINIMUM_SECS),
+			now_secs,
+		);
+
+		let kv_store = match io::utils::probe_kv_store(&*self.kv_store, &now_secs.to_be_bytes()) {
+			Ok(()) => HealthCheck::ok("Store is reachable"),
+			Err(e) => {
+				log_error!(self.logger, "Failed to probe store: {}", e);
+				HealthCheck::fail(format!("Failed to probe store: {}", e))
+			},
+		};
+
+		let num_channel_peers = self
+			.channel_manager
+			.list_channels()
+			.iter()
+			.map(|c| c.counterparty.node_id)
+			.collect::<HashSet<_>>()
+			.len();
+		let peer_connectivity = health::check_peer_connectivity(
+			self.peer_manager.get_peer_node_ids().len(),
+			num_channel_peers,
+		);
+
+		let gossip = if self.gossip_source.is_rgs() {
+			health::check_rgs_freshness(
+				*self.latest_rgs_snapshot_timestamp.read().unwrap(),
+				now_secs,
+			)
+		} else {
+			HealthCheck::ok("Gossip is synced via the peer-to-peer network")
+		};
+
+		HealthStatus { chain_sync, fee_estimates, kv_store, peer_connectivity, gossip }
+	}
+
 	/// Returns the config with which the [`Node`] was initialized.
 	pub fn config(&self) -> Config {
 		self.config.as_ref().clone()
@@ -786,6 +1329,33 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		self.event_queue.next_event_async().await
 	}
 
+	/// Returns the next event in the event queue along with the time it was generated, if
+	/// currently available.
+	///
+	/// This behaves exactly like [`Node::next_event`], i.e., it will always return the same event
+	/// until handling is confirmed via [`Node::event_handled`].
+	pub fn next_timestamped_event(&self) -> Option<TimestampedEvent> {
+		self.event_queue.next_timestamped_event()
+	}
+
+	/// Returns the next event in the event queue along with the time it was generated.
+	///
+	/// This behaves exactly like [`Node::wait_next_event`], i.e., it will block the current thread
+	/// until the next event is available.
+	pub fn wait_next_timestamped_event(&self) -> TimestampedEvent {
+		self.event_queue.wait_next_timestamped_event()
+	}
+
+	/// Returns up to `max` pending events in the order they will be returned by
+	/// [`Node::next_event`], without marking any of them handled.
+	///
+	/// This is meant for introspection of the event queue, e.g., for debugging purposes, and
+	/// doesn't interfere with consuming events via [`Node::next_event`] and
+	/// [`Node::event_handled`].
+	pub fn peek_events(&self, max: u32) -> Vec<Event> {
+		self.event_queue.peek_events(max as usize)
+	}
+
 	/// Returns the next event in the event queue.
 	///
 	/// Will block the current thread until the next event is available.
@@ -795,6 +1365,45 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		self.event_queue.wait_next_event()
 	}
 
+	/// Returns the first event in the event queue matching the given filter, if currently
+	/// available.
+	///
+	/// Non-matching events are skipped but stay in the queue, i.e., they are still returned by
+	/// [`Node::next_event`] or for other filters. This allows, e.g., one task to wait for
+	/// [`Event::PaymentReceived`] while another handles [`Event::ChannelReady`].
+	///
+	/// **Note:** Handling of the returned event needs to be confirmed via
+	/// [`Node::matching_event_handled`], not via [`Node::event_handled`].
+	pub fn next_event_matching<F: Fn(&Event) -> bool>(&self, filter: F) -> Option<Event> {
+		self.event_queue.next_event_matching(filter)
+	}
+
+	/// Returns the first event in the event queue matching the given filter.
+	///
+	/// Will block the current thread until a matching event is available. Non-matching events are
+	/// skipped but stay in the queue.
+	///
+	/// **Note:** Handling of the returned event needs to be confirmed via
+	/// [`Node::matching_event_handled`], not via [`Node::event_handled`].
+	pub fn wait_next_event_matching<F: Fn(&Event) -> bool>(&self, filter: F) -> Event {
+		self.event_queue.wait_next_event_matching(filter)
+	}
+
+	/// Confirm the given event, as retrieved via [`Node::next_event_matching`] or
+	/// [`Node::wait_next_event_matching`], handled.
+	///
+	/// This removes the first pending event equal to the given one from the event queue.
+	pub fn matching_event_handled(&self, event: &Event) {
+		self.event_queue.matching_event_handled(event).unwrap_or_else(|e| {
+			log_error!(
+				self.logger,
+				"Couldn't mark event handled due to persistence failure: {}",
+				e
+			);
+			panic!("Couldn't mark event handled due to persistence failure");
+		});
+	}
+
 	/// Confirm the last retrieved event handled.
 	///
 	/// **Note:** This **MUST** be called after each event has been handled.
@@ -809,6 +1418,39 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		});
 	}
 
+	/// Returns the events that were moved to the dead-letter store as they weren't marked handled
+	/// after [`Config::max_event_redeliveries`] redeliveries.
+	pub fn list_dead_letter_events(&self) -> Result<Vec<DeadLetterEvent>, Error> {
+		self.event_queue.list_dead_letter_events()
+	}
+
+	/// Moves the dead-letter event with the given id back to the end of the event queue, so that
+	/// it's delivered again.
+	///
+	/// Will return [`Error::DeadLetterEventNotFound`] if no dead-letter event with the given id
+	/// exists.
+	pub fn replay_dead_letter_event(&self, id: &str) -> Result<(), Error> {
+		self.event_queue.replay_dead_letter_event(id)?;
+		log_info!(self.logger, "Replayed dead-letter event {}", id);
+		Ok(())
+	}
+
+	/// Returns a [`Stream`] of events that can be consumed alongside the other event APIs.
+	///
+	/// Each event is automatically confirmed as handled once the next item is polled from the
+	/// stream, i.e., after the consumer finished processing it. Events are yielded one at a time, so
+	/// a slow consumer exerts backpressure rather than losing events. If the stream is dropped
+	/// while an event is in flight, that event is not marked as handled and will be returned again.
+	///
+	/// **Note:** as events are acknowledged automatically, the stream should not be used
+	/// concurrently with [`Node::event_handled`].
+	///
+	/// [`Stream`]: futures::Stream
+	#[cfg(feature = "event-stream")]
+	pub fn event_stream(&self) -> impl futures::Stream<Item = Event> + Send + Unpin {
+		event::EventStream::new(Arc::clone(&self.event_queue))
+	}
+
 	/// Returns our own node id
 	pub fn node_id(&self) -> PublicKey {
 		self.channel_manager.get_our_node_id()
@@ -819,6 +1461,42 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		self.config.listening_addresses.clone()
 	}
 
+	/// Returns the alias we use in our node announcements, if any.
+	pub fn node_alias(&self) -> Option<String> {
+		self.node_alias.read().unwrap().clone()
+	}
+
+	/// Sets the alias we use in our node announcements.
+	///
+	/// The alias must not exceed 32 bytes when UTF-8 encoded. A fresh node announcement carrying
+	/// the new alias will be broadcast by the background processor shortly after.
+	///
+	/// **Note:** The alias set here is not persisted and will be reset to the one configured via
+	/// the builder on restart.
+	pub fn set_node_alias(&self, node_alias: String) -> Result<(), Error> {
+		if node_alias_bytes(&node_alias).is_none() {
+			log_error!(self.logger, "Failed to set node alias: alias exceeds 32 bytes.");
+			return Err(Error::InvalidNodeAlias);
+		}
+
+		*self.node_alias.write().unwrap() = Some(node_alias);
+
+		// Reset the broadcast timestamp so that the next broadcast attempt isn't skipped.
+		io::utils::write_latest_node_ann_bcast_timestamp(
+			0,
+			Arc::clone(&self.kv_store),
+			Arc::clone(&self.logger),
+		)?;
+		Ok(())
+	}
+
+	/// Returns the addresses we announce to the gossip network.
+	///
+	/// Falls back to our listening addresses if no dedicated announced addresses were configured.
+	pub fn announced_addresses(&self) -> Option<Vec<SocketAddress>> {
+		self.config.announced_addresses.clone().or_else(|| self.config.listening_addresses.clone())
+	}
+
 	/// Retrieve a new on-chain/funding address.
 	pub fn new_onchain_address(&self) -> Result<Address, Error> {
 		let funding_address = self.wallet.get_new_address()?;
@@ -826,6 +1504,54 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		Ok(funding_address)
 	}
 
+	/// Retrieve a new on-chain/funding address and attach the given label to it.
+	///
+	/// The label will be persisted and may later be retrieved via
+	/// [`Node::onchain_address_labels`]. It must be non-empty, must not exceed 64 bytes, and must
+	/// not contain any control characters.
+	pub fn new_onchain_address_with_label(&self, label: &str) -> Result<Address, Error> {
+		if !is_valid_address_label(label) {
+			log_error!(self.logger, "Failed to generate labeled address: invalid label.");
+			return Err(Error::InvalidAddressLabel);
+		}
+
+		let address = self.wallet.get_new_address()?;
+		self.address_book.set_label(&address, label.to_string())?;
+		log_info!(self.logger, "Generated new funding address {} with label {}", address, label);
+		Ok(address)
+	}
+
+	/// Returns the labels attached to our on-chain addresses.
+	pub fn onchain_address_labels(&self) -> Vec<AddressLabel> {
+		self.address_book.list_labels()
+	}
+
+	/// Returns the BIP84 account-level extended public key of the on-chain wallet.
+	///
+	/// This allows to watch the on-chain wallet externally and doesn't expose any private key
+	/// material.
+	pub fn account_xpub(&self) -> ExtendedPubKey {
+		self.wallet.account_xpub()
+	}
+
+	/// Returns the public output descriptors of the on-chain wallet.
+	///
+	/// These may be imported into another wallet, e.g., Bitcoin Core, to set up a watch-only copy
+	/// of the on-chain wallet. They don't expose any private key material.
+	pub fn wallet_descriptors(&self) -> Result<WalletDescriptors, Error> {
+		let external = self.wallet.public_descriptor(KeychainKind::External)?;
+		let internal = self.wallet.public_descriptor(KeychainKind::Internal)?;
+		Ok(WalletDescriptors { external, internal })
+	}
+
+	/// Returns the unspent outputs of the on-chain wallet.
+	///
+	/// This may be used to pick the UTXOs to spend via [`Node::send_to_onchain_address_with_params`].
+	pub fn list_onchain_utxos(&self) -> Result<Vec<Utxo>, Error> {
+		let cur_height = self.channel_manager.current_best_block().height();
+		self.wallet.list_utxos(cur_height)
+	}
+
 	/// Send an on-chain payment to the given address.
 	pub fn send_to_onchain_address(
 		&self, address: &bitcoin::Address, amount_sats: u64,
@@ -834,13 +1560,45 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		if rt_lock.is_none() {
 			return Err(Error::NotRunning);
 		}
+		if self.sync_paused.load(Ordering::Acquire) {
+			return Err(Error::SyncPaused);
+		}
+
+		let cur_balance = self.wallet.get_balance()?;
+		let anchor_reserve_sats =
+			total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
+		if cur_balance.get_spendable().saturating_sub(anchor_reserve_sats) < amount_sats {
+			log_error!(self.logger, "Unable to send payment due to insufficient funds.");
+			return Err(Error::InsufficientFunds);
+		}
+		self.wallet.send_to_address(address, amount_sats)
+	}
+
+	/// Send an on-chain payment to the given address, using the given fee rate, coin selection
+	/// strategy, and UTXOs.
+	///
+	/// Will fail with [`Error::InvalidAmount`] if the payment output would be below the dust limit,
+	/// and with [`Error::InsufficientFunds`] if the amount exceeds our spendable balance minus the
+	/// reserve kept for Anchor channels.
+	pub fn send_to_onchain_address_with_params(
+		&self, address: &bitcoin::Address, amount_sats: u64, params: SendOnchainParams,
+	) -> Result<Txid, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+		if self.sync_paused.load(Ordering::Acquire) {
+			return Err(Error::SyncPaused);
+		}
 
 		let cur_balance = self.wallet.get_balance()?;
-		if cur_balance.get_spendable() < amount_sats {
+		let anchor_reserve_sats =
+			total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
+		if cur_balance.get_spendable().saturating_sub(anchor_reserve_sats) < amount_sats {
 			log_error!(self.logger, "Unable to send payment due to insufficient funds.");
 			return Err(Error::InsufficientFunds);
 		}
-		self.wallet.send_to_address(address, Some(amount_sats))
+		self.wallet.send_to_address_with_params(address, amount_sats, params)
 	}
 
 	/// Send an on-chain payment to the given address, draining all the available funds.
@@ -849,39 +1607,185 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		if rt_lock.is_none() {
 			return Err(Error::NotRunning);
 		}
+		if self.sync_paused.load(Ordering::Acquire) {
+			return Err(Error::SyncPaused);
+		}
 
-		self.wallet.send_to_address(address, None)
-	}
-
-	/// Retrieve a list of known channels.
-	pub fn list_channels(&self) -> Vec<ChannelDetails> {
-		self.channel_manager.list_channels().into_iter().map(|c| c.into()).collect()
+		self.wallet.sweep_to_address(address, None, 0).map(|sweep| sweep.txid)
 	}
 
-	/// Connect to a node on the peer-to-peer network.
+	/// Sweep the on-chain wallet to the given address, optionally retaining the Anchor reserve.
 	///
-	/// If `persist` is set to `true`, we'll remember the peer and reconnect to it on restart.
-	pub fn connect(
-		&self, node_id: PublicKey, address: SocketAddress, persist: bool,
-	) -> Result<(), Error> {
+	/// The transaction fee, determined by the given fee rate or our fee estimator if unset, is
+	/// deducted from the swept amount so that no change is left. However, unless `include_reserve`
+	/// is set, the funds we need to keep in reserve to bump the fees of Anchor channel closing
+	/// transactions are retained in a change output. UTXOs reserved to fund pending channels are
+	/// never spent.
+	///
+	/// Returns the txid of the sweeping transaction along with the swept amount.
+	pub fn sweep_to_onchain_address(
+		&self, address: &bitcoin::Address, sat_per_vbyte: Option<u32>, include_reserve: bool,
+	) -> Result<SweepResult, Error> {
 		let rt_lock = self.runtime.read().unwrap();
 		if rt_lock.is_none() {
 			return Err(Error::NotRunning);
 		}
-		let runtime = rt_lock.as_ref().unwrap();
+		if self.sync_paused.load(Ordering::Acquire) {
+			return Err(Error::SyncPaused);
+		}
 
-		let peer_info = PeerInfo { node_id, address };
+		let reserve_sats = if include_reserve {
+			0
+		} else {
+			total_anchor_channels_reserve_sats(&self.channel_manager, &self.config)
+		};
+		self.wallet.sweep_to_address(address, sat_per_vbyte, reserve_sats)
+	}
 
-		let con_node_id = peer_info.node_id;
+	/// Bumps the fee of the given unconfirmed transaction to the given fee rate.
+	///
+	/// Transactions sweeping channel outputs to our wallet as well as other transactions sent from
+	/// our on-chain wallet are replaced (RBF). As replacing it would invalidate the channel, the
+	/// fee of a channel funding transaction is bumped by spending our change output in a child
+	/// transaction (CPFP). Likewise, unconfirmed transactions paying to our wallet are bumped via
+	/// CPFP.
+	///
+	/// Will fail with [`Error::TransactionNotFound`] if the transaction is unknown to us, and with
+	/// [`Error::TransactionAlreadyConfirmed`] if it's already confirmed.
+	///
+	/// Returns the txid of the replacement or child transaction.
+	pub fn bump_transaction_fee(&self, txid: &Txid, sat_per_vbyte: u64) -> Result<Txid, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let fee_rate_sat_per_kw =
+			wallet::sat_per_vbyte_to_sat_per_kw(sat_per_vbyte).ok_or_else(|| {
+				log_error!(
+					self.logger,
+					"The given fee rate of {}sat/vB is below the minimum relay fee rate",
+					sat_per_vbyte
+				);
+				Error::InvalidFeeRate
+			})?;
+
+		if self.output_sweeper.tracks_spending_tx(txid) {
+			return self.output_sweeper.bump_fee(txid, fee_rate_sat_per_kw);
+		}
+
+		let is_funding_tx = self
+			.channel_manager
+			.list_channels()
+			.iter()
+			.any(|c| c.funding_txo.map_or(false, |o| o.txid == *txid));
+		self.wallet.bump_transaction_fee(txid, fee_rate_sat_per_kw, is_funding_tx)
+	}
+
+	/// Retrieve a list of known channels.
+	pub fn list_channels(&self) -> Vec<ChannelDetails> {
+		self.channel_manager.list_channels().into_iter().map(|c| c.into()).collect()
+	}
+
+	/// Retrieve a list of known channels with the given counterparty.
+	pub fn list_channels_with_peer(&self, counterparty_node_id: PublicKey) -> Vec<ChannelDetails> {
+		self.channel_manager
+			.list_channels_with_counterparty(&counterparty_node_id)
+			.into_iter()
+			.map(|c| c.into())
+			.collect()
+	}
+
+	/// Retrieve a list of known channels that are currently usable to send payments, i.e., for
+	/// which [`ChannelDetails::is_usable`] is set.
+	pub fn list_usable_channels(&self) -> Vec<ChannelDetails> {
+		self.channel_manager.list_usable_channels().into_iter().map(|c| c.into()).collect()
+	}
+
+	/// Retrieves all known channels that match the given predicate.
+	///
+	/// For example, you could retrieve all channels that are ready but not usable, e.g., as the
+	/// counterparty is offline, as follows:
+	/// ```
+	/// # use ldk_node::{Builder, Config};
+	/// # use ldk_node::bitcoin::Network;
+	/// # let mut config = Config::default();
+	/// # config.network = Network::Regtest;
+	/// # config.storage_dir_path = "/tmp/ldk_node_test/".to_string();
+	/// # let builder = Builder::from_config(config);
+	/// # let node = builder.build().unwrap();
+	/// node.list_channels_with_filter(|c| c.is_channel_ready && !c.is_usable);
+	/// ```
+	pub fn list_channels_with_filter<F: FnMut(&ChannelDetails) -> bool>(
+		&self, mut f: F,
+	) -> Vec<ChannelDetails> {
+		self.channel_manager
+			.list_channels()
+			.into_iter()
+			.map(|c| c.into())
+			.filter(|c: &ChannelDetails| f(c))
+			.collect()
+	}
+
+	/// Returns the total amount we can currently send over all usable channels, in
+	/// thousandths of a satoshi.
+	///
+	/// This is the sum of [`ChannelDetails::outbound_capacity_msat`] over all channels returned by
+	/// [`Node::list_usable_channels`]. Note that the amount sendable in a single payment might be
+	/// lower as further restrictions apply, see
+	/// [`ChannelDetails::next_outbound_htlc_limit_msat`].
+	pub fn usable_balance_msat(&self) -> u64 {
+		self.channel_manager.list_usable_channels().iter().map(|c| c.outbound_capacity_msat).sum()
+	}
+
+	/// Connect to a node on the peer-to-peer network.
+	///
+	/// If `persist` is set to `true`, we'll remember the peer and reconnect to it on restart.
+	pub fn connect(
+		&self, node_id: PublicKey, address: SocketAddress, persist: bool,
+	) -> Result<(), Error> {
+		self.connect_with_transport(node_id, address, PeerTransport::Tcp, persist)
+	}
+
+	/// Connect to a node on the peer-to-peer network using the given [`PeerTransport`].
+	///
+	/// If `persist` is set to `true`, we'll remember the peer and reconnect to it on restart using
+	/// the same transport.
+	///
+	/// Connecting via [`PeerTransport::WebSocket`] requires the `websocket` feature to be enabled
+	/// and will fail with [`Error::ConnectionFailed`] otherwise.
+	pub fn connect_with_transport(
+		&self, node_id: PublicKey, address: SocketAddress, transport: PeerTransport, persist: bool,
+	) -> Result<(), Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+		let runtime = rt_lock.as_ref().unwrap();
+
+		let peer_info = PeerInfo { node_id, address, transport, is_persistent: false };
+
+		let con_node_id = peer_info.node_id;
 		let con_addr = peer_info.address.clone();
 		let con_logger = Arc::clone(&self.logger);
 		let con_pm = Arc::clone(&self.peer_manager);
+		let con_tor_proxy = self.config.tor_proxy_address;
+		let con_peer_monitor = Arc::clone(&self.peer_monitor);
 
 		// We need to use our main runtime here as a local runtime might not be around to poll
 		// connection futures going forward.
 		tokio::task::block_in_place(move || {
 			runtime.block_on(async move {
-				connect_peer_if_necessary(con_node_id, con_addr, con_pm, con_logger).await
+				connect_peer_if_necessary(
+					con_node_id,
+					con_addr,
+					transport,
+					con_tor_proxy,
+					con_pm,
+					con_peer_monitor,
+					con_logger,
+				)
+				.await
 			})
 		})?;
 
@@ -894,6 +1798,54 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		Ok(())
 	}
 
+	/// Adds a persistent peer and connects to it.
+	///
+	/// Persistent peers are remembered across restarts and will be reconnected to after any
+	/// disconnect, backing off exponentially between failed attempts. The backoff schedule can be
+	/// configured via [`Config::persistent_peer_reconnection_initial_delay_secs`] and
+	/// [`Config::persistent_peer_reconnection_max_delay_secs`].
+	///
+	/// If the initial connection attempt fails, the peer is still added and we'll keep trying to
+	/// connect in the background.
+	pub fn add_persistent_peer(
+		&self, node_id: PublicKey, address: SocketAddress,
+	) -> Result<(), Error> {
+		if self.runtime.read().unwrap().is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let transport = self.peer_store.get_peer(&node_id).map(|p| p.transport).unwrap_or_default();
+		let peer_info =
+			PeerInfo { node_id, address: address.clone(), transport, is_persistent: true };
+		self.peer_store.add_or_update_peer(peer_info)?;
+		log_info!(self.logger, "Added persistent peer {}", node_id);
+
+		if let Err(e) = self.connect_with_transport(node_id, address, transport, false) {
+			log_error!(
+				self.logger,
+				"Failed to connect to persistent peer {}, will retry in the background: {}",
+				node_id,
+				e
+			);
+		}
+		Ok(())
+	}
+
+	/// Removes a peer previously added via [`Node::add_persistent_peer`].
+	///
+	/// The peer will be removed from the peer store, i.e., we won't try to reconnect to it after
+	/// it disconnects or on restart. Any existing connection to the peer is kept open.
+	pub fn remove_persistent_peer(&self, node_id: PublicKey) -> Result<(), Error> {
+		match self.peer_store.get_peer(&node_id) {
+			Some(peer_info) if peer_info.is_persistent => {
+				self.peer_store.remove_peer(&node_id)?;
+				log_info!(self.logger, "Removed persistent peer {}", node_id);
+			},
+			_ => {},
+		}
+		Ok(())
+	}
+
 	/// Disconnects the peer with the given node id.
 	///
 	/// Will also remove the peer from the peer store, i.e., after this has been called we won't
@@ -913,23 +1865,151 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 			},
 		}
 
+		self.peer_monitor.record_local_disconnect(counterparty_node_id);
 		self.peer_manager.disconnect_by_node_id(counterparty_node_id);
 		Ok(())
 	}
 
+	/// Returns the connection status of the peer with the given node ID.
+	///
+	/// If the peer is currently disconnected, the reason the last connection to it was closed is
+	/// reported, if known.
+	pub fn peer_connection_status(&self, node_id: PublicKey) -> PeerConnectionStatus {
+		let connected_peer =
+			self.peer_manager.get_peer_node_ids().into_iter().find(|(id, _)| *id == node_id);
+
+		match connected_peer {
+			Some((_, address)) => PeerConnectionStatus::Connected { address },
+			None => PeerConnectionStatus::Disconnected {
+				reason: self.peer_monitor.last_disconnect_reason(&node_id),
+			},
+		}
+	}
+
 	/// Connect to a node and open a new channel. Disconnects and re-connects are handled automatically
 	///
 	/// Disconnects and reconnects are handled automatically.
 	///
 	/// If `push_to_counterparty_msat` is set, the given value will be pushed (read: sent) to the
 	/// channel counterparty on channel open. This can be useful to start out with the balance not
-	/// entirely shifted to one side, therefore allowing to receive payments from the getgo.
+	/// entirely shifted to one side, therefore allowing to receive payments from the getgo. The
+	/// pushed amount needs to leave us with at least the channel reserve the counterparty will
+	/// likely require us to keep, i.e., it must be less than the channel value minus 1% of the
+	/// channel value (or minus 1000 satoshis for channels smaller than 100000 satoshis), and
+	/// will otherwise be rejected with [`Error::InvalidAmount`]. Once the channel is open, the
+	/// pushed amount will be reflected in the balances returned by [`Node::list_channels`].
+	///
+	/// Will fail with [`Error::InvalidChannelConfig`] if the given `channel_config` sets a zero
+	/// [`MaxDustHTLCExposure`].
 	///
 	/// Returns a [`UserChannelId`] allowing to locally keep track of the channel.
 	pub fn connect_open_channel(
 		&self, node_id: PublicKey, address: SocketAddress, channel_amount_sats: u64,
 		push_to_counterparty_msat: Option<u64>, channel_config: Option<Arc<ChannelConfig>>,
 		announce_channel: bool,
+	) -> Result<UserChannelId, Error> {
+		self.connect_open_channel_inner(
+			node_id,
+			address,
+			channel_amount_sats,
+			None,
+			ChannelFundingParameters::default(),
+			ChannelHandshakeParameters::default(),
+			push_to_counterparty_msat,
+			channel_config,
+			announce_channel,
+		)
+	}
+
+	/// Connect to a node and open a new channel, funded by a transaction built with the given
+	/// [`ChannelFundingParameters`].
+	///
+	/// This allows to set an explicit fee rate for the funding transaction, overriding our fee
+	/// estimation, e.g., during mempool congestion. Will fail with [`Error::InvalidFeeRate`] if
+	/// the given fee rate is below the minimum relay fee rate.
+	///
+	/// The funding transaction can be tracked via the `funding_txo` reported by
+	/// [`Event::ChannelPending`] and [`Self::list_channels`].
+	///
+	/// See [`Self::connect_open_channel`] for more information.
+	pub fn connect_open_channel_with_funding_params(
+		&self, node_id: PublicKey, address: SocketAddress, channel_amount_sats: u64,
+		funding_params: ChannelFundingParameters, push_to_counterparty_msat: Option<u64>,
+		channel_config: Option<Arc<ChannelConfig>>, announce_channel: bool,
+	) -> Result<UserChannelId, Error> {
+		self.connect_open_channel_inner(
+			node_id,
+			address,
+			channel_amount_sats,
+			None,
+			funding_params,
+			ChannelHandshakeParameters::default(),
+			push_to_counterparty_msat,
+			channel_config,
+			announce_channel,
+		)
+	}
+
+	/// Connect to a node and open a new channel, requiring the counterparty to adhere to the
+	/// given [`ChannelHandshakeParameters`].
+	///
+	/// This allows to set the channel reserve we require the counterparty to keep and to limit
+	/// the value and number of inbound HTLCs pending on the channel, overriding LDK's defaults.
+	/// Will fail with [`Error::InvalidChannelConfig`] if any of the given parameters is outside
+	/// of the documented bounds.
+	///
+	/// See [`Self::connect_open_channel`] for more information.
+	pub fn connect_open_channel_with_handshake_params(
+		&self, node_id: PublicKey, address: SocketAddress, channel_amount_sats: u64,
+		handshake_params: ChannelHandshakeParameters, push_to_counterparty_msat: Option<u64>,
+		channel_config: Option<Arc<ChannelConfig>>, announce_channel: bool,
+	) -> Result<UserChannelId, Error> {
+		self.connect_open_channel_inner(
+			node_id,
+			address,
+			channel_amount_sats,
+			None,
+			ChannelFundingParameters::default(),
+			handshake_params,
+			push_to_counterparty_msat,
+			channel_config,
+			announce_channel,
+		)
+	}
+
+	/// Connect to a node and open a new channel funded exclusively by the given UTXOs.
+	///
+	/// Any funds of the given UTXOs not needed for the channel amount and the fees are returned to
+	/// a change address of the on-chain wallet.
+	///
+	/// Will fail with [`Error::FundingUtxoUnavailable`] if any of the given UTXOs is unknown to the
+	/// wallet, already spent, or reserved for another pending channel, and with
+	/// [`Error::InsufficientFunds`] if they don't cover the channel amount plus the estimated fees.
+	///
+	/// See [`Self::connect_open_channel`] for more information.
+	pub fn connect_open_channel_with_funding_utxos(
+		&self, node_id: PublicKey, address: SocketAddress, channel_amount_sats: u64,
+		funding_utxos: Vec<OutPoint>, push_to_counterparty_msat: Option<u64>,
+		channel_config: Option<Arc<ChannelConfig>>, announce_channel: bool,
+	) -> Result<UserChannelId, Error> {
+		self.connect_open_channel_inner(
+			node_id,
+			address,
+			channel_amount_sats,
+			Some(funding_utxos),
+			ChannelFundingParameters::default(),
+			ChannelHandshakeParameters::default(),
+			push_to_counterparty_msat,
+			channel_config,
+			announce_channel,
+		)
+	}
+
+	fn connect_open_channel_inner(
+		&self, node_id: PublicKey, address: SocketAddress, channel_amount_sats: u64,
+		funding_utxos: Option<Vec<OutPoint>>, funding_params: ChannelFundingParameters,
+		handshake_params: ChannelHandshakeParameters, push_to_counterparty_msat: Option<u64>,
+		channel_config: Option<Arc<ChannelConfig>>, announce_channel: bool,
 	) -> Result<UserChannelId, Error> {
 		let rt_lock = self.runtime.read().unwrap();
 		if rt_lock.is_none() {
@@ -937,34 +2017,148 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		}
 		let runtime = rt_lock.as_ref().unwrap();
 
-		let cur_balance = self.wallet.get_balance()?;
-		if cur_balance.get_spendable() < channel_amount_sats {
+		if let Some(push_msat) = push_to_counterparty_msat {
+			// The exact channel reserve is selected by the counterparty during channel
+			// negotiation, so we check against the commonly used 1% of the channel value here.
+			let reserve_sats = (channel_amount_sats / 100).max(MIN_CHANNEL_RESERVE_SATS);
+			let max_push_msat = channel_amount_sats.saturating_sub(reserve_sats) * 1000;
+			if push_msat >= max_push_msat {
+				log_error!(
+					self.logger,
+					"Unable to create channel as the pushed amount of {}msat exceeds the channel value of {}sats minus the channel reserve of {}sats.",
+					push_msat,
+					channel_amount_sats,
+					reserve_sats
+				);
+				return Err(Error::InvalidAmount);
+			}
+		}
+
+		let mut channel_handshake_config = ChannelHandshakeConfig {
+			announced_channel: announce_channel,
+			negotiate_anchors_zero_fee_htlc_tx: self.config.anchor_channels_config.is_some(),
+			..Default::default()
+		};
+
+		if let Some(reserve_sats) = handshake_params.their_channel_reserve_sats {
+			// LDK won't select a reserve below `MIN_CHANNEL_RESERVE_SATS`, which also ensures the
+			// reserve output isn't dust.
+			if !(MIN_CHANNEL_RESERVE_SATS..channel_amount_sats).contains(&reserve_sats) {
+				log_error!(
+					self.logger,
+					"Unable to create channel: channel reserve of {}sats is invalid for a channel value of {}sats.",
+					reserve_sats,
+					channel_amount_sats
+				);
+				return Err(Error::InvalidChannelConfig);
+			}
+			let proportional_millionths =
+				(reserve_sats * 1_000_000 + channel_amount_sats - 1) / channel_amount_sats;
+			channel_handshake_config.their_channel_reserve_proportional_millionths =
+				proportional_millionths as u32;
+		}
+
+		if let Some(max_in_flight_msat) = handshake_params.max_htlc_value_in_flight_msat {
+			let percent = max_in_flight_msat / (channel_amount_sats * 10).max(1);
+			if percent < 1 || max_in_flight_msat > channel_amount_sats * 1000 {
+				log_error!(
+					self.logger,
+					"Unable to create channel: max HTLC value in flight of {}msat is invalid for a channel value of {}sats.",
+					max_in_flight_msat,
+					channel_amount_sats
+				);
+				return Err(Error::InvalidChannelConfig);
+			}
+			channel_handshake_config.max_inbound_htlc_value_in_flight_percent_of_channel =
+				percent as u8;
+		}
+
+		if let Some(max_accepted_htlcs) = handshake_params.max_accepted_htlcs {
+			if !(1..=MAX_ACCEPTED_HTLCS).contains(&max_accepted_htlcs) {
+				log_error!(
+					self.logger,
+					"Unable to create channel: max accepted HTLCs of {} is outside of the allowed range of 1 to {}.",
+					max_accepted_htlcs,
+					MAX_ACCEPTED_HTLCS
+				);
+				return Err(Error::InvalidChannelConfig);
+			}
+			channel_handshake_config.our_max_accepted_htlcs = max_accepted_htlcs;
+		}
+
+		let cur_spendable_sats = self.wallet.get_balance()?.get_spendable();
+		if funding_utxos.is_none() && cur_spendable_sats < channel_amount_sats {
 			log_error!(self.logger, "Unable to create channel due to insufficient funds.");
 			return Err(Error::InsufficientFunds);
 		}
 
-		let peer_info = PeerInfo { node_id, address };
+		if let Some(anchor_channels_config) = self.config.anchor_channels_config.as_ref() {
+			if !anchor_channels_config.trusted_peers_no_reserve.contains(&node_id) {
+				// We don't know yet whether the channel will be negotiated as an Anchor channel,
+				// so we make sure to be able to cover the reserve in any case.
+				let required_reserve_sats =
+					total_anchor_channels_reserve_sats(&self.channel_manager, &self.config)
+						+ anchor_channels_config.per_channel_reserve_sats;
+				let required_funds_sats = required_reserve_sats
+					+ if funding_utxos.is_none() { channel_amount_sats } else { 0 };
+				if cur_spendable_sats < required_funds_sats {
+					log_error!(self.logger,
+						"Unable to create channel due to insufficient funds. Available: {}sats, Required: {}sats (including an Anchor channel reserve of {}sats)",
+						cur_spendable_sats, required_funds_sats, required_reserve_sats,
+					);
+					return Err(Error::InsufficientFunds);
+				}
+			}
+		}
+
+		// Reuse the transport of a previously persisted connection to the peer, if any.
+		let transport = self.peer_store.get_peer(&node_id).map(|p| p.transport).unwrap_or_default();
+		let peer_info = PeerInfo { node_id, address, transport, is_persistent: false };
 
 		let con_node_id = peer_info.node_id;
 		let con_addr = peer_info.address.clone();
 		let con_logger = Arc::clone(&self.logger);
 		let con_pm = Arc::clone(&self.peer_manager);
+		let con_tor_proxy = self.config.tor_proxy_address;
+		let con_peer_monitor = Arc::clone(&self.peer_monitor);
 
 		// We need to use our main runtime here as a local runtime might not be around to poll
 		// connection futures going forward.
 		tokio::task::block_in_place(move || {
 			runtime.block_on(async move {
-				connect_peer_if_necessary(con_node_id, con_addr, con_pm, con_logger).await
+				connect_peer_if_necessary(
+					con_node_id,
+					con_addr,
+					transport,
+					con_tor_proxy,
+					con_pm,
+					con_peer_monitor,
+					con_logger,
+				)
+				.await
 			})
 		})?;
 
-		let channel_config = (*(channel_config.unwrap_or_default())).clone().into();
+		let channel_config = channel_config.unwrap_or_default();
+		let max_dust_htlc_exposure = channel_config.max_dust_htlc_exposure();
+		if !max_dust_htlc_exposure.is_valid() {
+			log_error!(
+				self.logger,
+				"Unable to create channel: invalid max dust HTLC exposure {:?}",
+				max_dust_htlc_exposure
+			);
+			return Err(Error::InvalidChannelConfig);
+		}
+		if let Some(min_confs) = self.config.channel_confirmation_target {
+			channel_handshake_config.minimum_depth = min_confs;
+		}
+		let channel_config = (*channel_config).clone().into();
 		let user_config = UserConfig {
-			channel_handshake_limits: Default::default(),
-			channel_handshake_config: ChannelHandshakeConfig {
-				announced_channel: announce_channel,
+			channel_handshake_limits: ChannelHandshakeLimits {
+				trust_own_funding_0conf: self.config.channel_confirmation_target.is_none(),
 				..Default::default()
 			},
+			channel_handshake_config,
 			channel_config,
 			..Default::default()
 		};
@@ -972,6 +2166,14 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		let push_msat = push_to_counterparty_msat.unwrap_or(0);
 		let user_channel_id: u128 = rand::thread_rng().gen::<u128>();
 
+		self.wallet.register_channel_funding(
+			user_channel_id,
+			funding_utxos,
+			funding_params,
+			channel_amount_sats,
+			ConfirmationTarget::NonAnchorChannelFee,
+		)?;
+
 		match self.channel_manager.create_channel(
 			peer_info.node_id,
 			channel_amount_sats,
@@ -991,11 +2193,34 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 			},
 			Err(e) => {
 				log_error!(self.logger, "Failed to initiate channel creation: {:?}", e);
+				self.wallet.release_channel_funding(user_channel_id);
 				Err(Error::ChannelCreationFailed)
 			},
 		}
 	}
 
+	/// Overrides the fee rate used for the given confirmation target with the given rate, in
+	/// satoshis per 1000 weight units.
+	///
+	/// The override takes precedence over any estimates and configured floors until it's removed
+	/// via [`Node::clear_fee_rate_override`] or the node is restarted. Note that we never use fee
+	/// rates below the minimum relay fee rate, independently of the override.
+	pub fn override_fee_rate(&self, confirmation_target: ConfirmationTarget, sat_per_kwu: u32) {
+		self.fee_estimator.set_fee_rate_override(confirmation_target, Some(sat_per_kwu));
+		log_info!(
+			self.logger,
+			"Overrode fee rate for {:?} with {} sats/kwu",
+			confirmation_target,
+			sat_per_kwu
+		);
+	}
+
+	/// Removes a fee rate override previously set via [`Node::override_fee_rate`].
+	pub fn clear_fee_rate_override(&self, confirmation_target: ConfirmationTarget) {
+		self.fee_estimator.set_fee_rate_override(confirmation_target, None);
+		log_info!(self.logger, "Cleared fee rate override for {:?}", confirmation_target);
+	}
+
 	/// Manually sync the LDK and BDK wallets with the current chain state.
 	///
 	/// **Note:** The wallets are regularly synced in the background, which is configurable via
@@ -1007,22 +2232,23 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		if rt_lock.is_none() {
 			return Err(Error::NotRunning);
 		}
+		if self.sync_paused.load(Ordering::Acquire) {
+			return Err(Error::SyncPaused);
+		}
 
 		let wallet = Arc::clone(&self.wallet);
-		let tx_sync = Arc::clone(&self.tx_sync);
+		let onchain_monitor = Arc::clone(&self.onchain_monitor);
+		let chain_source = Arc::clone(&self.chain_source);
 		let sync_cman = Arc::clone(&self.channel_manager);
 		let sync_cmon = Arc::clone(&self.chain_monitor);
 		let sync_sweeper = Arc::clone(&self.output_sweeper);
 		let sync_logger = Arc::clone(&self.logger);
-		let confirmables = vec![
-			&*sync_cman as &(dyn Confirm + Sync + Send),
-			&*sync_cmon as &(dyn Confirm + Sync + Send),
-			&*sync_sweeper as &(dyn Confirm + Sync + Send),
-		];
+		let sync_lock = Arc::clone(&self.sync_lock);
 
 		tokio::task::block_in_place(move || {
 			tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap().block_on(
 				async move {
+					let _sync_guard = sync_lock.lock().await;
 					let now = Instant::now();
 					match wallet.sync().await {
 						Ok(()) => {
@@ -1031,6 +2257,13 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 								"Sync of on-chain wallet finished in {}ms.",
 								now.elapsed().as_millis()
 							);
+							if let Err(e) = onchain_monitor.update(&wallet, &sync_cman) {
+								log_error!(
+									sync_logger,
+									"Failed to update on-chain payments: {}",
+									e
+								);
+							}
 						},
 						Err(e) => {
 							log_error!(sync_logger, "Sync of on-chain wallet failed: {}", e);
@@ -1039,7 +2272,10 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 					};
 
 					let now = Instant::now();
-					match tx_sync.sync(confirmables).await {
+					match chain_source
+						.sync_lightning_wallet(sync_cman, sync_cmon, sync_sweeper)
+						.await
+					{
 						Ok(()) => {
 							log_info!(
 								sync_logger,
@@ -1050,7 +2286,7 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 						},
 						Err(e) => {
 							log_error!(sync_logger, "Sync of Lightning wallet failed: {}", e);
-							Err(e.into())
+							Err(e)
 						},
 					}
 				},
@@ -1058,19 +2294,166 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		})
 	}
 
+	/// Immediately syncs the on-chain wallet, the Lightning wallet, i.e., the channel monitors,
+	/// and the fee rate cache, and blocks until all of them are done.
+	///
+	/// In contrast to [`Node::sync_wallets`], this also refreshes our fee rate estimates and
+	/// reports the chain tip we synced to. If another call to this method is already in
+	/// progress, we wait for it to complete and report its outcome rather than syncing twice.
+	///
+	/// Will return [`Error::SyncPaused`] if background syncing was paused via
+	/// [`Node::pause_background_sync`].
+	pub fn sync_now(&self) -> Result<SyncReport, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		let runtime = rt_lock.as_ref().ok_or(Error::NotRunning)?;
+		if self.sync_paused.load(Ordering::Acquire) {
+			return Err(Error::SyncPaused);
+		}
+
+		let start_height = self.channel_manager.current_best_block().height();
+		tokio::task::block_in_place(|| {
+			runtime.block_on(async {
+				let _sync_guard = match self.sync_now_lock.try_lock() {
+					Ok(guard) => guard,
+					Err(_) => {
+						// Another sync is in progress, coalesce with it.
+						log_info!(self.logger, "Sync in progress, waiting for it to complete.");
+						let _ = self.sync_now_lock.lock().await;
+						let tip_height = self.channel_manager.current_best_block().height();
+						let blocks_processed = tip_height.saturating_sub(start_height);
+						return Ok(SyncReport { blocks_processed, tip_height });
+					},
+				};
+
+				// Wait for any background sync to finish, rather than syncing concurrently.
+				let _sync_lock_guard = self.sync_lock.lock().await;
+
+				let now = Instant::now();
+				self.fee_estimator.update_fee_estimates().await.map_err(|e| {
+					log_error!(self.logger, "Update of fee rate cache failed: {}", e);
+					e
+				})?;
+				let unix_time_secs_opt =
+					SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+				*self.latest_fee_rate_cache_update_timestamp.write().unwrap() = unix_time_secs_opt;
+				persist_fee_rate_cache(
+					&self.fee_estimator,
+					unix_time_secs_opt,
+					Arc::clone(&self.kv_store),
+					&self.logger,
+				);
+
+				self.wallet.sync().await.map_err(|e| {
+					log_error!(self.logger, "Sync of on-chain wallet failed: {}", e);
+					e
+				})?;
+				let unix_time_secs_opt =
+					SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+				*self.latest_onchain_wallet_sync_timestamp.write().unwrap() = unix_time_secs_opt;
+				if let Err(e) = self.onchain_monitor.update(&self.wallet, &self.channel_manager) {
+					log_error!(self.logger, "Failed to update on-chain payments: {}", e);
+				}
+
+				self.chain_source
+					.sync_lightning_wallet(
+						Arc::clone(&self.channel_manager),
+						Arc::clone(&self.chain_monitor),
+						Arc::clone(&self.output_sweeper),
+					)
+					.await
+					.map_err(|e| {
+						log_error!(self.logger, "Sync of Lightning wallet failed: {}", e);
+						e
+					})?;
+				let unix_time_secs_opt =
+					SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+				*self.latest_wallet_sync_timestamp.write().unwrap() = unix_time_secs_opt;
+
+				let tip_height = self.channel_manager.current_best_block().height();
+				let blocks_processed = tip_height.saturating_sub(start_height);
+				log_info!(
+					self.logger,
+					"Sync finished in {}ms, processed {} blocks up to height {}.",
+					now.elapsed().as_millis(),
+					blocks_processed,
+					tip_height
+				);
+				Ok(SyncReport { blocks_processed, tip_height })
+			})
+		})
+	}
+
 	/// Close a previously opened channel.
 	pub fn close_channel(
 		&self, user_channel_id: &UserChannelId, counterparty_node_id: PublicKey,
 	) -> Result<(), Error> {
+		self.close_channel_with_params(user_channel_id, counterparty_node_id, None, None)
+	}
+
+	/// Close a previously opened channel, proposing the given closing fee rate and paying our
+	/// balance to the given destination address.
+	///
+	/// If `target_sat_per_vbyte` is unset, the closing fee rate will be determined by our fee
+	/// estimator. If `destination_address` is unset, our balance will be paid to our on-chain
+	/// wallet. Note that the destination address needs to be valid for the configured network and
+	/// might be rejected by the counterparty if it uses a script type it doesn't support.
+	///
+	/// The closing transaction is negotiated with the counterparty asynchronously. If the
+	/// negotiation fails, e.g., as the counterparty rejects our proposed fee rate, the channel will
+	/// be force-closed, which is reported via the `reason` of the respective
+	/// [`Event::ChannelClosed`].
+	pub fn close_channel_with_params(
+		&self, user_channel_id: &UserChannelId, counterparty_node_id: PublicKey,
+		target_sat_per_vbyte: Option<u64>, destination_address: Option<Address>,
+	) -> Result<(), Error> {
+		let target_feerate_sats_per_1000_weight = match target_sat_per_vbyte {
+			Some(sat_per_vbyte) => {
+				Some(wallet::sat_per_vbyte_to_sat_per_kw(sat_per_vbyte).ok_or_else(|| {
+					log_error!(
+						self.logger,
+						"The given closing fee rate of {}sat/vB is below the minimum relay fee rate",
+						sat_per_vbyte
+					);
+					Error::InvalidFeeRate
+				})?)
+			},
+			None => None,
+		};
+
+		let shutdown_script = match destination_address {
+			Some(address) => {
+				if !address.is_valid_for_network(self.config.network) {
+					log_error!(
+						self.logger,
+						"The given closing destination address {} is not valid for network {}",
+						address,
+						self.config.network
+					);
+					return Err(Error::InvalidAddress);
+				}
+				Some(ShutdownScript::try_from(address.script_pubkey()).map_err(|_| {
+					log_error!(
+						self.logger,
+						"The given closing destination address {} is not supported as shutdown script",
+						address
+					);
+					Error::InvalidAddress
+				})?)
+			},
+			None => None,
+		};
+
 		let open_channels =
 			self.channel_manager.list_channels_with_counterparty(&counterparty_node_id);
 		if let Some(channel_details) =
 			open_channels.iter().find(|c| c.user_channel_id == user_channel_id.0)
 		{
-			match self
-				.channel_manager
-				.close_channel(&channel_details.channel_id, &counterparty_node_id)
-			{
+			match self.channel_manager.close_channel_with_feerate_and_script(
+				&channel_details.channel_id,
+				&counterparty_node_id,
+				target_feerate_sats_per_1000_weight,
+				shutdown_script,
+			) {
 				Ok(_) => {
 					// Check if this was the last open channel, if so, forget the peer.
 					if open_channels.len() == 1 {
@@ -1078,6 +2461,14 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 					}
 					Ok(())
 				},
+				Err(APIError::IncompatibleShutdownScript { script }) => {
+					log_error!(
+						self.logger,
+						"Failed to close channel as the counterparty doesn't support shutdown script {}",
+						script
+					);
+					Err(Error::InvalidAddress)
+				},
 				Err(_) => Err(Error::ChannelClosingFailed),
 			}
 		} else {
@@ -1085,40 +2476,263 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		}
 	}
 
-	/// Update the config for a previously opened channel.
-	pub fn update_channel_config(
+	/// Force-close a previously opened channel by broadcasting our latest commitment transaction.
+	///
+	/// If given, the `reason` will be recorded in the `force_close_reason` field of the
+	/// respective [`Event::ChannelClosed`].
+	///
+	/// For Anchor channels, the fee of the broadcasted commitment transaction will be bumped by
+	/// spending its anchor output with funds from our on-chain wallet (CPFP) until it confirms,
+	/// unless the counterparty is listed in [`AnchorChannelsConfig::trusted_peers_no_reserve`].
+	/// Please refer to [`AnchorChannelsConfig`] for more information on the on-chain reserve we
+	/// maintain to this end.
+	///
+	/// **Note:** Force-closing a channel locks up our funds until the respective timelocks
+	/// expire and should therefore only be used if the counterparty is unresponsive or
+	/// misbehaving. Prefer [`Node::close_channel`] otherwise.
+	pub fn force_close_channel(
 		&self, user_channel_id: &UserChannelId, counterparty_node_id: PublicKey,
-		channel_config: Arc<ChannelConfig>,
+		reason: Option<String>,
 	) -> Result<(), Error> {
 		let open_channels =
 			self.channel_manager.list_channels_with_counterparty(&counterparty_node_id);
 		if let Some(channel_details) =
 			open_channels.iter().find(|c| c.user_channel_id == user_channel_id.0)
 		{
-			self.channel_manager
-				.update_channel_config(
-					&counterparty_node_id,
-					&[channel_details.channel_id],
-					&(*channel_config).clone().into(),
-				)
-				.map_err(|_| Error::ChannelConfigUpdateFailed)
-		} else {
-			Err(Error::ChannelConfigUpdateFailed)
-		}
-	}
-
+			if let Some(reason) = reason.as_ref() {
+				log_info!(
+					self.logger,
+					"Force-closing channel {} with peer {}: {}",
+					channel_details.channel_id,
+					counterparty_node_id,
+					reason
+				);
+				// The `ChannelClosed` event is generated during the force-closure, so we need to
+				// record the reason beforehand.
+				self.force_close_reasons.lock().unwrap().insert(user_channel_id.0, reason.clone());
+			}
+
+			match self.channel_manager.force_close_broadcasting_latest_txn(
+				&channel_details.channel_id,
+				&counterparty_node_id,
+			) {
+				Ok(()) => {
+					// Check if this was the last open channel, if so, forget the peer.
+					if open_channels.len() == 1 {
+						self.peer_store.remove_peer(&counterparty_node_id)?;
+					}
+					Ok(())
+				},
+				Err(e) => {
+					log_error!(self.logger, "Failed to force-close channel: {:?}", e);
+					self.force_close_reasons.lock().unwrap().remove(&user_channel_id.0);
+					Err(Error::ChannelClosingFailed)
+				},
+			}
+		} else {
+			Ok(())
+		}
+	}
+
+	/// Update the config for a previously opened channel.
+	///
+	/// The given config is validated as described in [`Node::update_partial_channel_config`].
+	pub fn update_channel_config(
+		&self, user_channel_id: &UserChannelId, counterparty_node_id: PublicKey,
+		channel_config: Arc<ChannelConfig>,
+	) -> Result<(), Error> {
+		let open_channels =
+			self.channel_manager.list_channels_with_counterparty(&counterparty_node_id);
+		if let Some(channel_details) =
+			open_channels.iter().find(|c| c.user_channel_id == user_channel_id.0)
+		{
+			let update = ChannelConfigUpdate {
+				forwarding_fee_proportional_millionths: Some(
+					channel_config.forwarding_fee_proportional_millionths(),
+				),
+				forwarding_fee_base_msat: Some(channel_config.forwarding_fee_base_msat()),
+				cltv_expiry_delta: Some(channel_config.cltv_expiry_delta()),
+				max_dust_htlc_exposure: Some(channel_config.max_dust_htlc_exposure()),
+			};
+			self.validate_channel_config_update(channel_details, &update)?;
+
+			self.channel_manager
+				.update_channel_config(
+					&counterparty_node_id,
+					&[channel_details.channel_id],
+					&(*channel_config).clone().into(),
+				)
+				.map_err(|_| Error::ChannelConfigUpdateFailed)
+		} else {
+			Err(Error::ChannelConfigUpdateFailed)
+		}
+	}
+
+	/// Update the forwarding fee policy or the dust exposure limit of a previously opened channel.
+	///
+	/// Only the fields set in the given [`ChannelConfigUpdate`] will be changed. For public
+	/// channels, the updated policy will be broadcast to the network via a `channel_update`
+	/// gossip message.
+	///
+	/// Will fail with [`Error::InvalidChannelConfig`] if the resulting `cltv_expiry_delta` is below
+	/// the minimum supported by LDK, if the given [`MaxDustHTLCExposure`] is zero, or, if
+	/// [`Config::strict_forwarding_fee_policy`] is set, if both the resulting base and
+	/// proportional forwarding fees are zero.
+	pub fn update_partial_channel_config(
+		&self, user_channel_id: &UserChannelId, counterparty_node_id: PublicKey,
+		update: ChannelConfigUpdate,
+	) -> Result<(), Error> {
+		let open_channels =
+			self.channel_manager.list_channels_with_counterparty(&counterparty_node_id);
+		if let Some(channel_details) =
+			open_channels.iter().find(|c| c.user_channel_id == user_channel_id.0)
+		{
+			self.validate_channel_config_update(channel_details, &update)?;
+
+			self.channel_manager
+				.update_partial_channel_config(
+					&counterparty_node_id,
+					&[channel_details.channel_id],
+					&update.into(),
+				)
+				.map_err(|_| Error::ChannelConfigUpdateFailed)
+		} else {
+			Err(Error::ChannelConfigUpdateFailed)
+		}
+	}
+
+	/// Update the forwarding fee policy or the dust exposure limit of all open channels.
+	///
+	/// The update is validated against all channels before it is applied, i.e., if it is
+	/// invalid for any of the channels, none of them will be updated. Please refer to
+	/// [`Node::update_partial_channel_config`] for more information.
+	pub fn update_all_channels_config(&self, update: ChannelConfigUpdate) -> Result<(), Error> {
+		let mut channel_ids_by_counterparty: HashMap<PublicKey, Vec<ChannelId>> = HashMap::new();
+		for channel_details in self.channel_manager.list_channels() {
+			self.validate_channel_config_update(&channel_details, &update)?;
+			channel_ids_by_counterparty
+				.entry(channel_details.counterparty.node_id)
+				.or_default()
+				.push(channel_details.channel_id);
+		}
+
+		let ldk_update = update.into();
+		for (counterparty_node_id, channel_ids) in channel_ids_by_counterparty {
+			self.channel_manager
+				.update_partial_channel_config(&counterparty_node_id, &channel_ids, &ldk_update)
+				.map_err(|e| {
+					log_error!(
+						self.logger,
+						"Failed to update config of channels with peer {}: {:?}",
+						counterparty_node_id,
+						e
+					);
+					Error::ChannelConfigUpdateFailed
+				})?;
+		}
+		Ok(())
+	}
+
+	fn validate_channel_config_update(
+		&self, channel_details: &channelmanager::ChannelDetails, update: &ChannelConfigUpdate,
+	) -> Result<(), Error> {
+		let current_config = channel_details.config.unwrap_or_default();
+
+		let cltv_expiry_delta =
+			update.cltv_expiry_delta.unwrap_or(current_config.cltv_expiry_delta);
+		if cltv_expiry_delta < MIN_CLTV_EXPIRY_DELTA {
+			log_error!(
+				self.logger,
+				"Rejecting channel config update for channel {}: cltv_expiry_delta of {} is below the minimum of {}",
+				channel_details.channel_id,
+				cltv_expiry_delta,
+				MIN_CLTV_EXPIRY_DELTA
+			);
+			return Err(Error::InvalidChannelConfig);
+		}
+
+		if let Some(max_dust_htlc_exposure) = update.max_dust_htlc_exposure {
+			if !max_dust_htlc_exposure.is_valid() {
+				log_error!(
+					self.logger,
+					"Rejecting channel config update for channel {}: invalid max dust HTLC exposure {:?}",
+					channel_details.channel_id,
+					max_dust_htlc_exposure
+				);
+				return Err(Error::InvalidChannelConfig);
+			}
+		}
+
+		if self.config.strict_forwarding_fee_policy {
+			let base_msat =
+				update.forwarding_fee_base_msat.unwrap_or(current_config.forwarding_fee_base_msat);
+			let proportional_millionths = update
+				.forwarding_fee_proportional_millionths
+				.unwrap_or(current_config.forwarding_fee_proportional_millionths);
+			if base_msat == 0 && proportional_millionths == 0 {
+				log_error!(
+					self.logger,
+					"Rejecting channel config update for channel {}: zero forwarding fees are not allowed in strict mode",
+					channel_details.channel_id
+				);
+				return Err(Error::InvalidChannelConfig);
+			}
+		}
+
+		Ok(())
+	}
+
 	/// Send a payment given an invoice.
 	pub fn send_payment(&self, invoice: &Bolt11Invoice) -> Result<PaymentHash, Error> {
+		self.send_payment_inner(invoice, SendingParameters::default())
+	}
+
+	/// Send a payment given an invoice, overriding the default sending parameters.
+	///
+	/// If no route can be found that satisfies the given [`SendingParameters`], e.g., as the only
+	/// available routes would exceed the fee budget, this will fail with [`Error::RouteNotFound`].
+	/// A fee limit given via [`SendingParameters::max_total_routing_fee_msat`] takes precedence
+	/// over [`Config::max_routing_fee_policy`].
+	pub fn send_payment_with_params(
+		&self, invoice: &Bolt11Invoice, sending_params: SendingParameters,
+	) -> Result<PaymentHash, Error> {
+		self.send_payment_inner(invoice, sending_params)
+	}
+
+	fn send_payment_inner(
+		&self, invoice: &Bolt11Invoice, sending_params: SendingParameters,
+	) -> Result<PaymentHash, Error> {
 		let rt_lock = self.runtime.read().unwrap();
 		if rt_lock.is_none() {
 			return Err(Error::NotRunning);
 		}
 
-		let (payment_hash, recipient_onion, route_params) = payment::payment_parameters_from_invoice(&invoice).map_err(|_| {
+		let (payment_hash, recipient_onion, mut route_params) = payment::payment_parameters_from_invoice(&invoice).map_err(|_| {
 			log_error!(self.logger, "Failed to send payment due to the given invoice being \"zero-amount\". Please use send_payment_using_amount instead.");
 			Error::InvalidInvoice
 		})?;
 
+		let is_fee_policy_applied =
+			if let Some(max_total_routing_fee_msat) = sending_params.max_total_routing_fee_msat {
+				route_params.max_total_routing_fee_msat = Some(max_total_routing_fee_msat);
+				false
+			} else {
+				self.apply_max_routing_fee_policy(&mut route_params)
+			};
+		if let Some(max_total_cltv_expiry_delta) = sending_params.max_total_cltv_expiry_delta {
+			route_params.payment_params.max_total_cltv_expiry_delta = max_total_cltv_expiry_delta;
+		}
+		if let Some(max_path_count) = sending_params.max_path_count {
+			route_params.payment_params.max_path_count = max_path_count.max(1);
+		}
+		if let Some(min_path_amount_msat) = sending_params.min_path_amount_msat {
+			let max_paths_for_amount =
+				(route_params.final_value_msat / min_path_amount_msat.max(1)).clamp(1, 255) as u8;
+			route_params.payment_params.max_path_count =
+				route_params.payment_params.max_path_count.min(max_paths_for_amount);
+		}
+		let is_single_path = route_params.payment_params.max_path_count == 1;
+
 		if let Some(payment) = self.payment_store.get(&payment_hash) {
 			if payment.status == PaymentStatus::Pending
 				|| payment.status == PaymentStatus::Succeeded
@@ -1128,15 +2742,29 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 			}
 		}
 
+		if let Some(channel_id) = sending_params.first_hop_channel_id {
+			let allow_fallback = sending_params.allow_first_hop_fallback.unwrap_or(false);
+			self.pin_first_hop(
+				payment_hash,
+				channel_id,
+				route_params.final_value_msat,
+				allow_fallback,
+			)?;
+		}
+
 		let payment_secret = Some(*invoice.payment_secret());
 		let payment_id = PaymentId(invoice.payment_hash().to_byte_array());
-		let retry_strategy = Retry::Timeout(LDK_PAYMENT_RETRY_TIMEOUT);
+		let retry_timeout = sending_params
+			.retry_timeout_secs
+			.map(Duration::from_secs)
+			.unwrap_or(LDK_PAYMENT_RETRY_TIMEOUT);
+		let retry_strategy = Retry::Timeout(retry_timeout);
 
 		match self.channel_manager.send_payment(
 			payment_hash,
-			recipient_onion,
+			recipient_onion.clone(),
 			payment_id,
-			route_params,
+			route_params.clone(),
 			retry_strategy,
 		) {
 			Ok(()) => {
@@ -1147,13 +2775,26 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				let payment = PaymentDetails {
 					preimage: None,
 					hash: payment_hash,
+					id: Some(payment_id),
 					secret: payment_secret,
 					amount_msat: invoice.amount_milli_satoshis(),
 					direction: PaymentDirection::Outbound,
 					status: PaymentStatus::Pending,
 					lsp_fee_limits: None,
+					kind: Some(PaymentKind::Bolt11),
+					description_hash: None,
+					expected_amount_msat: None,
+					invoice_expiry_timestamp: None,
+					custom_tlvs: Vec::new(),
 				};
 				self.payment_store.insert(payment)?;
+				self.payment_retrier.register(
+					payment_hash,
+					payment_id,
+					None,
+					&recipient_onion,
+					route_params,
+				);
 
 				Ok(payment_hash)
 			},
@@ -1163,31 +2804,84 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 					channelmanager::RetryableSendFailure::DuplicatePayment => {
 						Err(Error::DuplicatePayment)
 					},
-					_ => {
+					e => {
+						self.router.unpin_first_hop(&payment_hash);
 						let payment = PaymentDetails {
 							preimage: None,
 							hash: payment_hash,
+							id: Some(payment_id),
 							secret: payment_secret,
 							amount_msat: invoice.amount_milli_satoshis(),
 							direction: PaymentDirection::Outbound,
 							status: PaymentStatus::Failed,
 							lsp_fee_limits: None,
+							kind: Some(PaymentKind::Bolt11),
+							description_hash: None,
+							expected_amount_msat: None,
+							invoice_expiry_timestamp: None,
+							custom_tlvs: Vec::new(),
 						};
 
 						self.payment_store.insert(payment)?;
-						Err(Error::PaymentSendingFailed)
+						match e {
+							channelmanager::RetryableSendFailure::RouteNotFound => {
+								if is_fee_policy_applied
+									&& self.exceeds_max_routing_fee(&route_params)
+								{
+									Err(Error::RoutingFeeLimitExceeded)
+								} else if is_single_path {
+									Err(Error::SinglePathRouteNotFound)
+								} else {
+									Err(Error::RouteNotFound)
+								}
+							},
+							_ => Err(Error::PaymentSendingFailed),
+						}
 					},
 				}
 			},
 		}
 	}
 
+	/// Send a payment of `amount_msat` to the given [Lightning Address] or [LNURL-pay] target.
+	///
+	/// This retrieves an invoice from the respective LNURL service and pays it via
+	/// [`Node::send_payment`]. The invoice is checked to pay exactly the requested amount and to
+	/// commit to the metadata advertised by the service. If set, `comment` is passed to the
+	/// service, which needs to accept comments of its length.
+	///
+	/// If [`Config::tor_proxy_address`] is set, the service is contacted through the proxy.
+	///
+	/// [Lightning Address]: https://github.com/lnurl/luds/blob/luds/16.md
+	/// [LNURL-pay]: https://github.com/lnurl/luds/blob/luds/06.md
+	pub fn pay_lightning_address(
+		&self, address: &str, amount_msat: u64, comment: Option<String>,
+	) -> Result<PaymentHash, Error> {
+		let invoice = {
+			let rt_lock = self.runtime.read().unwrap();
+			let runtime = rt_lock.as_ref().ok_or(Error::NotRunning)?;
+
+			let lnurl_client =
+				lnurl::LnurlClient::new(self.config.tor_proxy_address, Arc::clone(&self.logger))?;
+			tokio::task::block_in_place(move || {
+				runtime.block_on(async move {
+					lnurl_client.request_pay_invoice(address, amount_msat, comment).await
+				})
+			})?
+		};
+
+		self.send_payment(&invoice)
+	}
+
 	/// Send a payment given an invoice and an amount in millisatoshi.
 	///
-	/// This will fail if the amount given is less than the value required by the given invoice.
+	/// This is intended to pay a so-called "zero-amount" invoice, i.e., an invoice that leaves the
+	/// amount paid to be determined by the user. The given amount will be recorded in the
+	/// resulting [`PaymentDetails`].
 	///
-	/// This can be used to pay a so-called "zero-amount" invoice, i.e., an invoice that leaves the
-	/// amount paid to be determined by the user.
+	/// If the invoice specifies an amount, this will fail with [`Error::InvalidAmount`] unless
+	/// the given amount matches it exactly, so that we never route an amount the recipient didn't
+	/// ask for.
 	pub fn send_payment_using_amount(
 		&self, invoice: &Bolt11Invoice, amount_msat: u64,
 	) -> Result<PaymentHash, Error> {
@@ -1196,11 +2890,16 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 			return Err(Error::NotRunning);
 		}
 
+		if amount_msat == 0 {
+			log_error!(self.logger, "Failed to pay as the given amount needs to be non-zero.");
+			return Err(Error::InvalidAmount);
+		}
+
 		if let Some(invoice_amount_msat) = invoice.amount_milli_satoshis() {
-			if amount_msat < invoice_amount_msat {
+			if amount_msat != invoice_amount_msat {
 				log_error!(
 					self.logger,
-					"Failed to pay as the given amount needs to be at least the invoice amount: required {}msat, gave {}msat.", invoice_amount_msat, amount_msat);
+					"Failed to pay as the given amount doesn't match the invoice amount: required {}msat, gave {}msat.", invoice_amount_msat, amount_msat);
 				return Err(Error::InvalidAmount);
 			}
 		}
@@ -1230,17 +2929,18 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				.with_bolt11_features(features.clone())
 				.map_err(|_| Error::InvalidInvoice)?;
 		}
-		let route_params =
+		let mut route_params =
 			RouteParameters::from_payment_params_and_value(payment_params, amount_msat);
+		let is_fee_policy_applied = self.apply_max_routing_fee_policy(&mut route_params);
 
 		let retry_strategy = Retry::Timeout(LDK_PAYMENT_RETRY_TIMEOUT);
 		let recipient_fields = RecipientOnionFields::secret_only(*payment_secret);
 
 		match self.channel_manager.send_payment(
 			payment_hash,
-			recipient_fields,
+			recipient_fields.clone(),
 			payment_id,
-			route_params,
+			route_params.clone(),
 			retry_strategy,
 		) {
 			Ok(_payment_id) => {
@@ -1254,14 +2954,27 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 
 				let payment = PaymentDetails {
 					hash: payment_hash,
+					id: Some(payment_id),
 					preimage: None,
 					secret: Some(*payment_secret),
 					amount_msat: Some(amount_msat),
 					direction: PaymentDirection::Outbound,
 					status: PaymentStatus::Pending,
 					lsp_fee_limits: None,
+					kind: Some(PaymentKind::Bolt11),
+					description_hash: None,
+					expected_amount_msat: None,
+					invoice_expiry_timestamp: None,
+					custom_tlvs: Vec::new(),
 				};
 				self.payment_store.insert(payment)?;
+				self.payment_retrier.register(
+					payment_hash,
+					payment_id,
+					None,
+					&recipient_fields,
+					route_params,
+				);
 
 				Ok(payment_hash)
 			},
@@ -1272,19 +2985,25 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 					channelmanager::RetryableSendFailure::DuplicatePayment => {
 						Err(Error::DuplicatePayment)
 					},
-					_ => {
+					e => {
 						let payment = PaymentDetails {
 							hash: payment_hash,
+							id: Some(payment_id),
 							preimage: None,
 							secret: Some(*payment_secret),
 							amount_msat: Some(amount_msat),
 							direction: PaymentDirection::Outbound,
 							status: PaymentStatus::Failed,
 							lsp_fee_limits: None,
+							kind: Some(PaymentKind::Bolt11),
+							description_hash: None,
+							expected_amount_msat: None,
+							invoice_expiry_timestamp: None,
+							custom_tlvs: Vec::new(),
 						};
 						self.payment_store.insert(payment)?;
 
-						Err(Error::PaymentSendingFailed)
+						Err(self.send_failure_error(e, is_fee_policy_applied, &route_params))
 					},
 				}
 			},
@@ -1294,6 +3013,24 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 	/// Send a spontaneous, aka. "keysend", payment
 	pub fn send_spontaneous_payment(
 		&self, amount_msat: u64, node_id: PublicKey,
+	) -> Result<PaymentHash, Error> {
+		self.send_spontaneous_payment_inner(amount_msat, node_id, Vec::new())
+	}
+
+	/// Send a spontaneous, aka. "keysend", payment carrying the given custom TLV records.
+	///
+	/// The records will be forwarded to the recipient as part of the payment onion. Will return
+	/// [`Error::InvalidCustomTlvs`] if any of the type numbers is even, lower than `2^16`, or
+	/// given more than once, as only odd records in the custom range are tolerated by nodes that
+	/// don't understand them.
+	pub fn send_spontaneous_payment_with_custom_tlvs(
+		&self, amount_msat: u64, node_id: PublicKey, custom_tlvs: Vec<CustomTlvRecord>,
+	) -> Result<PaymentHash, Error> {
+		self.send_spontaneous_payment_inner(amount_msat, node_id, custom_tlvs)
+	}
+
+	fn send_spontaneous_payment_inner(
+		&self, amount_msat: u64, node_id: PublicKey, custom_tlvs: Vec<CustomTlvRecord>,
 	) -> Result<PaymentHash, Error> {
 		let rt_lock = self.runtime.read().unwrap();
 		if rt_lock.is_none() {
@@ -1312,17 +3049,42 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 			}
 		}
 
-		let route_params = RouteParameters::from_payment_params_and_value(
+		let mut route_params = RouteParameters::from_payment_params_and_value(
 			PaymentParameters::from_node_id(node_id, self.config.default_cltv_expiry_delta),
 			amount_msat,
 		);
-		let recipient_fields = RecipientOnionFields::spontaneous_empty();
+		let is_fee_policy_applied = self.apply_max_routing_fee_policy(&mut route_params);
+		let mut custom_tlvs: Vec<(u64, Vec<u8>)> =
+			custom_tlvs.into_iter().map(|tlv| (tlv.type_num, tlv.value)).collect();
+		custom_tlvs.sort_unstable_by_key(|(type_num, _)| *type_num);
+		let has_invalid_type_num = custom_tlvs
+			.iter()
+			.any(|(type_num, _)| *type_num < CUSTOM_TLV_TYPE_NUM_MIN || *type_num % 2 == 0);
+		let has_duplicate_type_num = custom_tlvs.windows(2).any(|w| w[0].0 == w[1].0);
+		if has_invalid_type_num || has_duplicate_type_num {
+			log_error!(
+				self.logger,
+				"Payment error: custom TLV type numbers must be odd, unique, and at least {}.",
+				CUSTOM_TLV_TYPE_NUM_MIN
+			);
+			return Err(Error::InvalidCustomTlvs);
+		}
+
+		let recipient_fields = RecipientOnionFields::spontaneous_empty()
+			.with_custom_tlvs(custom_tlvs)
+			.map_err(|_| {
+				log_error!(self.logger, "Payment error: failed to add custom TLVs.");
+				Error::InvalidCustomTlvs
+			})?;
 
+		let custom_tlvs: Vec<CustomTlvRecord> =
+			recipient_fields.custom_tlvs().iter().map(|tlv| tlv.into()).collect();
+		let payment_id = PaymentId(payment_hash.0);
 		match self.channel_manager.send_spontaneous_payment_with_retry(
 			Some(payment_preimage),
-			recipient_fields,
-			PaymentId(payment_hash.0),
-			route_params,
+			recipient_fields.clone(),
+			payment_id,
+			route_params.clone(),
 			Retry::Timeout(LDK_PAYMENT_RETRY_TIMEOUT),
 		) {
 			Ok(_payment_id) => {
@@ -1330,14 +3092,27 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 
 				let payment = PaymentDetails {
 					hash: payment_hash,
+					id: Some(payment_id),
 					preimage: Some(payment_preimage),
 					secret: None,
 					status: PaymentStatus::Pending,
 					direction: PaymentDirection::Outbound,
 					amount_msat: Some(amount_msat),
 					lsp_fee_limits: None,
+					kind: Some(PaymentKind::Spontaneous),
+					description_hash: None,
+					expected_amount_msat: None,
+					invoice_expiry_timestamp: None,
+					custom_tlvs,
 				};
 				self.payment_store.insert(payment)?;
+				self.payment_retrier.register(
+					payment_hash,
+					payment_id,
+					Some(payment_preimage),
+					&recipient_fields,
+					route_params,
+				);
 
 				Ok(payment_hash)
 			},
@@ -1348,128 +3123,538 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 					channelmanager::RetryableSendFailure::DuplicatePayment => {
 						Err(Error::DuplicatePayment)
 					},
-					_ => {
+					e => {
 						let payment = PaymentDetails {
 							hash: payment_hash,
+							id: Some(payment_id),
 							preimage: Some(payment_preimage),
 							secret: None,
 							status: PaymentStatus::Failed,
 							direction: PaymentDirection::Outbound,
 							amount_msat: Some(amount_msat),
 							lsp_fee_limits: None,
+							kind: Some(PaymentKind::Spontaneous),
+							description_hash: None,
+							expected_amount_msat: None,
+							invoice_expiry_timestamp: None,
+							custom_tlvs,
 						};
 
 						self.payment_store.insert(payment)?;
-						Err(Error::PaymentSendingFailed)
+						Err(self.send_failure_error(e, is_fee_policy_applied, &route_params))
 					},
 				}
 			},
 		}
 	}
 
-	/// Sends payment probes over all paths of a route that would be used to pay the given invoice.
-	///
-	/// This may be used to send "pre-flight" probes, i.e., to train our scorer before conducting
-	/// the actual payment. Note this is only useful if there likely is sufficient time for the
-	/// probe to settle before sending out the actual payment, e.g., when waiting for user
-	/// confirmation in a wallet UI.
-	///
-	/// Otherwise, there is a chance the probe could take up some liquidity needed to complete the
-	/// actual payment. Users should therefore be cautious and might avoid sending probes if
-	/// liquidity is scarce and/or they don't expect the probe to return before they send the
-	/// payment. To mitigate this issue, channels with available liquidity less than the required
-	/// amount times [`Config::probing_liquidity_limit_multiplier`] won't be used to send
-	/// pre-flight probes.
-	pub fn send_payment_probes(&self, invoice: &Bolt11Invoice) -> Result<(), Error> {
-		let rt_lock = self.runtime.read().unwrap();
-		if rt_lock.is_none() {
-			return Err(Error::NotRunning);
-		}
-
-		let (_payment_hash, _recipient_onion, route_params) = payment::payment_parameters_from_invoice(&invoice).map_err(|_| {
-			log_error!(self.logger, "Failed to send probes due to the given invoice being \"zero-amount\". Please use send_payment_probes_using_amount instead.");
-			Error::InvalidInvoice
+	// Has all routes of the given payment leave through the given channel, after checking that it
+	// is usable for the given amount. If `allow_fallback` is set, we resort to our other channels
+	// rather than failing.
+	fn pin_first_hop(
+		&self, payment_hash: PaymentHash, channel_id: ChannelId, amount_msat: u64,
+		allow_fallback: bool,
+	) -> Result<(), Error> {
+		let channels = self.channel_manager.list_channels();
+		let channel = channels.iter().find(|c| c.channel_id == channel_id).ok_or_else(|| {
+			log_error!(self.logger, "Payment error: unknown first-hop channel {}.", channel_id);
+			Error::InvalidChannelId
 		})?;
 
-		let liquidity_limit_multiplier = Some(self.config.probing_liquidity_limit_multiplier);
-
-		self.channel_manager
-			.send_preflight_probes(route_params, liquidity_limit_multiplier)
-			.map_err(|e| {
-				log_error!(self.logger, "Failed to send payment probes: {:?}", e);
-				Error::ProbeSendingFailed
-			})?;
+		if !channel.is_usable || channel.next_outbound_htlc_limit_msat < amount_msat {
+			if allow_fallback {
+				log_warn!(
+					self.logger,
+					"First-hop channel {} can't be used to send {}msat, falling back to other channels.",
+					channel_id,
+					amount_msat
+				);
+				return Ok(());
+			}
+			log_error!(
+				self.logger,
+				"Payment error: first-hop channel {} can't be used to send {}msat.",
+				channel_id,
+				amount_msat
+			);
+			return Err(Error::FirstHopChannelUnusable);
+		}
 
+		self.router.pin_first_hop(payment_hash, channel_id, allow_fallback);
 		Ok(())
 	}
 
-	/// Sends payment probes over all paths of a route that would be used to pay the given
-	/// amount to the given `node_id`.
-	///
-	/// See [`Self::send_payment_probes`] for more information.
-	pub fn send_spontaneous_payment_probes(
-		&self, amount_msat: u64, node_id: PublicKey,
-	) -> Result<(), Error> {
-		let rt_lock = self.runtime.read().unwrap();
-		if rt_lock.is_none() {
-			return Err(Error::NotRunning);
+	// Limits the routing fees of the given payment according to our `MaxFeePolicy`, if any.
+	// Returns whether the policy was applied.
+	fn apply_max_routing_fee_policy(&self, route_params: &mut RouteParameters) -> bool {
+		match self.config.max_routing_fee_policy {
+			Some(policy) => {
+				route_params.max_total_routing_fee_msat =
+					Some(policy.max_fee_msat(route_params.final_value_msat));
+				true
+			},
+			None => false,
 		}
+	}
 
-		let liquidity_limit_multiplier = Some(self.config.probing_liquidity_limit_multiplier);
-		let cltv_expiry_delta = self.config.default_cltv_expiry_delta;
-
-		self.channel_manager
-			.send_spontaneous_preflight_probes(
-				node_id,
-				amount_msat,
-				cltv_expiry_delta,
-				liquidity_limit_multiplier,
+	// Returns whether a route for the given payment exists, but only when exceeding its routing
+	// fee limit.
+	fn exceeds_max_routing_fee(&self, route_params: &RouteParameters) -> bool {
+		let max_fee_msat = match route_params.max_total_routing_fee_msat {
+			Some(max_fee_msat) => max_fee_msat,
+			None => return false,
+		};
+		let mut unlimited_route_params = route_params.clone();
+		unlimited_route_params.max_total_routing_fee_msat = None;
+		let first_hops = self.channel_manager.list_usable_channels();
+		self.router
+			.find_route(
+				&self.channel_manager.get_our_node_id(),
+				&unlimited_route_params,
+				Some(&first_hops.iter().collect::<Vec<_>>()),
+				self.channel_manager.compute_inflight_htlcs(),
 			)
-			.map_err(|e| {
-				log_error!(self.logger, "Failed to send payment probes: {:?}", e);
-				Error::ProbeSendingFailed
-			})?;
+			.map_or(false, |route| route.get_total_fees() > max_fee_msat)
+	}
 
-		Ok(())
+	fn send_failure_error(
+		&self, failure: channelmanager::RetryableSendFailure, is_fee_policy_applied: bool,
+		route_params: &RouteParameters,
+	) -> Error {
+		match failure {
+			channelmanager::RetryableSendFailure::RouteNotFound
+				if is_fee_policy_applied && self.exceeds_max_routing_fee(route_params) =>
+			{
+				log_error!(
+					self.logger,
+					"Failed to send payment within the maximum routing fee of {}msat.",
+					route_params.max_total_routing_fee_msat.unwrap_or(0)
+				);
+				Error::RoutingFeeLimitExceeded
+			},
+			_ => Error::PaymentSendingFailed,
+		}
 	}
 
-	/// Sends payment probes over all paths of a route that would be used to pay the given
-	/// zero-value invoice using the given amount.
+	/// Moves liquidity from one of our channels to another by sending a circular payment to
+	/// ourselves.
 	///
-	/// This can be used to send pre-flight probes for a so-called "zero-amount" invoice, i.e., an
-	/// invoice that leaves the amount paid to be determined by the user.
+	/// The payment is routed out via the channel identified by `from_channel` and back in via the
+	/// channel identified by `to_channel`, shifting `amount_msat` of outbound liquidity from the
+	/// former to the latter. Routing fees of at most `max_fee_msat` are paid to the intermediate
+	/// nodes. The payment is recorded with [`PaymentKind::Rebalance`].
 	///
-	/// See [`Self::send_payment_probes`] for more information.
-	pub fn send_payment_probes_using_amount(
-		&self, invoice: &Bolt11Invoice, amount_msat: u64,
-	) -> Result<(), Error> {
+	/// If no suitable route can be found within the given fee limit, this will fail with
+	/// [`Error::RouteNotFound`]. Otherwise, the payment is sent asynchronously, and its outcome
+	/// will be surfaced via [`Event::PaymentSuccessful`] or [`Event::PaymentFailed`], where the
+	/// latter reports why the payment failed in-flight.
+	pub fn rebalance_channels(
+		&self, from_channel: &UserChannelId, to_channel: &UserChannelId, amount_msat: u64,
+		max_fee_msat: u64,
+	) -> Result<PaymentHash, Error> {
 		let rt_lock = self.runtime.read().unwrap();
 		if rt_lock.is_none() {
 			return Err(Error::NotRunning);
 		}
 
-		let (_payment_hash, _recipient_onion, route_params) = if let Some(invoice_amount_msat) =
-			invoice.amount_milli_satoshis()
-		{
-			if amount_msat < invoice_amount_msat {
-				log_error!(
-					self.logger,
-					"Failed to send probes as the given amount needs to be at least the invoice amount: required {}msat, gave {}msat.", invoice_amount_msat, amount_msat);
-				return Err(Error::InvalidAmount);
-			}
+		if from_channel == to_channel {
+			log_error!(self.logger, "Failed to rebalance as both given channels are the same.");
+			return Err(Error::InvalidChannelId);
+		}
 
-			payment::payment_parameters_from_invoice(&invoice).map_err(|_| {
-				log_error!(self.logger, "Failed to send probes due to the given invoice unexpectedly being \"zero-amount\".");
-				Error::InvalidInvoice
-			})?
-		} else {
-			payment::payment_parameters_from_zero_amount_invoice(&invoice, amount_msat).map_err(|_| {
-				log_error!(self.logger, "Failed to send probes due to the given invoice unexpectedly being not \"zero-amount\".");
-				Error::InvalidInvoice
-			})?
+		let usable_channels = self.channel_manager.list_usable_channels();
+		let find_channel = |user_channel_id: &UserChannelId| {
+			usable_channels.iter().find(|c| c.user_channel_id == user_channel_id.0).ok_or_else(
+				|| {
+					log_error!(
+						self.logger,
+						"Failed to rebalance as channel {} is unknown or not usable.",
+						user_channel_id.0
+					);
+					Error::InvalidChannelId
+				},
+			)
 		};
+		let from_details = find_channel(from_channel)?;
+		let to_details = find_channel(to_channel)?;
 
-		let liquidity_limit_multiplier = Some(self.config.probing_liquidity_limit_multiplier);
+		let (from_scid, to_scid) =
+			match (from_details.get_outbound_payment_scid(), to_details.get_inbound_payment_scid())
+			{
+				(Some(from_scid), Some(to_scid)) => (from_scid, to_scid),
+				_ => {
+					log_error!(self.logger, "Failed to rebalance as a channel is not ready yet.");
+					return Err(Error::InvalidChannelId);
+				},
+			};
+
+		// The fee and CLTV delta the counterparty of `to_channel` applies when forwarding to us.
+		let to_forwarding_info =
+			to_details.counterparty.forwarding_info.clone().ok_or_else(|| {
+				log_error!(
+					self.logger,
+					"Failed to rebalance as the forwarding policy of channel {} is unknown.",
+					to_channel.0
+				);
+				Error::RouteNotFound
+			})?;
+		let to_fee_msat = to_forwarding_info.fee_base_msat as u64
+			+ amount_msat * to_forwarding_info.fee_proportional_millionths as u64 / 1_000_000;
+
+		let our_node_id = self.channel_manager.get_our_node_id();
+		let from_node_id = from_details.counterparty.node_id;
+		let to_node_id = to_details.counterparty.node_id;
+		let final_cltv_expiry_delta = self.config.default_cltv_expiry_delta;
+
+		// Find a route from the counterparty of `from_channel` to the counterparty of
+		// `to_channel`, unless both channels are with the same counterparty.
+		let mut middle_hops = Vec::new();
+		let mut from_fee_msat = to_fee_msat;
+		let mut from_cltv_expiry_delta = to_forwarding_info.cltv_expiry_delta as u32;
+		if from_node_id != to_node_id {
+			let mut payment_params = PaymentParameters::from_node_id(
+				to_node_id,
+				to_forwarding_info.cltv_expiry_delta as u32,
+			);
+			// Avoid routing back through us.
+			payment_params.previously_failed_channels = self
+				.channel_manager
+				.list_channels()
+				.iter()
+				.filter_map(|c| c.short_channel_id)
+				.collect();
+			let route_params = RouteParameters {
+				payment_params,
+				final_value_msat: amount_msat + to_fee_msat,
+				max_total_routing_fee_msat: Some(max_fee_msat.saturating_sub(to_fee_msat)),
+			};
+			let route = self
+				.router
+				.find_route(
+					&from_node_id,
+					&route_params,
+					None,
+					self.channel_manager.compute_inflight_htlcs(),
+				)
+				.map_err(|e| {
+					log_error!(self.logger, "Failed to find rebalancing route: {}", e.err);
+					Error::RouteNotFound
+				})?;
+			let path = route.paths.into_iter().next().ok_or(Error::RouteNotFound)?;
+			middle_hops = path.hops;
+
+			// The counterparty of `from_channel` charges its fee for the first channel of the
+			// found route, which is not included in the route as it's the payer.
+			let first_scid = middle_hops.first().ok_or(Error::RouteNotFound)?.short_channel_id;
+			let read_only_graph = self.network_graph.read_only();
+			let first_channel = read_only_graph.channel(first_scid).ok_or(Error::RouteNotFound)?;
+			let first_update = if first_channel.node_one == NodeId::from_pubkey(&from_node_id) {
+				first_channel.one_to_two.as_ref()
+			} else {
+				first_channel.two_to_one.as_ref()
+			}
+			.ok_or(Error::RouteNotFound)?;
+			let forwarded_msat = middle_hops.iter().map(|h| h.fee_msat).sum::<u64>();
+			from_fee_msat = first_update.fees.base_msat as u64
+				+ forwarded_msat * first_update.fees.proportional_millionths as u64 / 1_000_000;
+			from_cltv_expiry_delta = first_update.cltv_expiry_delta as u32;
+
+			// The last hop of the found route is the counterparty of `to_channel`, which forwards
+			// the payment to us.
+			if let Some(last_hop) = middle_hops.last_mut() {
+				last_hop.fee_msat = to_fee_msat;
+				last_hop.cltv_expiry_delta = to_forwarding_info.cltv_expiry_delta as u32;
+			}
+		}
+
+		let mut hops = Vec::with_capacity(middle_hops.len() + 2);
+		hops.push(RouteHop {
+			pubkey: from_node_id,
+			node_features: from_details.counterparty.features.to_context(),
+			short_channel_id: from_scid,
+			channel_features: ChannelFeatures::empty(),
+			fee_msat: from_fee_msat,
+			cltv_expiry_delta: from_cltv_expiry_delta,
+			maybe_announced_channel: from_details.is_public,
+		});
+		hops.extend(middle_hops);
+		hops.push(RouteHop {
+			pubkey: our_node_id,
+			node_features: self.channel_manager.node_features(),
+			short_channel_id: to_scid,
+			channel_features: ChannelFeatures::empty(),
+			fee_msat: amount_msat,
+			cltv_expiry_delta: final_cltv_expiry_delta,
+			maybe_announced_channel: to_details.is_public,
+		});
+
+		let total_fee_msat = hops.iter().rev().skip(1).map(|h| h.fee_msat).sum::<u64>();
+		if total_fee_msat > max_fee_msat {
+			log_error!(
+				self.logger,
+				"Failed to find rebalancing route within the fee limit: required {}msat, allowed {}msat.",
+				total_fee_msat,
+				max_fee_msat
+			);
+			return Err(Error::RouteNotFound);
+		}
+
+		if from_details.next_outbound_htlc_limit_msat < amount_msat + total_fee_msat {
+			log_error!(
+				self.logger,
+				"Failed to rebalance as channel {} has insufficient outbound liquidity.",
+				from_channel.0
+			);
+			return Err(Error::InsufficientFunds);
+		}
+
+		let (payment_hash, payment_secret) = self
+			.channel_manager
+			.create_inbound_payment(Some(amount_msat), 3600, Some(final_cltv_expiry_delta as u16))
+			.map_err(|()| {
+				log_error!(self.logger, "Failed to create rebalancing payment.");
+				Error::InvoiceCreationFailed
+			})?;
+		let payment_preimage =
+			self.channel_manager.get_payment_preimage(payment_hash, payment_secret).ok();
+		let payment_id = PaymentId(payment_hash.0);
+
+		let payment = PaymentDetails {
+			preimage: payment_preimage,
+			hash: payment_hash,
+			id: Some(payment_id),
+			secret: Some(payment_secret),
+			amount_msat: Some(amount_msat),
+			direction: PaymentDirection::Outbound,
+			status: PaymentStatus::Pending,
+			lsp_fee_limits: None,
+			kind: Some(PaymentKind::Rebalance),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: None,
+			custom_tlvs: Vec::new(),
+		};
+		self.payment_store.insert(payment)?;
+
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(our_node_id, final_cltv_expiry_delta),
+			final_value_msat: amount_msat,
+			max_total_routing_fee_msat: Some(max_fee_msat),
+		};
+		let route = Route {
+			paths: vec![Path { hops, blinded_tail: None }],
+			route_params: Some(route_params),
+		};
+		let recipient_onion = RecipientOnionFields::secret_only(payment_secret);
+
+		match self.channel_manager.send_payment_with_route(
+			&route,
+			payment_hash,
+			recipient_onion,
+			payment_id,
+		) {
+			Ok(()) => {
+				log_info!(
+					self.logger,
+					"Initiated rebalancing {}msat from channel {} to channel {} with a fee of {}msat",
+					amount_msat,
+					from_channel.0,
+					to_channel.0,
+					total_fee_msat
+				);
+				Ok(payment_hash)
+			},
+			Err(e) => {
+				log_error!(self.logger, "Failed to send rebalancing payment: {:?}", e);
+				let update = PaymentDetailsUpdate {
+					status: Some(PaymentStatus::Failed),
+					..PaymentDetailsUpdate::new(payment_hash)
+				};
+				self.payment_store.update(&update)?;
+				Err(Error::PaymentSendingFailed)
+			},
+		}
+	}
+
+	/// Sends probes over all paths of a route that would be used to pay `amount_msat` to the given
+	/// target, without moving any funds.
+	///
+	/// Probes are sent asynchronously, i.e., their outcome will be surfaced via
+	/// [`Event::ProbeSuccessful`] or [`Event::ProbeFailed`] for each of the probes listed in the
+	/// returned [`ProbeResult`]. Either way, the scorer will learn from the outcome. Note that probes
+	/// may take a moment to resolve and their results are only advisory, as the liquidity in the
+	/// network may have shifted by the time the actual payment is sent.
+	///
+	/// See [`Self::send_payment_probes`] for more information.
+	pub fn send_probe(&self, target: ProbeTarget, amount_msat: u64) -> Result<ProbeResult, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let liquidity_limit_multiplier = Some(self.config.probing_liquidity_limit_multiplier);
+
+		let probe_res = match target {
+			ProbeTarget::Node { node_id } => {
+				let cltv_expiry_delta = self.config.default_cltv_expiry_delta;
+				self.channel_manager.send_spontaneous_preflight_probes(
+					node_id,
+					amount_msat,
+					cltv_expiry_delta,
+					liquidity_limit_multiplier,
+				)
+			},
+			ProbeTarget::Invoice { invoice } => {
+				let mut route_params = match invoice.amount_milli_satoshis() {
+					Some(invoice_amount_msat) => {
+						if amount_msat < invoice_amount_msat {
+							log_error!(
+								self.logger,
+								"Failed to send probes as the given amount needs to be at least the invoice amount: required {}msat, gave {}msat.",
+								invoice_amount_msat,
+								amount_msat
+							);
+							return Err(Error::InvalidAmount);
+						}
+						let (_payment_hash, _recipient_onion, mut route_params) =
+							payment::payment_parameters_from_invoice(&invoice)
+								.map_err(|_| Error::InvalidInvoice)?;
+						route_params.final_value_msat = amount_msat;
+						route_params
+					},
+					None => {
+						let (_payment_hash, _recipient_onion, route_params) =
+							payment::payment_parameters_from_zero_amount_invoice(
+								&invoice,
+								amount_msat,
+							)
+							.map_err(|_| Error::InvalidInvoice)?;
+						route_params
+					},
+				};
+				self.apply_max_routing_fee_policy(&mut route_params);
+				self.channel_manager.send_preflight_probes(route_params, liquidity_limit_multiplier)
+			},
+		};
+
+		let probes = probe_res.map_err(|e| {
+			log_error!(self.logger, "Failed to send probes: {:?}", e);
+			Error::ProbeSendingFailed
+		})?;
+
+		let probe_hashes =
+			probes.into_iter().map(|(payment_hash, _payment_id)| payment_hash).collect();
+		Ok(ProbeResult { probe_hashes })
+	}
+
+	/// Sends payment probes over all paths of a route that would be used to pay the given invoice.
+	///
+	/// This may be used to send "pre-flight" probes, i.e., to train our scorer before conducting
+	/// the actual payment. Note this is only useful if there likely is sufficient time for the
+	/// probe to settle before sending out the actual payment, e.g., when waiting for user
+	/// confirmation in a wallet UI.
+	///
+	/// Otherwise, there is a chance the probe could take up some liquidity needed to complete the
+	/// actual payment. Users should therefore be cautious and might avoid sending probes if
+	/// liquidity is scarce and/or they don't expect the probe to return before they send the
+	/// payment. To mitigate this issue, channels with available liquidity less than the required
+	/// amount times [`Config::probing_liquidity_limit_multiplier`] won't be used to send
+	/// pre-flight probes.
+	///
+	/// Probes for invoices are only sent over routes within the fee limit of
+	/// [`Config::max_routing_fee_policy`], i.e., the routes the actual payment may take.
+	pub fn send_payment_probes(&self, invoice: &Bolt11Invoice) -> Result<(), Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let (_payment_hash, _recipient_onion, mut route_params) = payment::payment_parameters_from_invoice(&invoice).map_err(|_| {
+			log_error!(self.logger, "Failed to send probes due to the given invoice being \"zero-amount\". Please use send_payment_probes_using_amount instead.");
+			Error::InvalidInvoice
+		})?;
+
+		self.apply_max_routing_fee_policy(&mut route_params);
+		let liquidity_limit_multiplier = Some(self.config.probing_liquidity_limit_multiplier);
+
+		self.channel_manager
+			.send_preflight_probes(route_params, liquidity_limit_multiplier)
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to send payment probes: {:?}", e);
+				Error::ProbeSendingFailed
+			})?;
+
+		Ok(())
+	}
+
+	/// Sends payment probes over all paths of a route that would be used to pay the given
+	/// amount to the given `node_id`.
+	///
+	/// See [`Self::send_payment_probes`] for more information.
+	pub fn send_spontaneous_payment_probes(
+		&self, amount_msat: u64, node_id: PublicKey,
+	) -> Result<(), Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let liquidity_limit_multiplier = Some(self.config.probing_liquidity_limit_multiplier);
+		let cltv_expiry_delta = self.config.default_cltv_expiry_delta;
+
+		self.channel_manager
+			.send_spontaneous_preflight_probes(
+				node_id,
+				amount_msat,
+				cltv_expiry_delta,
+				liquidity_limit_multiplier,
+			)
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to send payment probes: {:?}", e);
+				Error::ProbeSendingFailed
+			})?;
+
+		Ok(())
+	}
+
+	/// Sends payment probes over all paths of a route that would be used to pay the given
+	/// zero-value invoice using the given amount.
+	///
+	/// This can be used to send pre-flight probes for a so-called "zero-amount" invoice, i.e., an
+	/// invoice that leaves the amount paid to be determined by the user.
+	///
+	/// See [`Self::send_payment_probes`] for more information.
+	pub fn send_payment_probes_using_amount(
+		&self, invoice: &Bolt11Invoice, amount_msat: u64,
+	) -> Result<(), Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let (_payment_hash, _recipient_onion, mut route_params) = if let Some(invoice_amount_msat) =
+			invoice.amount_milli_satoshis()
+		{
+			if amount_msat < invoice_amount_msat {
+				log_error!(
+					self.logger,
+					"Failed to send probes as the given amount needs to be at least the invoice amount: required {}msat, gave {}msat.", invoice_amount_msat, amount_msat);
+				return Err(Error::InvalidAmount);
+			}
+
+			payment::payment_parameters_from_invoice(&invoice).map_err(|_| {
+				log_error!(self.logger, "Failed to send probes due to the given invoice unexpectedly being \"zero-amount\".");
+				Error::InvalidInvoice
+			})?
+		} else {
+			payment::payment_parameters_from_zero_amount_invoice(&invoice, amount_msat).map_err(|_| {
+				log_error!(self.logger, "Failed to send probes due to the given invoice unexpectedly being not \"zero-amount\".");
+				Error::InvalidInvoice
+			})?
+		};
+
+		self.apply_max_routing_fee_policy(&mut route_params);
+		let liquidity_limit_multiplier = Some(self.config.probing_liquidity_limit_multiplier);
 
 		self.channel_manager
 			.send_preflight_probes(route_params, liquidity_limit_multiplier)
@@ -1478,40 +3663,491 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				Error::ProbeSendingFailed
 			})?;
 
-		Ok(())
+		Ok(())
+	}
+
+	/// Returns a payable invoice that can be used to request and receive a payment of the amount
+	/// given.
+	///
+	/// If `expiry_secs` is `0`, the invoice will expire after
+	/// [`Config::default_invoice_expiry_secs`]. If the invoice isn't paid before it expires, an
+	/// [`Event::PaymentExpired`] will be emitted.
+	pub fn receive_payment(
+		&self, amount_msat: u64, description: &str, expiry_secs: u32,
+	) -> Result<Bolt11Invoice, Error> {
+		let description = InvoiceDescription::Direct(description);
+		self.receive_payment_inner(
+			Some(amount_msat),
+			description,
+			expiry_secs,
+			None,
+			None,
+			RouteHintSelection::Automatic,
+			None,
+		)
+	}
+
+	/// Returns a payable invoice that can be used to request and receive a payment of the amount
+	/// given, including the route hints selected via `route_hints`.
+	///
+	/// Channels selected via [`RouteHintSelection::Channels`] that aren't currently usable are
+	/// excluded with a warning rather than failing the invoice creation.
+	pub fn receive_payment_with_route_hints(
+		&self, amount_msat: u64, description: &str, expiry_secs: u32,
+		route_hints: RouteHintSelection,
+	) -> Result<Bolt11Invoice, Error> {
+		let description = InvoiceDescription::Direct(description);
+		self.receive_payment_inner(
+			Some(amount_msat),
+			description,
+			expiry_secs,
+			None,
+			None,
+			route_hints,
+			None,
+		)
+	}
+
+	/// Returns a payable invoice that can be used to request and receive a payment of the amount
+	/// given, requiring the payment to arrive with at least `min_final_cltv_expiry_delta` blocks
+	/// left until its HTLCs expire.
+	///
+	/// A higher delta gives us more time to claim the payment on-chain should the channel be
+	/// force-closed while it's pending, e.g., if we're offline for longer periods or expect
+	/// congested blocks. However, it also locks up the payer's funds for longer if the payment
+	/// fails, and payers may not find a route if the total CLTV delta of a path exceeds their
+	/// limit, which for LDK-based payers is 1008 blocks by default.
+	///
+	/// Will fail with [`Error::InvalidCltvExpiryDelta`] if the given delta is below the minimum
+	/// supported by LDK. See [`Config::min_final_cltv_expiry_delta`] for the default used by the
+	/// other `receive_*` methods.
+	pub fn receive_payment_with_min_final_cltv_expiry_delta(
+		&self, amount_msat: u64, description: &str, expiry_secs: u32,
+		min_final_cltv_expiry_delta: u16,
+	) -> Result<Bolt11Invoice, Error> {
+		let description = InvoiceDescription::Direct(description);
+		self.receive_payment_inner(
+			Some(amount_msat),
+			description,
+			expiry_secs,
+			None,
+			None,
+			RouteHintSelection::Automatic,
+			Some(min_final_cltv_expiry_delta),
+		)
+	}
+
+	/// Returns a payable "zero-amount" invoice, requiring the payment to arrive with at least
+	/// `min_final_cltv_expiry_delta` blocks left until its HTLCs expire.
+	///
+	/// See [`Node::receive_payment_with_min_final_cltv_expiry_delta`] for more information.
+	pub fn receive_variable_amount_payment_with_min_final_cltv_expiry_delta(
+		&self, description: &str, expiry_secs: u32, min_final_cltv_expiry_delta: u16,
+	) -> Result<Bolt11Invoice, Error> {
+		let description = InvoiceDescription::Direct(description);
+		self.receive_payment_inner(
+			None,
+			description,
+			expiry_secs,
+			None,
+			None,
+			RouteHintSelection::Automatic,
+			Some(min_final_cltv_expiry_delta),
+		)
+	}
+
+	/// Returns a payable "zero-amount" invoice including the route hints selected via
+	/// `route_hints`.
+	///
+	/// See [`Node::receive_payment_with_route_hints`] for more information.
+	pub fn receive_variable_amount_payment_with_route_hints(
+		&self, description: &str, expiry_secs: u32, route_hints: RouteHintSelection,
+	) -> Result<Bolt11Invoice, Error> {
+		let description = InvoiceDescription::Direct(description);
+		self.receive_payment_inner(None, description, expiry_secs, None, None, route_hints, None)
+	}
+
+	/// Returns a payable invoice that can be used to request and receive a payment of the amount
+	/// given, committing to the given description hash rather than to a description.
+	///
+	/// This allows to use descriptions that exceed the maximum length of the invoice's description
+	/// field. The full description, of which `description_hash` is the SHA-256 hash, is expected
+	/// to be provided to the payer out-of-band.
+	pub fn receive_payment_with_description_hash(
+		&self, amount_msat: u64, description_hash: DescriptionHash, expiry_secs: u32,
+	) -> Result<Bolt11Invoice, Error> {
+		let description = InvoiceDescription::Hash(description_hash);
+		self.receive_payment_inner(
+			Some(amount_msat),
+			description,
+			expiry_secs,
+			None,
+			None,
+			RouteHintSelection::Automatic,
+			None,
+		)
+	}
+
+	/// Returns a payable invoice that can be used to request and receive a payment for which the
+	/// amount is to be determined by the user, also known as a "zero-amount" invoice.
+	pub fn receive_variable_amount_payment(
+		&self, description: &str, expiry_secs: u32,
+	) -> Result<Bolt11Invoice, Error> {
+		let description = InvoiceDescription::Direct(description);
+		self.receive_payment_inner(
+			None,
+			description,
+			expiry_secs,
+			None,
+			None,
+			RouteHintSelection::Automatic,
+			None,
+		)
+	}
+
+	/// Returns a payable invoice that can be used to request and receive a payment for which the
+	/// amount is to be determined by the user, while recording the amount we expect to receive.
+	///
+	/// The `expected_amount_msat` is purely informational and will be persisted as part of the
+	/// payment's [`PaymentDetails`]. Any amount paid will be accepted, while
+	/// [`Event::PaymentReceived`] will indicate whether the expected amount was met.
+	pub fn receive_variable_amount_payment_with_expected_amount(
+		&self, description: &str, expiry_secs: u32, expected_amount_msat: u64,
+	) -> Result<Bolt11Invoice, Error> {
+		let description = InvoiceDescription::Direct(description);
+		self.receive_payment_inner(
+			None,
+			description,
+			expiry_secs,
+			Some(expected_amount_msat),
+			None,
+			RouteHintSelection::Automatic,
+			None,
+		)
+	}
+
+	/// Returns a payable "zero-amount" invoice committing to the given description hash rather than
+	/// to a description.
+	///
+	/// See [`Node::receive_payment_with_description_hash`] for more information.
+	pub fn receive_variable_amount_payment_with_description_hash(
+		&self, description_hash: DescriptionHash, expiry_secs: u32,
+	) -> Result<Bolt11Invoice, Error> {
+		let description = InvoiceDescription::Hash(description_hash);
+		self.receive_payment_inner(
+			None,
+			description,
+			expiry_secs,
+			None,
+			None,
+			RouteHintSelection::Automatic,
+			None,
+		)
+	}
+
+	/// Returns a payable hold invoice that can be used to request and receive a payment of the
+	/// amount given.
+	///
+	/// In contrast to other invoices, payments to hold invoices won't be claimed automatically.
+	/// Instead, an [`Event::PaymentClaimable`] will be emitted once the payment arrives, after
+	/// which the payment needs to be claimed via [`Node::claim_payment`] or failed via
+	/// [`Node::fail_payment`] before its claim deadline. The preimage required to claim the
+	/// payment is available via the [`PaymentDetails::preimage`] returned by [`Node::payment`].
+	///
+	/// If the payment is neither claimed nor failed in time, we'll fail it back automatically and
+	/// emit an [`Event::HeldPaymentAutoFailed`].
+	pub fn receive_hold_payment(
+		&self, amount_msat: u64, description: &str, expiry_secs: u32,
+	) -> Result<Bolt11Invoice, Error> {
+		let description = InvoiceDescription::Direct(description);
+		let invoice = self.receive_payment_inner(
+			Some(amount_msat),
+			description,
+			expiry_secs,
+			None,
+			None,
+			RouteHintSelection::Automatic,
+			None,
+		)?;
+
+		let payment_hash = PaymentHash(invoice.payment_hash().to_byte_array());
+		let payment_preimage = self
+			.channel_manager
+			.get_payment_preimage(payment_hash, *invoice.payment_secret())
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to retrieve payment preimage: {:?}", e);
+				Error::InvoiceCreationFailed
+			})?;
+		let update = PaymentDetailsUpdate {
+			preimage: Some(Some(payment_preimage)),
+			..PaymentDetailsUpdate::new(payment_hash)
+		};
+		self.payment_store.update(&update)?;
+		self.hold_invoice_handler.register(payment_hash)?;
+
+		Ok(invoice)
+	}
+
+	/// Returns a payable hold invoice committing to the given payment hash, for which the
+	/// preimage isn't known to us.
+	///
+	/// This allows to keep custody of the preimage elsewhere, e.g., in a hardware security module,
+	/// and only reveal it to claim the payment. As for [`Node::receive_hold_payment`], an
+	/// [`Event::PaymentClaimable`] will be emitted once the payment arrives, after which it needs
+	/// to be claimed via [`Node::claim_payment`], given the preimage of `payment_hash`, or failed
+	/// via [`Node::fail_payment`].
+	///
+	/// Will return [`Error::DuplicatePayment`] if we already know a payment with the given hash.
+	pub fn receive_payment_for_hash(
+		&self, amount_msat: u64, description: &str, expiry_secs: u32, payment_hash: PaymentHash,
+	) -> Result<Bolt11Invoice, Error> {
+		self.receive_for_hash_inner(Some(amount_msat), description, expiry_secs, payment_hash)
+	}
+
+	/// Returns a payable "zero-amount" hold invoice committing to the given payment hash, for
+	/// which the preimage isn't known to us.
+	///
+	/// See [`Node::receive_payment_for_hash`] for more information.
+	pub fn receive_variable_amount_payment_for_hash(
+		&self, description: &str, expiry_secs: u32, payment_hash: PaymentHash,
+	) -> Result<Bolt11Invoice, Error> {
+		self.receive_for_hash_inner(None, description, expiry_secs, payment_hash)
+	}
+
+	fn receive_for_hash_inner(
+		&self, amount_msat: Option<u64>, description: &str, expiry_secs: u32,
+		payment_hash: PaymentHash,
+	) -> Result<Bolt11Invoice, Error> {
+		if self.payment_store.get(&payment_hash).is_some() {
+			log_error!(
+				self.logger,
+				"Failed to create invoice: payment hash {} is already known.",
+				hex_utils::to_string(&payment_hash.0)
+			);
+			return Err(Error::DuplicatePayment);
+		}
+
+		let description = InvoiceDescription::Direct(description);
+		let invoice = self.receive_payment_inner(
+			amount_msat,
+			description,
+			expiry_secs,
+			None,
+			Some(payment_hash),
+			RouteHintSelection::Automatic,
+			None,
+		)?;
+		self.hold_invoice_handler.register(payment_hash)?;
+
+		Ok(invoice)
 	}
 
-	/// Returns a payable invoice that can be used to request and receive a payment of the amount
-	/// given.
-	pub fn receive_payment(
+	/// Claims the held payment with the given hash, which was previously announced via an
+	/// [`Event::PaymentClaimable`].
+	///
+	/// Will return [`Error::InvalidPaymentPreimage`] if the given preimage doesn't match the
+	/// payment hash. An [`Event::PaymentReceived`] will be emitted once the payment was claimed.
+	pub fn claim_payment(
+		&self, payment_hash: &PaymentHash, payment_preimage: PaymentPreimage,
+	) -> Result<(), Error> {
+		self.hold_invoice_handler.claim(payment_hash, payment_preimage)
+	}
+
+	/// Fails the held payment with the given hash, which was previously announced via an
+	/// [`Event::PaymentClaimable`], back to the sender.
+	///
+	/// Any further payments to the respective hold invoice will be failed, too.
+	pub fn fail_payment(&self, payment_hash: &PaymentHash) -> Result<(), Error> {
+		self.hold_invoice_handler.fail(payment_hash)
+	}
+
+	/// Returns the route hints other nodes need to include in phantom invoices to allow paying
+	/// them to us.
+	///
+	/// Will return [`Error::PhantomPaymentsNotEnabled`] if no phantom cross-node seed was
+	/// configured via [`Builder::set_phantom_cross_node_seed`].
+	pub fn phantom_route_hints(&self) -> Result<PhantomRouteHints, Error> {
+		if !self.keys_manager.is_phantom() {
+			log_error!(self.logger, "Failed to retrieve phantom route hints: not enabled.");
+			return Err(Error::PhantomPaymentsNotEnabled);
+		}
+		Ok(self.channel_manager.get_phantom_route_hints())
+	}
+
+	/// Returns a payable phantom invoice that can be paid to any of the nodes whose
+	/// [`Node::phantom_route_hints`] are given.
+	///
+	/// The payment may be received by whichever of these nodes is online, which will then emit an
+	/// [`Event::PaymentReceived`]. All of them need to share the same phantom cross-node seed, see
+	/// [`Builder::set_phantom_cross_node_seed`].
+	pub fn receive_phantom_payment(
 		&self, amount_msat: u64, description: &str, expiry_secs: u32,
+		phantom_route_hints: Vec<PhantomRouteHints>,
 	) -> Result<Bolt11Invoice, Error> {
-		self.receive_payment_inner(Some(amount_msat), description, expiry_secs)
+		self.receive_phantom_payment_inner(
+			Some(amount_msat),
+			description,
+			expiry_secs,
+			phantom_route_hints,
+		)
 	}
 
-	/// Returns a payable invoice that can be used to request and receive a payment for which the
-	/// amount is to be determined by the user, also known as a "zero-amount" invoice.
-	pub fn receive_variable_amount_payment(
-		&self, description: &str, expiry_secs: u32,
+	/// Returns a payable "zero-amount" phantom invoice.
+	///
+	/// See [`Node::receive_phantom_payment`] for more information.
+	pub fn receive_variable_amount_phantom_payment(
+		&self, description: &str, expiry_secs: u32, phantom_route_hints: Vec<PhantomRouteHints>,
 	) -> Result<Bolt11Invoice, Error> {
-		self.receive_payment_inner(None, description, expiry_secs)
+		self.receive_phantom_payment_inner(None, description, expiry_secs, phantom_route_hints)
 	}
 
-	fn receive_payment_inner(
+	fn receive_phantom_payment_inner(
 		&self, amount_msat: Option<u64>, description: &str, expiry_secs: u32,
+		phantom_route_hints: Vec<PhantomRouteHints>,
 	) -> Result<Bolt11Invoice, Error> {
-		let currency = Currency::from(self.config.network);
-		let keys_manager = Arc::clone(&self.keys_manager);
-		let invoice = match lightning_invoice::utils::create_invoice_from_channelmanager(
-			&self.channel_manager,
-			keys_manager,
-			Arc::clone(&self.logger),
-			currency,
+		if !self.keys_manager.is_phantom() {
+			log_error!(self.logger, "Failed to create phantom invoice: not enabled.");
+			return Err(Error::PhantomPaymentsNotEnabled);
+		}
+
+		let expiry_secs = self.invoice_expiry_secs(expiry_secs);
+		let min_final_cltv_expiry_delta = self.min_final_cltv_expiry_delta(None)?;
+		let duration_since_epoch = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map_err(|_| Error::InvoiceCreationFailed)?;
+		let invoice = lightning_invoice::utils::create_phantom_invoice(
 			amount_msat,
+			None,
 			description.to_string(),
 			expiry_secs,
-			None,
-		) {
+			phantom_route_hints,
+			Arc::clone(&self.keys_manager),
+			Arc::clone(&self.keys_manager),
+			Arc::clone(&self.logger),
+			Currency::from(self.config.network),
+			Some(min_final_cltv_expiry_delta),
+			duration_since_epoch,
+		)
+		.map_err(|e| {
+			log_error!(self.logger, "Failed to create phantom invoice: {}", e);
+			Error::InvoiceCreationFailed
+		})?;
+		log_info!(self.logger, "Phantom invoice created: {}", invoice);
+
+		let payment = PaymentDetails {
+			hash: PaymentHash(invoice.payment_hash().to_byte_array()),
+			id: None,
+			preimage: None,
+			secret: Some(*invoice.payment_secret()),
+			amount_msat,
+			direction: PaymentDirection::Inbound,
+			status: PaymentStatus::Pending,
+			lsp_fee_limits: None,
+			kind: Some(PaymentKind::Bolt11),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: Some(invoice_expiry_timestamp(&invoice)),
+			custom_tlvs: Vec::new(),
+		};
+		self.payment_store.insert(payment)?;
+
+		Ok(invoice)
+	}
+
+	// Falls back to the configured default invoice expiry if none was given.
+	fn invoice_expiry_secs(&self, expiry_secs: u32) -> u32 {
+		if expiry_secs == 0 {
+			self.config.default_invoice_expiry_secs
+		} else {
+			expiry_secs
+		}
+	}
+
+	// Falls back to the configured default final CLTV expiry delta if none was given.
+	fn min_final_cltv_expiry_delta(
+		&self, min_final_cltv_expiry_delta: Option<u16>,
+	) -> Result<u16, Error> {
+		let min_final_cltv_expiry_delta =
+			min_final_cltv_expiry_delta.unwrap_or(self.config.min_final_cltv_expiry_delta);
+		if min_final_cltv_expiry_delta < MIN_FINAL_CLTV_EXPIRY_DELTA {
+			log_error!(
+				self.logger,
+				"Failed to create invoice: min_final_cltv_expiry_delta of {} is below the minimum of {}",
+				min_final_cltv_expiry_delta,
+				MIN_FINAL_CLTV_EXPIRY_DELTA
+			);
+			return Err(Error::InvalidCltvExpiryDelta);
+		}
+		Ok(min_final_cltv_expiry_delta)
+	}
+
+	// If `manual_claim_payment_hash` is set, the invoice will commit to the given payment hash,
+	// which is only supported for invoices with a direct description.
+	fn receive_payment_inner(
+		&self, amount_msat: Option<u64>, description: InvoiceDescription, expiry_secs: u32,
+		expected_amount_msat: Option<u64>, manual_claim_payment_hash: Option<PaymentHash>,
+		route_hints: RouteHintSelection, min_final_cltv_expiry_delta: Option<u16>,
+	) -> Result<Bolt11Invoice, Error> {
+		let expiry_secs = self.invoice_expiry_secs(expiry_secs);
+		let min_final_cltv_expiry_delta =
+			self.min_final_cltv_expiry_delta(min_final_cltv_expiry_delta)?;
+		let currency = Currency::from(self.config.network);
+		let keys_manager = Arc::clone(&self.keys_manager);
+		let invoice_res = match description {
+			_ if route_hints != RouteHintSelection::Automatic => self
+				.create_invoice_with_route_hints(
+					amount_msat,
+					description,
+					expiry_secs,
+					manual_claim_payment_hash,
+					route_hints,
+					min_final_cltv_expiry_delta,
+				),
+			InvoiceDescription::Direct(description) => {
+				if let Some(payment_hash) = manual_claim_payment_hash {
+					let duration_since_epoch = SystemTime::now()
+						.duration_since(UNIX_EPOCH)
+						.map_err(|_| Error::InvoiceCreationFailed)?;
+					lightning_invoice::utils::create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash(
+						&self.channel_manager,
+						keys_manager,
+						Arc::clone(&self.logger),
+						currency,
+						amount_msat,
+						description.to_string(),
+						duration_since_epoch,
+						expiry_secs,
+						payment_hash,
+						Some(min_final_cltv_expiry_delta),
+					)
+				} else {
+					lightning_invoice::utils::create_invoice_from_channelmanager(
+						&self.channel_manager,
+						keys_manager,
+						Arc::clone(&self.logger),
+						currency,
+						amount_msat,
+						description.to_string(),
+						expiry_secs,
+						Some(min_final_cltv_expiry_delta),
+					)
+				}
+			},
+			InvoiceDescription::Hash(description_hash) => {
+				debug_assert!(manual_claim_payment_hash.is_none());
+				lightning_invoice::utils::create_invoice_from_channelmanager_with_description_hash(
+					&self.channel_manager,
+					keys_manager,
+					Arc::clone(&self.logger),
+					currency,
+					amount_msat,
+					lightning_invoice::Sha256(Sha256::from_byte_array(description_hash.0)),
+					expiry_secs,
+					Some(min_final_cltv_expiry_delta),
+				)
+			},
+		};
+		let invoice = match invoice_res {
 			Ok(inv) => {
 				log_info!(self.logger, "Invoice created: {}", inv);
 				inv
@@ -1525,12 +4161,18 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		let payment_hash = PaymentHash(invoice.payment_hash().to_byte_array());
 		let payment = PaymentDetails {
 			hash: payment_hash,
+			id: None,
 			preimage: None,
 			secret: Some(invoice.payment_secret().clone()),
 			amount_msat,
 			direction: PaymentDirection::Inbound,
 			status: PaymentStatus::Pending,
 			lsp_fee_limits: None,
+			kind: Some(PaymentKind::Bolt11),
+			description_hash: description.hash(),
+			expected_amount_msat,
+			invoice_expiry_timestamp: Some(invoice_expiry_timestamp(&invoice)),
+			custom_tlvs: Vec::new(),
 		};
 
 		self.payment_store.insert(payment)?;
@@ -1538,6 +4180,323 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		Ok(invoice)
 	}
 
+	fn create_invoice_with_route_hints(
+		&self, amount_msat: Option<u64>, description: InvoiceDescription, expiry_secs: u32,
+		manual_claim_payment_hash: Option<PaymentHash>, route_hints: RouteHintSelection,
+		min_final_cltv_expiry_delta: u16,
+	) -> Result<Bolt11Invoice, SignOrCreationError> {
+		let invalid_amount_err =
+			|()| SignOrCreationError::CreationError(CreationError::InvalidAmount);
+		let (payment_hash, payment_secret) = match manual_claim_payment_hash {
+			Some(payment_hash) => {
+				let payment_secret = self
+					.channel_manager
+					.create_inbound_payment_for_hash(
+						payment_hash,
+						amount_msat,
+						expiry_secs,
+						Some(min_final_cltv_expiry_delta),
+					)
+					.map_err(invalid_amount_err)?;
+				(payment_hash, payment_secret)
+			},
+			None => self
+				.channel_manager
+				.create_inbound_payment(amount_msat, expiry_secs, Some(min_final_cltv_expiry_delta))
+				.map_err(invalid_amount_err)?,
+		};
+
+		let usable_channels = self.channel_manager.list_usable_channels();
+		let hinted_channels = match route_hints {
+			RouteHintSelection::Automatic => {
+				debug_assert!(false, "Automatic route hints are selected by LDK");
+				Vec::new()
+			},
+			RouteHintSelection::Channels { user_channel_ids } => {
+				let mut hinted_channels: Vec<&LdkChannelDetails> = Vec::new();
+				for user_channel_id in user_channel_ids {
+					if hinted_channels.iter().any(|c| c.user_channel_id == user_channel_id.0) {
+						continue;
+					}
+					match usable_channels
+						.iter()
+						.find(|c| c.user_channel_id == user_channel_id.0 && route_hint(c).is_some())
+					{
+						Some(channel) => hinted_channels.push(channel),
+						None => {
+							log_warn!(
+								self.logger,
+								"Not including route hint for channel {} as it isn't usable.",
+								user_channel_id.0
+							);
+						},
+					}
+				}
+				hinted_channels
+			},
+			RouteHintSelection::Limit { max_hints } => {
+				let mut hinted_channels =
+					usable_channels.iter().filter(|c| route_hint(c).is_some()).collect::<Vec<_>>();
+				hinted_channels
+					.sort_unstable_by(|a, b| b.inbound_capacity_msat.cmp(&a.inbound_capacity_msat));
+				hinted_channels.truncate(max_hints as usize);
+				hinted_channels
+			},
+		};
+
+		let mut invoice_builder = InvoiceBuilder::new(Currency::from(self.config.network))
+			.payment_hash(Sha256::from_byte_array(payment_hash.0))
+			.payment_secret(payment_secret)
+			.current_timestamp()
+			.min_final_cltv_expiry_delta(min_final_cltv_expiry_delta.into())
+			.expiry_time(Duration::from_secs(expiry_secs.into()))
+			.basic_mpp();
+		if let Some(amount_msat) = amount_msat {
+			invoice_builder = invoice_builder.amount_milli_satoshis(amount_msat);
+		}
+		for hint in hinted_channels.into_iter().filter_map(route_hint) {
+			invoice_builder = invoice_builder.private_route(hint);
+		}
+		let invoice_builder = match description {
+			InvoiceDescription::Direct(description) => {
+				invoice_builder.description(description.to_string())
+			},
+			InvoiceDescription::Hash(description_hash) => {
+				invoice_builder.description_hash(Sha256::from_byte_array(description_hash.0))
+			},
+		};
+
+		let node_secret_key = self.keys_manager.get_node_secret_key();
+		invoice_builder
+			.build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &node_secret_key))
+	}
+
+	/// Redeems the given [LNURL-withdraw] link by having the service pay us `amount_msat`.
+	///
+	/// This creates an invoice for the given amount and hands it to the service, which will then
+	/// pay it. The payment is tracked like any other inbound payment, i.e., an
+	/// [`Event::PaymentReceived`] is emitted once it arrived.
+	///
+	/// Will return [`Error::InvalidAmount`] if `amount_msat` is outside of the range accepted by
+	/// the service, and [`Error::LnurlWithdrawRejected`] if the service refused to pay.
+	///
+	/// [LNURL-withdraw]: https://github.com/lnurl/luds/blob/luds/03.md
+	pub fn withdraw_via_lnurl(&self, lnurl: &str, amount_msat: u64) -> Result<PaymentHash, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		let runtime = rt_lock.as_ref().ok_or(Error::NotRunning)?;
+
+		let lnurl_client =
+			lnurl::LnurlClient::new(self.config.tor_proxy_address, Arc::clone(&self.logger))?;
+		let params = tokio::task::block_in_place(|| {
+			runtime.block_on(lnurl_client.request_withdraw_params(lnurl))
+		})?;
+
+		if amount_msat < params.min_withdrawable_msat || amount_msat > params.max_withdrawable_msat
+		{
+			log_error!(
+				self.logger,
+				"Amount of {}msat is outside of the range accepted by the LNURL service: {}-{}msat",
+				amount_msat,
+				params.min_withdrawable_msat,
+				params.max_withdrawable_msat
+			);
+			return Err(Error::InvalidAmount);
+		}
+
+		let invoice = self.receive_payment(amount_msat, &params.default_description, 0)?;
+		tokio::task::block_in_place(|| {
+			runtime.block_on(lnurl_client.submit_withdraw_invoice(&params, &invoice))
+		})?;
+
+		log_info!(self.logger, "Requested withdrawal of {}msat via LNURL", amount_msat);
+		Ok(PaymentHash(invoice.payment_hash().to_byte_array()))
+	}
+
+	/// Returns a payment handler allowing to create and parse unified [BIP 21] URIs.
+	///
+	/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+	pub fn unified_qr_payment(&self) -> UnifiedQrPayment<'_, K> {
+		UnifiedQrPayment::new(self)
+	}
+
+	/// Returns a [BOLT 12] offer that can be used to request and receive payments.
+	///
+	/// If `amount_msat` is `None` or zero, the offer won't commit to an amount, i.e., the payer is
+	/// free to choose the amount to pay. Such "any amount" offers can be told apart from
+	/// fixed-amount offers as [`Offer::amount`] will return `None`.
+	///
+	/// If set, the offer will expire at `absolute_expiry_secs`, given in seconds since the UNIX
+	/// epoch. If `max_quantity` is set, payers may request up to the given number of items, while
+	/// `0` allows for an unbounded quantity. If set, `issuer` will be displayed to the payer.
+	///
+	/// The offer's metadata is derived from our node's keys, which allows us to recognize invoice
+	/// requests for it without persisting any additional state, also across restarts.
+	///
+	/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+	pub fn create_offer(
+		&self, amount_msat: Option<u64>, description: String, absolute_expiry_secs: Option<u64>,
+		max_quantity: Option<u64>, issuer: Option<String>,
+	) -> Result<Offer, Error> {
+		let mut offer_builder = self.channel_manager.create_offer_builder(description);
+
+		if let Some(amount_msat) = amount_msat.filter(|a| *a > 0) {
+			offer_builder = offer_builder.amount_msats(amount_msat);
+		}
+
+		if let Some(absolute_expiry_secs) = absolute_expiry_secs {
+			offer_builder =
+				offer_builder.absolute_expiry(Duration::from_secs(absolute_expiry_secs));
+		}
+
+		if let Some(max_quantity) = max_quantity {
+			let quantity = match NonZeroU64::new(max_quantity) {
+				Some(max_quantity) => Quantity::Bounded(max_quantity),
+				None => Quantity::Unbounded,
+			};
+			offer_builder = offer_builder.supported_quantity(quantity);
+		}
+
+		if let Some(issuer) = issuer {
+			offer_builder = offer_builder.issuer(issuer);
+		}
+
+		let offer = offer_builder.build().map_err(|e| {
+			log_error!(self.logger, "Failed to create offer: {:?}", e);
+			Error::OfferCreationFailed
+		})?;
+
+		log_info!(self.logger, "Offer created: {}", offer);
+		Ok(offer)
+	}
+
+	/// Returns a [BOLT 12] refund for a previously received payment.
+	///
+	/// The refund may be handed to the payer of the original payment, who can then request to be
+	/// paid via [`Node::request_refund_payment`]. Once paid, the refund will be tracked in the
+	/// payment store as a payment of kind [`PaymentKind::Bolt12Refund`]. The refund will expire
+	/// after `expiry_secs`.
+	///
+	/// Will return [`Error::InvalidAmount`] if `amount_msat` is zero or exceeds the amount of the
+	/// original payment.
+	///
+	/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+	pub fn initiate_refund(
+		&self, original_payment_hash: &PaymentHash, amount_msat: u64, expiry_secs: u32,
+	) -> Result<Refund, Error> {
+		let original_payment = match self.payment_store.get(original_payment_hash) {
+			Some(payment)
+				if payment.direction == PaymentDirection::Inbound
+					&& payment.status == PaymentStatus::Succeeded =>
+			{
+				payment
+			},
+			_ => {
+				log_error!(
+					self.logger,
+					"Failed to initiate refund as no received payment with hash {} is known",
+					hex_utils::to_string(&original_payment_hash.0)
+				);
+				return Err(Error::InvalidPaymentHash);
+			},
+		};
+
+		let original_amount_msat = original_payment.amount_msat.unwrap_or(0);
+		if amount_msat == 0 || amount_msat > original_amount_msat {
+			log_error!(
+				self.logger,
+				"Failed to initiate refund of {}msat for a payment of {}msat",
+				amount_msat,
+				original_amount_msat
+			);
+			return Err(Error::InvalidAmount);
+		}
+
+		let absolute_expiry = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|now| now + Duration::from_secs(expiry_secs as u64))
+			.map_err(|_| Error::RefundCreationFailed)?;
+		let description =
+			format!("Refund for payment {}", hex_utils::to_string(&original_payment_hash.0));
+		let payment_id = PaymentId(self.keys_manager.get_secure_random_bytes());
+		let retry_strategy = Retry::Timeout(LDK_PAYMENT_RETRY_TIMEOUT);
+		let max_total_routing_fee_msat =
+			self.config.max_routing_fee_policy.map(|policy| policy.max_fee_msat(amount_msat));
+
+		let refund = self
+			.channel_manager
+			.create_refund_builder(
+				description,
+				amount_msat,
+				absolute_expiry,
+				payment_id,
+				retry_strategy,
+				max_total_routing_fee_msat,
+			)
+			.and_then(|refund_builder| refund_builder.build())
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to create refund: {:?}", e);
+				Error::RefundCreationFailed
+			})?;
+		self.payment_store.add_pending_refund(payment_id, amount_msat)?;
+
+		log_info!(self.logger, "Refund created: {}", refund);
+		Ok(refund)
+	}
+
+	/// Requests to be paid for the given [BOLT 12] refund.
+	///
+	/// This will have us send an invoice for the refund's amount to its creator. The returned
+	/// payment is tracked in the payment store as a pending inbound payment of kind
+	/// [`PaymentKind::Bolt12Refund`] until it's received.
+	///
+	/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+	pub fn request_refund_payment(&self, refund: &Refund) -> Result<PaymentDetails, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let invoice = self
+			.offers_message_handler
+			.request_refund_payment(refund)
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to request refund payment: {:?}", e);
+				Error::InvoiceCreationFailed
+			})?
+			.ok_or_else(|| {
+				log_error!(self.logger, "Failed to request refund payment: invoice not found");
+				Error::InvoiceCreationFailed
+			})?;
+
+		let payment_hash = invoice.payment_hash();
+		let invoice_expiry_timestamp = (invoice.created_at() + invoice.relative_expiry()).as_secs();
+		let payment = PaymentDetails {
+			hash: payment_hash,
+			id: None,
+			preimage: None,
+			secret: None,
+			amount_msat: Some(refund.amount_msats()),
+			direction: PaymentDirection::Inbound,
+			status: PaymentStatus::Pending,
+			lsp_fee_limits: None,
+			kind: Some(PaymentKind::Bolt12Refund),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: Some(invoice_expiry_timestamp),
+			custom_tlvs: Vec::new(),
+		};
+		self.payment_store.insert(payment.clone())?;
+
+		log_info!(
+			self.logger,
+			"Requested refund payment of {}msat from {} with payment hash {}",
+			refund.amount_msats(),
+			refund.payer_id(),
+			hex_utils::to_string(&payment_hash.0)
+		);
+		Ok(payment)
+	}
+
 	/// Returns a payable invoice that can be used to request a payment of the amount given and
 	/// receive it via a newly created just-in-time (JIT) channel.
 	///
@@ -1590,32 +4549,15 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		max_total_lsp_fee_limit_msat: Option<u64>,
 		max_proportional_lsp_fee_limit_ppm_msat: Option<u64>,
 	) -> Result<Bolt11Invoice, Error> {
+		let expiry_secs = self.invoice_expiry_secs(expiry_secs);
 		let liquidity_source =
 			self.liquidity_source.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
 
-		let (node_id, address) = liquidity_source
-			.get_liquidity_source_details()
-			.ok_or(Error::LiquiditySourceUnavailable)?;
-
 		let rt_lock = self.runtime.read().unwrap();
 		let runtime = rt_lock.as_ref().unwrap();
 
-		let peer_info = PeerInfo { node_id, address };
-
-		let con_node_id = peer_info.node_id;
-		let con_addr = peer_info.address.clone();
-		let con_logger = Arc::clone(&self.logger);
-		let con_pm = Arc::clone(&self.peer_manager);
-
-		// We need to use our main runtime here as a local runtime might not be around to poll
-		// connection futures going forward.
-		tokio::task::block_in_place(move || {
-			runtime.block_on(async move {
-				connect_peer_if_necessary(con_node_id, con_addr, con_pm, con_logger).await
-			})
-		})?;
-
-		log_info!(self.logger, "Connected to LSP {}@{}. ", peer_info.node_id, peer_info.address);
+		let peer_info =
+			self.connect_to_lsp(runtime, liquidity_source.get_liquidity_source_details())?;
 
 		let liquidity_source = Arc::clone(&liquidity_source);
 		let (invoice, lsp_total_opening_fee, lsp_prop_opening_fee) =
@@ -1647,17 +4589,105 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		// Register payment in payment store.
 		let payment_hash = PaymentHash(invoice.payment_hash().to_byte_array());
 		let lsp_fee_limits = Some(LSPFeeLimits {
-			max_total_opening_fee_msat: lsp_total_opening_fee,
-			max_proportional_opening_fee_ppm_msat: lsp_prop_opening_fee,
+			max_total_opening_fee_msat: lsp_total_opening_fee,
+			max_proportional_opening_fee_ppm_msat: lsp_prop_opening_fee,
+		});
+		let payment = PaymentDetails {
+			hash: payment_hash,
+			id: None,
+			preimage: None,
+			secret: Some(invoice.payment_secret().clone()),
+			amount_msat,
+			direction: PaymentDirection::Inbound,
+			status: PaymentStatus::Pending,
+			lsp_fee_limits,
+			kind: Some(PaymentKind::Bolt11Jit),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: Some(invoice_expiry_timestamp(&invoice)),
+			custom_tlvs: Vec::new(),
+		};
+
+		self.payment_store.insert(payment)?;
+
+		// Persist LSP peer to make sure we reconnect on restart.
+		self.peer_store.add_peer(peer_info)?;
+
+		Ok(invoice)
+	}
+
+	/// Requests a quote for receiving a payment of the amount given via a newly created
+	/// just-in-time (JIT) channel from the configured [LSPS2]-compliant LSP.
+	///
+	/// Requesting a quote doesn't commit us to anything. The returned [`JitChannelQuote`] details
+	/// the total fee the LSP would take for opening the channel. To proceed, it needs to be accepted
+	/// via [`Self::accept_jit_channel`] before it expires. Pending quotes are persisted, i.e., they
+	/// may still be accepted after a restart, or discarded via [`Self::abandon_jit_channel_quote`].
+	///
+	/// [LSPS2]: https://github.com/BitcoinAndLightningLayerSpecs/lsp/blob/main/LSPS2/README.md
+	pub fn request_jit_channel_quote(&self, amount_msat: u64) -> Result<JitChannelQuote, Error> {
+		let liquidity_source =
+			self.liquidity_source.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
+
+		let rt_lock = self.runtime.read().unwrap();
+		let runtime = rt_lock.as_ref().ok_or(Error::NotRunning)?;
+
+		self.connect_to_lsp(runtime, liquidity_source.get_liquidity_source_details())?;
+
+		let liquidity_source = Arc::clone(&liquidity_source);
+		tokio::task::block_in_place(move || {
+			runtime.block_on(async move {
+				liquidity_source.lsps2_request_jit_channel_quote(amount_msat).await
+			})
+		})
+	}
+
+	/// Accepts a quote previously retrieved via [`Self::request_jit_channel_quote`] and returns a
+	/// payable invoice for the quoted amount.
+	///
+	/// When the returned invoice is paid, the LSP will open a channel to us, taking the quoted
+	/// opening fee from the payment.
+	pub fn accept_jit_channel(
+		&self, quote: &JitChannelQuote, description: &str, expiry_secs: u32,
+	) -> Result<Bolt11Invoice, Error> {
+		let expiry_secs = self.invoice_expiry_secs(expiry_secs);
+		let liquidity_source =
+			self.liquidity_source.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
+
+		let rt_lock = self.runtime.read().unwrap();
+		let runtime = rt_lock.as_ref().ok_or(Error::NotRunning)?;
+
+		let peer_info =
+			self.connect_to_lsp(runtime, liquidity_source.get_liquidity_source_details())?;
+
+		let liquidity_source = Arc::clone(&liquidity_source);
+		let quote_id = quote.id;
+		let (invoice, quote) = tokio::task::block_in_place(move || {
+			runtime.block_on(async move {
+				liquidity_source.lsps2_accept_jit_channel(&quote_id, description, expiry_secs).await
+			})
+		})?;
+
+		// Register payment in payment store.
+		let payment_hash = PaymentHash(invoice.payment_hash().to_byte_array());
+		let lsp_fee_limits = Some(LSPFeeLimits {
+			max_total_opening_fee_msat: Some(quote.opening_fee_msat),
+			max_proportional_opening_fee_ppm_msat: None,
 		});
 		let payment = PaymentDetails {
 			hash: payment_hash,
+			id: None,
 			preimage: None,
 			secret: Some(invoice.payment_secret().clone()),
-			amount_msat,
+			amount_msat: Some(quote.amount_msat),
 			direction: PaymentDirection::Inbound,
 			status: PaymentStatus::Pending,
 			lsp_fee_limits,
+			kind: Some(PaymentKind::Bolt11Jit),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: Some(invoice_expiry_timestamp(&invoice)),
+			custom_tlvs: Vec::new(),
 		};
 
 		self.payment_store.insert(payment)?;
@@ -1668,6 +4698,125 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		Ok(invoice)
 	}
 
+	/// Discards a pending quote previously retrieved via [`Self::request_jit_channel_quote`].
+	pub fn abandon_jit_channel_quote(&self, quote_id: &JitChannelQuoteId) -> Result<(), Error> {
+		let liquidity_source =
+			self.liquidity_source.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
+		liquidity_source.lsps2_abandon_jit_channel_quote(quote_id)
+	}
+
+	/// Retrieves all quotes retrieved via [`Self::request_jit_channel_quote`] that were neither
+	/// accepted nor abandoned yet.
+	pub fn list_jit_channel_quotes(&self) -> Vec<JitChannelQuote> {
+		self.liquidity_source
+			.as_ref()
+			.map(|l| l.lsps2_pending_jit_channel_quotes())
+			.unwrap_or_default()
+	}
+
+	/// Places an order to buy a channel from the configured [LSPS1]-compliant LSP.
+	///
+	/// The LSP will open a channel to us with `lsp_balance_sat` on its side and
+	/// `client_balance_sat` on ours, and promises to keep it open for at least
+	/// `channel_expiry_blocks`. The returned [`ChannelOrder`] details the on-chain address and
+	/// the invoice either of which may be paid to satisfy the order. Use
+	/// [`Self::check_order_status`] to poll the order until the channel is opened, after which it
+	/// will show up in [`Self::list_channels`] like any other channel.
+	///
+	/// [LSPS1]: https://github.com/BitcoinAndLightningLayerSpecs/lsp/blob/main/LSPS1/README.md
+	#[cfg(lsps1)]
+	pub fn request_channel_order(
+		&self, lsp_balance_sat: u64, client_balance_sat: u64, channel_expiry_blocks: u32,
+		announce_channel: bool,
+	) -> Result<ChannelOrder, Error> {
+		let liquidity_source =
+			self.liquidity_source.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
+
+		let rt_lock = self.runtime.read().unwrap();
+		let runtime = rt_lock.as_ref().ok_or(Error::NotRunning)?;
+
+		let peer_info =
+			self.connect_to_lsp(runtime, liquidity_source.get_lsps1_service_details())?;
+
+		let liquidity_source = Arc::clone(&liquidity_source);
+		let order = tokio::task::block_in_place(move || {
+			runtime.block_on(async move {
+				liquidity_source
+					.lsps1_request_channel_order(
+						lsp_balance_sat,
+						client_balance_sat,
+						channel_expiry_blocks,
+						announce_channel,
+					)
+					.await
+			})
+		})?;
+
+		// Persist LSP peer to make sure we reconnect on restart.
+		self.peer_store.add_peer(peer_info)?;
+
+		Ok(order)
+	}
+
+	/// Retrieves the current status of an order previously placed via
+	/// [`Self::request_channel_order`].
+	///
+	/// Will return [`Error::OrderExpired`] if the order is still awaiting payment but its payment
+	/// deadline passed already. Orders the LSP refunded are reported with
+	/// [`OrderPaymentState::Refunded`].
+	#[cfg(lsps1)]
+	pub fn check_order_status(&self, order_id: &OrderId) -> Result<ChannelOrder, Error> {
+		let liquidity_source =
+			self.liquidity_source.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
+
+		let rt_lock = self.runtime.read().unwrap();
+		let runtime = rt_lock.as_ref().ok_or(Error::NotRunning)?;
+
+		self.connect_to_lsp(runtime, liquidity_source.get_lsps1_service_details())?;
+
+		let liquidity_source = Arc::clone(&liquidity_source);
+		tokio::task::block_in_place(move || {
+			runtime
+				.block_on(async move { liquidity_source.lsps1_check_order_status(order_id).await })
+		})
+	}
+
+	fn connect_to_lsp(
+		&self, runtime: &tokio::runtime::Runtime, lsp_details: Option<(PublicKey, SocketAddress)>,
+	) -> Result<PeerInfo, Error> {
+		let (node_id, address) = lsp_details.ok_or(Error::LiquiditySourceUnavailable)?;
+
+		let transport = PeerTransport::Tcp;
+		let peer_info = PeerInfo { node_id, address, transport, is_persistent: false };
+
+		let con_node_id = peer_info.node_id;
+		let con_addr = peer_info.address.clone();
+		let con_logger = Arc::clone(&self.logger);
+		let con_pm = Arc::clone(&self.peer_manager);
+		let con_tor_proxy = self.config.tor_proxy_address;
+		let con_peer_monitor = Arc::clone(&self.peer_monitor);
+
+		// We need to use our main runtime here as a local runtime might not be around to poll
+		// connection futures going forward.
+		tokio::task::block_in_place(move || {
+			runtime.block_on(async move {
+				connect_peer_if_necessary(
+					con_node_id,
+					con_addr,
+					transport,
+					con_tor_proxy,
+					con_pm,
+					con_peer_monitor,
+					con_logger,
+				)
+				.await
+			})
+		})?;
+
+		log_info!(self.logger, "Connected to LSP {}@{}. ", peer_info.node_id, peer_info.address);
+		Ok(peer_info)
+	}
+
 	/// Retrieve the details of a specific payment with the given hash.
 	///
 	/// Returns `Some` if the payment was known and `None` otherwise.
@@ -1675,11 +4824,95 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		self.payment_store.get(payment_hash)
 	}
 
+	/// Retrieve the details of a specific payment with the given [`PaymentId`].
+	///
+	/// Only outbound payments are tracked by their id, see [`PaymentDetails::id`].
+	///
+	/// Returns `Some` if the payment was known and `None` otherwise.
+	pub fn payment_by_id(&self, payment_id: &PaymentId) -> Option<PaymentDetails> {
+		self.payment_store.get_by_id(payment_id)
+	}
+
+	/// Blocks until the payment with the given [`PaymentId`] reaches a final [`PaymentStatus`],
+	/// returning the final status.
+	///
+	/// Will return [`Error::PaymentNotFound`] if the payment is unknown and
+	/// [`Error::PaymentTimedOut`] if it didn't reach a final status within the given `timeout`.
+	/// Note that the payment may still complete after the timeout elapsed.
+	pub fn await_payment_final(
+		&self, payment_id: &PaymentId, timeout: Duration,
+	) -> Result<PaymentStatus, Error> {
+		match self.payment_store.wait_for_final_status(payment_id, timeout) {
+			Some(Ok(status)) => Ok(status),
+			Some(Err(status)) => {
+				log_info!(
+					self.logger,
+					"Payment with id {} is still {:?} after waiting for {}s",
+					hex_utils::to_string(&payment_id.0),
+					status,
+					timeout.as_secs()
+				);
+				Err(Error::PaymentTimedOut)
+			},
+			None => Err(Error::PaymentNotFound),
+		}
+	}
+
 	/// Remove the payment with the given hash from the store.
 	pub fn remove_payment(&self, payment_hash: &PaymentHash) -> Result<(), Error> {
 		self.payment_store.remove(&payment_hash)
 	}
 
+	/// Abandons the pending outbound payment with the given hash, cancelling any further attempts
+	/// to send it.
+	///
+	/// An [`Event::PaymentFailed`] will be emitted once any in-flight attempt has been abandoned.
+	/// Note that HTLCs already sent may still succeed, in which case we'll emit an
+	/// [`Event::PaymentSuccessful`] instead.
+	pub fn abandon_payment(&self, payment_hash: &PaymentHash) -> Result<(), Error> {
+		let payment_id = match self.payment_store.get(payment_hash) {
+			Some(payment)
+				if payment.direction == PaymentDirection::Outbound
+					&& payment.status == PaymentStatus::Pending =>
+			{
+				// Payments persisted before we recorded their id were always sent with an id
+				// derived from their payment hash.
+				payment.id.unwrap_or(PaymentId(payment_hash.0))
+			},
+			_ => {
+				log_error!(
+					self.logger,
+					"Failed to abandon payment with hash {}: not a pending outbound payment.",
+					hex_utils::to_string(&payment_hash.0)
+				);
+				return Err(Error::PaymentNotPending);
+			},
+		};
+
+		self.payment_retrier.abandon(payment_hash, payment_id);
+		log_info!(
+			self.logger,
+			"Abandoned payment with hash {}",
+			hex_utils::to_string(&payment_hash.0)
+		);
+		Ok(())
+	}
+
+	/// Returns the on-chain balance we can actually spend, in satoshis.
+	///
+	/// This is the sum of our confirmed UTXOs and unconfirmed change outputs, excluding any UTXOs
+	/// which are uneconomical to spend as spending them at our current on-chain fee rate estimate
+	/// would cost at least their value, minus the
+	/// [`BalanceDetails::total_anchor_channels_reserve_sats`] we retain to be able to bump the
+	/// fees of Anchor channel closing transactions. In contrast to
+	/// [`BalanceDetails::spendable_onchain_balance_sats`] it hence doesn't include dust.
+	pub fn spendable_onchain_balance_sats(&self) -> Result<u64, Error> {
+		let spendable_sats = self.wallet.get_spendable_balance_excluding_dust()?;
+		let anchor_reserve_sats =
+			total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
+		Ok(spendable_sats.saturating_sub(anchor_reserve_sats))
+	}
+
 	/// Retrieves an overview of all known balances.
 	pub fn list_balances(&self) -> BalanceDetails {
 		let (total_onchain_balance_sats, spendable_onchain_balance_sats) = self
@@ -1688,6 +4921,11 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 			.map(|bal| (bal.get_total(), bal.get_spendable()))
 			.unwrap_or((0, 0));
 
+		let total_anchor_channels_reserve_sats =
+			total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
+		let spendable_onchain_balance_sats =
+			spendable_onchain_balance_sats.saturating_sub(total_anchor_channels_reserve_sats);
+
 		let mut total_lightning_balance_sats = 0;
 		let mut lightning_balances = Vec::new();
 		for funding_txo in self.chain_monitor.list_monitors() {
@@ -1723,6 +4961,7 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		BalanceDetails {
 			total_onchain_balance_sats,
 			spendable_onchain_balance_sats,
+			total_anchor_channels_reserve_sats,
 			total_lightning_balance_sats,
 			lightning_balances,
 			pending_balances_from_channel_closures,
@@ -1753,6 +4992,35 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		self.payment_store.list_filter(|_| true)
 	}
 
+	/// Retrieves all payments with the given [`PaymentDirection`].
+	pub fn list_payments_by_direction(&self, direction: PaymentDirection) -> Vec<PaymentDetails> {
+		self.payment_store.list_filter(|p| p.direction == direction)
+	}
+
+	/// Retrieves all payments with the given [`PaymentStatus`].
+	pub fn list_payments_by_status(&self, status: PaymentStatus) -> Vec<PaymentDetails> {
+		self.payment_store.list_filter(|p| p.status == status)
+	}
+
+	/// Retrieves up to `limit` payments that match the given predicate, skipping the first
+	/// `offset` matches.
+	///
+	/// Payments are ordered by their payment hash, which allows to page through a large number of
+	/// payments without retrieving all of them at once.
+	pub fn list_payments_with_filter_paginated<F: FnMut(&&PaymentDetails) -> bool>(
+		&self, f: F, offset: u32, limit: u32,
+	) -> Vec<PaymentDetails> {
+		self.payment_store.list_filter_paginated(f, offset as usize, limit as usize)
+	}
+
+	/// Retrieves up to `limit` payments, skipping the first `offset` ones.
+	///
+	/// Payments are ordered by their payment hash, which allows to page through a large number of
+	/// payments without retrieving all of them at once.
+	pub fn list_payments_paginated(&self, offset: u32, limit: u32) -> Vec<PaymentDetails> {
+		self.payment_store.list_filter_paginated(|_| true, offset as usize, limit as usize)
+	}
+
 	/// Retrieves a list of known peers.
 	pub fn list_peers(&self) -> Vec<PeerDetails> {
 		let mut peers = Vec::new();
@@ -1771,7 +5039,8 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 
 			let is_persisted = stored_peer.is_some();
 			let is_connected = true;
-			let details = PeerDetails { node_id, address, is_persisted, is_connected };
+			let transport = stored_peer.map(|p| p.transport).unwrap_or_default();
+			let details = PeerDetails { node_id, address, is_persisted, is_connected, transport };
 			peers.push(details);
 		}
 
@@ -1786,6 +5055,7 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 				address: p.address,
 				is_persisted: true,
 				is_connected: false,
+				transport: p.transport,
 			};
 
 			peers.push(details);
@@ -1794,6 +5064,314 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 		peers
 	}
 
+	/// Returns statistics about the current state of the network graph.
+	pub fn network_graph_stats(&self) -> NetworkGraphStats {
+		(&self.network_graph.read_only()).into()
+	}
+
+	/// Returns a snapshot of metrics describing the current state of the node.
+	///
+	/// The metrics are derived from live node state and may be used for monitoring purposes.
+	pub fn metrics_snapshot(&self) -> Metrics {
+		let channels = self.channel_manager.list_channels();
+		let balances = self.list_balances();
+		let num_pending_htlcs = balances
+			.lightning_balances
+			.iter()
+			.filter(|b| {
+				matches!(
+					b,
+					LightningBalance::MaybeTimeoutClaimableHTLC { .. }
+						| LightningBalance::MaybePreimageClaimableHTLC { .. }
+				)
+			})
+			.count() as u64;
+
+		let mut metrics = Metrics {
+			num_channels: channels.len() as u64,
+			num_usable_channels: channels.iter().filter(|c| c.is_usable).count() as u64,
+			num_pending_htlcs,
+			num_connected_peers: self.peer_manager.get_peer_node_ids().len() as u64,
+			total_onchain_balance_sats: balances.total_onchain_balance_sats,
+			spendable_onchain_balance_sats: balances.spendable_onchain_balance_sats,
+			total_lightning_balance_sats: balances.total_lightning_balance_sats,
+			latest_rgs_snapshot_timestamp: *self.latest_rgs_snapshot_timestamp.read().unwrap(),
+			event_queue_depth: self.event_queue.depth() as u64,
+			..Default::default()
+		};
+
+		for payment in self.payment_store.list_filter(|_| true) {
+			match (payment.direction, payment.status) {
+				(PaymentDirection::Outbound, PaymentStatus::Succeeded) => {
+					metrics.num_payments_sent += 1
+				},
+				(PaymentDirection::Inbound, PaymentStatus::Succeeded) => {
+					metrics.num_payments_received += 1
+				},
+				(_, PaymentStatus::Failed) => metrics.num_payments_failed += 1,
+				(_, PaymentStatus::Pending) => metrics.num_payments_pending += 1,
+				(_, PaymentStatus::Expired) => {},
+			}
+		}
+
+		let graph_stats = self.network_graph_stats();
+		metrics.network_graph_node_count = graph_stats.node_count;
+		metrics.network_graph_channel_count = graph_stats.channel_count;
+		metrics
+	}
+
+	/// Returns information about the channel with the given short channel ID from the network
+	/// graph, if known.
+	pub fn lookup_channel(&self, short_channel_id: u64) -> Option<ChannelInfo> {
+		self.network_graph.read_only().channel(short_channel_id).map(|c| c.into())
+	}
+
+	/// Returns information about the node with the given node ID from the network graph, if
+	/// known.
+	pub fn lookup_node(&self, node_id: &PublicKey) -> Option<NodeInfo> {
+		self.network_graph.read_only().node(&NodeId::from_pubkey(node_id)).map(|n| n.into())
+	}
+
+	/// Exports the serialized state of the scorer, i.e., what the node learned about the liquidity
+	/// available in the network's channels.
+	///
+	/// The result can be given to [`Node::import_scorer`] to seed another node.
+	pub fn export_scorer(&self) -> Vec<u8> {
+		self.scorer.lock().unwrap().encode()
+	}
+
+	/// Replaces the state of the scorer with the given serialized scorer and persists it.
+	///
+	/// The scorer data is expected to have been exported via [`Node::export_scorer`]. Will return
+	/// [`Error::InvalidScorer`] if the given data can't be deserialized against our current
+	/// network graph, and [`Error::ScorerGraphMismatch`] if it holds data but none of it is about
+	/// the channels in our network graph. The graph should therefore be synced before a scorer is
+	/// imported.
+	pub fn import_scorer(&self, scorer_bytes: &[u8]) -> Result<(), Error> {
+		let scorer = io::utils::read_imported_scorer(
+			scorer_bytes,
+			self.scoring_decay_params,
+			Arc::clone(&self.network_graph),
+			Arc::clone(&self.logger),
+		)?;
+
+		let mut locked_scorer = self.scorer.lock().unwrap();
+		self.kv_store
+			.write(
+				SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
+				SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+				SCORER_PERSISTENCE_KEY,
+				scorer_bytes,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Writing data to key {}/{}/{} failed due to: {}",
+					SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
+					SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+					SCORER_PERSISTENCE_KEY,
+					e
+				);
+				Error::PersistenceFailed
+			})?;
+		*locked_scorer = scorer;
+		log_info!(self.logger, "Imported scorer.");
+		Ok(())
+	}
+
+	/// Avoids routing any payments through the channel with the given short channel id for the
+	/// next `duration_secs` seconds.
+	///
+	/// This allows to blacklist channels observed to fail payments out-of-band. The penalty is
+	/// persisted and therefore survives restarts until it expires. A duration of zero lifts any
+	/// existing penalty of the channel.
+	pub fn penalize_channel(&self, short_channel_id: u64, duration_secs: u64) -> Result<(), Error> {
+		let now_secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+		let mut locked_penalties = self.channel_penalties.write().unwrap();
+		if duration_secs == 0 {
+			if locked_penalties.contains_key(&short_channel_id) {
+				io::utils::remove_channel_penalty(
+					short_channel_id,
+					Arc::clone(&self.kv_store),
+					Arc::clone(&self.logger),
+				)?;
+				locked_penalties.remove(&short_channel_id);
+			}
+		} else {
+			let expiry_secs = now_secs.saturating_add(duration_secs);
+			io::utils::write_channel_penalty(
+				short_channel_id,
+				expiry_secs,
+				Arc::clone(&self.kv_store),
+				Arc::clone(&self.logger),
+			)?;
+			locked_penalties.insert(short_channel_id, expiry_secs);
+		}
+		log_info!(
+			self.logger,
+			"Penalized channel {} for {} seconds.",
+			short_channel_id,
+			duration_secs
+		);
+		Ok(())
+	}
+
+	/// Applies the given Rapid Gossip Sync (RGS) snapshot to the network graph.
+	///
+	/// This allows to feed snapshots retrieved from another source rather than fetching them from
+	/// the configured RGS server. On success, the persisted RGS sync timestamp is updated and the
+	/// new timestamp is returned.
+	///
+	/// Will return [`Error::RgsSnapshotOutdated`] if the snapshot is older than the latest
+	/// applied one, and [`Error::GossipUpdateFailed`] if the node is not configured to source its
+	/// gossip data via RGS.
+	pub fn apply_rgs_snapshot(&self, snapshot: &[u8]) -> Result<u32, Error> {
+		if !self.gossip_source.is_rgs() {
+			log_error!(self.logger, "Failed to apply RGS snapshot as RGS is not configured");
+			return Err(Error::GossipUpdateFailed);
+		}
+
+		let updated_timestamp = self.gossip_source.apply_rgs_snapshot(snapshot)?;
+		io::utils::write_latest_rgs_sync_timestamp(
+			updated_timestamp,
+			Arc::clone(&self.kv_store),
+			Arc::clone(&self.logger),
+		)?;
+		*self.latest_rgs_snapshot_timestamp.write().unwrap() = Some(updated_timestamp as u64);
+		log_info!(self.logger, "Applied RGS snapshot with timestamp {}", updated_timestamp);
+		Ok(updated_timestamp)
+	}
+
+	/// Checks that all data persisted by `ldk-node` can be deserialized.
+	///
+	/// Returns a report listing all corrupt entries. If `repair` is set, corrupt entries are
+	/// quarantined so that they won't prevent the node from starting. Repairing is only possible
+	/// while the node is stopped.
+	///
+	/// See [`io::verify_store`] for more details, which can also be used directly on a
+	/// [`KVStore`] prior to building a [`Node`].
+	pub fn verify_store(&self, repair: bool) -> Result<StoreVerificationReport, Error> {
+		if repair && self.runtime.read().unwrap().is_some() {
+			return Err(Error::AlreadyRunning);
+		}
+
+		io::verify_store(Arc::clone(&self.kv_store), repair, Arc::clone(&self.logger)).map_err(
+			|e| {
+				log_error!(self.logger, "Failed to verify store: {}", e);
+				Error::PersistenceFailed
+			},
+		)
+	}
+
+	/// Exports a static channel backup of our channels.
+	///
+	/// The backup contains the state of our channel monitors and the peers we have channels with,
+	/// encrypted under a key derived from our node's secret key. It is therefore safe to store it
+	/// remotely. It can be imported into a fresh node initialized from the same seed via
+	/// [`Builder::recover_from_channel_backup`].
+	///
+	/// **Note:** Static channel backups are meant for disaster recovery only, not for live
+	/// replication. On recovery, we will reconnect to our former channel counterparties and
+	/// request them to force-close the channels, which allows us to claim our funds on-chain.
+	/// Continuing to operate channels from a restored backup is not possible.
+	///
+	/// **Caution:** A backup only reflects the channel state at the time it was exported and
+	/// becomes outdated as soon as any further payments are sent or received over the channels.
+	/// Broadcasting the commitment transaction of an outdated channel state would allow our
+	/// counterparty to claim the full channel balance, so a restored node never closes the
+	/// channels itself. Recovering funds therefore relies on our counterparties being reachable and
+	/// force-closing the channels upon our request. Also note that a backup only covers the
+	/// channels that existed when it was exported, so it should be exported again after opening
+	/// new channels.
+	pub fn export_channel_backup(&self) -> Result<Vec<u8>, Error> {
+		let backup = ChannelBackup::from_store(
+			&*self.kv_store,
+			self.peer_store.list_peers(),
+			Arc::clone(&self.logger),
+		)
+		.map_err(|e| {
+			log_error!(self.logger, "Failed to create channel backup: {}", e);
+			Error::ChannelBackupFailed
+		})?;
+
+		backup.encrypt(&self.keys_manager.get_node_secret_key(), &mut rand::thread_rng()).map_err(
+			|()| {
+				log_error!(self.logger, "Failed to encrypt channel backup.");
+				Error::ChannelBackupFailed
+			},
+		)
+	}
+
+	/// Exports a snapshot of the node's full persisted state to the given writer.
+	///
+	/// The snapshot includes all data held in the node's [`KVStore`], such as the channel
+	/// manager, channel monitors, payments, and peers, and can be imported into a fresh node via
+	/// [`Builder::restore_from_snapshot`]. Note that the snapshot is not encrypted.
+	///
+	/// **Caution:** The snapshot is only consistent with the channel state at the time of
+	/// export. Restoring an outdated snapshot may lead to loss of funds, so it shouldn't be
+	/// used as a substitute for a replicated [`KVStore`].
+	pub fn export_snapshot<W: std::io::Write>(&self, mut writer: W) -> Result<(), Error> {
+		io::utils::write_store_snapshot(&*self.kv_store, &mut writer, Arc::clone(&self.logger))
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to export store snapshot: {}", e);
+				Error::SnapshotExportFailed
+			})
+	}
+
+	/// Sends an onion message of the given custom TLV type carrying the given data to the node
+	/// with the given node id.
+	///
+	/// The message is queued and routed to the destination by LDK's onion messenger, which, if
+	/// necessary, tries to connect to the destination if it announced its addresses. Upon
+	/// receiving the message, an LDK Node recipient will emit an [`Event::OnionMessageReceived`].
+	///
+	/// The TLV type must be in the range of custom onion message types configured via
+	/// [`Config::custom_onion_message_tlv_type_min`] and
+	/// [`Config::custom_onion_message_tlv_type_max`], which by default includes all types of at
+	/// least `2^16`. Will return [`Error::InvalidOnionMessageType`] otherwise.
+	pub fn send_onion_message(
+		&self, destination: PublicKey, tlv_type: u64, data: Vec<u8>,
+	) -> Result<(), Error> {
+		self.enqueue_onion_message(Destination::Node(destination), tlv_type, data)?;
+		log_info!(self.logger, "Queued onion message of type {} to {}.", tlv_type, destination);
+		Ok(())
+	}
+
+	/// Sends an onion message of the given custom TLV type carrying the given data via the
+	/// given reply path, as given by the sender of an [`Event::OnionMessageReceived`].
+	///
+	/// See [`Node::send_onion_message`] for the range of TLV types allowed.
+	pub fn reply_to_onion_message(
+		&self, reply_path: BlindedPath, tlv_type: u64, data: Vec<u8>,
+	) -> Result<(), Error> {
+		self.enqueue_onion_message(Destination::BlindedPath(reply_path), tlv_type, data)?;
+		log_info!(self.logger, "Queued onion message of type {} via reply path.", tlv_type);
+		Ok(())
+	}
+
+	fn enqueue_onion_message(
+		&self, destination: Destination, tlv_type: u64, data: Vec<u8>,
+	) -> Result<(), Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		if !self.custom_onion_message_handler.accepts_tlv_type(tlv_type) {
+			log_error!(
+				self.logger,
+				"Failed to send onion message as type {} is not in the custom range.",
+				tlv_type
+			);
+			return Err(Error::InvalidOnionMessageType);
+		}
+
+		self.custom_onion_message_handler.enqueue_message(destination, tlv_type, data);
+		self.peer_manager.process_events();
+		Ok(())
+	}
+
 	/// Creates a digital ECDSA signature of a message with the node's secret key.
 	///
 	/// A receiver knowing the corresponding `PublicKey` (e.g. the node’s id) and the message
@@ -1811,7 +5389,7 @@ impl<K: KVStore + Sync + Send + 'static> Node<K> {
 	}
 }
 
-impl<K: KVStore + Sync + Send + 'static> Drop for Node<K> {
+impl<K: BatchKVStore + Sync + Send + 'static> Drop for Node<K> {
 	fn drop(&mut self) {
 		let _ = self.stop();
 	}
@@ -1854,8 +5432,114 @@ pub struct NodeStatus {
 	pub latest_node_announcement_broadcast_timestamp: Option<u64>,
 }
 
+/// The outcome of a sync triggered via [`Node::sync_now`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyncReport {
+	/// The number of blocks our view of the chain advanced by during the sync.
+	pub blocks_processed: u32,
+	/// The height of the chain tip we synced to.
+	pub tip_height: u32,
+}
+
+// A handle to a background task spawned on start-up.
+enum BackgroundTask {
+	Thread(std::thread::JoinHandle<()>),
+	Task(tokio::task::JoinHandle<()>),
+}
+
+impl BackgroundTask {
+	fn is_finished(&self) -> bool {
+		match self {
+			Self::Thread(handle) => handle.is_finished(),
+			Self::Task(handle) => handle.is_finished(),
+		}
+	}
+}
+
+/// Returns the route hint allowing to reach us via the given channel, if the counterparty's
+/// forwarding parameters are known.
+fn route_hint(channel: &LdkChannelDetails) -> Option<RouteHint> {
+	let forwarding_info = channel.counterparty.forwarding_info.as_ref()?;
+	Some(RouteHint(vec![RouteHintHop {
+		src_node_id: channel.counterparty.node_id,
+		short_channel_id: channel.get_inbound_payment_scid()?,
+		fees: RoutingFees {
+			base_msat: forwarding_info.fee_base_msat,
+			proportional_millionths: forwarding_info.fee_proportional_millionths,
+		},
+		cltv_expiry_delta: forwarding_info.cltv_expiry_delta,
+		htlc_minimum_msat: channel.inbound_htlc_minimum_msat,
+		htlc_maximum_msat: channel.inbound_htlc_maximum_msat,
+	}]))
+}
+
+/// The description a BOLT11 invoice commits to.
+#[derive(Clone, Copy)]
+enum InvoiceDescription<'a> {
+	Direct(&'a str),
+	Hash(DescriptionHash),
+}
+
+impl<'a> InvoiceDescription<'a> {
+	fn hash(&self) -> Option<DescriptionHash> {
+		match self {
+			Self::Direct(_) => None,
+			Self::Hash(description_hash) => Some(*description_hash),
+		}
+	}
+}
+
+/// Returns the zero-padded alias bytes used in node announcements, or `None` if the alias
+/// exceeds 32 bytes when UTF-8 encoded.
+pub(crate) fn node_alias_bytes(node_alias: &str) -> Option<[u8; 32]> {
+	let alias_bytes = node_alias.as_bytes();
+	if alias_bytes.len() > 32 {
+		return None;
+	}
+
+	let mut bytes = [0u8; 32];
+	bytes[..alias_bytes.len()].copy_from_slice(alias_bytes);
+	Some(bytes)
+}
+
+pub(crate) fn total_anchor_channels_reserve_sats<K: KVStore + Sync + Send + 'static>(
+	channel_manager: &ChannelManager<K>, config: &Config,
+) -> u64 {
+	config.anchor_channels_config.as_ref().map_or(0, |anchor_channels_config| {
+		channel_manager
+			.list_channels()
+			.into_iter()
+			.filter(|c| {
+				!anchor_channels_config.trusted_peers_no_reserve.contains(&c.counterparty.node_id)
+					&& c.channel_type
+						.as_ref()
+						.map_or(false, |t| t.requires_anchors_zero_fee_htlc_tx())
+			})
+			.count() as u64
+			* anchor_channels_config.per_channel_reserve_sats
+	})
+}
+
+fn invoice_expiry_timestamp(invoice: &Bolt11Invoice) -> u64 {
+	invoice.duration_since_epoch().saturating_add(invoice.expiry_time()).as_secs()
+}
+
+fn persist_fee_rate_cache<K: KVStore + Sync + Send + 'static>(
+	fee_estimator: &FeeEstimator, update_timestamp: Option<u64>, kv_store: Arc<K>,
+	logger: &Arc<FilesystemLogger>,
+) {
+	let fee_rate_cache = fee_estimator::PersistedFeeRateCache {
+		update_timestamp: update_timestamp.unwrap_or(0),
+		fee_rates: fee_estimator.cached_fee_rates(),
+	};
+	// Failing to persist isn't critical, as we'd just start out with older estimates next time.
+	let _ = io::utils::write_fee_rate_cache(&fee_rate_cache, kv_store, Arc::clone(logger));
+}
+
 async fn connect_peer_if_necessary<K: KVStore + Sync + Send + 'static>(
-	node_id: PublicKey, addr: SocketAddress, peer_manager: Arc<PeerManager<K>>,
+	node_id: PublicKey, addr: SocketAddress, transport: PeerTransport,
+	tor_proxy: Option<SocketAddr>, peer_manager: Arc<PeerManager<K>>,
+	peer_monitor: Arc<PeerConnectionMonitor<K, Arc<FilesystemLogger>>>,
 	logger: Arc<FilesystemLogger>,
 ) -> Result<(), Error> {
 	for (pman_node_id, _pman_addr) in peer_manager.get_peer_node_ids() {
@@ -1864,33 +5548,81 @@ async fn connect_peer_if_necessary<K: KVStore + Sync + Send + 'static>(
 		}
 	}
 
-	do_connect_peer(node_id, addr, peer_manager, logger).await
+	do_connect_peer(node_id, addr, transport, tor_proxy, peer_manager, peer_monitor, logger).await
 }
 
 async fn do_connect_peer<K: KVStore + Sync + Send + 'static>(
-	node_id: PublicKey, addr: SocketAddress, peer_manager: Arc<PeerManager<K>>,
+	node_id: PublicKey, addr: SocketAddress, transport: PeerTransport,
+	tor_proxy: Option<SocketAddr>, peer_manager: Arc<PeerManager<K>>,
+	peer_monitor: Arc<PeerConnectionMonitor<K, Arc<FilesystemLogger>>>,
 	logger: Arc<FilesystemLogger>,
 ) -> Result<(), Error> {
-	log_info!(logger, "Connecting to peer: {}@{}", node_id, addr);
+	log_info!(logger, "Connecting to peer: {}@{} via {:?}", node_id, addr, transport);
 
-	let socket_addr = addr
-		.to_socket_addrs()
-		.map_err(|e| {
-			log_error!(logger, "Failed to resolve network address: {}", e);
-			Error::InvalidSocketAddress
-		})?
-		.next()
-		.ok_or(Error::ConnectionFailed)?;
+	let connection_closed_future: Option<Pin<Box<dyn Future<Output = ()> + Send>>> = match transport
+	{
+		PeerTransport::Tcp if tor::requires_tor(&addr) => {
+			let proxy_addr = tor_proxy.ok_or_else(|| {
+				log_error!(
+					logger,
+					"Failed to connect to peer {}: no Tor proxy configured to reach {}",
+					node_id,
+					addr
+				);
+				Error::TorProxyNotConfigured
+			})?;
+
+			tor::connect_outbound(
+				Arc::clone(&peer_manager),
+				node_id,
+				proxy_addr,
+				&addr,
+				Arc::clone(&logger),
+			)
+			.await
+			.map(|f| Box::pin(f) as Pin<Box<dyn Future<Output = ()> + Send>>)
+		},
+		PeerTransport::Tcp => {
+			let socket_addr = addr
+				.to_socket_addrs()
+				.map_err(|e| {
+					log_error!(logger, "Failed to resolve network address: {}", e);
+					Error::InvalidSocketAddress
+				})?
+				.next()
+				.ok_or(Error::ConnectionFailed)?;
 
-	match lightning_net_tokio::connect_outbound(Arc::clone(&peer_manager), node_id, socket_addr)
+			lightning_net_tokio::connect_outbound(Arc::clone(&peer_manager), node_id, socket_addr)
+				.await
+				.map(|f| Box::pin(f) as Pin<Box<dyn Future<Output = ()> + Send>>)
+		},
+		#[cfg(feature = "websocket")]
+		PeerTransport::WebSocket => websocket::connect_outbound(
+			Arc::clone(&peer_manager),
+			node_id,
+			&addr,
+			Arc::clone(&logger),
+		)
 		.await
-	{
-		Some(connection_closed_future) => {
-			let mut connection_closed_future = Box::pin(connection_closed_future);
+		.map(|f| Box::pin(f) as Pin<Box<dyn Future<Output = ()> + Send>>),
+		#[cfg(not(feature = "websocket"))]
+		PeerTransport::WebSocket => {
+			log_error!(
+				logger,
+				"Failed to connect to peer {}: WebSocket transport support is not enabled.",
+				node_id
+			);
+			return Err(Error::ConnectionFailed);
+		},
+	};
+
+	match connection_closed_future {
+		Some(mut connection_closed_future) => {
 			loop {
 				match futures::poll!(&mut connection_closed_future) {
 					std::task::Poll::Ready(_) => {
 						log_info!(logger, "Peer connection closed: {}@{}", node_id, addr);
+						peer_monitor.record_handshake_failure(node_id);
 						return Err(Error::ConnectionFailed);
 					},
 					std::task::Poll::Pending => {},
@@ -1,11 +1,24 @@
+#[cfg(lsps1)]
+pub(crate) mod lsps1;
+
+use crate::hex_utils;
+use crate::io::{
+	JIT_CHANNEL_QUOTE_PERSISTENCE_PRIMARY_NAMESPACE,
+	JIT_CHANNEL_QUOTE_PERSISTENCE_SECONDARY_NAMESPACE,
+};
 use crate::logger::{log_debug, log_error, log_info, Logger};
-use crate::types::{ChannelManager, KeysManager, LiquidityManager, PeerManager};
+use crate::types::{
+	ChannelManager, JitChannelQuote, JitChannelQuoteId, KeysManager, LiquidityManager, PeerManager,
+};
 use crate::{Config, Error};
 
+use lightning::impl_writeable_tlv_based;
 use lightning::ln::channelmanager::MIN_FINAL_CLTV_EXPIRY_DELTA;
 use lightning::ln::msgs::SocketAddress;
 use lightning::routing::router::{RouteHint, RouteHintHop};
+use lightning::sign::EntropySource;
 use lightning::util::persist::KVStore;
+use lightning::util::ser::Writeable;
 use lightning_invoice::{Bolt11Invoice, InvoiceBuilder, RoutingFees};
 use lightning_liquidity::events::Event;
 use lightning_liquidity::lsps0::ser::RequestId;
@@ -16,6 +29,8 @@ use lightning_liquidity::lsps2::utils::compute_opening_fee;
 use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::{PublicKey, Secp256k1};
 
+use chrono::{TimeZone, Utc};
+
 use tokio::sync::oneshot;
 
 use std::collections::HashMap;
@@ -31,6 +46,7 @@ struct LSPS2Service {
 	token: Option<String>,
 	pending_fee_requests: Mutex<HashMap<RequestId, oneshot::Sender<LSPS2FeeResponse>>>,
 	pending_buy_requests: Mutex<HashMap<RequestId, oneshot::Sender<LSPS2BuyResponse>>>,
+	pending_quotes: Mutex<HashMap<JitChannelQuoteId, PendingJitChannelQuote>>,
 }
 
 pub(crate) struct LiquiditySource<K: KVStore + Sync + Send + 'static, L: Deref>
@@ -38,9 +54,12 @@ where
 	L::Target: Logger,
 {
 	lsps2_service: Option<LSPS2Service>,
+	#[cfg(lsps1)]
+	lsps1_service: Option<lsps1::LSPS1Service>,
 	channel_manager: Arc<ChannelManager<K>>,
 	keys_manager: Arc<KeysManager>,
 	liquidity_manager: Arc<LiquidityManager<K>>,
+	kv_store: Arc<K>,
 	config: Arc<Config>,
 	logger: L,
 }
@@ -49,21 +68,45 @@ impl<K: KVStore + Sync + Send, L: Deref> LiquiditySource<K, L>
 where
 	L::Target: Logger,
 {
-	pub(crate) fn new_lsps2(
-		address: SocketAddress, node_id: PublicKey, token: Option<String>,
-		channel_manager: Arc<ChannelManager<K>>, keys_manager: Arc<KeysManager>,
-		liquidity_manager: Arc<LiquidityManager<K>>, config: Arc<Config>, logger: L,
+	pub(crate) fn new(
+		lsps2_service: Option<(SocketAddress, PublicKey, Option<String>)>,
+		pending_quotes: Vec<PendingJitChannelQuote>, channel_manager: Arc<ChannelManager<K>>,
+		keys_manager: Arc<KeysManager>, liquidity_manager: Arc<LiquidityManager<K>>,
+		kv_store: Arc<K>, config: Arc<Config>, logger: L,
 	) -> Self {
-		let pending_fee_requests = Mutex::new(HashMap::new());
-		let pending_buy_requests = Mutex::new(HashMap::new());
-		let lsps2_service = Some(LSPS2Service {
-			address,
-			node_id,
-			token,
-			pending_fee_requests,
-			pending_buy_requests,
+		let lsps2_service = lsps2_service.map(|(address, node_id, token)| {
+			let pending_fee_requests = Mutex::new(HashMap::new());
+			let pending_buy_requests = Mutex::new(HashMap::new());
+			let pending_quotes =
+				Mutex::new(pending_quotes.into_iter().map(|q| (q.quote.id, q)).collect());
+			LSPS2Service {
+				address,
+				node_id,
+				token,
+				pending_fee_requests,
+				pending_buy_requests,
+				pending_quotes,
+			}
 		});
-		Self { lsps2_service, channel_manager, keys_manager, liquidity_manager, config, logger }
+		Self {
+			lsps2_service,
+			#[cfg(lsps1)]
+			lsps1_service: None,
+			channel_manager,
+			keys_manager,
+			liquidity_manager,
+			kv_store,
+			config,
+			logger,
+		}
+	}
+
+	#[cfg(lsps1)]
+	pub(crate) fn with_lsps1_service(
+		mut self, address: SocketAddress, node_id: PublicKey, token: Option<String>,
+	) -> Self {
+		self.lsps1_service = Some(lsps1::LSPS1Service::new(address, node_id, token));
+		self
 	}
 
 	pub(crate) fn set_peer_manager(&self, peer_manager: Arc<PeerManager<K>>) {
@@ -183,6 +226,8 @@ where
 					);
 				}
 			},
+			#[cfg(lsps1)]
+			Event::LSPS1Client(event) => self.handle_lsps1_event(event),
 			e => {
 				log_error!(self.logger, "Received unexpected liquidity event: {:?}", e);
 			},
@@ -195,30 +240,8 @@ where
 	) -> Result<(Bolt11Invoice, u64), Error> {
 		let fee_response = self.lsps2_request_opening_fee_params().await?;
 
-		let (min_total_fee_msat, min_opening_params) = fee_response
-			.opening_fee_params_menu
-			.into_iter()
-			.filter_map(|params| {
-				if amount_msat < params.min_payment_size_msat
-					|| amount_msat > params.max_payment_size_msat
-				{
-					log_debug!(self.logger,
-						"Skipping LSP-offered JIT parameters as the payment of {}msat doesn't meet LSP limits (min: {}msat, max: {}msat)",
-						amount_msat,
-						params.min_payment_size_msat,
-						params.max_payment_size_msat
-					);
-					None
-				} else {
-					compute_opening_fee(amount_msat, params.min_fee_msat, params.proportional as u64)
-						.map(|fee| (fee, params))
-				}
-			})
-			.min_by_key(|p| p.0)
-			.ok_or_else(|| {
-				log_error!(self.logger, "Failed to handle response from liquidity service",);
-				Error::LiquidityRequestFailed
-			})?;
+		let (min_total_fee_msat, min_opening_params) =
+			self.select_cheapest_opening_params(amount_msat, fee_response)?;
 
 		if let Some(max_total_lsp_fee_limit_msat) = max_total_lsp_fee_limit_msat {
 			if min_total_fee_msat > max_total_lsp_fee_limit_msat {
@@ -292,6 +315,181 @@ where
 		Ok((invoice, min_prop_fee_ppm_msat))
 	}
 
+	pub(crate) async fn lsps2_request_jit_channel_quote(
+		&self, amount_msat: u64,
+	) -> Result<JitChannelQuote, Error> {
+		let lsps2_service = self.lsps2_service.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
+
+		let fee_response = self.lsps2_request_opening_fee_params().await?;
+		let (opening_fee_msat, opening_fee_params) =
+			self.select_cheapest_opening_params(amount_msat, fee_response)?;
+
+		let quote = JitChannelQuote {
+			id: JitChannelQuoteId(self.keys_manager.get_secure_random_bytes()),
+			lsp_node_id: lsps2_service.node_id,
+			amount_msat,
+			opening_fee_msat,
+			valid_until_secs: opening_fee_params.valid_until.timestamp().max(0) as u64,
+			min_lifetime: opening_fee_params.min_lifetime,
+			max_client_to_self_delay: opening_fee_params.max_client_to_self_delay,
+		};
+		let pending_quote = PendingJitChannelQuote::new(quote.clone(), opening_fee_params);
+
+		self.persist_quote(&pending_quote)?;
+		lsps2_service.pending_quotes.lock().unwrap().insert(quote.id, pending_quote);
+
+		log_info!(
+			self.logger,
+			"Received JIT-channel quote {} for {}msat, will pay {}msat in total LSP fees if accepted",
+			hex_utils::to_string(&quote.id.0),
+			amount_msat,
+			opening_fee_msat
+		);
+		Ok(quote)
+	}
+
+	pub(crate) async fn lsps2_accept_jit_channel(
+		&self, quote_id: &JitChannelQuoteId, description: &str, expiry_secs: u32,
+	) -> Result<(Bolt11Invoice, JitChannelQuote), Error> {
+		let lsps2_service = self.lsps2_service.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
+
+		let pending_quote = lsps2_service
+			.pending_quotes
+			.lock()
+			.unwrap()
+			.get(quote_id)
+			.cloned()
+			.ok_or(Error::InvalidJitChannelQuote)?;
+
+		if pending_quote.quote.lsp_node_id != lsps2_service.node_id {
+			log_error!(
+				self.logger,
+				"Failed to accept JIT-channel quote as it was issued by LSP {}, which is not configured anymore",
+				pending_quote.quote.lsp_node_id
+			);
+			return Err(Error::InvalidJitChannelQuote);
+		}
+
+		let opening_fee_params =
+			pending_quote.opening_fee_params().ok_or(Error::InvalidJitChannelQuote)?;
+		if opening_fee_params.valid_until <= Utc::now() {
+			log_error!(
+				self.logger,
+				"Failed to accept JIT-channel quote {} as it expired",
+				hex_utils::to_string(&quote_id.0)
+			);
+			return Err(Error::JitChannelQuoteExpired);
+		}
+
+		let amount_msat = pending_quote.quote.amount_msat;
+		let buy_response =
+			self.lsps2_send_buy_request(Some(amount_msat), opening_fee_params).await?;
+		let invoice = self.lsps2_create_jit_invoice(
+			buy_response,
+			Some(amount_msat),
+			description,
+			expiry_secs,
+		)?;
+
+		self.lsps2_abandon_jit_channel_quote(quote_id)?;
+
+		log_info!(self.logger, "JIT-channel invoice created: {}", invoice);
+		Ok((invoice, pending_quote.quote))
+	}
+
+	pub(crate) fn lsps2_abandon_jit_channel_quote(
+		&self, quote_id: &JitChannelQuoteId,
+	) -> Result<(), Error> {
+		let lsps2_service = self.lsps2_service.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
+
+		let mut pending_quotes_lock = lsps2_service.pending_quotes.lock().unwrap();
+		if !pending_quotes_lock.contains_key(quote_id) {
+			return Err(Error::InvalidJitChannelQuote);
+		}
+
+		let store_key = hex_utils::to_string(&quote_id.0);
+		self.kv_store
+			.remove(
+				JIT_CHANNEL_QUOTE_PERSISTENCE_PRIMARY_NAMESPACE,
+				JIT_CHANNEL_QUOTE_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				false,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Removing JIT-channel quote {}/{}/{} failed due to: {}",
+					JIT_CHANNEL_QUOTE_PERSISTENCE_PRIMARY_NAMESPACE,
+					JIT_CHANNEL_QUOTE_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})?;
+
+		pending_quotes_lock.remove(quote_id);
+		Ok(())
+	}
+
+	pub(crate) fn lsps2_pending_jit_channel_quotes(&self) -> Vec<JitChannelQuote> {
+		self.lsps2_service
+			.as_ref()
+			.map(|s| s.pending_quotes.lock().unwrap().values().map(|q| q.quote.clone()).collect())
+			.unwrap_or_default()
+	}
+
+	fn persist_quote(&self, pending_quote: &PendingJitChannelQuote) -> Result<(), Error> {
+		let store_key = hex_utils::to_string(&pending_quote.quote.id.0);
+		let data = pending_quote.encode();
+		self.kv_store
+			.write(
+				JIT_CHANNEL_QUOTE_PERSISTENCE_PRIMARY_NAMESPACE,
+				JIT_CHANNEL_QUOTE_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				&data,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					JIT_CHANNEL_QUOTE_PERSISTENCE_PRIMARY_NAMESPACE,
+					JIT_CHANNEL_QUOTE_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})
+	}
+
+	fn select_cheapest_opening_params(
+		&self, amount_msat: u64, fee_response: LSPS2FeeResponse,
+	) -> Result<(u64, OpeningFeeParams), Error> {
+		fee_response
+			.opening_fee_params_menu
+			.into_iter()
+			.filter_map(|params| {
+				if amount_msat < params.min_payment_size_msat
+					|| amount_msat > params.max_payment_size_msat
+				{
+					log_debug!(self.logger,
+						"Skipping LSP-offered JIT parameters as the payment of {}msat doesn't meet LSP limits (min: {}msat, max: {}msat)",
+						amount_msat,
+						params.min_payment_size_msat,
+						params.max_payment_size_msat
+					);
+					None
+				} else {
+					compute_opening_fee(amount_msat, params.min_fee_msat, params.proportional as u64)
+						.map(|fee| (fee, params))
+				}
+			})
+			.min_by_key(|p| p.0)
+			.ok_or_else(|| {
+				log_error!(self.logger, "Failed to handle response from liquidity service",);
+				Error::LiquidityRequestFailed
+			})
+	}
+
 	async fn lsps2_request_opening_fee_params(&self) -> Result<LSPS2FeeResponse, Error> {
 		let lsps2_service = self.lsps2_service.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
 
@@ -373,7 +571,11 @@ where
 		let lsps2_service = self.lsps2_service.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
 
 		// LSPS2 requires min_final_cltv_expiry_delta to be at least 2 more than usual.
-		let min_final_cltv_expiry_delta = MIN_FINAL_CLTV_EXPIRY_DELTA + 2;
+		let min_final_cltv_expiry_delta = self
+			.config
+			.min_final_cltv_expiry_delta
+			.max(MIN_FINAL_CLTV_EXPIRY_DELTA)
+			.saturating_add(2);
 		let (payment_hash, payment_secret) = self
 			.channel_manager
 			.create_inbound_payment(None, expiry_secs, Some(min_final_cltv_expiry_delta))
@@ -432,3 +634,58 @@ pub(crate) struct LSPS2BuyResponse {
 	intercept_scid: u64,
 	cltv_expiry_delta: u32,
 }
+
+/// A [`JitChannelQuote`] along with the LSP's opening fee parameters it is based on, as persisted
+/// until the quote is accepted or abandoned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PendingJitChannelQuote {
+	pub(crate) quote: JitChannelQuote,
+	min_fee_msat: u64,
+	proportional: u32,
+	valid_until_secs: u64,
+	valid_until_nanos: u32,
+	min_payment_size_msat: u64,
+	max_payment_size_msat: u64,
+	promise: String,
+}
+
+impl PendingJitChannelQuote {
+	fn new(quote: JitChannelQuote, params: OpeningFeeParams) -> Self {
+		Self {
+			quote,
+			min_fee_msat: params.min_fee_msat,
+			proportional: params.proportional,
+			valid_until_secs: params.valid_until.timestamp().max(0) as u64,
+			valid_until_nanos: params.valid_until.timestamp_subsec_nanos(),
+			min_payment_size_msat: params.min_payment_size_msat,
+			max_payment_size_msat: params.max_payment_size_msat,
+			promise: params.promise,
+		}
+	}
+
+	fn opening_fee_params(&self) -> Option<OpeningFeeParams> {
+		let valid_until =
+			Utc.timestamp_opt(self.valid_until_secs as i64, self.valid_until_nanos).single()?;
+		Some(OpeningFeeParams {
+			min_fee_msat: self.min_fee_msat,
+			proportional: self.proportional,
+			valid_until,
+			min_lifetime: self.quote.min_lifetime,
+			max_client_to_self_delay: self.quote.max_client_to_self_delay,
+			min_payment_size_msat: self.min_payment_size_msat,
+			max_payment_size_msat: self.max_payment_size_msat,
+			promise: self.promise.clone(),
+		})
+	}
+}
+
+impl_writeable_tlv_based!(PendingJitChannelQuote, {
+	(0, quote, required),
+	(2, min_fee_msat, required),
+	(4, proportional, required),
+	(6, valid_until_secs, required),
+	(8, valid_until_nanos, required),
+	(10, min_payment_size_msat, required),
+	(12, max_payment_size_msat, required),
+	(14, promise, required),
+});
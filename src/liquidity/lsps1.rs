@@ -0,0 +1,377 @@
+//! Objects related to buying channels from an [LSPS1]-compliant LSP.
+//!
+//! [LSPS1]: https://github.com/BitcoinAndLightningLayerSpecs/lsp/blob/main/LSPS1/README.md
+
+use super::{LiquiditySource, LIQUIDITY_REQUEST_TIMEOUT_SECS};
+use crate::logger::{log_error, log_info, Logger};
+use crate::Error;
+
+use lightning::ln::msgs::SocketAddress;
+use lightning::util::persist::KVStore;
+use lightning_invoice::Bolt11Invoice;
+use lightning_liquidity::lsps0::ser::RequestId;
+use lightning_liquidity::lsps1::event::LSPS1ClientEvent;
+use lightning_liquidity::lsps1::msgs::{
+	ChannelInfo, OrderId as LdkOrderId, OrderParams, OrderPayment, PaymentState,
+};
+
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Address, OutPoint};
+
+use tokio::sync::oneshot;
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The number of blocks we require the LSP to fund the channel within.
+const LSPS1_FUNDING_CONFIRMS_WITHIN_BLOCKS: u16 = 6;
+
+/// The number of confirmations we require before the purchased channel is considered usable.
+const LSPS1_REQUIRED_CHANNEL_CONFIRMATIONS: u16 = 0;
+
+pub(crate) struct LSPS1Service {
+	address: SocketAddress,
+	node_id: PublicKey,
+	token: Option<String>,
+	pending_create_order_requests: Mutex<HashMap<RequestId, oneshot::Sender<LSPS1OrderStatus>>>,
+	pending_check_order_status_requests:
+		Mutex<HashMap<RequestId, oneshot::Sender<LSPS1OrderStatus>>>,
+}
+
+impl LSPS1Service {
+	pub(crate) fn new(address: SocketAddress, node_id: PublicKey, token: Option<String>) -> Self {
+		let pending_create_order_requests = Mutex::new(HashMap::new());
+		let pending_check_order_status_requests = Mutex::new(HashMap::new());
+		Self {
+			address,
+			node_id,
+			token,
+			pending_create_order_requests,
+			pending_check_order_status_requests,
+		}
+	}
+
+	fn order_params(
+		&self, lsp_balance_sat: u64, client_balance_sat: u64, channel_expiry_blocks: u32,
+		announce_channel: bool,
+	) -> OrderParams {
+		OrderParams {
+			lsp_balance_sat,
+			client_balance_sat,
+			required_channel_confirmations: LSPS1_REQUIRED_CHANNEL_CONFIRMATIONS,
+			funding_confirms_within_blocks: LSPS1_FUNDING_CONFIRMS_WITHIN_BLOCKS,
+			channel_expiry_blocks,
+			token: self.token.clone(),
+			announce_channel,
+		}
+	}
+}
+
+/// The identifier of a channel order placed with an [LSPS1]-compliant LSP.
+///
+/// [LSPS1]: https://github.com/BitcoinAndLightningLayerSpecs/lsp/blob/main/LSPS1/README.md
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OrderId(pub String);
+
+/// The state of the payment of a channel order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OrderPaymentState {
+	/// The LSP is waiting for the order to be paid.
+	ExpectPayment,
+	/// The payment was received and is held until the channel is opened.
+	Hold,
+	/// The payment was received and the channel is opened.
+	Paid,
+	/// The order failed and the payment, if any, was refunded.
+	Refunded,
+}
+
+/// The details of the channel opened to satisfy a channel order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderChannelInfo {
+	/// The funding transaction output of the channel.
+	pub funding_outpoint: OutPoint,
+	/// The time the channel was funded at, as seconds since the UNIX epoch.
+	pub funded_at_secs: u64,
+	/// The time until which the LSP promises to keep the channel open, as seconds since the UNIX
+	/// epoch.
+	pub expires_at_secs: u64,
+}
+
+/// A channel order placed with an [LSPS1]-compliant LSP, as returned by
+/// [`Node::request_channel_order`] and [`Node::check_order_status`].
+///
+/// The order is satisfied by paying `order_total_sat` either via `bolt11_invoice` or to the
+/// `onchain_address`. Once paid, the LSP will open the channel, which then shows up in
+/// [`Node::list_channels`] like any other channel.
+///
+/// [LSPS1]: https://github.com/BitcoinAndLightningLayerSpecs/lsp/blob/main/LSPS1/README.md
+/// [`Node::request_channel_order`]: crate::Node::request_channel_order
+/// [`Node::check_order_status`]: crate::Node::check_order_status
+/// [`Node::list_channels`]: crate::Node::list_channels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelOrder {
+	/// The identifier of the order.
+	pub order_id: OrderId,
+	/// The node id of the LSP the order was placed with.
+	pub lsp_node_id: PublicKey,
+	/// The balance on the LSP's side of the channel, in satoshis.
+	pub lsp_balance_sat: u64,
+	/// The balance on our side of the channel, in satoshis.
+	pub client_balance_sat: u64,
+	/// The number of blocks the LSP promises to keep the channel open for.
+	pub channel_expiry_blocks: u32,
+	/// The fee the LSP charges for opening the channel, in satoshis.
+	pub fee_total_sat: u64,
+	/// The total amount to be paid to the LSP, i.e., the fee plus `client_balance_sat`, in
+	/// satoshis.
+	pub order_total_sat: u64,
+	/// The invoice that may be paid to satisfy the order.
+	pub bolt11_invoice: Bolt11Invoice,
+	/// The on-chain address that may be paid to satisfy the order.
+	pub onchain_address: Address,
+	/// The state of the order's payment.
+	pub payment_state: OrderPaymentState,
+	/// The details of the opened channel, if the LSP opened it already.
+	pub channel: Option<OrderChannelInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct LSPS1OrderStatus {
+	order_id: LdkOrderId,
+	order_params: OrderParams,
+	payment: OrderPayment,
+	channel: Option<ChannelInfo>,
+}
+
+impl<K: KVStore + Sync + Send, L: Deref> LiquiditySource<K, L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn get_lsps1_service_details(&self) -> Option<(PublicKey, SocketAddress)> {
+		self.lsps1_service.as_ref().map(|s| (s.node_id, s.address.clone()))
+	}
+
+	pub(super) fn handle_lsps1_event(&self, event: LSPS1ClientEvent) {
+		let (request_id, counterparty_node_id, status, is_create_order) = match event {
+			LSPS1ClientEvent::OrderCreated {
+				request_id,
+				counterparty_node_id,
+				order_id,
+				order,
+				payment,
+				channel,
+			} => {
+				let status = LSPS1OrderStatus { order_id, order_params: order, payment, channel };
+				(request_id, counterparty_node_id, status, true)
+			},
+			LSPS1ClientEvent::OrderStatus {
+				request_id,
+				counterparty_node_id,
+				order_id,
+				order,
+				payment,
+				channel,
+			} => {
+				let status = LSPS1OrderStatus { order_id, order_params: order, payment, channel };
+				(request_id, counterparty_node_id, status, false)
+			},
+			e => {
+				log_error!(self.logger, "Received unexpected LSPS1 event: {:?}", e);
+				return;
+			},
+		};
+
+		let lsps1_service = if let Some(lsps1_service) = self.lsps1_service.as_ref() {
+			lsps1_service
+		} else {
+			log_error!(self.logger, "Received unexpected LSPS1Client event!");
+			return;
+		};
+
+		if counterparty_node_id != lsps1_service.node_id {
+			debug_assert!(
+				false,
+				"Received response from unexpected LSP counterparty. This should never happen."
+			);
+			log_error!(
+				self.logger,
+				"Received response from unexpected LSP counterparty. This should never happen."
+			);
+			return;
+		}
+
+		let sender = if is_create_order {
+			lsps1_service.pending_create_order_requests.lock().unwrap().remove(&request_id)
+		} else {
+			lsps1_service.pending_check_order_status_requests.lock().unwrap().remove(&request_id)
+		};
+
+		if let Some(sender) = sender {
+			if let Err(e) = sender.send(status) {
+				log_error!(
+					self.logger,
+					"Failed to handle response from liquidity service: {:?}",
+					e
+				);
+			}
+		} else {
+			debug_assert!(false, "Received response from liquidity service for unknown request.");
+			log_error!(
+				self.logger,
+				"Received response from liquidity service for unknown request."
+			);
+		}
+	}
+
+	pub(crate) async fn lsps1_request_channel_order(
+		&self, lsp_balance_sat: u64, client_balance_sat: u64, channel_expiry_blocks: u32,
+		announce_channel: bool,
+	) -> Result<ChannelOrder, Error> {
+		let lsps1_service = self.lsps1_service.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
+
+		let client_handler = self.liquidity_manager.lsps1_client_handler().ok_or_else(|| {
+			log_error!(self.logger, "LSPS1 liquidity client was not configured.",);
+			Error::LiquiditySourceUnavailable
+		})?;
+
+		let order_params = lsps1_service.order_params(
+			lsp_balance_sat,
+			client_balance_sat,
+			channel_expiry_blocks,
+			announce_channel,
+		);
+
+		let (order_sender, order_receiver) = oneshot::channel();
+		{
+			let mut pending_create_order_requests_lock =
+				lsps1_service.pending_create_order_requests.lock().unwrap();
+			let request_id =
+				client_handler.create_order(&lsps1_service.node_id, order_params, None);
+			pending_create_order_requests_lock.insert(request_id, order_sender);
+		}
+
+		let status = self.await_lsps1_response(order_receiver).await?;
+		let order = self.channel_order_from_status(status)?;
+
+		log_info!(
+			self.logger,
+			"Placed LSPS1 channel order {} with LSP {}, need to pay {}sat in total",
+			order.order_id.0,
+			order.lsp_node_id,
+			order.order_total_sat
+		);
+		Ok(order)
+	}
+
+	pub(crate) async fn lsps1_check_order_status(
+		&self, order_id: &OrderId,
+	) -> Result<ChannelOrder, Error> {
+		let lsps1_service = self.lsps1_service.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
+
+		let client_handler = self.liquidity_manager.lsps1_client_handler().ok_or_else(|| {
+			log_error!(self.logger, "LSPS1 liquidity client was not configured.",);
+			Error::LiquiditySourceUnavailable
+		})?;
+
+		let (status_sender, status_receiver) = oneshot::channel();
+		{
+			let mut pending_check_order_status_requests_lock =
+				lsps1_service.pending_check_order_status_requests.lock().unwrap();
+			let request_id = client_handler
+				.check_order_status(&lsps1_service.node_id, LdkOrderId(order_id.0.clone()));
+			pending_check_order_status_requests_lock.insert(request_id, status_sender);
+		}
+
+		let status = self.await_lsps1_response(status_receiver).await?;
+		self.channel_order_from_status(status)
+	}
+
+	async fn await_lsps1_response(
+		&self, receiver: oneshot::Receiver<LSPS1OrderStatus>,
+	) -> Result<LSPS1OrderStatus, Error> {
+		tokio::time::timeout(Duration::from_secs(LIQUIDITY_REQUEST_TIMEOUT_SECS), receiver)
+			.await
+			.map_err(|e| {
+				log_error!(self.logger, "Liquidity request timed out: {}", e);
+				Error::LiquidityRequestFailed
+			})?
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to handle response from liquidity service: {}", e);
+				Error::LiquidityRequestFailed
+			})
+	}
+
+	fn channel_order_from_status(&self, status: LSPS1OrderStatus) -> Result<ChannelOrder, Error> {
+		let lsps1_service = self.lsps1_service.as_ref().ok_or(Error::LiquiditySourceUnavailable)?;
+
+		let payment_state = match status.payment.state {
+			PaymentState::ExpectPayment => OrderPaymentState::ExpectPayment,
+			PaymentState::Hold => OrderPaymentState::Hold,
+			PaymentState::Paid => OrderPaymentState::Paid,
+			PaymentState::Refunded => OrderPaymentState::Refunded,
+		};
+
+		// The order needs to be paid before its payment expires, which is when the order's
+		// invoice expires. Note that refunded orders are reported as such, as the LSP may also
+		// refund an order for other reasons, e.g., if it failed to open the channel.
+		if payment_state == OrderPaymentState::ExpectPayment
+			&& status.payment.bolt11_invoice.is_expired()
+		{
+			log_error!(
+				self.logger,
+				"LSPS1 channel order {} expired before it was paid",
+				status.order_id.0
+			);
+			return Err(Error::OrderExpired);
+		}
+
+		let channel = status.channel.map(|c| OrderChannelInfo {
+			funding_outpoint: c.funding_outpoint,
+			funded_at_secs: c.funded_at.timestamp().max(0) as u64,
+			expires_at_secs: c.expires_at.timestamp().max(0) as u64,
+		});
+
+		Ok(ChannelOrder {
+			order_id: OrderId(status.order_id.0),
+			lsp_node_id: lsps1_service.node_id,
+			lsp_balance_sat: status.order_params.lsp_balance_sat,
+			client_balance_sat: status.order_params.client_balance_sat,
+			channel_expiry_blocks: status.order_params.channel_expiry_blocks,
+			fee_total_sat: status.payment.fee_total_sat,
+			order_total_sat: status.payment.order_total_sat,
+			bolt11_invoice: status.payment.bolt11_invoice,
+			onchain_address: status.payment.onchain_address,
+			payment_state,
+			channel,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::str::FromStr;
+
+	#[test]
+	fn order_params_are_built_from_request() {
+		let address = SocketAddress::from_str("127.0.0.1:9735").unwrap();
+		let node_id = PublicKey::from_str(
+			"0276607124ebe6a6c9338517b6f485825b27c2dcc0b9fc2aa6a4c0df91194e5993",
+		)
+		.unwrap();
+		let service = LSPS1Service::new(address, node_id, Some("token".to_string()));
+
+		let params = service.order_params(100_000, 20_000, 13_000, true);
+		assert_eq!(params.lsp_balance_sat, 100_000);
+		assert_eq!(params.client_balance_sat, 20_000);
+		assert_eq!(params.channel_expiry_blocks, 13_000);
+		assert_eq!(params.required_channel_confirmations, LSPS1_REQUIRED_CHANNEL_CONFIRMATIONS);
+		assert_eq!(params.funding_confirms_within_blocks, LSPS1_FUNDING_CONFIRMS_WITHIN_BLOCKS);
+		assert_eq!(params.token, Some("token".to_string()));
+		assert!(params.announce_channel);
+	}
+}
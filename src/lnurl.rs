@@ -0,0 +1,386 @@
+//! Objects and methods for paying to [LNURL-pay] and [Lightning Address] targets, and for
+//! redeeming [LNURL-withdraw] links.
+//!
+//! [LNURL-pay]: https://github.com/lnurl/luds/blob/luds/06.md
+//! [LNURL-withdraw]: https://github.com/lnurl/luds/blob/luds/03.md
+//! [Lightning Address]: https://github.com/lnurl/luds/blob/luds/16.md
+
+use crate::logger::{log_error, FilesystemLogger, Logger};
+use crate::unified_qr::percent_encode;
+use crate::Error;
+
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+
+use bitcoin::bech32::{self, FromBase32};
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::Hash;
+
+use serde_json::Value;
+
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+const LNURL_HRP: &str = "lnurl";
+
+// The timeout we apply to any requests made to LNURL services.
+const LNURL_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Resolves the given LNURL target to the URL that needs to be queried.
+///
+/// Supports bech32-encoded LNURLs, Lightning Addresses, `lnurlp://` and `lnurlw://` URIs, and
+/// plain URLs.
+pub(crate) fn resolve_url(target: &str) -> Result<String, Error> {
+	let target = target.trim();
+	let target = target.strip_prefix("lightning:").unwrap_or(target);
+
+	if target.len() > LNURL_HRP.len()
+		&& target[..LNURL_HRP.len()].eq_ignore_ascii_case(LNURL_HRP)
+		&& !target.contains("://")
+	{
+		let (hrp, data, _) = bech32::decode(target).map_err(|_| Error::InvalidUri)?;
+		if hrp != LNURL_HRP {
+			return Err(Error::InvalidUri);
+		}
+		let bytes = Vec::<u8>::from_base32(&data).map_err(|_| Error::InvalidUri)?;
+		return String::from_utf8(bytes).map_err(|_| Error::InvalidUri);
+	}
+
+	if let Some(rest) = target.strip_prefix("lnurlp://").or(target.strip_prefix("lnurlw://")) {
+		return Ok(format!("{}://{}", scheme_for_host(rest), rest));
+	}
+
+	if target.starts_with("https://") || target.starts_with("http://") {
+		return Ok(target.to_string());
+	}
+
+	let (user, domain) = target.split_once('@').ok_or(Error::InvalidUri)?;
+	let is_valid_user =
+		!user.is_empty() && user.chars().all(|c| c.is_ascii_alphanumeric() || "-_.+".contains(c));
+	if !is_valid_user || domain.is_empty() || domain.contains('/') {
+		return Err(Error::InvalidUri);
+	}
+	let user = user.to_ascii_lowercase();
+	let domain = domain.to_ascii_lowercase();
+	Ok(format!("{}://{}/.well-known/lnurlp/{}", scheme_for_host(&domain), domain, user))
+}
+
+// Onion services are reached via plain HTTP as recommended by LUD-01.
+fn scheme_for_host(host: &str) -> &'static str {
+	let host = host.split(|c| c == '/' || c == ':').next().unwrap_or(host);
+	if host.ends_with(".onion") {
+		"http"
+	} else {
+		"https"
+	}
+}
+
+/// The parameters of an LNURL-pay service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PayParams {
+	pub callback: String,
+	pub min_sendable_msat: u64,
+	pub max_sendable_msat: u64,
+	pub metadata: String,
+	pub comment_allowed: u64,
+}
+
+impl PayParams {
+	pub(crate) fn from_json(json: &Value) -> Result<Self, Error> {
+		check_status(json)?;
+		if json["tag"].as_str() != Some("payRequest") {
+			return Err(Error::LnurlRequestFailed);
+		}
+
+		let callback = json["callback"].as_str().ok_or(Error::LnurlRequestFailed)?.to_string();
+		let min_sendable_msat = json["minSendable"].as_u64().ok_or(Error::LnurlRequestFailed)?;
+		let max_sendable_msat = json["maxSendable"].as_u64().ok_or(Error::LnurlRequestFailed)?;
+		let metadata = json["metadata"].as_str().ok_or(Error::LnurlRequestFailed)?.to_string();
+		let comment_allowed = json["commentAllowed"].as_u64().unwrap_or(0);
+
+		if min_sendable_msat > max_sendable_msat {
+			return Err(Error::LnurlRequestFailed);
+		}
+
+		Ok(Self { callback, min_sendable_msat, max_sendable_msat, metadata, comment_allowed })
+	}
+
+	/// Checks that the given invoice pays the requested amount and commits to our metadata.
+	pub(crate) fn validate_invoice(
+		&self, invoice: &Bolt11Invoice, amount_msat: u64,
+	) -> Result<(), Error> {
+		if invoice.amount_milli_satoshis() != Some(amount_msat) {
+			return Err(Error::InvalidInvoice);
+		}
+
+		let metadata_hash = Sha256::hash(self.metadata.as_bytes());
+		match invoice.description() {
+			Bolt11InvoiceDescription::Hash(hash) if hash.0 == metadata_hash => Ok(()),
+			_ => Err(Error::InvalidInvoice),
+		}
+	}
+}
+
+/// The parameters of an LNURL-withdraw service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WithdrawParams {
+	pub callback: String,
+	pub k1: String,
+	pub default_description: String,
+	pub min_withdrawable_msat: u64,
+	pub max_withdrawable_msat: u64,
+}
+
+impl WithdrawParams {
+	pub(crate) fn from_json(json: &Value) -> Result<Self, Error> {
+		check_status(json)?;
+		if json["tag"].as_str() != Some("withdrawRequest") {
+			return Err(Error::LnurlRequestFailed);
+		}
+
+		let callback = json["callback"].as_str().ok_or(Error::LnurlRequestFailed)?.to_string();
+		let k1 = json["k1"].as_str().ok_or(Error::LnurlRequestFailed)?.to_string();
+		let default_description = json["defaultDescription"].as_str().unwrap_or("").to_string();
+		let min_withdrawable_msat = json["minWithdrawable"].as_u64().unwrap_or(0);
+		let max_withdrawable_msat =
+			json["maxWithdrawable"].as_u64().ok_or(Error::LnurlRequestFailed)?;
+
+		if min_withdrawable_msat > max_withdrawable_msat {
+			return Err(Error::LnurlRequestFailed);
+		}
+
+		Ok(Self { callback, k1, default_description, min_withdrawable_msat, max_withdrawable_msat })
+	}
+}
+
+// LNURL services signal errors via a `status` field rather than HTTP status codes.
+fn check_status(json: &Value) -> Result<(), Error> {
+	match json["status"].as_str() {
+		Some(status) if status.eq_ignore_ascii_case("ERROR") => Err(Error::LnurlRequestFailed),
+		_ => Ok(()),
+	}
+}
+
+fn append_query(url: &str, query: &str) -> String {
+	let separator = if url.contains('?') { '&' } else { '?' };
+	format!("{}{}{}", url, separator, query)
+}
+
+/// A client for LNURL services.
+///
+/// If a Tor proxy is configured, all requests are made through it.
+pub(crate) struct LnurlClient {
+	client: reqwest::Client,
+	logger: Arc<FilesystemLogger>,
+}
+
+impl LnurlClient {
+	pub(crate) fn new(
+		tor_proxy: Option<SocketAddr>, logger: Arc<FilesystemLogger>,
+	) -> Result<Self, Error> {
+		let mut builder = reqwest::Client::builder().timeout(LNURL_REQUEST_TIMEOUT);
+		if let Some(proxy_addr) = tor_proxy {
+			// Use `socks5h` so that host names are resolved by the proxy.
+			let proxy = reqwest::Proxy::all(format!("socks5h://{}", proxy_addr)).map_err(|e| {
+				log_error!(logger, "Failed to set up LNURL proxy: {}", e);
+				Error::LnurlRequestFailed
+			})?;
+			builder = builder.proxy(proxy);
+		}
+		let client = builder.build().map_err(|e| {
+			log_error!(logger, "Failed to set up LNURL client: {}", e);
+			Error::LnurlRequestFailed
+		})?;
+		Ok(Self { client, logger })
+	}
+
+	/// Retrieves an invoice of `amount_msat` from the LNURL-pay service at `target`.
+	///
+	/// The returned invoice is checked to pay the requested amount and to commit to the service's
+	/// metadata.
+	pub(crate) async fn request_pay_invoice(
+		&self, target: &str, amount_msat: u64, comment: Option<String>,
+	) -> Result<Bolt11Invoice, Error> {
+		let url = resolve_url(target)?;
+		let params = PayParams::from_json(&self.get_json(&url).await?).map_err(|e| {
+			log_error!(self.logger, "Received invalid LNURL-pay parameters from {}", url);
+			e
+		})?;
+
+		if amount_msat < params.min_sendable_msat || amount_msat > params.max_sendable_msat {
+			log_error!(
+				self.logger,
+				"Amount of {}msat is outside of the range accepted by the LNURL service: {}-{}msat",
+				amount_msat,
+				params.min_sendable_msat,
+				params.max_sendable_msat
+			);
+			return Err(Error::InvalidAmount);
+		}
+
+		let mut query = format!("amount={}", amount_msat);
+		if let Some(comment) = comment.filter(|c| !c.is_empty()) {
+			if comment.chars().count() as u64 > params.comment_allowed {
+				log_error!(
+					self.logger,
+					"Comment exceeds the {} characters accepted by the LNURL service",
+					params.comment_allowed
+				);
+				return Err(Error::LnurlRequestFailed);
+			}
+			query.push_str(&format!("&comment={}", percent_encode(&comment)));
+		}
+
+		let callback_url = append_query(&params.callback, &query);
+		let json = self.get_json(&callback_url).await?;
+		let invoice = json["pr"]
+			.as_str()
+			.and_then(|pr| Bolt11Invoice::from_str(pr).ok())
+			.ok_or_else(|| {
+				log_error!(self.logger, "Received no valid invoice from LNURL service");
+				Error::LnurlRequestFailed
+			})?;
+
+		params.validate_invoice(&invoice, amount_msat).map_err(|e| {
+			log_error!(self.logger, "Received mismatching invoice from LNURL service");
+			e
+		})?;
+		Ok(invoice)
+	}
+
+	/// Retrieves the parameters of the LNURL-withdraw service at `target`.
+	pub(crate) async fn request_withdraw_params(
+		&self, target: &str,
+	) -> Result<WithdrawParams, Error> {
+		let url = resolve_url(target)?;
+		WithdrawParams::from_json(&self.get_json(&url).await?).map_err(|e| {
+			log_error!(self.logger, "Received invalid LNURL-withdraw parameters from {}", url);
+			e
+		})
+	}
+
+	/// Hands the given invoice to the LNURL-withdraw service, asking it to pay it.
+	///
+	/// Returns [`Error::LnurlWithdrawRejected`] if the service refused to pay the invoice.
+	pub(crate) async fn submit_withdraw_invoice(
+		&self, params: &WithdrawParams, invoice: &Bolt11Invoice,
+	) -> Result<(), Error> {
+		let query = format!(
+			"k1={}&pr={}",
+			percent_encode(&params.k1),
+			percent_encode(&invoice.to_string())
+		);
+		let callback_url = append_query(&params.callback, &query);
+		let json = self.fetch_json(&callback_url).await?;
+		check_status(&json).map_err(|_| {
+			let reason = json["reason"].as_str().unwrap_or("unknown");
+			log_error!(self.logger, "LNURL-withdraw service rejected our invoice: {}", reason);
+			Error::LnurlWithdrawRejected
+		})
+	}
+
+	async fn get_json(&self, url: &str) -> Result<Value, Error> {
+		let json = self.fetch_json(url).await?;
+		check_status(&json).map_err(|e| {
+			let reason = json["reason"].as_str().unwrap_or("unknown");
+			log_error!(self.logger, "LNURL service at {} returned an error: {}", url, reason);
+			e
+		})?;
+		Ok(json)
+	}
+
+	// Unlike `get_json`, this doesn't check whether the service signalled an error.
+	async fn fetch_json(&self, url: &str) -> Result<Value, Error> {
+		let response = self.client.get(url).send().await.map_err(|e| {
+			log_error!(self.logger, "Failed to query LNURL service at {}: {}", url, e);
+			Error::LnurlRequestFailed
+		})?;
+		let body = response.bytes().await.map_err(|e| {
+			log_error!(self.logger, "Failed to read LNURL response from {}: {}", url, e);
+			Error::LnurlRequestFailed
+		})?;
+		let json: Value = serde_json::from_slice(&body).map_err(|e| {
+			log_error!(self.logger, "Failed to parse LNURL response from {}: {}", url, e);
+			Error::LnurlRequestFailed
+		})?;
+		Ok(json)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn resolves_lnurl_targets() {
+		assert_eq!(
+			resolve_url("alice@Example.com").unwrap(),
+			"https://example.com/.well-known/lnurlp/alice"
+		);
+		assert_eq!(
+			resolve_url("bob@someonionaddress.onion").unwrap(),
+			"http://someonionaddress.onion/.well-known/lnurlp/bob"
+		);
+		assert_eq!(
+			resolve_url("lnurlp://service.com/api?q=1").unwrap(),
+			"https://service.com/api?q=1"
+		);
+
+		let lnurl =
+			"LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34\
+			X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS";
+		assert_eq!(
+			resolve_url(lnurl).unwrap(),
+			"https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df"
+		);
+
+		assert_eq!(resolve_url("not an address"), Err(Error::InvalidUri));
+		assert_eq!(resolve_url("@example.com"), Err(Error::InvalidUri));
+	}
+
+	#[test]
+	fn parses_pay_params() {
+		let json = serde_json::json!({
+			"tag": "payRequest",
+			"callback": "https://example.com/callback?id=1",
+			"minSendable": 1000,
+			"maxSendable": 100_000_000,
+			"metadata": "[[\"text/plain\",\"Pay Alice\"]]",
+			"commentAllowed": 144,
+		});
+		let params = PayParams::from_json(&json).unwrap();
+		assert_eq!(params.min_sendable_msat, 1000);
+		assert_eq!(params.max_sendable_msat, 100_000_000);
+		assert_eq!(params.comment_allowed, 144);
+		assert_eq!(
+			append_query(&params.callback, "amount=5000"),
+			"https://example.com/callback?id=1&amount=5000"
+		);
+
+		let error = serde_json::json!({ "status": "ERROR", "reason": "unknown user" });
+		assert_eq!(PayParams::from_json(&error), Err(Error::LnurlRequestFailed));
+		let withdraw = serde_json::json!({ "tag": "withdrawRequest" });
+		assert_eq!(PayParams::from_json(&withdraw), Err(Error::LnurlRequestFailed));
+	}
+
+	#[test]
+	fn parses_withdraw_params() {
+		let json = serde_json::json!({
+			"tag": "withdrawRequest",
+			"callback": "https://example.com/withdraw",
+			"k1": "secret",
+			"defaultDescription": "Faucet",
+			"minWithdrawable": 1000,
+			"maxWithdrawable": 50_000,
+		});
+		let params = WithdrawParams::from_json(&json).unwrap();
+		assert_eq!(params.k1, "secret");
+		assert_eq!(params.default_description, "Faucet");
+		assert_eq!(params.min_withdrawable_msat, 1000);
+		assert_eq!(params.max_withdrawable_msat, 50_000);
+
+		let pay = serde_json::json!({ "tag": "payRequest" });
+		assert_eq!(WithdrawParams::from_json(&pay), Err(Error::LnurlRequestFailed));
+	}
+}
@@ -1,23 +1,99 @@
 pub(crate) use lightning::util::logger::Logger;
-pub(crate) use lightning::{log_bytes, log_debug, log_error, log_info, log_trace};
+pub(crate) use lightning::{log_bytes, log_debug, log_error, log_info, log_trace, log_warn};
 
 use lightning::util::logger::{Level, Record};
 use lightning::util::ser::Writer;
 
+use crate::config::LogRotationConfig;
+
 use chrono::Utc;
 
 use std::fs;
 #[cfg(not(target_os = "windows"))]
 use std::os::unix::fs::symlink;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The format in which log lines are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+	/// Human-readable lines of the form `<timestamp> <level> [<module>:<line>] <message>`.
+	#[default]
+	Text,
+	/// JSON objects, one per line, with the fields `timestamp`, `level`, `module`, `line`, and
+	/// `message`.
+	Json,
+}
+
+/// A log record handed to a [`Log`] sink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+	/// The verbosity level of the record.
+	pub level: Level,
+	/// The module path of the code that emitted the record.
+	pub module_path: String,
+	/// The line of the code that emitted the record.
+	pub line: u32,
+	/// The log message.
+	pub message: String,
+	/// The time the record was emitted, in milliseconds since the UNIX epoch.
+	pub timestamp: u64,
+}
+
+/// A sink receiving the log records emitted by a [`Node`], e.g., to integrate with the logging
+/// stack of the embedding application.
+///
+/// Records are only handed to the sink if they pass the configured level filters.
+///
+/// [`Node`]: crate::Node
+pub trait Log: Send + Sync {
+	/// Called for every log record emitted by the [`Node`].
+	///
+	/// [`Node`]: crate::Node
+	fn log(&self, record: LogRecord);
+}
+
+/// A [`Log`] sink forwarding all records to a callback.
+pub(crate) struct LogCallback(pub(crate) Box<dyn Fn(LogRecord) + Send + Sync>);
+
+impl Log for LogCallback {
+	fn log(&self, record: LogRecord) {
+		(self.0)(record)
+	}
+}
 
 pub(crate) struct FilesystemLogger {
-	file_path: String,
+	file_path: Option<String>,
 	level: Level,
+	// Sorted so that the longest, i.e., most specific, module path comes first.
+	module_levels: Vec<(String, Level)>,
+	format: LogFormat,
+	sink: Option<Arc<dyn Log>>,
+	rotation_config: Option<LogRotationConfig>,
+	// Serializes all writes to the log file, which ensures we never lose lines while rotating.
+	file_state: Mutex<LogFileState>,
+}
+
+struct LogFileState {
+	size_bytes: u64,
+	opened_at: Instant,
+}
+
+impl LogFileState {
+	fn needs_rotation(&self, rotation_config: &LogRotationConfig, next_line_len: u64) -> bool {
+		let exceeds_size = rotation_config.max_file_size_bytes.map_or(false, |max_size| {
+			self.size_bytes > 0 && self.size_bytes + next_line_len > max_size
+		});
+		let exceeds_age = rotation_config
+			.max_file_age_secs
+			.map_or(false, |max_age| self.opened_at.elapsed() >= Duration::from_secs(max_age));
+		exceeds_size || exceeds_age
+	}
 }
 
 impl FilesystemLogger {
-	pub(crate) fn new(log_dir: String, level: Level) -> Result<Self, ()> {
+	pub(crate) fn new(log_dir: String, level: Level, format: LogFormat) -> Result<Self, ()> {
 		let log_file_name =
 			format!("ldk_node_{}.log", chrono::offset::Local::now().format("%Y_%m_%d"));
 		let log_file_path = format!("{}/{}", log_dir, log_file_name);
@@ -46,29 +122,274 @@ impl FilesystemLogger {
 			}
 		}
 
-		Ok(Self { file_path: log_file_path, level })
+		let size_bytes = fs::metadata(&log_file_path).map(|m| m.len()).unwrap_or(0);
+		let file_state = Mutex::new(LogFileState { size_bytes, opened_at: Instant::now() });
+		Ok(Self {
+			file_path: Some(log_file_path),
+			level,
+			module_levels: Vec::new(),
+			format,
+			sink: None,
+			rotation_config: None,
+			file_state,
+		})
+	}
+
+	/// Creates a logger that only forwards records to the given sink, without writing to disk.
+	pub(crate) fn new_sink_only(sink: Arc<dyn Log>, level: Level) -> Self {
+		Self {
+			file_path: None,
+			level,
+			module_levels: Vec::new(),
+			format: LogFormat::default(),
+			sink: Some(sink),
+			rotation_config: None,
+			file_state: Mutex::new(LogFileState { size_bytes: 0, opened_at: Instant::now() }),
+		}
+	}
+
+	/// Rotates the log file according to the given config, if any.
+	pub(crate) fn with_rotation(mut self, rotation_config: Option<LogRotationConfig>) -> Self {
+		self.rotation_config = rotation_config;
+		self
+	}
+
+	/// Sets the given sink to receive all records in addition to the log file.
+	pub(crate) fn with_sink(mut self, sink: Arc<dyn Log>) -> Self {
+		self.sink = Some(sink);
+		self
+	}
+
+	/// Overrides the log level for all modules starting with the given module paths.
+	pub(crate) fn with_module_levels(
+		mut self, module_levels: impl IntoIterator<Item = (String, Level)>,
+	) -> Self {
+		self.module_levels = module_levels.into_iter().collect();
+		self.module_levels.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+		self
+	}
+
+	fn level_for_module(&self, module_path: &str) -> Level {
+		self.module_levels
+			.iter()
+			.find(|(prefix, _)| {
+				module_path
+					.strip_prefix(prefix.as_str())
+					.map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+			})
+			.map_or(self.level, |(_, level)| *level)
 	}
 }
+
 impl Logger for FilesystemLogger {
 	fn log(&self, record: Record) {
-		if record.level < self.level {
+		// Filter before doing any formatting to avoid needless allocations.
+		if record.level < self.level_for_module(record.module_path) {
 			return;
 		}
-		let raw_log = record.args.to_string();
-		let log = format!(
-			"{} {:<5} [{}:{}] {}\n",
-			Utc::now().format("%Y-%m-%d %H:%M:%S"),
-			record.level.to_string(),
-			record.module_path,
-			record.line,
-			raw_log
+
+		let now = Utc::now();
+		let message = record.args.to_string();
+		if let Some(file_path) = &self.file_path {
+			let log = format_log_line(
+				self.format,
+				&now.format("%Y-%m-%d %H:%M:%S").to_string(),
+				record.level,
+				record.module_path,
+				record.line,
+				&message,
+			);
+
+			let mut file_state = self.file_state.lock().unwrap();
+			if let Some(rotation_config) = &self.rotation_config {
+				if file_state.needs_rotation(rotation_config, log.len() as u64) {
+					match rotate_log_file(file_path, rotation_config.max_retained_files) {
+						Ok(()) => {
+							*file_state = LogFileState { size_bytes: 0, opened_at: Instant::now() };
+						},
+						Err(e) => eprintln!("ERROR: Failed to rotate log file: {}", e),
+					}
+				}
+			}
+
+			fs::OpenOptions::new()
+				.create(true)
+				.append(true)
+				.open(file_path)
+				.expect("Failed to open log file")
+				.write_all(log.as_bytes())
+				.expect("Failed to write to log file");
+			file_state.size_bytes += log.len() as u64;
+		}
+
+		if let Some(sink) = &self.sink {
+			sink.log(LogRecord {
+				level: record.level,
+				module_path: record.module_path.to_string(),
+				line: record.line,
+				message,
+				timestamp: now.timestamp_millis() as u64,
+			});
+		}
+	}
+}
+
+// Renames the current log file by appending a timestamp suffix and deletes the oldest rotated
+// files so that at most `max_retained_files` remain.
+//
+// Must only be called while holding the `file_state` lock.
+fn rotate_log_file(file_path: &str, max_retained_files: u32) -> std::io::Result<()> {
+	let timestamp = Utc::now().format("%Y%m%d_%H%M%S").to_string();
+	let mut rotated_path = format!("{}.{}", file_path, timestamp);
+	let mut counter = 1;
+	while Path::new(&rotated_path).exists() {
+		// Pad the counter so that rotated files still sort chronologically by name.
+		rotated_path = format!("{}.{}_{:04}", file_path, timestamp, counter);
+		counter += 1;
+	}
+	fs::rename(file_path, &rotated_path)?;
+
+	// Recreate the file right away so that the symlink never dangles.
+	fs::OpenOptions::new().create(true).append(true).open(file_path)?;
+
+	let log_dir = match Path::new(file_path).parent() {
+		Some(log_dir) => log_dir,
+		None => return Ok(()),
+	};
+	let mut rotated_files = fs::read_dir(log_dir)?
+		.filter_map(|entry| entry.ok())
+		.map(|entry| entry.file_name().to_string_lossy().to_string())
+		.filter(|name| name.starts_with("ldk_node_") && name.contains(".log."))
+		.collect::<Vec<_>>();
+	rotated_files.sort();
+	let num_to_remove = rotated_files.len().saturating_sub(max_retained_files as usize);
+	for name in rotated_files.iter().take(num_to_remove) {
+		fs::remove_file(log_dir.join(name))?;
+	}
+	Ok(())
+}
+
+fn format_log_line(
+	format: LogFormat, timestamp: &str, level: Level, module_path: &str, line: u32, message: &str,
+) -> String {
+	match format {
+		LogFormat::Text => {
+			format!(
+				"{} {:<5} [{}:{}] {}\n",
+				timestamp,
+				level.to_string(),
+				module_path,
+				line,
+				message
+			)
+		},
+		LogFormat::Json => {
+			// `serde_json` takes care of escaping, e.g., any quotes or control characters that
+			// made it into the message.
+			let json = serde_json::json!({
+				"timestamp": timestamp,
+				"level": level.to_string(),
+				"module": module_path,
+				"line": line,
+				"message": message,
+			});
+			format!("{}\n", json)
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::io::test_utils::random_storage_path;
+
+	#[test]
+	fn json_log_lines_are_escaped() {
+		let message = "Failed to read \"ns\"/\u{FFFD}\nkey\\";
+		let line = format_log_line(
+			LogFormat::Json,
+			"2024-01-01 00:00:00",
+			Level::Error,
+			"ldk_node::io",
+			42,
+			message,
+		);
+		assert!(line.ends_with('\n'));
+		assert_eq!(line.lines().count(), 1);
+
+		let json: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+		assert_eq!(json["message"], message);
+		assert_eq!(json["level"], "ERROR");
+		assert_eq!(json["module"], "ldk_node::io");
+		assert_eq!(json["line"], 42);
+		assert_eq!(json["timestamp"], "2024-01-01 00:00:00");
+	}
+
+	#[test]
+	fn module_levels_override_global_level() {
+		struct NoopLog;
+		impl Log for NoopLog {
+			fn log(&self, _record: LogRecord) {}
+		}
+
+		let logger = FilesystemLogger::new_sink_only(Arc::new(NoopLog), Level::Info)
+			.with_module_levels(vec![
+				("lightning::routing".to_string(), Level::Error),
+				("lightning::routing::gossip".to_string(), Level::Trace),
+				("lightning::ln".to_string(), Level::Debug),
+			]);
+		assert_eq!(logger.level_for_module("ldk_node::builder"), Level::Info);
+		assert_eq!(logger.level_for_module("lightning::ln::channel"), Level::Debug);
+		assert_eq!(logger.level_for_module("lightning::routing::router"), Level::Error);
+		assert_eq!(logger.level_for_module("lightning::routing::gossip"), Level::Trace);
+		// Prefixes only match whole path segments.
+		assert_eq!(logger.level_for_module("lightning::lnx"), Level::Info);
+	}
+
+	#[test]
+	fn log_files_are_rotated() {
+		let log_dir = random_storage_path().to_str().unwrap().to_string();
+		let rotation_config = LogRotationConfig {
+			max_file_size_bytes: Some(200),
+			max_file_age_secs: None,
+			max_retained_files: 2,
+		};
+		let logger = FilesystemLogger::new(log_dir.clone(), Level::Trace, LogFormat::Text)
+			.unwrap()
+			.with_rotation(Some(rotation_config));
+
+		for i in 0..50 {
+			log_info!(logger, "Log line number {}", i);
+		}
+
+		let mut num_rotated_files = 0;
+		for entry in fs::read_dir(&log_dir).unwrap() {
+			let entry = entry.unwrap();
+			let name = entry.file_name().to_string_lossy().to_string();
+			if name.contains(".log.") {
+				num_rotated_files += 1;
+			}
+			if !entry.path().is_symlink() {
+				assert!(entry.metadata().unwrap().len() <= 200);
+			}
+		}
+		assert_eq!(num_rotated_files, 2);
+
+		// The most recent line always ends up in the current log file.
+		let current_log = fs::read_to_string(logger.file_path.as_ref().unwrap()).unwrap();
+		assert!(current_log.contains("Log line number 49\n"));
+	}
+
+	#[test]
+	fn text_log_lines_are_unchanged() {
+		let line = format_log_line(
+			LogFormat::Text,
+			"2024-01-01 00:00:00",
+			Level::Info,
+			"ldk_node",
+			7,
+			"Hi",
 		);
-		fs::OpenOptions::new()
-			.create(true)
-			.append(true)
-			.open(self.file_path.clone())
-			.expect("Failed to open log file")
-			.write_all(log.as_bytes())
-			.expect("Failed to write to log file")
+		assert_eq!(line, "2024-01-01 00:00:00 INFO  [ldk_node:7] Hi\n");
 	}
 }
@@ -1,8 +1,17 @@
+use crate::backup::RecoveredChannels;
 use crate::liquidity::LiquiditySource;
+use crate::logger::log_info;
 
+use lightning::events::{MessageSendEvent, MessageSendEventsProvider};
 use lightning::ln::features::{InitFeatures, NodeFeatures};
+use lightning::ln::msgs::{
+	ChannelAnnouncement, ChannelUpdate, ErrorAction, ErrorMessage, Init, LightningError,
+	NodeAnnouncement, QueryChannelRange, QueryShortChannelIds, ReplyChannelRange,
+	ReplyShortChannelIdsEnd, RoutingMessageHandler,
+};
 use lightning::ln::peer_handler::CustomMessageHandler;
 use lightning::ln::wire::CustomMessageReader;
+use lightning::routing::gossip::NodeId;
 use lightning::util::logger::Logger;
 use lightning::util::persist::KVStore;
 
@@ -11,7 +20,7 @@ use lightning_liquidity::lsps0::ser::RawLSPSMessage;
 use bitcoin::secp256k1::PublicKey;
 
 use std::ops::Deref;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 pub(crate) enum NodeCustomMessageHandler<K: KVStore + Sync + Send + 'static, L: Deref>
 where
@@ -96,3 +105,139 @@ where
 		}
 	}
 }
+
+/// A [`RoutingMessageHandler`] asking the counterparties of channels restored from a static
+/// channel backup to force-close them whenever they connect.
+///
+/// We can't close these channels ourselves as our commitment transactions might be revoked. As the
+/// [`PeerManager`] also delivers the message events of the routing handler, we send the error
+/// messages from here, while leaving the actual gossip handling to the wrapped handler.
+///
+/// [`PeerManager`]: lightning::ln::peer_handler::PeerManager
+pub(crate) struct RecoveryMessageHandler<L: Deref>
+where
+	L::Target: Logger,
+{
+	inner: Arc<dyn RoutingMessageHandler + Send + Sync>,
+	recovered_channels: Arc<RecoveredChannels>,
+	pending_msg_events: Mutex<Vec<MessageSendEvent>>,
+	logger: L,
+}
+
+impl<L: Deref> RecoveryMessageHandler<L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(
+		inner: Arc<dyn RoutingMessageHandler + Send + Sync>,
+		recovered_channels: Arc<RecoveredChannels>, logger: L,
+	) -> Self {
+		let pending_msg_events = Mutex::new(Vec::new());
+		Self { inner, recovered_channels, pending_msg_events, logger }
+	}
+}
+
+impl<L: Deref> MessageSendEventsProvider for RecoveryMessageHandler<L>
+where
+	L::Target: Logger,
+{
+	fn get_and_clear_pending_msg_events(&self) -> Vec<MessageSendEvent> {
+		let mut msg_events = std::mem::take(&mut *self.pending_msg_events.lock().unwrap());
+		msg_events.append(&mut self.inner.get_and_clear_pending_msg_events());
+		msg_events
+	}
+}
+
+impl<L: Deref> RoutingMessageHandler for RecoveryMessageHandler<L>
+where
+	L::Target: Logger,
+{
+	fn handle_node_announcement(&self, msg: &NodeAnnouncement) -> Result<bool, LightningError> {
+		self.inner.handle_node_announcement(msg)
+	}
+
+	fn handle_channel_announcement(
+		&self, msg: &ChannelAnnouncement,
+	) -> Result<bool, LightningError> {
+		self.inner.handle_channel_announcement(msg)
+	}
+
+	fn handle_channel_update(&self, msg: &ChannelUpdate) -> Result<bool, LightningError> {
+		self.inner.handle_channel_update(msg)
+	}
+
+	fn get_next_channel_announcement(
+		&self, starting_point: u64,
+	) -> Option<(ChannelAnnouncement, Option<ChannelUpdate>, Option<ChannelUpdate>)> {
+		self.inner.get_next_channel_announcement(starting_point)
+	}
+
+	fn get_next_node_announcement(
+		&self, starting_point: Option<&NodeId>,
+	) -> Option<NodeAnnouncement> {
+		self.inner.get_next_node_announcement(starting_point)
+	}
+
+	fn peer_connected(
+		&self, their_node_id: &PublicKey, init: &Init, inbound: bool,
+	) -> Result<(), ()> {
+		let channel_ids = self.recovered_channels.channels_with_peer(their_node_id);
+		if !channel_ids.is_empty() {
+			let mut pending_msg_events = self.pending_msg_events.lock().unwrap();
+			for channel_id in channel_ids {
+				log_info!(
+					self.logger,
+					"Asking peer {} to force-close channel {} restored from backup",
+					their_node_id,
+					channel_id
+				);
+				let msg = ErrorMessage {
+					channel_id,
+					data: "Channel was restored from a backup and can't be operated anymore"
+						.to_string(),
+				};
+				pending_msg_events.push(MessageSendEvent::HandleError {
+					node_id: *their_node_id,
+					action: ErrorAction::SendErrorMessage { msg },
+				});
+			}
+		}
+		self.inner.peer_connected(their_node_id, init, inbound)
+	}
+
+	fn handle_reply_channel_range(
+		&self, their_node_id: &PublicKey, msg: ReplyChannelRange,
+	) -> Result<(), LightningError> {
+		self.inner.handle_reply_channel_range(their_node_id, msg)
+	}
+
+	fn handle_reply_short_channel_ids_end(
+		&self, their_node_id: &PublicKey, msg: ReplyShortChannelIdsEnd,
+	) -> Result<(), LightningError> {
+		self.inner.handle_reply_short_channel_ids_end(their_node_id, msg)
+	}
+
+	fn handle_query_channel_range(
+		&self, their_node_id: &PublicKey, msg: QueryChannelRange,
+	) -> Result<(), LightningError> {
+		self.inner.handle_query_channel_range(their_node_id, msg)
+	}
+
+	fn handle_query_short_channel_ids(
+		&self, their_node_id: &PublicKey, msg: QueryShortChannelIds,
+	) -> Result<(), LightningError> {
+		self.inner.handle_query_short_channel_ids(their_node_id, msg)
+	}
+
+	fn processing_queue_high(&self) -> bool {
+		self.inner.processing_queue_high()
+	}
+
+	fn provided_node_features(&self) -> NodeFeatures {
+		self.inner.provided_node_features()
+	}
+
+	fn provided_init_features(&self, their_node_id: &PublicKey) -> InitFeatures {
+		self.inner.provided_init_features(their_node_id)
+	}
+}
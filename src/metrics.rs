@@ -0,0 +1,127 @@
+//! Objects for exposing metrics about the state of a [`Node`].
+//!
+//! [`Node`]: crate::Node
+
+/// A snapshot of metrics describing the current state of a [`Node`].
+///
+/// All values are derived from live node state at the time [`Node::metrics_snapshot`] was
+/// called. If the `prometheus` feature is enabled, the snapshot may be rendered in the
+/// Prometheus text exposition format via [`Metrics::to_prometheus_text`].
+///
+/// [`Node`]: crate::Node
+/// [`Node::metrics_snapshot`]: crate::Node::metrics_snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Metrics {
+	/// The number of channels we have with our peers, including unusable ones.
+	pub num_channels: u64,
+	/// The number of channels that are currently usable for payments.
+	pub num_usable_channels: u64,
+	/// The number of HTLCs that are pending in our channels and may yet be claimed by either
+	/// side.
+	pub num_pending_htlcs: u64,
+	/// The number of peers we're currently connected to.
+	pub num_connected_peers: u64,
+	/// The total balance of our on-chain wallet, in satoshis.
+	pub total_onchain_balance_sats: u64,
+	/// The spendable balance of our on-chain wallet, in satoshis.
+	pub spendable_onchain_balance_sats: u64,
+	/// The total balance we would be able to claim across all our channels, in satoshis.
+	pub total_lightning_balance_sats: u64,
+	/// The number of outbound payments that succeeded.
+	pub num_payments_sent: u64,
+	/// The number of payments that failed.
+	pub num_payments_failed: u64,
+	/// The number of inbound payments that succeeded.
+	pub num_payments_received: u64,
+	/// The number of payments that are still pending.
+	pub num_payments_pending: u64,
+	/// The number of nodes in our network graph.
+	pub network_graph_node_count: u64,
+	/// The number of channels in our network graph.
+	pub network_graph_channel_count: u64,
+	/// The timestamp, in seconds since the UNIX epoch, of the last successful Rapid Gossip Sync
+	/// snapshot, if any.
+	pub latest_rgs_snapshot_timestamp: Option<u64>,
+	/// The number of events in the event queue that have yet to be handled.
+	pub event_queue_depth: u64,
+}
+
+#[cfg(feature = "prometheus")]
+impl Metrics {
+	/// Renders the metrics in the [Prometheus text exposition format].
+	///
+	/// All metrics are exported as gauges and prefixed with `ldk_node_`.
+	///
+	/// [Prometheus text exposition format]: https://prometheus.io/docs/instrumenting/exposition_formats/
+	pub fn to_prometheus_text(&self) -> String {
+		let mut gauges = vec![
+			("channels", "The number of channels.", self.num_channels),
+			("usable_channels", "The number of usable channels.", self.num_usable_channels),
+			("pending_htlcs", "The number of pending HTLCs.", self.num_pending_htlcs),
+			("connected_peers", "The number of connected peers.", self.num_connected_peers),
+			(
+				"onchain_balance_sats",
+				"The total on-chain balance in satoshis.",
+				self.total_onchain_balance_sats,
+			),
+			(
+				"spendable_onchain_balance_sats",
+				"The spendable on-chain balance in satoshis.",
+				self.spendable_onchain_balance_sats,
+			),
+			(
+				"lightning_balance_sats",
+				"The total Lightning balance in satoshis.",
+				self.total_lightning_balance_sats,
+			),
+			("payments_sent", "The number of succeeded outbound payments.", self.num_payments_sent),
+			("payments_failed", "The number of failed payments.", self.num_payments_failed),
+			(
+				"payments_received",
+				"The number of succeeded inbound payments.",
+				self.num_payments_received,
+			),
+			("payments_pending", "The number of pending payments.", self.num_payments_pending),
+			(
+				"network_graph_nodes",
+				"The number of nodes in the network graph.",
+				self.network_graph_node_count,
+			),
+			(
+				"network_graph_channels",
+				"The number of channels in the network graph.",
+				self.network_graph_channel_count,
+			),
+			("event_queue_depth", "The number of unhandled events.", self.event_queue_depth),
+		];
+		if let Some(timestamp) = self.latest_rgs_snapshot_timestamp {
+			gauges.push((
+				"latest_rgs_snapshot_timestamp",
+				"The UNIX timestamp of the last Rapid Gossip Sync snapshot.",
+				timestamp,
+			));
+		}
+
+		let mut text = String::new();
+		for (name, help, value) in gauges {
+			text.push_str(&format!("# HELP ldk_node_{} {}\n", name, help));
+			text.push_str(&format!("# TYPE ldk_node_{} gauge\n", name));
+			text.push_str(&format!("ldk_node_{} {}\n", name, value));
+		}
+		text
+	}
+}
+
+#[cfg(all(test, feature = "prometheus"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn renders_prometheus_text() {
+		let metrics = Metrics { num_channels: 3, event_queue_depth: 1, ..Default::default() };
+		let text = metrics.to_prometheus_text();
+		assert!(text.contains("# TYPE ldk_node_channels gauge\nldk_node_channels 3\n"));
+		assert!(text.contains("ldk_node_event_queue_depth 1\n"));
+		assert!(!text.contains("latest_rgs_snapshot_timestamp"));
+	}
+}
@@ -0,0 +1,259 @@
+use crate::address_book::AddressBook;
+use crate::event::EventQueue;
+use crate::io::{
+	ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_KEY, ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+	ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use crate::logger::{log_error, log_info, Logger};
+use crate::types::{ChannelManager, Wallet};
+use crate::{Error, Event};
+
+use lightning::util::persist::KVStore;
+use lightning::util::ser::{Readable, Writeable, Writer};
+
+use bitcoin::{OutPoint, Txid};
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+/// Keeps track of the transactions of our on-chain wallet, optionally emitting
+/// [`Event::OnchainPaymentReceived`] and [`Event::OnchainPaymentSent`] events.
+///
+/// Transactions are tracked even if events are disabled, so that enabling them later on doesn't
+/// emit events for the whole wallet history.
+///
+/// Transactions replacing (RBF) one we sent before are considered the same payment, i.e., no
+/// further event is emitted for them.
+pub(crate) struct OnchainPaymentMonitor<K: KVStore + Sync + Send + 'static, L: Deref>
+where
+	L::Target: Logger,
+{
+	state: Mutex<OnchainPaymentMonitorState>,
+	emit_events: bool,
+	min_confirmations: u32,
+	address_book: Arc<AddressBook<K, L>>,
+	event_queue: Arc<EventQueue<K, L>>,
+	kv_store: Arc<K>,
+	logger: L,
+}
+
+impl<K: KVStore + Sync + Send + 'static, L: Deref> OnchainPaymentMonitor<K, L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(
+		state: OnchainPaymentMonitorState, emit_events: bool, min_confirmations: u32,
+		address_book: Arc<AddressBook<K, L>>, event_queue: Arc<EventQueue<K, L>>, kv_store: Arc<K>,
+		logger: L,
+	) -> Self {
+		let state = Mutex::new(state);
+		Self { state, emit_events, min_confirmations, address_book, event_queue, kv_store, logger }
+	}
+
+	/// Checks the wallet's transactions for new payments, emitting the respective events.
+	///
+	/// Should be called after every sync of the on-chain wallet.
+	pub(crate) fn update(
+		&self, wallet: &Wallet, channel_manager: &ChannelManager<K>,
+	) -> Result<(), Error> {
+		let transactions = wallet.list_transactions()?;
+		let cur_height = channel_manager.current_best_block().height();
+		let funding_txids: Vec<Txid> = channel_manager
+			.list_channels()
+			.into_iter()
+			.filter_map(|c| c.funding_txo.map(|o| o.txid))
+			.collect();
+
+		let mut state = self.state.lock().unwrap();
+		let mut updated = false;
+		for tx in transactions {
+			if state.handled_txids.contains(&tx.txid) {
+				// Once our transaction confirmed, it can't be replaced anymore.
+				if tx.confirmation_height.is_some()
+					&& state.unconfirmed_sent_inputs.values().any(|txid| *txid == tx.txid)
+				{
+					state.unconfirmed_sent_inputs.retain(|_, txid| *txid != tx.txid);
+					updated = true;
+				}
+				continue;
+			}
+
+			let event = if tx.sent_sats == 0 {
+				let confirmations = tx
+					.confirmation_height
+					.map_or(0, |height| cur_height.saturating_sub(height) + 1);
+				if confirmations < self.min_confirmations {
+					continue;
+				}
+
+				let label = tx
+					.receiving_addresses
+					.iter()
+					.find_map(|address| self.address_book.label(address));
+				log_info!(
+					self.logger,
+					"Received on-chain payment of {}sats with transaction {}",
+					tx.received_sats,
+					tx.txid
+				);
+				Some(Event::OnchainPaymentReceived {
+					txid: tx.txid,
+					amount_sat: tx.received_sats,
+					confirmations,
+					label,
+				})
+			} else {
+				let replaced_txid = tx
+					.spent_outpoints
+					.iter()
+					.find_map(|outpoint| state.unconfirmed_sent_inputs.get(outpoint))
+					.copied();
+				if let Some(replaced_txid) = replaced_txid {
+					state.unconfirmed_sent_inputs.retain(|_, txid| *txid != replaced_txid);
+				}
+				if tx.confirmation_height.is_none() {
+					for outpoint in tx.spent_outpoints.iter() {
+						state.unconfirmed_sent_inputs.insert(*outpoint, tx.txid);
+					}
+				}
+
+				// We don't consider channel fundings or transactions only moving funds within our
+				// wallet to be payments.
+				let amount_sat =
+					tx.sent_sats.saturating_sub(tx.received_sats).saturating_sub(tx.fee_sats);
+				if let Some(replaced_txid) = replaced_txid {
+					log_info!(
+						self.logger,
+						"On-chain transaction {} was replaced by transaction {}",
+						replaced_txid,
+						tx.txid
+					);
+					None
+				} else if amount_sat == 0 || funding_txids.contains(&tx.txid) {
+					None
+				} else {
+					Some(Event::OnchainPaymentSent { txid: tx.txid, amount_sat })
+				}
+			};
+
+			if let Some(event) = event.filter(|_| self.emit_events) {
+				self.event_queue.add_event(event)?;
+			}
+			state.handled_txids.insert(tx.txid);
+			updated = true;
+		}
+
+		if updated {
+			self.persist(&*state)?;
+		}
+		Ok(())
+	}
+
+	fn persist(&self, state: &OnchainPaymentMonitorState) -> Result<(), Error> {
+		let data = state.encode();
+		self.kv_store
+			.write(
+				ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+				ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+				ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_KEY,
+				&data,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+					ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+					ONCHAIN_PAYMENT_MONITOR_PERSISTENCE_KEY,
+					e
+				);
+				Error::PersistenceFailed
+			})
+	}
+}
+
+/// The persisted state of the [`OnchainPaymentMonitor`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct OnchainPaymentMonitorState {
+	/// The txids of the transactions we already emitted an event for, or decided not to.
+	pub handled_txids: HashSet<Txid>,
+	/// The outpoints spent by our unconfirmed outgoing transactions, mapped to the spending
+	/// transaction, which allows us to detect when they get replaced.
+	pub unconfirmed_sent_inputs: HashMap<OutPoint, Txid>,
+}
+
+impl Readable for OnchainPaymentMonitorState {
+	fn read<R: lightning::io::Read>(
+		reader: &mut R,
+	) -> Result<Self, lightning::ln::msgs::DecodeError> {
+		let len: u64 = Readable::read(reader)?;
+		let mut handled_txids = HashSet::with_capacity(len.min(1024) as usize);
+		for _ in 0..len {
+			let txid: Txid = Readable::read(reader)?;
+			handled_txids.insert(txid);
+		}
+
+		// Data written before we started tracking the inputs of our transactions ends here.
+		let len: u64 = match Readable::read(reader) {
+			Ok(len) => len,
+			Err(lightning::ln::msgs::DecodeError::ShortRead) => 0,
+			Err(e) => return Err(e),
+		};
+		let mut unconfirmed_sent_inputs = HashMap::with_capacity(len.min(1024) as usize);
+		for _ in 0..len {
+			let outpoint: OutPoint = Readable::read(reader)?;
+			let txid: Txid = Readable::read(reader)?;
+			unconfirmed_sent_inputs.insert(outpoint, txid);
+		}
+		Ok(Self { handled_txids, unconfirmed_sent_inputs })
+	}
+}
+
+impl Writeable for OnchainPaymentMonitorState {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
+		(self.handled_txids.len() as u64).write(writer)?;
+		for txid in self.handled_txids.iter() {
+			txid.write(writer)?;
+		}
+		(self.unconfirmed_sent_inputs.len() as u64).write(writer)?;
+		for (outpoint, txid) in self.unconfirmed_sent_inputs.iter() {
+			outpoint.write(writer)?;
+			txid.write(writer)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bitcoin::hashes::Hash;
+
+	#[test]
+	fn monitor_state_serialization_roundtrip() {
+		let mut state = OnchainPaymentMonitorState::default();
+		state.handled_txids.insert(Txid::all_zeros());
+		state.handled_txids.insert(Txid::from_byte_array([42; 32]));
+		state.unconfirmed_sent_inputs.insert(
+			OutPoint { txid: Txid::from_byte_array([23; 32]), vout: 1 },
+			Txid::from_byte_array([42; 32]),
+		);
+
+		let encoded = state.encode();
+		let decoded = OnchainPaymentMonitorState::read(&mut &encoded[..]).unwrap();
+		assert_eq!(state, decoded);
+	}
+
+	#[test]
+	fn monitor_state_without_sent_inputs_is_read() {
+		// Data written before we tracked the inputs of our transactions only holds the txids.
+		let mut encoded = Vec::new();
+		1u64.write(&mut encoded).unwrap();
+		Txid::all_zeros().write(&mut encoded).unwrap();
+
+		let decoded = OnchainPaymentMonitorState::read(&mut &encoded[..]).unwrap();
+		assert_eq!(decoded.handled_txids.len(), 1);
+		assert!(decoded.unconfirmed_sent_inputs.is_empty());
+	}
+}
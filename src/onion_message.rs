@@ -0,0 +1,246 @@
+use crate::event::EventQueue;
+use crate::logger::{log_error, log_info, FilesystemLogger, Logger};
+use crate::types::{ChannelManager, KeysManager, OnionMessenger};
+use crate::Event;
+
+use lightning::blinded_path::BlindedPath;
+use lightning::ln::features::{InitFeatures, NodeFeatures};
+use lightning::ln::msgs::{DecodeError, Init, OnionMessage, OnionMessageHandler};
+use lightning::offers::invoice::Bolt12Invoice;
+use lightning::offers::parse::Bolt12SemanticError;
+use lightning::offers::refund::Refund;
+use lightning::onion_message::messenger::{
+	peel_onion_message, CustomOnionMessageHandler as LdkCustomOnionMessageHandler, Destination,
+	PeeledOnion, PendingOnionMessage,
+};
+use lightning::onion_message::offers::{
+	OffersMessage, OffersMessageHandler as LdkOffersMessageHandler,
+};
+use lightning::onion_message::packet::{OnionMessageContents, ParsedOnionMessageContents};
+use lightning::util::persist::KVStore;
+use lightning::util::ser::{Writeable, Writer};
+
+use bitcoin::secp256k1::{self, PublicKey, Secp256k1};
+
+use std::ops::{Deref, RangeInclusive};
+use std::sync::{Arc, Mutex};
+
+/// An application-layer onion message of a custom TLV type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CustomOnionMessage {
+	tlv_type: u64,
+	data: Vec<u8>,
+}
+
+impl OnionMessageContents for CustomOnionMessage {
+	fn tlv_type(&self) -> u64 {
+		self.tlv_type
+	}
+}
+
+impl Writeable for CustomOnionMessage {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
+		writer.write_all(&self.data)
+	}
+}
+
+/// Sends and receives onion messages of custom TLV types on behalf of the user, emitting an
+/// [`Event::OnionMessageReceived`] for every message received.
+pub(crate) struct CustomOnionMessageHandler<K: KVStore + Sync + Send, L: Deref>
+where
+	L::Target: Logger,
+{
+	pending_messages: Mutex<Vec<PendingOnionMessage<CustomOnionMessage>>>,
+	tlv_types: RangeInclusive<u64>,
+	event_queue: Arc<EventQueue<K, L>>,
+	logger: L,
+}
+
+impl<K: KVStore + Sync + Send, L: Deref> CustomOnionMessageHandler<K, L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(
+		tlv_types: RangeInclusive<u64>, event_queue: Arc<EventQueue<K, L>>, logger: L,
+	) -> Self {
+		let pending_messages = Mutex::new(Vec::new());
+		Self { pending_messages, tlv_types, event_queue, logger }
+	}
+
+	/// Returns whether the given TLV type is in the range of custom onion message types we
+	/// accept, as configured via [`Config::custom_onion_message_tlv_types`].
+	///
+	/// [`Config::custom_onion_message_tlv_types`]: crate::config::Config::custom_onion_message_tlv_types
+	pub(crate) fn accepts_tlv_type(&self, tlv_type: u64) -> bool {
+		self.tlv_types.contains(&tlv_type)
+	}
+
+	/// Queues the given message to be sent to the given destination the next time the
+	/// `OnionMessenger` is polled for outgoing messages.
+	pub(crate) fn enqueue_message(&self, destination: Destination, tlv_type: u64, data: Vec<u8>) {
+		let contents = CustomOnionMessage { tlv_type, data };
+		let message = PendingOnionMessage { contents, destination, reply_path: None };
+		self.pending_messages.lock().unwrap().push(message);
+	}
+
+	fn handle_message(&self, msg: CustomOnionMessage, reply_path: Option<BlindedPath>) {
+		log_info!(self.logger, "Received onion message of type {}.", msg.tlv_type);
+		let event =
+			Event::OnionMessageReceived { tlv_type: msg.tlv_type, data: msg.data, reply_path };
+		self.event_queue.add_event(event).unwrap_or_else(|e| {
+			log_error!(self.logger, "Failed to push to event queue: {}", e);
+		});
+	}
+}
+
+impl<K: KVStore + Sync + Send, L: Deref> LdkCustomOnionMessageHandler
+	for CustomOnionMessageHandler<K, L>
+where
+	L::Target: Logger,
+{
+	type CustomMessage = CustomOnionMessage;
+
+	fn handle_custom_message(&self, msg: Self::CustomMessage) -> Option<Self::CustomMessage> {
+		// Messages addressed to us are usually handed to us by `NodeOnionMessageHandler` along
+		// with their reply path, which LDK doesn't pass on here.
+		self.handle_message(msg, None);
+		None
+	}
+
+	fn read_custom_message<R: lightning::io::Read>(
+		&self, message_type: u64, buffer: &mut R,
+	) -> Result<Option<Self::CustomMessage>, DecodeError> {
+		if !self.accepts_tlv_type(message_type) {
+			return Ok(None);
+		}
+
+		let mut data = Vec::new();
+		buffer.read_to_end(&mut data)?;
+		Ok(Some(CustomOnionMessage { tlv_type: message_type, data }))
+	}
+
+	fn release_pending_custom_messages(&self) -> Vec<PendingOnionMessage<Self::CustomMessage>> {
+		std::mem::take(&mut *self.pending_messages.lock().unwrap())
+	}
+}
+
+/// Handles [BOLT 12] messages on behalf of the [`ChannelManager`].
+///
+/// As LDK's [`ChannelManager::request_refund_payment`] doesn't return the invoice it sends, we
+/// retrieve the messages queued by the [`ChannelManager`] ourselves to learn about the payment
+/// hash of the refund payment, and hand them to the `OnionMessenger` afterwards.
+///
+/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
+/// [`ChannelManager::request_refund_payment`]: lightning::ln::channelmanager::ChannelManager::request_refund_payment
+pub(crate) struct NodeOffersMessageHandler<K: KVStore + Sync + Send + 'static> {
+	channel_manager: Arc<ChannelManager<K>>,
+	pending_messages: Mutex<Vec<PendingOnionMessage<OffersMessage>>>,
+}
+
+impl<K: KVStore + Sync + Send + 'static> NodeOffersMessageHandler<K> {
+	pub(crate) fn new(channel_manager: Arc<ChannelManager<K>>) -> Self {
+		let pending_messages = Mutex::new(Vec::new());
+		Self { channel_manager, pending_messages }
+	}
+
+	/// Requests to be paid for the given refund, returning the invoice we send in response.
+	///
+	/// Will return `Ok(None)` if the invoice couldn't be found among the queued messages.
+	pub(crate) fn request_refund_payment(
+		&self, refund: &Refund,
+	) -> Result<Option<Bolt12Invoice>, Bolt12SemanticError> {
+		// We hold the lock while queueing the invoice, so the `OnionMessenger` can't retrieve it
+		// before we had a look.
+		let mut pending_messages = self.pending_messages.lock().unwrap();
+		self.channel_manager.request_refund_payment(refund)?;
+		pending_messages.extend(self.channel_manager.release_pending_messages());
+		Ok(pending_messages.iter().rev().find_map(|message| match &message.contents {
+			OffersMessage::Invoice(invoice)
+				if invoice.payer_id() == refund.payer_id()
+					&& invoice.amount_msats() == refund.amount_msats() =>
+			{
+				Some(invoice.clone())
+			},
+			_ => None,
+		}))
+	}
+}
+
+impl<K: KVStore + Sync + Send + 'static> LdkOffersMessageHandler for NodeOffersMessageHandler<K> {
+	fn handle_message(&self, message: OffersMessage) -> Option<OffersMessage> {
+		self.channel_manager.handle_message(message)
+	}
+
+	fn release_pending_messages(&self) -> Vec<PendingOnionMessage<OffersMessage>> {
+		let mut pending_messages = std::mem::take(&mut *self.pending_messages.lock().unwrap());
+		pending_messages.extend(self.channel_manager.release_pending_messages());
+		pending_messages
+	}
+}
+
+/// Handles the onion messages received by the `PeerManager`.
+///
+/// As LDK's [`LdkCustomOnionMessageHandler`] interface doesn't expose the reply path given by the
+/// sender, we peel the messages ourselves and hand the custom ones addressed to us to our
+/// [`CustomOnionMessageHandler`] along with their reply path. All other messages are handled by
+/// the wrapped `OnionMessenger`.
+pub(crate) struct NodeOnionMessageHandler<K: KVStore + Sync + Send + 'static> {
+	onion_messenger: Arc<OnionMessenger<K>>,
+	custom_onion_message_handler: Arc<CustomOnionMessageHandler<K, Arc<FilesystemLogger>>>,
+	keys_manager: Arc<KeysManager>,
+	logger: Arc<FilesystemLogger>,
+	secp_ctx: Secp256k1<secp256k1::All>,
+}
+
+impl<K: KVStore + Sync + Send + 'static> NodeOnionMessageHandler<K> {
+	pub(crate) fn new(
+		onion_messenger: Arc<OnionMessenger<K>>,
+		custom_onion_message_handler: Arc<CustomOnionMessageHandler<K, Arc<FilesystemLogger>>>,
+		keys_manager: Arc<KeysManager>, logger: Arc<FilesystemLogger>,
+	) -> Self {
+		let secp_ctx = Secp256k1::new();
+		Self { onion_messenger, custom_onion_message_handler, keys_manager, logger, secp_ctx }
+	}
+}
+
+impl<K: KVStore + Sync + Send + 'static> OnionMessageHandler for NodeOnionMessageHandler<K> {
+	fn handle_onion_message(&self, peer_node_id: &PublicKey, msg: &OnionMessage) {
+		match peel_onion_message(
+			msg,
+			&self.secp_ctx,
+			&*self.keys_manager,
+			&*self.logger,
+			&*self.custom_onion_message_handler,
+		) {
+			Ok(PeeledOnion::Receive(
+				ParsedOnionMessageContents::Custom(message),
+				_,
+				reply_path,
+			)) => self.custom_onion_message_handler.handle_message(message, reply_path),
+			_ => self.onion_messenger.handle_onion_message(peer_node_id, msg),
+		}
+	}
+
+	fn next_onion_message_for_peer(&self, peer_node_id: PublicKey) -> Option<OnionMessage> {
+		self.onion_messenger.next_onion_message_for_peer(peer_node_id)
+	}
+
+	fn peer_connected(
+		&self, their_node_id: &PublicKey, init: &Init, inbound: bool,
+	) -> Result<(), ()> {
+		self.onion_messenger.peer_connected(their_node_id, init, inbound)
+	}
+
+	fn peer_disconnected(&self, their_node_id: &PublicKey) {
+		self.onion_messenger.peer_disconnected(their_node_id)
+	}
+
+	fn provided_node_features(&self) -> NodeFeatures {
+		self.onion_messenger.provided_node_features()
+	}
+
+	fn provided_init_features(&self, their_node_id: &PublicKey) -> InitFeatures {
+		self.onion_messenger.provided_init_features(their_node_id)
+	}
+}
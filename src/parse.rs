@@ -0,0 +1,262 @@
+//! Standalone helpers allowing to inspect payment requests before paying them.
+//!
+//! None of these require a running [`Node`], so that they can be used to validate and preview
+//! user input, e.g., a scanned QR code.
+//!
+//! [`Node`]: crate::Node
+
+use crate::types::DescriptionHash;
+use crate::unified_qr::{self, UnifiedPaymentRequest};
+use crate::Error;
+
+use lightning::ln::PaymentHash;
+use lightning::offers::offer::{Amount, Offer};
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+
+use bitcoin::blockdata::constants::ChainHash;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Network;
+
+use std::str::FromStr;
+
+/// The details of a parsed [BOLT 11] invoice.
+///
+/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bolt11InvoiceInfo {
+	/// The network the invoice is valid for.
+	///
+	/// Should be checked against [`Config::network`] to detect invoices for a different network.
+	///
+	/// [`Config::network`]: crate::Config::network
+	pub network: Network,
+	/// The hash of the payment.
+	pub payment_hash: PaymentHash,
+	/// The amount requested, or `None` if the payer may choose the amount.
+	pub amount_msat: Option<u64>,
+	/// The description of the payment, if the invoice contains one.
+	pub description: Option<String>,
+	/// The hash of the description, if the invoice commits to it in place of the description.
+	pub description_hash: Option<DescriptionHash>,
+	/// The node id of the recipient.
+	pub payee_pubkey: PublicKey,
+	/// The time, in seconds since the UNIX epoch, at which the invoice was created.
+	pub created_at_secs: u64,
+	/// The time, in seconds since the UNIX epoch, at which the invoice expires.
+	pub expires_at_secs: u64,
+	/// Whether the invoice already expired.
+	pub is_expired: bool,
+	/// The minimum CLTV expiry delta the final hop requires.
+	pub min_final_cltv_expiry_delta: u64,
+}
+
+impl From<&Bolt11Invoice> for Bolt11InvoiceInfo {
+	fn from(invoice: &Bolt11Invoice) -> Self {
+		let (description, description_hash) = match invoice.description() {
+			Bolt11InvoiceDescription::Direct(description) => (Some(description.to_string()), None),
+			Bolt11InvoiceDescription::Hash(hash) => {
+				(None, Some(DescriptionHash(hash.0.to_byte_array())))
+			},
+		};
+		let created_at_secs = invoice.duration_since_epoch().as_secs();
+		Self {
+			network: invoice.network(),
+			payment_hash: PaymentHash(invoice.payment_hash().to_byte_array()),
+			amount_msat: invoice.amount_milli_satoshis(),
+			description,
+			description_hash,
+			payee_pubkey: invoice.recover_payee_pub_key(),
+			created_at_secs,
+			expires_at_secs: created_at_secs.saturating_add(invoice.expiry_time().as_secs()),
+			is_expired: invoice.is_expired(),
+			min_final_cltv_expiry_delta: invoice.min_final_cltv_expiry_delta(),
+		}
+	}
+}
+
+/// The amount requested by a [BOLT 12] offer.
+///
+/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OfferAmount {
+	/// An amount denominated in bitcoin.
+	Bitcoin {
+		/// The amount in millisatoshis.
+		amount_msat: u64,
+	},
+	/// An amount denominated in a fiat currency.
+	Currency {
+		/// The ISO 4217 code of the currency.
+		iso4217_code: String,
+		/// The amount in the currency's smallest unit.
+		amount: u64,
+	},
+}
+
+/// The details of a parsed [BOLT 12] offer.
+///
+/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OfferInfo {
+	/// The networks the offer may be paid on.
+	///
+	/// Should be checked to contain [`Config::network`] to detect offers for a different network.
+	/// Chains we don't know are omitted.
+	///
+	/// [`Config::network`]: crate::Config::network
+	pub networks: Vec<Network>,
+	/// The amount requested, or `None` if the payer may choose the amount.
+	pub amount: Option<OfferAmount>,
+	/// The description of the offer.
+	pub description: String,
+	/// The issuer of the offer, if given.
+	pub issuer: Option<String>,
+	/// The time, in seconds since the UNIX epoch, at which the offer expires, if it does.
+	pub absolute_expiry_secs: Option<u64>,
+	/// Whether the offer already expired.
+	pub is_expired: bool,
+	/// The public key used by the recipient to sign invoices.
+	pub signing_pubkey: PublicKey,
+}
+
+impl From<&Offer> for OfferInfo {
+	fn from(offer: &Offer) -> Self {
+		let known_networks =
+			[Network::Bitcoin, Network::Testnet, Network::Signet, Network::Regtest];
+		let networks = offer
+			.chains()
+			.into_iter()
+			.filter_map(|chain| {
+				known_networks
+					.iter()
+					.find(|n| ChainHash::using_genesis_block(**n) == chain)
+					.copied()
+			})
+			.collect();
+		let amount = offer.amount().map(|amount| match amount {
+			Amount::Bitcoin { amount_msats } => OfferAmount::Bitcoin { amount_msat: *amount_msats },
+			Amount::Currency { iso4217_code, amount } => OfferAmount::Currency {
+				iso4217_code: String::from_utf8_lossy(iso4217_code).to_string(),
+				amount: *amount,
+			},
+		});
+		Self {
+			networks,
+			amount,
+			description: offer.description().to_string(),
+			issuer: offer.issuer().map(|issuer| issuer.to_string()),
+			absolute_expiry_secs: offer.absolute_expiry().map(|expiry| expiry.as_secs()),
+			is_expired: offer.is_expired(),
+			signing_pubkey: offer.signing_pubkey(),
+		}
+	}
+}
+
+/// Parses the given [BOLT 11] invoice, returning its details.
+///
+/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+pub fn parse_bolt11_invoice(invoice: &str) -> Result<Bolt11InvoiceInfo, Error> {
+	let invoice = Bolt11Invoice::from_str(&invoice.trim().to_ascii_lowercase())
+		.map_err(|_| Error::InvalidInvoice)?;
+	Ok(Bolt11InvoiceInfo::from(&invoice))
+}
+
+/// Parses the given [BOLT 12] offer, returning its details.
+///
+/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+pub fn parse_bolt12_offer(offer: &str) -> Result<OfferInfo, Error> {
+	let offer =
+		Offer::from_str(&offer.trim().to_ascii_lowercase()).map_err(|_| Error::InvalidOffer)?;
+	Ok(OfferInfo::from(&offer))
+}
+
+/// Parses the given [BIP 21] URI into its components.
+///
+/// Parsing is lenient towards common real-world variations: the scheme and parameter names are
+/// matched case-insensitively, the on-chain address and amount may be omitted, and unknown
+/// optional parameters are ignored. Parameters prefixed with `req-` that we don't know, however,
+/// render the URI invalid as mandated by [BIP 21].
+///
+/// Will return [`Error::InvalidAddress`] if the included on-chain address isn't valid for the
+/// given `network`.
+///
+/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+pub fn parse_bip21(uri: &str, network: Network) -> Result<UnifiedPaymentRequest, Error> {
+	unified_qr::parse_uri(uri, network)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use lightning::ln::PaymentSecret;
+	use lightning::offers::offer::OfferBuilder;
+	use lightning_invoice::{Currency, InvoiceBuilder};
+
+	use bitcoin::hashes::sha256::Hash as Sha256;
+	use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+	#[test]
+	fn bolt11_invoice_details_are_exposed() {
+		let secp_ctx = Secp256k1::new();
+		let secret_key = SecretKey::from_slice(&[42; 32]).unwrap();
+		let payment_hash = Sha256::hash(&[43; 32]);
+		let invoice = InvoiceBuilder::new(Currency::BitcoinTestnet)
+			.description("coffee".to_string())
+			.payment_hash(payment_hash)
+			.payment_secret(PaymentSecret([44; 32]))
+			.current_timestamp()
+			.min_final_cltv_expiry_delta(144)
+			.amount_milli_satoshis(10_000)
+			.build_signed(|hash| secp_ctx.sign_ecdsa_recoverable(hash, &secret_key))
+			.unwrap();
+
+		let info = parse_bolt11_invoice(&invoice.to_string().to_ascii_uppercase()).unwrap();
+		assert_eq!(info.network, Network::Testnet);
+		assert_eq!(info.payment_hash, PaymentHash(payment_hash.to_byte_array()));
+		assert_eq!(info.amount_msat, Some(10_000));
+		assert_eq!(info.description.as_deref(), Some("coffee"));
+		assert_eq!(info.description_hash, None);
+		assert_eq!(info.payee_pubkey, PublicKey::from_secret_key(&secp_ctx, &secret_key));
+		assert!(info.expires_at_secs > info.created_at_secs);
+		assert!(!info.is_expired);
+		assert_eq!(info.min_final_cltv_expiry_delta, 144);
+
+		assert_eq!(parse_bolt11_invoice("lnbc1invalid"), Err(Error::InvalidInvoice));
+	}
+
+	#[test]
+	fn offer_details_are_exposed() {
+		let secp_ctx = Secp256k1::new();
+		let signing_pubkey =
+			PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		let offer = OfferBuilder::new("coffee".to_string(), signing_pubkey)
+			.amount_msats(10_000)
+			.chain(Network::Testnet)
+			.issuer("cafe".to_string())
+			.build()
+			.unwrap();
+
+		let info = parse_bolt12_offer(&offer.to_string()).unwrap();
+		assert_eq!(info.networks, vec![Network::Testnet]);
+		assert_eq!(info.amount, Some(OfferAmount::Bitcoin { amount_msat: 10_000 }));
+		assert_eq!(info.description, "coffee");
+		assert_eq!(info.issuer.as_deref(), Some("cafe"));
+		assert_eq!(info.absolute_expiry_secs, None);
+		assert!(!info.is_expired);
+		assert_eq!(info.signing_pubkey, signing_pubkey);
+
+		assert_eq!(parse_bolt12_offer("lno1invalid"), Err(Error::InvalidOffer));
+	}
+
+	#[test]
+	fn bip21_uris_are_parsed() {
+		let address = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+		let uri = format!("bitcoin:{}?amount=0.0001", address);
+		let request = parse_bip21(&uri, Network::Testnet).unwrap();
+		assert_eq!(request.address.unwrap().to_string(), address);
+		assert_eq!(request.amount_sats, Some(10_000));
+		assert_eq!(parse_bip21(&uri, Network::Bitcoin).unwrap_err(), Error::InvalidAddress);
+	}
+}
@@ -0,0 +1,401 @@
+use crate::config::{RetryPolicy, LDK_PAYMENT_RETRY_TIMEOUT};
+use crate::event::EventQueue;
+use crate::hex_utils;
+use crate::io::{
+	PAYMENT_RETRY_PERSISTENCE_PRIMARY_NAMESPACE, PAYMENT_RETRY_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use crate::logger::{log_error, log_info, Logger};
+use crate::payment_store::{PaymentDetailsUpdate, PaymentStatus, PaymentStore};
+use crate::types::{ChannelManager, CustomTlvRecord};
+use crate::{Error, Event};
+
+use lightning::events::PaymentFailureReason;
+use lightning::impl_writeable_tlv_based;
+use lightning::ln::channelmanager::{PaymentId, RecipientOnionFields, Retry, RetryableSendFailure};
+use lightning::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
+use lightning::routing::router::RouteParameters;
+use lightning::util::persist::KVStore;
+use lightning::util::ser::Writeable;
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The state of an outbound payment we may retry according to our [`RetryPolicy`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PendingPaymentRetry {
+	pub payment_hash: PaymentHash,
+	/// The id the payment is tracked under, which is reused for each attempt.
+	pub payment_id: PaymentId,
+	/// The preimage of a spontaneous payment.
+	pub payment_preimage: Option<PaymentPreimage>,
+	pub payment_secret: Option<PaymentSecret>,
+	pub payment_metadata: Option<Vec<u8>>,
+	pub custom_tlvs: Vec<CustomTlvRecord>,
+	pub route_params: RouteParameters,
+	/// The number of attempts made so far.
+	pub attempts: u32,
+	/// The time of the initial attempt, in seconds since the UNIX epoch.
+	pub first_attempt_timestamp: u64,
+	/// The time at which we make the next attempt, in seconds since the UNIX epoch, if the most
+	/// recent attempt failed.
+	pub next_attempt_timestamp: Option<u64>,
+}
+
+impl_writeable_tlv_based!(PendingPaymentRetry, {
+	(0, payment_hash, required),
+	(2, payment_id, required),
+	(4, payment_preimage, option),
+	(6, payment_secret, option),
+	(8, payment_metadata, option),
+	(10, custom_tlvs, optional_vec),
+	(12, route_params, required),
+	(14, attempts, required),
+	(16, first_attempt_timestamp, required),
+	(18, next_attempt_timestamp, option),
+});
+
+/// Retries failed outbound payments according to the configured [`RetryPolicy`].
+///
+/// Each attempt is sent with the payment's original [`PaymentId`], which LDK allows to be reused
+/// once it failed the previous attempt.
+pub(crate) struct PaymentRetrier<K: KVStore + Sync + Send, L: Deref>
+where
+	L::Target: Logger,
+{
+	policy: RetryPolicy,
+	pending_retries: Mutex<HashMap<PaymentHash, PendingPaymentRetry>>,
+	channel_manager: Arc<ChannelManager<K>>,
+	payment_store: Arc<PaymentStore<K, L>>,
+	event_queue: Arc<EventQueue<K, L>>,
+	kv_store: Arc<K>,
+	logger: L,
+}
+
+impl<K: KVStore + Sync + Send + 'static, L: Deref> PaymentRetrier<K, L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(
+		policy: RetryPolicy, pending_retries: Vec<PendingPaymentRetry>,
+		channel_manager: Arc<ChannelManager<K>>, payment_store: Arc<PaymentStore<K, L>>,
+		event_queue: Arc<EventQueue<K, L>>, kv_store: Arc<K>, logger: L,
+	) -> Self {
+		let pending_retries = Mutex::new(
+			pending_retries.into_iter().map(|retry| (retry.payment_hash, retry)).collect(),
+		);
+		Self {
+			policy,
+			pending_retries,
+			channel_manager,
+			payment_store,
+			event_queue,
+			kv_store,
+			logger,
+		}
+	}
+
+	/// Starts tracking the given payment after its initial attempt was sent successfully.
+	///
+	/// As the payment is already underway, we don't fail if its retry state can't be persisted,
+	/// in which case it's retried as usual but not after a restart.
+	///
+	/// This is a no-op if the policy doesn't allow for any retries.
+	pub(crate) fn register(
+		&self, payment_hash: PaymentHash, payment_id: PaymentId,
+		payment_preimage: Option<PaymentPreimage>, recipient_onion: &RecipientOnionFields,
+		route_params: RouteParameters,
+	) {
+		if self.policy.max_attempts <= 1 {
+			return;
+		}
+
+		let custom_tlvs = recipient_onion.custom_tlvs().iter().map(|tlv| tlv.into()).collect();
+		let retry = PendingPaymentRetry {
+			payment_hash,
+			payment_id,
+			payment_preimage,
+			payment_secret: recipient_onion.payment_secret,
+			payment_metadata: recipient_onion.payment_metadata.clone(),
+			custom_tlvs,
+			route_params,
+			attempts: 1,
+			first_attempt_timestamp: unix_time_secs(),
+			next_attempt_timestamp: None,
+		};
+
+		let mut locked_retries = self.pending_retries.lock().unwrap();
+		if self.persist_retry(&retry).is_err() {
+			log_error!(
+				self.logger,
+				"Failed to persist retry state of payment with hash {}, it won't be retried after a restart",
+				hex_utils::to_string(&payment_hash.0)
+			);
+		}
+		locked_retries.insert(payment_hash, retry);
+	}
+
+	/// Handles the failure of the most recent attempt to send the given payment.
+	///
+	/// Returns `true` if a retry was scheduled, in which case the failure must not be surfaced to
+	/// the user yet.
+	pub(crate) fn handle_payment_failed(
+		&self, payment_hash: &PaymentHash, reason: Option<PaymentFailureReason>,
+	) -> bool {
+		let mut locked_retries = self.pending_retries.lock().unwrap();
+		let retry = match locked_retries.get_mut(payment_hash) {
+			Some(retry) => retry,
+			None => return false,
+		};
+
+		let now = unix_time_secs();
+		let is_retryable = matches!(
+			reason,
+			Some(PaymentFailureReason::RetriesExhausted)
+				| Some(PaymentFailureReason::RouteNotFound)
+				| Some(PaymentFailureReason::UnexpectedError)
+		);
+		let elapsed_secs = now.saturating_sub(retry.first_attempt_timestamp);
+		if !is_retryable
+			|| retry.attempts >= self.policy.max_attempts
+			|| elapsed_secs >= self.policy.max_total_time_secs
+		{
+			locked_retries.remove(payment_hash);
+			self.remove_retry(payment_hash);
+			return false;
+		}
+
+		let backoff_secs = self
+			.policy
+			.backoff_secs
+			.saturating_mul(1u64 << (retry.attempts - 1).min(32))
+			.min(self.policy.max_total_time_secs - elapsed_secs);
+		retry.next_attempt_timestamp = Some(now + backoff_secs);
+		if self.persist_retry(retry).is_err() {
+			locked_retries.remove(payment_hash);
+			self.remove_retry(payment_hash);
+			return false;
+		}
+
+		log_info!(
+			self.logger,
+			"Attempt {} to send payment with hash {} failed, retrying in {}s",
+			retry.attempts,
+			hex_utils::to_string(&payment_hash.0),
+			backoff_secs
+		);
+		true
+	}
+
+	/// Makes a new attempt to send any payments whose backoff period has elapsed.
+	///
+	/// Payments whose new attempt can't be sent are failed.
+	pub(crate) fn process_pending_retries(&self) {
+		let mut locked_retries = self.pending_retries.lock().unwrap();
+		let now = unix_time_secs();
+		let due_hashes: Vec<PaymentHash> = locked_retries
+			.values()
+			.filter(|retry| retry.next_attempt_timestamp.map_or(false, |t| t <= now))
+			.map(|retry| retry.payment_hash)
+			.collect();
+
+		for payment_hash in due_hashes {
+			self.retry_payment(&mut locked_retries, &payment_hash);
+		}
+	}
+
+	fn retry_payment(
+		&self, locked_retries: &mut HashMap<PaymentHash, PendingPaymentRetry>,
+		payment_hash: &PaymentHash,
+	) {
+		let retry = match locked_retries.get_mut(payment_hash) {
+			Some(retry) => retry,
+			None => return,
+		};
+
+		let payment_id = retry.payment_id;
+		let custom_tlvs = retry.custom_tlvs.iter().map(|tlv| (tlv.type_num, tlv.value.clone()));
+		let mut recipient_onion = RecipientOnionFields::spontaneous_empty();
+		recipient_onion.payment_secret = retry.payment_secret;
+		recipient_onion.payment_metadata = retry.payment_metadata.clone();
+		let recipient_onion = match recipient_onion.with_custom_tlvs(custom_tlvs.collect()) {
+			Ok(recipient_onion) => recipient_onion,
+			Err(()) => {
+				log_error!(self.logger, "Failed to retry payment due to invalid custom TLVs");
+				locked_retries.remove(payment_hash);
+				self.remove_retry(payment_hash);
+				self.fail_payment(*payment_hash, PaymentFailureReason::UnexpectedError);
+				return;
+			},
+		};
+		let retry_strategy = Retry::Timeout(LDK_PAYMENT_RETRY_TIMEOUT);
+		let res = match retry.payment_preimage {
+			Some(payment_preimage) => self
+				.channel_manager
+				.send_spontaneous_payment_with_retry(
+					Some(payment_preimage),
+					recipient_onion,
+					payment_id,
+					retry.route_params.clone(),
+					retry_strategy,
+				)
+				.map(|_| ()),
+			None => self.channel_manager.send_payment(
+				*payment_hash,
+				recipient_onion,
+				payment_id,
+				retry.route_params.clone(),
+				retry_strategy,
+			),
+		};
+
+		match res {
+			Ok(()) => {
+				retry.attempts += 1;
+				retry.next_attempt_timestamp = None;
+				log_info!(
+					self.logger,
+					"Initiated attempt {} to send payment with hash {}",
+					retry.attempts,
+					hex_utils::to_string(&payment_hash.0)
+				);
+				let _ = self.persist_retry(retry);
+			},
+			Err(e) => {
+				log_error!(self.logger, "Failed to retry payment: {:?}", e);
+				locked_retries.remove(payment_hash);
+				self.remove_retry(payment_hash);
+				let reason = match e {
+					RetryableSendFailure::RouteNotFound => PaymentFailureReason::RouteNotFound,
+					RetryableSendFailure::PaymentExpired => PaymentFailureReason::PaymentExpired,
+					_ => PaymentFailureReason::UnexpectedError,
+				};
+				self.fail_payment(*payment_hash, reason);
+			},
+		}
+	}
+
+	/// Stops tracking the given payment after it succeeded.
+	pub(crate) fn handle_payment_sent(&self, payment_hash: &PaymentHash) {
+		if self.pending_retries.lock().unwrap().remove(payment_hash).is_some() {
+			self.remove_retry(payment_hash);
+		}
+	}
+
+	/// Abandons the given payment, cancelling any further retries.
+	///
+	/// The payment will be failed with [`PaymentFailureReason::UserAbandoned`] once any in-flight
+	/// attempt has been abandoned.
+	pub(crate) fn abandon(&self, payment_hash: &PaymentHash, payment_id: PaymentId) {
+		let retry = self.pending_retries.lock().unwrap().remove(payment_hash);
+		match retry {
+			Some(retry) => {
+				self.remove_retry(payment_hash);
+				if retry.next_attempt_timestamp.is_some() {
+					// LDK already failed the most recent attempt, so we fail the payment ourselves.
+					self.fail_payment(*payment_hash, PaymentFailureReason::UserAbandoned);
+				} else {
+					self.channel_manager.abandon_payment(payment_id);
+				}
+			},
+			None => self.channel_manager.abandon_payment(payment_id),
+		}
+	}
+
+	fn fail_payment(&self, payment_hash: PaymentHash, reason: PaymentFailureReason) {
+		let update = PaymentDetailsUpdate {
+			status: Some(PaymentStatus::Failed),
+			..PaymentDetailsUpdate::new(payment_hash)
+		};
+		if let Err(e) = self.payment_store.update(&update) {
+			log_error!(self.logger, "Failed to access payment store: {}", e);
+		}
+		if let Err(e) =
+			self.event_queue.add_event(Event::PaymentFailed { payment_hash, reason: Some(reason) })
+		{
+			log_error!(self.logger, "Failed to push to event queue: {}", e);
+		}
+	}
+
+	fn persist_retry(&self, retry: &PendingPaymentRetry) -> Result<(), Error> {
+		let store_key = hex_utils::to_string(&retry.payment_hash.0);
+		let data = retry.encode();
+		self.kv_store
+			.write(
+				PAYMENT_RETRY_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_RETRY_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				&data,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					PAYMENT_RETRY_PERSISTENCE_PRIMARY_NAMESPACE,
+					PAYMENT_RETRY_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})
+	}
+
+	fn remove_retry(&self, payment_hash: &PaymentHash) {
+		let store_key = hex_utils::to_string(&payment_hash.0);
+		if let Err(e) = self.kv_store.remove(
+			PAYMENT_RETRY_PERSISTENCE_PRIMARY_NAMESPACE,
+			PAYMENT_RETRY_PERSISTENCE_SECONDARY_NAMESPACE,
+			&store_key,
+			false,
+		) {
+			log_error!(
+				self.logger,
+				"Removing payment retry data for key {}/{}/{} failed due to: {}",
+				PAYMENT_RETRY_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_RETRY_PERSISTENCE_SECONDARY_NAMESPACE,
+				store_key,
+				e
+			);
+		}
+	}
+}
+
+fn unix_time_secs() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use lightning::routing::router::PaymentParameters;
+	use lightning::util::ser::Readable;
+
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+	#[test]
+	fn pending_payment_retry_serialization_roundtrip() {
+		let secp_ctx = Secp256k1::new();
+		let node_id =
+			PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		let retry = PendingPaymentRetry {
+			payment_hash: PaymentHash([1; 32]),
+			payment_id: PaymentId([2; 32]),
+			payment_preimage: Some(PaymentPreimage([3; 32])),
+			payment_secret: None,
+			payment_metadata: None,
+			custom_tlvs: vec![CustomTlvRecord { type_num: 65537, value: vec![4; 8] }],
+			route_params: RouteParameters::from_payment_params_and_value(
+				PaymentParameters::from_node_id(node_id, 144),
+				1000,
+			),
+			attempts: 2,
+			first_attempt_timestamp: 1_700_000_000,
+			next_attempt_timestamp: Some(1_700_000_010),
+		};
+
+		let encoded = retry.encode();
+		let decoded = PendingPaymentRetry::read(&mut &encoded[..]).unwrap();
+		assert_eq!(retry, decoded);
+	}
+}
@@ -1,25 +1,34 @@
 use crate::hex_utils;
 use crate::io::{
 	PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE, PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+	PENDING_REFUND_PERSISTENCE_PRIMARY_NAMESPACE, PENDING_REFUND_PERSISTENCE_SECONDARY_NAMESPACE,
 };
 use crate::logger::{log_error, Logger};
+use crate::types::{CustomTlvRecord, DescriptionHash};
 use crate::Error;
 
+use lightning::ln::channelmanager::PaymentId;
 use lightning::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 use lightning::util::persist::KVStore;
-use lightning::util::ser::Writeable;
+use lightning::util::ser::{Readable, Writeable};
 use lightning::{impl_writeable_tlv_based, impl_writeable_tlv_based_enum};
 
 use std::collections::HashMap;
 use std::iter::FromIterator;
 use std::ops::Deref;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
 
 /// Represents a payment.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PaymentDetails {
 	/// The payment hash, i.e., the hash of the `preimage`.
 	pub hash: PaymentHash,
+	/// The [`PaymentId`] the payment was sent with.
+	///
+	/// This is only `Some` for outbound payments, and will be `None` for payments that were
+	/// persisted by a previous version of LDK Node.
+	pub id: Option<PaymentId>,
 	/// The pre-image used by the payment.
 	pub preimage: Option<PaymentPreimage>,
 	/// The secret used by the payment.
@@ -39,16 +48,71 @@ pub struct PaymentDetails {
 	///
 	/// [`LdkChannelConfig::accept_underpaying_htlcs`]: lightning::util::config::ChannelConfig::accept_underpaying_htlcs
 	pub lsp_fee_limits: Option<LSPFeeLimits>,
+	/// The kind of the payment.
+	///
+	/// This will be `None` for payments that were persisted by a previous version of LDK Node.
+	pub kind: Option<PaymentKind>,
+	/// The description hash the invoice of the payment committed to.
+	///
+	/// This is only `Some` for received [BOLT 11] payments for which the invoice was created with a
+	/// description hash in place of a description.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	pub description_hash: Option<DescriptionHash>,
+	/// The amount we expected to receive for a "zero-amount" invoice.
+	///
+	/// This is purely informational, as any amount will be accepted for such an invoice.
+	pub expected_amount_msat: Option<u64>,
+	/// The time, in seconds since the UNIX epoch, at which the invoice of an inbound payment
+	/// expires.
+	///
+	/// This is only `Some` for [BOLT 11] invoices created by us. Once expired, pending payments
+	/// are marked as [`PaymentStatus::Expired`].
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	pub invoice_expiry_timestamp: Option<u64>,
+	/// The custom TLV records that were sent or received along with the payment.
+	pub custom_tlvs: Vec<CustomTlvRecord>,
+}
+
+impl PaymentDetails {
+	/// Returns the preimage of the payment if it has been settled.
+	///
+	/// As the preimage is only revealed by the recipient when the payment is claimed, it serves as
+	/// a proof of payment. Will return `None` for pending or failed payments.
+	pub fn settled_preimage(&self) -> Option<PaymentPreimage> {
+		match self.status {
+			PaymentStatus::Succeeded => self.preimage,
+			PaymentStatus::Pending | PaymentStatus::Failed | PaymentStatus::Expired => None,
+		}
+	}
+
+	/// Returns the payment secret of the payment if it has been settled.
+	///
+	/// Will return `None` for pending or failed payments, as well as for payments that don't
+	/// use a payment secret, such as spontaneous payments.
+	pub fn settled_secret(&self) -> Option<PaymentSecret> {
+		match self.status {
+			PaymentStatus::Succeeded => self.secret,
+			PaymentStatus::Pending | PaymentStatus::Failed | PaymentStatus::Expired => None,
+		}
+	}
 }
 
 impl_writeable_tlv_based!(PaymentDetails, {
 	(0, hash, required),
 	(1, lsp_fee_limits, option),
 	(2, preimage, required),
+	(3, kind, option),
 	(4, secret, required),
+	(5, description_hash, option),
 	(6, amount_msat, required),
+	(7, expected_amount_msat, option),
 	(8, direction, required),
-	(10, status, required)
+	(9, invoice_expiry_timestamp, option),
+	(10, status, required),
+	(11, id, option),
+	(13, custom_tlvs, optional_vec),
 });
 
 /// Represents the direction of a payment.
@@ -74,12 +138,65 @@ pub enum PaymentStatus {
 	Succeeded,
 	/// The payment failed.
 	Failed,
+	/// The invoice of the inbound payment expired before it was paid.
+	///
+	/// Note that a payment may still be received shortly after the invoice expired, in which case
+	/// the status will change to [`PaymentStatus::Succeeded`].
+	Expired,
+}
+
+impl PaymentStatus {
+	/// Returns whether the status is terminal, i.e., the payment is no longer in flight.
+	///
+	/// Note that, as documented on [`PaymentStatus::Expired`], an expired inbound payment may
+	/// still succeed if it is received shortly after the invoice expired.
+	pub fn is_final(&self) -> bool {
+		match self {
+			PaymentStatus::Pending => false,
+			PaymentStatus::Succeeded | PaymentStatus::Failed | PaymentStatus::Expired => true,
+		}
+	}
 }
 
 impl_writeable_tlv_based_enum!(PaymentStatus,
 	(0, Pending) => {},
 	(2, Succeeded) => {},
-	(4, Failed) => {};
+	(4, Failed) => {},
+	(6, Expired) => {};
+);
+
+/// Represents the kind of a payment.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PaymentKind {
+	/// A [BOLT 11] payment.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	Bolt11,
+	/// A [BOLT 11] payment intended to open a just-in-time (JIT) channel.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	Bolt11Jit,
+	/// A spontaneous ("keysend") payment.
+	Spontaneous,
+	/// A [BOLT 12] payment made for an offer.
+	///
+	/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+	Bolt12,
+	/// A [BOLT 12] payment made for a refund.
+	///
+	/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+	Bolt12Refund,
+	/// A circular payment to ourselves, moving liquidity between two of our channels.
+	Rebalance,
+}
+
+impl_writeable_tlv_based_enum!(PaymentKind,
+	(0, Bolt11) => {},
+	(2, Bolt11Jit) => {},
+	(4, Spontaneous) => {},
+	(6, Bolt12) => {},
+	(8, Bolt12Refund) => {},
+	(10, Rebalance) => {};
 );
 
 /// Limits applying to how much fee we allow an LSP to deduct from the payment amount.
@@ -111,6 +228,7 @@ pub(crate) struct PaymentDetailsUpdate {
 	pub direction: Option<PaymentDirection>,
 	pub status: Option<PaymentStatus>,
 	pub lsp_fee_limits: Option<Option<LSPFeeLimits>>,
+	pub custom_tlvs: Option<Vec<CustomTlvRecord>>,
 }
 
 impl PaymentDetailsUpdate {
@@ -123,6 +241,7 @@ impl PaymentDetailsUpdate {
 			direction: None,
 			status: None,
 			lsp_fee_limits: None,
+			custom_tlvs: None,
 		}
 	}
 }
@@ -132,6 +251,8 @@ where
 	L::Target: Logger,
 {
 	payments: Mutex<HashMap<PaymentHash, PaymentDetails>>,
+	payment_ids: Mutex<HashMap<PaymentId, PaymentHash>>,
+	status_notifier: Condvar,
 	kv_store: Arc<K>,
 	logger: L,
 }
@@ -141,10 +262,14 @@ where
 	L::Target: Logger,
 {
 	pub(crate) fn new(payments: Vec<PaymentDetails>, kv_store: Arc<K>, logger: L) -> Self {
+		let payment_ids = Mutex::new(HashMap::from_iter(
+			payments.iter().filter_map(|payment| payment.id.map(|id| (id, payment.hash))),
+		));
 		let payments = Mutex::new(HashMap::from_iter(
 			payments.into_iter().map(|payment| (payment.hash, payment)),
 		));
-		Self { payments, kv_store, logger }
+		let status_notifier = Condvar::new();
+		Self { payments, payment_ids, status_notifier, kv_store, logger }
 	}
 
 	pub(crate) fn insert(&self, payment: PaymentDetails) -> Result<bool, Error> {
@@ -152,11 +277,20 @@ where
 
 		let hash = payment.hash.clone();
 		let updated = locked_payments.insert(hash.clone(), payment.clone()).is_some();
+		if let Some(id) = payment.id {
+			self.payment_ids.lock().unwrap().insert(id, hash);
+		}
 		self.persist_info(&hash, &payment)?;
+		self.status_notifier.notify_all();
 		Ok(updated)
 	}
 
 	pub(crate) fn remove(&self, hash: &PaymentHash) -> Result<(), Error> {
+		let mut locked_payments = self.payments.lock().unwrap();
+		if let Some(id) = locked_payments.remove(hash).and_then(|payment| payment.id) {
+			self.payment_ids.lock().unwrap().remove(&id);
+		}
+
 		let store_key = hex_utils::to_string(&hash.0);
 		self.kv_store
 			.remove(
@@ -182,6 +316,36 @@ where
 		self.payments.lock().unwrap().get(hash).cloned()
 	}
 
+	pub(crate) fn get_by_id(&self, id: &PaymentId) -> Option<PaymentDetails> {
+		let locked_payments = self.payments.lock().unwrap();
+		let hash = self.payment_ids.lock().unwrap().get(id).copied()?;
+		locked_payments.get(&hash).cloned()
+	}
+
+	/// Blocks until the payment with the given id reached a final [`PaymentStatus`] or the
+	/// timeout elapsed.
+	///
+	/// Returns `None` if the payment is unknown and `Some(Err(..))` with the last known status if
+	/// the timeout elapsed.
+	pub(crate) fn wait_for_final_status(
+		&self, id: &PaymentId, timeout: Duration,
+	) -> Option<Result<PaymentStatus, PaymentStatus>> {
+		let hash = self.payment_ids.lock().unwrap().get(id).copied()?;
+		let locked_payments = self.payments.lock().unwrap();
+		let (locked_payments, _) = self
+			.status_notifier
+			.wait_timeout_while(locked_payments, timeout, |payments| {
+				payments.get(&hash).map_or(false, |p| !p.status.is_final())
+			})
+			.unwrap();
+		let status = locked_payments.get(&hash)?.status;
+		if status.is_final() {
+			Some(Ok(status))
+		} else {
+			Some(Err(status))
+		}
+	}
+
 	pub(crate) fn update(&self, update: &PaymentDetailsUpdate) -> Result<bool, Error> {
 		let mut updated = false;
 		let mut locked_payments = self.payments.lock().unwrap();
@@ -207,13 +371,51 @@ where
 				payment.lsp_fee_limits = lsp_fee_limits
 			}
 
+			if let Some(custom_tlvs) = &update.custom_tlvs {
+				payment.custom_tlvs = custom_tlvs.clone();
+			}
+
 			self.persist_info(&update.hash, payment)?;
 			updated = true;
 		}
 
+		if updated {
+			self.status_notifier.notify_all();
+		}
+
 		Ok(updated)
 	}
 
+	/// Marks any pending inbound payments whose invoice expired at or before `now_secs` as
+	/// [`PaymentStatus::Expired`], skipping the payments given in `excluded_hashes`.
+	///
+	/// Returns the hashes of the payments that were marked as expired.
+	pub(crate) fn expire_invoices(
+		&self, now_secs: u64, excluded_hashes: &[PaymentHash],
+	) -> Result<Vec<PaymentHash>, Error> {
+		let mut expired_hashes = Vec::new();
+		let mut locked_payments = self.payments.lock().unwrap();
+
+		for payment in locked_payments.values_mut() {
+			let is_expired = payment.direction == PaymentDirection::Inbound
+				&& payment.status == PaymentStatus::Pending
+				&& payment.invoice_expiry_timestamp.map_or(false, |t| t <= now_secs);
+			if !is_expired || excluded_hashes.contains(&payment.hash) {
+				continue;
+			}
+
+			payment.status = PaymentStatus::Expired;
+			self.persist_info(&payment.hash, payment)?;
+			expired_hashes.push(payment.hash);
+		}
+
+		if !expired_hashes.is_empty() {
+			self.status_notifier.notify_all();
+		}
+
+		Ok(expired_hashes)
+	}
+
 	pub(crate) fn list_filter<F: FnMut(&&PaymentDetails) -> bool>(
 		&self, f: F,
 	) -> Vec<PaymentDetails> {
@@ -227,6 +429,82 @@ where
 			.collect::<Vec<PaymentDetails>>()
 	}
 
+	/// Returns up to `limit` payments matching the given predicate, skipping the first `offset`
+	/// matches.
+	///
+	/// Payments are ordered by their payment hash so that consecutive pages are consistent.
+	pub(crate) fn list_filter_paginated<F: FnMut(&&PaymentDetails) -> bool>(
+		&self, f: F, offset: usize, limit: usize,
+	) -> Vec<PaymentDetails> {
+		let locked_payments = self.payments.lock().unwrap();
+		let mut matches = locked_payments.values().filter(f).collect::<Vec<&PaymentDetails>>();
+		matches.sort_unstable_by(|a, b| a.hash.0.cmp(&b.hash.0));
+		matches.into_iter().skip(offset).take(limit).cloned().collect::<Vec<PaymentDetails>>()
+	}
+
+	/// Remembers that we initiated a refund of the given amount, so that the outbound payment for
+	/// it can be recognized once it was sent.
+	pub(crate) fn add_pending_refund(
+		&self, payment_id: PaymentId, amount_msat: u64,
+	) -> Result<(), Error> {
+		let store_key = hex_utils::to_string(&payment_id.0);
+		self.kv_store
+			.write(
+				PENDING_REFUND_PERSISTENCE_PRIMARY_NAMESPACE,
+				PENDING_REFUND_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				&amount_msat.encode(),
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					PENDING_REFUND_PERSISTENCE_PRIMARY_NAMESPACE,
+					PENDING_REFUND_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})
+	}
+
+	/// Returns and forgets the amount of the pending refund with the given payment id, or `None`
+	/// if we didn't initiate a refund with that id.
+	pub(crate) fn take_pending_refund(&self, payment_id: &PaymentId) -> Option<u64> {
+		let store_key = hex_utils::to_string(&payment_id.0);
+		let data = self
+			.kv_store
+			.read(
+				PENDING_REFUND_PERSISTENCE_PRIMARY_NAMESPACE,
+				PENDING_REFUND_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+			)
+			.ok()?;
+		let amount_msat: u64 = Readable::read(&mut &data[..])
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to deserialize pending refund amount: {}", e);
+			})
+			.ok()?;
+		self.kv_store
+			.remove(
+				PENDING_REFUND_PERSISTENCE_PRIMARY_NAMESPACE,
+				PENDING_REFUND_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				false,
+			)
+			.unwrap_or_else(|e| {
+				log_error!(
+					self.logger,
+					"Removing pending refund for key {}/{}/{} failed due to: {}",
+					PENDING_REFUND_PERSISTENCE_PRIMARY_NAMESPACE,
+					PENDING_REFUND_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+			});
+		Some(amount_msat)
+	}
+
 	fn persist_info(&self, hash: &PaymentHash, payment: &PaymentDetails) -> Result<(), Error> {
 		let store_key = hex_utils::to_string(&hash.0);
 		let data = payment.encode();
@@ -278,12 +556,18 @@ mod tests {
 
 		let payment = PaymentDetails {
 			hash,
+			id: None,
 			preimage: None,
 			secret: None,
 			amount_msat: None,
 			direction: PaymentDirection::Inbound,
 			status: PaymentStatus::Pending,
 			lsp_fee_limits: None,
+			kind: Some(PaymentKind::Bolt11),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: None,
+			custom_tlvs: Vec::new(),
 		};
 
 		assert_eq!(Ok(false), payment_store.insert(payment.clone()));
@@ -305,5 +589,298 @@ mod tests {
 		assert!(payment_store.get(&hash).is_some());
 
 		assert_eq!(PaymentStatus::Succeeded, payment_store.get(&hash).unwrap().status);
+
+		// Custom TLVs survive a reload from the store.
+		let custom_tlvs = vec![CustomTlvRecord { type_num: 65537, value: vec![1, 2, 3] }];
+		let mut update = PaymentDetailsUpdate::new(hash);
+		update.custom_tlvs = Some(custom_tlvs.clone());
+		assert_eq!(Ok(true), payment_store.update(&update));
+		let persisted_bytes = store
+			.read(
+				PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+			)
+			.unwrap();
+		let persisted_payment = PaymentDetails::read(&mut &persisted_bytes[..]).unwrap();
+		assert_eq!(persisted_payment.custom_tlvs, custom_tlvs);
+	}
+
+	#[test]
+	fn payment_details_serialization_roundtrip() {
+		let payment = PaymentDetails {
+			hash: PaymentHash([42u8; 32]),
+			id: Some(PaymentId([23u8; 32])),
+			preimage: Some(PaymentPreimage([43u8; 32])),
+			secret: Some(PaymentSecret([44u8; 32])),
+			amount_msat: Some(1000),
+			direction: PaymentDirection::Inbound,
+			status: PaymentStatus::Succeeded,
+			lsp_fee_limits: Some(LSPFeeLimits {
+				max_total_opening_fee_msat: Some(2000),
+				max_proportional_opening_fee_ppm_msat: Some(3000),
+			}),
+			kind: Some(PaymentKind::Bolt11Jit),
+			description_hash: Some(DescriptionHash([45u8; 32])),
+			expected_amount_msat: Some(4000),
+			invoice_expiry_timestamp: Some(5000),
+			custom_tlvs: vec![CustomTlvRecord { type_num: 65537, value: vec![1, 2, 3] }],
+		};
+		let encoded = payment.encode();
+		assert_eq!(PaymentDetails::read(&mut &encoded[..]).unwrap(), payment);
+	}
+
+	#[test]
+	fn payment_details_persisted_by_previous_versions_can_be_read() {
+		// The `PaymentDetails` as persisted before any of the optional fields were added.
+		struct LegacyPaymentDetails {
+			hash: PaymentHash,
+			preimage: Option<PaymentPreimage>,
+			secret: Option<PaymentSecret>,
+			amount_msat: Option<u64>,
+			direction: PaymentDirection,
+			status: PaymentStatus,
+			lsp_fee_limits: Option<LSPFeeLimits>,
+		}
+
+		impl_writeable_tlv_based!(LegacyPaymentDetails, {
+			(0, hash, required),
+			(1, lsp_fee_limits, option),
+			(2, preimage, required),
+			(4, secret, required),
+			(6, amount_msat, required),
+			(8, direction, required),
+			(10, status, required)
+		});
+
+		let legacy_payment = LegacyPaymentDetails {
+			hash: PaymentHash([42u8; 32]),
+			preimage: Some(PaymentPreimage([43u8; 32])),
+			secret: None,
+			amount_msat: Some(1000),
+			direction: PaymentDirection::Outbound,
+			status: PaymentStatus::Failed,
+			lsp_fee_limits: None,
+		};
+		let encoded = legacy_payment.encode();
+		let payment = PaymentDetails::read(&mut &encoded[..]).unwrap();
+		assert_eq!(payment.hash, legacy_payment.hash);
+		assert_eq!(payment.preimage, legacy_payment.preimage);
+		assert_eq!(payment.secret, None);
+		assert_eq!(payment.amount_msat, Some(1000));
+		assert_eq!(payment.direction, PaymentDirection::Outbound);
+		assert_eq!(payment.status, PaymentStatus::Failed);
+		assert_eq!(payment.lsp_fee_limits, None);
+		assert_eq!(payment.id, None);
+		assert_eq!(payment.kind, None);
+		assert_eq!(payment.description_hash, None);
+		assert_eq!(payment.expected_amount_msat, None);
+		assert_eq!(payment.invoice_expiry_timestamp, None);
+		assert!(payment.custom_tlvs.is_empty());
+	}
+
+	#[test]
+	fn pending_refunds_are_persisted() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let payment_store = PaymentStore::new(Vec::new(), Arc::clone(&store), Arc::clone(&logger));
+
+		let payment_id = PaymentId([42u8; 32]);
+		assert_eq!(payment_store.take_pending_refund(&payment_id), None);
+		payment_store.add_pending_refund(payment_id, 1000).unwrap();
+
+		// Pending refunds survive a restart, but are only returned once.
+		let payment_store = PaymentStore::new(Vec::new(), Arc::clone(&store), logger);
+		assert_eq!(payment_store.take_pending_refund(&PaymentId([23u8; 32])), None);
+		assert_eq!(payment_store.take_pending_refund(&payment_id), Some(1000));
+		assert_eq!(payment_store.take_pending_refund(&payment_id), None);
+	}
+
+	#[test]
+	fn payments_can_be_looked_up_and_awaited_by_id() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let hash = PaymentHash([42u8; 32]);
+		let id = PaymentId([23u8; 32]);
+		let payment = PaymentDetails {
+			hash,
+			id: Some(id),
+			preimage: None,
+			secret: None,
+			amount_msat: Some(1000),
+			direction: PaymentDirection::Outbound,
+			status: PaymentStatus::Pending,
+			lsp_fee_limits: None,
+			kind: Some(PaymentKind::Bolt11),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: None,
+			custom_tlvs: Vec::new(),
+		};
+
+		// The index is populated from the payments read on startup.
+		let payment_store =
+			Arc::new(PaymentStore::new(vec![payment.clone()], Arc::clone(&store), logger));
+		assert_eq!(payment_store.get_by_id(&id), Some(payment.clone()));
+		assert_eq!(payment_store.get_by_id(&PaymentId(hash.0)), None);
+		assert_eq!(payment_store.get_by_id(&PaymentId([43u8; 32])), None);
+		assert_eq!(
+			payment_store.wait_for_final_status(&PaymentId([43u8; 32]), Duration::from_millis(1)),
+			None
+		);
+
+		assert_eq!(
+			payment_store.wait_for_final_status(&id, Duration::from_millis(10)),
+			Some(Err(PaymentStatus::Pending))
+		);
+
+		let updating_store = Arc::clone(&payment_store);
+		let handle = std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(50));
+			let mut update = PaymentDetailsUpdate::new(hash);
+			update.status = Some(PaymentStatus::Succeeded);
+			updating_store.update(&update).unwrap();
+		});
+		assert_eq!(
+			payment_store.wait_for_final_status(&id, Duration::from_secs(10)),
+			Some(Ok(PaymentStatus::Succeeded))
+		);
+		handle.join().unwrap();
+
+		// Removing the payment also drops it from the index.
+		payment_store.remove(&hash).unwrap();
+		assert_eq!(payment_store.get(&hash), None);
+		assert_eq!(payment_store.get_by_id(&id), None);
+		assert_eq!(payment_store.wait_for_final_status(&id, Duration::from_millis(1)), None);
+	}
+
+	#[test]
+	fn settled_preimage_and_secret_are_only_exposed_once_succeeded() {
+		let mut payment = PaymentDetails {
+			hash: PaymentHash([42u8; 32]),
+			id: None,
+			preimage: Some(PaymentPreimage([43u8; 32])),
+			secret: Some(PaymentSecret([44u8; 32])),
+			amount_msat: Some(1000),
+			direction: PaymentDirection::Outbound,
+			status: PaymentStatus::Pending,
+			lsp_fee_limits: None,
+			kind: Some(PaymentKind::Spontaneous),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: None,
+			custom_tlvs: Vec::new(),
+		};
+		assert_eq!(payment.settled_preimage(), None);
+		assert_eq!(payment.settled_secret(), None);
+
+		payment.status = PaymentStatus::Failed;
+		assert_eq!(payment.settled_preimage(), None);
+		assert_eq!(payment.settled_secret(), None);
+
+		payment.status = PaymentStatus::Succeeded;
+		assert_eq!(payment.settled_preimage(), Some(PaymentPreimage([43u8; 32])));
+		assert_eq!(payment.settled_secret(), Some(PaymentSecret([44u8; 32])));
+	}
+
+	#[test]
+	fn payments_can_be_filtered_and_paginated() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let payment_store = PaymentStore::new(Vec::new(), Arc::clone(&store), logger);
+
+		for i in 0..10u8 {
+			let direction =
+				if i % 2 == 0 { PaymentDirection::Inbound } else { PaymentDirection::Outbound };
+			let payment = PaymentDetails {
+				hash: PaymentHash([i; 32]),
+				id: None,
+				preimage: None,
+				secret: None,
+				amount_msat: None,
+				direction,
+				status: PaymentStatus::Pending,
+				lsp_fee_limits: None,
+				kind: Some(PaymentKind::Bolt11),
+				description_hash: None,
+				expected_amount_msat: None,
+				invoice_expiry_timestamp: None,
+				custom_tlvs: Vec::new(),
+			};
+			payment_store.insert(payment).unwrap();
+		}
+
+		let inbound = payment_store.list_filter(|p| p.direction == PaymentDirection::Inbound);
+		assert_eq!(inbound.len(), 5);
+
+		let first_page = payment_store.list_filter_paginated(|_| true, 0, 4);
+		let second_page = payment_store.list_filter_paginated(|_| true, 4, 4);
+		let last_page = payment_store.list_filter_paginated(|_| true, 8, 4);
+		assert_eq!(first_page.len(), 4);
+		assert_eq!(second_page.len(), 4);
+		assert_eq!(last_page.len(), 2);
+		assert_eq!(first_page[0].hash, PaymentHash([0; 32]));
+		assert_eq!(second_page[0].hash, PaymentHash([4; 32]));
+		assert_eq!(last_page[1].hash, PaymentHash([9; 32]));
+
+		let outbound_page = payment_store.list_filter_paginated(
+			|p| p.direction == PaymentDirection::Outbound,
+			1,
+			2,
+		);
+		assert_eq!(outbound_page.len(), 2);
+		assert_eq!(outbound_page[0].hash, PaymentHash([3; 32]));
+		assert_eq!(outbound_page[1].hash, PaymentHash([5; 32]));
+
+		assert!(payment_store.list_filter_paginated(|_| true, 10, 4).is_empty());
+	}
+
+	#[test]
+	fn expired_invoices_are_marked_as_such() {
+		let store = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let payment_store = PaymentStore::new(Vec::new(), Arc::clone(&store), logger);
+
+		let new_payment = |i: u8, direction: PaymentDirection, expiry: u64| PaymentDetails {
+			hash: PaymentHash([i; 32]),
+			id: None,
+			preimage: None,
+			secret: None,
+			amount_msat: None,
+			direction,
+			status: PaymentStatus::Pending,
+			lsp_fee_limits: None,
+			kind: Some(PaymentKind::Bolt11),
+			description_hash: None,
+			expected_amount_msat: None,
+			invoice_expiry_timestamp: Some(expiry),
+			custom_tlvs: Vec::new(),
+		};
+		payment_store.insert(new_payment(0, PaymentDirection::Inbound, 100)).unwrap();
+		payment_store.insert(new_payment(1, PaymentDirection::Inbound, 200)).unwrap();
+		payment_store.insert(new_payment(2, PaymentDirection::Outbound, 100)).unwrap();
+		payment_store.insert(new_payment(3, PaymentDirection::Inbound, 100)).unwrap();
+
+		let expired_hashes = payment_store.expire_invoices(150, &[PaymentHash([3; 32])]).unwrap();
+		assert_eq!(expired_hashes, vec![PaymentHash([0; 32])]);
+		assert_eq!(
+			payment_store.get(&PaymentHash([0; 32])).unwrap().status,
+			PaymentStatus::Expired
+		);
+		assert_eq!(
+			payment_store.get(&PaymentHash([1; 32])).unwrap().status,
+			PaymentStatus::Pending
+		);
+		assert_eq!(
+			payment_store.get(&PaymentHash([2; 32])).unwrap().status,
+			PaymentStatus::Pending
+		);
+		assert_eq!(
+			payment_store.get(&PaymentHash([3; 32])).unwrap().status,
+			PaymentStatus::Pending
+		);
+
+		// Already expired invoices aren't reported again.
+		assert_eq!(payment_store.expire_invoices(150, &[]).unwrap(), vec![PaymentHash([3; 32])]);
 	}
 }
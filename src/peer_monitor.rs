@@ -0,0 +1,127 @@
+use crate::event::EventQueue;
+use crate::logger::{log_error, log_info, Logger};
+use crate::types::PeerDisconnectReason;
+use crate::Event;
+
+use lightning::ln::msgs::SocketAddress;
+use lightning::util::persist::KVStore;
+
+use bitcoin::secp256k1::PublicKey;
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+/// Keeps track of peer connections and disconnections, optionally emitting
+/// [`Event::PeerConnected`] and [`Event::PeerDisconnected`] events.
+pub(crate) struct PeerConnectionMonitor<K: KVStore + Sync + Send + 'static, L: Deref>
+where
+	L::Target: Logger,
+{
+	connected_peers: Mutex<HashMap<PublicKey, Option<SocketAddress>>>,
+	// Disconnect reasons we learned about before the disconnect was observed.
+	pending_disconnect_reasons: Mutex<HashMap<PublicKey, PeerDisconnectReason>>,
+	last_disconnect_reasons: Mutex<HashMap<PublicKey, PeerDisconnectReason>>,
+	event_queue: Arc<EventQueue<K, L>>,
+	emit_events: bool,
+	logger: L,
+}
+
+impl<K: KVStore + Sync + Send + 'static, L: Deref> PeerConnectionMonitor<K, L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(event_queue: Arc<EventQueue<K, L>>, emit_events: bool, logger: L) -> Self {
+		let connected_peers = Mutex::new(HashMap::new());
+		let pending_disconnect_reasons = Mutex::new(HashMap::new());
+		let last_disconnect_reasons = Mutex::new(HashMap::new());
+		Self {
+			connected_peers,
+			pending_disconnect_reasons,
+			last_disconnect_reasons,
+			event_queue,
+			emit_events,
+			logger,
+		}
+	}
+
+	/// Records that we're about to close the connection to the given peer.
+	pub(crate) fn record_local_disconnect(&self, node_id: PublicKey) {
+		self.pending_disconnect_reasons
+			.lock()
+			.unwrap()
+			.insert(node_id, PeerDisconnectReason::LocallyInitiated);
+	}
+
+	/// Records that a connection attempt to the given peer failed during the handshake.
+	pub(crate) fn record_handshake_failure(&self, node_id: PublicKey) {
+		self.pending_disconnect_reasons
+			.lock()
+			.unwrap()
+			.insert(node_id, PeerDisconnectReason::HandshakeFailed);
+	}
+
+	/// Updates our view of the connected peers, emitting events for any changes.
+	pub(crate) fn update(&self, current_peers: Vec<(PublicKey, Option<SocketAddress>)>) {
+		let mut connected_peers = self.connected_peers.lock().unwrap();
+		let mut pending_disconnect_reasons = self.pending_disconnect_reasons.lock().unwrap();
+		let mut last_disconnect_reasons = self.last_disconnect_reasons.lock().unwrap();
+
+		let mut disconnected = Vec::new();
+		for node_id in connected_peers.keys() {
+			if !current_peers.iter().any(|(id, _)| id == node_id) {
+				let reason = pending_disconnect_reasons
+					.remove(node_id)
+					.unwrap_or(PeerDisconnectReason::RemotelyInitiated);
+				disconnected.push((*node_id, reason));
+			}
+		}
+
+		pending_disconnect_reasons.retain(|node_id, reason| {
+			if current_peers.iter().any(|(id, _)| id == node_id) {
+				// Keep local disconnects around until we observe them.
+				return *reason == PeerDisconnectReason::LocallyInitiated;
+			}
+
+			// Handshake failures never show up as connected peers, so we report them directly.
+			if *reason == PeerDisconnectReason::HandshakeFailed {
+				disconnected.push((*node_id, *reason));
+			}
+			false
+		});
+
+		for (node_id, reason) in disconnected {
+			log_info!(self.logger, "Peer {} disconnected: {:?}", node_id, reason);
+			connected_peers.remove(&node_id);
+			last_disconnect_reasons.insert(node_id, reason);
+			self.emit(Event::PeerDisconnected { node_id, reason });
+		}
+
+		for (node_id, address) in current_peers {
+			if connected_peers.contains_key(&node_id) {
+				continue;
+			}
+
+			log_info!(self.logger, "Peer {} connected", node_id);
+			connected_peers.insert(node_id, address.clone());
+			self.emit(Event::PeerConnected { node_id, address });
+		}
+	}
+
+	/// Returns the reason the last observed connection to the given peer was closed, if any.
+	pub(crate) fn last_disconnect_reason(
+		&self, node_id: &PublicKey,
+	) -> Option<PeerDisconnectReason> {
+		self.last_disconnect_reasons.lock().unwrap().get(node_id).copied()
+	}
+
+	fn emit(&self, event: Event) {
+		if !self.emit_events {
+			return;
+		}
+
+		self.event_queue.add_event(event).unwrap_or_else(|e| {
+			log_error!(self.logger, "Failed to push to event queue: {}", e);
+		});
+	}
+}
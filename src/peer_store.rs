@@ -3,7 +3,7 @@ use crate::io::{
 	PEER_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
 };
 use crate::logger::{log_error, Logger};
-use crate::{Error, SocketAddress};
+use crate::{Error, PeerTransport, SocketAddress};
 
 use lightning::impl_writeable_tlv_based;
 use lightning::util::persist::KVStore;
@@ -44,6 +44,17 @@ where
 		self.persist_peers(&*locked_peers)
 	}
 
+	pub(crate) fn add_or_update_peer(&self, peer_info: PeerInfo) -> Result<(), Error> {
+		let mut locked_peers = self.peers.write().unwrap();
+
+		if locked_peers.get(&peer_info.node_id) == Some(&peer_info) {
+			return Ok(());
+		}
+
+		locked_peers.insert(peer_info.node_id, peer_info);
+		self.persist_peers(&*locked_peers)
+	}
+
 	pub(crate) fn remove_peer(&self, node_id: &PublicKey) -> Result<(), Error> {
 		let mut locked_peers = self.peers.write().unwrap();
 
@@ -133,11 +144,15 @@ impl Writeable for PeerStoreSerWrapper<'_> {
 pub(crate) struct PeerInfo {
 	pub node_id: PublicKey,
 	pub address: SocketAddress,
+	pub transport: PeerTransport,
+	pub is_persistent: bool,
 }
 
 impl_writeable_tlv_based!(PeerInfo, {
 	(0, node_id, required),
 	(2, address, required),
+	(3, transport, (default_value, PeerTransport::Tcp)),
+	(5, is_persistent, (default_value, false)),
 });
 
 #[cfg(test)]
@@ -159,7 +174,8 @@ mod tests {
 		)
 		.unwrap();
 		let address = SocketAddress::from_str("127.0.0.1:9738").unwrap();
-		let expected_peer_info = PeerInfo { node_id, address };
+		let expected_peer_info =
+			PeerInfo { node_id, address, transport: PeerTransport::Tcp, is_persistent: true };
 		assert!(store
 			.read(
 				PEER_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
@@ -0,0 +1,279 @@
+use crate::config::BlindedPathConfig;
+use crate::logger::{log_warn, FilesystemLogger, Logger};
+use crate::types::{InnerRouter, NetworkGraph};
+
+use lightning::blinded_path::payment::ReceiveTlvs;
+use lightning::blinded_path::BlindedPath;
+use lightning::ln::channelmanager::{ChannelDetails, PaymentId};
+use lightning::ln::msgs::LightningError;
+use lightning::ln::{ChannelId, PaymentHash};
+use lightning::offers::invoice::BlindedPayInfo;
+use lightning::onion_message::messenger::{Destination, MessageRouter, OnionMessagePath};
+use lightning::routing::gossip::{NodeId, ReadOnlyNetworkGraph};
+use lightning::routing::router::{InFlightHtlcs, Route, RouteParameters, Router};
+use lightning::sign::EntropySource;
+
+use bitcoin::secp256k1::{PublicKey, Secp256k1, Signing, Verification};
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The maximum number of blinded paths we include in our offers.
+const MAX_MESSAGE_PATHS: usize = 3;
+
+// The minimum number of public channels a node needs to have to be used in our blinded paths, so
+// that it's harder to infer which of its counterparties is the recipient.
+const MIN_PEER_CHANNELS: usize = 3;
+
+/// The channels we manually penalized, mapped to the time until which we avoid them, in seconds
+/// since the UNIX epoch.
+pub(crate) type ChannelPenalties = RwLock<HashMap<u64, u64>>;
+
+#[derive(Debug, Copy, Clone)]
+struct FirstHopPin {
+	channel_id: ChannelId,
+	allow_fallback: bool,
+}
+
+/// A [`Router`] and [`MessageRouter`] wrapping LDK's [`DefaultRouter`], which avoids the channels
+/// we manually penalized and builds the blinded paths of our offers and invoices according to
+/// our [`BlindedPathConfig`].
+///
+/// [`DefaultRouter`]: lightning::routing::router::DefaultRouter
+pub(crate) struct NodeRouter {
+	inner: InnerRouter,
+	network_graph: Arc<NetworkGraph>,
+	config: BlindedPathConfig,
+	channel_penalties: Arc<ChannelPenalties>,
+	first_hop_pins: RwLock<HashMap<PaymentHash, FirstHopPin>>,
+	logger: Arc<FilesystemLogger>,
+}
+
+impl NodeRouter {
+	pub(crate) fn new(
+		inner: InnerRouter, network_graph: Arc<NetworkGraph>, config: BlindedPathConfig,
+		channel_penalties: Arc<ChannelPenalties>, logger: Arc<FilesystemLogger>,
+	) -> Self {
+		let first_hop_pins = RwLock::new(HashMap::new());
+		Self { inner, network_graph, config, channel_penalties, first_hop_pins, logger }
+	}
+
+	/// Has all routes for the payment with the given hash, including the ones for retries, leave
+	/// our node through the given channel.
+	///
+	/// If `allow_fallback` is set, any other of our channels is used if no route through the
+	/// given channel can be found.
+	pub(crate) fn pin_first_hop(
+		&self, payment_hash: PaymentHash, channel_id: ChannelId, allow_fallback: bool,
+	) {
+		let pin = FirstHopPin { channel_id, allow_fallback };
+		self.first_hop_pins.write().unwrap().insert(payment_hash, pin);
+	}
+
+	/// Removes the first hop pinned for the payment with the given hash, if any.
+	pub(crate) fn unpin_first_hop(&self, payment_hash: &PaymentHash) {
+		self.first_hop_pins.write().unwrap().remove(payment_hash);
+	}
+
+	// Returns a copy of the given route parameters that excludes the channels we currently
+	// penalize.
+	fn with_channel_penalties(&self, route_params: &RouteParameters) -> RouteParameters {
+		let mut route_params = route_params.clone();
+		let now_secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+		let failed_channels = &mut route_params.payment_params.previously_failed_channels;
+		for (short_channel_id, expiry_secs) in self.channel_penalties.read().unwrap().iter() {
+			if *expiry_secs > now_secs && !failed_channels.contains(short_channel_id) {
+				failed_channels.push(*short_channel_id);
+			}
+		}
+		route_params
+	}
+
+	// Returns the rank of the given node in our list of preferred introduction nodes, with
+	// non-preferred nodes ranking last.
+	fn preference_rank(&self, node_id: &PublicKey) -> usize {
+		self.config
+			.preferred_introduction_nodes
+			.iter()
+			.position(|preferred| preferred == node_id)
+			.unwrap_or(usize::MAX)
+	}
+
+	// Returns the sequences of nodes preceding the recipient in the blinded message paths we
+	// build, ordered by the preference of their introduction node.
+	fn message_path_hops(
+		&self, graph: &ReadOnlyNetworkGraph, recipient: PublicKey, peers: Vec<PublicKey>,
+	) -> Vec<Vec<PublicKey>> {
+		let is_suitable = |node_id: &PublicKey| {
+			graph
+				.node(&NodeId::from_pubkey(node_id))
+				.map_or(false, |node_info| node_info.channels.len() >= MIN_PEER_CHANNELS)
+		};
+
+		let mut paths = peers
+			.into_iter()
+			.filter(|peer| is_suitable(peer))
+			.map(|peer| vec![peer])
+			.collect::<Vec<_>>();
+
+		// Extend the paths by prepending a counterparty of their current introduction node.
+		for _ in 1..self.config.num_hops {
+			paths = paths
+				.into_iter()
+				.filter_map(|mut path| {
+					let intro_node_id = NodeId::from_pubkey(&path[0]);
+					let node_info = graph.node(&intro_node_id)?;
+					let next_hop = node_info
+						.channels
+						.iter()
+						.filter_map(|scid| graph.channel(*scid))
+						.filter_map(|channel| {
+							let counterparty = if channel.node_one == intro_node_id {
+								channel.node_two
+							} else {
+								channel.node_one
+							};
+							counterparty.as_pubkey().ok()
+						})
+						.filter(|node_id| *node_id != recipient && !path.contains(node_id))
+						.filter(|node_id| is_suitable(node_id))
+						.min_by_key(|node_id| self.preference_rank(node_id))?;
+					path.insert(0, next_hop);
+					Some(path)
+				})
+				.collect();
+		}
+
+		paths.sort_by_key(|path| self.preference_rank(&path[0]));
+		paths.truncate(MAX_MESSAGE_PATHS);
+		paths
+	}
+}
+
+impl Router for NodeRouter {
+	fn find_route(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: InFlightHtlcs,
+	) -> Result<Route, LightningError> {
+		let route_params = self.with_channel_penalties(route_params);
+		self.inner.find_route(payer, &route_params, first_hops, inflight_htlcs)
+	}
+
+	fn find_route_with_id(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: InFlightHtlcs,
+		payment_hash: PaymentHash, payment_id: PaymentId,
+	) -> Result<Route, LightningError> {
+		let route_params = self.with_channel_penalties(route_params);
+		let pin = self.first_hop_pins.read().unwrap().get(&payment_hash).copied();
+		if let (Some(pin), Some(first_hops)) = (pin, first_hops) {
+			let pinned_hops = first_hops
+				.iter()
+				.copied()
+				.filter(|details| details.channel_id == pin.channel_id)
+				.collect::<Vec<_>>();
+			match self.inner.find_route_with_id(
+				payer,
+				&route_params,
+				Some(&pinned_hops),
+				inflight_htlcs.clone(),
+				payment_hash,
+				payment_id,
+			) {
+				Ok(route) => return Ok(route),
+				Err(e) if !pin.allow_fallback => return Err(e),
+				Err(e) => {
+					log_warn!(
+						self.logger,
+						"Failed to find route through first-hop channel {}, falling back to other channels: {}",
+						pin.channel_id,
+						e.err
+					);
+				},
+			}
+		}
+
+		self.inner.find_route_with_id(
+			payer,
+			&route_params,
+			first_hops,
+			inflight_htlcs,
+			payment_hash,
+			payment_id,
+		)
+	}
+
+	fn create_blinded_payment_paths<ES: EntropySource + ?Sized, T: Signing + Verification>(
+		&self, recipient: PublicKey, mut first_hops: Vec<ChannelDetails>, tlvs: ReceiveTlvs,
+		amount_msats: u64, entropy_source: &ES, secp_ctx: &Secp256k1<T>,
+	) -> Result<Vec<(BlindedPayInfo, BlindedPath)>, ()> {
+		if self.config.num_hops == 0 {
+			return BlindedPath::one_hop_for_payment(recipient, tlvs, entropy_source, secp_ctx)
+				.map(|path| vec![path]);
+		}
+
+		// LDK picks the first suitable channels, so we list those to preferred nodes first.
+		first_hops.sort_by_key(|details| self.preference_rank(&details.counterparty.node_id));
+		match self.inner.create_blinded_payment_paths(
+			recipient,
+			first_hops,
+			tlvs.clone(),
+			amount_msats,
+			entropy_source,
+			secp_ctx,
+		) {
+			Ok(paths) if paths.iter().any(|(_, path)| path.introduction_node_id != recipient) => {
+				Ok(paths)
+			},
+			_ => {
+				log_warn!(
+					self.logger,
+					"No suitable introduction node available for blinded payment paths, falling back to a direct path."
+				);
+				BlindedPath::one_hop_for_payment(recipient, tlvs, entropy_source, secp_ctx)
+					.map(|path| vec![path])
+			},
+		}
+	}
+}
+
+impl MessageRouter for NodeRouter {
+	fn find_path(
+		&self, sender: PublicKey, peers: Vec<PublicKey>, destination: Destination,
+	) -> Result<OnionMessagePath, ()> {
+		self.inner.find_path(sender, peers, destination)
+	}
+
+	fn create_blinded_paths<ES: EntropySource + ?Sized, T: Signing + Verification>(
+		&self, recipient: PublicKey, peers: Vec<PublicKey>, entropy_source: &ES,
+		secp_ctx: &Secp256k1<T>,
+	) -> Result<Vec<BlindedPath>, ()> {
+		if self.config.num_hops == 0 {
+			return BlindedPath::one_hop_for_message(recipient, entropy_source, secp_ctx)
+				.map(|path| vec![path]);
+		}
+
+		let paths = {
+			let graph = self.network_graph.read_only();
+			self.message_path_hops(&graph, recipient, peers)
+				.into_iter()
+				.map(|mut hops| {
+					hops.push(recipient);
+					BlindedPath::new_for_message(&hops, entropy_source, secp_ctx)
+				})
+				.collect::<Result<Vec<_>, _>>()?
+		};
+
+		if paths.is_empty() {
+			log_warn!(
+				self.logger,
+				"No suitable introduction node available for blinded message paths, falling back to a direct path."
+			);
+			return BlindedPath::one_hop_for_message(recipient, entropy_source, secp_ctx)
+				.map(|path| vec![path]);
+		}
+
+		Ok(paths)
+	}
+}
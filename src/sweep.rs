@@ -1,4 +1,5 @@
 use crate::hex_utils;
+use crate::io::batch_kv_store::BatchKVStore;
 use crate::io::{
 	SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
 	SPENDABLE_OUTPUT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
@@ -13,6 +14,7 @@ use lightning::impl_writeable_tlv_based;
 use lightning::ln::ChannelId;
 use lightning::sign::{EntropySource, SpendableOutputDescriptor};
 use lightning::util::persist::KVStore;
+use lightning::util::persist::KVStore;
 use lightning::util::ser::Writeable;
 
 use bitcoin::blockdata::block::Header;
@@ -20,6 +22,7 @@ use bitcoin::blockdata::locktime::absolute::LockTime;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::{BlockHash, Transaction, Txid};
 
+use std::collections::HashSet;
 use std::ops::Deref;
 use std::sync::{Arc, Mutex};
 
@@ -37,6 +40,7 @@ pub(crate) struct SpendableOutputInfo {
 	pub(crate) latest_spending_tx: Option<Transaction>,
 	pub(crate) confirmation_height: Option<u32>,
 	pub(crate) confirmation_hash: Option<BlockHash>,
+	pub(crate) fee_rate_override_sat_per_kw: Option<u32>,
 }
 
 impl SpendableOutputInfo {
@@ -96,6 +100,7 @@ impl_writeable_tlv_based!(SpendableOutputInfo, {
 	(10, latest_spending_tx, option),
 	(12, confirmation_height, option),
 	(14, confirmation_hash, option),
+	(16, fee_rate_override_sat_per_kw, option),
 });
 
 pub(crate) struct OutputSweeper<B: Deref, E: Deref, F: Deref, K: Deref, L: Deref>
@@ -154,6 +159,10 @@ where
 		}
 	}
 
+	pub(crate) fn current_best_block(&self) -> BestBlock {
+		self.best_block.lock().unwrap().clone()
+	}
+
 	pub(crate) fn add_outputs(
 		&self, mut output_descriptors: Vec<SpendableOutputDescriptor>,
 		channel_id: Option<ChannelId>,
@@ -180,6 +189,7 @@ where
 					latest_spending_tx: None,
 					confirmation_height: None,
 					confirmation_hash: None,
+					fee_rate_override_sat_per_kw: None,
 				};
 
 				locked_outputs.push(output_info.clone());
@@ -196,6 +206,72 @@ where
 		self.outputs.lock().unwrap().clone()
 	}
 
+	/// Returns whether the given transaction is the latest transaction spending any of the tracked
+	/// outputs.
+	pub(crate) fn tracks_spending_tx(&self, txid: &Txid) -> bool {
+		self.outputs
+			.lock()
+			.unwrap()
+			.iter()
+			.any(|o| o.latest_spending_tx.as_ref().map_or(false, |tx| tx.txid() == *txid))
+	}
+
+	/// Replaces the given sweeping transaction with one paying the given fee rate.
+	///
+	/// The fee rate will be used as a lower bound for any further regenerated sweeping
+	/// transactions of the same outputs.
+	pub(crate) fn bump_fee(&self, txid: &Txid, fee_rate_sat_per_kw: u32) -> Result<Txid, Error> {
+		let cur_height = self.best_block.lock().unwrap().height();
+		let mut locked_outputs = self.outputs.lock().unwrap();
+
+		let swept_outputs = locked_outputs
+			.iter_mut()
+			.filter(|o| o.latest_spending_tx.as_ref().map_or(false, |tx| tx.txid() == *txid))
+			.collect::<Vec<_>>();
+
+		let spending_tx = match swept_outputs.first().and_then(|o| o.latest_spending_tx.clone()) {
+			Some(spending_tx) => spending_tx,
+			None => return Err(Error::TransactionNotFound),
+		};
+
+		if swept_outputs.iter().any(|o| o.confirmation_height.is_some()) {
+			log_error!(self.logger, "Failed to bump fee of confirmed transaction {}", txid);
+			return Err(Error::TransactionAlreadyConfirmed);
+		}
+
+		let input_value_sats = swept_outputs.iter().map(|o| o.value_satoshis()).sum::<u64>();
+		let output_value_sats = spending_tx.output.iter().map(|o| o.value).sum::<u64>();
+		let cur_fee_sats = input_value_sats.saturating_sub(output_value_sats);
+		let cur_fee_rate_sat_per_kw = cur_fee_sats * 1000 / spending_tx.weight().to_wu().max(1);
+		if fee_rate_sat_per_kw as u64 <= cur_fee_rate_sat_per_kw {
+			log_error!(
+				self.logger,
+				"Failed to bump fee of transaction {} as the given fee rate of {}sat/kW doesn't exceed its current fee rate of {}sat/kW",
+				txid,
+				fee_rate_sat_per_kw,
+				cur_fee_rate_sat_per_kw
+			);
+			return Err(Error::InvalidFeeRate);
+		}
+
+		let descriptors = swept_outputs.iter().map(|o| o.descriptor.clone()).collect::<Vec<_>>();
+		let bump_tx = self
+			.get_spending_tx(&descriptors, cur_height, Some(fee_rate_sat_per_kw))
+			.map_err(|()| Error::OnchainTxCreationFailed)?;
+		self.broadcaster.broadcast_transactions(&[&bump_tx]);
+
+		for output_info in swept_outputs {
+			output_info.latest_spending_tx = Some(bump_tx.clone());
+			output_info.latest_broadcast_height = Some(cur_height);
+			output_info.fee_rate_override_sat_per_kw = Some(fee_rate_sat_per_kw);
+			self.persist_info(&output_info).unwrap_or_else(|e| {
+				log_error!(self.logger, "Error persisting SpendableOutputInfo: {:?}", e)
+			});
+		}
+
+		Ok(bump_tx.txid())
+	}
+
 	fn rebroadcast_if_necessary(&self) {
 		let (cur_height, cur_hash) = {
 			let best_block = self.best_block.lock().unwrap();
@@ -204,6 +280,7 @@ where
 
 		let mut respend_descriptors = Vec::new();
 		let mut respend_ids = Vec::new();
+		let mut respend_fee_rate_override = None;
 
 		{
 			let mut locked_outputs = self.outputs.lock().unwrap();
@@ -220,6 +297,8 @@ where
 					if latest_broadcast_height + REGENERATE_SPEND_THRESHOLD >= cur_height {
 						respend_descriptors.push(output_info.descriptor.clone());
 						respend_ids.push(output_info.id);
+						respend_fee_rate_override =
+							respend_fee_rate_override.max(output_info.fee_rate_override_sat_per_kw);
 					} else if latest_broadcast_height < cur_height {
 						if let Some(latest_spending_tx) = output_info.latest_spending_tx.as_ref() {
 							self.broadcaster.broadcast_transactions(&[&latest_spending_tx]);
@@ -246,7 +325,8 @@ where
 		}
 
 		if !respend_descriptors.is_empty() {
-			match self.get_spending_tx(&respend_descriptors, cur_height) {
+			match self.get_spending_tx(&respend_descriptors, cur_height, respend_fee_rate_override)
+			{
 				Ok(spending_tx) => {
 					self.broadcaster.broadcast_transactions(&[&spending_tx]);
 					let mut locked_outputs = self.outputs.lock().unwrap();
@@ -276,45 +356,13 @@ where
 		}
 	}
 
-	fn prune_confirmed_outputs(&self) {
-		let cur_height = self.best_block.lock().unwrap().height();
-		let mut locked_outputs = self.outputs.lock().unwrap();
-
-		// Prune all outputs that have sufficient depth by now.
-		locked_outputs.retain(|o| {
-			if let Some(confirmation_height) = o.confirmation_height {
-				if cur_height >= confirmation_height + CONSIDERED_SPENT_THRESHOLD_CONF - 1 {
-					let key = hex_utils::to_string(&o.id);
-					match self.kv_store.remove(
-						SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
-						SPENDABLE_OUTPUT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
-						&key,
-						false,
-					) {
-						Ok(_) => return false,
-						Err(e) => {
-							log_error!(
-								self.logger,
-								"Removal of key {}/{}/{} failed due to: {}",
-								SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
-								SPENDABLE_OUTPUT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
-								key,
-								e
-							);
-							return true;
-						},
-					}
-				}
-			}
-			true
-		});
-	}
-
 	fn get_spending_tx(
 		&self, output_descriptors: &Vec<SpendableOutputDescriptor>, cur_height: u32,
+		fee_rate_override_sat_per_kw: Option<u32>,
 	) -> Result<Transaction, ()> {
 		let tx_feerate =
 			self.fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::NonAnchorChannelFee);
+		let tx_feerate = tx_feerate.max(fee_rate_override_sat_per_kw.unwrap_or(0));
 
 		let destination_address = self.wallet.get_new_address().map_err(|e| {
 			log_error!(self.logger, "Failed to get destination address from wallet: {}", e);
@@ -357,12 +405,56 @@ where
 	}
 }
 
+impl<B: Deref, E: Deref, F: Deref, K: Deref, L: Deref> OutputSweeper<B, E, F, K, L>
+where
+	B::Target: BroadcasterInterface,
+	E::Target: FeeEstimator,
+	F::Target: Filter,
+	K::Target: BatchKVStore,
+	L::Target: Logger,
+{
+	fn prune_confirmed_outputs(&self) {
+		let cur_height = self.best_block.lock().unwrap().height();
+		let mut locked_outputs = self.outputs.lock().unwrap();
+
+		// Prune all outputs that have sufficient depth by now. We first collect all of them so we
+		// can remove them from the store in one go. We only drop the outputs from memory once they
+		// were removed, so that a failed removal will be retried on the next block.
+		let prune_keys = locked_outputs
+			.iter()
+			.filter(|o| {
+				o.confirmation_height.map_or(false, |confirmation_height| {
+					cur_height >= confirmation_height + CONSIDERED_SPENT_THRESHOLD_CONF - 1
+				})
+			})
+			.map(|o| hex_utils::to_string(&o.id))
+			.collect::<HashSet<_>>();
+
+		if prune_keys.is_empty() {
+			return;
+		}
+
+		let keys = prune_keys.iter().map(|k| k.as_str()).collect::<Vec<_>>();
+		if let Err(e) = self.kv_store.remove_batch(
+			SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+			SPENDABLE_OUTPUT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+			&keys,
+			false,
+		) {
+			log_error!(self.logger, "Failed to prune spendable outputs: {}", e);
+			return;
+		}
+
+		locked_outputs.retain(|o| !prune_keys.contains(&hex_utils::to_string(&o.id)));
+	}
+}
+
 impl<B: Deref, E: Deref, F: Deref, K: Deref, L: Deref> Listen for OutputSweeper<B, E, F, K, L>
 where
 	B::Target: BroadcasterInterface,
 	E::Target: FeeEstimator,
 	F::Target: Filter,
-	K::Target: KVStore,
+	K::Target: BatchKVStore,
 	L::Target: Logger,
 {
 	fn filtered_block_connected(
@@ -410,7 +502,7 @@ where
 	B::Target: BroadcasterInterface,
 	E::Target: FeeEstimator,
 	F::Target: Filter,
-	K::Target: KVStore,
+	K::Target: BatchKVStore,
 	L::Target: Logger,
 {
 	fn transactions_confirmed(
@@ -0,0 +1,136 @@
+//! Objects for connecting to peers via a Tor SOCKS5 proxy.
+
+use crate::logger::{log_error, FilesystemLogger, Logger};
+use crate::types::PeerManager;
+
+use lightning::ln::msgs::SocketAddress;
+use lightning::util::persist::KVStore;
+
+use bitcoin::secp256k1::PublicKey;
+
+use std::future::Future;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_AUTH_NONE: u8 = 0x00;
+const SOCKS5_CMD_CONNECT: u8 = 0x01;
+const SOCKS5_ATYP_IPV4: u8 = 0x01;
+const SOCKS5_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS5_ATYP_IPV6: u8 = 0x04;
+const SOCKS5_REPLY_SUCCEEDED: u8 = 0x00;
+
+// Tor circuits may take a while to be built, so we allow for a generous handshake timeout.
+const SOCKS5_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Returns whether the given address can only be reached via the Tor network.
+pub(crate) fn requires_tor(addr: &SocketAddress) -> bool {
+	matches!(addr, SocketAddress::OnionV2(_) | SocketAddress::OnionV3 { .. })
+}
+
+/// Connects to the given peer through the SOCKS5 proxy at `proxy_addr` and hands the connection
+/// to the [`PeerManager`].
+///
+/// Returns a future that completes once the connection is closed, or `None` if we failed to
+/// establish the connection.
+pub(crate) async fn connect_outbound<K: KVStore + Sync + Send + 'static>(
+	peer_manager: Arc<PeerManager<K>>, node_id: PublicKey, proxy_addr: SocketAddr,
+	addr: &SocketAddress, logger: Arc<FilesystemLogger>,
+) -> Option<impl Future<Output = ()>> {
+	let (host, port) = match split_host_port(addr) {
+		Some(res) => res,
+		None => {
+			log_error!(logger, "Failed to parse peer address {}", addr);
+			return None;
+		},
+	};
+
+	let handshake_res =
+		tokio::task::spawn_blocking(move || socks5_connect(proxy_addr, &host, port)).await;
+
+	let stream = match handshake_res {
+		Ok(Ok(stream)) => stream,
+		Ok(Err(e)) => {
+			log_error!(logger, "Failed to connect to {} via Tor proxy {}: {}", addr, proxy_addr, e);
+			return None;
+		},
+		Err(e) => {
+			log_error!(logger, "Tor proxy connection task failed: {}", e);
+			return None;
+		},
+	};
+
+	Some(lightning_net_tokio::setup_outbound(peer_manager, node_id, stream))
+}
+
+fn split_host_port(addr: &SocketAddress) -> Option<(String, u16)> {
+	let addr_str = addr.to_string();
+	let (host, port) = addr_str.rsplit_once(':')?;
+	let port = port.parse::<u16>().ok()?;
+	Some((host.to_string(), port))
+}
+
+fn socks5_connect(proxy_addr: SocketAddr, host: &str, port: u16) -> std::io::Result<TcpStream> {
+	let host_len: u8 = host
+		.len()
+		.try_into()
+		.map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Host name too long"))?;
+
+	let mut stream = TcpStream::connect_timeout(&proxy_addr, SOCKS5_HANDSHAKE_TIMEOUT)?;
+	stream.set_read_timeout(Some(SOCKS5_HANDSHAKE_TIMEOUT))?;
+	stream.set_write_timeout(Some(SOCKS5_HANDSHAKE_TIMEOUT))?;
+
+	// Greeting: we only offer unauthenticated access.
+	stream.write_all(&[SOCKS5_VERSION, 1, SOCKS5_AUTH_NONE])?;
+	let mut greeting_resp = [0u8; 2];
+	stream.read_exact(&mut greeting_resp)?;
+	if greeting_resp != [SOCKS5_VERSION, SOCKS5_AUTH_NONE] {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::Other,
+			"Proxy doesn't support unauthenticated SOCKS5 access",
+		));
+	}
+
+	// Connect request: we always let the proxy resolve the host name.
+	let mut req = Vec::with_capacity(7 + host.len());
+	req.extend_from_slice(&[SOCKS5_VERSION, SOCKS5_CMD_CONNECT, 0x00, SOCKS5_ATYP_DOMAIN]);
+	req.push(host_len);
+	req.extend_from_slice(host.as_bytes());
+	req.extend_from_slice(&port.to_be_bytes());
+	stream.write_all(&req)?;
+
+	let mut resp_header = [0u8; 4];
+	stream.read_exact(&mut resp_header)?;
+	if resp_header[0] != SOCKS5_VERSION || resp_header[1] != SOCKS5_REPLY_SUCCEEDED {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::ConnectionRefused,
+			format!("Proxy refused connection with reply code {}", resp_header[1]),
+		));
+	}
+
+	// Skip over the bound address reported by the proxy.
+	let bound_addr_len = match resp_header[3] {
+		SOCKS5_ATYP_IPV4 => 4,
+		SOCKS5_ATYP_IPV6 => 16,
+		SOCKS5_ATYP_DOMAIN => {
+			let mut len = [0u8; 1];
+			stream.read_exact(&mut len)?;
+			len[0] as usize
+		},
+		_ => {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"Proxy replied with unknown address type",
+			))
+		},
+	};
+	let mut bound_addr = vec![0u8; bound_addr_len + 2];
+	stream.read_exact(&mut bound_addr)?;
+
+	stream.set_read_timeout(None)?;
+	stream.set_write_timeout(None)?;
+	stream.set_nonblocking(true)?;
+	Ok(stream)
+}
@@ -1,16 +1,17 @@
+use crate::backup::RecoveredChannels;
+use crate::chain::ChainSource;
 use crate::logger::{log_bytes, log_debug, log_error, log_trace, Logger};
 
 use lightning::chain::chaininterface::BroadcasterInterface;
 use lightning::util::ser::Writeable;
 
-use esplora_client::AsyncClient as EsploraClient;
-
 use bitcoin::Transaction;
 
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
 
 use std::ops::Deref;
+use std::sync::Arc;
 use std::time::Duration;
 
 const BCAST_PACKAGE_QUEUE_SIZE: usize = 50;
@@ -21,7 +22,8 @@ where
 {
 	queue_sender: mpsc::Sender<Vec<Transaction>>,
 	queue_receiver: Mutex<mpsc::Receiver<Vec<Transaction>>>,
-	esplora_client: EsploraClient,
+	chain_source: Arc<ChainSource>,
+	recovered_channels: Arc<RecoveredChannels>,
 	logger: L,
 }
 
@@ -29,38 +31,62 @@ impl<L: Deref> TransactionBroadcaster<L>
 where
 	L::Target: Logger,
 {
-	pub(crate) fn new(esplora_client: EsploraClient, logger: L) -> Self {
+	pub(crate) fn new(
+		chain_source: Arc<ChainSource>, recovered_channels: Arc<RecoveredChannels>, logger: L,
+	) -> Self {
 		let (queue_sender, queue_receiver) = mpsc::channel(BCAST_PACKAGE_QUEUE_SIZE);
-		Self { queue_sender, queue_receiver: Mutex::new(queue_receiver), esplora_client, logger }
+		let queue_receiver = Mutex::new(queue_receiver);
+		Self { queue_sender, queue_receiver, chain_source, recovered_channels, logger }
 	}
 
 	pub(crate) async fn process_queue(&self) {
 		let mut receiver = self.queue_receiver.lock().await;
 		while let Some(next_package) = receiver.recv().await {
 			for tx in &next_package {
-				match self.esplora_client.broadcast(tx).await {
-					Ok(()) => {
-						log_trace!(self.logger, "Successfully broadcast transaction {}", tx.txid());
-					},
-					Err(e) => match e {
-						esplora_client::Error::Reqwest(_) => {
-							// Wait 500 ms and retry in case we get a `Reqwest` error (typically
-							// 429)
-							tokio::time::sleep(Duration::from_millis(500)).await;
-							log_error!(
-								self.logger,
-								"Sync failed due to HTTP connection error, retrying: {}",
-								e
-							);
-							match self.esplora_client.broadcast(tx).await {
-								Ok(()) => {
-									log_debug!(
+				match &*self.chain_source {
+					ChainSource::Esplora { tx_sync } => {
+						match tx_sync.client().broadcast(tx).await {
+							Ok(()) => {
+								log_trace!(
+									self.logger,
+									"Successfully broadcast transaction {}",
+									tx.txid()
+								);
+							},
+							Err(e) => match e {
+								esplora_client::Error::Reqwest(_) => {
+									// Wait 500 ms and retry in case we get a `Reqwest` error (typically
+									// 429)
+									tokio::time::sleep(Duration::from_millis(500)).await;
+									log_error!(
 										self.logger,
-										"Successfully broadcast transaction {}",
-										tx.txid()
+										"Sync failed due to HTTP connection error, retrying: {}",
+										e
 									);
+									match tx_sync.client().broadcast(tx).await {
+										Ok(()) => {
+											log_debug!(
+												self.logger,
+												"Successfully broadcast transaction {}",
+												tx.txid()
+											);
+										},
+										Err(e) => {
+											log_error!(
+												self.logger,
+												"Failed to broadcast transaction {}: {}",
+												tx.txid(),
+												e
+											);
+											log_trace!(
+												self.logger,
+												"Failed broadcast transaction bytes: {}",
+												log_bytes!(tx.encode())
+											);
+										},
+									}
 								},
-								Err(e) => {
+								_ => {
 									log_error!(
 										self.logger,
 										"Failed to broadcast transaction {}: {}",
@@ -73,21 +99,44 @@ where
 										log_bytes!(tx.encode())
 									);
 								},
-							}
-						},
-						_ => {
-							log_error!(
+							},
+						}
+					},
+					ChainSource::BitcoindRpc { bitcoind_rpc_client, .. } => {
+						// Rejections are logged with a description of the reason by the client.
+						if bitcoind_rpc_client
+							.broadcast_transaction(tx, &*self.logger)
+							.await
+							.is_ok()
+						{
+							log_trace!(
 								self.logger,
-								"Failed to broadcast transaction {}: {}",
-								tx.txid(),
-								e
+								"Successfully broadcast transaction {}",
+								tx.txid()
 							);
+						} else {
 							log_trace!(
 								self.logger,
 								"Failed broadcast transaction bytes: {}",
 								log_bytes!(tx.encode())
 							);
-						},
+						}
+					},
+					#[cfg(feature = "cbf")]
+					ChainSource::Cbf { cbf_client, .. } => {
+						if cbf_client.broadcast_transaction(tx).await.is_ok() {
+							log_trace!(
+								self.logger,
+								"Successfully sent transaction {} to our peer",
+								tx.txid()
+							);
+						} else {
+							log_trace!(
+								self.logger,
+								"Failed broadcast transaction bytes: {}",
+								log_bytes!(tx.encode())
+							);
+						}
 					},
 				}
 			}
@@ -100,7 +149,26 @@ where
 	L::Target: Logger,
 {
 	fn broadcast_transactions(&self, txs: &[&Transaction]) {
-		let package = txs.iter().map(|&t| t.clone()).collect::<Vec<Transaction>>();
+		let package = txs
+			.iter()
+			.filter(|t| {
+				// Our commitment transactions for channels restored from a backup might be
+				// revoked, so we leave closing these channels to our counterparties.
+				let spends_recovered_funding = self.recovered_channels.spends_funding_output(t);
+				if spends_recovered_funding {
+					log_error!(
+						self.logger,
+						"Refusing to broadcast transaction {} spending the funding output of a channel restored from backup",
+						t.txid()
+					);
+				}
+				!spends_recovered_funding
+			})
+			.map(|&t| t.clone())
+			.collect::<Vec<Transaction>>();
+		if package.is_empty() {
+			return;
+		}
 		self.queue_sender.try_send(package).unwrap_or_else(|e| {
 			log_error!(self.logger, "Failed to broadcast transactions: {}", e);
 		});
@@ -1,27 +1,28 @@
 use crate::logger::FilesystemLogger;
 use crate::message_handler::NodeCustomMessageHandler;
+use crate::onion_message::{NodeOffersMessageHandler, NodeOnionMessageHandler};
 use crate::sweep::OutputSweeper;
 
-use lightning::blinded_path::BlindedPath;
 use lightning::chain::chainmonitor;
 use lightning::chain::BestBlock as LdkBestBlock;
 use lightning::ln::channelmanager::ChannelDetails as LdkChannelDetails;
 use lightning::ln::msgs::RoutingMessageHandler;
 use lightning::ln::msgs::SocketAddress;
-use lightning::ln::peer_handler::IgnoringMessageHandler;
-use lightning::ln::ChannelId;
+use lightning::ln::{ChannelId, PaymentHash};
 use lightning::routing::gossip;
 use lightning::routing::router::DefaultRouter;
 use lightning::routing::scoring::{ProbabilisticScorer, ProbabilisticScoringFeeParameters};
-use lightning::sign::{EntropySource, InMemorySigner};
+use lightning::sign::InMemorySigner;
 use lightning::util::config::ChannelConfig as LdkChannelConfig;
+use lightning::util::config::ChannelConfigUpdate as LdkChannelConfigUpdate;
 use lightning::util::config::MaxDustHTLCExposure as LdkMaxDustHTLCExposure;
 use lightning::util::ser::{Readable, Writeable, Writer};
+use lightning::{impl_writeable_tlv_based, impl_writeable_tlv_based_enum};
+use lightning_invoice::Bolt11Invoice;
 use lightning_net_tokio::SocketDescriptor;
-use lightning_transaction_sync::EsploraSyncClient;
 
-use bitcoin::secp256k1::{self, PublicKey, Secp256k1};
-use bitcoin::{BlockHash, OutPoint};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{BlockHash, OutPoint, Txid};
 
 use std::sync::{Arc, Mutex, RwLock};
 
@@ -38,13 +39,13 @@ pub(crate) type PeerManager<K> = lightning::ln::peer_handler::PeerManager<
 	SocketDescriptor,
 	Arc<ChannelManager<K>>,
 	Arc<dyn RoutingMessageHandler + Send + Sync>,
-	Arc<OnionMessenger>,
+	Arc<NodeOnionMessageHandler<K>>,
 	Arc<FilesystemLogger>,
 	Arc<NodeCustomMessageHandler<K, Arc<FilesystemLogger>>>,
 	Arc<KeysManager>,
 >;
 
-pub(crate) type ChainSource = EsploraSyncClient<Arc<FilesystemLogger>>;
+pub(crate) type ChainSource = crate::chain::ChainSource;
 
 pub(crate) type LiquidityManager<K> = lightning_liquidity::LiquidityManager<
 	Arc<KeysManager>,
@@ -81,7 +82,16 @@ pub(crate) type KeysManager = crate::wallet::WalletKeysManager<
 	Arc<FilesystemLogger>,
 >;
 
-pub(crate) type Router = DefaultRouter<
+pub(crate) type BumpTransactionEventHandler =
+	lightning::events::bump_transaction::BumpTransactionEventHandler<
+		Arc<Broadcaster>,
+		Arc<lightning::events::bump_transaction::Wallet<Arc<Wallet>, Arc<FilesystemLogger>>>,
+		Arc<KeysManager>,
+		Arc<FilesystemLogger>,
+	>;
+
+pub(crate) type Router = crate::router::NodeRouter;
+pub(crate) type InnerRouter = DefaultRouter<
 	Arc<NetworkGraph>,
 	Arc<FilesystemLogger>,
 	Arc<Mutex<Scorer>>,
@@ -110,34 +120,22 @@ pub(crate) type GossipSync = lightning_background_processor::GossipSync<
 	Arc<FilesystemLogger>,
 >;
 
-pub(crate) type OnionMessenger = lightning::onion_message::messenger::OnionMessenger<
+pub(crate) type OnionMessenger<K> = lightning::onion_message::messenger::OnionMessenger<
 	Arc<KeysManager>,
 	Arc<KeysManager>,
 	Arc<FilesystemLogger>,
-	Arc<FakeMessageRouter>,
-	IgnoringMessageHandler,
-	IgnoringMessageHandler,
+	Arc<MessageRouter>,
+	Arc<NodeOffersMessageHandler<K>>,
+	Arc<CustomOnionMessageHandler<K>>,
 >;
 
-pub(crate) struct FakeMessageRouter {}
+pub(crate) type CustomOnionMessageHandler<K> =
+	crate::onion_message::CustomOnionMessageHandler<K, Arc<FilesystemLogger>>;
 
-impl lightning::onion_message::messenger::MessageRouter for FakeMessageRouter {
-	fn find_path(
-		&self, _sender: PublicKey, _peers: Vec<PublicKey>,
-		_destination: lightning::onion_message::messenger::Destination,
-	) -> Result<lightning::onion_message::messenger::OnionMessagePath, ()> {
-		unimplemented!()
-	}
-	fn create_blinded_paths<
-		ES: EntropySource + ?Sized,
-		T: secp256k1::Signing + secp256k1::Verification,
-	>(
-		&self, _recipient: PublicKey, _peers: Vec<PublicKey>, _entropy_source: &ES,
-		_secp_ctx: &Secp256k1<T>,
-	) -> Result<Vec<BlindedPath>, ()> {
-		unreachable!()
-	}
-}
+pub(crate) type MessageRouter = lightning::onion_message::messenger::DefaultMessageRouter<
+	Arc<NetworkGraph>,
+	Arc<FilesystemLogger>,
+>;
 
 pub(crate) type Sweeper<K> = OutputSweeper<
 	Arc<Broadcaster>,
@@ -167,6 +165,27 @@ impl Readable for UserChannelId {
 	}
 }
 
+/// The SHA-256 hash of a description, which a [BOLT 11] invoice may commit to in place of the
+/// description itself.
+///
+/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DescriptionHash(pub [u8; 32]);
+
+impl Writeable for DescriptionHash {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
+		Ok(self.0.write(writer)?)
+	}
+}
+
+impl Readable for DescriptionHash {
+	fn read<R: lightning::io::Read>(
+		reader: &mut R,
+	) -> Result<Self, lightning::ln::msgs::DecodeError> {
+		Ok(Self(Readable::read(reader)?))
+	}
+}
+
 /// Details of a channel as returned by [`Node::list_channels`].
 ///
 /// [`Node::list_channels`]: crate::Node::list_channels
@@ -355,6 +374,371 @@ pub struct PeerDetails {
 	pub is_persisted: bool,
 	/// Indicates whether we currently have an active connection with the peer.
 	pub is_connected: bool,
+	/// The transport used to connect to the peer.
+	pub transport: PeerTransport,
+}
+
+/// The transport used for a peer connection.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum PeerTransport {
+	/// A plain TCP connection.
+	#[default]
+	Tcp,
+	/// A WebSocket connection, established to `ws://<address>`.
+	///
+	/// **Note:** This requires the `websocket` feature to be enabled.
+	WebSocket,
+}
+
+impl_writeable_tlv_based_enum!(PeerTransport,
+	(0, Tcp) => {},
+	(2, WebSocket) => {};
+);
+
+/// The reason a peer connection was closed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PeerDisconnectReason {
+	/// We closed the connection, e.g., via [`Node::disconnect`].
+	///
+	/// [`Node::disconnect`]: crate::Node::disconnect
+	LocallyInitiated,
+	/// The peer closed the connection or it was otherwise lost.
+	RemotelyInitiated,
+	/// The connection was closed before the handshake with the peer completed.
+	HandshakeFailed,
+}
+
+impl_writeable_tlv_based_enum!(PeerDisconnectReason,
+	(0, LocallyInitiated) => {},
+	(2, RemotelyInitiated) => {},
+	(4, HandshakeFailed) => {};
+);
+
+/// The connection status of a peer as returned by [`Node::peer_connection_status`].
+///
+/// [`Node::peer_connection_status`]: crate::Node::peer_connection_status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerConnectionStatus {
+	/// We currently have an active connection with the peer.
+	Connected {
+		/// The network address of the peer, if known.
+		address: Option<SocketAddress>,
+	},
+	/// We currently don't have an active connection with the peer.
+	Disconnected {
+		/// The reason the last connection with the peer was closed, if known.
+		///
+		/// This will be `None` if we haven't been connected to the peer since the node was
+		/// started.
+		reason: Option<PeerDisconnectReason>,
+	},
+}
+
+/// Parameters overriding the defaults used when sending a payment.
+///
+/// Any parameter left unset will fall back to the respective default.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SendingParameters {
+	/// The maximum total fees, in millisatoshi, that may accrue during routing.
+	///
+	/// Routes exceeding this budget won't be considered, even if no other route is available.
+	pub max_total_routing_fee_msat: Option<u64>,
+	/// The maximum total CLTV delta we accept for the route.
+	pub max_total_cltv_expiry_delta: Option<u32>,
+	/// The time, in seconds, after which we stop retrying to send the payment.
+	pub retry_timeout_secs: Option<u64>,
+	/// The maximum number of paths the payment may be split into.
+	///
+	/// Setting this to `1` disables multi-path payments (MPP).
+	pub max_path_count: Option<u8>,
+	/// The minimum amount, in millisatoshi, each path of a multi-path payment has to carry.
+	///
+	/// This is enforced by limiting the number of paths the payment may be split into.
+	pub min_path_amount_msat: Option<u64>,
+	/// The channel the payment has to leave our node through.
+	///
+	/// This allows to choose among multiple channels to the same peer, e.g., to preferably spend
+	/// the outbound liquidity of a specific channel. The channel needs to be usable and have
+	/// sufficient outbound capacity for the payment amount.
+	pub first_hop_channel_id: Option<ChannelId>,
+	/// Whether the payment may be routed through any other of our channels if the channel given
+	/// via [`Self::first_hop_channel_id`] isn't usable or no route through it can be found.
+	///
+	/// If unset, the payment will fail with [`Error::FirstHopChannelUnusable`] in such a case.
+	///
+	/// [`Error::FirstHopChannelUnusable`]: crate::Error::FirstHopChannelUnusable
+	pub allow_first_hop_fallback: Option<bool>,
+}
+
+/// Parameters of the funding transaction of a channel opened via
+/// [`Node::connect_open_channel_with_funding_params`].
+///
+/// [`Node::connect_open_channel_with_funding_params`]: crate::Node::connect_open_channel_with_funding_params
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChannelFundingParameters {
+	/// The fee rate of the funding transaction, in satoshis per virtual byte.
+	///
+	/// If unset, the fee rate will be determined by our fee estimator. Must not be below the
+	/// minimum relay fee rate.
+	pub fee_rate_sat_per_vbyte: Option<u64>,
+	/// Whether the funding transaction signals replaceability as per [BIP 125], allowing to bump
+	/// its fee later on.
+	///
+	/// [BIP 125]: https://github.com/bitcoin/bips/blob/master/bip-0125.mediawiki
+	pub enable_rbf: bool,
+}
+
+impl Default for ChannelFundingParameters {
+	fn default() -> Self {
+		Self { fee_rate_sat_per_vbyte: None, enable_rbf: true }
+	}
+}
+
+/// Limits we require the counterparty to adhere to on a channel opened via
+/// [`Node::connect_open_channel_with_handshake_params`].
+///
+/// Any fields left unset will use LDK's defaults.
+///
+/// [`Node::connect_open_channel_with_handshake_params`]: crate::Node::connect_open_channel_with_handshake_params
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ChannelHandshakeParameters {
+	/// The channel reserve we require the counterparty to keep, in satoshis.
+	///
+	/// Must be at least 1000 satoshis and less than the channel value. As LDK expresses the
+	/// reserve as a proportion of the channel value, it will be rounded up to the nearest
+	/// millionth of the channel value.
+	pub their_channel_reserve_sats: Option<u64>,
+	/// The maximum total value of inbound HTLCs that may be pending on the channel at any time,
+	/// in millisatoshis.
+	///
+	/// Must be at least 1% of the channel value and must not exceed the channel value. As LDK
+	/// expresses the limit as a percentage of the channel value, it will be rounded down to the
+	/// nearest whole percent of the channel value.
+	pub max_htlc_value_in_flight_msat: Option<u64>,
+	/// The maximum number of inbound HTLCs that may be pending on the channel at any time.
+	///
+	/// Must be between 1 and 483, the maximum allowed by the protocol.
+	pub max_accepted_htlcs: Option<u16>,
+}
+
+/// An inbound channel open request, as handed to the policy configured via
+/// [`Builder::set_inbound_channel_policy`].
+///
+/// [`Builder::set_inbound_channel_policy`]: crate::Builder::set_inbound_channel_policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingChannelRequest {
+	/// The node id of the peer requesting to open the channel.
+	pub counterparty_node_id: PublicKey,
+	/// The channel value, in satoshis.
+	pub funding_sats: u64,
+	/// The amount the peer will push to us on channel open, in millisatoshis.
+	pub push_msat: u64,
+	/// Whether the channel would be an Anchor channel.
+	pub is_anchor_channel: bool,
+	/// Whether the peer is one of our persistent peers, i.e., one we reconnect to automatically.
+	pub is_persistent_peer: bool,
+}
+
+/// The decision of an inbound channel policy on a [`PendingChannelRequest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelDecision {
+	/// Accept the channel.
+	Accept,
+	/// Accept the channel, allowing it to be used before its funding transaction confirmed.
+	///
+	/// **Note:** This is only secure if the peer is trusted not to double-spend the funding
+	/// transaction.
+	AcceptTrustedZeroConf,
+	/// Reject the channel.
+	Reject,
+}
+
+/// A callback deciding whether to accept inbound channels.
+pub(crate) type InboundChannelPolicy =
+	dyn Fn(&PendingChannelRequest) -> ChannelDecision + Send + Sync;
+
+/// The coin selection algorithm used to pick the inputs of an on-chain transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoinSelectionStrategy {
+	/// Spend the UTXOs with the largest value first, minimizing the number of inputs.
+	LargestFirst,
+	/// Spend the UTXOs with the smallest value first, consolidating small outputs.
+	SmallestFirst,
+	/// Search for an input set which doesn't require a change output, falling back to a random
+	/// selection if none is found.
+	BranchAndBound,
+}
+
+/// Parameters for sending an on-chain payment via [`Node::send_to_onchain_address_with_params`].
+///
+/// [`Node::send_to_onchain_address_with_params`]: crate::Node::send_to_onchain_address_with_params
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendOnchainParams {
+	/// The fee rate of the transaction, in satoshis per virtual byte.
+	///
+	/// If unset, the fee rate will be determined by our fee estimator. Must not be below the
+	/// minimum relay fee rate.
+	pub sat_per_vbyte: Option<u64>,
+	/// The coin selection algorithm used to pick the transaction's inputs.
+	pub coin_selection: CoinSelectionStrategy,
+	/// The UTXOs to exclusively spend from.
+	///
+	/// If unset, the inputs will be chosen from all available UTXOs of the wallet.
+	pub utxos_to_use: Option<Vec<OutPoint>>,
+}
+
+impl Default for SendOnchainParams {
+	fn default() -> Self {
+		Self {
+			sat_per_vbyte: None,
+			coin_selection: CoinSelectionStrategy::BranchAndBound,
+			utxos_to_use: None,
+		}
+	}
+}
+
+/// An unspent output of the on-chain wallet as returned by [`Node::list_onchain_utxos`].
+///
+/// [`Node::list_onchain_utxos`]: crate::Node::list_onchain_utxos
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utxo {
+	/// The outpoint of the unspent output.
+	pub outpoint: OutPoint,
+	/// The value of the output.
+	pub value_sats: u64,
+	/// The address the output pays to.
+	///
+	/// Will be `None` if the output script doesn't correspond to a standard address type.
+	pub address: Option<bitcoin::Address>,
+	/// The number of confirmations of the transaction creating the output.
+	///
+	/// Will be `0` if the transaction is still unconfirmed.
+	pub confirmations: u32,
+	/// Whether the output is reserved to fund a pending channel.
+	pub is_reserved: bool,
+	/// Whether the output is an unconfirmed change output of a transaction sent by us.
+	///
+	/// Spending unconfirmed change ties the new transaction to the fate of its parent.
+	pub is_unconfirmed_change: bool,
+}
+
+/// A label attached to one of our on-chain addresses via [`Node::new_onchain_address_with_label`].
+///
+/// [`Node::new_onchain_address_with_label`]: crate::Node::new_onchain_address_with_label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressLabel {
+	/// The labeled address.
+	pub address: bitcoin::Address,
+	/// The label attached to the address.
+	pub label: String,
+}
+
+/// The result of sweeping the on-chain wallet via [`Node::sweep_to_onchain_address`].
+///
+/// [`Node::sweep_to_onchain_address`]: crate::Node::sweep_to_onchain_address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SweepResult {
+	/// The txid of the sweeping transaction.
+	pub txid: Txid,
+	/// The amount sent to the destination address, i.e., after deducting the transaction fee.
+	pub swept_amount_sats: u64,
+}
+
+/// The destination probed via [`Node::send_probe`].
+///
+/// [`Node::send_probe`]: crate::Node::send_probe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeTarget {
+	/// Probe the paths to the node with the given node id.
+	Node {
+		/// The node id of the probed node.
+		node_id: PublicKey,
+	},
+	/// Probe the paths that would be used to pay the given invoice.
+	Invoice {
+		/// The probed invoice.
+		invoice: Bolt11Invoice,
+	},
+}
+
+/// Selects the route hints included in invoices created via
+/// [`Node::receive_payment_with_route_hints`].
+///
+/// Route hints allow payers to reach us via unannounced channels, but also reveal these channels
+/// to anybody the invoice is shared with.
+///
+/// [`Node::receive_payment_with_route_hints`]: crate::Node::receive_payment_with_route_hints
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum RouteHintSelection {
+	/// Let LDK select the route hints automatically.
+	#[default]
+	Automatic,
+	/// Only include route hints for the given channels.
+	///
+	/// Channels that aren't currently usable for receiving are excluded.
+	Channels {
+		/// The channels to include route hints for.
+		user_channel_ids: Vec<UserChannelId>,
+	},
+	/// Include route hints for up to `max_hints` usable channels, preferring the channels with the
+	/// most inbound capacity.
+	Limit {
+		/// The maximum number of route hints to include.
+		max_hints: u32,
+	},
+}
+
+/// The probes sent by [`Node::send_probe`].
+///
+/// [`Node::send_probe`]: crate::Node::send_probe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeResult {
+	/// The payment hashes of the sent probes, one for each probed path.
+	///
+	/// These may be used to match the probes to the corresponding [`Event::ProbeSuccessful`] and
+	/// [`Event::ProbeFailed`] events.
+	///
+	/// [`Event::ProbeSuccessful`]: crate::Event::ProbeSuccessful
+	/// [`Event::ProbeFailed`]: crate::Event::ProbeFailed
+	pub probe_hashes: Vec<PaymentHash>,
+}
+
+/// A custom TLV record sent along with or received as part of a payment.
+///
+/// As intermediate and receiving nodes need to tolerate unknown records, the type number of custom
+/// records must be odd and may not be lower than `2^16`, which is reserved for BOLT records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomTlvRecord {
+	/// The type number of the record.
+	pub type_num: u64,
+	/// The serialized value of the record.
+	pub value: Vec<u8>,
+}
+
+impl_writeable_tlv_based!(CustomTlvRecord, {
+	(0, type_num, required),
+	(2, value, required),
+});
+
+impl From<&(u64, Vec<u8>)> for CustomTlvRecord {
+	fn from(tlv: &(u64, Vec<u8>)) -> Self {
+		CustomTlvRecord { type_num: tlv.0, value: tlv.1.clone() }
+	}
+}
+
+/// The public output descriptors of the node's on-chain wallet as returned by
+/// [`Node::wallet_descriptors`].
+///
+/// The descriptors don't contain any private key material and may be used to set up a watch-only
+/// copy of the wallet.
+///
+/// [`Node::wallet_descriptors`]: crate::Node::wallet_descriptors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletDescriptors {
+	/// The descriptor used to derive receiving addresses.
+	pub external: String,
+	/// The descriptor used to derive change addresses.
+	pub internal: String,
 }
 
 /// Options which apply on a per-channel basis.
@@ -427,6 +811,11 @@ impl ChannelConfig {
 		self.inner.write().unwrap().accept_underpaying_htlcs = value;
 	}
 
+	/// Returns the set `max_dust_htlc_exposure`.
+	pub fn max_dust_htlc_exposure(&self) -> MaxDustHTLCExposure {
+		self.inner.read().unwrap().max_dust_htlc_exposure.into()
+	}
+
 	/// Sets the `max_dust_htlc_exposure` from a fixed limit.
 	pub fn set_max_dust_htlc_exposure_from_fixed_limit(&self, limit_msat: u64) {
 		self.inner.write().unwrap().max_dust_htlc_exposure =
@@ -440,6 +829,66 @@ impl ChannelConfig {
 	}
 }
 
+/// The maximum total value of dust HTLCs we allow to be pending on a channel.
+///
+/// HTLCs below the dust limit aren't represented as outputs in the commitment transaction, their
+/// value is instead added to the transaction fee. If the channel is force-closed while they are
+/// pending, their value is therefore lost to miners. A higher limit allows to route and receive
+/// more small payments concurrently, while a lower limit reduces the funds at risk. As the dust
+/// limit rises with the on-chain fee rate, a fixed limit may become too restrictive during fee
+/// spikes, which is why limiting via a fee-rate multiplier is usually preferable.
+///
+/// Zero values are considered invalid, as they would have the channel reject any dust HTLC.
+///
+/// See [`LdkChannelConfig::max_dust_htlc_exposure`] for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxDustHTLCExposure {
+	/// A fixed limit on the total dust HTLC exposure.
+	FixedLimit {
+		/// The limit, in millisatoshis.
+		limit_msat: u64,
+	},
+	/// A limit that scales with the current on-chain fee rate.
+	///
+	/// The limit in millisatoshis is the given multiplier times our high-priority fee rate
+	/// estimate, in satoshis per 1000 weight units.
+	FeeRateMultiplier {
+		/// The multiplier applied to the fee rate estimate.
+		multiplier: u64,
+	},
+}
+
+impl MaxDustHTLCExposure {
+	pub(crate) fn is_valid(&self) -> bool {
+		match self {
+			Self::FixedLimit { limit_msat } => *limit_msat > 0,
+			Self::FeeRateMultiplier { multiplier } => *multiplier > 0,
+		}
+	}
+}
+
+impl From<LdkMaxDustHTLCExposure> for MaxDustHTLCExposure {
+	fn from(value: LdkMaxDustHTLCExposure) -> Self {
+		match value {
+			LdkMaxDustHTLCExposure::FixedLimitMsat(limit_msat) => Self::FixedLimit { limit_msat },
+			LdkMaxDustHTLCExposure::FeeRateMultiplier(multiplier) => {
+				Self::FeeRateMultiplier { multiplier }
+			},
+		}
+	}
+}
+
+impl From<MaxDustHTLCExposure> for LdkMaxDustHTLCExposure {
+	fn from(value: MaxDustHTLCExposure) -> Self {
+		match value {
+			MaxDustHTLCExposure::FixedLimit { limit_msat } => Self::FixedLimitMsat(limit_msat),
+			MaxDustHTLCExposure::FeeRateMultiplier { multiplier } => {
+				Self::FeeRateMultiplier(multiplier)
+			},
+		}
+	}
+}
+
 impl From<LdkChannelConfig> for ChannelConfig {
 	fn from(value: LdkChannelConfig) -> Self {
 		Self { inner: RwLock::new(value) }
@@ -458,6 +907,36 @@ impl Default for ChannelConfig {
 	}
 }
 
+/// An update to the forwarding fee policy and dust exposure limit of a channel.
+///
+/// Any fields left unset will retain the currently configured value.
+///
+/// See documentation of [`LdkChannelConfig`] for details on the individual fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelConfigUpdate {
+	/// The proportional forwarding fee, in millionths of a satoshi per satoshi forwarded.
+	pub forwarding_fee_proportional_millionths: Option<u32>,
+	/// The base forwarding fee, in thousandths of a satoshi.
+	pub forwarding_fee_base_msat: Option<u32>,
+	/// The difference in the CLTV value between incoming HTLCs and outbound HTLCs forwarded over
+	/// the channel.
+	pub cltv_expiry_delta: Option<u16>,
+	/// The maximum total value of dust HTLCs we allow to be pending on the channel.
+	pub max_dust_htlc_exposure: Option<MaxDustHTLCExposure>,
+}
+
+impl From<ChannelConfigUpdate> for LdkChannelConfigUpdate {
+	fn from(value: ChannelConfigUpdate) -> Self {
+		Self {
+			forwarding_fee_proportional_millionths: value.forwarding_fee_proportional_millionths,
+			forwarding_fee_base_msat: value.forwarding_fee_base_msat,
+			cltv_expiry_delta: value.cltv_expiry_delta,
+			max_dust_htlc_exposure_msat: value.max_dust_htlc_exposure.map(|e| e.into()),
+			..Default::default()
+		}
+	}
+}
+
 /// The best known block as identified by its hash and height.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BestBlock {
@@ -474,3 +953,62 @@ impl From<LdkBestBlock> for BestBlock {
 		Self { block_hash, height }
 	}
 }
+
+/// An identifier of a [`JitChannelQuote`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct JitChannelQuoteId(pub [u8; 32]);
+
+impl Writeable for JitChannelQuoteId {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
+		Ok(self.0.write(writer)?)
+	}
+}
+
+impl Readable for JitChannelQuoteId {
+	fn read<R: lightning::io::Read>(
+		reader: &mut R,
+	) -> Result<Self, lightning::ln::msgs::DecodeError> {
+		Ok(Self(Readable::read(reader)?))
+	}
+}
+
+/// The fee and parameters an [LSPS2]-compliant LSP quoted for opening a just-in-time (JIT)
+/// channel to us, as returned by [`Node::request_jit_channel_quote`].
+///
+/// Requesting a quote doesn't commit us to anything. It needs to be accepted via
+/// [`Node::accept_jit_channel`] to retrieve a payable invoice, or may be discarded via
+/// [`Node::abandon_jit_channel_quote`].
+///
+/// [LSPS2]: https://github.com/BitcoinAndLightningLayerSpecs/lsp/blob/main/LSPS2/README.md
+/// [`Node::request_jit_channel_quote`]: crate::Node::request_jit_channel_quote
+/// [`Node::accept_jit_channel`]: crate::Node::accept_jit_channel
+/// [`Node::abandon_jit_channel_quote`]: crate::Node::abandon_jit_channel_quote
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JitChannelQuote {
+	/// The local identifier of the quote.
+	pub id: JitChannelQuoteId,
+	/// The node id of the LSP that issued the quote.
+	pub lsp_node_id: PublicKey,
+	/// The amount we'd like to receive via the JIT channel, in millisatoshis.
+	pub amount_msat: u64,
+	/// The total fee the LSP will take from the payment for opening the channel, in
+	/// millisatoshis.
+	pub opening_fee_msat: u64,
+	/// The time until which the quote may be accepted, as seconds since the UNIX epoch.
+	pub valid_until_secs: u64,
+	/// The number of blocks the LSP promises to keep the channel open for.
+	pub min_lifetime: u32,
+	/// The maximum number of blocks the LSP allows us to delay claiming our funds when the
+	/// channel is closed.
+	pub max_client_to_self_delay: u32,
+}
+
+impl_writeable_tlv_based!(JitChannelQuote, {
+	(0, id, required),
+	(2, lsp_node_id, required),
+	(4, amount_msat, required),
+	(6, opening_fee_msat, required),
+	(8, valid_until_secs, required),
+	(10, min_lifetime, required),
+	(12, max_client_to_self_delay, required),
+});
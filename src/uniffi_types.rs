@@ -1,24 +1,34 @@
+pub use lightning::blinded_path::BlindedPath;
+pub use lightning::chain::chaininterface::ConfirmationTarget;
 pub use lightning::events::{ClosureReason, PaymentFailureReason};
 pub use lightning::ln::ChannelId;
 pub use lightning::ln::PaymentSecret;
+pub use lightning::offers::offer::Offer;
+pub use lightning::offers::refund::Refund;
+pub use lightning::routing::gossip::NodeId;
 pub use lightning::util::string::UntrustedString;
 
+pub use bitcoin::bip32::ExtendedPubKey;
 pub use bitcoin::{BlockHash, Network, OutPoint};
 
 pub use bip39::Mnemonic;
 
+pub use std::net::SocketAddr;
+
 use crate::UniffiCustomTypeConverter;
 
 use crate::error::Error;
 use crate::hex_utils;
 use crate::io::sqlite_store::SqliteStore;
-use crate::{Node, SocketAddress, UserChannelId};
+use crate::{DescriptionHash, JitChannelQuoteId, Node, SocketAddress, UserChannelId};
 
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::Hash;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::{Address, Txid};
+use lightning::ln::channelmanager::{PaymentId, PhantomRouteHints};
 use lightning::ln::{PaymentHash, PaymentPreimage};
+use lightning::util::ser::{Readable, Writeable};
 use lightning_invoice::{Bolt11Invoice, SignedRawBolt11Invoice};
 
 use std::convert::TryInto;
@@ -94,6 +104,22 @@ impl UniffiCustomTypeConverter for PaymentHash {
 	}
 }
 
+impl UniffiCustomTypeConverter for DescriptionHash {
+	type Builtin = String;
+
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		if let Ok(hash) = Sha256::from_str(&val) {
+			Ok(DescriptionHash(hash.to_byte_array()))
+		} else {
+			Err(Error::InvalidDescriptionHash.into())
+		}
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		Sha256::from_slice(&obj.0).unwrap().to_string()
+	}
+}
+
 impl UniffiCustomTypeConverter for PaymentPreimage {
 	type Builtin = String;
 
@@ -112,6 +138,48 @@ impl UniffiCustomTypeConverter for PaymentPreimage {
 	}
 }
 
+impl UniffiCustomTypeConverter for PaymentId {
+	type Builtin = String;
+
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		if let Some(bytes_vec) = hex_utils::to_vec(&val) {
+			let bytes_res = bytes_vec.try_into();
+			if let Ok(bytes) = bytes_res {
+				return Ok(PaymentId(bytes));
+			}
+		}
+		Err(Error::InvalidPaymentId.into())
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		hex_utils::to_string(&obj.0)
+	}
+}
+
+impl UniffiCustomTypeConverter for PhantomRouteHints {
+	type Builtin = Vec<u8>;
+
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		Ok(PhantomRouteHints::read(&mut &val[..]).map_err(|_| Error::InvalidPhantomRouteHints)?)
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		obj.encode()
+	}
+}
+
+impl UniffiCustomTypeConverter for BlindedPath {
+	type Builtin = Vec<u8>;
+
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		Ok(BlindedPath::read(&mut &val[..]).map_err(|_| Error::InvalidBlindedPath)?)
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		obj.encode()
+	}
+}
+
 impl UniffiCustomTypeConverter for PaymentSecret {
 	type Builtin = String;
 
@@ -161,6 +229,48 @@ impl UniffiCustomTypeConverter for UserChannelId {
 	}
 }
 
+impl UniffiCustomTypeConverter for JitChannelQuoteId {
+	type Builtin = String;
+
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		if let Some(bytes_vec) = hex_utils::to_vec(&val) {
+			let bytes_res = bytes_vec.try_into();
+			if let Ok(bytes) = bytes_res {
+				return Ok(JitChannelQuoteId(bytes));
+			}
+		}
+		Err(Error::InvalidJitChannelQuote.into())
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		hex_utils::to_string(&obj.0)
+	}
+}
+
+impl UniffiCustomTypeConverter for Offer {
+	type Builtin = String;
+
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		Offer::from_str(&val).map_err(|_| Error::InvalidOffer.into())
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		obj.to_string()
+	}
+}
+
+impl UniffiCustomTypeConverter for Refund {
+	type Builtin = String;
+
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		Refund::from_str(&val).map_err(|_| Error::InvalidRefund.into())
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		obj.to_string()
+	}
+}
+
 impl UniffiCustomTypeConverter for Txid {
 	type Builtin = String;
 	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
@@ -172,6 +282,17 @@ impl UniffiCustomTypeConverter for Txid {
 	}
 }
 
+impl UniffiCustomTypeConverter for ExtendedPubKey {
+	type Builtin = String;
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		Ok(ExtendedPubKey::from_str(&val)?)
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		obj.to_string()
+	}
+}
+
 impl UniffiCustomTypeConverter for BlockHash {
 	type Builtin = String;
 	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
@@ -194,6 +315,17 @@ impl UniffiCustomTypeConverter for Mnemonic {
 	}
 }
 
+impl UniffiCustomTypeConverter for SocketAddr {
+	type Builtin = String;
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		Ok(SocketAddr::from_str(&val).map_err(|_| Error::InvalidSocketAddress)?)
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		obj.to_string()
+	}
+}
+
 impl UniffiCustomTypeConverter for SocketAddress {
 	type Builtin = String;
 	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
@@ -215,3 +347,20 @@ impl UniffiCustomTypeConverter for UntrustedString {
 		obj.to_string()
 	}
 }
+
+impl UniffiCustomTypeConverter for NodeId {
+	type Builtin = String;
+
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		if let Some(bytes_vec) = hex_utils::to_vec(&val) {
+			if let Ok(node_id) = NodeId::from_slice(&bytes_vec) {
+				return Ok(node_id);
+			}
+		}
+		Err(Error::InvalidPublicKey.into())
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		obj.to_string()
+	}
+}
@@ -0,0 +1,451 @@
+//! Holds a payment handler allowing to create and parse [BIP 21] URIs that combine an on-chain
+//! address with [BOLT 11] invoices and [BOLT 12] offers.
+//!
+//! [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+//! [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+//! [BOLT 12]: https://github.com/lightning/bolts/pull/798
+
+use crate::io::batch_kv_store::BatchKVStore;
+use crate::logger::{log_error, log_info, Logger};
+use crate::{Error, Node};
+
+use lightning::ln::PaymentHash;
+use lightning::offers::offer::Offer;
+use lightning_invoice::Bolt11Invoice;
+
+use bitcoin::{Address, Amount, Denomination, Network, Txid};
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BIP21_SCHEME: &str = "bitcoin";
+
+/// The components of a payment request decoded from, or encoded into, a [BIP 21] URI.
+///
+/// Its [`Display`] implementation yields the corresponding URI, so that parsing a URI via
+/// [`parse_bip21`] and displaying the result round-trips all supported fields.
+///
+/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+/// [`Display`]: fmt::Display
+/// [`parse_bip21`]: crate::parse_bip21
+#[derive(Debug, Clone, Default)]
+pub struct UnifiedPaymentRequest {
+	/// The on-chain address to pay to, if any.
+	///
+	/// When parsed from a URI, the address is checked to be valid for the given network.
+	pub address: Option<Address>,
+	/// The amount to pay, in satoshis.
+	pub amount_sats: Option<u64>,
+	/// A label for the recipient.
+	pub label: Option<String>,
+	/// A message describing the payment.
+	pub message: Option<String>,
+	/// A [BOLT 11] invoice that may be paid instead of the on-chain address.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	pub bolt11_invoice: Option<Bolt11Invoice>,
+	/// A [BOLT 12] offer that may be paid instead of the on-chain address.
+	///
+	/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+	pub bolt12_offer: Option<Offer>,
+}
+
+impl fmt::Display for UnifiedPaymentRequest {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:", BIP21_SCHEME)?;
+		if let Some(address) = &self.address {
+			write!(f, "{}", address)?;
+		}
+
+		let mut params = Vec::new();
+		if let Some(amount_sats) = self.amount_sats {
+			let amount = Amount::from_sat(amount_sats).to_string_in(Denomination::Bitcoin);
+			params.push(format!("amount={}", amount));
+		}
+		if let Some(label) = &self.label {
+			params.push(format!("label={}", percent_encode(label)));
+		}
+		if let Some(message) = &self.message {
+			params.push(format!("message={}", percent_encode(message)));
+		}
+		if let Some(invoice) = &self.bolt11_invoice {
+			params.push(format!("lightning={}", invoice));
+		}
+		if let Some(offer) = &self.bolt12_offer {
+			params.push(format!("lno={}", offer));
+		}
+
+		if !params.is_empty() {
+			write!(f, "?{}", params.join("&"))?;
+		}
+		Ok(())
+	}
+}
+
+/// Selects which components are included in the URIs created via [`UnifiedQrPayment::receive`].
+///
+/// By default, all components are included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnifiedQrComponents {
+	/// Whether to include a newly generated on-chain address.
+	pub onchain: bool,
+	/// Whether to include a [BOLT 11] invoice.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	pub bolt11: bool,
+	/// Whether to include a [BOLT 12] offer.
+	///
+	/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+	pub bolt12: bool,
+}
+
+impl Default for UnifiedQrComponents {
+	fn default() -> Self {
+		Self { onchain: true, bolt11: true, bolt12: true }
+	}
+}
+
+/// Determines which payment methods [`UnifiedQrPayment::send_auto`] may use.
+///
+/// Falling back to an on-chain payment needs to be opted into explicitly, as on-chain payments
+/// are public and therefore come with very different privacy properties.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RailPreference {
+	/// Only pay via Lightning.
+	#[default]
+	LightningOnly,
+	/// Pay via Lightning, but fall back to paying on-chain if the Lightning payment can't be
+	/// initiated, e.g., as our outbound liquidity is insufficient.
+	LightningWithOnchainFallback,
+	/// Only pay on-chain.
+	OnchainOnly,
+}
+
+/// The payment method used by [`UnifiedQrPayment::send_auto`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QrPaymentResult {
+	/// The [BOLT 11] invoice was paid.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	Bolt11 {
+		/// The hash of the payment.
+		payment_hash: PaymentHash,
+	},
+	/// The on-chain address was paid.
+	Onchain {
+		/// The id of the broadcast transaction.
+		txid: Txid,
+	},
+}
+
+/// A payment handler allowing to create and parse unified [BIP 21] URIs, as commonly encoded in
+/// QR codes.
+///
+/// Should be retrieved by calling [`Node::unified_qr_payment`].
+///
+/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+pub struct UnifiedQrPayment<'a, K: BatchKVStore + Sync + Send + 'static> {
+	node: &'a Node<K>,
+}
+
+impl<'a, K: BatchKVStore + Sync + Send + 'static> UnifiedQrPayment<'a, K> {
+	pub(crate) fn new(node: &'a Node<K>) -> Self {
+		Self { node }
+	}
+
+	/// Returns a [BIP 21] URI that can be used to request a payment of the given amount via the
+	/// selected `components`.
+	///
+	/// If `amount_sats` is `None`, the payer is free to choose the amount to pay. The included
+	/// invoice and offer will expire after `expiry_secs`, or after
+	/// [`Config::default_invoice_expiry_secs`] if `expiry_secs` is `0`.
+	///
+	/// Will return [`Error::InvalidUri`] if no component is selected.
+	///
+	/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+	/// [`Config::default_invoice_expiry_secs`]: crate::Config::default_invoice_expiry_secs
+	pub fn receive(
+		&self, amount_sats: Option<u64>, description: &str, expiry_secs: u32,
+		components: UnifiedQrComponents,
+	) -> Result<String, Error> {
+		if !components.onchain && !components.bolt11 && !components.bolt12 {
+			log_error!(self.node.logger, "Failed to create URI: no component was selected.");
+			return Err(Error::InvalidUri);
+		}
+
+		let amount_msat = amount_sats.map(|a| a.saturating_mul(1000));
+		let mut request = UnifiedPaymentRequest {
+			amount_sats,
+			message: Some(description.to_string()).filter(|d| !d.is_empty()),
+			..Default::default()
+		};
+
+		if components.onchain {
+			request.address = Some(self.node.new_onchain_address()?);
+		}
+
+		if components.bolt11 {
+			let invoice = match amount_msat {
+				Some(amount_msat) => {
+					self.node.receive_payment(amount_msat, description, expiry_secs)?
+				},
+				None => self.node.receive_variable_amount_payment(description, expiry_secs)?,
+			};
+			request.bolt11_invoice = Some(invoice);
+		}
+
+		if components.bolt12 {
+			let expiry_secs = self.node.invoice_expiry_secs(expiry_secs);
+			let absolute_expiry_secs = SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map(|now| now.as_secs() + expiry_secs as u64)
+				.map_err(|_| Error::OfferCreationFailed)?;
+			let offer = self.node.create_offer(
+				amount_msat,
+				description.to_string(),
+				Some(absolute_expiry_secs),
+				None,
+				None,
+			)?;
+			request.bolt12_offer = Some(offer);
+		}
+
+		let uri = request.to_string();
+		log_info!(self.node.logger, "Created unified payment URI: {}", uri);
+		Ok(uri)
+	}
+
+	/// Pays the given [BIP 21] URI, automatically selecting the payment method to use.
+	///
+	/// Lightning is always preferred over paying on-chain. If the URI doesn't allow paying via
+	/// Lightning, or the Lightning payment can't be initiated due to a lack of outbound liquidity,
+	/// i.e., fails with [`Error::InsufficientFunds`], [`Error::RouteNotFound`], or
+	/// [`Error::SinglePathRouteNotFound`], we'll only pay the on-chain address if `preference` is
+	/// [`RailPreference::LightningWithOnchainFallback`]. Any other error is returned as is. Note
+	/// that we can't fall back once a Lightning payment was initiated, even if it fails later on.
+	///
+	/// **Note:** As we don't support paying [BOLT 12] offers yet, any offer in the URI is ignored.
+	///
+	/// Will return [`Error::PaymentRailUnavailable`] if none of the URI's payment methods may be
+	/// used given the `preference`.
+	///
+	/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+	/// [BOLT 12]: https://github.com/lightning/bolts/pull/798
+	pub fn send_auto(
+		&self, uri: &str, preference: RailPreference,
+	) -> Result<QrPaymentResult, Error> {
+		let request = parse_uri(uri, self.node.config.network)?;
+		let onchain_allowed = preference != RailPreference::LightningOnly;
+
+		if preference != RailPreference::OnchainOnly {
+			if let Some(invoice) = &request.bolt11_invoice {
+				match self.send_bolt11(invoice, request.amount_sats) {
+					Ok(payment_hash) => return Ok(QrPaymentResult::Bolt11 { payment_hash }),
+					Err(
+						e @ (Error::InsufficientFunds
+						| Error::RouteNotFound
+						| Error::SinglePathRouteNotFound),
+					) if onchain_allowed && request.address.is_some() => {
+						log_info!(
+							self.node.logger,
+							"Failed to pay via Lightning, falling back to on-chain: {}",
+							e
+						);
+					},
+					Err(e) => return Err(e),
+				}
+			}
+		}
+
+		let address = match request.address.filter(|_| onchain_allowed) {
+			Some(address) => address,
+			None => {
+				log_error!(self.node.logger, "Failed to pay URI: no usable payment method.");
+				return Err(Error::PaymentRailUnavailable);
+			},
+		};
+		let amount_sats = request.amount_sats.ok_or_else(|| {
+			log_error!(self.node.logger, "Failed to pay URI: no amount to pay on-chain given.");
+			Error::InvalidAmount
+		})?;
+
+		let txid = self.node.send_to_onchain_address(&address, amount_sats)?;
+		Ok(QrPaymentResult::Onchain { txid })
+	}
+
+	fn send_bolt11(
+		&self, invoice: &Bolt11Invoice, amount_sats: Option<u64>,
+	) -> Result<PaymentHash, Error> {
+		let amount_msat = invoice
+			.amount_milli_satoshis()
+			.or(amount_sats.map(|a| a.saturating_mul(1000)))
+			.ok_or(Error::InvalidAmount)?;
+		if amount_msat > self.node.usable_balance_msat() {
+			log_error!(
+				self.node.logger,
+				"Insufficient outbound liquidity to send payment of {}msat",
+				amount_msat
+			);
+			return Err(Error::InsufficientFunds);
+		}
+
+		if invoice.amount_milli_satoshis().is_some() {
+			self.node.send_payment(invoice)
+		} else {
+			self.node.send_payment_using_amount(invoice, amount_msat)
+		}
+	}
+}
+
+pub(crate) fn parse_uri(uri: &str, network: Network) -> Result<UnifiedPaymentRequest, Error> {
+	let uri = uri.trim();
+	let (scheme, rest) = uri.split_once(':').ok_or(Error::InvalidUri)?;
+	if !scheme.eq_ignore_ascii_case(BIP21_SCHEME) {
+		return Err(Error::InvalidUri);
+	}
+
+	let (address, query) = match rest.split_once('?') {
+		Some((address, query)) => (address, Some(query)),
+		None => (rest, None),
+	};
+
+	let mut request = UnifiedPaymentRequest::default();
+	if !address.is_empty() {
+		let address = Address::from_str(address)
+			.and_then(|address| address.require_network(network))
+			.map_err(|_| Error::InvalidAddress)?;
+		request.address = Some(address);
+	}
+
+	for param in query.into_iter().flat_map(|q| q.split('&')).filter(|p| !p.is_empty()) {
+		let (key, value) = param.split_once('=').unwrap_or((param, ""));
+		let value = percent_decode(value)?;
+		match key.to_ascii_lowercase().as_str() {
+			"amount" => {
+				let amount = Amount::from_str_in(&value, Denomination::Bitcoin)
+					.map_err(|_| Error::InvalidAmount)?;
+				request.amount_sats = Some(amount.to_sat());
+			},
+			"label" => request.label = Some(value),
+			"message" => request.message = Some(value),
+			"lightning" => {
+				let invoice = Bolt11Invoice::from_str(&value.to_ascii_lowercase())
+					.map_err(|_| Error::InvalidInvoice)?;
+				request.bolt11_invoice = Some(invoice);
+			},
+			"lno" => {
+				let offer = Offer::from_str(&value.to_ascii_lowercase())
+					.map_err(|_| Error::InvalidOffer)?;
+				request.bolt12_offer = Some(offer);
+			},
+			key if key.starts_with("req-") => return Err(Error::InvalidUri),
+			_ => {},
+		}
+	}
+
+	if request.address.is_none()
+		&& request.bolt11_invoice.is_none()
+		&& request.bolt12_offer.is_none()
+	{
+		return Err(Error::InvalidUri);
+	}
+
+	Ok(request)
+}
+
+pub(crate) fn percent_encode(value: &str) -> String {
+	let mut encoded = String::with_capacity(value.len());
+	for byte in value.bytes() {
+		match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+				encoded.push(byte as char)
+			},
+			_ => encoded.push_str(&format!("%{:02X}", byte)),
+		}
+	}
+	encoded
+}
+
+fn percent_decode(value: &str) -> Result<String, Error> {
+	let bytes = value.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		match bytes[i] {
+			b'%' => {
+				let hex = bytes.get(i + 1..i + 3).ok_or(Error::InvalidUri)?;
+				let hex = std::str::from_utf8(hex).map_err(|_| Error::InvalidUri)?;
+				decoded.push(u8::from_str_radix(hex, 16).map_err(|_| Error::InvalidUri)?);
+				i += 3;
+			},
+			b'+' => {
+				decoded.push(b' ');
+				i += 1;
+			},
+			byte => {
+				decoded.push(byte);
+				i += 1;
+			},
+		}
+	}
+	String::from_utf8(decoded).map_err(|_| Error::InvalidUri)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_lenient_uris() {
+		let address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+
+		let request = parse_uri(&format!("BITCOIN:{}", address), Network::Bitcoin).unwrap();
+		assert_eq!(request.address.unwrap().to_string(), address);
+		assert_eq!(request.amount_sats, None);
+
+		let uri = format!(
+			"bitcoin:{}?Amount=0.001&foo=bar&label=Luke%20Jr&message=Donation+for+project",
+			address.to_ascii_uppercase()
+		);
+		let request = parse_uri(&uri, Network::Bitcoin).unwrap();
+		assert_eq!(request.address.unwrap().to_string(), address);
+		assert_eq!(request.amount_sats, Some(100_000));
+		assert_eq!(request.label.as_deref(), Some("Luke Jr"));
+		assert_eq!(request.message.as_deref(), Some("Donation for project"));
+
+		let uri = format!("bitcoin:{}?req-somethingyoudontunderstand=50", address);
+		assert_eq!(parse_uri(&uri, Network::Bitcoin).unwrap_err(), Error::InvalidUri);
+		let uri = "bitcoin:?amount=1";
+		assert_eq!(parse_uri(uri, Network::Bitcoin).unwrap_err(), Error::InvalidUri);
+		let uri = format!("lightning:{}", address);
+		assert_eq!(parse_uri(&uri, Network::Bitcoin).unwrap_err(), Error::InvalidUri);
+	}
+
+	#[test]
+	fn rejects_addresses_for_other_networks() {
+		let uri = "bitcoin:bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+		assert_eq!(parse_uri(uri, Network::Testnet).unwrap_err(), Error::InvalidAddress);
+		assert_eq!(parse_uri(uri, Network::Regtest).unwrap_err(), Error::InvalidAddress);
+		assert!(parse_uri(uri, Network::Bitcoin).is_ok());
+	}
+
+	#[test]
+	fn uri_roundtrip() {
+		let address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+		let request = UnifiedPaymentRequest {
+			address: Some(Address::from_str(address).unwrap().assume_checked()),
+			amount_sats: Some(123_456),
+			label: Some("Café & Co".to_string()),
+			message: Some("Order #42".to_string()),
+			bolt11_invoice: None,
+			bolt12_offer: None,
+		};
+		let parsed = parse_uri(&request.to_string(), Network::Bitcoin).unwrap();
+		assert_eq!(parsed.address, request.address);
+		assert_eq!(parsed.amount_sats, request.amount_sats);
+		assert_eq!(parsed.label, request.label);
+		assert_eq!(parsed.message, request.message);
+		assert_eq!(parsed.to_string(), request.to_string());
+	}
+}
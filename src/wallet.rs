@@ -1,35 +1,140 @@
+use crate::chain::bitcoind_rpc::BitcoindRpcClient;
+#[cfg(feature = "cbf")]
+use crate::chain::cbf::CbfClient;
 use crate::logger::{log_error, log_info, log_trace, Logger};
 
+use crate::types::{
+	ChannelFundingParameters, CoinSelectionStrategy, SendOnchainParams, SweepResult,
+};
 use crate::Error;
 
-use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator};
+use lightning::chain::chaininterface::{
+	BroadcasterInterface, ConfirmationTarget, FeeEstimator, FEERATE_FLOOR_SATS_PER_KW,
+};
+use lightning::events::bump_transaction::{Utxo, WalletSource};
 
 use lightning::ln::msgs::{DecodeError, UnsignedGossipMessage};
 use lightning::ln::script::ShutdownScript;
 use lightning::sign::{
-	EntropySource, InMemorySigner, KeyMaterial, KeysManager, NodeSigner, Recipient, SignerProvider,
-	SpendableOutputDescriptor,
+	EntropySource, InMemorySigner, KeyMaterial, KeysManager, NodeSigner, PhantomKeysManager,
+	Recipient, SignerProvider, SpendableOutputDescriptor,
 };
 
 use lightning::util::message_signing;
 
 use bdk::blockchain::EsploraBlockchain;
-use bdk::database::BatchDatabase;
+use bdk::database::{BatchDatabase, Database};
+use bdk::wallet::coin_selection::{
+	decide_change, BranchAndBoundCoinSelection, CoinSelectionAlgorithm, CoinSelectionResult,
+	LargestFirstCoinSelection,
+};
 use bdk::wallet::AddressIndex;
-use bdk::FeeRate;
-use bdk::{SignOptions, SyncOptions};
+use bdk::{FeeRate, WeightedUtxo};
+use bdk::{KeychainKind, SignOptions, SyncOptions};
 
 use bitcoin::bech32::u5;
+use bitcoin::bip32::ExtendedPubKey;
 use bitcoin::blockdata::locktime::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::PartiallySignedTransaction;
 use bitcoin::secp256k1::ecdh::SharedSecret;
 use bitcoin::secp256k1::ecdsa::{RecoverableSignature, Signature};
 use bitcoin::secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey, Signing};
-use bitcoin::{ScriptBuf, Transaction, TxOut, Txid};
+use bitcoin::{OutPoint, Script, ScriptBuf, Transaction, TxOut, Txid, WPubkeyHash, Weight};
+
+use std::collections::{HashMap, HashSet};
 
 use std::ops::Deref;
 use std::sync::{Arc, Condvar, Mutex};
 use std::time::Duration;
 
+/// Converts the given fee rate to satoshis per 1000 weight units.
+///
+/// Returns `None` if the fee rate is below the minimum relay fee rate.
+pub(crate) fn sat_per_vbyte_to_sat_per_kw(sat_per_vbyte: u64) -> Option<u32> {
+	// LDK's fee rate floor of 253 sat/kW corresponds to the minimum relay fee rate of 1 sat/vB,
+	// when rounded down.
+	let min_sat_per_vbyte = FEERATE_FLOOR_SATS_PER_KW as u64 * 4 / 1000;
+	if sat_per_vbyte < min_sat_per_vbyte {
+		return None;
+	}
+	Some(sat_per_vbyte.saturating_mul(250).min(u32::MAX as u64) as u32)
+}
+
+// The weight of a transaction input without its witness, i.e., the previous outpoint (36 bytes),
+// the sequence (4 bytes) and the empty script length (1 byte).
+const TXIN_BASE_WEIGHT: usize = (32 + 4 + 4 + 1) * 4;
+
+/// A coin selection algorithm spending the UTXOs with the smallest value first.
+///
+/// BDK only ships the inverse [`LargestFirstCoinSelection`], which we mirror here.
+#[derive(Debug, Default, Clone, Copy)]
+struct SmallestFirstCoinSelection;
+
+impl<D: Database> CoinSelectionAlgorithm<D> for SmallestFirstCoinSelection {
+	fn coin_select(
+		&self, _database: &D, required_utxos: Vec<WeightedUtxo>,
+		mut optional_utxos: Vec<WeightedUtxo>, fee_rate: FeeRate, target_amount: u64,
+		drain_script: &Script,
+	) -> Result<CoinSelectionResult, bdk::Error> {
+		optional_utxos.sort_unstable_by_key(|wu| wu.utxo.txout().value);
+
+		let mut selected = Vec::new();
+		let mut selected_amount = 0u64;
+		let mut fee_amount = 0u64;
+		for (must_use, weighted_utxo) in required_utxos
+			.into_iter()
+			.map(|wu| (true, wu))
+			.chain(optional_utxos.into_iter().map(|wu| (false, wu)))
+		{
+			if !must_use && selected_amount >= target_amount.saturating_add(fee_amount) {
+				break;
+			}
+			let input_weight = TXIN_BASE_WEIGHT + weighted_utxo.satisfaction_weight;
+			fee_amount += fee_rate.fee_wu(Weight::from_wu(input_weight as u64));
+			selected_amount += weighted_utxo.utxo.txout().value;
+			selected.push(weighted_utxo.utxo);
+		}
+
+		let needed = target_amount.saturating_add(fee_amount);
+		if selected_amount < needed {
+			return Err(bdk::Error::InsufficientFunds { needed, available: selected_amount });
+		}
+
+		let excess = decide_change(selected_amount - needed, fee_rate, drain_script);
+		Ok(CoinSelectionResult { selected, fee_amount, excess })
+	}
+}
+
+/// The BDK blockchain backend used to sync the on-chain wallet.
+pub(crate) enum WalletBlockchain {
+	Esplora(EsploraBlockchain),
+	BitcoindRpc(Arc<BitcoindRpcClient>),
+	#[cfg(feature = "cbf")]
+	Cbf(Arc<CbfClient>),
+}
+
+/// A transaction affecting the balance of our on-chain wallet.
+pub(crate) struct WalletTransaction {
+	pub txid: Txid,
+	/// The sum of the transaction's outputs paying to us.
+	pub received_sats: u64,
+	/// The sum of the transaction's inputs spending from us.
+	pub sent_sats: u64,
+	pub fee_sats: u64,
+	pub confirmation_height: Option<u32>,
+	/// The addresses of our wallet the transaction pays to.
+	pub receiving_addresses: Vec<bitcoin::Address>,
+	/// The outpoints spent by the transaction.
+	pub spent_outpoints: Vec<OutPoint>,
+}
+
+struct PendingChannelFunding {
+	utxos: Option<Vec<OutPoint>>,
+	fee_rate_sat_per_kw: Option<u32>,
+	enable_rbf: bool,
+}
+
 pub struct Wallet<D, B: Deref, E: Deref, L: Deref>
 where
 	D: BatchDatabase,
@@ -38,9 +143,13 @@ where
 	L::Target: Logger,
 {
 	// A BDK blockchain used for wallet sync.
-	blockchain: EsploraBlockchain,
+	blockchain: WalletBlockchain,
 	// A BDK on-chain wallet.
 	inner: Mutex<bdk::Wallet<D>>,
+	// The BIP84 account-level extended public key of the wallet.
+	account_xpub: ExtendedPubKey,
+	// The funding parameters of pending channels, keyed by their `user_channel_id`.
+	pending_channel_fundings: Mutex<HashMap<u128, PendingChannelFunding>>,
 	// A cache storing the most recently retrieved fee rate estimations.
 	broadcaster: B,
 	fee_estimator: E,
@@ -56,12 +165,22 @@ where
 	L::Target: Logger,
 {
 	pub(crate) fn new(
-		blockchain: EsploraBlockchain, wallet: bdk::Wallet<D>, broadcaster: B, fee_estimator: E,
-		logger: L,
+		blockchain: WalletBlockchain, wallet: bdk::Wallet<D>, account_xpub: ExtendedPubKey,
+		broadcaster: B, fee_estimator: E, logger: L,
 	) -> Self {
 		let inner = Mutex::new(wallet);
+		let pending_channel_fundings = Mutex::new(HashMap::new());
 		let sync_lock = (Mutex::new(()), Condvar::new());
-		Self { blockchain, inner, broadcaster, fee_estimator, sync_lock, logger }
+		Self {
+			blockchain,
+			inner,
+			account_xpub,
+			pending_channel_fundings,
+			broadcaster,
+			fee_estimator,
+			sync_lock,
+			logger,
+		}
 	}
 
 	pub(crate) async fn sync(&self) -> Result<(), Error> {
@@ -80,32 +199,49 @@ where
 
 		let sync_options = SyncOptions { progress: None };
 		let wallet_lock = self.inner.lock().unwrap();
-		let res = match wallet_lock.sync(&self.blockchain, sync_options).await {
-			Ok(()) => Ok(()),
-			Err(e) => match e {
-				bdk::Error::Esplora(ref be) => match **be {
-					bdk::blockchain::esplora::EsploraError::Reqwest(_) => {
-						tokio::time::sleep(Duration::from_secs(1)).await;
-						log_error!(
-							self.logger,
-							"Sync failed due to HTTP connection error, retrying: {}",
-							e
-						);
-						let sync_options = SyncOptions { progress: None };
-						wallet_lock
-							.sync(&self.blockchain, sync_options)
-							.await
-							.map_err(|e| From::from(e))
+		let res = match &self.blockchain {
+			WalletBlockchain::Esplora(blockchain) => {
+				match wallet_lock.sync(blockchain, sync_options).await {
+					Ok(()) => Ok(()),
+					Err(e) => match e {
+						bdk::Error::Esplora(ref be) => match **be {
+							bdk::blockchain::esplora::EsploraError::Reqwest(_) => {
+								tokio::time::sleep(Duration::from_secs(1)).await;
+								log_error!(
+									self.logger,
+									"Sync failed due to HTTP connection error, retrying: {}",
+									e
+								);
+								let sync_options = SyncOptions { progress: None };
+								wallet_lock
+									.sync(blockchain, sync_options)
+									.await
+									.map_err(|e| From::from(e))
+							},
+							_ => {
+								log_error!(self.logger, "Sync failed due to Esplora error: {}", e);
+								Err(From::from(e))
+							},
+						},
+						_ => {
+							log_error!(self.logger, "Wallet sync error: {}", e);
+							Err(From::from(e))
+						},
 					},
-					_ => {
-						log_error!(self.logger, "Sync failed due to Esplora error: {}", e);
-						Err(From::from(e))
-					},
-				},
-				_ => {
-					log_error!(self.logger, "Wallet sync error: {}", e);
-					Err(From::from(e))
-				},
+				}
+			},
+			WalletBlockchain::BitcoindRpc(bitcoind_rpc_client) => {
+				wallet_lock.sync(&**bitcoind_rpc_client, sync_options).await.map_err(|e| {
+					log_error!(self.logger, "Sync failed due to bitcoind RPC error: {}", e);
+					From::from(e)
+				})
+			},
+			#[cfg(feature = "cbf")]
+			WalletBlockchain::Cbf(cbf_client) => {
+				wallet_lock.sync(&**cbf_client, sync_options).await.map_err(|e| {
+					log_error!(self.logger, "Sync failed due to compact filter error: {}", e);
+					From::from(e)
+				})
 			},
 		};
 
@@ -114,22 +250,161 @@ where
 		res
 	}
 
+	/// Registers how the channel with the given `user_channel_id` is to be funded.
+	///
+	/// If `utxos` are given, they are reserved to exclusively fund the channel. Fails if any of them
+	/// is not an unspent output of our wallet or already reserved for another channel, or if they
+	/// don't cover the channel amount plus the estimated fees.
+	pub(crate) fn register_channel_funding(
+		&self, user_channel_id: u128, utxos: Option<Vec<OutPoint>>,
+		funding_params: ChannelFundingParameters, channel_amount_sats: u64,
+		confirmation_target: ConfirmationTarget,
+	) -> Result<(), Error> {
+		let fee_rate_sat_per_kw =
+			self.fee_rate_or_estimate(funding_params.fee_rate_sat_per_vbyte, confirmation_target)?;
+
+		let mut pending_fundings_lock = self.pending_channel_fundings.lock().unwrap();
+
+		if let Some(utxos) = utxos.as_ref() {
+			let unspent = self.inner.lock().unwrap().list_unspent()?;
+
+			let mut total_value_sats = 0u64;
+			for (i, outpoint) in utxos.iter().enumerate() {
+				if utxos[..i].contains(outpoint) {
+					log_error!(self.logger, "Funding UTXO {} was given more than once", outpoint);
+					return Err(Error::FundingUtxoUnavailable);
+				}
+
+				if pending_fundings_lock
+					.values()
+					.any(|f| f.utxos.as_ref().map_or(false, |u| u.contains(outpoint)))
+				{
+					log_error!(
+						self.logger,
+						"Funding UTXO {} is already reserved to fund another channel",
+						outpoint
+					);
+					return Err(Error::FundingUtxoUnavailable);
+				}
+
+				match unspent.iter().find(|u| u.outpoint == *outpoint) {
+					Some(utxo) => total_value_sats += utxo.txout.value,
+					None => {
+						log_error!(
+							self.logger,
+							"Funding UTXO {} is unknown to the wallet or already spent",
+							outpoint
+						);
+						return Err(Error::FundingUtxoUnavailable);
+					},
+				}
+			}
+
+			// We estimate the funding transaction's size assuming P2WPKH inputs, a P2WSH funding
+			// output, and a P2WPKH change output.
+			let estimated_vbytes = 11 + 68 * utxos.len() as u64 + 43 + 31;
+			let estimated_fee_sats = estimated_vbytes * 4 * fee_rate_sat_per_kw as u64 / 1000;
+
+			let required_sats = channel_amount_sats.saturating_add(estimated_fee_sats);
+			if total_value_sats < required_sats {
+				log_error!(
+					self.logger,
+					"The given funding UTXOs are insufficient: required {}sat (including an estimated fee of {}sat), gave {}sat",
+					required_sats,
+					estimated_fee_sats,
+					total_value_sats
+				);
+				return Err(Error::InsufficientFunds);
+			}
+		}
+
+		let fee_rate_sat_per_kw =
+			funding_params.fee_rate_sat_per_vbyte.map(|_| fee_rate_sat_per_kw);
+		let pending_funding = PendingChannelFunding {
+			utxos,
+			fee_rate_sat_per_kw,
+			enable_rbf: funding_params.enable_rbf,
+		};
+		pending_fundings_lock.insert(user_channel_id, pending_funding);
+		Ok(())
+	}
+
+	/// Drops the funding parameters registered for the channel with the given `user_channel_id`, if
+	/// any, releasing the reserved UTXOs.
+	pub(crate) fn release_channel_funding(&self, user_channel_id: u128) {
+		self.pending_channel_fundings.lock().unwrap().remove(&user_channel_id);
+	}
+
+	/// Returns the given fee rate in sat/kW if set, or our fee estimate for the given
+	/// confirmation target otherwise.
+	///
+	/// Fails if the given fee rate is below the minimum relay fee rate.
+	fn fee_rate_or_estimate(
+		&self, sat_per_vbyte: Option<u64>, confirmation_target: ConfirmationTarget,
+	) -> Result<u32, Error> {
+		match sat_per_vbyte {
+			Some(sat_per_vbyte) => sat_per_vbyte_to_sat_per_kw(sat_per_vbyte).ok_or_else(|| {
+				log_error!(
+					self.logger,
+					"The given fee rate of {}sat/vB is below the minimum relay fee rate",
+					sat_per_vbyte
+				);
+				Error::InvalidFeeRate
+			}),
+			None => Ok(self.fee_estimator.get_est_sat_per_1000_weight(confirmation_target)),
+		}
+	}
+
+	/// Returns the UTXOs reserved to fund pending channels, which must not be spent otherwise.
+	///
+	/// Must not be called while holding the wallet lock, as `register_channel_funding` acquires the
+	/// locks in the opposite order.
+	fn reserved_utxos(&self) -> Vec<OutPoint> {
+		self.pending_channel_fundings
+			.lock()
+			.unwrap()
+			.values()
+			.filter_map(|f| f.utxos.clone())
+			.flatten()
+			.collect()
+	}
+
 	pub(crate) fn create_funding_transaction(
 		&self, output_script: ScriptBuf, value_sats: u64, confirmation_target: ConfirmationTarget,
-		locktime: LockTime,
+		locktime: LockTime, user_channel_id: u128,
 	) -> Result<Transaction, Error> {
-		let fee_rate = FeeRate::from_sat_per_kwu(
-			self.fee_estimator.get_est_sat_per_1000_weight(confirmation_target) as f32,
-		);
+		let pending_funding =
+			self.pending_channel_fundings.lock().unwrap().remove(&user_channel_id);
+
+		let fee_rate_sat_per_kw = pending_funding
+			.as_ref()
+			.and_then(|f| f.fee_rate_sat_per_kw)
+			.unwrap_or_else(|| self.fee_estimator.get_est_sat_per_1000_weight(confirmation_target));
+		let fee_rate = FeeRate::from_sat_per_kwu(fee_rate_sat_per_kw as f32);
+		let enable_rbf = pending_funding.as_ref().map_or(true, |f| f.enable_rbf);
+
+		// The UTXOs reserved for this channel were released above, so these are the ones of other
+		// pending channels.
+		let reserved_utxos = self.reserved_utxos();
 
 		let locked_wallet = self.inner.lock().unwrap();
 		let mut tx_builder = locked_wallet.build_tx();
 
-		tx_builder
-			.add_recipient(output_script, value_sats)
-			.fee_rate(fee_rate)
-			.nlocktime(locktime)
-			.enable_rbf();
+		tx_builder.add_recipient(output_script, value_sats).fee_rate(fee_rate).nlocktime(locktime);
+
+		if enable_rbf {
+			tx_builder.enable_rbf();
+		}
+
+		if let Some(funding_utxos) = pending_funding.and_then(|f| f.utxos) {
+			tx_builder.add_utxos(&funding_utxos).map_err(|e| {
+				log_error!(self.logger, "Failed to add funding UTXOs: {}", e);
+				Error::FundingUtxoUnavailable
+			})?;
+			tx_builder.manually_selected_only();
+		} else {
+			tx_builder.unspendable(reserved_utxos);
+		}
 
 		let mut psbt = match tx_builder.finish() {
 			Ok((psbt, _)) => {
@@ -162,38 +437,279 @@ where
 		Ok(address_info.address)
 	}
 
+	pub(crate) fn account_xpub(&self) -> ExtendedPubKey {
+		self.account_xpub
+	}
+
+	/// Returns the public output descriptor of the given keychain.
+	pub(crate) fn public_descriptor(&self, keychain: KeychainKind) -> Result<String, Error> {
+		let descriptor = self.inner.lock().unwrap().public_descriptor(keychain)?;
+		descriptor.map(|d| d.to_string()).ok_or_else(|| {
+			log_error!(self.logger, "Failed to retrieve {:?} wallet descriptor", keychain);
+			Error::WalletOperationFailed
+		})
+	}
+
 	pub(crate) fn get_balance(&self) -> Result<bdk::Balance, Error> {
 		Ok(self.inner.lock().unwrap().get_balance()?)
 	}
 
-	/// Send funds to the given address.
+	/// Returns the spendable balance of the wallet, excluding any UTXOs which are uneconomical to
+	/// spend, i.e., whose value doesn't exceed the fee required to spend them at our current
+	/// on-chain fee rate estimate.
+	///
+	/// Like BDK's [`bdk::Balance::get_spendable`], this includes confirmed UTXOs as well as
+	/// unconfirmed change outputs of our own transactions.
+	pub(crate) fn get_spendable_balance_excluding_dust(&self) -> Result<u64, Error> {
+		let fee_rate_sat_per_kw =
+			self.fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::NonAnchorChannelFee);
+
+		let locked_wallet = self.inner.lock().unwrap();
+		let spendable_sats = locked_wallet.get_balance()?.get_spendable();
+
+		let confirmed_txids: HashSet<Txid> = locked_wallet
+			.list_transactions(false)?
+			.into_iter()
+			.filter(|t| t.confirmation_time.is_some())
+			.map(|t| t.txid)
+			.collect();
+		let dust_sats: u64 = locked_wallet
+			.list_unspent()?
+			.into_iter()
+			.filter(|u| {
+				confirmed_txids.contains(&u.outpoint.txid) || u.keychain == KeychainKind::Internal
+			})
+			.filter(|u| {
+				let satisfaction_weight = locked_wallet
+					.get_descriptor_for_keychain(u.keychain)
+					.max_satisfaction_weight()
+					.unwrap_or(0);
+				let input_weight = (TXIN_BASE_WEIGHT + satisfaction_weight) as u64;
+				let spend_fee_sats = input_weight * fee_rate_sat_per_kw as u64 / 1000;
+				u.txout.value <= spend_fee_sats
+			})
+			.map(|u| u.txout.value)
+			.sum();
+
+		Ok(spendable_sats.saturating_sub(dust_sats))
+	}
+
+	/// Lists all transactions affecting the balance of the wallet.
+	pub(crate) fn list_transactions(&self) -> Result<Vec<WalletTransaction>, Error> {
+		let locked_wallet = self.inner.lock().unwrap();
+		let network = locked_wallet.network();
+
+		let mut res = Vec::new();
+		for details in locked_wallet.list_transactions(true)? {
+			let mut receiving_addresses = Vec::new();
+			let mut spent_outpoints = Vec::new();
+			if let Some(tx) = details.transaction.as_ref() {
+				spent_outpoints.extend(tx.input.iter().map(|input| input.previous_output));
+				for output in tx.output.iter() {
+					if locked_wallet.is_mine(&output.script_pubkey)? {
+						if let Ok(address) =
+							bitcoin::Address::from_script(&output.script_pubkey, network)
+						{
+							receiving_addresses.push(address);
+						}
+					}
+				}
+			}
+
+			res.push(WalletTransaction {
+				txid: details.txid,
+				received_sats: details.received,
+				sent_sats: details.sent,
+				fee_sats: details.fee.unwrap_or(0),
+				confirmation_height: details.confirmation_time.map(|ct| ct.height),
+				receiving_addresses,
+				spent_outpoints,
+			});
+		}
+		Ok(res)
+	}
+
+	/// Lists the unspent outputs of the wallet, counting confirmations based on the given chain tip
+	/// height.
+	pub(crate) fn list_utxos(&self, cur_height: u32) -> Result<Vec<crate::types::Utxo>, Error> {
+		// We have to collect the reserved UTXOs before locking the wallet, as
+		// `register_channel_funding` acquires the locks in this order.
+		let reserved_utxos = self.reserved_utxos();
+
+		let locked_wallet = self.inner.lock().unwrap();
+		let network = locked_wallet.network();
+		let confirmation_heights: HashMap<Txid, u32> = locked_wallet
+			.list_transactions(false)?
+			.into_iter()
+			.filter_map(|t| t.confirmation_time.map(|ct| (t.txid, ct.height)))
+			.collect();
+
+		let utxos = locked_wallet
+			.list_unspent()?
+			.into_iter()
+			.map(|u| {
+				let confirmations = confirmation_heights
+					.get(&u.outpoint.txid)
+					.map_or(0, |height| cur_height.saturating_sub(*height) + 1);
+				crate::types::Utxo {
+					outpoint: u.outpoint,
+					value_sats: u.txout.value,
+					address: bitcoin::Address::from_script(&u.txout.script_pubkey, network).ok(),
+					confirmations,
+					is_reserved: reserved_utxos.contains(&u.outpoint),
+					is_unconfirmed_change: confirmations == 0
+						&& u.keychain == KeychainKind::Internal,
+				}
+			})
+			.collect();
+		Ok(utxos)
+	}
+
+	/// Bumps the fee of the given unconfirmed wallet transaction to the given fee rate.
 	///
-	/// If `amount_msat_or_drain` is `None` the wallet will be drained, i.e., all available funds will be
-	/// spent.
+	/// Transactions sent by us are replaced via RBF, unless `must_cpfp` is set, e.g., as the
+	/// transaction funds a channel and hence mustn't change its txid. Otherwise, as well as for
+	/// transactions paying to us, a child spending one of our outputs is created (CPFP) so that
+	/// both transactions together pay the given fee rate.
+	///
+	/// Returns the txid of the replacement or child transaction.
+	pub(crate) fn bump_transaction_fee(
+		&self, txid: &Txid, fee_rate_sat_per_kw: u32, must_cpfp: bool,
+	) -> Result<Txid, Error> {
+		let fee_rate = FeeRate::from_sat_per_kwu(fee_rate_sat_per_kw as f32);
+		let reserved_utxos = self.reserved_utxos();
+
+		let tx = {
+			let locked_wallet = self.inner.lock().unwrap();
+			let tx_details = locked_wallet.get_tx(txid, true)?.ok_or_else(|| {
+				log_error!(self.logger, "Failed to bump fee of unknown transaction {}", txid);
+				Error::TransactionNotFound
+			})?;
+
+			if tx_details.confirmation_time.is_some() {
+				log_error!(self.logger, "Failed to bump fee of confirmed transaction {}", txid);
+				return Err(Error::TransactionAlreadyConfirmed);
+			}
+
+			let mut psbt = if tx_details.sent > 0 && !must_cpfp {
+				let mut tx_builder = locked_wallet.build_fee_bump(*txid).map_err(|e| {
+					log_error!(self.logger, "Failed to replace transaction {}: {}", txid, e);
+					Error::OnchainTxCreationFailed
+				})?;
+				tx_builder.fee_rate(fee_rate).unspendable(reserved_utxos).enable_rbf();
+				tx_builder
+					.finish()
+					.map_err(|e| {
+						log_error!(self.logger, "Failed to replace transaction {}: {}", txid, e);
+						Error::InvalidFeeRate
+					})?
+					.0
+			} else {
+				let parent_tx = tx_details.transaction.ok_or(Error::TransactionNotFound)?;
+				let unspent = locked_wallet.list_unspent()?;
+				let (vout, value_sats) = parent_tx
+					.output
+					.iter()
+					.enumerate()
+					.filter(|(vout, _)| {
+						let outpoint = OutPoint { txid: *txid, vout: *vout as u32 };
+						unspent.iter().any(|u| u.outpoint == outpoint)
+							&& !reserved_utxos.contains(&outpoint)
+					})
+					.map(|(vout, output)| (vout as u32, output.value))
+					.max_by_key(|(_, value)| *value)
+					.ok_or_else(|| {
+						log_error!(
+							self.logger,
+							"Failed to bump fee of transaction {} as it has no unspent outputs of ours",
+							txid
+						);
+						Error::TransactionNotFound
+					})?;
+
+				// The child needs to pay for the whole package, while we account for what the
+				// parent already paid, if known. We assume a single P2WPKH input and output.
+				let child_weight = (11 + 68 + 31) * 4;
+				let package_weight = parent_tx.weight().to_wu() + child_weight;
+				let package_fee_sats = package_weight * fee_rate_sat_per_kw as u64 / 1000;
+				let child_fee_sats = package_fee_sats.saturating_sub(tx_details.fee.unwrap_or(0));
+				if child_fee_sats >= value_sats {
+					log_error!(
+						self.logger,
+						"Failed to bump fee of transaction {} as its output of {}sat can't cover the required fee of {}sat",
+						txid,
+						value_sats,
+						child_fee_sats
+					);
+					return Err(Error::InsufficientFunds);
+				}
+
+				let drain_script =
+					locked_wallet.get_address(AddressIndex::New)?.address.script_pubkey();
+				let mut tx_builder = locked_wallet.build_tx();
+				tx_builder
+					.add_utxo(OutPoint { txid: *txid, vout })?
+					.manually_selected_only()
+					.drain_to(drain_script)
+					.fee_absolute(child_fee_sats)
+					.enable_rbf();
+				tx_builder
+					.finish()
+					.map_err(|e| {
+						log_error!(self.logger, "Failed to create CPFP transaction: {}", e);
+						Error::OnchainTxCreationFailed
+					})?
+					.0
+			};
+
+			match locked_wallet.sign(&mut psbt, SignOptions::default()) {
+				Ok(finalized) => {
+					if !finalized {
+						return Err(Error::OnchainTxCreationFailed);
+					}
+				},
+				Err(err) => {
+					log_error!(self.logger, "Failed to create transaction: {}", err);
+					return Err(err.into());
+				},
+			}
+			psbt.extract_tx()
+		};
+
+		self.broadcaster.broadcast_transactions(&[&tx]);
+
+		let bump_txid = tx.txid();
+		log_info!(
+			self.logger,
+			"Bumped fee of transaction {} via transaction {} to {}sat/kW",
+			txid,
+			bump_txid,
+			fee_rate_sat_per_kw
+		);
+		Ok(bump_txid)
+	}
+
+	/// Send the given amount to the given address.
+	///
+	/// To drain the wallet, use [`Self::sweep_to_address`] instead.
 	pub(crate) fn send_to_address(
-		&self, address: &bitcoin::Address, amount_msat_or_drain: Option<u64>,
+		&self, address: &bitcoin::Address, amount_sats: u64,
 	) -> Result<Txid, Error> {
 		let confirmation_target = ConfirmationTarget::NonAnchorChannelFee;
 		let fee_rate = FeeRate::from_sat_per_kwu(
 			self.fee_estimator.get_est_sat_per_1000_weight(confirmation_target) as f32,
 		);
+		let reserved_utxos = self.reserved_utxos();
 
 		let tx = {
 			let locked_wallet = self.inner.lock().unwrap();
 			let mut tx_builder = locked_wallet.build_tx();
 
-			if let Some(amount_sats) = amount_msat_or_drain {
-				tx_builder
-					.add_recipient(address.script_pubkey(), amount_sats)
-					.fee_rate(fee_rate)
-					.enable_rbf();
-			} else {
-				tx_builder
-					.drain_wallet()
-					.drain_to(address.script_pubkey())
-					.fee_rate(fee_rate)
-					.enable_rbf();
-			}
+			tx_builder
+				.add_recipient(address.script_pubkey(), amount_sats)
+				.unspendable(reserved_utxos)
+				.fee_rate(fee_rate)
+				.enable_rbf();
 
 			let mut psbt = match tx_builder.finish() {
 				Ok((psbt, _)) => {
@@ -224,25 +740,364 @@ where
 
 		let txid = tx.txid();
 
-		if let Some(amount_sats) = amount_msat_or_drain {
-			log_info!(
+		log_info!(
+			self.logger,
+			"Created new transaction {} sending {}sats on-chain to address {}",
+			txid,
+			amount_sats,
+			address
+		);
+
+		Ok(txid)
+	}
+
+	/// Send the given amount to the given address, using the given fee rate and coin selection.
+	///
+	/// If `utxos_to_use` are given, the transaction will exclusively spend from them. Otherwise,
+	/// UTXOs reserved to fund pending channels are excluded from the coin selection.
+	pub(crate) fn send_to_address_with_params(
+		&self, address: &bitcoin::Address, amount_sats: u64, params: SendOnchainParams,
+	) -> Result<Txid, Error> {
+		let script_pubkey = address.script_pubkey();
+		let dust_limit_sats = script_pubkey.dust_value().to_sat();
+		if amount_sats < dust_limit_sats {
+			log_error!(
 				self.logger,
-				"Created new transaction {} sending {}sats on-chain to address {}",
-				txid,
+				"Unable to send {}sats to {} as the output would be below the dust limit of {}sats",
 				amount_sats,
-				address
-			);
-		} else {
-			log_info!(
-				self.logger,
-				"Created new transaction {} sending all available on-chain funds to address {}",
-				txid,
-				address
+				address,
+				dust_limit_sats
 			);
+			return Err(Error::InvalidAmount);
 		}
 
+		let fee_rate_sat_per_kw = self
+			.fee_rate_or_estimate(params.sat_per_vbyte, ConfirmationTarget::NonAnchorChannelFee)?;
+		let fee_rate = FeeRate::from_sat_per_kwu(fee_rate_sat_per_kw as f32);
+
+		// We have to collect the reserved UTXOs before locking the wallet, as
+		// `register_channel_funding` acquires the locks in this order.
+		let reserved_utxos = self.reserved_utxos();
+
+		if let Some(utxos) = params.utxos_to_use.as_ref() {
+			let unspent = self.inner.lock().unwrap().list_unspent()?;
+			for (i, outpoint) in utxos.iter().enumerate() {
+				if utxos[..i].contains(outpoint) {
+					log_error!(self.logger, "UTXO {} was given more than once", outpoint);
+					return Err(Error::UtxoUnavailable);
+				}
+				if reserved_utxos.contains(outpoint) {
+					log_error!(
+						self.logger,
+						"UTXO {} is reserved to fund a pending channel",
+						outpoint
+					);
+					return Err(Error::UtxoUnavailable);
+				}
+				if !unspent.iter().any(|u| u.outpoint == *outpoint) {
+					log_error!(
+						self.logger,
+						"UTXO {} is unknown to the wallet or already spent",
+						outpoint
+					);
+					return Err(Error::UtxoUnavailable);
+				}
+			}
+		}
+
+		let utxos_to_use = params.utxos_to_use.as_deref();
+		let tx = match params.coin_selection {
+			CoinSelectionStrategy::LargestFirst => self.create_send_transaction(
+				LargestFirstCoinSelection,
+				script_pubkey,
+				amount_sats,
+				fee_rate,
+				utxos_to_use,
+				reserved_utxos,
+			)?,
+			CoinSelectionStrategy::SmallestFirst => self.create_send_transaction(
+				SmallestFirstCoinSelection,
+				script_pubkey,
+				amount_sats,
+				fee_rate,
+				utxos_to_use,
+				reserved_utxos,
+			)?,
+			CoinSelectionStrategy::BranchAndBound => self.create_send_transaction(
+				BranchAndBoundCoinSelection::default(),
+				script_pubkey,
+				amount_sats,
+				fee_rate,
+				utxos_to_use,
+				reserved_utxos,
+			)?,
+		};
+
+		self.broadcaster.broadcast_transactions(&[&tx]);
+
+		let txid = tx.txid();
+		log_info!(
+			self.logger,
+			"Created new transaction {} sending {}sats on-chain to address {} at {}sat/kW",
+			txid,
+			amount_sats,
+			address,
+			fee_rate_sat_per_kw
+		);
+
 		Ok(txid)
 	}
+
+	/// Sends all spendable funds except for `reserve_sats` to the given address, deducting the
+	/// transaction fee from the swept amount.
+	///
+	/// If `reserve_sats` is non-zero, it is kept in a change output. UTXOs reserved to fund pending
+	/// channels are never swept.
+	pub(crate) fn sweep_to_address(
+		&self, address: &bitcoin::Address, sat_per_vbyte: Option<u32>, reserve_sats: u64,
+	) -> Result<SweepResult, Error> {
+		let fee_rate_sat_per_kw = self.fee_rate_or_estimate(
+			sat_per_vbyte.map(|r| r as u64),
+			ConfirmationTarget::NonAnchorChannelFee,
+		)?;
+		let fee_rate = FeeRate::from_sat_per_kwu(fee_rate_sat_per_kw as f32);
+
+		// We have to collect the reserved UTXOs before locking the wallet, as
+		// `register_channel_funding` acquires the locks in this order.
+		let reserved_utxos = self.reserved_utxos();
+
+		let script_pubkey = address.script_pubkey();
+		let dust_limit_sats = script_pubkey.dust_value().to_sat();
+
+		let tx = {
+			let locked_wallet = self.inner.lock().unwrap();
+
+			// We first build a transaction draining all spendable funds to the given address,
+			// which, if we don't need to keep a reserve, is the one we broadcast.
+			let mut tx_builder = locked_wallet.build_tx();
+			tx_builder
+				.drain_wallet()
+				.drain_to(script_pubkey.clone())
+				.unspendable(reserved_utxos.clone())
+				.fee_rate(fee_rate)
+				.enable_rbf();
+			let (mut psbt, details) = tx_builder.finish().map_err(|e| {
+				log_error!(self.logger, "Failed to create sweeping transaction: {}", e);
+				match e {
+					bdk::Error::InsufficientFunds { .. } | bdk::Error::NoUtxosSelected => {
+						Error::InsufficientFunds
+					},
+					e => e.into(),
+				}
+			})?;
+
+			if reserve_sats > 0 {
+				// Otherwise, we send the drained amount minus the reserve and the estimated fee,
+				// leaving the reserve in a change output.
+				let drained_sats = details.sent.saturating_sub(details.fee.unwrap_or(0));
+				let amount_sats = drained_sats.saturating_sub(reserve_sats);
+				if amount_sats < dust_limit_sats {
+					log_error!(
+						self.logger,
+						"Unable to sweep wallet as the spendable funds of {}sats don't exceed the reserve of {}sats",
+						drained_sats,
+						reserve_sats
+					);
+					return Err(Error::InsufficientFunds);
+				}
+
+				let mut tx_builder = locked_wallet.build_tx();
+				tx_builder
+					.add_recipient(script_pubkey.clone(), amount_sats)
+					.unspendable(reserved_utxos)
+					.fee_rate(fee_rate)
+					.enable_rbf();
+				psbt = match tx_builder.finish() {
+					Ok((psbt, _)) => psbt,
+					Err(err) => {
+						log_error!(self.logger, "Failed to create sweeping transaction: {}", err);
+						return Err(err.into());
+					},
+				};
+			}
+			log_trace!(self.logger, "Created sweeping PSBT: {:?}", psbt);
+
+			match locked_wallet.sign(&mut psbt, SignOptions::default()) {
+				Ok(finalized) => {
+					if !finalized {
+						return Err(Error::OnchainTxCreationFailed);
+					}
+				},
+				Err(err) => {
+					log_error!(self.logger, "Failed to create sweeping transaction: {}", err);
+					return Err(err.into());
+				},
+			}
+			psbt.extract_tx()
+		};
+
+		let swept_amount_sats = tx
+			.output
+			.iter()
+			.filter(|o| o.script_pubkey == script_pubkey)
+			.map(|o| o.value)
+			.sum::<u64>();
+		if swept_amount_sats < dust_limit_sats {
+			log_error!(self.logger, "Unable to sweep wallet as the swept amount would be dust");
+			return Err(Error::InsufficientFunds);
+		}
+
+		self.broadcaster.broadcast_transactions(&[&tx]);
+
+		let txid = tx.txid();
+		log_info!(
+			self.logger,
+			"Created new transaction {} sweeping {}sats on-chain to address {}",
+			txid,
+			swept_amount_sats,
+			address
+		);
+
+		Ok(SweepResult { txid, swept_amount_sats })
+	}
+
+	fn create_send_transaction<Cs: CoinSelectionAlgorithm<D>>(
+		&self, coin_selection: Cs, script_pubkey: ScriptBuf, amount_sats: u64, fee_rate: FeeRate,
+		utxos_to_use: Option<&[OutPoint]>, reserved_utxos: Vec<OutPoint>,
+	) -> Result<Transaction, Error> {
+		let locked_wallet = self.inner.lock().unwrap();
+		let mut tx_builder = locked_wallet.build_tx().coin_selection(coin_selection);
+		tx_builder.add_recipient(script_pubkey, amount_sats).fee_rate(fee_rate).enable_rbf();
+
+		if let Some(utxos) = utxos_to_use {
+			tx_builder.add_utxos(utxos).map_err(|e| {
+				log_error!(self.logger, "Failed to add UTXOs: {}", e);
+				Error::UtxoUnavailable
+			})?;
+			tx_builder.manually_selected_only();
+		} else {
+			tx_builder.unspendable(reserved_utxos);
+		}
+
+		let mut psbt = match tx_builder.finish() {
+			Ok((psbt, _)) => {
+				log_trace!(self.logger, "Created PSBT: {:?}", psbt);
+				psbt
+			},
+			Err(bdk::Error::InsufficientFunds { needed, available }) => {
+				log_error!(
+					self.logger,
+					"Failed to create transaction due to insufficient funds: needed {}sats, available {}sats",
+					needed,
+					available
+				);
+				return Err(Error::InsufficientFunds);
+			},
+			Err(err) => {
+				log_error!(self.logger, "Failed to create transaction: {}", err);
+				return Err(err.into());
+			},
+		};
+
+		match locked_wallet.sign(&mut psbt, SignOptions::default()) {
+			Ok(finalized) => {
+				if !finalized {
+					return Err(Error::OnchainTxCreationFailed);
+				}
+			},
+			Err(err) => {
+				log_error!(self.logger, "Failed to create transaction: {}", err);
+				return Err(err.into());
+			},
+		}
+
+		Ok(psbt.extract_tx())
+	}
+}
+
+impl<D, B: Deref, E: Deref, L: Deref> WalletSource for Wallet<D, B, E, L>
+where
+	D: BatchDatabase,
+	B::Target: BroadcasterInterface,
+	E::Target: FeeEstimator,
+	L::Target: Logger,
+{
+	fn list_confirmed_utxos(&self) -> Result<Vec<Utxo>, ()> {
+		let reserved_utxos = self.reserved_utxos();
+		let locked_wallet = self.inner.lock().unwrap();
+		let confirmed_txids: Vec<Txid> = locked_wallet
+			.list_transactions(false)
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to retrieve transactions from wallet: {}", e);
+			})?
+			.into_iter()
+			.filter(|t| t.confirmation_time.is_some())
+			.map(|t| t.txid)
+			.collect();
+
+		let unspent_confirmed_utxos = locked_wallet
+			.list_unspent()
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to retrieve unspent outputs from wallet: {}", e);
+			})?
+			.into_iter()
+			.filter(|u| confirmed_txids.contains(&u.outpoint.txid))
+			.filter(|u| !reserved_utxos.contains(&u.outpoint));
+
+		let mut utxos = Vec::new();
+		for u in unspent_confirmed_utxos {
+			if !u.txout.script_pubkey.is_v0_p2wpkh() {
+				log_error!(
+					self.logger,
+					"Unexpected script type of UTXO {}, only P2WPKH is supported",
+					u.outpoint
+				);
+				return Err(());
+			}
+
+			// The P2WPKH script consists of the witness version, a push opcode, and the 20-byte
+			// pubkey hash.
+			let wpkh =
+				WPubkeyHash::from_slice(&u.txout.script_pubkey.as_bytes()[2..]).map_err(|e| {
+					log_error!(self.logger, "Failed to retrieve pubkey hash of UTXO: {}", e);
+				})?;
+			utxos.push(Utxo::new_v0_p2wpkh(u.outpoint, u.txout.value, &wpkh));
+		}
+
+		Ok(utxos)
+	}
+
+	fn get_change_script(&self) -> Result<ScriptBuf, ()> {
+		let locked_wallet = self.inner.lock().unwrap();
+		let address_info = locked_wallet.get_address(AddressIndex::LastUnused).map_err(|e| {
+			log_error!(self.logger, "Failed to retrieve new address from wallet: {}", e);
+		})?;
+
+		Ok(address_info.address.script_pubkey())
+	}
+
+	fn sign_psbt(&self, mut psbt: PartiallySignedTransaction) -> Result<Transaction, ()> {
+		let locked_wallet = self.inner.lock().unwrap();
+
+		// While BDK populates both `witness_utxo` and `non_witness_utxo` fields, LDK does not. As
+		// BDK by default doesn't trust the witness UTXO to account for the Segwit bug, we must set
+		// `trust_witness_utxo` to accept the PSBT.
+		let sign_options = SignOptions { trust_witness_utxo: true, ..Default::default() };
+
+		match locked_wallet.sign(&mut psbt, sign_options) {
+			Ok(_finalized) => {
+				// BDK will fail to finalize for all LDK-provided inputs of the PSBT. Unfortunately
+				// we can't check more fine grained if it succeeded for all the other inputs here,
+				// so we just ignore the returned `finalized` bool.
+			},
+			Err(err) => {
+				log_error!(self.logger, "Failed to sign transaction: {}", err);
+				return Err(());
+			},
+		}
+
+		Ok(psbt.extract_tx())
+	}
 }
 
 /// Similar to [`KeysManager`], but overrides the destination and shutdown scripts so they are
@@ -254,11 +1109,43 @@ where
 	E::Target: FeeEstimator,
 	L::Target: Logger,
 {
-	inner: KeysManager,
+	inner: InnerKeysManager,
 	wallet: Arc<Wallet<D, B, E, L>>,
 	logger: L,
 }
 
+/// The LDK keys manager we delegate to, depending on whether phantom payments are enabled.
+///
+/// Both variants derive different inbound payment keys, which is why we only use the
+/// [`PhantomKeysManager`] if it was explicitly configured.
+enum InnerKeysManager {
+	Standard(KeysManager),
+	Phantom(PhantomKeysManager),
+}
+
+impl InnerKeysManager {
+	fn node_signer(&self) -> &dyn NodeSigner {
+		match self {
+			Self::Standard(keys_manager) => keys_manager,
+			Self::Phantom(keys_manager) => keys_manager,
+		}
+	}
+
+	fn entropy_source(&self) -> &dyn EntropySource {
+		match self {
+			Self::Standard(keys_manager) => keys_manager,
+			Self::Phantom(keys_manager) => keys_manager,
+		}
+	}
+
+	fn signer_provider(&self) -> &dyn SignerProvider<EcdsaSigner = InMemorySigner> {
+		match self {
+			Self::Standard(keys_manager) => keys_manager,
+			Self::Phantom(keys_manager) => keys_manager,
+		}
+	}
+}
+
 impl<D, B: Deref, E: Deref, L: Deref> WalletKeysManager<D, B, E, L>
 where
 	D: BatchDatabase,
@@ -269,38 +1156,68 @@ where
 	/// Constructs a `WalletKeysManager` that overrides the destination and shutdown scripts.
 	///
 	/// See [`KeysManager::new`] for more information on `seed`, `starting_time_secs`, and
-	/// `starting_time_nanos`.
+	/// `starting_time_nanos`. If `phantom_cross_node_seed` is set, a [`PhantomKeysManager`] is
+	/// used, allowing to receive payments to phantom invoices shared with other nodes.
 	pub fn new(
 		seed: &[u8; 32], starting_time_secs: u64, starting_time_nanos: u32,
-		wallet: Arc<Wallet<D, B, E, L>>, logger: L,
+		phantom_cross_node_seed: Option<&[u8; 32]>, wallet: Arc<Wallet<D, B, E, L>>, logger: L,
 	) -> Self {
-		let inner = KeysManager::new(seed, starting_time_secs, starting_time_nanos);
+		let inner = match phantom_cross_node_seed {
+			Some(cross_node_seed) => InnerKeysManager::Phantom(PhantomKeysManager::new(
+				seed,
+				starting_time_secs,
+				starting_time_nanos,
+				cross_node_seed,
+			)),
+			None => InnerKeysManager::Standard(KeysManager::new(
+				seed,
+				starting_time_secs,
+				starting_time_nanos,
+			)),
+		};
 		Self { inner, wallet, logger }
 	}
 
+	/// Returns whether phantom payments are enabled.
+	pub fn is_phantom(&self) -> bool {
+		matches!(self.inner, InnerKeysManager::Phantom(_))
+	}
+
 	/// See [`KeysManager::spend_spendable_outputs`] for documentation on this method.
 	pub fn spend_spendable_outputs<C: Signing>(
 		&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>,
 		change_destination_script: ScriptBuf, feerate_sat_per_1000_weight: u32,
 		locktime: Option<LockTime>, secp_ctx: &Secp256k1<C>,
 	) -> Result<Transaction, ()> {
-		self.inner.spend_spendable_outputs(
-			descriptors,
-			outputs,
-			change_destination_script,
-			feerate_sat_per_1000_weight,
-			locktime,
-			secp_ctx,
-		)
+		match &self.inner {
+			InnerKeysManager::Standard(keys_manager) => keys_manager.spend_spendable_outputs(
+				descriptors,
+				outputs,
+				change_destination_script,
+				feerate_sat_per_1000_weight,
+				locktime,
+				secp_ctx,
+			),
+			InnerKeysManager::Phantom(keys_manager) => keys_manager.spend_spendable_outputs(
+				descriptors,
+				outputs,
+				change_destination_script,
+				feerate_sat_per_1000_weight,
+				locktime,
+				secp_ctx,
+			),
+		}
 	}
 
 	pub fn sign_message(&self, msg: &[u8]) -> Result<String, Error> {
-		message_signing::sign(msg, &self.inner.get_node_secret_key())
-			.or(Err(Error::MessageSigningFailed))
+		message_signing::sign(msg, &self.get_node_secret_key()).or(Err(Error::MessageSigningFailed))
 	}
 
 	pub fn get_node_secret_key(&self) -> SecretKey {
-		self.inner.get_node_secret_key()
+		match &self.inner {
+			InnerKeysManager::Standard(keys_manager) => keys_manager.get_node_secret_key(),
+			InnerKeysManager::Phantom(keys_manager) => keys_manager.get_node_secret_key(),
+		}
 	}
 
 	pub fn verify_signature(&self, msg: &[u8], sig: &str, pkey: &PublicKey) -> bool {
@@ -316,39 +1233,39 @@ where
 	L::Target: Logger,
 {
 	fn get_node_id(&self, recipient: Recipient) -> Result<PublicKey, ()> {
-		self.inner.get_node_id(recipient)
+		self.inner.node_signer().get_node_id(recipient)
 	}
 
 	fn ecdh(
 		&self, recipient: Recipient, other_key: &PublicKey, tweak: Option<&Scalar>,
 	) -> Result<SharedSecret, ()> {
-		self.inner.ecdh(recipient, other_key, tweak)
+		self.inner.node_signer().ecdh(recipient, other_key, tweak)
 	}
 
 	fn get_inbound_payment_key_material(&self) -> KeyMaterial {
-		self.inner.get_inbound_payment_key_material()
+		self.inner.node_signer().get_inbound_payment_key_material()
 	}
 
 	fn sign_invoice(
 		&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient,
 	) -> Result<RecoverableSignature, ()> {
-		self.inner.sign_invoice(hrp_bytes, invoice_data, recipient)
+		self.inner.node_signer().sign_invoice(hrp_bytes, invoice_data, recipient)
 	}
 
 	fn sign_gossip_message(&self, msg: UnsignedGossipMessage<'_>) -> Result<Signature, ()> {
-		self.inner.sign_gossip_message(msg)
+		self.inner.node_signer().sign_gossip_message(msg)
 	}
 
 	fn sign_bolt12_invoice(
 		&self, invoice: &lightning::offers::invoice::UnsignedBolt12Invoice,
 	) -> Result<bitcoin::secp256k1::schnorr::Signature, ()> {
-		self.inner.sign_bolt12_invoice(invoice)
+		self.inner.node_signer().sign_bolt12_invoice(invoice)
 	}
 
 	fn sign_bolt12_invoice_request(
 		&self, invoice_request: &lightning::offers::invoice_request::UnsignedInvoiceRequest,
 	) -> Result<bitcoin::secp256k1::schnorr::Signature, ()> {
-		self.inner.sign_bolt12_invoice_request(invoice_request)
+		self.inner.node_signer().sign_bolt12_invoice_request(invoice_request)
 	}
 }
 
@@ -360,7 +1277,7 @@ where
 	L::Target: Logger,
 {
 	fn get_secure_random_bytes(&self) -> [u8; 32] {
-		self.inner.get_secure_random_bytes()
+		self.inner.entropy_source().get_secure_random_bytes()
 	}
 }
 
@@ -376,17 +1293,21 @@ where
 	fn generate_channel_keys_id(
 		&self, inbound: bool, channel_value_satoshis: u64, user_channel_id: u128,
 	) -> [u8; 32] {
-		self.inner.generate_channel_keys_id(inbound, channel_value_satoshis, user_channel_id)
+		self.inner.signer_provider().generate_channel_keys_id(
+			inbound,
+			channel_value_satoshis,
+			user_channel_id,
+		)
 	}
 
 	fn derive_channel_signer(
 		&self, channel_value_satoshis: u64, channel_keys_id: [u8; 32],
 	) -> Self::EcdsaSigner {
-		self.inner.derive_channel_signer(channel_value_satoshis, channel_keys_id)
+		self.inner.signer_provider().derive_channel_signer(channel_value_satoshis, channel_keys_id)
 	}
 
 	fn read_chan_signer(&self, reader: &[u8]) -> Result<Self::EcdsaSigner, DecodeError> {
-		self.inner.read_chan_signer(reader)
+		self.inner.signer_provider().read_chan_signer(reader)
 	}
 
 	fn get_destination_script(&self, _channel_keys_id: [u8; 32]) -> Result<ScriptBuf, ()> {
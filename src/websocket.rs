@@ -0,0 +1,128 @@
+//! Objects for connecting to peers via a WebSocket transport.
+//!
+//! As our [`PeerManager`] is tied to a single socket descriptor type, WebSocket connections are
+//! bridged to a local TCP socket pair which is then handed to `lightning-net-tokio`. This allows
+//! us to reuse the same peer handling and noise handshake as for plain TCP connections.
+
+use crate::logger::{log_error, log_trace, FilesystemLogger, Logger};
+use crate::types::PeerManager;
+
+use lightning::ln::msgs::SocketAddress;
+use lightning::util::persist::KVStore;
+
+use bitcoin::secp256k1::PublicKey;
+
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+
+use std::future::Future;
+use std::sync::Arc;
+
+const BRIDGE_READ_BUFFER_SIZE: usize = 65536;
+
+/// Connects to the given peer via WebSocket and hands the connection to the [`PeerManager`].
+///
+/// Returns a future that completes once the connection is closed, or `None` if we failed to
+/// establish the connection.
+pub(crate) async fn connect_outbound<K: KVStore + Sync + Send + 'static>(
+	peer_manager: Arc<PeerManager<K>>, node_id: PublicKey, addr: &SocketAddress,
+	logger: Arc<FilesystemLogger>,
+) -> Option<impl Future<Output = ()>> {
+	let url = format!("ws://{}", addr);
+	let (ws_stream, _) = match tokio_tungstenite::connect_async(&url).await {
+		Ok(res) => res,
+		Err(e) => {
+			log_error!(logger, "Failed to open WebSocket connection to {}: {}", url, e);
+			return None;
+		},
+	};
+
+	let local_stream = match bridge_to_local_tcp(ws_stream, Arc::clone(&logger)).await {
+		Ok(stream) => stream,
+		Err(e) => {
+			log_error!(logger, "Failed to set up WebSocket bridge for {}: {}", url, e);
+			return None;
+		},
+	};
+
+	Some(lightning_net_tokio::setup_outbound(peer_manager, node_id, local_stream))
+}
+
+/// Creates a loopback TCP socket pair, spawns a task forwarding data between the given WebSocket
+/// and one end, and returns the other end.
+async fn bridge_to_local_tcp<S>(
+	ws_stream: tokio_tungstenite::WebSocketStream<S>, logger: Arc<FilesystemLogger>,
+) -> std::io::Result<std::net::TcpStream>
+where
+	S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+	let listener = TcpListener::bind("127.0.0.1:0").await?;
+	let local_addr = listener.local_addr()?;
+
+	// Any other local process may connect to the listener, too. As our connection is queued once
+	// `connect` returns, we only accept the connection originating from our end of the pair and
+	// drop any other.
+	let peer_end = TcpStream::connect(local_addr).await?;
+	let peer_end_addr = peer_end.local_addr()?;
+	let bridge_end = loop {
+		let (stream, remote_addr) = listener.accept().await?;
+		if remote_addr == peer_end_addr {
+			break stream;
+		}
+		log_error!(
+			logger,
+			"Dropping unexpected connection from {} to WebSocket bridge",
+			remote_addr
+		);
+	};
+	drop(listener);
+
+	tokio::spawn(async move {
+		let (mut ws_sink, mut ws_source) = ws_stream.split();
+		let (mut tcp_read, mut tcp_write) = bridge_end.into_split();
+
+		let ws_to_tcp = async {
+			while let Some(msg) = ws_source.next().await {
+				match msg {
+					Ok(Message::Binary(data)) => {
+						if tcp_write.write_all(&data).await.is_err() {
+							break;
+						}
+					},
+					Ok(Message::Close(_)) => break,
+					Ok(_) => {},
+					Err(e) => {
+						log_trace!(logger, "WebSocket connection errored: {}", e);
+						break;
+					},
+				}
+			}
+		};
+
+		let tcp_to_ws = async {
+			let mut buf = vec![0u8; BRIDGE_READ_BUFFER_SIZE];
+			loop {
+				match tcp_read.read(&mut buf).await {
+					Ok(0) | Err(_) => break,
+					Ok(n) => {
+						if ws_sink.send(Message::Binary(buf[..n].to_vec())).await.is_err() {
+							break;
+						}
+					},
+				}
+			}
+			let _ = ws_sink.close().await;
+		};
+
+		tokio::select! {
+			_ = ws_to_tcp => {},
+			_ = tcp_to_ws => {},
+		}
+	});
+
+	let peer_end = peer_end.into_std()?;
+	peer_end.set_nonblocking(true)?;
+	Ok(peer_end)
+}
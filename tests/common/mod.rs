@@ -1,6 +1,7 @@
 #![cfg(any(test, cln_test, vss_test))]
 #![allow(dead_code)]
 
+use ldk_node::io::batch_kv_store::BatchKVStore;
 use ldk_node::io::sqlite_store::SqliteStore;
 use ldk_node::{
 	Builder, Config, Event, LogLevel, Node, NodeError, PaymentDirection, PaymentStatus,
@@ -83,7 +84,7 @@ pub(crate) use expect_channel_ready_event;
 macro_rules! expect_payment_received_event {
 	($node: expr, $amount_msat: expr) => {{
 		match $node.wait_next_event() {
-			ref e @ Event::PaymentReceived { payment_hash, amount_msat } => {
+			ref e @ Event::PaymentReceived { payment_hash, amount_msat, .. } => {
 				println!("{} got event {:?}", $node.node_id(), e);
 				assert_eq!(amount_msat, $amount_msat);
 				$node.event_handled();
@@ -332,7 +333,7 @@ pub(crate) fn premine_and_distribute_funds<E: ElectrumApi>(
 	generate_blocks_and_wait(bitcoind, electrs, 1);
 }
 
-pub fn open_channel<K: KVStore + Sync + Send>(
+pub fn open_channel<K: BatchKVStore + Sync + Send>(
 	node_a: &TestNode<K>, node_b: &TestNode<K>, funding_amount_sat: u64, announce: bool,
 	electrsd: &ElectrsD,
 ) {
@@ -354,7 +355,7 @@ pub fn open_channel<K: KVStore + Sync + Send>(
 	wait_for_tx(&electrsd.client, funding_txo_a.txid);
 }
 
-pub(crate) fn do_channel_full_cycle<K: KVStore + Sync + Send, E: ElectrumApi>(
+pub(crate) fn do_channel_full_cycle<K: BatchKVStore + Sync + Send, E: ElectrumApi>(
 	node_a: TestNode<K>, node_b: TestNode<K>, bitcoind: &BitcoindClient, electrsd: &E,
 	allow_0conf: bool,
 ) {
@@ -472,12 +473,14 @@ pub(crate) fn do_channel_full_cycle<K: KVStore + Sync + Send, E: ElectrumApi>(
 		node_a.send_payment_using_amount(&invoice, underpaid_amount)
 	);
 
-	println!("\nB overpaid receive_payment");
-	let invoice = node_b.receive_payment(invoice_amount_2_msat, &"asdf", 9217).unwrap();
 	let overpaid_amount_msat = invoice_amount_2_msat + 100;
+	assert_eq!(
+		Err(NodeError::InvalidAmount),
+		node_a.send_payment_using_amount(&invoice, overpaid_amount_msat)
+	);
 
-	println!("\nA overpaid send_payment");
-	let payment_hash = node_a.send_payment_using_amount(&invoice, overpaid_amount_msat).unwrap();
+	println!("\nA send_payment_using_amount matching the invoice amount");
+	let payment_hash = node_a.send_payment_using_amount(&invoice, invoice_amount_2_msat).unwrap();
 	expect_event!(node_a, PaymentSuccessful);
 	let received_amount = match node_b.wait_next_event() {
 		ref e @ Event::PaymentReceived { amount_msat, .. } => {
@@ -489,13 +492,13 @@ pub(crate) fn do_channel_full_cycle<K: KVStore + Sync + Send, E: ElectrumApi>(
 			panic!("{} got unexpected event!: {:?}", std::stringify!(node_b), e);
 		},
 	};
-	assert_eq!(received_amount, overpaid_amount_msat);
+	assert_eq!(received_amount, invoice_amount_2_msat);
 	assert_eq!(node_a.payment(&payment_hash).unwrap().status, PaymentStatus::Succeeded);
 	assert_eq!(node_a.payment(&payment_hash).unwrap().direction, PaymentDirection::Outbound);
-	assert_eq!(node_a.payment(&payment_hash).unwrap().amount_msat, Some(overpaid_amount_msat));
+	assert_eq!(node_a.payment(&payment_hash).unwrap().amount_msat, Some(invoice_amount_2_msat));
 	assert_eq!(node_b.payment(&payment_hash).unwrap().status, PaymentStatus::Succeeded);
 	assert_eq!(node_b.payment(&payment_hash).unwrap().direction, PaymentDirection::Inbound);
-	assert_eq!(node_b.payment(&payment_hash).unwrap().amount_msat, Some(overpaid_amount_msat));
+	assert_eq!(node_b.payment(&payment_hash).unwrap().amount_msat, Some(invoice_amount_2_msat));
 
 	// Test "zero-amount" invoice payment
 	println!("\nB receive_variable_amount_payment");
@@ -740,3 +743,5 @@ impl KVStore for TestSyncStore {
 		self.do_list(primary_namespace, secondary_namespace)
 	}
 }
+
+impl BatchKVStore for TestSyncStore {}
@@ -1,17 +1,44 @@
 mod common;
 
 use common::{
-	do_channel_full_cycle, expect_event, expect_payment_received_event,
+	do_channel_full_cycle, expect_channel_ready_event, expect_event, expect_payment_received_event,
 	expect_payment_successful_event, generate_blocks_and_wait, open_channel,
 	premine_and_distribute_funds, random_config, setup_bitcoind_and_electrsd, setup_builder,
-	setup_node, setup_two_nodes, wait_for_tx, TestSyncStore,
+	setup_node, setup_two_nodes, wait_for_outpoint_spend, wait_for_tx, TestSyncStore,
 };
 
-use ldk_node::{Builder, Event, NodeError};
+use ldk_node::{
+	parse_bip21, AnchorChannelsConfig, BlindedPathConfig, BuildError, Builder, ChannelConfigUpdate,
+	ChannelDecision, ChannelHandshakeParameters, CoinSelectionStrategy, EsploraSyncConfig, Event,
+	LogRecord, MaxDustHTLCExposure, MaxFeePolicy, Node, NodeError, PaymentDirection, PaymentKind,
+	PaymentStatus, PeerConnectionStatus, PeerDisconnectReason, PendingChannelRequest,
+	PendingSweepBalance, QrPaymentResult, RailPreference, RetryPolicy, RouteHintSelection,
+	SendOnchainParams, SendingParameters, UnifiedQrComponents, UserChannelId,
+};
+
+use ldk_node::io::backup_sink_store::BackupSink;
+
+use lightning::blinded_path::BlindedPath;
+use lightning::chain::chaininterface::ConfirmationTarget;
+use lightning::ln::msgs::SocketAddress;
+use lightning::ln::{PaymentHash, PaymentPreimage};
+use lightning::sign::KeysManager;
+use lightning::util::persist::KVStore;
+use lightning::util::ser::Writeable;
+
+use bitcoincore_rpc::RpcApi;
 
-use bitcoin::{Amount, Network};
+use bip39::Mnemonic;
 
-use std::sync::Arc;
+use bitcoin::blockdata::constants::ChainHash;
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{PublicKey, Secp256k1};
+use bitcoin::{Amount, Network, OutPoint, Txid};
+
+use std::str::FromStr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[test]
 fn channel_full_cycle() {
@@ -62,6 +89,168 @@ fn channel_open_fails_when_funds_insufficient() {
 	);
 }
 
+#[test]
+fn channel_open_fails_when_funding_utxos_unavailable() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+
+	let premine_amount_sat = 100_000;
+
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(premine_amount_sat),
+	);
+	node_a.sync_wallets().unwrap();
+	assert_eq!(node_a.list_balances().spendable_onchain_balance_sats, premine_amount_sat);
+
+	println!("\nA -- connect_open_channel_with_funding_utxos -> B");
+	let unknown_utxo = OutPoint { txid: Txid::all_zeros(), vout: 0 };
+	assert_eq!(
+		Err(NodeError::FundingUtxoUnavailable),
+		node_a.connect_open_channel_with_funding_utxos(
+			node_b.node_id(),
+			node_b.listening_addresses().unwrap().first().unwrap().clone(),
+			50000,
+			vec![unknown_utxo],
+			None,
+			None,
+			true
+		)
+	);
+}
+
+#[test]
+fn channel_open_fails_when_push_amount_exceeds_reserve() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let channel_amount_sat = 100_000;
+	let reserve_sat = channel_amount_sat / 100;
+	assert_eq!(
+		Err(NodeError::InvalidAmount),
+		node_a.connect_open_channel(
+			node_b.node_id(),
+			node_b.listening_addresses().unwrap().first().unwrap().clone(),
+			channel_amount_sat,
+			Some((channel_amount_sat - reserve_sat) * 1000),
+			None,
+			true
+		)
+	);
+}
+
+#[test]
+fn channel_open_fails_when_handshake_params_invalid() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let channel_amount_sat = 100_000;
+	let invalid_params = [
+		ChannelHandshakeParameters { their_channel_reserve_sats: Some(999), ..Default::default() },
+		ChannelHandshakeParameters {
+			their_channel_reserve_sats: Some(channel_amount_sat),
+			..Default::default()
+		},
+		ChannelHandshakeParameters {
+			max_htlc_value_in_flight_msat: Some(channel_amount_sat * 10 - 1),
+			..Default::default()
+		},
+		ChannelHandshakeParameters {
+			max_htlc_value_in_flight_msat: Some(channel_amount_sat * 1000 + 1),
+			..Default::default()
+		},
+		ChannelHandshakeParameters { max_accepted_htlcs: Some(0), ..Default::default() },
+		ChannelHandshakeParameters { max_accepted_htlcs: Some(484), ..Default::default() },
+	];
+	for handshake_params in invalid_params {
+		assert_eq!(
+			Err(NodeError::InvalidChannelConfig),
+			node_a.connect_open_channel_with_handshake_params(
+				node_b.node_id(),
+				node_b.listening_addresses().unwrap().first().unwrap().clone(),
+				channel_amount_sat,
+				handshake_params,
+				None,
+				None,
+				true
+			)
+		);
+	}
+}
+
+#[test]
+fn force_close_anchor_channel_with_reason() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let mut config_a = random_config();
+	config_a.anchor_channels_config = Some(AnchorChannelsConfig::default());
+	let node_a = setup_node(&electrsd, config_a);
+	let mut config_b = random_config();
+	config_b.anchor_channels_config = Some(AnchorChannelsConfig::default());
+	let node_b = setup_node(&electrsd, config_b);
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	let addr_b = node_b.new_onchain_address().unwrap();
+
+	let premine_amount_sat = 100_000;
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a, addr_b],
+		Amount::from_sat(premine_amount_sat),
+	);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	// We can't open a channel that would leave us without sufficient funds for the reserve.
+	let reserve_sat = AnchorChannelsConfig::default().per_channel_reserve_sats;
+	assert_eq!(
+		Err(NodeError::InsufficientFunds),
+		node_a.connect_open_channel(
+			node_b.node_id(),
+			node_b.listening_addresses().unwrap().first().unwrap().clone(),
+			premine_amount_sat - reserve_sat + 1,
+			None,
+			None,
+			true
+		)
+	);
+
+	let funding_amount_sat = 50_000;
+	open_channel(&node_a, &node_b, funding_amount_sat, true, &electrsd);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	let user_channel_id = expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
+
+	assert_eq!(node_a.list_balances().total_anchor_channels_reserve_sats, reserve_sat);
+	assert_eq!(node_b.list_balances().total_anchor_channels_reserve_sats, reserve_sat);
+	assert!(
+		node_a.list_balances().spendable_onchain_balance_sats
+			< premine_amount_sat - funding_amount_sat - reserve_sat
+	);
+
+	let reason = "Counterparty unresponsive".to_string();
+	node_a.force_close_channel(&user_channel_id, node_b.node_id(), Some(reason.clone())).unwrap();
+
+	match node_a.wait_next_event() {
+		Event::ChannelClosed { force_close_reason, .. } => {
+			assert_eq!(force_close_reason, Some(reason));
+			node_a.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+	expect_event!(node_b, ChannelClosed);
+
+	assert_eq!(node_a.list_balances().total_anchor_channels_reserve_sats, 0);
+}
+
 #[test]
 fn multi_hop_sending() {
 	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
@@ -140,193 +329,2339 @@ fn multi_hop_sending() {
 }
 
 #[test]
-fn connect_to_public_testnet_esplora() {
-	let mut config = random_config();
-	config.network = Network::Testnet;
-	setup_builder!(builder, config);
-	builder.set_esplora_server("https://blockstream.info/testnet/api".to_string());
-	let node = builder.build().unwrap();
-	node.start().unwrap();
-	node.stop().unwrap();
-}
-
-#[test]
-fn start_stop_reinit() {
+fn max_routing_fee_policy_limits_payments() {
 	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
-	let config = random_config();
-
 	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
 
-	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.clone().into()));
+	// Setup and fund 3 nodes, the first one not willing to pay any routing fees.
+	let mut nodes = Vec::new();
+	for i in 0..3 {
+		let config = random_config();
+		setup_builder!(builder, config);
+		builder.set_esplora_server(esplora_url.clone());
+		if i == 0 {
+			builder
+				.set_max_routing_fee_policy(MaxFeePolicy { percent_millionths: 0, base_msat: 0 });
+		}
+		let node = builder.build().unwrap();
+		node.start().unwrap();
+		nodes.push(node);
+	}
 
-	setup_builder!(builder, config);
-	builder.set_esplora_server(esplora_url.clone());
+	let addresses = nodes.iter().map(|n| n.new_onchain_address().unwrap()).collect();
+	let premine_amount_sat = 5_000_000;
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		addresses,
+		Amount::from_sat(premine_amount_sat),
+	);
 
-	let node = builder.build_with_store(Arc::clone(&test_sync_store)).unwrap();
-	node.start().unwrap();
+	for n in &nodes {
+		n.sync_wallets().unwrap();
+	}
 
-	let expected_node_id = node.node_id();
-	assert_eq!(node.start(), Err(NodeError::AlreadyRunning));
+	// Setup channel topology: N0 -(1M:0)-> N1 -(1M:0)-> N2
+	open_channel(&nodes[0], &nodes[1], 1_000_000, true, &electrsd);
+	open_channel(&nodes[1], &nodes[2], 1_000_000, true, &electrsd);
 
-	let funding_address = node.new_onchain_address().unwrap();
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
 
-	assert_eq!(node.list_balances().total_onchain_balance_sats, 0);
+	for n in &nodes {
+		n.sync_wallets().unwrap();
+	}
 
-	let expected_amount = Amount::from_sat(100000);
+	expect_event!(nodes[0], ChannelReady);
+	expect_event!(nodes[1], ChannelReady);
+	expect_event!(nodes[1], ChannelReady);
+	expect_event!(nodes[2], ChannelReady);
+
+	// Sleep a bit for gossip to propagate.
+	std::thread::sleep(std::time::Duration::from_secs(1));
+
+	let invoice = nodes[2].receive_payment(100_000, &"asdf", 9217).unwrap();
+	assert_eq!(nodes[0].send_payment(&invoice), Err(NodeError::RoutingFeeLimitExceeded));
+
+	// A fee limit given for the individual payment takes precedence over the policy.
+	let invoice = nodes[2].receive_payment(100_000, &"asdf", 9217).unwrap();
+	let sending_params =
+		SendingParameters { max_total_routing_fee_msat: Some(10_000), ..Default::default() };
+	nodes[0].send_payment_with_params(&invoice, sending_params).unwrap();
+	let payment_hash = expect_payment_received_event!(&nodes[2], 100_000);
+	expect_payment_successful_event!(nodes[0], payment_hash, Some(1000));
+}
+
+#[test]
+fn failed_payments_are_retried_according_to_policy() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+
+	// Setup and fund 3 nodes, the first one retrying failed payments.
+	let mut nodes = Vec::new();
+	for i in 0..3 {
+		let config = random_config();
+		setup_builder!(builder, config);
+		builder.set_esplora_server(esplora_url.clone());
+		if i == 0 {
+			let retry_policy =
+				RetryPolicy { max_attempts: 3, max_total_time_secs: 120, backoff_secs: 1 };
+			builder.set_payment_retry_policy(retry_policy).unwrap();
+		}
+		let node = builder.build().unwrap();
+		node.start().unwrap();
+		nodes.push(node);
+	}
+
+	let addresses = nodes.iter().map(|n| n.new_onchain_address().unwrap()).collect();
+	let premine_amount_sat = 5_000_000;
 	premine_and_distribute_funds(
 		&bitcoind.client,
 		&electrsd.client,
-		vec![funding_address],
-		expected_amount,
+		addresses,
+		Amount::from_sat(premine_amount_sat),
 	);
 
-	node.sync_wallets().unwrap();
-	assert_eq!(node.list_balances().spendable_onchain_balance_sats, expected_amount.to_sat());
+	for n in &nodes {
+		n.sync_wallets().unwrap();
+	}
 
-	let log_file_symlink = format!("{}/logs/ldk_node_latest.log", config.clone().storage_dir_path);
-	assert!(std::path::Path::new(&log_file_symlink).is_symlink());
+	// Setup channel topology: N0 -(1M:0)-> N1 -(1M:0)-> N2
+	open_channel(&nodes[0], &nodes[1], 1_000_000, true, &electrsd);
+	open_channel(&nodes[1], &nodes[2], 1_000_000, true, &electrsd);
 
-	node.stop().unwrap();
-	assert_eq!(node.stop(), Err(NodeError::NotRunning));
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
 
-	node.start().unwrap();
-	assert_eq!(node.start(), Err(NodeError::AlreadyRunning));
+	for n in &nodes {
+		n.sync_wallets().unwrap();
+	}
 
-	node.stop().unwrap();
-	assert_eq!(node.stop(), Err(NodeError::NotRunning));
-	drop(node);
+	expect_event!(nodes[0], ChannelReady);
+	expect_event!(nodes[1], ChannelReady);
+	expect_event!(nodes[1], ChannelReady);
+	expect_event!(nodes[2], ChannelReady);
 
-	setup_builder!(builder, config);
-	builder.set_esplora_server(esplora_url.clone());
+	// Sleep a bit for gossip to propagate.
+	std::thread::sleep(std::time::Duration::from_secs(1));
 
-	let reinitialized_node = builder.build_with_store(Arc::clone(&test_sync_store)).unwrap();
-	reinitialized_node.start().unwrap();
-	assert_eq!(reinitialized_node.node_id(), expected_node_id);
+	// With the recipient offline, the initial attempt fails once LDK gave up on it after 10
+	// seconds. The failure isn't surfaced as we're about to retry.
+	let invoice = nodes[2].receive_payment(100_000, &"asdf", 9217).unwrap();
+	nodes[2].stop().unwrap();
+	let payment_hash = nodes[0].send_payment(&invoice).unwrap();
+	std::thread::sleep(std::time::Duration::from_secs(12));
+	assert_eq!(nodes[0].next_event(), None);
+	assert_eq!(nodes[0].payment(&payment_hash).unwrap().status, PaymentStatus::Pending);
+
+	// Once the recipient is back online, one of the retries succeeds.
+	nodes[2].start().unwrap();
+	expect_payment_received_event!(&nodes[2], 100_000);
+	expect_payment_successful_event!(nodes[0], payment_hash, Some(1000));
+}
 
-	assert_eq!(
-		reinitialized_node.list_balances().spendable_onchain_balance_sats,
-		expected_amount.to_sat()
+#[test]
+fn first_hop_channel_can_be_pinned() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(5_000_000),
 	);
+	node_a.sync_wallets().unwrap();
 
-	reinitialized_node.sync_wallets().unwrap();
+	// Open a small and a large channel to the same peer.
+	open_channel(&node_a, &node_b, 100_000, true, &electrsd);
+	node_a.sync_wallets().unwrap();
+	open_channel(&node_a, &node_b, 1_000_000, true, &electrsd);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	expect_event!(node_a, ChannelReady);
+	expect_event!(node_a, ChannelReady);
+	expect_event!(node_b, ChannelReady);
+	expect_event!(node_b, ChannelReady);
+
+	let channels = node_a.list_channels();
+	let small_channel = channels.iter().find(|c| c.channel_value_sats == 100_000).unwrap();
+	let large_channel = channels.iter().find(|c| c.channel_value_sats == 1_000_000).unwrap();
+
+	// The small channel can't carry the payment, so we either fail or fall back.
+	let invoice = node_b.receive_payment(200_000_000, &"asdf", 9217).unwrap();
+	let sending_params = SendingParameters {
+		first_hop_channel_id: Some(small_channel.channel_id),
+		..Default::default()
+	};
 	assert_eq!(
-		reinitialized_node.list_balances().spendable_onchain_balance_sats,
-		expected_amount.to_sat()
+		node_a.send_payment_with_params(&invoice, sending_params),
+		Err(NodeError::FirstHopChannelUnusable)
 	);
 
-	reinitialized_node.stop().unwrap();
+	let sending_params = SendingParameters {
+		first_hop_channel_id: Some(small_channel.channel_id),
+		allow_first_hop_fallback: Some(true),
+		..Default::default()
+	};
+	node_a.send_payment_with_params(&invoice, sending_params).unwrap();
+	expect_payment_received_event!(&node_b, 200_000_000);
+	expect_event!(node_a, PaymentSuccessful);
+
+	// A pinned channel with sufficient capacity is used.
+	let capacity_msat = |channel_id| {
+		let channels = node_a.list_channels();
+		channels.iter().find(|c| c.channel_id == channel_id).unwrap().outbound_capacity_msat
+	};
+	let small_capacity_msat = capacity_msat(small_channel.channel_id);
+	let large_capacity_msat = capacity_msat(large_channel.channel_id);
+	let invoice = node_b.receive_payment(10_000_000, &"asdf", 9217).unwrap();
+	let sending_params = SendingParameters {
+		first_hop_channel_id: Some(small_channel.channel_id),
+		..Default::default()
+	};
+	node_a.send_payment_with_params(&invoice, sending_params).unwrap();
+	expect_payment_received_event!(&node_b, 10_000_000);
+	expect_event!(node_a, PaymentSuccessful);
+
+	assert!(capacity_msat(small_channel.channel_id) <= small_capacity_msat - 10_000_000);
+	assert_eq!(capacity_msat(large_channel.channel_id), large_capacity_msat);
 }
 
 #[test]
-fn onchain_spend_receive() {
+fn channels_can_be_filtered() {
 	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
 	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
 
 	let addr_a = node_a.new_onchain_address().unwrap();
-	let addr_b = node_b.new_onchain_address().unwrap();
-
 	premine_and_distribute_funds(
 		&bitcoind.client,
 		&electrsd.client,
-		vec![addr_b.clone()],
-		Amount::from_sat(100000),
+		vec![addr_a],
+		Amount::from_sat(2_000_000),
 	);
-
 	node_a.sync_wallets().unwrap();
-	node_b.sync_wallets().unwrap();
-	assert_eq!(node_b.list_balances().spendable_onchain_balance_sats, 100000);
 
-	assert_eq!(Err(NodeError::InsufficientFunds), node_a.send_to_onchain_address(&addr_b, 1000));
+	open_channel(&node_a, &node_b, 1_000_000, true, &electrsd);
+	assert!(node_a.list_channels_with_filter(|c| c.is_channel_ready).is_empty());
+	assert_eq!(node_a.usable_balance_msat(), 0);
 
-	let txid = node_b.send_to_onchain_address(&addr_a, 1000).unwrap();
 	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
-	wait_for_tx(&electrsd.client, txid);
-
 	node_a.sync_wallets().unwrap();
 	node_b.sync_wallets().unwrap();
 
-	assert_eq!(node_a.list_balances().spendable_onchain_balance_sats, 1000);
-	assert!(node_b.list_balances().spendable_onchain_balance_sats > 98000);
-	assert!(node_b.list_balances().spendable_onchain_balance_sats < 100000);
-
-	let addr_b = node_b.new_onchain_address().unwrap();
-	let txid = node_a.send_all_to_onchain_address(&addr_b).unwrap();
-	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
-	wait_for_tx(&electrsd.client, txid);
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
 
-	node_a.sync_wallets().unwrap();
-	node_b.sync_wallets().unwrap();
+	assert_eq!(node_a.list_channels_with_peer(node_b.node_id()).len(), 1);
+	assert!(node_a.list_channels_with_peer(node_a.node_id()).is_empty());
+	assert_eq!(
+		node_a.list_channels_with_filter(|c| c.is_channel_ready).len(),
+		node_a.list_channels().len()
+	);
 
-	assert_eq!(node_a.list_balances().total_onchain_balance_sats, 0);
-	assert!(node_b.list_balances().spendable_onchain_balance_sats > 99000);
-	assert!(node_b.list_balances().spendable_onchain_balance_sats < 100000);
+	let usable_channels = node_a.list_usable_channels();
+	assert_eq!(usable_channels.len(), 1);
+	assert_eq!(node_a.usable_balance_msat(), usable_channels[0].outbound_capacity_msat);
+	assert!(node_a.usable_balance_msat() > 0);
 }
 
 #[test]
-fn sign_verify_msg() {
-	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
-	let config = random_config();
-	let node = setup_node(&electrsd, config);
+fn partial_channel_config_updates() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
 
-	// Tests arbitrary message signing and later verification
-	let msg = "OK computer".as_bytes();
-	let sig = node.sign_message(msg).unwrap();
-	let pkey = node.node_id();
-	assert!(node.verify_signature(msg, sig.as_str(), &pkey));
+	let addr_a = node_a.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(2_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+
+	open_channel(&node_a, &node_b, 1_000_000, true, &electrsd);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	let user_channel_id = expect_channel_ready_event!(node_b, node_a.node_id());
+
+	let config = node_b.list_channels().first().unwrap().config.clone();
+
+	// Invalid updates are rejected without changing anything.
+	let invalid_update = ChannelConfigUpdate { cltv_expiry_delta: Some(1), ..Default::default() };
+	assert_eq!(
+		Err(NodeError::InvalidChannelConfig),
+		node_b.update_partial_channel_config(&user_channel_id, node_a.node_id(), invalid_update)
+	);
+	assert_eq!(
+		node_b.list_channels().first().unwrap().config.cltv_expiry_delta(),
+		config.cltv_expiry_delta()
+	);
+
+	// Only the given fields are updated.
+	let fee_update =
+		ChannelConfigUpdate { forwarding_fee_base_msat: Some(1234), ..Default::default() };
+	node_b.update_partial_channel_config(&user_channel_id, node_a.node_id(), fee_update).unwrap();
+	let updated_config = node_b.list_channels().first().unwrap().config.clone();
+	assert_eq!(updated_config.forwarding_fee_base_msat(), 1234);
+	assert_eq!(
+		updated_config.forwarding_fee_proportional_millionths(),
+		config.forwarding_fee_proportional_millionths()
+	);
+	assert_eq!(updated_config.cltv_expiry_delta(), config.cltv_expiry_delta());
+
+	let bulk_update = ChannelConfigUpdate {
+		forwarding_fee_proportional_millionths: Some(42),
+		..Default::default()
+	};
+	node_b.update_all_channels_config(bulk_update).unwrap();
+	let updated_config = node_b.list_channels().first().unwrap().config.clone();
+	assert_eq!(updated_config.forwarding_fee_base_msat(), 1234);
+	assert_eq!(updated_config.forwarding_fee_proportional_millionths(), 42);
 }
 
 #[test]
-fn connection_restart_behavior() {
-	do_connection_restart_behavior(true);
-	do_connection_restart_behavior(false);
+fn max_dust_htlc_exposure_can_be_updated() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(2_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+
+	open_channel(&node_a, &node_b, 1_000_000, true, &electrsd);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	let user_channel_id = expect_channel_ready_event!(node_b, node_a.node_id());
+
+	let invalid_dust_update = ChannelConfigUpdate {
+		max_dust_htlc_exposure: Some(MaxDustHTLCExposure::FeeRateMultiplier { multiplier: 0 }),
+		..Default::default()
+	};
+	assert_eq!(
+		Err(NodeError::InvalidChannelConfig),
+		node_b.update_partial_channel_config(
+			&user_channel_id,
+			node_a.node_id(),
+			invalid_dust_update
+		)
+	);
+	let dust_exposure = MaxDustHTLCExposure::FixedLimit { limit_msat: 1_000_000 };
+	let dust_update =
+		ChannelConfigUpdate { max_dust_htlc_exposure: Some(dust_exposure), ..Default::default() };
+	node_b.update_partial_channel_config(&user_channel_id, node_a.node_id(), dust_update).unwrap();
+	assert_eq!(
+		node_b.list_channels().first().unwrap().config.max_dust_htlc_exposure(),
+		dust_exposure
+	);
 }
 
-fn do_connection_restart_behavior(persist: bool) {
+#[test]
+fn custom_onion_messages_are_exchanged() {
 	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
-	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+	let node_a = setup_node(&electrsd, random_config());
 
-	let node_id_a = node_a.node_id();
-	let node_id_b = node_b.node_id();
+	// Node B only accepts a narrower range of custom types.
+	let min_tlv_type = (1 << 16) + 1;
+	let max_tlv_type = (1 << 16) + 10;
+	let mut config_b = random_config();
+	config_b.custom_onion_message_tlv_type_min = min_tlv_type;
+	config_b.custom_onion_message_tlv_type_max = max_tlv_type;
+	let node_b = setup_node(&electrsd, config_b);
 
+	let node_id_b = node_b.node_id();
 	let node_addr_b = node_b.listening_addresses().unwrap().first().unwrap().clone();
+	node_a.connect(node_id_b, node_addr_b, false).unwrap();
+	expect_event!(node_a, PeerConnected);
+	expect_event!(node_b, PeerConnected);
 
-	while !node_b.status().is_listening {
-		std::thread::sleep(std::time::Duration::from_millis(10));
+	// Types reserved by the specification are rejected.
+	assert_eq!(
+		node_a.send_onion_message(node_id_b, 64, vec![1, 2, 3]),
+		Err(NodeError::InvalidOnionMessageType)
+	);
+
+	// As are types outside of the configured range.
+	assert_eq!(
+		node_b.send_onion_message(node_a.node_id(), max_tlv_type + 1, vec![1, 2, 3]),
+		Err(NodeError::InvalidOnionMessageType)
+	);
+
+	let tlv_type = min_tlv_type;
+	node_a.send_onion_message(node_id_b, tlv_type, vec![1, 2, 3]).unwrap();
+	match node_b.wait_next_event() {
+		Event::OnionMessageReceived { tlv_type: received_type, data, reply_path } => {
+			assert_eq!(received_type, tlv_type);
+			assert_eq!(data, vec![1, 2, 3]);
+			assert_eq!(reply_path, None);
+			node_b.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
 	}
 
-	node_a.connect(node_id_b, node_addr_b, persist).unwrap();
+	// Reply via a blinded path to node A.
+	let entropy_source = KeysManager::new(&[42u8; 32], 0, 0);
+	let reply_path =
+		BlindedPath::new_for_message(&[node_a.node_id()], &entropy_source, &Secp256k1::new())
+			.unwrap();
+	node_b.reply_to_onion_message(reply_path, max_tlv_type, vec![4, 5, 6]).unwrap();
+	match node_a.wait_next_event() {
+		Event::OnionMessageReceived { tlv_type: received_type, data, .. } => {
+			assert_eq!(received_type, max_tlv_type);
+			assert_eq!(data, vec![4, 5, 6]);
+			node_a.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+}
 
-	let peer_details_a = node_a.list_peers().first().unwrap().clone();
-	assert_eq!(peer_details_a.node_id, node_id_b);
-	assert_eq!(peer_details_a.is_persisted, persist);
-	assert!(peer_details_a.is_connected);
+#[test]
+fn custom_onion_message_tlv_types_are_validated() {
+	let config = random_config();
+	setup_builder!(builder, config);
 
-	let peer_details_b = node_b.list_peers().first().unwrap().clone();
-	assert_eq!(peer_details_b.node_id, node_id_a);
-	assert_eq!(peer_details_b.is_persisted, false);
-	assert!(peer_details_a.is_connected);
+	// Types reserved by the specification can't be included.
+	assert!(matches!(
+		builder.set_custom_onion_message_tlv_types(64, 1 << 16),
+		Err(BuildError::InvalidCustomOnionMessageTlvTypes)
+	));
+	// Neither can the range be empty.
+	assert!(matches!(
+		builder.set_custom_onion_message_tlv_types((1 << 16) + 1, 1 << 16),
+		Err(BuildError::InvalidCustomOnionMessageTlvTypes)
+	));
+	assert!(builder.set_custom_onion_message_tlv_types(1 << 16, 1 << 16).is_ok());
+
+	// Invalid ranges set directly on the config are rejected on build.
+	let mut config = random_config();
+	config.custom_onion_message_tlv_type_min = 64;
+	setup_builder!(builder, config);
+	assert!(matches!(builder.build(), Err(BuildError::InvalidCustomOnionMessageTlvTypes)));
+}
 
-	// Restart nodes.
+#[test]
+fn channel_backup_restore_roundtrip() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	let seed_bytes = vec![42u8; 64];
+
+	let config_a = random_config();
+	setup_builder!(builder_a, config_a);
+	builder_a.set_esplora_server(esplora_url.clone());
+	builder_a.set_entropy_seed_bytes(seed_bytes.clone()).unwrap();
+	let store_a = Arc::new(TestSyncStore::new(config_a.storage_dir_path.clone().into()));
+	let node_a = builder_a.build_with_store(store_a).unwrap();
+	node_a.start().unwrap();
+	let node_b = setup_node(&electrsd, random_config());
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(2_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+	open_channel(&node_a, &node_b, 1_000_000, false, &electrsd);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	expect_event!(node_a, ChannelReady);
+	expect_event!(node_b, ChannelReady);
+
+	let channel_backup = node_a.export_channel_backup().unwrap();
+	let node_id_a = node_a.node_id();
+	node_a.stop().unwrap();
+	drop(node_a);
+
+	// Recover into a fresh node initialized from the same seed.
+	let config_restored = random_config();
+	setup_builder!(builder, config_restored);
+	builder.set_esplora_server(esplora_url.clone());
+	builder.set_entropy_seed_bytes(seed_bytes.clone()).unwrap();
+	builder.recover_from_channel_backup(channel_backup.clone());
+	let restored_store =
+		Arc::new(TestSyncStore::new(config_restored.storage_dir_path.clone().into()));
+	let restored_node = builder.build_with_store(Arc::clone(&restored_store)).unwrap();
+	assert_eq!(restored_node.node_id(), node_id_a);
+	assert!(restored_node.list_peers().iter().any(|p| p.node_id == node_b.node_id()));
+	restored_node.start().unwrap();
+
+	// Our counterparty can't reestablish the channel with us and hence closes it.
+	expect_event!(node_b, ChannelClosed);
+	restored_node.stop().unwrap();
+	drop(restored_node);
+
+	// Backups may only be restored into fresh nodes.
+	setup_builder!(builder, config_restored);
+	builder.set_esplora_server(esplora_url);
+	builder.set_entropy_seed_bytes(seed_bytes).unwrap();
+	builder.recover_from_channel_backup(channel_backup);
+	assert!(matches!(
+		builder.build_with_store(restored_store),
+		Err(BuildError::InvalidChannelBackup)
+	));
+}
+
+#[test]
+fn outdated_channel_backup_restore_broadcasts_nothing_revoked() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	let seed_bytes = vec![42u8; 64];
+
+	let config_a = random_config();
+	setup_builder!(builder_a, config_a);
+	builder_a.set_esplora_server(esplora_url.clone());
+	builder_a.set_entropy_seed_bytes(seed_bytes.clone()).unwrap();
+	let store_a = Arc::new(TestSyncStore::new(config_a.storage_dir_path.clone().into()));
+	let node_a = builder_a.build_with_store(store_a).unwrap();
+	node_a.start().unwrap();
+	let node_b = setup_node(&electrsd, random_config());
+
+	let premine_amount_sat = 2_000_000;
+	let funding_amount_sat = 1_000_000;
+	let addr_a = node_a.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(premine_amount_sat),
+	);
+	node_a.sync_wallets().unwrap();
+	open_channel(&node_a, &node_b, funding_amount_sat, false, &electrsd);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	expect_event!(node_a, ChannelReady);
+	expect_event!(node_b, ChannelReady);
+	let funding_txo = node_a.list_channels()[0].funding_txo.unwrap();
+
+	// Export the backup before making further payments, which revoke the backed up state.
+	let channel_backup = node_a.export_channel_backup().unwrap();
+	let payment_amount_msat = 10_000_000;
+	for _ in 0..3 {
+		let invoice = node_b.receive_payment(payment_amount_msat, &"asdf", 9217).unwrap();
+		node_a.send_payment(&invoice).unwrap();
+		let payment_hash = expect_payment_received_event!(node_b, payment_amount_msat);
+		expect_payment_successful_event!(node_a, payment_hash, Some(0));
+	}
 	node_a.stop().unwrap();
+	drop(node_a);
+
+	// Keep our counterparty offline for now, so we get the chance to broadcast our commitment.
 	node_b.stop().unwrap();
+
+	let config_restored = random_config();
+	setup_builder!(builder, config_restored);
+	builder.set_esplora_server(esplora_url.clone());
+	builder.set_entropy_seed_bytes(seed_bytes.clone()).unwrap();
+	builder.recover_from_channel_backup(channel_backup);
+	let restored_store =
+		Arc::new(TestSyncStore::new(config_restored.storage_dir_path.clone().into()));
+	let restored_node = builder.build_with_store(Arc::clone(&restored_store)).unwrap();
+	restored_node.start().unwrap();
+	restored_node.stop().unwrap();
+	drop(restored_node);
+
+	// Upon restart, LDK would force-close the channel it doesn't know, i.e., broadcast the stale
+	// commitment transaction from the restored monitor.
+	setup_builder!(builder, config_restored);
+	builder.set_esplora_server(esplora_url);
+	builder.set_entropy_seed_bytes(seed_bytes).unwrap();
+	let restored_node = builder.build_with_store(restored_store).unwrap();
+	restored_node.start().unwrap();
+	restored_node.sync_wallets().unwrap();
+	std::thread::sleep(std::time::Duration::from_secs(1));
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+	let funding_output =
+		bitcoind.client.get_tx_out(&funding_txo.txid, funding_txo.vout, Some(true)).unwrap();
+	assert!(funding_output.is_some());
+
+	// Once back online, our counterparty closes the channel upon our request.
 	node_b.start().unwrap();
+	expect_event!(node_b, ChannelClosed);
+	wait_for_outpoint_spend(&electrsd.client, funding_txo);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+	restored_node.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	// Sweeping our output from the counterparty's commitment transaction gets us our funds back,
+	// which we'd have lost to a penalty transaction if we had broadcast the revoked commitment.
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	restored_node.sync_wallets().unwrap();
+	let sum_of_payments_sat = 3 * payment_amount_msat / 1000;
+	let onchain_fee_buffer_sat = 20_000;
+	let lower_bound_sat = premine_amount_sat - sum_of_payments_sat - onchain_fee_buffer_sat;
+	let restored_balances = restored_node.list_balances();
+	let pending_sweep_sat = restored_balances
+		.pending_balances_from_channel_closures
+		.iter()
+		.map(|b| match b {
+			PendingSweepBalance::PendingBroadcast { amount_satoshis, .. }
+			| PendingSweepBalance::BroadcastAwaitingConfirmation { amount_satoshis, .. }
+			| PendingSweepBalance::AwaitingThresholdConfirmations { amount_satoshis, .. } => {
+				*amount_satoshis
+			},
+		})
+		.sum::<u64>();
+	let recovered_sat = restored_balances.total_onchain_balance_sats
+		+ restored_balances.total_lightning_balance_sats
+		+ pending_sweep_sat;
+	assert!(recovered_sat > lower_bound_sat);
+
+	restored_node.stop().unwrap();
+	node_b.stop().unwrap();
+}
+
+#[test]
+fn refund_payment_is_tracked_once_requested() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(2_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+	open_channel(&node_a, &node_b, 1_000_000, true, &electrsd);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	expect_event!(node_a, ChannelReady);
+	expect_event!(node_b, ChannelReady);
+
+	// Sleep a bit for gossip to propagate.
+	std::thread::sleep(std::time::Duration::from_secs(1));
+
+	let amount_msat = 100_000;
+	let invoice = node_b.receive_payment(amount_msat, &"asdf", 9217).unwrap();
+	node_a.send_payment(&invoice).unwrap();
+	let payment_hash = expect_payment_received_event!(node_b, amount_msat);
+	expect_payment_successful_event!(node_a, payment_hash, Some(0));
+
+	// We can't refund more than we received.
+	assert_eq!(
+		node_b.initiate_refund(&payment_hash, amount_msat + 1, 3600),
+		Err(NodeError::InvalidAmount)
+	);
+	let refund_amount_msat = amount_msat / 2;
+	let refund = node_b.initiate_refund(&payment_hash, refund_amount_msat, 3600).unwrap();
+
+	let refund_payment = node_a.request_refund_payment(&refund).unwrap();
+	assert_eq!(refund_payment.kind, Some(PaymentKind::Bolt12Refund));
+	assert_eq!(refund_payment.direction, PaymentDirection::Inbound);
+	assert_eq!(refund_payment.status, PaymentStatus::Pending);
+	assert_eq!(refund_payment.amount_msat, Some(refund_amount_msat));
+	assert_eq!(node_a.payment(&refund_payment.hash), Some(refund_payment.clone()));
+
+	// The pending refund payment survives a restart.
+	node_a.stop().unwrap();
 	node_a.start().unwrap();
+	assert_eq!(node_a.payment(&refund_payment.hash), Some(refund_payment));
 
-	// Sleep a bit to allow for the reconnect to happen.
-	std::thread::sleep(std::time::Duration::from_secs(5));
+	node_a.stop().unwrap();
+	node_b.stop().unwrap();
+}
 
-	if persist {
-		let peer_details_a = node_a.list_peers().first().unwrap().clone();
-		assert_eq!(peer_details_a.node_id, node_id_b);
-		assert_eq!(peer_details_a.is_persisted, persist);
-		assert!(peer_details_a.is_connected);
+#[test]
+fn connect_to_public_testnet_esplora() {
+	let mut config = random_config();
+	config.network = Network::Testnet;
+	setup_builder!(builder, config);
+	builder.set_esplora_server("https://blockstream.info/testnet/api".to_string());
+	let node = builder.build().unwrap();
+	node.start().unwrap();
+	node.stop().unwrap();
+}
 
-		let peer_details_b = node_b.list_peers().first().unwrap().clone();
-		assert_eq!(peer_details_b.node_id, node_id_a);
-		assert_eq!(peer_details_b.is_persisted, false);
-		assert!(peer_details_a.is_connected);
-	} else {
-		assert!(node_a.list_peers().is_empty());
-		assert!(node_b.list_peers().is_empty());
+#[test]
+fn onchain_receive_via_bitcoind_rpc() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let config = random_config();
+	setup_builder!(builder, config);
+
+	let rpc_socket = bitcoind.params.rpc_socket;
+	let cookie = std::fs::read_to_string(&bitcoind.params.cookie_file).unwrap();
+	let (rpc_user, rpc_password) = cookie.trim().split_once(':').unwrap();
+	builder.set_chain_source_bitcoind_rpc(
+		rpc_socket.ip().to_string(),
+		rpc_socket.port(),
+		rpc_user.to_string(),
+		rpc_password.to_string(),
+	);
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.clone().into()));
+	let node = builder.build_with_store(test_sync_store).unwrap();
+	node.start().unwrap();
+
+	let addr = node.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr],
+		Amount::from_sat(100000),
+	);
+
+	node.sync_wallets().unwrap();
+	assert_eq!(node.list_balances().spendable_onchain_balance_sats, 100000);
+	node.stop().unwrap();
+}
+
+#[test]
+fn onchain_wallet_sync_via_bitcoind_rpc_handles_mempool_and_reorgs() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let config = random_config();
+	setup_builder!(builder, config);
+
+	let rpc_socket = bitcoind.params.rpc_socket;
+	let cookie = std::fs::read_to_string(&bitcoind.params.cookie_file).unwrap();
+	let (rpc_user, rpc_password) = cookie.trim().split_once(':').unwrap();
+	builder.set_chain_source_bitcoind_rpc(
+		rpc_socket.ip().to_string(),
+		rpc_socket.port(),
+		rpc_user.to_string(),
+		rpc_password.to_string(),
+	);
+	builder.set_wallet_birthday_height(bitcoind.client.get_block_count().unwrap() as u32);
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.clone().into()));
+	let node = builder.build_with_store(test_sync_store).unwrap();
+	node.start().unwrap();
+
+	let addr = node.new_onchain_address().unwrap();
+	premine_and_distribute_funds(&bitcoind.client, &electrsd.client, vec![], Amount::ZERO);
+	let txid = bitcoind
+		.client
+		.send_to_address(&addr, Amount::from_sat(100000), None, None, None, None, None, None)
+		.unwrap();
+	wait_for_tx(&electrsd.client, txid);
+
+	// Unconfirmed transactions are picked up from the mempool.
+	node.sync_wallets().unwrap();
+	assert_eq!(node.list_balances().total_onchain_balance_sats, 100000);
+	assert_eq!(node.list_balances().spendable_onchain_balance_sats, 0);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+	node.sync_wallets().unwrap();
+	assert_eq!(node.list_balances().spendable_onchain_balance_sats, 100000);
+
+	// Reorging out the confirming block unconfirms the transaction again.
+	let confirming_block_hash = bitcoind.client.get_best_block_hash().unwrap();
+	bitcoind.client.invalidate_block(&confirming_block_hash).unwrap();
+	node.sync_wallets().unwrap();
+	assert_eq!(node.list_balances().total_onchain_balance_sats, 100000);
+	assert_eq!(node.list_balances().spendable_onchain_balance_sats, 0);
+
+	// Once it's mined on the new chain, it's confirmed again.
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 2);
+	node.sync_wallets().unwrap();
+	assert_eq!(node.list_balances().spendable_onchain_balance_sats, 100000);
+	node.stop().unwrap();
+}
+
+#[test]
+fn chain_sync_config_is_validated() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let config = random_config();
+	setup_builder!(builder, config);
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	builder.set_esplora_server(esplora_url);
+
+	let invalid_sync_config =
+		EsploraSyncConfig { lightning_wallet_sync_interval_secs: 0, ..Default::default() };
+	assert!(builder.set_chain_sync_config(invalid_sync_config).is_err());
+
+	let sync_config = EsploraSyncConfig {
+		onchain_wallet_sync_interval_secs: 20,
+		max_concurrent_requests: u8::MAX,
+		..Default::default()
+	};
+	builder.set_chain_sync_config(sync_config).unwrap();
+
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.into()));
+	let node = builder.build_with_store(test_sync_store).unwrap();
+	assert_eq!(node.config().onchain_wallet_sync_interval_secs, 20);
+	assert_eq!(node.config().wallet_sync_interval_secs, 30);
+	node.start().unwrap();
+	node.sync_wallets().unwrap();
+	node.stop().unwrap();
+}
+
+#[test]
+fn channel_confirmation_target_is_validated() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let config = random_config();
+	setup_builder!(builder, config);
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	builder.set_esplora_server(esplora_url);
+
+	assert!(builder.set_channel_confirmation_target(0).is_err());
+	assert!(builder.set_channel_confirmation_target(145).is_err());
+	builder.set_channel_confirmation_target(12).unwrap();
+
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.into()));
+	let node = builder.build_with_store(test_sync_store).unwrap();
+	assert_eq!(node.config().channel_confirmation_target, Some(12));
+}
+
+#[test]
+fn blinded_path_config_is_validated() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let config = random_config();
+	setup_builder!(builder, config);
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	builder.set_esplora_server(esplora_url);
+
+	let invalid_config = BlindedPathConfig { num_hops: 4, ..Default::default() };
+	assert!(builder.set_blinded_path_config(invalid_config).is_err());
+
+	let preferred_node =
+		PublicKey::from_str("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+			.unwrap();
+	let blinded_path_config =
+		BlindedPathConfig { num_hops: 2, preferred_introduction_nodes: vec![preferred_node] };
+	builder.set_blinded_path_config(blinded_path_config.clone()).unwrap();
+
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.into()));
+	let node = builder.build_with_store(test_sync_store).unwrap();
+	assert_eq!(node.config().blinded_path_config, blinded_path_config);
+}
+
+#[test]
+fn corrupt_payments_fail_build_only_if_strict() {
+	let config = random_config();
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.clone().into()));
+	test_sync_store.write("payments", "", &"17".repeat(32), &[1, 2, 3]).unwrap();
+
+	setup_builder!(builder, config);
+	builder.set_skip_corrupt_payments(false);
+	assert!(matches!(
+		builder.build_with_store(Arc::clone(&test_sync_store)),
+		Err(BuildError::ReadFailed)
+	));
+
+	// By default, the corrupt entry is skipped.
+	setup_builder!(builder, config);
+	let node = builder.build_with_store(test_sync_store).unwrap();
+	assert!(node.list_payments().is_empty());
+}
+
+#[test]
+fn start_stop_reinit() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let config = random_config();
+
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.clone().into()));
+
+	setup_builder!(builder, config);
+	builder.set_esplora_server(esplora_url.clone());
+
+	let node = builder.build_with_store(Arc::clone(&test_sync_store)).unwrap();
+	node.start().unwrap();
+
+	let expected_node_id = node.node_id();
+	assert_eq!(node.start(), Err(NodeError::AlreadyRunning));
+
+	let funding_address = node.new_onchain_address().unwrap();
+
+	assert_eq!(node.list_balances().total_onchain_balance_sats, 0);
+
+	let expected_amount = Amount::from_sat(100000);
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![funding_address],
+		expected_amount,
+	);
+
+	node.sync_wallets().unwrap();
+	assert_eq!(node.list_balances().spendable_onchain_balance_sats, expected_amount.to_sat());
+
+	let log_file_symlink = format!("{}/logs/ldk_node_latest.log", config.clone().storage_dir_path);
+	assert!(std::path::Path::new(&log_file_symlink).is_symlink());
+
+	node.stop().unwrap();
+	assert_eq!(node.stop(), Err(NodeError::NotRunning));
+
+	node.start().unwrap();
+	assert_eq!(node.start(), Err(NodeError::AlreadyRunning));
+
+	node.stop().unwrap();
+	assert_eq!(node.stop(), Err(NodeError::NotRunning));
+	drop(node);
+
+	setup_builder!(builder, config);
+	builder.set_esplora_server(esplora_url.clone());
+
+	let reinitialized_node = builder.build_with_store(Arc::clone(&test_sync_store)).unwrap();
+	reinitialized_node.start().unwrap();
+	assert_eq!(reinitialized_node.node_id(), expected_node_id);
+
+	assert_eq!(
+		reinitialized_node.list_balances().spendable_onchain_balance_sats,
+		expected_amount.to_sat()
+	);
+
+	reinitialized_node.sync_wallets().unwrap();
+	assert_eq!(
+		reinitialized_node.list_balances().spendable_onchain_balance_sats,
+		expected_amount.to_sat()
+	);
+
+	reinitialized_node.stop().unwrap();
+}
+
+#[test]
+fn applied_rgs_snapshot_timestamp_is_persisted() {
+	// Returns an RGS snapshot generated at the given timestamp which doesn't hold any gossip.
+	let empty_rgs_snapshot = |timestamp: u32| {
+		let mut snapshot = vec![76, 68, 75, 1];
+		snapshot.extend_from_slice(&ChainHash::using_genesis_block(Network::Regtest).encode());
+		snapshot.extend_from_slice(&timestamp.to_be_bytes());
+		snapshot.extend_from_slice(&[0u8; 38]);
+		snapshot
+	};
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+
+	let config = random_config();
+	let rgs_server_url = "http://127.0.0.1:8080".to_string();
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.clone().into()));
+
+	setup_builder!(builder, config);
+	builder.set_gossip_source_rgs(rgs_server_url.clone());
+	let node = builder.build_with_store(Arc::clone(&test_sync_store)).unwrap();
+	assert_eq!(node.apply_rgs_snapshot(&empty_rgs_snapshot(now - 60)), Ok(now - 60));
+	assert_eq!(node.status().latest_rgs_snapshot_timestamp, Some(now as u64 - 60));
+	drop(node);
+
+	setup_builder!(builder, config);
+	builder.set_gossip_source_rgs(rgs_server_url);
+	let reinitialized_node = builder.build_with_store(test_sync_store).unwrap();
+	assert_eq!(
+		reinitialized_node.apply_rgs_snapshot(&empty_rgs_snapshot(now - 120)),
+		Err(NodeError::RgsSnapshotOutdated)
+	);
+	assert_eq!(reinitialized_node.apply_rgs_snapshot(&empty_rgs_snapshot(now)), Ok(now));
+}
+
+#[test]
+fn onchain_spend_receive() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	let addr_b = node_b.new_onchain_address().unwrap();
+
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_b.clone()],
+		Amount::from_sat(100000),
+	);
+
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	assert_eq!(node_b.list_balances().spendable_onchain_balance_sats, 100000);
+
+	assert_eq!(Err(NodeError::InsufficientFunds), node_a.send_to_onchain_address(&addr_b, 1000));
+
+	let txid = node_b.send_to_onchain_address(&addr_a, 1000).unwrap();
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	wait_for_tx(&electrsd.client, txid);
+
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	assert_eq!(node_a.list_balances().spendable_onchain_balance_sats, 1000);
+	assert!(node_b.list_balances().spendable_onchain_balance_sats > 98000);
+	assert!(node_b.list_balances().spendable_onchain_balance_sats < 100000);
+
+	let addr_b = node_b.new_onchain_address().unwrap();
+	let txid = node_a.send_all_to_onchain_address(&addr_b).unwrap();
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	wait_for_tx(&electrsd.client, txid);
+
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	assert_eq!(node_a.list_balances().total_onchain_balance_sats, 0);
+	assert!(node_b.list_balances().spendable_onchain_balance_sats > 99000);
+	assert!(node_b.list_balances().spendable_onchain_balance_sats < 100000);
+}
+
+#[test]
+fn spendable_onchain_balance_excludes_uneconomical_utxos() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let node = setup_node(&electrsd, random_config());
+
+	// A P2WPKH output of 300 sats is just above the relay dust limit of 294 sats.
+	let addr = node.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr.clone()],
+		Amount::from_sat(100000),
+	);
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr],
+		Amount::from_sat(300),
+	);
+	node.sync_wallets().unwrap();
+	assert_eq!(node.list_balances().spendable_onchain_balance_sats, 100300);
+
+	// At 1 sat/vB, spending the output costs less than its value.
+	node.override_fee_rate(ConfirmationTarget::NonAnchorChannelFee, 253);
+	assert_eq!(node.spendable_onchain_balance_sats(), Ok(100300));
+
+	// At 10 sat/vB, spending the output would cost more than its value.
+	node.override_fee_rate(ConfirmationTarget::NonAnchorChannelFee, 2500);
+	assert_eq!(node.spendable_onchain_balance_sats(), Ok(100000));
+	assert_eq!(node.list_balances().spendable_onchain_balance_sats, 100300);
+}
+
+#[test]
+fn onchain_send_with_params() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	let addr_b = node_b.new_onchain_address().unwrap();
+
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_b.clone()],
+		Amount::from_sat(100000),
+	);
+
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	let utxos = node_b.list_onchain_utxos().unwrap();
+	assert_eq!(utxos.len(), 1);
+	assert_eq!(utxos[0].value_sats, 100000);
+	assert_eq!(utxos[0].address, Some(addr_b.clone()));
+	assert!(utxos[0].confirmations >= 1);
+	assert!(!utxos[0].is_reserved);
+	assert!(!utxos[0].is_unconfirmed_change);
+
+	let dust_params = SendOnchainParams::default();
+	assert_eq!(
+		Err(NodeError::InvalidAmount),
+		node_b.send_to_onchain_address_with_params(&addr_a, 100, dust_params)
+	);
+
+	let low_fee_params = SendOnchainParams { sat_per_vbyte: Some(0), ..Default::default() };
+	assert_eq!(
+		Err(NodeError::InvalidFeeRate),
+		node_b.send_to_onchain_address_with_params(&addr_a, 10000, low_fee_params)
+	);
+
+	let unknown_utxo = OutPoint { txid: Txid::all_zeros(), vout: 0 };
+	let unknown_utxo_params =
+		SendOnchainParams { utxos_to_use: Some(vec![unknown_utxo]), ..Default::default() };
+	assert_eq!(
+		Err(NodeError::UtxoUnavailable),
+		node_b.send_to_onchain_address_with_params(&addr_a, 10000, unknown_utxo_params)
+	);
+
+	let params = SendOnchainParams {
+		sat_per_vbyte: Some(5),
+		coin_selection: CoinSelectionStrategy::SmallestFirst,
+		utxos_to_use: None,
+	};
+	let txid = node_b.send_to_onchain_address_with_params(&addr_a, 10000, params).unwrap();
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	wait_for_tx(&electrsd.client, txid);
+
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	assert_eq!(node_a.list_balances().spendable_onchain_balance_sats, 10000);
+	assert!(node_b.list_balances().spendable_onchain_balance_sats < 90000);
+}
+
+#[test]
+fn onchain_wallet_sweep() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	let addr_b = node_b.new_onchain_address().unwrap();
+
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a.clone(), addr_a.clone()],
+		Amount::from_sat(100000),
+	);
+
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	assert_eq!(node_a.list_balances().spendable_onchain_balance_sats, 200000);
+
+	assert_eq!(
+		Err(NodeError::InsufficientFunds),
+		node_b.sweep_to_onchain_address(&addr_a, None, true)
+	);
+
+	let sweep = node_a.sweep_to_onchain_address(&addr_b, Some(2), false).unwrap();
+	assert!(sweep.swept_amount_sats > 199000);
+	assert!(sweep.swept_amount_sats < 200000);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	wait_for_tx(&electrsd.client, sweep.txid);
+
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	assert_eq!(node_a.list_balances().total_onchain_balance_sats, 0);
+	assert_eq!(node_b.list_balances().spendable_onchain_balance_sats, sweep.swept_amount_sats);
+}
+
+#[test]
+fn onchain_wallet_sweep_leaves_reserved_utxos() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	let node_a = setup_node(&electrsd, random_config());
+
+	// Hold the inbound channel request on node B until we swept node A's wallet, so that the
+	// UTXO funding the channel stays reserved in the meantime.
+	let (release_sender, release_receiver) = mpsc::channel::<()>();
+	let release_receiver = Mutex::new(release_receiver);
+	let config_b = random_config();
+	setup_builder!(builder_b, config_b);
+	builder_b.set_esplora_server(esplora_url);
+	builder_b.set_inbound_channel_policy(Box::new(move |_: &PendingChannelRequest| {
+		let _ = release_receiver.lock().unwrap().recv();
+		ChannelDecision::Reject
+	}));
+	let test_sync_store = Arc::new(TestSyncStore::new(config_b.storage_dir_path.into()));
+	let node_b = builder_b.build_with_store(test_sync_store).unwrap();
+	node_b.start().unwrap();
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a.clone(), addr_a],
+		Amount::from_sat(100_000),
+	);
+	node_a.sync_wallets().unwrap();
+	assert_eq!(node_a.list_balances().spendable_onchain_balance_sats, 200_000);
+
+	let funding_utxo = node_a.list_onchain_utxos().unwrap().first().unwrap().outpoint;
+	node_a
+		.connect_open_channel_with_funding_utxos(
+			node_b.node_id(),
+			node_b.listening_addresses().unwrap().first().unwrap().clone(),
+			50_000,
+			vec![funding_utxo],
+			None,
+			None,
+			true,
+		)
+		.unwrap();
+
+	let addr_b = node_b.new_onchain_address().unwrap();
+	let sweep = node_a.sweep_to_onchain_address(&addr_b, None, true).unwrap();
+	assert!(sweep.swept_amount_sats > 99_000);
+	assert!(sweep.swept_amount_sats < 100_000);
+
+	release_sender.send(()).unwrap();
+	expect_event!(node_a, ChannelClosed);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	wait_for_tx(&electrsd.client, sweep.txid);
+	node_a.sync_wallets().unwrap();
+
+	let utxos = node_a.list_onchain_utxos().unwrap();
+	assert_eq!(utxos.len(), 1);
+	assert_eq!(utxos[0].outpoint, funding_utxo);
+	assert_eq!(node_a.list_balances().spendable_onchain_balance_sats, 100_000);
+}
+
+#[test]
+fn onchain_payment_events() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let mut config_a = random_config();
+	config_a.emit_onchain_payment_events = true;
+	config_a.onchain_receive_min_confirmations = 3;
+	let node_a = setup_node(&electrsd, config_a);
+	let mut config_b = random_config();
+	config_b.emit_onchain_payment_events = true;
+	let node_b = setup_node(&electrsd, config_b);
+
+	assert_eq!(
+		Err(NodeError::InvalidAddressLabel),
+		node_a.new_onchain_address_with_label("with\ncontrol character")
+	);
+	let addr_a = node_a.new_onchain_address_with_label("Savings").unwrap();
+	let labels = node_a.onchain_address_labels();
+	assert_eq!(labels.len(), 1);
+	assert_eq!(labels[0].address, addr_a);
+	assert_eq!(labels[0].label, "Savings");
+
+	let addr_b = node_b.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_b.clone()],
+		Amount::from_sat(100000),
+	);
+	node_b.sync_wallets().unwrap();
+	match node_b.wait_next_event() {
+		Event::OnchainPaymentReceived { amount_sat, confirmations, label, .. } => {
+			assert_eq!(amount_sat, 100000);
+			assert!(confirmations >= 1);
+			assert_eq!(label, None);
+			node_b.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+
+	let txid = node_b.send_to_onchain_address(&addr_a, 10000).unwrap();
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+	wait_for_tx(&electrsd.client, txid);
+	node_b.sync_wallets().unwrap();
+	match node_b.wait_next_event() {
+		Event::OnchainPaymentSent { txid: sent_txid, amount_sat } => {
+			assert_eq!(sent_txid, txid);
+			assert_eq!(amount_sat, 10000);
+			node_b.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+
+	// The payment only has a single confirmation, so we don't emit an event yet.
+	node_a.sync_wallets().unwrap();
+	assert_eq!(node_a.next_event(), None);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 2);
+	node_a.sync_wallets().unwrap();
+	match node_a.wait_next_event() {
+		Event::OnchainPaymentReceived { txid: received_txid, amount_sat, confirmations, label } => {
+			assert_eq!(received_txid, txid);
+			assert_eq!(amount_sat, 10000);
+			assert_eq!(confirmations, 3);
+			assert_eq!(label, Some("Savings".to_string()));
+			node_a.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+
+	// Replacing a payment via RBF doesn't emit another event.
+	let txid = node_b.send_to_onchain_address(&addr_a, 5000).unwrap();
+	wait_for_tx(&electrsd.client, txid);
+	node_b.sync_wallets().unwrap();
+	match node_b.wait_next_event() {
+		Event::OnchainPaymentSent { txid: sent_txid, amount_sat } => {
+			assert_eq!(sent_txid, txid);
+			assert_eq!(amount_sat, 5000);
+			node_b.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+
+	let bump_txid = node_b.bump_transaction_fee(&txid, 20).unwrap();
+	assert_ne!(bump_txid, txid);
+	wait_for_tx(&electrsd.client, bump_txid);
+	node_b.sync_wallets().unwrap();
+	assert_eq!(node_b.next_event(), None);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 3);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	assert_eq!(node_b.next_event(), None);
+	match node_a.wait_next_event() {
+		Event::OnchainPaymentReceived { txid: received_txid, amount_sat, .. } => {
+			assert_eq!(received_txid, bump_txid);
+			assert_eq!(amount_sat, 5000);
+			node_a.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+	assert_eq!(node_a.next_event(), None);
+}
+
+#[test]
+fn account_xpub_and_wallet_descriptors_match_bip84_test_vectors() {
+	// The test vectors of BIP84, see https://github.com/bitcoin/bips/blob/master/bip-0084.mediawiki
+	let mnemonic = Mnemonic::from_str(
+		"abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+	)
+	.unwrap();
+	let mainnet_xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+	let testnet_xpub = "tpubDC8msFGeGuwnKG9Upg7DM2b4DaRqg3CUZa5g8v2SRQ6K4NSkxUgd7HsL2XVWbVm39yBA4LAxysQAm397zwQSQoQgewGiYZqrA9DsP4zbQ1M";
+	let expected = [
+		(
+			Network::Bitcoin,
+			mainnet_xpub,
+			format!("wpkh([73c5da0a/84'/0'/0']{}/0/*)#wc3n3van", mainnet_xpub),
+			format!("wpkh([73c5da0a/84'/0'/0']{}/1/*)#lv5jvedt", mainnet_xpub),
+		),
+		(
+			Network::Testnet,
+			testnet_xpub,
+			format!("wpkh([73c5da0a/84'/1'/0']{}/0/*)#2ag6nxcd", testnet_xpub),
+			format!("wpkh([73c5da0a/84'/1'/0']{}/1/*)#mfdmwng4", testnet_xpub),
+		),
+	];
+
+	for (network, xpub, external, internal) in expected {
+		let mut config = random_config();
+		config.network = network;
+		setup_builder!(builder, config);
+		builder.set_entropy_bip39_mnemonic(mnemonic.clone(), None);
+		let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.into()));
+		let node = builder.build_with_store(test_sync_store).unwrap();
+
+		assert_eq!(node.account_xpub().to_string(), xpub);
+		let descriptors = node.wallet_descriptors().unwrap();
+		assert_eq!(descriptors.external, external);
+		assert_eq!(descriptors.internal, internal);
+	}
+}
+
+#[test]
+fn sign_verify_msg() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let config = random_config();
+	let node = setup_node(&electrsd, config);
+
+	// Tests arbitrary message signing and later verification
+	let msg = "OK computer".as_bytes();
+	let sig = node.sign_message(msg).unwrap();
+	let pkey = node.node_id();
+	assert!(node.verify_signature(msg, sig.as_str(), &pkey));
+}
+
+#[test]
+fn connection_restart_behavior() {
+	do_connection_restart_behavior(true);
+	do_connection_restart_behavior(false);
+}
+
+fn do_connection_restart_behavior(persist: bool) {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let node_id_a = node_a.node_id();
+	let node_id_b = node_b.node_id();
+
+	let node_addr_b = node_b.listening_addresses().unwrap().first().unwrap().clone();
+
+	while !node_b.status().is_listening {
+		std::thread::sleep(std::time::Duration::from_millis(10));
+	}
+
+	node_a.connect(node_id_b, node_addr_b, persist).unwrap();
+
+	let peer_details_a = node_a.list_peers().first().unwrap().clone();
+	assert_eq!(peer_details_a.node_id, node_id_b);
+	assert_eq!(peer_details_a.is_persisted, persist);
+	assert!(peer_details_a.is_connected);
+
+	let peer_details_b = node_b.list_peers().first().unwrap().clone();
+	assert_eq!(peer_details_b.node_id, node_id_a);
+	assert_eq!(peer_details_b.is_persisted, false);
+	assert!(peer_details_a.is_connected);
+
+	// Restart nodes.
+	node_a.stop().unwrap();
+	node_b.stop().unwrap();
+	node_b.start().unwrap();
+	node_a.start().unwrap();
+
+	// Sleep a bit to allow for the reconnect to happen.
+	std::thread::sleep(std::time::Duration::from_secs(5));
+
+	if persist {
+		let peer_details_a = node_a.list_peers().first().unwrap().clone();
+		assert_eq!(peer_details_a.node_id, node_id_b);
+		assert_eq!(peer_details_a.is_persisted, persist);
+		assert!(peer_details_a.is_connected);
+
+		let peer_details_b = node_b.list_peers().first().unwrap().clone();
+		assert_eq!(peer_details_b.node_id, node_id_a);
+		assert_eq!(peer_details_b.is_persisted, false);
+		assert!(peer_details_a.is_connected);
+	} else {
+		assert!(node_a.list_peers().is_empty());
+		assert!(node_b.list_peers().is_empty());
+	}
+}
+
+#[test]
+fn onion_connection_requires_tor_proxy() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+	let onion_addr =
+		SocketAddress::OnionV3 { ed25519_pubkey: [42u8; 32], checksum: 0, version: 3, port: 9735 };
+
+	assert_eq!(
+		Err(NodeError::TorProxyNotConfigured),
+		node_a.connect(node_b.node_id(), onion_addr, true)
+	);
+	assert!(node_a.list_peers().is_empty());
+
+	node_a.stop().unwrap();
+	node_b.stop().unwrap();
+}
+
+#[test]
+fn announced_addresses_are_kept_separate() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+
+	let unspecified_addr = SocketAddress::from_str("0.0.0.0:9735").unwrap();
+	assert!(Builder::new().set_announced_addresses(vec![unspecified_addr]).is_err());
+
+	let mut config = random_config();
+	let announced_addresses = vec![SocketAddress::from_str("1.2.3.4:9735").unwrap()];
+	config.announced_addresses = Some(announced_addresses.clone());
+	let node = setup_node(&electrsd, config.clone());
+
+	assert_eq!(node.listening_addresses(), config.listening_addresses);
+	assert_eq!(node.announced_addresses(), Some(announced_addresses));
+
+	node.stop().unwrap();
+}
+
+#[test]
+fn backup_sink_is_never_silently_dropped() {
+	struct RecordingSink(Arc<Mutex<Vec<String>>>);
+
+	impl BackupSink for RecordingSink {
+		fn on_write(&self, p: &str, s: &str, k: &str, _buf: &[u8]) -> std::io::Result<()> {
+			self.0.lock().unwrap().push(format!("{}/{}/{}", p, s, k));
+			Ok(())
+		}
+
+		fn on_remove(&self, _p: &str, _s: &str, _k: &str) -> std::io::Result<()> {
+			Ok(())
+		}
+	}
+
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	let config = random_config();
+	let changes = Arc::new(Mutex::new(Vec::new()));
+	setup_builder!(builder, config);
+	builder.set_esplora_server(esplora_url);
+	builder.set_backup_sink(Box::new(RecordingSink(Arc::clone(&changes))));
+
+	// Building without a store forwarding to the sink fails rather than running without backups.
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.clone().into()));
+	assert!(matches!(
+		builder.build_with_store(Arc::clone(&test_sync_store)),
+		Err(BuildError::KVStoreSetupFailed)
+	));
+	assert!(matches!(builder.build_with_fs_store(), Err(BuildError::KVStoreSetupFailed)));
+	assert!(changes.lock().unwrap().is_empty());
+
+	let node = builder.build_with_backup_sink_store(test_sync_store).unwrap();
+	node.start().unwrap();
+	assert!(!changes.lock().unwrap().is_empty());
+	node.stop().unwrap();
+}
+
+#[test]
+fn node_alias_is_validated() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+
+	let too_long_alias = "ä".repeat(17);
+	assert!(Builder::new().set_node_alias(&too_long_alias).is_err());
+
+	let node = setup_node(&electrsd, random_config());
+	assert_eq!(node.node_alias(), None);
+
+	assert_eq!(Err(NodeError::InvalidNodeAlias), node.set_node_alias(too_long_alias));
+	assert_eq!(node.node_alias(), None);
+
+	node.set_node_alias("LDK Node ⚡".to_string()).unwrap();
+	assert_eq!(node.node_alias(), Some("LDK Node ⚡".to_string()));
+
+	node.stop().unwrap();
+}
+
+#[test]
+fn peer_connection_events() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+
+	let mut config_a = random_config();
+	config_a.emit_peer_connection_events = true;
+	let node_a = setup_node(&electrsd, config_a);
+
+	let mut config_b = random_config();
+	config_b.emit_peer_connection_events = true;
+	let node_b = setup_node(&electrsd, config_b);
+
+	let node_id_a = node_a.node_id();
+	let node_id_b = node_b.node_id();
+	assert_eq!(
+		node_a.peer_connection_status(node_id_b),
+		PeerConnectionStatus::Disconnected { reason: None }
+	);
+
+	let node_addr_b = node_b.listening_addresses().unwrap().first().unwrap().clone();
+	node_a.connect(node_id_b, node_addr_b, false).unwrap();
+
+	expect_event!(node_a, PeerConnected);
+	expect_event!(node_b, PeerConnected);
+	assert!(matches!(
+		node_a.peer_connection_status(node_id_b),
+		PeerConnectionStatus::Connected { .. }
+	));
+
+	node_a.disconnect(node_id_b).unwrap();
+
+	match node_a.wait_next_event() {
+		Event::PeerDisconnected { node_id, reason } => {
+			assert_eq!(node_id, node_id_b);
+			assert_eq!(reason, PeerDisconnectReason::LocallyInitiated);
+			node_a.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+
+	match node_b.wait_next_event() {
+		Event::PeerDisconnected { node_id, reason } => {
+			assert_eq!(node_id, node_id_a);
+			assert_eq!(reason, PeerDisconnectReason::RemotelyInitiated);
+			node_b.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+
+	assert_eq!(
+		node_a.peer_connection_status(node_id_b),
+		PeerConnectionStatus::Disconnected { reason: Some(PeerDisconnectReason::LocallyInitiated) }
+	);
+
+	node_a.stop().unwrap();
+	node_b.stop().unwrap();
+}
+
+#[test]
+fn persistent_peer_reconnects() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let node_id_b = node_b.node_id();
+	let node_addr_b = node_b.listening_addresses().unwrap().first().unwrap().clone();
+	node_a.add_persistent_peer(node_id_b, node_addr_b).unwrap();
+
+	let peer_details_a = node_a.list_peers().first().unwrap().clone();
+	assert_eq!(peer_details_a.node_id, node_id_b);
+	assert!(peer_details_a.is_persisted);
+	assert!(peer_details_a.is_connected);
+
+	// Restart node B, which should have node A reconnect after the initial backoff delay.
+	node_b.stop().unwrap();
+	node_b.start().unwrap();
+	std::thread::sleep(std::time::Duration::from_secs(5));
+
+	let peer_details_a = node_a.list_peers().first().unwrap().clone();
+	assert_eq!(peer_details_a.node_id, node_id_b);
+	assert!(peer_details_a.is_connected);
+
+	// After removal, the peer is forgotten.
+	node_a.remove_persistent_peer(node_id_b).unwrap();
+	node_a.disconnect(node_id_b).unwrap();
+	std::thread::sleep(std::time::Duration::from_secs(5));
+	assert!(node_a.list_peers().is_empty());
+
+	node_a.stop().unwrap();
+	node_b.stop().unwrap();
+}
+
+#[test]
+fn hold_invoice_claim_and_fail() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(500_000),
+	);
+	node_a.sync_wallets().unwrap();
+
+	open_channel(&node_a, &node_b, 200_000, true, &electrsd);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
+
+	// A held payment is only received once we claim it.
+	let amount_msat = 2_500_000;
+	let invoice = node_b.receive_hold_payment(amount_msat, "held", 3600).unwrap();
+	let payment_hash = node_a.send_payment(&invoice).unwrap();
+	match node_b.wait_next_event() {
+		Event::PaymentClaimable {
+			payment_hash: claimable_hash,
+			amount_msat: claimable_msat,
+			..
+		} => {
+			assert_eq!(claimable_hash, payment_hash);
+			assert_eq!(claimable_msat, amount_msat);
+			node_b.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+
+	let payment_preimage = node_b.payment(&payment_hash).unwrap().preimage.unwrap();
+	let wrong_preimage = PaymentPreimage([42; 32]);
+	assert_eq!(
+		Err(NodeError::InvalidPaymentPreimage),
+		node_b.claim_payment(&payment_hash, wrong_preimage)
+	);
+	node_b.claim_payment(&payment_hash, payment_preimage).unwrap();
+	expect_event!(node_a, PaymentSuccessful);
+	expect_payment_received_event!(node_b, amount_msat);
+
+	// A failed held payment is failed back to the sender.
+	let invoice = node_b.receive_hold_payment(amount_msat, "held", 3600).unwrap();
+	let payment_hash = node_a.send_payment(&invoice).unwrap();
+	expect_event!(node_b, PaymentClaimable);
+	node_b.fail_payment(&payment_hash).unwrap();
+	expect_event!(node_a, PaymentFailed);
+	assert_eq!(node_b.payment(&payment_hash).unwrap().status, PaymentStatus::Failed);
+	assert_eq!(Err(NodeError::PaymentNotClaimable), node_b.fail_payment(&payment_hash));
+
+	// A payment to an externally-supplied hash can only be claimed with the matching preimage.
+	let payment_preimage = PaymentPreimage([7; 32]);
+	let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).to_byte_array());
+	let invoice =
+		node_b.receive_payment_for_hash(amount_msat, "external", 3600, payment_hash).unwrap();
+	assert_eq!(
+		Err(NodeError::DuplicatePayment),
+		node_b.receive_payment_for_hash(amount_msat, "external", 3600, payment_hash)
+	);
+	assert_eq!(node_a.send_payment(&invoice).unwrap(), payment_hash);
+	expect_event!(node_b, PaymentClaimable);
+	assert_eq!(
+		Err(NodeError::InvalidPaymentPreimage),
+		node_b.claim_payment(&payment_hash, wrong_preimage)
+	);
+	node_b.claim_payment(&payment_hash, payment_preimage).unwrap();
+	expect_event!(node_a, PaymentSuccessful);
+	expect_payment_received_event!(node_b, amount_msat);
+
+	node_a.stop().unwrap();
+	node_b.stop().unwrap();
+}
+
+#[test]
+fn held_payments_survive_restarts_and_are_auto_failed() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let node_a = setup_node(&electrsd, random_config());
+	let config_b = random_config();
+	let node_b = setup_node(&electrsd, config_b.clone());
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(500_000),
+	);
+	node_a.sync_wallets().unwrap();
+
+	open_channel(&node_a, &node_b, 200_000, true, &electrsd);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
+
+	// A held payment can still be claimed after a restart.
+	let amount_msat = 2_500_000;
+	let invoice = node_b.receive_hold_payment(amount_msat, "held", 3600).unwrap();
+	let payment_hash = node_a.send_payment(&invoice).unwrap();
+	expect_event!(node_b, PaymentClaimable);
+
+	node_b.stop().unwrap();
+	drop(node_b);
+	let node_b = setup_node(&electrsd, config_b);
+
+	let payment_preimage = node_b.payment(&payment_hash).unwrap().preimage.unwrap();
+	node_b.claim_payment(&payment_hash, payment_preimage).unwrap();
+	expect_event!(node_a, PaymentSuccessful);
+	expect_payment_received_event!(node_b, amount_msat);
+
+	// A held payment that isn't claimed or failed in time is failed back automatically.
+	let invoice = node_b.receive_hold_payment(amount_msat, "held", 3600).unwrap();
+	let payment_hash = node_a.send_payment(&invoice).unwrap();
+	let claim_deadline = match node_b.wait_next_event() {
+		Event::PaymentClaimable { payment_hash: claimable_hash, claim_deadline, .. } => {
+			assert_eq!(claimable_hash, payment_hash);
+			node_b.event_handled();
+			claim_deadline.unwrap()
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	};
+
+	let cur_height = bitcoind.client.get_block_count().unwrap() as u32;
+	assert!(cur_height + 3 < claim_deadline);
+	generate_blocks_and_wait(
+		&bitcoind.client,
+		&electrsd.client,
+		(claim_deadline - 3 - cur_height) as usize,
+	);
+	node_b.sync_wallets().unwrap();
+	match node_b.wait_next_event() {
+		Event::HeldPaymentAutoFailed { payment_hash: failed_hash, claim_deadline: deadline } => {
+			assert_eq!(failed_hash, payment_hash);
+			assert_eq!(deadline, claim_deadline);
+			node_b.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+	expect_event!(node_a, PaymentFailed);
+	assert_eq!(node_b.payment(&payment_hash).unwrap().status, PaymentStatus::Failed);
+	let payment_preimage = node_b.payment(&payment_hash).unwrap().preimage.unwrap();
+	assert_eq!(
+		Err(NodeError::PaymentNotClaimable),
+		node_b.claim_payment(&payment_hash, payment_preimage)
+	);
+
+	node_a.stop().unwrap();
+	node_b.stop().unwrap();
+}
+
+#[test]
+fn unpaid_invoice_expires() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let node = setup_node(&electrsd, random_config());
+
+	let invoice = node.receive_payment(1_000_000, "expiring", 1).unwrap();
+	let payment_hash = PaymentHash(invoice.payment_hash().to_byte_array());
+	match node.wait_next_event() {
+		Event::PaymentExpired { payment_hash: expired_hash } => {
+			assert_eq!(expired_hash, payment_hash);
+			node.event_handled();
+		},
+		e => panic!("Unexpected event: {:?}", e),
+	}
+	assert_eq!(node.payment(&payment_hash).unwrap().status, PaymentStatus::Expired);
+
+	node.stop().unwrap();
+}
+
+#[test]
+fn unified_qr_receive_and_parse() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let node = setup_node(&electrsd, random_config());
+
+	let components = UnifiedQrComponents { onchain: true, bolt11: true, bolt12: false };
+	let uri = node.unified_qr_payment().receive(Some(50_000), "qr test", 3600, components).unwrap();
+
+	let request = parse_bip21(&uri, Network::Regtest).unwrap();
+	assert!(request.address.is_some());
+	assert_eq!(request.amount_sats, Some(50_000));
+	assert_eq!(request.message.as_deref(), Some("qr test"));
+	assert!(request.bolt12_offer.is_none());
+	let invoice = request.bolt11_invoice.clone().unwrap();
+	assert_eq!(invoice.amount_milli_satoshis(), Some(50_000_000));
+	let payment_hash = PaymentHash(invoice.payment_hash().to_byte_array());
+	assert_eq!(node.payment(&payment_hash).unwrap().status, PaymentStatus::Pending);
+	assert_eq!(request.to_string(), uri);
+
+	node.stop().unwrap();
+}
+
+#[test]
+fn unified_qr_send_auto_falls_back_to_onchain() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let (node_a, node_b) = setup_two_nodes(&electrsd, false);
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(500_000),
+	);
+	node_a.sync_wallets().unwrap();
+
+	let components = UnifiedQrComponents { onchain: true, bolt11: true, bolt12: false };
+	let uri = node_b.unified_qr_payment().receive(Some(10_000), "auto", 3600, components).unwrap();
+
+	// Without any channels, paying via Lightning fails and we only fall back if allowed to.
+	assert_eq!(
+		node_a.unified_qr_payment().send_auto(&uri, RailPreference::LightningOnly),
+		Err(NodeError::InsufficientFunds)
+	);
+	match node_a
+		.unified_qr_payment()
+		.send_auto(&uri, RailPreference::LightningWithOnchainFallback)
+		.unwrap()
+	{
+		QrPaymentResult::Onchain { txid } => wait_for_tx(&electrsd.client, txid),
+		r => panic!("Unexpected payment result: {:?}", r),
+	}
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+	node_a.sync_wallets().unwrap();
+
+	open_channel(&node_a, &node_b, 100_000, true, &electrsd);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
+
+	let uri = node_b.unified_qr_payment().receive(Some(10_000), "auto", 3600, components).unwrap();
+	match node_a
+		.unified_qr_payment()
+		.send_auto(&uri, RailPreference::LightningWithOnchainFallback)
+		.unwrap()
+	{
+		QrPaymentResult::Bolt11 { .. } => {},
+		r => panic!("Unexpected payment result: {:?}", r),
+	}
+	expect_event!(node_a, PaymentSuccessful);
+	expect_payment_received_event!(node_b, 10_000_000);
+
+	// Errors other than a lack of liquidity are returned rather than falling back to on-chain.
+	assert_eq!(
+		node_a.unified_qr_payment().send_auto(&uri, RailPreference::LightningWithOnchainFallback),
+		Err(NodeError::DuplicatePayment)
+	);
+
+	node_a.stop().unwrap();
+	node_b.stop().unwrap();
+}
+
+#[test]
+fn log_callback_replaces_log_files() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let config = random_config();
+	let records = Arc::new(Mutex::new(Vec::new()));
+
+	setup_builder!(builder, config);
+	builder.set_esplora_server(format!("http://{}", electrsd.esplora_url.as_ref().unwrap()));
+	let callback_records = Arc::clone(&records);
+	builder.set_log_callback(Box::new(move |record: LogRecord| {
+		callback_records.lock().unwrap().push(record);
+	}));
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.clone().into()));
+	let node = builder.build_with_store(test_sync_store).unwrap();
+	node.start().unwrap();
+	node.stop().unwrap();
+
+	let records = records.lock().unwrap();
+	assert!(records.iter().any(|r| r.message.contains("Startup complete.")));
+	assert!(records.iter().all(|r| r.timestamp > 0 && !r.module_path.is_empty()));
+	assert!(!std::path::Path::new(&config.storage_dir_path).join("logs").exists());
+}
+
+#[test]
+fn stop_with_timeout_flushes_and_stops() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let node = setup_node(&electrsd, random_config());
+
+	node.stop_with_timeout(std::time::Duration::from_secs(10)).unwrap();
+	assert!(!node.status().is_running);
+	assert_eq!(Err(NodeError::NotRunning), node.stop_with_timeout(std::time::Duration::ZERO));
+
+	// The node can be restarted after a graceful shutdown.
+	node.start().unwrap();
+	node.stop().unwrap();
+}
+
+#[test]
+fn pause_and_resume_background_sync() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let node = setup_node(&electrsd, random_config());
+	assert!(!node.is_sync_paused());
+
+	node.pause_background_sync();
+	assert!(node.is_sync_paused());
+	assert_eq!(Err(NodeError::SyncPaused), node.sync_wallets());
+	let addr = node.new_onchain_address().unwrap();
+	assert_eq!(Err(NodeError::SyncPaused), node.send_all_to_onchain_address(&addr));
+
+	node.resume_background_sync();
+	assert!(!node.is_sync_paused());
+	node.sync_wallets().unwrap();
+
+	node.stop().unwrap();
+}
+
+#[test]
+fn sync_now_reports_new_tip() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let node = setup_node(&electrsd, random_config());
+	node.sync_now().unwrap();
+	let start_height = node.status().current_best_block.height;
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 5);
+	let report = node.sync_now().unwrap();
+	assert_eq!(report.tip_height, start_height + 5);
+	// The background sync may have picked up some of the blocks already.
+	assert!(report.blocks_processed <= 5);
+	assert_eq!(node.status().current_best_block.height, report.tip_height);
+
+	node.stop().unwrap();
+}
+
+#[test]
+fn sync_now_runs_alongside_background_syncs() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let mut config = random_config();
+	config.onchain_wallet_sync_interval_secs = 10;
+	config.wallet_sync_interval_secs = 10;
+	config.fee_rate_cache_update_interval_secs = 10;
+	let node = setup_node(&electrsd, config);
+	let addr = node.new_onchain_address().unwrap();
+
+	// Keep syncing explicitly from multiple threads while the background syncs are running and
+	// new blocks are mined.
+	let deadline = std::time::Instant::now() + std::time::Duration::from_secs(25);
+	std::thread::scope(|s| {
+		for _ in 0..3 {
+			s.spawn(|| {
+				while std::time::Instant::now() < deadline {
+					let report = node.sync_now().unwrap();
+					assert!(report.tip_height >= report.blocks_processed);
+					std::thread::sleep(std::time::Duration::from_millis(100));
+				}
+			});
+		}
+		premine_and_distribute_funds(
+			&bitcoind.client,
+			&electrsd.client,
+			vec![addr],
+			Amount::from_sat(100_000),
+		);
+	});
+
+	let report = node.sync_now().unwrap();
+	assert_eq!(report.tip_height, bitcoind.client.get_block_count().unwrap() as u32);
+	assert_eq!(node.status().current_best_block.height, report.tip_height);
+	assert_eq!(node.list_balances().spendable_onchain_balance_sats, 100_000);
+
+	node.stop().unwrap();
+}
+
+#[test]
+fn route_hint_selection_is_applied() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let node_a = setup_node(&electrsd, random_config());
+	let config_b = random_config();
+	let node_b = setup_node(&electrsd, config_b.clone());
+	let node_c = setup_node(&electrsd, random_config());
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	let addr_c = node_c.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a, addr_c],
+		Amount::from_sat(1_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+	node_c.sync_wallets().unwrap();
+
+	// Both channels are unannounced, so B's invoices need route hints for them. As C's channel
+	// is larger, it provides B with more inbound capacity.
+	open_channel(&node_a, &node_b, 200_000, false, &electrsd);
+	open_channel(&node_c, &node_b, 500_000, false, &electrsd);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	node_c.sync_wallets().unwrap();
+
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
+	expect_channel_ready_event!(node_b, node_c.node_id());
+	expect_channel_ready_event!(node_c, node_b.node_id());
+
+	// Sleep a bit for the counterparties' channel updates to arrive.
+	std::thread::sleep(std::time::Duration::from_secs(1));
+
+	let user_channel_id = |counterparty_node_id: PublicKey| {
+		node_b
+			.list_channels()
+			.into_iter()
+			.find(|c| c.counterparty_node_id == counterparty_node_id)
+			.unwrap()
+			.user_channel_id
+	};
+	let channel_a = user_channel_id(node_a.node_id());
+	let channel_c = user_channel_id(node_c.node_id());
+	let hinted_nodes = |route_hints: RouteHintSelection| {
+		node_b
+			.receive_payment_with_route_hints(10_000, "asdf", 3600, route_hints)
+			.unwrap()
+			.route_hints()
+			.iter()
+			.map(|hint| hint.0[0].src_node_id)
+			.collect::<Vec<_>>()
+	};
+
+	// Limiting the hints prefers the channels with the most inbound capacity.
+	assert_eq!(hinted_nodes(RouteHintSelection::Limit { max_hints: 1 }), vec![node_c.node_id()]);
+	assert_eq!(
+		hinted_nodes(RouteHintSelection::Limit { max_hints: 5 }),
+		vec![node_c.node_id(), node_a.node_id()]
+	);
+	assert!(hinted_nodes(RouteHintSelection::Limit { max_hints: 0 }).is_empty());
+
+	// Unknown channels are skipped.
+	assert_eq!(
+		hinted_nodes(RouteHintSelection::Channels {
+			user_channel_ids: vec![channel_a, UserChannelId(42)],
+		}),
+		vec![node_a.node_id()]
+	);
+
+	// Once C is offline, its channel isn't usable anymore and is excluded with a warning.
+	node_c.stop().unwrap();
+	for _ in 0..20 {
+		if node_b
+			.list_channels()
+			.iter()
+			.all(|c| c.counterparty_node_id == node_a.node_id() || !c.is_usable)
+		{
+			break;
+		}
+		std::thread::sleep(std::time::Duration::from_millis(500));
+	}
+	assert_eq!(
+		hinted_nodes(RouteHintSelection::Channels { user_channel_ids: vec![channel_c, channel_a] }),
+		vec![node_a.node_id()]
+	);
+	assert_eq!(hinted_nodes(RouteHintSelection::Limit { max_hints: 5 }), vec![node_a.node_id()]);
+
+	let log_file = format!("{}/logs/ldk_node_latest.log", config_b.storage_dir_path);
+	let logs = std::fs::read_to_string(log_file).unwrap();
+	assert!(logs.contains(&format!(
+		"Not including route hint for channel {} as it isn't usable.",
+		channel_c.0
+	)));
+	assert!(logs.contains("Not including route hint for channel 42 as it isn't usable."));
+}
+
+#[test]
+fn phantom_invoices_are_shared_across_nodes() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	let cross_node_seed = vec![42u8; 32];
+
+	let mut phantom_nodes = Vec::new();
+	for _ in 0..2 {
+		let config = random_config();
+		setup_builder!(builder, config);
+		builder.set_esplora_server(esplora_url.clone());
+		builder.set_phantom_cross_node_seed(cross_node_seed.clone()).unwrap();
+		let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.into()));
+		let node = builder.build_with_store(test_sync_store).unwrap();
+		node.start().unwrap();
+		phantom_nodes.push(node);
+	}
+	let payer = setup_node(&electrsd, random_config());
+
+	let addr_payer = payer.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_payer],
+		Amount::from_sat(2_000_000),
+	);
+	payer.sync_wallets().unwrap();
+
+	open_channel(&payer, &phantom_nodes[0], 500_000, false, &electrsd);
+	// We need to sync wallets in-between back-to-back channel opens from the same node so BDK
+	// wallet picks up on the broadcast funding tx and doesn't double-spend itself.
+	payer.sync_wallets().unwrap();
+	open_channel(&payer, &phantom_nodes[1], 500_000, false, &electrsd);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	payer.sync_wallets().unwrap();
+	for node in &phantom_nodes {
+		node.sync_wallets().unwrap();
+		expect_channel_ready_event!(node, payer.node_id());
+	}
+	expect_event!(payer, ChannelReady);
+	expect_event!(payer, ChannelReady);
+
+	// Sleep a bit for the counterparties' channel updates to arrive.
+	std::thread::sleep(std::time::Duration::from_secs(1));
+
+	let route_hints =
+		phantom_nodes.iter().map(|node| node.phantom_route_hints().unwrap()).collect::<Vec<_>>();
+	let invoice_amount_msat = 100_000_000;
+	let invoice = phantom_nodes[0]
+		.receive_phantom_payment(invoice_amount_msat, "asdf", 3600, route_hints.clone())
+		.unwrap();
+	let variable_amount_invoice = phantom_nodes[1]
+		.receive_variable_amount_phantom_payment("asdf", 3600, route_hints)
+		.unwrap();
+	assert_eq!(invoice.recover_payee_pub_key(), variable_amount_invoice.recover_payee_pub_key());
+	assert_ne!(invoice.recover_payee_pub_key(), phantom_nodes[0].node_id());
+	assert_ne!(invoice.recover_payee_pub_key(), phantom_nodes[1].node_id());
+
+	// The invoice created by the first node is still paid while it's offline.
+	phantom_nodes[0].stop().unwrap();
+	let payment_hash = payer.send_payment(&invoice).unwrap();
+	assert_eq!(expect_payment_received_event!(phantom_nodes[1], invoice_amount_msat), payment_hash);
+	expect_event!(payer, PaymentSuccessful);
+	assert_eq!(payer.payment(&payment_hash).unwrap().status, PaymentStatus::Succeeded);
+	assert_eq!(phantom_nodes[1].payment(&payment_hash).unwrap().status, PaymentStatus::Succeeded);
+
+	let regular_node = setup_node(&electrsd, random_config());
+	assert_eq!(Err(NodeError::PhantomPaymentsNotEnabled), regular_node.phantom_route_hints());
+}
+
+#[test]
+fn inbound_channel_policy_rejects_channels() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	let node_a = setup_node(&electrsd, random_config());
+
+	let config_b = random_config();
+	setup_builder!(builder_b, config_b);
+	builder_b.set_esplora_server(esplora_url);
+	builder_b.set_inbound_channel_policy(Box::new(|request: &PendingChannelRequest| {
+		if request.funding_sats < 200_000 {
+			ChannelDecision::Reject
+		} else {
+			ChannelDecision::Accept
+		}
+	}));
+	let test_sync_store = Arc::new(TestSyncStore::new(config_b.storage_dir_path.into()));
+	let node_b = builder_b.build_with_store(test_sync_store).unwrap();
+	node_b.start().unwrap();
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(500_000),
+	);
+	node_a.sync_wallets().unwrap();
+
+	node_a
+		.connect_open_channel(
+			node_b.node_id(),
+			node_b.listening_addresses().unwrap().first().unwrap().clone(),
+			100_000,
+			None,
+			None,
+			true,
+		)
+		.unwrap();
+	expect_event!(node_a, ChannelClosed);
+	assert!(node_a.list_channels().is_empty());
+	assert!(node_b.list_channels().is_empty());
+}
+
+#[test]
+fn min_final_cltv_expiry_delta_is_encoded_in_invoices() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let mut config = random_config();
+	config.min_final_cltv_expiry_delta = 72;
+	let node = setup_node(&electrsd, config);
+
+	let invoice = node.receive_payment(10_000, "asdf", 3600).unwrap();
+	assert_eq!(invoice.min_final_cltv_expiry_delta(), 72);
+
+	let invoice =
+		node.receive_payment_with_min_final_cltv_expiry_delta(10_000, "asdf", 3600, 144).unwrap();
+	assert_eq!(invoice.min_final_cltv_expiry_delta(), 144);
+
+	assert_eq!(
+		Err(NodeError::InvalidCltvExpiryDelta),
+		node.receive_variable_amount_payment_with_min_final_cltv_expiry_delta("asdf", 3600, 10)
+	);
+}
+
+#[test]
+fn persisted_trusted_peers_0conf_allow_zero_conf_channels() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	let node_a = setup_node(&electrsd, random_config());
+	let node_c = setup_node(&electrsd, random_config());
+
+	let config_b = random_config();
+	setup_builder!(builder_b, config_b);
+	builder_b.set_esplora_server(esplora_url);
+	builder_b.set_trusted_peers_0conf(vec![node_a.node_id()]);
+	let test_sync_store = Arc::new(TestSyncStore::new(config_b.storage_dir_path.clone().into()));
+	let node_b = builder_b.build_with_store(test_sync_store).unwrap();
+	node_b.start().unwrap();
+
+	// The trusted peers are still known after rebuilding the node without setting them again.
+	node_b.stop().unwrap();
+	drop(node_b);
+	let node_b = setup_node(&electrsd, config_b);
+
+	let addr_a = node_a.new_onchain_address().unwrap();
+	let addr_c = node_c.new_onchain_address().unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a, addr_c],
+		Amount::from_sat(1_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+	node_c.sync_wallets().unwrap();
+
+	// The channel from the trusted peer is ready before its funding transaction confirmed.
+	open_channel(&node_a, &node_b, 200_000, false, &electrsd);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
+
+	// The channel from the untrusted peer isn't accepted as a zero-conf channel, i.e., it only
+	// becomes ready once its funding transaction confirmed.
+	open_channel(&node_c, &node_b, 200_000, false, &electrsd);
+	node_b.sync_wallets().unwrap();
+	node_c.sync_wallets().unwrap();
+	std::thread::sleep(std::time::Duration::from_secs(1));
+	assert_eq!(node_b.next_event(), None);
+	assert_eq!(node_c.next_event(), None);
+	let channel_c = node_b
+		.list_channels()
+		.into_iter()
+		.find(|c| c.counterparty_node_id == node_c.node_id())
+		.unwrap();
+	assert!(!channel_c.is_channel_ready);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_b.sync_wallets().unwrap();
+	node_c.sync_wallets().unwrap();
+	expect_channel_ready_event!(node_c, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_c.node_id());
+}
+
+#[test]
+fn penalized_channels_are_avoided() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let config_a = random_config();
+	let node_a = setup_node(&electrsd, config_a.clone());
+	let node_b = setup_node(&electrsd, random_config());
+	let node_c = setup_node(&electrsd, random_config());
+	let node_d = setup_node(&electrsd, random_config());
+
+	let addresses = vec![
+		node_a.new_onchain_address().unwrap(),
+		node_b.new_onchain_address().unwrap(),
+		node_c.new_onchain_address().unwrap(),
+	];
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		addresses,
+		Amount::from_sat(2_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	node_c.sync_wallets().unwrap();
+
+	// Setup channel topology:
+	//        (500k:0)- B -(500k:0)
+	//       /                     \
+	//     A                         D
+	//       \                     /
+	//        (500k:0)- C -(500k:0)
+	open_channel(&node_a, &node_b, 500_000, true, &electrsd);
+	node_a.sync_wallets().unwrap();
+	open_channel(&node_a, &node_c, 500_000, true, &electrsd);
+	open_channel(&node_b, &node_d, 500_000, true, &electrsd);
+	open_channel(&node_c, &node_d, 500_000, true, &electrsd);
+
+	let funding_height = bitcoind.client.get_block_count().unwrap() + 1;
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	for node in [&node_a, &node_b, &node_c, &node_d] {
+		node.sync_wallets().unwrap();
+		expect_event!(node, ChannelReady);
+		expect_event!(node, ChannelReady);
+	}
+
+	// Sleep a bit for gossip to propagate.
+	std::thread::sleep(std::time::Duration::from_secs(1));
+
+	// All funding transactions confirmed in the same block, from which we derive the short
+	// channel ids of D's channels.
+	let funding_block = bitcoind
+		.client
+		.get_block(&bitcoind.client.get_block_hash(funding_height).unwrap())
+		.unwrap();
+	let channel_to_d = |counterparty_node_id: PublicKey| {
+		node_d
+			.list_channels()
+			.into_iter()
+			.find(|c| c.counterparty_node_id == counterparty_node_id)
+			.unwrap()
+	};
+	let short_channel_id = |counterparty_node_id: PublicKey| {
+		let funding_txo = channel_to_d(counterparty_node_id).funding_txo.unwrap();
+		let tx_index =
+			funding_block.txdata.iter().position(|tx| tx.txid() == funding_txo.txid).unwrap();
+		funding_height << 40 | (tx_index as u64) << 16 | funding_txo.vout as u64
+	};
+	let scid_b = short_channel_id(node_b.node_id());
+	let scid_c = short_channel_id(node_c.node_id());
+
+	let send_payment = |sender: &Node<TestSyncStore>| {
+		let invoice = node_d.receive_payment(50_000_000, "asdf", 3600).unwrap();
+		sender.send_payment(&invoice).unwrap();
+		expect_payment_received_event!(node_d, 50_000_000);
+		expect_event!(sender, PaymentSuccessful);
+	};
+
+	// With the channel via C penalized, the payment is routed via B.
+	node_a.penalize_channel(scid_c, 3600).unwrap();
+	send_payment(&node_a);
+	assert!(channel_to_d(node_b.node_id()).outbound_capacity_msat > 0);
+	assert_eq!(channel_to_d(node_c.node_id()).outbound_capacity_msat, 0);
+
+	// The penalty still applies after rebuilding the node.
+	node_a.stop().unwrap();
+	drop(node_a);
+	let node_a = setup_node(&electrsd, config_a);
+	for _ in 0..20 {
+		if node_a.list_channels().iter().all(|c| c.is_usable) {
+			break;
+		}
+		std::thread::sleep(std::time::Duration::from_millis(500));
 	}
+	let outbound_capacity_via_b_msat = channel_to_d(node_b.node_id()).outbound_capacity_msat;
+	send_payment(&node_a);
+	assert!(channel_to_d(node_b.node_id()).outbound_capacity_msat > outbound_capacity_via_b_msat);
+	assert_eq!(channel_to_d(node_c.node_id()).outbound_capacity_msat, 0);
+
+	// Once we lift the penalty and penalize the channel via B instead, the payment is routed via C.
+	node_a.penalize_channel(scid_c, 0).unwrap();
+	node_a.penalize_channel(scid_b, 3600).unwrap();
+	send_payment(&node_a);
+	assert!(channel_to_d(node_c.node_id()).outbound_capacity_msat > 0);
 }